@@ -0,0 +1,113 @@
+#ifndef BTSNOOP_H
+#define BTSNOOP_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A parsed capture, opaque to C
+typedef struct BtsnoopCapture BtsnoopCapture;
+
+// A packet record, its data points into the capture
+typedef struct BtsnoopPacket {
+  uint32_t original_length;
+  // bit 0 received, bit 1 command or event
+  uint32_t flags;
+  uint32_t cumulative_drops;
+  int64_t timestamp_us;
+  const uint8_t *data;
+  size_t data_len;
+} BtsnoopPacket;
+
+// The HCI fields of an HCI UART (H4) packet, zero when not part of its type
+typedef struct BtsnoopHci {
+  // 1 command, 2 ACL data, 3 SCO data, 4 event, 5 ISO data
+  uint8_t packet_type;
+  uint16_t opcode;
+  uint8_t event_code;
+  // the subevent of an LE Meta event
+  uint8_t subevent_code;
+  // the connection handle of ACL data
+  uint16_t handle;
+  uint8_t packet_boundary_flag;
+  // command or event parameters, ACL data, or the rest of the packet for other types
+  const uint8_t *params;
+  size_t params_len;
+} BtsnoopHci;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Read a capture file, of any format the crate reads, detected from its first octets
+//
+// # Safety
+//
+// `path` must be a NUL terminated string.
+struct BtsnoopCapture *btsnoop_open(const char *path);
+
+// Parse a btsnoop capture in memory, the data is copied
+//
+// # Safety
+//
+// `data` must point to `len` readable octets.
+struct BtsnoopCapture *btsnoop_parse(const uint8_t *data, size_t len);
+
+// Free a capture, NULL is ignored
+//
+// # Safety
+//
+// `capture` must come from [`btsnoop_open`] or [`btsnoop_parse`] and not be freed already.
+void btsnoop_free(struct BtsnoopCapture *capture);
+
+// The message of the last error on this thread, NULL if none. Valid until the next error.
+const char *btsnoop_last_error(void);
+
+// The datalink type code of the header, 1002 for HCI UART (H4)
+//
+// # Safety
+//
+// `capture` must be a capture not freed yet.
+uint32_t btsnoop_datalink(const struct BtsnoopCapture *capture);
+
+// # Safety
+//
+// `capture` must be a capture not freed yet.
+size_t btsnoop_packet_count(const struct BtsnoopCapture *capture);
+
+// Fill `out` with the packet at `index`, false if out of range
+//
+// # Safety
+//
+// `capture` must be a capture not freed yet and `out` writable.
+bool btsnoop_packet(const struct BtsnoopCapture *capture, size_t index, struct BtsnoopPacket *out);
+
+// Fill `out` with the HCI fields of the packet at `index`, false if out of range, the
+// capture is not HCI UART or the packet does not decode
+//
+// # Safety
+//
+// `capture` must be a capture not freed yet and `out` writable.
+bool btsnoop_packet_hci(const struct BtsnoopCapture *capture, size_t index, struct BtsnoopHci *out);
+
+// Write the one line summary of the packet at `index` to `buffer`, truncated to `len - 1`
+// octets and NUL terminated. Returns the length of the whole summary, like `snprintf`, 0 if
+// out of range.
+//
+// # Safety
+//
+// `capture` must be a capture not freed yet and `buffer` point to `len` writable octets.
+size_t btsnoop_packet_summary(const struct BtsnoopCapture *capture,
+                              size_t index,
+                              char *buffer,
+                              size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BTSNOOP_H */
@@ -0,0 +1,499 @@
+//! Python bindings, the `btsnoop` module. Built with maturin, `maturin develop` in this
+//! directory installs it in the current virtual environment.
+//!
+//! ```python
+//! import btsnoop
+//!
+//! capture = btsnoop.open("btsnoop_hci.log")
+//! for packet in capture:
+//!     hci = packet.hci
+//!     if isinstance(hci, btsnoop.Acl) and hci.l2cap and hci.l2cap.att:
+//!         print(packet.timestamp_us, hci.handle, hci.l2cap.att.name)
+//! ```
+//!
+//! Packets convert to and from the HCI packets of [Bumble](https://github.com/google/bumble),
+//! to replay a capture through a live stack or compare it with one:
+//!
+//! ```python
+//! hci_packet = packet.to_bumble()  # a bumble.hci.HCI_Packet
+//! packet = btsnoop.from_bumble(hci_packet, received=True, timestamp_us=time.time_ns() // 1000)
+//! ```
+//!
+//! Parse errors raise `ValueError`, other IO errors `OSError`. Timestamps are in microseconds
+//! since the Unix epoch.
+
+use std::{fs, io};
+
+use btsnoop::{
+    att,
+    bumble::HciPacket,
+    formats::{self, Format},
+    hci::{self, event_code_name, opcode_name, LeMetaEvent},
+    l2cap::BasicFrame,
+    parse_uart_packet, Btsnoop, DatalinkType, UartData,
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+fn error(e: io::Error) -> PyErr {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            PyValueError::new_err(e.to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+fn bytes(py: Python<'_>, data: &[u8]) -> Py<PyBytes> {
+    PyBytes::new(py, data).unbind()
+}
+
+/// A capture, a sequence of packets
+#[pyclass(frozen, module = "btsnoop")]
+struct Capture {
+    capture: Btsnoop,
+}
+
+#[pymethods]
+impl Capture {
+    #[getter]
+    fn version(&self) -> u32 {
+        self.capture.header.version
+    }
+
+    /// The datalink type code, 1002 for HCI UART (H4)
+    #[getter]
+    fn datalink(&self) -> u32 {
+        self.capture.header.datalink_type.into()
+    }
+
+    fn __len__(&self) -> usize {
+        self.capture.packets.len()
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<Packet> {
+        let len = self.capture.packets.len() as isize;
+        let index = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&index) {
+            return Err(pyo3::exceptions::PyIndexError::new_err(
+                "packet index out of range",
+            ));
+        }
+        Ok(self.packet(index as usize))
+    }
+
+    fn __iter__(slf: Py<Self>) -> PacketIter {
+        PacketIter {
+            capture: slf,
+            index: 0,
+        }
+    }
+}
+
+impl Capture {
+    fn packet(&self, index: usize) -> Packet {
+        Packet {
+            packet: self.capture.packets[index].clone(),
+            uart: matches!(self.capture.header.datalink_type, DatalinkType::Uart),
+        }
+    }
+}
+
+#[pyclass(module = "btsnoop")]
+struct PacketIter {
+    capture: Py<Capture>,
+    index: usize,
+}
+
+#[pymethods]
+impl PacketIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<Packet> {
+        let capture = self.capture.get();
+        if self.index >= capture.capture.packets.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(capture.packet(self.index - 1))
+    }
+}
+
+/// A packet record
+#[pyclass(frozen, module = "btsnoop")]
+struct Packet {
+    packet: btsnoop::Packet,
+    uart: bool,
+}
+
+#[pymethods]
+impl Packet {
+    #[getter]
+    fn timestamp_us(&self) -> i64 {
+        self.packet.description.unix_timestamp()
+    }
+
+    /// Received by the host, from the controller
+    #[getter]
+    fn received(&self) -> bool {
+        self.packet.description.flags.is_received()
+    }
+
+    #[getter]
+    fn flags(&self) -> u32 {
+        self.packet.description.flags.0
+    }
+
+    #[getter]
+    fn original_length(&self) -> u32 {
+        self.packet.description.original_length
+    }
+
+    #[getter]
+    fn drops(&self) -> u32 {
+        self.packet.description.cumulative_drops
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.packet.data.0)
+    }
+
+    /// One line summary, like btmon
+    #[getter]
+    fn summary(&self) -> String {
+        self.packet.summary().to_string()
+    }
+
+    /// The decoded `Command`, `Event` or `Acl`, None for other packets and captures which
+    /// are not HCI UART
+    #[getter]
+    fn hci(&self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        if !self.uart {
+            return Ok(None);
+        }
+        let decoded = match parse_uart_packet(&self.packet) {
+            Ok(UartData::Command(command)) => Py::new(py, Command::new(py, &command))?.into_any(),
+            Ok(UartData::Event(event)) => Py::new(py, Event::new(py, &event))?.into_any(),
+            Ok(UartData::Acl(acl)) => Py::new(py, Acl::new(py, &acl))?.into_any(),
+            _ => return Ok(None),
+        };
+        Ok(Some(decoded))
+    }
+
+    /// The `bumble.hci.HCI_Packet` of the packet data, for HCI UART (H4) captures
+    fn to_bumble(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        if !self.uart {
+            return Err(PyValueError::new_err("not an HCI UART (H4) packet"));
+        }
+        let packet = HciPacket::try_from(&self.packet).map_err(error)?;
+        let from_bytes = py
+            .import("bumble.hci")?
+            .getattr("HCI_Packet")?
+            .getattr("from_bytes")?;
+        Ok(from_bytes.call1((PyBytes::new(py, &packet.0),))?.unbind())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Packet {}>", self.packet)
+    }
+}
+
+#[pyclass(frozen, module = "btsnoop")]
+struct Command {
+    #[pyo3(get)]
+    opcode: u16,
+    #[pyo3(get)]
+    ogf: u8,
+    #[pyo3(get)]
+    ocf: u16,
+    /// None when unknown
+    #[pyo3(get)]
+    name: Option<&'static str>,
+    #[pyo3(get)]
+    params: Py<PyBytes>,
+}
+
+impl Command {
+    fn new(py: Python<'_>, command: &hci::Command<'_>) -> Self {
+        let opcode = command.opcode.raw();
+        Self {
+            opcode,
+            ogf: (opcode >> 10) as u8,
+            ocf: opcode & 0x03FF,
+            name: opcode_name(opcode),
+            params: bytes(py, command.params),
+        }
+    }
+}
+
+#[pymethods]
+impl Command {
+    fn __repr__(&self) -> String {
+        format!(
+            "Command(opcode=0x{:04x}, name={:?})",
+            self.opcode,
+            self.name.unwrap_or("Unknown")
+        )
+    }
+}
+
+#[pyclass(frozen, module = "btsnoop")]
+struct Event {
+    #[pyo3(get)]
+    code: u8,
+    /// None when unknown
+    #[pyo3(get)]
+    name: Option<&'static str>,
+    /// The subevent code of LE Meta events
+    #[pyo3(get)]
+    subevent: Option<u8>,
+    #[pyo3(get)]
+    params: Py<PyBytes>,
+}
+
+impl Event {
+    fn new(py: Python<'_>, event: &hci::Event<'_>) -> Self {
+        let subevent = match event.params.first() {
+            Some(code) if event.code == hci::Event::LE_META => Some(*code),
+            _ => None,
+        };
+        Self {
+            code: event.code,
+            name: subevent
+                .and_then(LeMetaEvent::subevent_name)
+                .or(event_code_name(event.code)),
+            subevent,
+            params: bytes(py, event.params),
+        }
+    }
+}
+
+#[pymethods]
+impl Event {
+    fn __repr__(&self) -> String {
+        format!(
+            "Event(code=0x{:02x}, name={:?})",
+            self.code,
+            self.name.unwrap_or("Unknown")
+        )
+    }
+}
+
+/// ACL data
+#[pyclass(frozen, module = "btsnoop")]
+struct Acl {
+    #[pyo3(get)]
+    handle: u16,
+    /// 0b00 and 0b10 start an L2CAP PDU, 0b01 continues one
+    #[pyo3(get)]
+    packet_boundary_flag: u8,
+    #[pyo3(get)]
+    broadcast_flag: u8,
+    #[pyo3(get)]
+    data: Py<PyBytes>,
+}
+
+impl Acl {
+    fn new(py: Python<'_>, acl: &hci::Acl<'_>) -> Self {
+        Self {
+            handle: acl.handle,
+            packet_boundary_flag: acl.packet_boundary_flag as u8,
+            broadcast_flag: acl.broadcast_flag,
+            data: bytes(py, acl.data),
+        }
+    }
+}
+
+#[pymethods]
+impl Acl {
+    /// The L2CAP frame started by this fragment, None for continuing fragments
+    #[getter]
+    fn l2cap(&self, py: Python<'_>) -> Option<L2capFrame> {
+        if self.packet_boundary_flag == hci::PacketBoundaryFlag::ContinuingFragment as u8 {
+            return None;
+        }
+        let frame = BasicFrame::try_from(self.data.as_bytes(py)).ok()?;
+        Some(L2capFrame {
+            length: frame.length,
+            channel_id: frame.channel_id,
+            complete: frame.is_complete(),
+            payload: bytes(py, frame.payload),
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Acl(handle=0x{:04x})", self.handle)
+    }
+}
+
+/// A basic L2CAP frame, its payload is only the part in this ACL fragment unless complete
+#[pyclass(frozen, module = "btsnoop")]
+struct L2capFrame {
+    #[pyo3(get)]
+    length: u16,
+    #[pyo3(get)]
+    channel_id: u16,
+    #[pyo3(get)]
+    complete: bool,
+    #[pyo3(get)]
+    payload: Py<PyBytes>,
+}
+
+#[pymethods]
+impl L2capFrame {
+    /// The attribute PDU of a complete frame on the ATT channel
+    #[getter]
+    fn att(&self, py: Python<'_>) -> Option<AttPdu> {
+        if self.channel_id != BasicFrame::ATT_CID || !self.complete {
+            return None;
+        }
+        let payload = self.payload.as_bytes(py);
+        let pdu = att::Pdu::try_from(&payload[..self.length as usize]).ok()?;
+        Some(AttPdu {
+            opcode: pdu.opcode,
+            name: att::opcode_name(pdu.opcode),
+            params: bytes(py, pdu.params),
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "L2capFrame(channel_id=0x{:04x}, length={})",
+            self.channel_id, self.length
+        )
+    }
+}
+
+#[pyclass(frozen, module = "btsnoop")]
+struct AttPdu {
+    #[pyo3(get)]
+    opcode: u8,
+    /// None when unknown
+    #[pyo3(get)]
+    name: Option<&'static str>,
+    #[pyo3(get)]
+    params: Py<PyBytes>,
+}
+
+#[pymethods]
+impl AttPdu {
+    fn __repr__(&self) -> String {
+        format!(
+            "AttPdu(opcode=0x{:02x}, name={:?})",
+            self.opcode,
+            self.name.unwrap_or("Unknown")
+        )
+    }
+}
+
+/// The packet of a Bumble HCI packet, or of anything `bytes()` gives the H4 packet of
+#[pyfunction]
+#[pyo3(signature = (packet, received, timestamp_us = 0))]
+fn from_bumble(packet: &Bound<'_, PyAny>, received: bool, timestamp_us: i64) -> PyResult<Packet> {
+    let data: Vec<u8> = packet
+        .py()
+        .import("builtins")?
+        .getattr("bytes")?
+        .call1((packet,))?
+        .extract()?;
+    Ok(Packet {
+        packet: HciPacket(data).to_packet(timestamp_us, received),
+        uart: true,
+    })
+}
+
+/// Parse a btsnoop capture from bytes
+#[pyfunction]
+fn parse(data: &[u8]) -> PyResult<Capture> {
+    let capture = Btsnoop::parse_from_slice(data).map_err(error)?;
+    Ok(Capture { capture })
+}
+
+/// Read a capture file of any format the crate reads, detected from its first octets
+#[pyfunction]
+fn open(path: std::path::PathBuf) -> PyResult<Capture> {
+    let data = fs::read(path).map_err(error)?;
+    let format = Format::detect(&data).unwrap_or(Format::Btsnoop);
+    let capture = formats::read(&mut &data[..], format).map_err(error)?;
+    Ok(Capture { capture })
+}
+
+#[pymodule]
+#[pyo3(name = "btsnoop")]
+fn btsnoop_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    m.add_function(wrap_pyfunction!(from_bumble, m)?)?;
+    m.add_class::<Capture>()?;
+    m.add_class::<Packet>()?;
+    m.add_class::<Command>()?;
+    m.add_class::<Event>()?;
+    m.add_class::<Acl>()?;
+    m.add_class::<L2capFrame>()?;
+    m.add_class::<AttPdu>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn python_objects() {
+        Python::initialize();
+        Python::attach(|py| {
+            let original: &[u8] = include_bytes!("../../res/btsnoop_hci_android.log");
+            let capture = Py::new(py, parse(original).unwrap()).unwrap();
+            let capture = capture.bind(py);
+            assert_eq!(capture.len().unwrap(), 222);
+
+            let packet = capture.get_item(-1).unwrap();
+            assert!(
+                packet
+                    .getattr("timestamp_us")
+                    .unwrap()
+                    .extract::<i64>()
+                    .unwrap()
+                    > 0
+            );
+            let first = capture.get_item(0).unwrap();
+            let command = first.getattr("hci").unwrap();
+            assert_eq!(
+                command
+                    .getattr("name")
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "Reset"
+            );
+            assert_eq!(
+                command.getattr("ogf").unwrap().extract::<u8>().unwrap(),
+                0x03
+            );
+
+            let acl: &[u8] = include_bytes!("../../res/btsnoop_hci.cfa");
+            let capture = Py::new(py, parse(acl).unwrap()).unwrap();
+            let acl = capture
+                .bind(py)
+                .try_iter()
+                .unwrap()
+                .map(|packet| packet.unwrap().getattr("hci").unwrap())
+                .find(|hci| hci.is_instance_of::<Acl>())
+                .unwrap();
+            assert!(acl.getattr("l2cap").unwrap().getattr("channel_id").is_ok());
+
+            let reset = PyBytes::new(py, &[0x01, 0x03, 0x0c, 0x00]);
+            let packet = from_bumble(&reset, false, 1_000).unwrap();
+            assert_eq!(packet.timestamp_us(), 1_000);
+            assert_eq!(
+                packet.summary(),
+                "< HCI Command: Reset (0x03|0x0003) plen 0"
+            );
+
+            let error = parse(b"btsnoop").err().unwrap();
+            assert!(error.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
@@ -0,0 +1,244 @@
+//! Getting the HCI snoop log off a connected Android device, through the `adb` command, which
+//! must be in the `PATH`.
+//!
+//! The log is written by the Bluetooth stack when the snoop log mode, the
+//! `persist.bluetooth.btsnooplogmode` property set by the developer options, is `full` or
+//! `filtered`. Where it is written depends on the Android version and on the vendor, so the
+//! stack configuration is looked up before the usual paths.
+
+use std::{
+    io::{self, BufReader},
+    process::{Child, ChildStdout, Command, Output, Stdio},
+};
+
+use crate::{Btsnoop, Header, Packet, Reader};
+
+const SNOOP_MODE_PROPERTY: &str = "persist.bluetooth.btsnooplogmode";
+
+/// Bluetooth stack configurations which may override the log path
+const STACK_CONFIGS: [&str; 3] = [
+    "/data/misc/bluedroid/bt_stack.conf",
+    "/vendor/etc/bluetooth/bt_stack.conf",
+    "/etc/bluetooth/bt_stack.conf",
+];
+
+/// Paths of the log used by AOSP and vendors, the most common first
+pub const LOG_PATHS: [&str; 5] = [
+    "/data/misc/bluetooth/logs/btsnoop_hci.log",
+    "/sdcard/btsnoop_hci.log",
+    "/data/log/bt/btsnoop_hci.log",
+    "/data/vendor/bluetooth/btsnoop_hci.log",
+    "/sdcard/Android/data/btsnoop_hci.log",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnoopMode {
+    Disabled,
+    /// the payload of data packets is truncated
+    Filtered,
+    Full,
+}
+
+impl SnoopMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnoopMode::Disabled => "disabled",
+            SnoopMode::Filtered => "filtered",
+            SnoopMode::Full => "full",
+        }
+    }
+
+    /// The mode of a property value, an unset property means disabled
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" | "disabled" => Some(SnoopMode::Disabled),
+            "filtered" => Some(SnoopMode::Filtered),
+            "full" => Some(SnoopMode::Full),
+            _ => None,
+        }
+    }
+}
+
+/// A device connected to adb
+#[derive(Debug, Clone, Default)]
+pub struct Device {
+    /// the only connected device when none
+    serial: Option<String>,
+}
+
+impl Device {
+    pub fn new(serial: Option<String>) -> Self {
+        Self { serial }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("adb");
+        if let Some(serial) = &self.serial {
+            command.args(["-s", serial]);
+        }
+        command
+    }
+
+    /// Run a shell command on the device and return its standard output
+    pub fn shell(&self, command: &str) -> io::Result<Vec<u8>> {
+        let output = self
+            .command()
+            .args(["exec-out", command])
+            .stderr(Stdio::piped())
+            .output()?;
+        check(output)
+    }
+
+    fn shell_text(&self, command: &str) -> io::Result<String> {
+        Ok(String::from_utf8_lossy(&self.shell(command)?).into_owned())
+    }
+
+    pub fn snoop_mode(&self) -> io::Result<SnoopMode> {
+        let value = self.shell_text(&format!("getprop {}", SNOOP_MODE_PROPERTY))?;
+        SnoopMode::parse(&value).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown snoop log mode {:?}", value.trim()),
+            )
+        })
+    }
+
+    /// Change the snoop log mode and restart Bluetooth for it to be applied.
+    /// The property is only writable by the shell user on userdebug builds or as root.
+    pub fn set_snoop_mode(&self, mode: SnoopMode) -> io::Result<()> {
+        self.shell(&format!(
+            "setprop {} {}",
+            SNOOP_MODE_PROPERTY,
+            mode.as_str()
+        ))?;
+        self.shell("cmd bluetooth_manager disable")?;
+        self.shell("cmd bluetooth_manager wait-for-state:STATE_OFF")
+            .or_else(|_| self.shell("sleep 2"))?;
+        self.shell("cmd bluetooth_manager enable")?;
+        Ok(())
+    }
+
+    /// The path of the active log: the one configured by the stack if any, else the first of
+    /// [`LOG_PATHS`] which exists
+    pub fn locate_log(&self) -> io::Result<String> {
+        for config in STACK_CONFIGS {
+            if let Ok(config) = self.shell_text(&format!("cat {} 2>/dev/null", config)) {
+                if let Some(path) = configured_log_path(&config) {
+                    if self.exists(&path)? {
+                        return Ok(path);
+                    }
+                }
+            }
+        }
+        for path in LOG_PATHS {
+            if self.exists(path)? {
+                return Ok(path.to_string());
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no snoop log found, is the snoop log enabled and the device rooted?",
+        ))
+    }
+
+    fn exists(&self, path: &str) -> io::Result<bool> {
+        let output = self.shell_text(&format!("test -f {} && echo yes", path))?;
+        Ok(output.trim() == "yes")
+    }
+
+    /// Pull and parse the active log
+    pub fn pull(&self) -> io::Result<Btsnoop> {
+        let path = self.locate_log()?;
+        let data = self.shell(&format!("cat {}", path))?;
+        Btsnoop::parse(&mut &data[..])
+    }
+
+    /// The btsnooz summary of the last packets kept in memory by the stack, which does not
+    /// require the snoop log to be enabled nor root
+    #[cfg(feature = "btsnooz")]
+    pub fn pull_summary(&self) -> io::Result<Btsnoop> {
+        let dump = self.shell("dumpsys bluetooth_manager")?;
+        crate::formats::btsnooz::read(&mut &dump[..])
+    }
+
+    /// Stream the packets of the active log, from its start and then as they are written
+    pub fn stream(&self) -> io::Result<Stream> {
+        let path = self.locate_log()?;
+        let mut child = self
+            .command()
+            .args(["exec-out", &format!("tail -c +1 -f {}", path)])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let reader = Reader::new(BufReader::new(child.stdout.take().expect("piped stdout")))?;
+        Ok(Stream { child, reader })
+    }
+}
+
+/// Packets of a log being streamed from a device, ends when the device is disconnected
+#[derive(Debug)]
+pub struct Stream {
+    child: Child,
+    reader: Reader<BufReader<ChildStdout>>,
+}
+
+impl Stream {
+    pub fn header(&self) -> &Header {
+        self.reader.header()
+    }
+}
+
+impl Iterator for Stream {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next()
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn check(output: Output) -> io::Result<Vec<u8>> {
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(io::Error::other(format!(
+            "adb failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// The `BtSnoopFileName` of a bt_stack.conf
+fn configured_log_path(config: &str) -> Option<String> {
+    config
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix("BtSnoopFileName="))
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stack_config_and_mode() {
+        let config = "# BtSnoopFileName=/sdcard/old.log\nTraceConf=true\nBtSnoopFileName=/data/log/bt/btsnoop_hci.log\n";
+        assert_eq!(
+            configured_log_path(config).as_deref(),
+            Some("/data/log/bt/btsnoop_hci.log")
+        );
+        assert_eq!(configured_log_path("TraceConf=true"), None);
+        assert_eq!(SnoopMode::parse("full\n"), Some(SnoopMode::Full));
+        assert_eq!(SnoopMode::parse(""), Some(SnoopMode::Disabled));
+        assert_eq!(SnoopMode::parse("on"), None);
+    }
+}
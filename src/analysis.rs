@@ -0,0 +1,114 @@
+//! Passes over a parsed capture which correlate packets with each other, e.g. requests with
+//! their responses or data with the connection it was sent on. They only understand HCI UART (H4)
+//! packet data, packets that don't decode are skipped.
+
+use crate::{parse_uart_packet, Btsnoop, DatalinkType, Packet, UartData};
+
+pub mod advertisers;
+pub mod channel_map;
+pub mod command_errors;
+pub mod connection_interval;
+pub mod corpus;
+pub mod data_stall;
+pub mod firmware;
+pub mod gatt_replay;
+pub mod health_check;
+pub mod hfp;
+pub mod hid;
+pub mod iso;
+pub mod mesh;
+pub mod pairing;
+pub mod power_control;
+pub mod rtp;
+pub mod statistics;
+pub mod streams;
+
+/// decoded packets with their index in the capture, none unless the capture is HCI UART (H4)
+pub(crate) fn uart_packets(
+    capture: &Btsnoop,
+) -> impl Iterator<Item = (usize, &Packet, UartData<'_>)> {
+    let uart = matches!(capture.header.datalink_type, DatalinkType::Uart);
+    capture
+        .packets
+        .iter()
+        .take(if uart { usize::MAX } else { 0 })
+        .enumerate()
+        .filter_map(|(index, packet)| match parse_uart_packet(packet) {
+            Ok(data) => Some((index, packet, data)),
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(index, error = %_e, "packet not decoded, skipped");
+                None
+            }
+        })
+}
+
+/// direction flag (bit 0), received by the host from the controller
+pub(crate) fn is_received(packet: &Packet) -> bool {
+    packet.description.flags.is_received()
+}
+
+#[cfg(test)]
+pub(crate) mod test_capture {
+    use crate::{
+        Btsnoop, DatalinkType, Header, IdentificationPattern, Packet, PacketData,
+        PacketDescription, PacketFlags,
+    };
+
+    /// build an H4 capture from (timestamp, received, packet data including the type byte)
+    pub fn h4(packets: Vec<(i64, bool, Vec<u8>)>) -> Btsnoop {
+        let packets = packets
+            .into_iter()
+            .map(|(timestamp, received, data)| {
+                let command_or_event = matches!(data.first(), Some(1) | Some(4));
+                let flags = received as u32 | (command_or_event as u32) << 1;
+                Packet {
+                    description: PacketDescription {
+                        original_length: data.len() as u32,
+                        included_length: data.len() as u32,
+                        flags: PacketFlags(flags),
+                        cumulative_drops: 0,
+                        timestamp,
+                    },
+                    data: PacketData(data),
+                }
+            })
+            .collect();
+        Btsnoop {
+            header: Header {
+                identification_pattern: IdentificationPattern,
+                version: 1,
+                datalink_type: DatalinkType::Uart,
+            },
+            packets,
+        }
+    }
+
+    /// H4 ACL packet with a single, complete basic L2CAP frame
+    pub fn l2cap(handle: u16, cid: u16, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x02];
+        data.extend_from_slice(&(handle | 0x2000).to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u16 + 4).to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        data.extend_from_slice(&cid.to_le_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// H4 event packet
+    pub fn event(code: u8, params: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x04, code, params.len() as u8];
+        data.extend_from_slice(params);
+        data
+    }
+
+    /// H4 command packet
+    pub fn command(ogf: u8, ocf: u16, params: &[u8]) -> Vec<u8> {
+        let opcode = (ogf as u16) << 10 | ocf;
+        let mut data = vec![0x01];
+        data.extend_from_slice(&opcode.to_le_bytes());
+        data.push(params.len() as u8);
+        data.extend_from_slice(params);
+        data
+    }
+}
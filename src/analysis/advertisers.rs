@@ -0,0 +1,315 @@
+//! Scan report: the advertising reports of the capture collapsed into one entry per advertiser,
+//! with how often and how strongly it was heard and the distinct data it advertised.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    analysis::uart_packets,
+    crypto::{is_resolvable_private_address, resolve_private_address},
+    hci::{BdAddr, Event, LeAdvertisingReport, LeMetaEvent},
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanConfig {
+    /// Identity resolving keys, most significant octet first. Resolvable private addresses
+    /// generated from one of them are merged into a single advertiser.
+    pub irks: Vec<[u8; 16]>,
+}
+
+/// How reports are attributed to an advertiser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdvertiserId {
+    Address {
+        address_type: u8,
+        address: BdAddr,
+    },
+    /// resolvable private addresses of the IRK at this index of [`ScanConfig::irks`]
+    Irk(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataVariant {
+    pub scan_response: bool,
+    pub data: Vec<u8>,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Advertiser {
+    pub id: AdvertiserId,
+    /// every address the advertiser used, more than one when its private address rotated
+    pub addresses: Vec<BdAddr>,
+    /// advertising and scan response reports
+    pub reports: usize,
+    pub scan_responses: usize,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    /// (min, max) in dBm of the reports which had one
+    pub rssi: Option<(i8, i8)>,
+    pub data_variants: Vec<DataVariant>,
+    /// Estimate of the advertising interval, the lower decile of the time between advertising
+    /// reports. Scan windows and controller duplicate filtering hide advertising events, so
+    /// it tends to over-estimate. `None` with fewer than two advertising reports.
+    pub interval_estimate_us: Option<i64>,
+    advertising_timestamps: Vec<i64>,
+}
+
+impl Advertiser {
+    fn new(id: AdvertiserId, timestamp: i64) -> Self {
+        Self {
+            id,
+            addresses: Vec::new(),
+            reports: 0,
+            scan_responses: 0,
+            first_seen: timestamp,
+            last_seen: timestamp,
+            rssi: None,
+            data_variants: Vec::new(),
+            interval_estimate_us: None,
+            advertising_timestamps: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, report: &LeAdvertisingReport, data: &[u8], timestamp: i64) {
+        if !self.addresses.contains(&report.address) {
+            self.addresses.push(report.address);
+        }
+        self.reports += 1;
+        self.last_seen = timestamp;
+        let scan_response = report.is_scan_response();
+        if scan_response {
+            self.scan_responses += 1;
+        } else {
+            self.advertising_timestamps.push(timestamp);
+        }
+        if let Some(rssi) = report.rssi {
+            self.rssi = Some(match self.rssi {
+                Some((min, max)) => (min.min(rssi), max.max(rssi)),
+                None => (rssi, rssi),
+            });
+        }
+        match self
+            .data_variants
+            .iter_mut()
+            .find(|v| v.scan_response == scan_response && v.data == data)
+        {
+            Some(variant) => variant.count += 1,
+            None => self.data_variants.push(DataVariant {
+                scan_response,
+                data: data.to_vec(),
+                count: 1,
+            }),
+        }
+    }
+
+    fn finish(&mut self) {
+        let mut gaps: Vec<_> = self
+            .advertising_timestamps
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|gap| *gap > 0)
+            .collect();
+        gaps.sort_unstable();
+        self.interval_estimate_us = gaps.get(gaps.len() / 10).copied();
+    }
+
+    /// complete or shortened local name of the first variant advertising one
+    pub fn local_name(&self) -> Option<String> {
+        self.data_variants.iter().find_map(|variant| {
+            ad_structures(&variant.data)
+                .find(|(ad_type, _)| {
+                    *ad_type == AD_TYPE_COMPLETE_LOCAL_NAME
+                        || *ad_type == AD_TYPE_SHORTENED_LOCAL_NAME
+                })
+                .map(|(_, name)| String::from_utf8_lossy(name).into_owned())
+        })
+    }
+}
+
+pub const AD_TYPE_SHORTENED_LOCAL_NAME: u8 = 0x08;
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+
+/// AD structures of advertising or scan response data: (AD type, AD data)
+pub fn ad_structures(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let (&length, tail) = rest.split_first()?;
+        // a zero length field terminates the significant part of the data
+        let (&ad_type, tail) = tail.split_first().filter(|_| length > 0)?;
+        let ad_data = tail.get(..length as usize - 1)?;
+        rest = &tail[length as usize - 1..];
+        Some((ad_type, ad_data))
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// ordered by the first report
+    pub advertisers: Vec<Advertiser>,
+}
+
+impl Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for advertiser in &self.advertisers {
+            match advertiser.id {
+                AdvertiserId::Address { address, .. } => write!(f, "{}", address)?,
+                AdvertiserId::Irk(index) => write!(
+                    f,
+                    "IRK #{} ({} addresses)",
+                    index,
+                    advertiser.addresses.len()
+                )?,
+            }
+            if let Some(name) = advertiser.local_name() {
+                write!(f, " \"{}\"", name)?;
+            }
+            write!(
+                f,
+                ": {} reports, {} scan responses, {} data variants",
+                advertiser.reports,
+                advertiser.scan_responses,
+                advertiser.data_variants.len()
+            )?;
+            if let Some((min, max)) = advertiser.rssi {
+                write!(f, ", RSSI {} to {} dBm", min, max)?;
+            }
+            if let Some(interval) = advertiser.interval_estimate_us {
+                write!(f, ", interval ~{:.1} ms", interval as f64 / 1000.0)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collapse the LE Advertising Report and LE Extended Advertising Report events of the capture
+/// per advertiser. Fragmented extended advertising data is reassembled before it is compared.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn scan_report(capture: &Btsnoop, config: &ScanConfig) -> ScanReport {
+    let mut advertisers: Vec<Advertiser> = Vec::new();
+    let mut index: HashMap<AdvertiserId, usize> = HashMap::new();
+    // data of incomplete extended reports by (address, advertising SID)
+    let mut fragments: HashMap<(BdAddr, Option<u8>), Vec<u8>> = HashMap::new();
+
+    for (_, packet, data) in uart_packets(capture) {
+        let UartData::Event(event) = data else {
+            continue;
+        };
+        if event.code != Event::LE_META {
+            continue;
+        }
+        let Ok(LeMetaEvent::AdvertisingReport(reports)) = LeMetaEvent::try_from(event.params)
+        else {
+            continue;
+        };
+        let timestamp = packet.description.timestamp;
+        for report in reports {
+            let key = (report.address, report.advertising_sid);
+            if report.is_incomplete() {
+                fragments
+                    .entry(key)
+                    .or_default()
+                    .extend_from_slice(report.data);
+                continue;
+            }
+            let data = match fragments.remove(&key) {
+                Some(mut data) => {
+                    data.extend_from_slice(report.data);
+                    data
+                }
+                None => report.data.to_vec(),
+            };
+
+            let id = advertiser_id(&report, config);
+            let position = *index.entry(id).or_insert_with(|| {
+                advertisers.push(Advertiser::new(id, timestamp));
+                advertisers.len() - 1
+            });
+            advertisers[position].add(&report, &data, timestamp);
+        }
+    }
+
+    for advertiser in &mut advertisers {
+        advertiser.finish();
+    }
+    ScanReport { advertisers }
+}
+
+fn advertiser_id(report: &LeAdvertisingReport, config: &ScanConfig) -> AdvertiserId {
+    if report.address_type == LeAdvertisingReport::ADDRESS_TYPE_RANDOM
+        && is_resolvable_private_address(&report.address)
+    {
+        if let Some(irk) = config
+            .irks
+            .iter()
+            .position(|irk| resolve_private_address(irk, &report.address))
+        {
+            return AdvertiserId::Irk(irk);
+        }
+    }
+    AdvertiserId::Address {
+        address_type: report.address_type,
+        address: report.address,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4};
+
+    /// LE Advertising Report with one report
+    fn advertising_report(event_type: u8, address: [u8; 6], data: &[u8], rssi: i8) -> Vec<u8> {
+        let mut params = vec![LeMetaEvent::ADVERTISING_REPORT, 1, event_type, 0x01];
+        params.extend_from_slice(&address);
+        params.push(data.len() as u8);
+        params.extend_from_slice(data);
+        params.push(rssi as u8);
+        event(Event::LE_META, &params)
+    }
+
+    #[test]
+    fn collapse_reports_and_resolve_rpa() {
+        let irk = [
+            0xec, 0x02, 0x34, 0xa3, 0x57, 0xc8, 0xad, 0x05, 0x34, 0x10, 0x10, 0xa6, 0x0a, 0x39,
+            0x7d, 0x9b,
+        ];
+        // resolvable with the IRK above
+        let rpa = [0xaa, 0xfb, 0x0d, 0x94, 0x81, 0x70];
+        // another resolvable private address, not of that IRK
+        let other = [0x01, 0x02, 0x03, 0x04, 0x05, 0x46];
+        let name = [0x04, 0x09, b'a', b'b', b'c'];
+        let capture = h4(vec![
+            (0, true, advertising_report(0x00, rpa, &name, -60)),
+            (20_000, true, advertising_report(0x04, rpa, &[], -58)),
+            (100_000, true, advertising_report(0x00, rpa, &name, -70)),
+            (
+                150_000,
+                true,
+                advertising_report(0x03, other, &[0x02, 0x01, 0x06], 127),
+            ),
+            (200_000, true, advertising_report(0x00, rpa, &name, -65)),
+        ]);
+
+        let report = scan_report(&capture, &ScanConfig::default());
+        assert_eq!(report.advertisers.len(), 2);
+
+        let report = scan_report(&capture, &ScanConfig { irks: vec![irk] });
+        assert_eq!(report.advertisers.len(), 2);
+        let advertiser = &report.advertisers[0];
+        assert_eq!(advertiser.id, AdvertiserId::Irk(0));
+        assert_eq!(advertiser.reports, 4);
+        assert_eq!(advertiser.scan_responses, 1);
+        assert_eq!(advertiser.rssi, Some((-70, -58)));
+        assert_eq!(advertiser.data_variants.len(), 2);
+        assert_eq!(advertiser.data_variants[0].count, 3);
+        assert_eq!(advertiser.interval_estimate_us, Some(100_000));
+        assert_eq!(advertiser.local_name().as_deref(), Some("abc"));
+        assert_eq!(report.advertisers[1].rssi, None);
+        assert_eq!(report.advertisers[1].interval_estimate_us, None);
+    }
+}
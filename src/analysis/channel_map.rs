@@ -0,0 +1,300 @@
+//! Channel map history: how the Host's channel classification and the adaptive frequency hopping
+//! channel maps of the connections evolved over the capture, to follow coexistence issues.
+
+use std::fmt::{Debug, Display};
+
+use crate::{
+    analysis::uart_packets,
+    hci::{
+        CommandComplete, Event, LeReadChannelMap, LeSetHostChannelClassification,
+        ReadAfhChannelMap, SetAfhHostChannelClassification,
+    },
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    /// 79 channels of 1 MHz
+    BrEdr,
+    /// 37 data channels of 2 MHz
+    Le,
+}
+
+impl Band {
+    pub fn channel_count(&self) -> u8 {
+        match self {
+            Band::BrEdr => 79,
+            Band::Le => 37,
+        }
+    }
+}
+
+/// Channel bit map, channel n is bit n
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMap {
+    pub band: Band,
+    bits: u128,
+}
+
+impl ChannelMap {
+    /// from the little-endian map of the HCI parameters, bits beyond the band's channels are ignored
+    pub fn new(band: Band, map: &[u8]) -> Self {
+        let bits = map
+            .iter()
+            .take(16)
+            .enumerate()
+            .fold(0u128, |bits, (i, byte)| bits | (*byte as u128) << (i * 8));
+        let mask = (1u128 << band.channel_count()) - 1;
+        Self {
+            band,
+            bits: bits & mask,
+        }
+    }
+
+    /// all channels of the band in use
+    pub fn all(band: Band) -> Self {
+        Self {
+            band,
+            bits: (1u128 << band.channel_count()) - 1,
+        }
+    }
+
+    pub fn is_used(&self, channel: u8) -> bool {
+        channel < self.band.channel_count() && self.bits & 1 << channel != 0
+    }
+
+    pub fn used_count(&self) -> u32 {
+        self.bits.count_ones()
+    }
+
+    pub fn used(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.band.channel_count()).filter(|channel| self.is_used(*channel))
+    }
+}
+
+/// One character per channel, lowest first: `#` used, `.` unused
+impl Display for ChannelMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for channel in 0..self.band.channel_count() {
+            f.write_str(if self.is_used(channel) { "#" } else { "." })?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for ChannelMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChannelMap({:?}, {})", self.band, self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMapSource {
+    /// channels the Host classified, Set AFH Host Channel Classification or
+    /// LE Set Host Channel Classification. Unused channels are the ones known to be bad.
+    HostClassification(Band),
+    /// channels in use on a connection, as returned by Read AFH Channel Map or LE Read Channel Map
+    Connection(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMapUpdate {
+    /// the packet the map was learned from
+    pub packet_index: usize,
+    pub timestamp: i64,
+    pub source: ChannelMapSource,
+    pub map: ChannelMap,
+}
+
+/// Extract the channel maps of the capture, in capture order.
+///
+/// Host classifications are taken once their Command Complete reports success. A connection
+/// with AFH disabled hops over all the channels of the band, and is reported as such.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn channel_maps(capture: &Btsnoop) -> Vec<ChannelMapUpdate> {
+    let mut updates = Vec::new();
+    // the last classification sent for each band, waiting for its Command Complete
+    let mut pending: Vec<(u16, ChannelMap)> = Vec::new();
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let timestamp = packet.description.timestamp;
+        match data {
+            UartData::Command(command) => {
+                let mut params = command.params;
+                let map = match command.opcode.raw() {
+                    SetAfhHostChannelClassification::OPCODE => {
+                        SetAfhHostChannelClassification::parse(&mut params)
+                            .map(|c| ChannelMap::new(Band::BrEdr, &c.channel_map))
+                    }
+                    LeSetHostChannelClassification::OPCODE => {
+                        LeSetHostChannelClassification::parse(&mut params)
+                            .map(|c| ChannelMap::new(Band::Le, &c.channel_map))
+                    }
+                    _ => continue,
+                };
+                if let Ok(map) = map {
+                    pending.retain(|(opcode, _)| *opcode != command.opcode.raw());
+                    pending.push((command.opcode.raw(), map));
+                }
+            }
+            UartData::Event(event) if event.code == Event::COMMAND_COMPLETE => {
+                let Ok(complete) = CommandComplete::try_from(event.params) else {
+                    continue;
+                };
+                let mut params = complete.return_parameters;
+                let update = match complete.opcode.raw() {
+                    opcode @ (SetAfhHostChannelClassification::OPCODE
+                    | LeSetHostChannelClassification::OPCODE) => {
+                        let Some(position) = pending.iter().position(|(o, _)| *o == opcode) else {
+                            continue;
+                        };
+                        let (_, map) = pending.remove(position);
+                        (complete.status() == Some(0))
+                            .then_some((ChannelMapSource::HostClassification(map.band), map))
+                    }
+                    ReadAfhChannelMap::OPCODE => ReadAfhChannelMap::parse(&mut params)
+                        .ok()
+                        .filter(|r| r.status == 0)
+                        .map(|r| {
+                            let map = if r.afh_mode == 0 {
+                                ChannelMap::all(Band::BrEdr)
+                            } else {
+                                ChannelMap::new(Band::BrEdr, &r.channel_map)
+                            };
+                            (ChannelMapSource::Connection(r.handle), map)
+                        }),
+                    LeReadChannelMap::OPCODE => LeReadChannelMap::parse(&mut params)
+                        .ok()
+                        .filter(|r| r.status == 0)
+                        .map(|r| {
+                            (
+                                ChannelMapSource::Connection(r.handle),
+                                ChannelMap::new(Band::Le, &r.channel_map),
+                            )
+                        }),
+                    _ => None,
+                };
+                if let Some((source, map)) = update {
+                    updates.push(ChannelMapUpdate {
+                        packet_index,
+                        timestamp,
+                        source,
+                        map,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    updates
+}
+
+/// The channel map of `source` in effect at `timestamp`, i.e. the last one learned before it
+pub fn channel_map_at(
+    updates: &[ChannelMapUpdate],
+    source: ChannelMapSource,
+    timestamp: i64,
+) -> Option<ChannelMap> {
+    updates
+        .iter()
+        .take_while(|u| u.timestamp <= timestamp)
+        .filter(|u| u.source == source)
+        .last()
+        .map(|u| u.map)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4};
+
+    #[test]
+    fn host_classification_and_connection_maps() {
+        let capture = h4(vec![
+            // Set AFH Host Channel Classification: channels 0-7 bad
+            (
+                0,
+                false,
+                command(
+                    0x03,
+                    0x3F,
+                    &[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F],
+                ),
+            ),
+            (
+                10,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x3F, 0x0C, 0x00]),
+            ),
+            // LE Set Host Channel Classification rejected: Invalid HCI Command Parameters
+            (
+                20,
+                false,
+                command(0x08, 0x14, &[0x00, 0x00, 0x00, 0x00, 0x00]),
+            ),
+            (
+                30,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x14, 0x20, 0x12]),
+            ),
+            // LE Read Channel Map of 0x0040: channels 0-3 and 36
+            (
+                40,
+                true,
+                event(
+                    Event::COMMAND_COMPLETE,
+                    &[
+                        0x01, 0x15, 0x20, 0x00, 0x40, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x10,
+                    ],
+                ),
+            ),
+            // Read AFH Channel Map of 0x0001 with AFH disabled
+            (
+                50,
+                true,
+                event(
+                    Event::COMMAND_COMPLETE,
+                    &[
+                        0x01, 0x06, 0x14, 0x00, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    ],
+                ),
+            ),
+        ]);
+        let updates = channel_maps(&capture);
+        assert_eq!(updates.len(), 3);
+
+        assert_eq!(updates[0].packet_index, 1);
+        assert_eq!(
+            updates[0].source,
+            ChannelMapSource::HostClassification(Band::BrEdr)
+        );
+        assert_eq!(updates[0].map.used_count(), 71);
+        assert!(!updates[0].map.is_used(7));
+        assert!(updates[0].map.is_used(78));
+
+        assert_eq!(updates[1].source, ChannelMapSource::Connection(0x40));
+        assert_eq!(
+            updates[1].map.used().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 36]
+        );
+        assert_eq!(
+            updates[1].map.to_string(),
+            "####................................#"
+        );
+
+        assert_eq!(updates[2].map, ChannelMap::all(Band::BrEdr));
+
+        assert_eq!(
+            channel_map_at(&updates, ChannelMapSource::Connection(0x40), 39),
+            None
+        );
+        assert_eq!(
+            channel_map_at(&updates, ChannelMapSource::Connection(0x40), 100),
+            Some(updates[1].map)
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Command error report: every HCI command which finished with a non-success status and every
+//! ATT Error Response, grouped by the failed operation and the status.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    analysis::uart_packets,
+    att::{self, ErrorResponse},
+    hci::{self, CommandComplete, CommandStatus, Event},
+    l2cap::BasicFrame,
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailedOperation {
+    /// HCI command opcode, failed in its Command Status or Command Complete event
+    Command(u16),
+    /// ATT request opcode, answered with an Error Response
+    AttRequest(u8),
+}
+
+impl FailedOperation {
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            FailedOperation::Command(opcode) => hci::opcode::name(*opcode),
+            FailedOperation::AttRequest(opcode) => att::opcode_name(*opcode),
+        }
+    }
+
+    /// name of `status` in the error code space of this operation
+    pub fn status_name(&self, status: u8) -> Option<&'static str> {
+        match self {
+            FailedOperation::Command(_) => hci::error_code_name(status),
+            FailedOperation::AttRequest(_) => att::error_code_name(status),
+        }
+    }
+}
+
+impl Display for FailedOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.name().unwrap_or("Unknown");
+        match self {
+            FailedOperation::Command(opcode) => write!(f, "HCI {} (0x{:04X})", name, opcode),
+            FailedOperation::AttRequest(opcode) => write!(f, "ATT {} (0x{:02X})", name, opcode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorGroup {
+    pub operation: FailedOperation,
+    pub status: u8,
+    pub count: usize,
+    pub first_packet_index: usize,
+    pub first_timestamp: i64,
+}
+
+/// failures grouped by operation and status, in order of first occurrence
+#[derive(Debug, Default)]
+pub struct ErrorReport {
+    pub groups: Vec<ErrorGroup>,
+}
+
+impl ErrorReport {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// total number of failures
+    pub fn count(&self) -> usize {
+        self.groups.iter().map(|g| g.count).sum()
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for group in &self.groups {
+            writeln!(
+                f,
+                "{:>5} x {}: {} (0x{:02X}), first at packet #{}",
+                group.count,
+                group.operation,
+                group
+                    .operation
+                    .status_name(group.status)
+                    .unwrap_or("Unknown"),
+                group.status,
+                group.first_packet_index,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect every failed command and ATT request of the capture.
+///
+/// Commands with no return parameters (e.g. the Command Complete credits of opcode 0x0000) are skipped.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn command_errors(capture: &Btsnoop) -> ErrorReport {
+    let mut report = ErrorReport::default();
+    let mut index: HashMap<(FailedOperation, u8), usize> = HashMap::new();
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let failure = match data {
+            UartData::Event(event) if event.code == Event::COMMAND_STATUS => {
+                CommandStatus::parse(&mut &event.params[..])
+                    .ok()
+                    .filter(|s| s.status != 0)
+                    .map(|s| (FailedOperation::Command(s.opcode.raw()), s.status))
+            }
+            UartData::Event(event) if event.code == Event::COMMAND_COMPLETE => {
+                CommandComplete::try_from(event.params).ok().and_then(|c| {
+                    c.status()
+                        .filter(|status| *status != 0)
+                        .map(|status| (FailedOperation::Command(c.opcode.raw()), status))
+                })
+            }
+            UartData::Acl(acl) if acl.packet_boundary_flag.is_start() => {
+                BasicFrame::try_from(acl.data)
+                    .ok()
+                    .filter(|frame| frame.channel_id == BasicFrame::ATT_CID)
+                    .and_then(|frame| att::Pdu::try_from(frame.payload).ok())
+                    .filter(|pdu| pdu.opcode == att::Pdu::ERROR_RESPONSE)
+                    .and_then(|pdu| ErrorResponse::parse(&mut &pdu.params[..]).ok())
+                    .map(|e| (FailedOperation::AttRequest(e.request_opcode), e.error_code))
+            }
+            _ => None,
+        };
+        let Some((operation, status)) = failure else {
+            continue;
+        };
+        match index.get(&(operation, status)) {
+            Some(&group) => report.groups[group].count += 1,
+            None => {
+                index.insert((operation, status), report.groups.len());
+                report.groups.push(ErrorGroup {
+                    operation,
+                    status,
+                    count: 1,
+                    first_packet_index: packet_index,
+                    first_timestamp: packet.description.timestamp,
+                });
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4, l2cap};
+
+    #[test]
+    fn groups_failures() {
+        let capture = h4(vec![
+            // LE Create Connection: Command Disallowed
+            (
+                0,
+                true,
+                event(Event::COMMAND_STATUS, &[0x0C, 0x01, 0x0D, 0x20]),
+            ),
+            (
+                1,
+                true,
+                event(Event::COMMAND_STATUS, &[0x00, 0x01, 0x0D, 0x20]),
+            ),
+            (
+                2,
+                true,
+                event(Event::COMMAND_STATUS, &[0x0C, 0x01, 0x0D, 0x20]),
+            ),
+            // Reset: success
+            (
+                3,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x03, 0x0C, 0x00]),
+            ),
+            // Read Request on handle 0x0010: Insufficient Authentication
+            (
+                4,
+                true,
+                l2cap(0x40, BasicFrame::ATT_CID, &[0x01, 0x0A, 0x10, 0x00, 0x05]),
+            ),
+        ]);
+        let report = command_errors(&capture);
+        assert_eq!(report.count(), 3);
+        assert_eq!(
+            report.groups[0],
+            ErrorGroup {
+                operation: FailedOperation::Command(0x200D),
+                status: 0x0C,
+                count: 2,
+                first_packet_index: 0,
+                first_timestamp: 0,
+            }
+        );
+        assert_eq!(
+            report.groups[1].operation,
+            FailedOperation::AttRequest(0x0A)
+        );
+        assert_eq!(
+            report.to_string().lines().next(),
+            Some("    2 x HCI LE Create Connection (0x200D): Command Disallowed (0x0C), first at packet #0")
+        );
+    }
+}
@@ -0,0 +1,692 @@
+//! LE connection interval compliance: checks that the traffic observed on every LE connection is
+//! paced consistently with the negotiated connection interval and peripheral latency, and that
+//! connection parameter update requests were answered and applied.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{
+        BdAddr, CommandStatus, DisconnectionComplete, Event, LeConnectionUpdate, LeMetaEvent,
+        NumberOfCompletedPackets,
+    },
+    l2cap::{
+        BasicFrame, ConnectionParameterUpdateRequest, ConnectionParameterUpdateResponse,
+        SignalingCommand,
+    },
+    Btsnoop, UartData,
+};
+
+/// LE controller commands OGF
+const LE_OGF: u8 = 0x08;
+
+#[derive(Debug, Clone)]
+pub struct ComplianceConfig {
+    /// Packets closer than this are considered part of the same connection event.
+    /// It is also the slack allowed on every timing bound, to absorb host timestamping jitter.
+    pub jitter_us: i64,
+    /// How long a parameter update request may stay unanswered, or accepted but not applied,
+    /// before it's reported as ignored.
+    pub response_timeout_us: i64,
+}
+
+impl Default for ComplianceConfig {
+    fn default() -> Self {
+        Self {
+            jitter_us: 1_250,
+            // L2CAP RTX timer upper bound
+            response_timeout_us: 60_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionParameters {
+    /// Time = N × 1.25 ms
+    pub interval: u16,
+    /// number of connection events the peripheral may skip
+    pub latency: u16,
+    /// Time = N × 10 ms
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionParameters {
+    pub fn interval_us(&self) -> i64 {
+        self.interval as i64 * 1_250
+    }
+
+    pub fn supervision_timeout_us(&self) -> i64 {
+        self.supervision_timeout as i64 * 10_000
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOrigin {
+    /// L2CAP Connection Parameter Update Request on the LE signaling channel
+    L2capRequest,
+    /// LE Connection Update command issued by the local Host
+    LocalCommand,
+    /// LE Remote Connection Parameter Request event, the remote asked through the link layer
+    RemoteRequest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComplianceFinding {
+    /// Two received ACL packets were more than one connection event apart,
+    /// but closer than the negotiated connection interval.
+    IntervalViolation {
+        packet_index: usize,
+        gap_us: i64,
+        interval_us: i64,
+    },
+    /// A sent ACL packet wasn't reported completed within `latency + 1` connection intervals
+    /// after it became the oldest outstanding packet of the connection.
+    CompletionTooLate {
+        packet_index: usize,
+        delay_us: i64,
+        bound_us: i64,
+    },
+    /// The update was refused, `reason` is the L2CAP result or the HCI status / reason code
+    UpdateRejected {
+        packet_index: usize,
+        origin: UpdateOrigin,
+        reason: u16,
+    },
+    /// The update was never answered, or accepted but never applied
+    UpdateIgnored {
+        packet_index: usize,
+        origin: UpdateOrigin,
+    },
+    /// The applied connection interval is outside the range that was requested
+    UpdateOutOfRange {
+        packet_index: usize,
+        interval_min: u16,
+        interval_max: u16,
+        applied: u16,
+    },
+}
+
+impl Display for UpdateOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UpdateOrigin::L2capRequest => "L2CAP Connection Parameter Update Request",
+            UpdateOrigin::LocalCommand => "LE Connection Update",
+            UpdateOrigin::RemoteRequest => "LE Remote Connection Parameter Request",
+        })
+    }
+}
+
+impl Display for ComplianceFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |us: i64| us as f64 / 1000.0;
+        match self {
+            ComplianceFinding::IntervalViolation {
+                gap_us,
+                interval_us,
+                ..
+            } => write!(
+                f,
+                "received {:.2} ms after the previous packet, within the {:.2} ms connection interval",
+                ms(*gap_us),
+                ms(*interval_us)
+            ),
+            ComplianceFinding::CompletionTooLate {
+                delay_us, bound_us, ..
+            } => write!(
+                f,
+                "completed after {:.2} ms, expected within {:.2} ms",
+                ms(*delay_us),
+                ms(*bound_us)
+            ),
+            ComplianceFinding::UpdateRejected { origin, reason, .. } => {
+                write!(f, "{} rejected (0x{:04X})", origin, reason)
+            }
+            ComplianceFinding::UpdateIgnored { origin, .. } => write!(f, "{} ignored", origin),
+            ComplianceFinding::UpdateOutOfRange {
+                interval_min,
+                interval_max,
+                applied,
+                ..
+            } => write!(
+                f,
+                "applied interval {:.2} ms outside the requested {:.2} - {:.2} ms",
+                *applied as f64 * 1.25,
+                *interval_min as f64 * 1.25,
+                *interval_max as f64 * 1.25
+            ),
+        }
+    }
+}
+
+impl ComplianceFinding {
+    pub fn packet_index(&self) -> usize {
+        match self {
+            ComplianceFinding::IntervalViolation { packet_index, .. }
+            | ComplianceFinding::CompletionTooLate { packet_index, .. }
+            | ComplianceFinding::UpdateRejected { packet_index, .. }
+            | ComplianceFinding::UpdateIgnored { packet_index, .. }
+            | ComplianceFinding::UpdateOutOfRange { packet_index, .. } => *packet_index,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConnectionReport {
+    pub handle: u16,
+    pub peer_address: BdAddr,
+    /// 0 = Central, 1 = Peripheral
+    pub role: u8,
+    pub connected_at: i64,
+    pub disconnected_at: Option<i64>,
+    /// connection parameters with the timestamp from which they were in effect
+    pub parameters: Vec<(i64, ConnectionParameters)>,
+    pub findings: Vec<ComplianceFinding>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum UpdateState {
+    /// waiting for an answer
+    Requested,
+    /// waiting for the LE Connection Update Complete event
+    Accepted,
+}
+
+#[derive(Debug)]
+struct PendingUpdate {
+    packet_index: usize,
+    origin: UpdateOrigin,
+    state: UpdateState,
+    /// time of the last state change
+    since: i64,
+    /// the L2CAP signaling identifier
+    identifier: Option<u8>,
+    interval_min: u16,
+    interval_max: u16,
+}
+
+#[derive(Debug)]
+struct Connection {
+    report: ConnectionReport,
+    last_rx: Option<i64>,
+    /// sent but not completed ACL packets: (packet index, timestamp)
+    tx_queue: VecDeque<(usize, i64)>,
+    last_completion: Option<i64>,
+    pending: Vec<PendingUpdate>,
+}
+
+impl Connection {
+    fn parameters(&self) -> ConnectionParameters {
+        // a connection always starts with the parameters of the connection complete event
+        self.report.parameters.last().unwrap().1
+    }
+
+    fn expire(&mut self, now: i64, timeout_us: i64) {
+        let findings = &mut self.report.findings;
+        self.pending.retain(|p| {
+            if now.saturating_sub(p.since) > timeout_us {
+                findings.push(ComplianceFinding::UpdateIgnored {
+                    packet_index: p.packet_index,
+                    origin: p.origin,
+                });
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn reject(&mut self, index: usize, reason: u16) {
+        let pending = self.pending.remove(index);
+        self.report
+            .findings
+            .push(ComplianceFinding::UpdateRejected {
+                packet_index: pending.packet_index,
+                origin: pending.origin,
+                reason,
+            });
+    }
+
+    /// a parameter update was applied, everything waiting for it is resolved
+    fn applied(&mut self, timestamp: i64, parameters: ConnectionParameters) {
+        let (resolved, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|p| {
+                p.state == UpdateState::Accepted || p.origin == UpdateOrigin::LocalCommand
+            });
+        self.pending = pending;
+        // older requests may have been superseded, only the latest one has to match
+        if let Some(latest) = resolved.last() {
+            if !(latest.interval_min..=latest.interval_max).contains(&parameters.interval) {
+                self.report
+                    .findings
+                    .push(ComplianceFinding::UpdateOutOfRange {
+                        packet_index: latest.packet_index,
+                        interval_min: latest.interval_min,
+                        interval_max: latest.interval_max,
+                        applied: parameters.interval,
+                    });
+            }
+        }
+        self.report.parameters.push((timestamp, parameters));
+    }
+
+    /// a connection update failed with `status`, the oldest update waiting for it failed
+    fn update_failed(&mut self, status: u8) {
+        if let Some(index) = self.pending.iter().position(|p| {
+            p.state == UpdateState::Accepted || p.origin == UpdateOrigin::LocalCommand
+        }) {
+            self.reject(index, status as u16);
+        }
+    }
+
+    fn received(&mut self, packet_index: usize, timestamp: i64, jitter_us: i64) {
+        let interval_us = self.parameters().interval_us();
+        if let Some(last_rx) = self.last_rx {
+            let gap_us = timestamp.saturating_sub(last_rx);
+            if gap_us > jitter_us && gap_us < interval_us - jitter_us {
+                self.report
+                    .findings
+                    .push(ComplianceFinding::IntervalViolation {
+                        packet_index,
+                        gap_us,
+                        interval_us,
+                    });
+            }
+        }
+        self.last_rx = Some(timestamp);
+    }
+
+    fn completed(&mut self, count: u16, timestamp: i64, jitter_us: i64) {
+        let parameters = self.parameters();
+        let bound_us = (parameters.latency as i64 + 1) * parameters.interval_us() + jitter_us;
+        for _ in 0..count {
+            let Some((packet_index, sent)) = self.tx_queue.pop_front() else {
+                break;
+            };
+            let start = self.last_completion.map_or(sent, |last| last.max(sent));
+            let delay_us = timestamp.saturating_sub(start);
+            if delay_us > bound_us {
+                self.report
+                    .findings
+                    .push(ComplianceFinding::CompletionTooLate {
+                        packet_index,
+                        delay_us,
+                        bound_us,
+                    });
+            }
+            self.last_completion = Some(timestamp);
+        }
+    }
+
+    fn signaling(&mut self, packet_index: usize, timestamp: i64, command: SignalingCommand) {
+        let pending_l2cap = |pending: &Vec<PendingUpdate>| {
+            pending.iter().position(|p| {
+                p.origin == UpdateOrigin::L2capRequest
+                    && p.state == UpdateState::Requested
+                    && p.identifier == Some(command.identifier)
+            })
+        };
+        match command.code {
+            SignalingCommand::CONNECTION_PARAMETER_UPDATE_REQUEST => {
+                let Ok(request) = ConnectionParameterUpdateRequest::parse(&mut &command.data[..])
+                else {
+                    return;
+                };
+                self.pending.push(PendingUpdate {
+                    packet_index,
+                    origin: UpdateOrigin::L2capRequest,
+                    state: UpdateState::Requested,
+                    since: timestamp,
+                    identifier: Some(command.identifier),
+                    interval_min: request.interval_min,
+                    interval_max: request.interval_max,
+                });
+            }
+            SignalingCommand::CONNECTION_PARAMETER_UPDATE_RESPONSE => {
+                let Ok(response) = ConnectionParameterUpdateResponse::parse(&mut &command.data[..])
+                else {
+                    return;
+                };
+                let Some(index) = pending_l2cap(&self.pending) else {
+                    return;
+                };
+                if response.result == ConnectionParameterUpdateResponse::ACCEPTED {
+                    self.pending[index].state = UpdateState::Accepted;
+                    self.pending[index].since = timestamp;
+                } else {
+                    self.reject(index, response.result);
+                }
+            }
+            SignalingCommand::COMMAND_REJECT => {
+                if let Some(index) = pending_l2cap(&self.pending) {
+                    let reason = command
+                        .data
+                        .get(..2)
+                        .map_or(0, |r| u16::from_le_bytes([r[0], r[1]]));
+                    self.reject(index, reason);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Check every LE connection of the capture, connections are returned in the order they were established.
+///
+/// The timing checks rely on host timestamps, which are taken when the packet crosses the HCI
+/// transport rather than on air, so `config.jitter_us` should cover the transport latency.
+/// Controllers that batch Number Of Completed Packets events will show up as late completions.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn check_connection_intervals(
+    capture: &Btsnoop,
+    config: &ComplianceConfig,
+) -> Vec<ConnectionReport> {
+    let mut connections: HashMap<u16, Connection> = HashMap::new();
+    let mut reports = vec![];
+    // LE Connection Update commands waiting for their command status, by handle
+    let mut update_commands: VecDeque<u16> = VecDeque::new();
+    let mut now = 0;
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        now = packet.description.timestamp;
+        for connection in connections.values_mut() {
+            connection.expire(now, config.response_timeout_us);
+        }
+
+        match data {
+            UartData::Event(event) => match event.code {
+                Event::LE_META => match LeMetaEvent::try_from(event.params) {
+                    Ok(LeMetaEvent::ConnectionComplete(complete)) if complete.status == 0 => {
+                        let connection = Connection {
+                            report: ConnectionReport {
+                                handle: complete.handle,
+                                peer_address: complete.peer_address,
+                                role: complete.role,
+                                connected_at: now,
+                                disconnected_at: None,
+                                parameters: vec![(
+                                    now,
+                                    ConnectionParameters {
+                                        interval: complete.connection_interval,
+                                        latency: complete.peripheral_latency,
+                                        supervision_timeout: complete.supervision_timeout,
+                                    },
+                                )],
+                                findings: vec![],
+                            },
+                            last_rx: None,
+                            tx_queue: VecDeque::new(),
+                            last_completion: None,
+                            pending: vec![],
+                        };
+                        if let Some(old) = connections.insert(complete.handle, connection) {
+                            reports.push(old.report);
+                        }
+                    }
+                    Ok(LeMetaEvent::ConnectionUpdateComplete(update)) => {
+                        let Some(connection) = connections.get_mut(&update.handle) else {
+                            continue;
+                        };
+                        if update.status == 0 {
+                            connection.applied(
+                                now,
+                                ConnectionParameters {
+                                    interval: update.connection_interval,
+                                    latency: update.peripheral_latency,
+                                    supervision_timeout: update.supervision_timeout,
+                                },
+                            );
+                        } else {
+                            connection.update_failed(update.status);
+                        }
+                    }
+                    Ok(LeMetaEvent::RemoteConnectionParameterRequest(request)) => {
+                        if let Some(connection) = connections.get_mut(&request.handle) {
+                            connection.pending.push(PendingUpdate {
+                                packet_index,
+                                origin: UpdateOrigin::RemoteRequest,
+                                state: UpdateState::Requested,
+                                since: now,
+                                identifier: None,
+                                interval_min: request.interval_min,
+                                interval_max: request.interval_max,
+                            });
+                        }
+                    }
+                    _ => {}
+                },
+                Event::COMMAND_STATUS => {
+                    let Ok(status) = CommandStatus::parse(&mut &event.params[..]) else {
+                        continue;
+                    };
+                    if status.opcode.ogf() != LE_OGF
+                        || status.opcode.ocf() != LeConnectionUpdate::OCF
+                    {
+                        continue;
+                    }
+                    let Some(handle) = update_commands.pop_front() else {
+                        continue;
+                    };
+                    if status.status != 0 {
+                        if let Some(connection) = connections.get_mut(&handle) {
+                            connection.update_failed(status.status);
+                        }
+                    }
+                }
+                Event::NUMBER_OF_COMPLETED_PACKETS => {
+                    let Ok(completed) = NumberOfCompletedPackets::parse(&mut &event.params[..])
+                    else {
+                        continue;
+                    };
+                    for (handle, count) in completed.completed {
+                        if let Some(connection) = connections.get_mut(&handle) {
+                            connection.completed(count, now, config.jitter_us);
+                        }
+                    }
+                }
+                Event::DISCONNECTION_COMPLETE => {
+                    let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..])
+                    else {
+                        continue;
+                    };
+                    if disconnection.status != 0 {
+                        continue;
+                    }
+                    if let Some(mut connection) = connections.remove(&disconnection.handle) {
+                        // the connection is gone, nothing pending can be answered anymore
+                        connection.expire(now, -1);
+                        connection.report.disconnected_at = Some(now);
+                        reports.push(connection.report);
+                    }
+                }
+                _ => {}
+            },
+            UartData::Command(command) if command.opcode.ogf() == LE_OGF => {
+                let ocf = command.opcode.ocf();
+                if ocf == LeConnectionUpdate::OCF {
+                    let Ok(update) = LeConnectionUpdate::parse(&mut &command.params[..]) else {
+                        continue;
+                    };
+                    update_commands.push_back(update.handle);
+                    if let Some(connection) = connections.get_mut(&update.handle) {
+                        connection.pending.push(PendingUpdate {
+                            packet_index,
+                            origin: UpdateOrigin::LocalCommand,
+                            state: UpdateState::Requested,
+                            since: now,
+                            identifier: None,
+                            interval_min: update.interval_min,
+                            interval_max: update.interval_max,
+                        });
+                    }
+                } else if ocf == LeConnectionUpdate::REPLY_OCF
+                    || ocf == LeConnectionUpdate::NEGATIVE_REPLY_OCF
+                {
+                    let mut params = command.params;
+                    let Ok(handle) = crate::hci::read_handle(&mut params) else {
+                        continue;
+                    };
+                    let Some(connection) = connections.get_mut(&handle) else {
+                        continue;
+                    };
+                    let Some(index) = connection.pending.iter().position(|p| {
+                        p.origin == UpdateOrigin::RemoteRequest && p.state == UpdateState::Requested
+                    }) else {
+                        continue;
+                    };
+                    if ocf == LeConnectionUpdate::REPLY_OCF {
+                        connection.pending[index].state = UpdateState::Accepted;
+                        connection.pending[index].since = now;
+                    } else {
+                        let reason = params.first().copied().unwrap_or_default();
+                        connection.reject(index, reason as u16);
+                    }
+                }
+            }
+            UartData::Acl(acl) => {
+                let Some(connection) = connections.get_mut(&acl.handle) else {
+                    continue;
+                };
+                if is_received(packet) {
+                    connection.received(packet_index, now, config.jitter_us);
+                } else {
+                    connection.tx_queue.push_back((packet_index, now));
+                }
+                if !acl.packet_boundary_flag.is_start() {
+                    continue;
+                }
+                let Ok(frame) = BasicFrame::try_from(acl.data) else {
+                    continue;
+                };
+                if frame.channel_id != BasicFrame::LE_SIGNALING_CID {
+                    continue;
+                }
+                if let Ok(command) = SignalingCommand::try_from(frame.payload) {
+                    connection.signaling(packet_index, now, command);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (_, mut connection) in connections {
+        connection.expire(now, config.response_timeout_us);
+        reports.push(connection.report);
+    }
+    reports.sort_by_key(|r| r.connected_at);
+    reports
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    fn le_connection_complete(handle: u16, interval: u16, latency: u16) -> Vec<u8> {
+        let mut params = vec![LeMetaEvent::CONNECTION_COMPLETE, 0x00];
+        params.extend_from_slice(&handle.to_le_bytes());
+        params.extend_from_slice(&[0x00, 0x00, 1, 2, 3, 4, 5, 6]);
+        params.extend_from_slice(&interval.to_le_bytes());
+        params.extend_from_slice(&latency.to_le_bytes());
+        params.extend_from_slice(&400u16.to_le_bytes());
+        params.push(0x00);
+        event(Event::LE_META, &params)
+    }
+
+    #[test]
+    fn pacing_and_rejected_update() {
+        // 30 ms interval
+        let capture = h4(vec![
+            (0, true, le_connection_complete(0x40, 24, 0)),
+            (10_000, true, l2cap(0x40, BasicFrame::ATT_CID, &[0x1B])),
+            // 10 ms later, faster than the connection interval
+            (20_000, true, l2cap(0x40, BasicFrame::ATT_CID, &[0x1B])),
+            (50_000, true, l2cap(0x40, BasicFrame::ATT_CID, &[0x1B])),
+            (
+                80_000,
+                true,
+                l2cap(
+                    0x40,
+                    BasicFrame::LE_SIGNALING_CID,
+                    &[0x12, 0x07, 0x08, 0x00, 6, 0, 12, 0, 0, 0, 0x90, 0x01],
+                ),
+            ),
+            (
+                81_000,
+                false,
+                l2cap(
+                    0x40,
+                    BasicFrame::LE_SIGNALING_CID,
+                    &[0x13, 0x07, 0x02, 0x00, 0x01, 0x00],
+                ),
+            ),
+            (
+                90_000,
+                false,
+                command(
+                    LE_OGF,
+                    0x13,
+                    &[0x40, 0, 6, 0, 12, 0, 0, 0, 0x90, 0x01, 0, 0, 0, 0],
+                ),
+            ),
+        ]);
+        let reports = check_connection_intervals(&capture, &ComplianceConfig::default());
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            reports[0].findings,
+            vec![
+                ComplianceFinding::IntervalViolation {
+                    packet_index: 2,
+                    gap_us: 10_000,
+                    interval_us: 30_000,
+                },
+                ComplianceFinding::UpdateRejected {
+                    packet_index: 4,
+                    origin: UpdateOrigin::L2capRequest,
+                    reason: ConnectionParameterUpdateResponse::REJECTED,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unanswered_update_is_ignored_on_disconnect() {
+        let capture = h4(vec![
+            (0, true, le_connection_complete(0x40, 24, 0)),
+            (
+                10_000,
+                false,
+                command(
+                    LE_OGF,
+                    0x13,
+                    &[0x40, 0, 6, 0, 12, 0, 0, 0, 0x90, 0x01, 0, 0, 0, 0],
+                ),
+            ),
+            (
+                11_000,
+                true,
+                event(Event::COMMAND_STATUS, &[0x00, 0x01, 0x13, 0x20]),
+            ),
+            (
+                90_000,
+                true,
+                event(Event::DISCONNECTION_COMPLETE, &[0x00, 0x40, 0x00, 0x13]),
+            ),
+        ]);
+        let reports = check_connection_intervals(&capture, &ComplianceConfig::default());
+        assert_eq!(reports[0].disconnected_at, Some(90_000));
+        assert_eq!(
+            reports[0].findings,
+            vec![ComplianceFinding::UpdateIgnored {
+                packet_index: 1,
+                origin: UpdateOrigin::LocalCommand,
+            }]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Fuzzing seeds sliced out of a capture, one corpus per layer: the HCI commands and events, the
+//! payloads of the ACL packets, the reassembled L2CAP PDUs and the ATT PDUs. Real traffic makes
+//! seeds that get a fuzzer past the length and opcode checks of a Bluetooth stack.
+
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{DisconnectionComplete, Event},
+    l2cap::{BasicFrame, Reassembler},
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Layer {
+    /// HCI command packets: opcode, length and parameters
+    Command,
+    /// HCI event packets: code, length and parameters
+    Event,
+    /// data of the ACL packets, without their HCI header
+    Acl,
+    /// complete L2CAP PDUs, basic header included
+    L2cap,
+    /// ATT PDUs, from the opcode
+    Att,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 5] = [
+        Layer::Command,
+        Layer::Event,
+        Layer::Acl,
+        Layer::L2cap,
+        Layer::Att,
+    ];
+
+    /// directory of the corpus
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Command => "hci_command",
+            Layer::Event => "hci_event",
+            Layer::Acl => "acl",
+            Layer::L2cap => "l2cap",
+            Layer::Att => "att",
+        }
+    }
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    pub layer: Layer,
+    /// packet index of the first occurrence, of the first fragment for PDUs
+    pub packet_index: usize,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    pub data: Vec<u8>,
+}
+
+impl Seed {
+    /// `{dir}/{layer}/{packet index}`
+    pub fn path(&self, dir: impl AsRef<Path>) -> PathBuf {
+        dir.as_ref()
+            .join(self.layer.name())
+            .join(format!("{:06}", self.packet_index))
+    }
+}
+
+#[derive(Default)]
+struct Corpus {
+    seeds: Vec<Seed>,
+    /// (layer, data) of the seeds, fuzzers gain nothing from duplicates
+    seen: HashSet<(Layer, Vec<u8>)>,
+}
+
+impl Corpus {
+    fn push(&mut self, layer: Layer, packet_index: usize, sent: bool, data: &[u8]) {
+        if self.seen.insert((layer, data.to_vec())) {
+            self.seeds.push(Seed {
+                layer,
+                packet_index,
+                sent,
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    fn pdu(&mut self, pdu: crate::l2cap::Pdu) {
+        if !pdu.complete {
+            return;
+        }
+        let Ok(frame) = pdu.frame() else {
+            return;
+        };
+        self.push(Layer::L2cap, pdu.packet_index, pdu.sent, &pdu.data);
+        if frame.channel_id == BasicFrame::ATT_CID && !frame.payload.is_empty() {
+            let payload = &frame.payload[..frame.payload.len().min(frame.length as usize)];
+            self.push(Layer::Att, pdu.packet_index, pdu.sent, payload);
+        }
+    }
+}
+
+/// The distinct seeds of every layer, in order of their packet. Truncated packets and PDUs
+/// whose fragments were lost are left out.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn fuzz_seeds(capture: &Btsnoop) -> Vec<Seed> {
+    let mut corpus = Corpus::default();
+    let mut reassembler = Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let sent = !is_received(packet);
+        if packet.description.included_length < packet.description.original_length {
+            continue;
+        }
+        // the HCI packet, after the H4 packet type
+        let hci = &packet.data.0[1..];
+        match data {
+            UartData::Command(_) => corpus.push(Layer::Command, packet_index, sent, hci),
+            UartData::Event(event) => {
+                corpus.push(Layer::Event, packet_index, sent, hci);
+                if event.code == Event::DISCONNECTION_COMPLETE {
+                    let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..])
+                    else {
+                        continue;
+                    };
+                    for pdu in reassembler.disconnected(disconnection.handle) {
+                        corpus.pdu(pdu);
+                    }
+                }
+            }
+            UartData::Acl(acl) => {
+                corpus.push(Layer::Acl, packet_index, sent, acl.data);
+                for pdu in reassembler.push(&acl, sent, packet_index) {
+                    corpus.pdu(pdu);
+                }
+            }
+            UartData::Todos => {}
+        }
+    }
+    corpus.seeds
+}
+
+/// Write each seed to its own file under `dir`, see [`Seed::path`], creating the directory of
+/// each layer. Returns the number of files written.
+pub fn write_corpus(seeds: &[Seed], dir: impl AsRef<Path>) -> io::Result<usize> {
+    let mut layers = HashSet::new();
+    for seed in seeds {
+        let path = seed.path(&dir);
+        if layers.insert(seed.layer) {
+            fs::create_dir_all(dir.as_ref().join(seed.layer.name()))?;
+        }
+        fs::write(path, &seed.data)?;
+    }
+    Ok(seeds.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    #[test]
+    fn seeds() {
+        let read_request = l2cap(0x40, 0x0004, &[0x0a, 0x03, 0x00]);
+        let capture = h4(vec![
+            (0, false, command(0x03, 0x0003, &[])),
+            (1, true, event(0x0e, &[0x01, 0x03, 0x0c, 0x00])),
+            (2, false, read_request.clone()),
+            // a retransmission adds nothing
+            (3, false, read_request),
+            (4, true, l2cap(0x40, 0x0005, &[0x01, 0x01, 0x00, 0x00])),
+        ]);
+        let seeds = fuzz_seeds(&capture);
+        let layers: Vec<_> = seeds.iter().map(|seed| seed.layer).collect();
+        assert_eq!(
+            layers,
+            [
+                Layer::Command,
+                Layer::Event,
+                Layer::Acl,
+                Layer::L2cap,
+                Layer::Att,
+                Layer::Acl,
+                Layer::L2cap,
+            ]
+        );
+        assert_eq!(seeds[0].data, [0x03, 0x0c, 0x00]);
+        assert_eq!(seeds[2].data, [0x03, 0x00, 0x04, 0x00, 0x0a, 0x03, 0x00]);
+        assert_eq!(seeds[2].data, seeds[3].data);
+        assert_eq!(seeds[4].data, [0x0a, 0x03, 0x00]);
+        assert!(!seeds[6].sent);
+
+        let dir = std::env::temp_dir().join(format!("btsnoop-corpus-{}", std::process::id()));
+        assert_eq!(write_corpus(&seeds, &dir).unwrap(), 7);
+        assert_eq!(
+            fs::read(dir.join("att").join("000002")).unwrap(),
+            seeds[4].data
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+//! Data stall detection: periods where ACL data handed to the Controller made no progress,
+//! either because no Number Of Completed Packets event arrived for a connection, or because
+//! all the Controller's ACL buffers stayed occupied.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{
+        CommandComplete, ConnectionComplete, DisconnectionComplete, Event, LeMetaEvent,
+        LeReadBufferSize, NumberOfCompletedPackets, ReadBufferSize,
+    },
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone)]
+pub struct StallConfig {
+    /// minimal duration without progress to be reported
+    pub threshold_us: i64,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        Self {
+            threshold_us: 1_000_000,
+        }
+    }
+}
+
+/// The Controller's ACL buffers, LE connections use their own pool unless LE Read Buffer Size reported none
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BufferPool {
+    Acl,
+    Le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallKind {
+    /// the connection had outstanding packets but none of them completed
+    NoCompletion,
+    /// every buffer of the pool was occupied, the Host could not send on any connection of the pool
+    CreditsExhausted(BufferPool),
+}
+
+impl Display for StallKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StallKind::NoCompletion => f.write_str("no completed packets"),
+            StallKind::CreditsExhausted(BufferPool::Acl) => f.write_str("ACL buffers exhausted"),
+            StallKind::CreditsExhausted(BufferPool::Le) => f.write_str("LE ACL buffers exhausted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stall {
+    pub kind: StallKind,
+    /// The connection likely blocked: the stalled connection, or for exhausted credits
+    /// the one holding the oldest outstanding packet
+    pub handle: u16,
+    pub start: i64,
+    /// `None` when the stall lasted until the end of the capture
+    pub end: Option<i64>,
+    /// the packet which started the stall
+    pub packet_index: usize,
+    /// outstanding packets when the stall started
+    pub outstanding: usize,
+}
+
+impl Stall {
+    pub fn duration_us(&self, capture_end: i64) -> i64 {
+        self.end.unwrap_or(capture_end).saturating_sub(self.start)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// sent but not completed packets: (packet index, timestamp)
+    outstanding: VecDeque<(usize, i64)>,
+    /// when the connection last made progress while it had outstanding packets
+    since: i64,
+    /// packet from which no progress was made
+    since_index: usize,
+}
+
+#[derive(Debug)]
+struct Exhausted {
+    since: i64,
+    packet_index: usize,
+}
+
+#[derive(Debug, Default)]
+struct Tracker {
+    connections: HashMap<u16, Connection>,
+    le_handles: HashSet<u16>,
+    /// total buffers per pool, unknown until the buffer sizes were read
+    totals: HashMap<BufferPool, usize>,
+    exhausted: HashMap<BufferPool, Exhausted>,
+    stalls: Vec<Stall>,
+}
+
+impl Tracker {
+    fn pool(&self, handle: u16) -> BufferPool {
+        if self.le_handles.contains(&handle) && self.totals.contains_key(&BufferPool::Le) {
+            BufferPool::Le
+        } else {
+            BufferPool::Acl
+        }
+    }
+
+    fn outstanding(&self, pool: BufferPool) -> usize {
+        self.connections
+            .iter()
+            .filter(|(handle, _)| self.pool(**handle) == pool)
+            .map(|(_, c)| c.outstanding.len())
+            .sum()
+    }
+
+    /// connection with the oldest outstanding packet of the pool
+    fn oldest(&self, pool: BufferPool) -> Option<u16> {
+        self.connections
+            .iter()
+            .filter(|(handle, _)| self.pool(**handle) == pool)
+            .filter_map(|(handle, c)| c.outstanding.front().map(|(index, _)| (*index, *handle)))
+            .min()
+            .map(|(_, handle)| handle)
+    }
+
+    fn sent(&mut self, handle: u16, packet_index: usize, now: i64) {
+        let connection = self.connections.entry(handle).or_default();
+        if connection.outstanding.is_empty() {
+            connection.since = now;
+            connection.since_index = packet_index;
+        }
+        connection.outstanding.push_back((packet_index, now));
+
+        let pool = self.pool(handle);
+        if let Some(total) = self.totals.get(&pool) {
+            if self.outstanding(pool) >= *total && !self.exhausted.contains_key(&pool) {
+                self.exhausted.insert(
+                    pool,
+                    Exhausted {
+                        since: now,
+                        packet_index,
+                    },
+                );
+            }
+        }
+    }
+
+    /// `count` packets of `handle` left the Controller's buffers, `None` when the connection is gone
+    fn completed(&mut self, handle: u16, count: Option<u16>, threshold_us: i64, now: i64) {
+        let pool = self.pool(handle);
+        let Some(connection) = self.connections.get_mut(&handle) else {
+            return;
+        };
+        if connection.outstanding.is_empty() {
+            return;
+        }
+        if now.saturating_sub(connection.since) > threshold_us {
+            self.stalls.push(Stall {
+                kind: StallKind::NoCompletion,
+                handle,
+                start: connection.since,
+                end: Some(now),
+                packet_index: connection.since_index,
+                outstanding: connection.outstanding.len(),
+            });
+        }
+        let count = count.map_or(connection.outstanding.len(), |c| c as usize);
+        let blocked = self.oldest(pool);
+        let connection = self.connections.get_mut(&handle).unwrap();
+        connection
+            .outstanding
+            .drain(..count.min(connection.outstanding.len()));
+        connection.since = now;
+
+        if let Some(total) = self.totals.get(&pool) {
+            if self.outstanding(pool) < *total {
+                if let Some(exhausted) = self.exhausted.remove(&pool) {
+                    if now.saturating_sub(exhausted.since) > threshold_us {
+                        self.stalls.push(Stall {
+                            kind: StallKind::CreditsExhausted(pool),
+                            handle: blocked.unwrap_or(handle),
+                            start: exhausted.since,
+                            end: Some(now),
+                            packet_index: exhausted.packet_index,
+                            outstanding: *total,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(mut self, threshold_us: i64, now: i64) -> Vec<Stall> {
+        for (handle, connection) in &self.connections {
+            if !connection.outstanding.is_empty()
+                && now.saturating_sub(connection.since) > threshold_us
+            {
+                self.stalls.push(Stall {
+                    kind: StallKind::NoCompletion,
+                    handle: *handle,
+                    start: connection.since,
+                    end: None,
+                    packet_index: connection.since_index,
+                    outstanding: connection.outstanding.len(),
+                });
+            }
+        }
+        for (pool, exhausted) in &self.exhausted {
+            if now.saturating_sub(exhausted.since) > threshold_us {
+                self.stalls.push(Stall {
+                    kind: StallKind::CreditsExhausted(*pool),
+                    handle: self.oldest(*pool).unwrap_or_default(),
+                    start: exhausted.since,
+                    end: None,
+                    packet_index: exhausted.packet_index,
+                    outstanding: self.totals[pool],
+                });
+            }
+        }
+        self.stalls.sort_by_key(|s| (s.start, s.packet_index));
+        self.stalls
+    }
+}
+
+/// Find the stall intervals of the capture, ordered by their start.
+///
+/// Credit exhaustion can only be detected when the capture contains the Read Buffer Size /
+/// LE Read Buffer Size exchange, i.e. it was started before the Controller was initialized.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn data_stalls(capture: &Btsnoop, config: &StallConfig) -> Vec<Stall> {
+    let mut tracker = Tracker::default();
+    let mut now = 0;
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        now = packet.description.timestamp;
+        match data {
+            UartData::Acl(acl) if !is_received(packet) => {
+                tracker.sent(acl.handle, packet_index, now);
+            }
+            UartData::Event(event) => match event.code {
+                Event::NUMBER_OF_COMPLETED_PACKETS => {
+                    let Ok(completed) = NumberOfCompletedPackets::parse(&mut &event.params[..])
+                    else {
+                        continue;
+                    };
+                    for (handle, count) in completed.completed {
+                        tracker.completed(handle, Some(count), config.threshold_us, now);
+                    }
+                }
+                Event::DISCONNECTION_COMPLETE => {
+                    let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..])
+                    else {
+                        continue;
+                    };
+                    if disconnection.status == 0 {
+                        // the Controller frees the buffers of a disconnected link without reporting them
+                        tracker.completed(disconnection.handle, None, config.threshold_us, now);
+                        tracker.connections.remove(&disconnection.handle);
+                        tracker.le_handles.remove(&disconnection.handle);
+                    }
+                }
+                Event::CONNECTION_COMPLETE => {
+                    if let Ok(complete) = ConnectionComplete::parse(&mut &event.params[..]) {
+                        tracker.le_handles.remove(&complete.handle);
+                    }
+                }
+                Event::LE_META => {
+                    if let Ok(LeMetaEvent::ConnectionComplete(complete)) =
+                        LeMetaEvent::try_from(event.params)
+                    {
+                        if complete.status == 0 {
+                            tracker.le_handles.insert(complete.handle);
+                        }
+                    }
+                }
+                Event::COMMAND_COMPLETE => {
+                    let Ok(complete) = CommandComplete::try_from(event.params) else {
+                        continue;
+                    };
+                    let mut params = complete.return_parameters;
+                    match complete.opcode.raw() {
+                        ReadBufferSize::OPCODE => {
+                            if let Ok(size) = ReadBufferSize::parse(&mut params) {
+                                if size.status == 0 {
+                                    tracker.totals.insert(
+                                        BufferPool::Acl,
+                                        size.total_num_acl_data_packets as usize,
+                                    );
+                                }
+                            }
+                        }
+                        opcode @ (LeReadBufferSize::OPCODE_V1 | LeReadBufferSize::OPCODE_V2) => {
+                            let v2 = opcode == LeReadBufferSize::OPCODE_V2;
+                            if let Ok(size) = LeReadBufferSize::parse(&mut params, v2) {
+                                if size.status == 0 && size.total_num_le_acl_data_packets > 0 {
+                                    tracker.totals.insert(
+                                        BufferPool::Le,
+                                        size.total_num_le_acl_data_packets as usize,
+                                    );
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    tracker.finish(config.threshold_us, now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4, l2cap};
+    use crate::l2cap::BasicFrame;
+
+    #[test]
+    fn credits_and_completion_stalls() {
+        let acl = || l2cap(0x01, BasicFrame::ATT_CID, &[0x52, 0x10, 0x00]);
+        let capture = h4(vec![
+            // LE Read Buffer Size [v1]: 2 LE ACL buffers
+            (
+                0,
+                true,
+                event(
+                    Event::COMMAND_COMPLETE,
+                    &[0x01, 0x02, 0x20, 0x00, 0xFB, 0x00, 0x02],
+                ),
+            ),
+            (
+                1,
+                true,
+                event(
+                    Event::LE_META,
+                    &[
+                        0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 1, 2, 3, 4, 5, 6, 24, 0, 0, 0, 0x90,
+                        0x01, 0x00,
+                    ],
+                ),
+            ),
+            (10, false, acl()),
+            (20, false, acl()),
+            (
+                3_000_000,
+                true,
+                event(
+                    Event::NUMBER_OF_COMPLETED_PACKETS,
+                    &[0x01, 0x01, 0x00, 0x01, 0x00],
+                ),
+            ),
+        ]);
+        let stalls = data_stalls(&capture, &StallConfig::default());
+        assert_eq!(
+            stalls,
+            vec![
+                Stall {
+                    kind: StallKind::NoCompletion,
+                    handle: 0x01,
+                    start: 10,
+                    end: Some(3_000_000),
+                    packet_index: 2,
+                    outstanding: 2,
+                },
+                Stall {
+                    kind: StallKind::CreditsExhausted(BufferPool::Le),
+                    handle: 0x01,
+                    start: 20,
+                    end: Some(3_000_000),
+                    packet_index: 3,
+                    outstanding: 2,
+                },
+            ]
+        );
+    }
+}
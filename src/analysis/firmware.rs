@@ -0,0 +1,235 @@
+//! Broadcom and Cypress firmware patch downloads: the host sends Download Minidriver, then the
+//! patch as Write RAM commands and finally Launch RAM. The patch file they come from, the
+//! `.hcd`, is these commands one after the other without the H4 packet type, so the file can
+//! be rebuilt from the capture to tell which patch the controller runs.
+//!
+//! ```text
+//! | opcode 16 bit | parameter length 8 bit | address 32 bit | data |
+//! ```
+
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
+
+use crate::{
+    analysis::uart_packets,
+    hci::{CommandComplete, Event},
+    Btsnoop, UartData,
+};
+
+/// Download Minidriver, starts a download
+pub const DOWNLOAD_MINIDRIVER: u16 = 0xFC2E;
+/// Write RAM, a segment of the patch at an address
+pub const WRITE_RAM: u16 = 0xFC4C;
+/// Launch RAM, runs the patch
+pub const LAUNCH_RAM: u16 = 0xFC4E;
+
+/// A Write RAM command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub packet_index: usize,
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// A firmware download, from its Download Minidriver or first Write RAM command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwareDownload {
+    /// of the Download Minidriver command, none when the capture starts after it
+    pub minidriver_index: Option<usize>,
+    pub segments: Vec<Segment>,
+    /// packet index and address of the Launch RAM command
+    pub launch: Option<(usize, u32)>,
+    /// Write RAM commands which completed with an error
+    pub failed_writes: usize,
+    /// some of its commands were truncated in the capture, the image is incomplete
+    pub truncated: bool,
+}
+
+impl FirmwareDownload {
+    /// Bytes of the patch written
+    pub fn size(&self) -> usize {
+        self.segments.iter().map(|segment| segment.data.len()).sum()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.launch.is_some() && !self.truncated && self.failed_writes == 0
+    }
+
+    /// The contiguous address ranges written, in the order of the download, end excluded
+    pub fn ranges(&self) -> Vec<(u32, u32)> {
+        let mut ranges: Vec<(u32, u32)> = vec![];
+        for segment in &self.segments {
+            let end = segment.address.wrapping_add(segment.data.len() as u32);
+            match ranges.last_mut() {
+                Some((_, last)) if *last == segment.address => *last = end,
+                _ => ranges.push((segment.address, end)),
+            }
+        }
+        ranges
+    }
+
+    /// Write the download as a `.hcd` file: its Write RAM commands and Launch RAM
+    pub fn write_hcd<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut command = |opcode: u16, address: u32, data: &[u8]| {
+            let length = u8::try_from(data.len() + 4)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "segment too long"))?;
+            writer.write_all(&opcode.to_le_bytes())?;
+            writer.write_all(&[length])?;
+            writer.write_all(&address.to_le_bytes())?;
+            writer.write_all(data)
+        };
+        for segment in &self.segments {
+            command(WRITE_RAM, segment.address, &segment.data)?;
+        }
+        if let Some((_, address)) = self.launch {
+            command(LAUNCH_RAM, address, &[])?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FirmwareDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} Write RAM commands",
+            self.size(),
+            self.segments.len()
+        )?;
+        for (start, end) in self.ranges() {
+            write!(f, ", 0x{:08X}-0x{:08X}", start, end)?;
+        }
+        match self.launch {
+            Some((index, address)) => write!(f, ", launched at 0x{:08X} in #{}", address, index)?,
+            None => write!(f, ", not launched")?,
+        }
+        if self.failed_writes > 0 {
+            write!(f, ", {} writes failed", self.failed_writes)?;
+        }
+        if self.truncated {
+            write!(f, ", truncated")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every firmware download of the capture, in order
+pub fn firmware_downloads(capture: &Btsnoop) -> Vec<FirmwareDownload> {
+    let mut downloads: Vec<FirmwareDownload> = vec![];
+    // the last download is still going
+    let mut downloading = false;
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            UartData::Command(command) => {
+                let opcode = command.opcode.raw();
+                if opcode == DOWNLOAD_MINIDRIVER {
+                    downloads.push(FirmwareDownload {
+                        minidriver_index: Some(packet_index),
+                        ..Default::default()
+                    });
+                    downloading = true;
+                    continue;
+                }
+                if opcode != WRITE_RAM && opcode != LAUNCH_RAM {
+                    continue;
+                }
+                if !downloading {
+                    downloads.push(FirmwareDownload::default());
+                    downloading = true;
+                }
+                let download = downloads.last_mut().expect("download started");
+                let description = &packet.description;
+                download.truncated |= description.included_length < description.original_length;
+                let params = command.params;
+                let Some(address) = params.get(..4) else {
+                    download.truncated = true;
+                    continue;
+                };
+                let address = u32::from_le_bytes(address.try_into().unwrap_or_default());
+                if opcode == WRITE_RAM {
+                    download.segments.push(Segment {
+                        packet_index,
+                        address,
+                        data: params[4..].to_vec(),
+                    });
+                } else {
+                    download.launch = Some((packet_index, address));
+                    downloading = false;
+                }
+            }
+            UartData::Event(event) if event.code == Event::COMMAND_COMPLETE => {
+                let Ok(complete) = CommandComplete::try_from(event.params) else {
+                    continue;
+                };
+                if complete.opcode.raw() == WRITE_RAM && complete.status().is_some_and(|s| s != 0) {
+                    if let Some(download) = downloads.last_mut() {
+                        download.failed_writes += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    downloads
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4};
+
+    #[test]
+    fn rebuilds_hcd() {
+        let hcd: &[u8] = &[
+            0x4c, 0xfc, 0x07, 0x00, 0x00, 0x21, 0x00, 0x01, 0x02, 0x03, //
+            0x4c, 0xfc, 0x06, 0x03, 0x00, 0x21, 0x00, 0x04, 0x05, //
+            0x4c, 0xfc, 0x05, 0x00, 0x10, 0x22, 0x00, 0x06, //
+            0x4e, 0xfc, 0x04, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let mut packets = vec![
+            (0, false, command(0x3F, 0x002E, &[])),
+            (
+                1,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x2e, 0xfc, 0x00]),
+            ),
+        ];
+        let mut rest = hcd;
+        while !rest.is_empty() {
+            let length = rest[2] as usize + 3;
+            let mut data = vec![0x01];
+            data.extend_from_slice(&rest[..length]);
+            packets.push((2, false, data));
+            rest = &rest[length..];
+        }
+        // a write of a second download fails
+        packets.push((3, false, command(0x3F, 0x004C, &[0, 0, 0x21, 0, 9])));
+        packets.push((
+            4,
+            true,
+            event(Event::COMMAND_COMPLETE, &[0x01, 0x4c, 0xfc, 0x01]),
+        ));
+
+        let downloads = firmware_downloads(&h4(packets));
+        assert_eq!(downloads.len(), 2);
+        let download = &downloads[0];
+        assert!(download.is_complete());
+        assert_eq!(download.minidriver_index, Some(0));
+        assert_eq!(download.size(), 6);
+        assert_eq!(
+            download.ranges(),
+            [(0x0021_0000, 0x0021_0005), (0x0022_1000, 0x0022_1001)]
+        );
+        let mut written = vec![];
+        download.write_hcd(&mut written).unwrap();
+        assert_eq!(written, hcd);
+        assert_eq!(
+            download.to_string(),
+            "6 bytes in 3 Write RAM commands, 0x00210000-0x00210005, 0x00221000-0x00221001, launched at 0xFFFFFFFF in #5"
+        );
+        assert_eq!(downloads[1].failed_writes, 1);
+        assert!(!downloads[1].is_complete());
+    }
+}
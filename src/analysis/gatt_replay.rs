@@ -0,0 +1,620 @@
+//! GATT replay: the requests and commands the GATT client of each LE connection, the Central,
+//! sent to the peripheral, in order with the delays between them, the values they carried and
+//! what the peripheral answered, to reproduce an issue against the peripheral without
+//! transcribing the capture.
+//!
+//! The attribute handles are the ones of the capture: replaying assumes the peripheral has the
+//! same attribute database, the discovery procedures of the capture are not replayed. The
+//! attribute types discovered are kept so tools addressing characteristics by UUID can be
+//! used, see [`GattReplay::write_bleak_script`].
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Write},
+};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    att::Pdu,
+    gatt::{AttributeMap, Uuid},
+    hci::{BdAddr, DisconnectionComplete, Event, LeConnectionComplete, LeMetaEvent},
+    hexdump::hex_string,
+    l2cap::{BasicFrame, Reassembler},
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// the client's receive MTU
+    ExchangeMtu {
+        mtu: u16,
+    },
+    Read {
+        attribute: u16,
+    },
+    ReadBlob {
+        attribute: u16,
+        offset: u16,
+    },
+    /// a Write Request, or a Write Command without response
+    Write {
+        attribute: u16,
+        value: Vec<u8>,
+        with_response: bool,
+    },
+    PrepareWrite {
+        attribute: u16,
+        offset: u16,
+        value: Vec<u8>,
+    },
+    /// executes the prepared writes, or cancels them
+    ExecuteWrite {
+        execute: bool,
+    },
+}
+
+impl Operation {
+    /// The attribute it reads or writes
+    pub fn attribute(&self) -> Option<u16> {
+        match self {
+            Operation::Read { attribute }
+            | Operation::ReadBlob { attribute, .. }
+            | Operation::Write { attribute, .. }
+            | Operation::PrepareWrite { attribute, .. } => Some(*attribute),
+            Operation::ExchangeMtu { .. } | Operation::ExecuteWrite { .. } => None,
+        }
+    }
+
+    fn parse(pdu: &Pdu) -> Option<Self> {
+        let params = pdu.params;
+        let u16_at = |offset: usize| {
+            params
+                .get(offset..offset + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        };
+        let operation = match pdu.opcode {
+            Pdu::EXCHANGE_MTU_REQUEST => Operation::ExchangeMtu { mtu: u16_at(0)? },
+            Pdu::READ_REQUEST => Operation::Read {
+                attribute: u16_at(0)?,
+            },
+            Pdu::READ_BLOB_REQUEST => Operation::ReadBlob {
+                attribute: u16_at(0)?,
+                offset: u16_at(2)?,
+            },
+            Pdu::WRITE_REQUEST | Pdu::WRITE_COMMAND => Operation::Write {
+                attribute: u16_at(0)?,
+                value: params[2..].to_vec(),
+                with_response: pdu.opcode == Pdu::WRITE_REQUEST,
+            },
+            Pdu::PREPARE_WRITE_REQUEST => Operation::PrepareWrite {
+                attribute: u16_at(0)?,
+                offset: u16_at(2)?,
+                value: params.get(4..)?.to_vec(),
+            },
+            Pdu::EXECUTE_WRITE_REQUEST => Operation::ExecuteWrite {
+                execute: *params.first()? == 0x01,
+            },
+            _ => return None,
+        };
+        Some(operation)
+    }
+}
+
+/// e.g. `Write Request 0x0012: 0100`
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::ExchangeMtu { mtu } => write!(f, "Exchange MTU {}", mtu),
+            Operation::Read { attribute } => write!(f, "Read 0x{:04x}", attribute),
+            Operation::ReadBlob { attribute, offset } => {
+                write!(f, "Read Blob 0x{:04x} offset {}", attribute, offset)
+            }
+            Operation::Write {
+                attribute,
+                value,
+                with_response,
+            } => write!(
+                f,
+                "Write {} 0x{:04x}: {}",
+                if *with_response { "Request" } else { "Command" },
+                attribute,
+                hex_string(value)
+            ),
+            Operation::PrepareWrite {
+                attribute,
+                offset,
+                value,
+            } => write!(
+                f,
+                "Prepare Write 0x{:04x} offset {}: {}",
+                attribute,
+                offset,
+                hex_string(value)
+            ),
+            Operation::ExecuteWrite { execute: true } => f.write_str("Execute Write"),
+            Operation::ExecuteWrite { execute: false } => f.write_str("Cancel Prepared Writes"),
+        }
+    }
+}
+
+/// What the peripheral answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// the server's receive MTU
+    Mtu(u16),
+    /// value read, or echoed by a Prepare Write Response
+    Value(Vec<u8>),
+    /// written or executed
+    Done,
+    /// an ATT error code
+    Error(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayStep {
+    pub packet_index: usize,
+    /// since the previous step, or the connection for the first one
+    pub delay_us: i64,
+    pub operation: Operation,
+    /// type of the attribute, when the capture discovered it
+    pub uuid: Option<Uuid>,
+    /// none for commands and requests left unanswered
+    pub outcome: Option<Outcome>,
+}
+
+/// The GATT client operations of an LE connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattReplay {
+    pub handle: u16,
+    /// address type and address of the peripheral, when the capture has the connection
+    pub peer: Option<(u8, BdAddr)>,
+    /// whether the Host of the capture is the Central, the client replayed; none when the
+    /// capture has no connection event, the client is then the first side sending a request
+    pub local_central: Option<bool>,
+    /// the ATT MTU negotiated
+    pub mtu: Option<u16>,
+    /// whether the client ran discovery procedures
+    pub discovery: bool,
+    /// attribute types discovered, by attribute handle
+    pub attributes: Vec<(u16, Uuid)>,
+    pub steps: Vec<ReplayStep>,
+}
+
+impl GattReplay {
+    fn new(handle: u16) -> Self {
+        Self {
+            handle,
+            peer: None,
+            local_central: None,
+            mtu: None,
+            discovery: false,
+            attributes: vec![],
+            steps: vec![],
+        }
+    }
+
+    /// How a bleak script addresses a characteristic: by UUID when only one attribute has it,
+    /// by handle otherwise
+    fn characteristic(&self, step: &ReplayStep, attribute: u16) -> String {
+        match step.uuid {
+            Some(uuid)
+                if self
+                    .attributes
+                    .iter()
+                    .filter(|(_, other)| *other == uuid)
+                    .count()
+                    == 1 =>
+            {
+                format!("\"{}\"", uuid.to_full_string())
+            }
+            _ => format!("0x{:04x}", attribute),
+        }
+    }
+
+    /// Write a Python script replaying the operations with [bleak](https://github.com/hbldh/bleak),
+    /// the peripheral address given as its argument. Descriptors, the attributes of types
+    /// 0x2900 to 0x29FF, are written by handle; reads of a characteristic from an offset and
+    /// prepared writes have no bleak counterpart and are replayed as whole reads and writes.
+    pub fn write_bleak_script<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let address = self
+            .peer
+            .map(|(_, address)| address.to_string())
+            .unwrap_or_default();
+        writeln!(writer, "#!/usr/bin/env python3")?;
+        writeln!(
+            writer,
+            "# GATT replay of connection 0x{:04x}, generated by btsnoop",
+            self.handle
+        )?;
+        writeln!(writer, "import asyncio")?;
+        writeln!(writer, "import sys")?;
+        writeln!(writer)?;
+        writeln!(writer, "from bleak import BleakClient")?;
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "ADDRESS = sys.argv[1] if len(sys.argv) > 1 else \"{}\"",
+            address
+        )?;
+        writeln!(writer)?;
+        writeln!(writer)?;
+        writeln!(writer, "async def main():")?;
+        writeln!(writer, "    async with BleakClient(ADDRESS) as client:")?;
+        if self.steps.is_empty() {
+            writeln!(writer, "        pass")?;
+        }
+        // values of the prepared writes not executed yet
+        let mut prepared: Vec<(u16, &ReplayStep, Vec<u8>)> = vec![];
+        for step in &self.steps {
+            if step.delay_us > 0 {
+                writeln!(
+                    writer,
+                    "        await asyncio.sleep({:.6})",
+                    step.delay_us as f64 / 1e6
+                )?;
+            }
+            let outcome = match &step.outcome {
+                Some(Outcome::Value(value)) => format!("  # captured: {}", hex_string(value)),
+                Some(Outcome::Error(code)) => format!("  # captured: error 0x{:02x}", code),
+                _ => String::new(),
+            };
+            let descriptor = step
+                .uuid
+                .and_then(|uuid| uuid.as_u16())
+                .is_some_and(|alias| (0x2900..=0x29FF).contains(&alias));
+            match &step.operation {
+                Operation::ExchangeMtu { mtu } => writeln!(
+                    writer,
+                    "        # MTU {} requested, bleak negotiates it when connecting",
+                    mtu
+                )?,
+                Operation::Read { attribute } | Operation::ReadBlob { attribute, .. } => {
+                    if descriptor {
+                        writeln!(
+                            writer,
+                            "        await client.read_gatt_descriptor(0x{:04x}){}",
+                            attribute, outcome
+                        )?
+                    } else {
+                        writeln!(
+                            writer,
+                            "        await client.read_gatt_char({}){}",
+                            self.characteristic(step, *attribute),
+                            outcome
+                        )?
+                    }
+                }
+                Operation::Write {
+                    attribute,
+                    value,
+                    with_response,
+                } => {
+                    if descriptor {
+                        writeln!(
+                            writer,
+                            "        await client.write_gatt_descriptor(0x{:04x}, bytes.fromhex(\"{}\")){}",
+                            attribute,
+                            hex_string(value),
+                            outcome
+                        )?
+                    } else {
+                        writeln!(
+                            writer,
+                            "        await client.write_gatt_char({}, bytes.fromhex(\"{}\"), response={}){}",
+                            self.characteristic(step, *attribute),
+                            hex_string(value),
+                            if *with_response { "True" } else { "False" },
+                            outcome
+                        )?
+                    }
+                }
+                Operation::PrepareWrite {
+                    attribute,
+                    offset,
+                    value,
+                } => {
+                    match prepared.iter_mut().find(|(a, _, _)| a == attribute) {
+                        Some((_, _, prepared)) => {
+                            let offset = *offset as usize;
+                            prepared.resize(prepared.len().max(offset + value.len()), 0);
+                            prepared[offset..offset + value.len()].copy_from_slice(value);
+                        }
+                        None => {
+                            let mut prepared_value = vec![0; *offset as usize];
+                            prepared_value.extend_from_slice(value);
+                            prepared.push((*attribute, step, prepared_value));
+                        }
+                    }
+                    writeln!(
+                        writer,
+                        "        # prepared write of 0x{:04x} at offset {}",
+                        attribute, offset
+                    )?
+                }
+                Operation::ExecuteWrite { execute } => {
+                    for (attribute, first, value) in prepared.drain(..) {
+                        if *execute {
+                            writeln!(
+                                writer,
+                                "        await client.write_gatt_char({}, bytes.fromhex(\"{}\"), response=True){}",
+                                self.characteristic(first, attribute),
+                                hex_string(&value),
+                                outcome
+                            )?;
+                        }
+                    }
+                    if !*execute {
+                        writeln!(writer, "        # prepared writes cancelled")?;
+                    }
+                }
+            }
+        }
+        writeln!(writer)?;
+        writeln!(writer)?;
+        writeln!(writer, "asyncio.run(main())")
+    }
+}
+
+#[derive(Default)]
+struct Connection {
+    /// index in the replays
+    replay: usize,
+    /// the Host of the capture is the client
+    local_client: Option<bool>,
+    /// timestamp of the connection or of the last step
+    last: Option<i64>,
+    /// step waiting for its response
+    pending: Option<usize>,
+    client_mtu: Option<u16>,
+}
+
+const DISCOVERY_REQUESTS: [u8; 4] = [
+    Pdu::FIND_INFORMATION_REQUEST,
+    Pdu::FIND_BY_TYPE_VALUE_REQUEST,
+    Pdu::READ_BY_TYPE_REQUEST,
+    Pdu::READ_BY_GROUP_TYPE_REQUEST,
+];
+
+/// The GATT client operations of every LE connection which had some, in order of connection
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn gatt_replays(capture: &Btsnoop) -> Vec<GattReplay> {
+    let mut replays: Vec<GattReplay> = vec![];
+    let mut connections: HashMap<u16, Connection> = HashMap::new();
+    let mut attributes = AttributeMap::default();
+    let mut reassembler = Reassembler::default();
+
+    let open = |replays: &mut Vec<GattReplay>, handle: u16| {
+        replays.push(GattReplay::new(handle));
+        Connection {
+            replay: replays.len() - 1,
+            ..Default::default()
+        }
+    };
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let now = packet.description.timestamp;
+        match data {
+            UartData::Event(event) if event.code == Event::LE_META => {
+                let Ok(LeMetaEvent::ConnectionComplete(complete)) =
+                    LeMetaEvent::try_from(event.params)
+                else {
+                    continue;
+                };
+                if complete.status != 0 {
+                    continue;
+                }
+                let mut connection = open(&mut replays, complete.handle);
+                let central = complete.role == LeConnectionComplete::ROLE_CENTRAL;
+                connection.local_client = Some(central);
+                connection.last = Some(now);
+                let replay = &mut replays[connection.replay];
+                replay.peer = Some((complete.peer_address_type, complete.peer_address));
+                replay.local_central = Some(central);
+                connections.insert(complete.handle, connection);
+            }
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                if let Some(connection) = connections.remove(&disconnection.handle) {
+                    replays[connection.replay].attributes =
+                        attributes.attributes(disconnection.handle);
+                }
+                attributes.disconnected(disconnection.handle);
+                reassembler.disconnected(disconnection.handle);
+            }
+            UartData::Acl(acl) => {
+                let sent = !is_received(packet);
+                for l2cap in reassembler.push(&acl, sent, packet_index) {
+                    let Ok(frame) = l2cap.frame() else {
+                        continue;
+                    };
+                    if !l2cap.complete || frame.channel_id != BasicFrame::ATT_CID {
+                        continue;
+                    }
+                    let Ok(pdu) = Pdu::try_from(frame.payload) else {
+                        continue;
+                    };
+                    let attribute = attributes.update(acl.handle, sent, &pdu);
+                    let connection = connections
+                        .entry(acl.handle)
+                        .or_insert_with(|| open(&mut replays, acl.handle));
+                    let replay = &mut replays[connection.replay];
+                    let operation = Operation::parse(&pdu);
+                    let request = operation.is_some() || DISCOVERY_REQUESTS.contains(&pdu.opcode);
+                    if request && connection.local_client.is_none() {
+                        connection.local_client = Some(sent);
+                    }
+                    if connection.local_client != Some(sent) {
+                        // a response of the server
+                        let Some(step) = connection.pending.take() else {
+                            continue;
+                        };
+                        let params = pdu.params;
+                        let outcome = match pdu.opcode {
+                            Pdu::EXCHANGE_MTU_RESPONSE => {
+                                let Some(mtu) = params.get(..2) else {
+                                    continue;
+                                };
+                                let mtu = u16::from_le_bytes([mtu[0], mtu[1]]);
+                                replay.mtu = connection.client_mtu.map(|client| client.min(mtu));
+                                Outcome::Mtu(mtu)
+                            }
+                            Pdu::READ_RESPONSE | Pdu::READ_BLOB_RESPONSE => {
+                                Outcome::Value(params.to_vec())
+                            }
+                            Pdu::PREPARE_WRITE_RESPONSE => {
+                                Outcome::Value(params.get(4..).unwrap_or_default().to_vec())
+                            }
+                            Pdu::WRITE_RESPONSE | Pdu::EXECUTE_WRITE_RESPONSE => Outcome::Done,
+                            Pdu::ERROR_RESPONSE => {
+                                Outcome::Error(params.get(3).copied().unwrap_or_default())
+                            }
+                            _ => {
+                                // a notification or indication, the request is still waiting
+                                connection.pending = Some(step);
+                                continue;
+                            }
+                        };
+                        replay.steps[step].outcome = Some(outcome);
+                        continue;
+                    }
+                    if DISCOVERY_REQUESTS.contains(&pdu.opcode) {
+                        replay.discovery = true;
+                        continue;
+                    }
+                    let Some(operation) = operation else {
+                        continue;
+                    };
+                    if let Operation::ExchangeMtu { mtu } = operation {
+                        connection.client_mtu = Some(mtu);
+                    }
+                    if pdu.opcode != Pdu::WRITE_COMMAND {
+                        connection.pending = Some(replay.steps.len());
+                    }
+                    let uuid = attribute
+                        .or(operation.attribute())
+                        .and_then(|attribute| attributes.uuid(acl.handle, attribute));
+                    replay.steps.push(ReplayStep {
+                        packet_index,
+                        delay_us: connection.last.map_or(0, |last| now.saturating_sub(last)),
+                        operation,
+                        uuid,
+                        outcome: None,
+                    });
+                    connection.last = Some(now);
+                }
+            }
+            _ => {}
+        }
+    }
+    for (handle, connection) in connections {
+        replays[connection.replay].attributes = attributes.attributes(handle);
+    }
+    replays.retain(|replay| !replay.steps.is_empty());
+    replays
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4, l2cap};
+
+    #[test]
+    fn replays_central_operations() {
+        let mut connection = vec![
+            LeMetaEvent::CONNECTION_COMPLETE,
+            0x00,
+            0x40,
+            0x00,
+            0x00,
+            0x01,
+        ];
+        connection.extend_from_slice(&[6, 5, 4, 3, 2, 0xc1]);
+        connection.extend_from_slice(&[24, 0, 0, 0, 0x90, 0x01, 0x00]);
+        let att = |payload: &[u8]| l2cap(0x40, BasicFrame::ATT_CID, payload);
+        let nus_rx = Uuid(0x6e40_0002_b5a3_f393_e0a9_e50e_24dc_ca9e);
+        let mut characteristics = vec![0x09, 0x15, 0x11, 0x00, 0x0c, 0x12, 0x00];
+        characteristics.extend_from_slice(&nus_rx.0.to_le_bytes());
+
+        let capture = h4(vec![
+            (0, true, event(Event::LE_META, &connection)),
+            (1_000, false, att(&[0x02, 0xf7, 0x00])),
+            (2_000, true, att(&[0x03, 0x17, 0x00])),
+            // characteristic discovery
+            (
+                3_000,
+                false,
+                att(&[0x08, 0x01, 0x00, 0xff, 0xff, 0x03, 0x28]),
+            ),
+            (4_000, true, att(&characteristics)),
+            (5_000, false, att(&[0x04, 0x13, 0x00, 0x13, 0x00])),
+            (6_000, true, att(&[0x05, 0x01, 0x13, 0x00, 0x02, 0x29])),
+            (7_000, false, att(&[0x12, 0x13, 0x00, 0x01, 0x00])),
+            (8_000, true, att(&[0x13])),
+            (10_000, false, att(&[0x52, 0x12, 0x00, b'h', b'i'])),
+            (11_000, false, att(&[0x0a, 0x12, 0x00])),
+            // a notification before the response
+            (11_500, true, att(&[0x1b, 0x12, 0x00, 0x2a])),
+            (12_000, true, att(&[0x01, 0x0a, 0x12, 0x00, 0x02])),
+        ]);
+        let replays = gatt_replays(&capture);
+        assert_eq!(replays.len(), 1);
+        let replay = &replays[0];
+        assert_eq!(replay.peer, Some((0x01, BdAddr([6, 5, 4, 3, 2, 0xc1]))));
+        assert_eq!(replay.local_central, Some(true));
+        assert_eq!(replay.mtu, Some(23));
+        assert!(replay.discovery);
+        assert_eq!(
+            replay.attributes,
+            [(0x12, nus_rx), (0x13, Uuid::from_u16(0x2902))]
+        );
+
+        let steps: Vec<_> = replay
+            .steps
+            .iter()
+            .map(|step| {
+                (
+                    step.delay_us,
+                    step.operation.to_string(),
+                    step.outcome.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (
+                    1_000,
+                    "Exchange MTU 247".to_string(),
+                    Some(Outcome::Mtu(23))
+                ),
+                (
+                    6_000,
+                    "Write Request 0x0013: 0100".to_string(),
+                    Some(Outcome::Done)
+                ),
+                (3_000, "Write Command 0x0012: 6869".to_string(), None),
+                (1_000, "Read 0x0012".to_string(), Some(Outcome::Error(0x02))),
+            ]
+        );
+        assert_eq!(replay.steps[2].uuid, Some(nus_rx));
+
+        let mut script = vec![];
+        replay.write_bleak_script(&mut script).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script
+            .contains("ADDRESS = sys.argv[1] if len(sys.argv) > 1 else \"C1:02:03:04:05:06\""));
+        assert!(script.contains(
+            "        await client.write_gatt_descriptor(0x0013, bytes.fromhex(\"0100\"))\n"
+        ));
+        assert!(script.contains(
+            "        await client.write_gatt_char(\"6e400002-b5a3-f393-e0a9-e50e24dcca9e\", bytes.fromhex(\"6869\"), response=False)\n"
+        ));
+        assert!(script.contains("        await asyncio.sleep(0.003000)\n"));
+    }
+}
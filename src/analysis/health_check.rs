@@ -0,0 +1,400 @@
+//! Capture health check: structural sanity of the capture itself, beyond what strict parsing
+//! rejects, to tell whether the capture can be trusted before analyzing it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    hci::{
+        CommandComplete, CommandStatus, ConnectionComplete, DisconnectionComplete, Event,
+        LeMetaEvent,
+    },
+    parse_uart_packet, Btsnoop, DatalinkType, Packet, UartData, UartPacketType,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HealthCategory {
+    Timestamps,
+    Lengths,
+    Flags,
+    Commands,
+    Connections,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthIssue {
+    /// the packet is timestamped before the previous one
+    TimestampWentBackwards { previous: i64, timestamp: i64 },
+    /// more octets included than the original packet had
+    IncludedLengthExceedsOriginal { included: u32, original: u32 },
+    /// the cumulative drops count decreased
+    DropsWentBackwards { previous: u32, drops: u32 },
+    /// the packet data is too short for the HCI header of its packet type
+    TruncatedHeader,
+    /// the HCI header announces a different length than the (untruncated) packet data carries
+    HciLengthMismatch { announced: usize, actual: usize },
+    /// the H4 packet type indicator is not a known one
+    UnknownPacketType(u8),
+    /// the direction or command flags contradict the H4 packet type indicator
+    FlagsContradictPacketType { packet_type: u8, flags: u32 },
+    /// Command Complete or Command Status for a command that was not sent
+    UnmatchedCommandResponse { opcode: u16 },
+    /// ACL data on a connection after its Disconnection Complete
+    TrafficAfterDisconnection { handle: u16 },
+}
+
+impl HealthIssue {
+    pub fn category(&self) -> HealthCategory {
+        match self {
+            HealthIssue::TimestampWentBackwards { .. } => HealthCategory::Timestamps,
+            HealthIssue::IncludedLengthExceedsOriginal { .. }
+            | HealthIssue::DropsWentBackwards { .. }
+            | HealthIssue::TruncatedHeader
+            | HealthIssue::HciLengthMismatch { .. } => HealthCategory::Lengths,
+            HealthIssue::UnknownPacketType(_) | HealthIssue::FlagsContradictPacketType { .. } => {
+                HealthCategory::Flags
+            }
+            HealthIssue::UnmatchedCommandResponse { .. } => HealthCategory::Commands,
+            HealthIssue::TrafficAfterDisconnection { .. } => HealthCategory::Connections,
+        }
+    }
+}
+
+impl Display for HealthIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthIssue::TimestampWentBackwards {
+                previous,
+                timestamp,
+            } => write!(
+                f,
+                "timestamp {} us before the previous packet",
+                previous - timestamp
+            ),
+            HealthIssue::IncludedLengthExceedsOriginal { included, original } => write!(
+                f,
+                "included length {} exceeds original length {}",
+                included, original
+            ),
+            HealthIssue::DropsWentBackwards { previous, drops } => write!(
+                f,
+                "cumulative drops went from {} down to {}",
+                previous, drops
+            ),
+            HealthIssue::TruncatedHeader => f.write_str("packet shorter than its HCI header"),
+            HealthIssue::HciLengthMismatch { announced, actual } => write!(
+                f,
+                "HCI header announces {} octets, packet carries {}",
+                announced, actual
+            ),
+            HealthIssue::UnknownPacketType(packet_type) => {
+                write!(f, "unknown packet type 0x{:02X}", packet_type)
+            }
+            HealthIssue::FlagsContradictPacketType { packet_type, flags } => write!(
+                f,
+                "flags 0x{:X} contradict packet type 0x{:02X}",
+                flags, packet_type
+            ),
+            HealthIssue::UnmatchedCommandResponse { opcode } => {
+                write!(f, "response to unsent command 0x{:04X}", opcode)
+            }
+            HealthIssue::TrafficAfterDisconnection { handle } => {
+                write!(f, "ACL data on handle 0x{:04X} after disconnection", handle)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthFinding {
+    pub packet_index: usize,
+    pub issue: HealthIssue,
+}
+
+/// Check the capture's structure, findings are in capture order.
+///
+/// Record level checks apply to every datalink type, the HCI level ones only to HCI UART (H4).
+/// A capture started after the Host sent some commands reports their responses as unmatched.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn health_check(capture: &Btsnoop) -> Vec<HealthFinding> {
+    let mut findings = Vec::new();
+    let h4 = matches!(capture.header.datalink_type, DatalinkType::Uart);
+    let mut previous: Option<&Packet> = None;
+    // commands sent and not answered yet, by opcode
+    let mut outstanding: HashMap<u16, usize> = HashMap::new();
+    let mut disconnected: HashSet<u16> = HashSet::new();
+
+    for (packet_index, packet) in capture.packets.iter().enumerate() {
+        let mut report = |issue| {
+            findings.push(HealthFinding {
+                packet_index,
+                issue,
+            })
+        };
+        let description = &packet.description;
+        if let Some(previous) = previous {
+            if description.timestamp < previous.description.timestamp {
+                report(HealthIssue::TimestampWentBackwards {
+                    previous: previous.description.timestamp,
+                    timestamp: description.timestamp,
+                });
+            }
+            if description.cumulative_drops < previous.description.cumulative_drops {
+                report(HealthIssue::DropsWentBackwards {
+                    previous: previous.description.cumulative_drops,
+                    drops: description.cumulative_drops,
+                });
+            }
+        }
+        previous = Some(packet);
+        if description.included_length > description.original_length {
+            report(HealthIssue::IncludedLengthExceedsOriginal {
+                included: description.included_length,
+                original: description.original_length,
+            });
+        }
+        if !h4 {
+            continue;
+        }
+
+        if let Some(issue) = check_h4_structure(packet) {
+            report(issue);
+            continue;
+        }
+        let Ok(data) = parse_uart_packet(packet) else {
+            continue;
+        };
+        match data {
+            UartData::Command(command) => {
+                *outstanding.entry(command.opcode.raw()).or_default() += 1;
+            }
+            UartData::Event(event) => {
+                let opcode = match event.code {
+                    Event::COMMAND_COMPLETE => {
+                        CommandComplete::try_from(event.params).map(|c| c.opcode.raw())
+                    }
+                    Event::COMMAND_STATUS => {
+                        CommandStatus::parse(&mut &event.params[..]).map(|c| c.opcode.raw())
+                    }
+                    Event::CONNECTION_COMPLETE => {
+                        if let Ok(complete) = ConnectionComplete::parse(&mut &event.params[..]) {
+                            if complete.status == 0 {
+                                disconnected.remove(&complete.handle);
+                            }
+                        }
+                        continue;
+                    }
+                    Event::DISCONNECTION_COMPLETE => {
+                        if let Ok(disconnection) =
+                            DisconnectionComplete::parse(&mut &event.params[..])
+                        {
+                            if disconnection.status == 0 {
+                                disconnected.insert(disconnection.handle);
+                            }
+                        }
+                        continue;
+                    }
+                    Event::LE_META => {
+                        if let Ok(LeMetaEvent::ConnectionComplete(complete)) =
+                            LeMetaEvent::try_from(event.params)
+                        {
+                            if complete.status == 0 {
+                                disconnected.remove(&complete.handle);
+                            }
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+                // opcode 0x0000 only returns command credits
+                let Ok(opcode @ 1..) = opcode else {
+                    continue;
+                };
+                match outstanding.get_mut(&opcode) {
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => report(HealthIssue::UnmatchedCommandResponse { opcode }),
+                }
+            }
+            UartData::Acl(acl) => {
+                // reported once per disconnection
+                if disconnected.remove(&acl.handle) {
+                    report(HealthIssue::TrafficAfterDisconnection { handle: acl.handle });
+                }
+            }
+            UartData::Todos => {}
+        }
+    }
+
+    findings
+}
+
+/// Type indicator, flags and HCI header length of an H4 packet
+fn check_h4_structure(packet: &Packet) -> Option<HealthIssue> {
+    let data = &packet.data.0;
+    let (&packet_type, hci) = data.split_first()?;
+    let Ok(uart_type) = UartPacketType::try_from(packet_type) else {
+        return Some(HealthIssue::UnknownPacketType(packet_type));
+    };
+
+    let flags = packet.description.flags;
+    let received = flags.is_received();
+    let command_or_event = flags.is_command_or_event();
+    let consistent = match uart_type {
+        UartPacketType::Cmd => !received && command_or_event,
+        UartPacketType::Evt => received && command_or_event,
+        UartPacketType::Acl | UartPacketType::Sco | UartPacketType::Iso => !command_or_event,
+    };
+    if !consistent {
+        return Some(HealthIssue::FlagsContradictPacketType {
+            packet_type,
+            flags: flags.0,
+        });
+    }
+
+    // header length and offset of the length field
+    let (header, announced) = match uart_type {
+        UartPacketType::Cmd => (3, hci.get(2).map(|l| *l as usize)),
+        UartPacketType::Evt => (2, hci.get(1).map(|l| *l as usize)),
+        UartPacketType::Acl => (
+            4,
+            hci.get(2..4)
+                .map(|l| u16::from_le_bytes([l[0], l[1]]) as usize),
+        ),
+        UartPacketType::Sco => (3, hci.get(2).map(|l| *l as usize)),
+        // the data total length is the lower 14 bits
+        UartPacketType::Iso => (
+            4,
+            hci.get(2..4)
+                .map(|l| (u16::from_le_bytes([l[0], l[1]]) & 0x3FFF) as usize),
+        ),
+    };
+    let (Some(announced), true) = (announced, hci.len() >= header) else {
+        return Some(HealthIssue::TruncatedHeader);
+    };
+    let actual = hci.len() - header;
+    let truncated = packet.description.included_length < packet.description.original_length;
+    if announced != actual && !(truncated && actual < announced) {
+        return Some(HealthIssue::HciLengthMismatch { announced, actual });
+    }
+    None
+}
+
+/// Number of findings per category
+pub fn summary(findings: &[HealthFinding]) -> Vec<(HealthCategory, usize)> {
+    let mut counts: HashMap<HealthCategory, usize> = HashMap::new();
+    for finding in findings {
+        *counts.entry(finding.issue.category()).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort();
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+    use crate::l2cap::BasicFrame;
+
+    #[test]
+    fn structural_findings() {
+        let mut capture = h4(vec![
+            // Reset and its Command Complete
+            (0, false, command(0x03, 0x03, &[])),
+            (
+                10,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x03, 0x0C, 0x00]),
+            ),
+            // a second Command Complete for Reset
+            (
+                20,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x03, 0x0C, 0x00]),
+            ),
+            // went back in time
+            (
+                15,
+                true,
+                event(Event::DISCONNECTION_COMPLETE, &[0x00, 0x40, 0x00, 0x13]),
+            ),
+            (
+                30,
+                false,
+                l2cap(0x40, BasicFrame::ATT_CID, &[0x0A, 0x01, 0x00]),
+            ),
+            (
+                40,
+                false,
+                l2cap(0x40, BasicFrame::ATT_CID, &[0x0A, 0x01, 0x00]),
+            ),
+            // event claiming 4 parameters but carrying 1
+            (50, true, vec![0x04, 0x0E, 0x04, 0x01]),
+            (60, true, vec![0x07, 0x00]),
+        ]);
+        // ACL data flagged as command
+        capture.packets[5].description.flags.0 = 0b10;
+
+        let findings = health_check(&capture);
+        assert_eq!(
+            findings,
+            vec![
+                HealthFinding {
+                    packet_index: 2,
+                    issue: HealthIssue::UnmatchedCommandResponse { opcode: 0x0C03 },
+                },
+                HealthFinding {
+                    packet_index: 3,
+                    issue: HealthIssue::TimestampWentBackwards {
+                        previous: 20,
+                        timestamp: 15
+                    },
+                },
+                HealthFinding {
+                    packet_index: 4,
+                    issue: HealthIssue::TrafficAfterDisconnection { handle: 0x40 },
+                },
+                HealthFinding {
+                    packet_index: 5,
+                    issue: HealthIssue::FlagsContradictPacketType {
+                        packet_type: 0x02,
+                        flags: 0b10
+                    },
+                },
+                HealthFinding {
+                    packet_index: 6,
+                    issue: HealthIssue::HciLengthMismatch {
+                        announced: 4,
+                        actual: 1
+                    },
+                },
+                HealthFinding {
+                    packet_index: 7,
+                    issue: HealthIssue::UnknownPacketType(0x07),
+                },
+            ]
+        );
+        assert_eq!(
+            summary(&findings),
+            vec![
+                (HealthCategory::Timestamps, 1),
+                (HealthCategory::Lengths, 1),
+                (HealthCategory::Flags, 2),
+                (HealthCategory::Commands, 1),
+                (HealthCategory::Connections, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn android_capture_is_healthy() {
+        let mut file: &[u8] = include_bytes!("../../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse(&mut file).unwrap();
+        assert_eq!(health_check(&capture), vec![]);
+    }
+}
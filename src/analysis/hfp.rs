@@ -0,0 +1,613 @@
+//! Hands-Free Profile codec negotiation: the codecs the Hands-Free unit makes available with
+//! `AT+BAC`, the codec the Audio Gateway selects with `+BCS` and the Hands-Free unit confirms
+//! with `AT+BCS`, correlated with the synchronous connections set up afterwards, to tell which
+//! codec a voice link actually carried: CVSD, mSBC (wideband) or LC3-SWB (super-wideband).
+//!
+//! The AT commands are read from the data of every RFCOMM channel whose L2CAP connection is in
+//! the capture. With a transparent air mode the codec is the coding format of an Enhanced Setup
+//! or Accept, or the one negotiated before when the Host encodes the audio itself.
+
+use std::collections::HashMap;
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{
+        synchronous::{
+            SynchronousConnectionChanged, SynchronousConnectionComplete,
+            SynchronousConnectionSetup, CODING_FORMAT_CVSD, CODING_FORMAT_LC3, CODING_FORMAT_MSBC,
+            CODING_FORMAT_TRANSPARENT,
+        },
+        BdAddr, CommandStatus, ConnectionComplete, DisconnectionComplete, Event,
+    },
+    l2cap::{BasicFrame, ChannelMap, Pdu, Reassembler, PSM_RFCOMM},
+    rfcomm, Btsnoop, UartData,
+};
+
+/// The codec IDs of the Hands-Free Profile
+pub const CODEC_CVSD: u8 = 0x01;
+pub const CODEC_MSBC: u8 = 0x02;
+pub const CODEC_LC3_SWB: u8 = 0x03;
+
+/// `CVSD`, `mSBC` or `LC3-SWB`
+pub fn codec_name(codec: u8) -> Option<&'static str> {
+    let name = match codec {
+        CODEC_CVSD => "CVSD",
+        CODEC_MSBC => "mSBC",
+        CODEC_LC3_SWB => "LC3-SWB",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The codec negotiation messages of the service level connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtMessage {
+    /// `AT+BAC=`: the codecs available on the Hands-Free unit
+    AvailableCodecs(Vec<u8>),
+    /// `+BCS:`: the Audio Gateway selected a codec
+    CodecSelection(u8),
+    /// `AT+BCS=`: the Hands-Free unit confirmed the selection
+    CodecConfirmation(u8),
+    /// `AT+BCC`: the Hands-Free unit asks the Audio Gateway for a codec connection
+    CodecConnection,
+}
+
+impl AtMessage {
+    /// The codec negotiation message of a line, none for other commands and results
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim().to_ascii_uppercase();
+        let codec = |value: &str| value.trim().parse().ok();
+        if let Some(codecs) = line.strip_prefix("AT+BAC=") {
+            let codecs = codecs.split(',').map(codec).collect::<Option<_>>()?;
+            Some(Self::AvailableCodecs(codecs))
+        } else if let Some(value) = line.strip_prefix("+BCS:") {
+            Some(Self::CodecSelection(codec(value)?))
+        } else if let Some(value) = line.strip_prefix("AT+BCS=") {
+            Some(Self::CodecConfirmation(codec(value)?))
+        } else {
+            (line == "AT+BCC").then_some(Self::CodecConnection)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiationStep {
+    /// packet index of the end of the line
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    pub dlci: u8,
+    pub message: AtMessage,
+}
+
+/// A synchronous connection set up or accepted, or its attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceLink {
+    /// packet index of the setup or accept command, none when it isn't in the capture
+    pub setup_packet: Option<usize>,
+    pub setup: Option<SynchronousConnectionSetup>,
+    /// the codec last selected or confirmed before the link
+    pub negotiated_codec: Option<u8>,
+    /// packet index of the completion, or of the Command Status rejecting the command
+    pub complete_packet: usize,
+    pub timestamp: i64,
+    pub status: u8,
+    /// none when the command was rejected
+    pub complete: Option<SynchronousConnectionComplete>,
+    /// renegotiations of the eSCO parameters by the link managers
+    pub changes: Vec<(usize, SynchronousConnectionChanged)>,
+    pub disconnect_packet: Option<usize>,
+    /// established with another codec than negotiated, or after an attempt with another codec
+    /// failed
+    pub fallback: bool,
+}
+
+impl VoiceLink {
+    pub fn is_established(&self) -> bool {
+        self.status == 0 && self.complete.is_some()
+    }
+
+    /// The codec of the setup: the coding format of the enhanced commands, CVSD with a CVSD
+    /// air coding, else the codec negotiated
+    pub fn attempted_codec(&self) -> Option<u8> {
+        match self.setup.map(|setup| setup.air_coding_format()) {
+            Some(CODING_FORMAT_CVSD) => Some(CODEC_CVSD),
+            Some(CODING_FORMAT_MSBC) => Some(CODEC_MSBC),
+            Some(CODING_FORMAT_LC3) => Some(CODEC_LC3_SWB),
+            _ => self.negotiated_codec,
+        }
+    }
+
+    /// The codec the established link carries, by its air mode
+    pub fn codec(&self) -> Option<u8> {
+        if self.status != 0 {
+            return None;
+        }
+        match self.complete?.air_mode {
+            CODING_FORMAT_CVSD => Some(CODEC_CVSD),
+            CODING_FORMAT_TRANSPARENT => {
+                self.attempted_codec().filter(|&codec| codec != CODEC_CVSD)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The codec negotiation and voice links with a device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HfpSession {
+    /// the ACL connection, none when neither its setup nor its AT commands are in the capture
+    pub handle: Option<u16>,
+    pub bd_addr: Option<BdAddr>,
+    pub first_packet: usize,
+    pub negotiation: Vec<NegotiationStep>,
+    pub voice_links: Vec<VoiceLink>,
+}
+
+impl HfpSession {
+    /// The codecs last made available by the Hands-Free unit
+    pub fn available_codecs(&self) -> Option<&[u8]> {
+        self.negotiation
+            .iter()
+            .rev()
+            .find_map(|step| match &step.message {
+                AtMessage::AvailableCodecs(codecs) => Some(&codecs[..]),
+                _ => None,
+            })
+    }
+
+    /// The selections of another codec than the one selected before
+    pub fn renegotiations(&self) -> usize {
+        let selections: Vec<_> = self
+            .negotiation
+            .iter()
+            .filter_map(|step| match step.message {
+                AtMessage::CodecSelection(codec) => Some(codec),
+                _ => None,
+            })
+            .collect();
+        selections
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count()
+    }
+
+    pub fn fallbacks(&self) -> usize {
+        self.voice_links.iter().filter(|link| link.fallback).count()
+    }
+
+    fn negotiated_codec(&self) -> Option<u8> {
+        self.negotiation
+            .iter()
+            .rev()
+            .find_map(|step| match step.message {
+                AtMessage::CodecSelection(codec) | AtMessage::CodecConfirmation(codec) => {
+                    Some(codec)
+                }
+                _ => None,
+            })
+    }
+}
+
+struct PendingSetup {
+    packet_index: usize,
+    setup: SynchronousConnectionSetup,
+    bd_addr: Option<BdAddr>,
+}
+
+#[derive(Default)]
+struct Analyzer {
+    sessions: Vec<HfpSession>,
+    /// ACL handle -> index of its session, until it is disconnected
+    open: HashMap<u16, usize>,
+    /// address of each ACL connection
+    addresses: HashMap<u16, BdAddr>,
+    channels: ChannelMap,
+    /// partial line of each RFCOMM data link connection and direction
+    lines: HashMap<(u16, u8, bool), Vec<u8>>,
+    pending: Vec<PendingSetup>,
+    /// SCO handle -> (session, voice link)
+    links: HashMap<u16, (usize, usize)>,
+}
+
+impl Analyzer {
+    fn session(
+        &mut self,
+        handle: Option<u16>,
+        bd_addr: Option<BdAddr>,
+        packet_index: usize,
+    ) -> usize {
+        if let Some(&session) = handle.and_then(|handle| self.open.get(&handle)) {
+            return session;
+        }
+        if handle.is_none() {
+            let known = self
+                .sessions
+                .iter()
+                .rposition(|session| session.bd_addr.is_some() && session.bd_addr == bd_addr);
+            if let Some(session) = known {
+                return session;
+            }
+        }
+        self.sessions.push(HfpSession {
+            handle,
+            bd_addr: bd_addr
+                .or_else(|| handle.and_then(|handle| self.addresses.get(&handle).copied())),
+            first_packet: packet_index,
+            ..Default::default()
+        });
+        let session = self.sessions.len() - 1;
+        if let Some(handle) = handle {
+            self.open.insert(handle, session);
+        }
+        session
+    }
+
+    fn pdu(&mut self, pdu: Pdu, timestamp: i64) {
+        let Ok(frame) = pdu.frame() else {
+            return;
+        };
+        if !pdu.complete {
+            return;
+        }
+        let cid = frame.channel_id;
+        if cid == BasicFrame::SIGNALING_CID {
+            self.channels
+                .update(pdu.handle, pdu.sent, pdu.packet_index, &frame);
+            return;
+        }
+        if self
+            .channels
+            .lookup(pdu.handle, pdu.sent, cid)
+            .is_none_or(|channel| channel.psm != PSM_RFCOMM)
+        {
+            return;
+        }
+        let payload = &frame.payload[..frame.payload.len().min(frame.length as usize)];
+        let Ok(frame) = rfcomm::Frame::try_from(payload) else {
+            return;
+        };
+        if !frame.is_data() {
+            return;
+        }
+        let buffer = self
+            .lines
+            .entry((pdu.handle, frame.dlci, pdu.sent))
+            .or_default();
+        buffer.extend_from_slice(frame.information);
+        let mut messages = vec![];
+        while let Some(end) = buffer.iter().position(|&b| b == b'\r' || b == b'\n') {
+            let line: Vec<_> = buffer.drain(..=end).collect();
+            if let Some(message) = AtMessage::parse(&String::from_utf8_lossy(&line)) {
+                messages.push(message);
+            }
+        }
+        for message in messages {
+            let session = self.session(Some(pdu.handle), None, pdu.packet_index);
+            self.sessions[session].negotiation.push(NegotiationStep {
+                packet_index: pdu.packet_index,
+                timestamp,
+                sent: pdu.sent,
+                dlci: frame.dlci,
+                message,
+            });
+        }
+    }
+
+    /// Add a voice link to the session of its device, returns (session, voice link)
+    fn link(
+        &mut self,
+        pending: Option<PendingSetup>,
+        mut link: VoiceLink,
+        bd_addr: Option<BdAddr>,
+    ) -> (usize, usize) {
+        let handle = pending
+            .as_ref()
+            .and_then(|pending| pending.setup.handle)
+            .or_else(|| {
+                self.addresses
+                    .iter()
+                    .find(|(_, address)| Some(**address) == bd_addr)
+                    .map(|(handle, _)| *handle)
+            });
+        let index = self.session(handle, bd_addr, link.complete_packet);
+        let session = &mut self.sessions[index];
+        if let Some(pending) = pending {
+            link.setup_packet = Some(pending.packet_index);
+            link.setup = Some(pending.setup);
+        }
+        link.negotiated_codec = session.negotiated_codec();
+        if link.is_established() {
+            let codec = link.codec();
+            let failed_before = session
+                .voice_links
+                .last()
+                .filter(|previous| !previous.is_established())
+                .and_then(|previous| previous.attempted_codec());
+            link.fallback = codec.is_some()
+                && ((link.negotiated_codec.is_some() && link.negotiated_codec != codec)
+                    || (failed_before.is_some() && failed_before != codec));
+        }
+        session.voice_links.push(link);
+        (index, session.voice_links.len() - 1)
+    }
+
+    fn event(&mut self, event: &Event, packet_index: usize, timestamp: i64) {
+        let mut params = event.params;
+        match event.code {
+            Event::CONNECTION_COMPLETE => {
+                let Ok(complete) = ConnectionComplete::parse(&mut params) else {
+                    return;
+                };
+                if complete.status == 0 && complete.link_type == ConnectionComplete::LINK_TYPE_ACL {
+                    self.addresses.insert(complete.handle, complete.bd_addr);
+                }
+            }
+            Event::COMMAND_STATUS => {
+                let Ok(status) = CommandStatus::parse(&mut params) else {
+                    return;
+                };
+                if status.status == 0
+                    || !SynchronousConnectionSetup::OPCODES.contains(&status.opcode)
+                {
+                    return;
+                }
+                let Some(position) = self
+                    .pending
+                    .iter()
+                    .rposition(|pending| pending.setup.opcode == status.opcode)
+                else {
+                    return;
+                };
+                let pending = self.pending.remove(position);
+                let bd_addr = pending.bd_addr;
+                let link = VoiceLink {
+                    setup_packet: None,
+                    setup: None,
+                    negotiated_codec: None,
+                    complete_packet: packet_index,
+                    timestamp,
+                    status: status.status,
+                    complete: None,
+                    changes: vec![],
+                    disconnect_packet: None,
+                    fallback: false,
+                };
+                self.link(Some(pending), link, bd_addr);
+            }
+            Event::SYNCHRONOUS_CONNECTION_COMPLETE => {
+                let Ok(complete) = SynchronousConnectionComplete::parse(&mut params) else {
+                    return;
+                };
+                let position = self
+                    .pending
+                    .iter()
+                    .position(|pending| pending.bd_addr == Some(complete.bd_addr))
+                    .or_else(|| {
+                        self.pending
+                            .iter()
+                            .position(|pending| pending.bd_addr.is_none())
+                    });
+                let pending = position.map(|position| self.pending.remove(position));
+                let link = VoiceLink {
+                    setup_packet: None,
+                    setup: None,
+                    negotiated_codec: None,
+                    complete_packet: packet_index,
+                    timestamp,
+                    status: complete.status,
+                    complete: Some(complete),
+                    changes: vec![],
+                    disconnect_packet: None,
+                    fallback: false,
+                };
+                let link = self.link(pending, link, Some(complete.bd_addr));
+                if complete.status == 0 {
+                    self.links.insert(complete.handle, link);
+                }
+            }
+            Event::SYNCHRONOUS_CONNECTION_CHANGED => {
+                let Ok(changed) = SynchronousConnectionChanged::parse(&mut params) else {
+                    return;
+                };
+                if let Some(&(session, link)) = self.links.get(&changed.handle) {
+                    self.sessions[session].voice_links[link]
+                        .changes
+                        .push((packet_index, changed));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The codec negotiations and voice links of every device, in order of the first one
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn hfp_sessions(capture: &Btsnoop) -> Vec<HfpSession> {
+    let mut analyzer = Analyzer::default();
+    let mut reassembler = Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let timestamp = packet.description.timestamp;
+        match data {
+            UartData::Command(command) => {
+                let Ok(setup) =
+                    SynchronousConnectionSetup::parse(command.opcode, &mut &command.params[..])
+                else {
+                    continue;
+                };
+                let bd_addr = setup.bd_addr.or_else(|| {
+                    setup
+                        .handle
+                        .and_then(|handle| analyzer.addresses.get(&handle).copied())
+                });
+                analyzer.pending.push(PendingSetup {
+                    packet_index,
+                    setup,
+                    bd_addr,
+                });
+            }
+            UartData::Acl(acl) => {
+                let sent = !is_received(packet);
+                for pdu in reassembler.push(&acl, sent, packet_index) {
+                    analyzer.pdu(pdu, timestamp);
+                }
+            }
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                if disconnection.status != 0 {
+                    continue;
+                }
+                let handle = disconnection.handle;
+                if let Some((session, link)) = analyzer.links.remove(&handle) {
+                    analyzer.sessions[session].voice_links[link].disconnect_packet =
+                        Some(packet_index);
+                    continue;
+                }
+                for pdu in reassembler.disconnected(handle) {
+                    analyzer.pdu(pdu, timestamp);
+                }
+                analyzer.channels.disconnected(handle);
+                analyzer
+                    .lines
+                    .retain(|(line_handle, _, _), _| *line_handle != handle);
+                analyzer.open.remove(&handle);
+                analyzer.addresses.remove(&handle);
+            }
+            UartData::Event(event) => analyzer.event(&event, packet_index, timestamp),
+            UartData::Todos => {}
+        }
+    }
+    analyzer.sessions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    /// RFCOMM UIH frame of a line on server channel 1, in L2CAP channel 0x0040
+    fn at(handle: u16, line: &str) -> Vec<u8> {
+        let mut frame = vec![
+            0x02 << 2 | 0x03,
+            rfcomm::Frame::UIH,
+            (line.len() as u8) << 1 | 1,
+        ];
+        frame.extend_from_slice(line.as_bytes());
+        frame.push(0x00);
+        l2cap(handle, 0x0040, &frame)
+    }
+
+    fn synchronous_complete(status: u8, handle: u16, air_mode: u8) -> Vec<u8> {
+        let mut params = vec![status, handle as u8, (handle >> 8) as u8];
+        params.extend_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+        params.extend_from_slice(&[0x02, 0x0c, 0x04, 0x3c, 0x00, 0x3c, 0x00, air_mode]);
+        event(0x2c, &params)
+    }
+
+    #[test]
+    fn fallback_to_cvsd() {
+        // legacy setup of ACL 0x0001: transparent or CVSD air coding
+        let setup = |voice_setting: u8| {
+            command(
+                0x01,
+                0x0028,
+                &[
+                    0x01,
+                    0x00,
+                    0x40,
+                    0x1f,
+                    0x00,
+                    0x00,
+                    0x40,
+                    0x1f,
+                    0x00,
+                    0x00,
+                    0x0d,
+                    0x00,
+                    voice_setting,
+                    0x00,
+                    0x02,
+                    0x08,
+                    0x03,
+                ],
+            )
+        };
+        let capture = h4(vec![
+            (
+                0,
+                true,
+                event(
+                    0x03,
+                    &[
+                        0x00, 0x01, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x01, 0x00,
+                    ],
+                ),
+            ),
+            // RFCOMM on 0x0040 in both directions
+            (
+                1,
+                false,
+                l2cap(
+                    0x0001,
+                    0x0001,
+                    &[0x02, 0x01, 0x04, 0x00, 0x03, 0x00, 0x40, 0x00],
+                ),
+            ),
+            (
+                2,
+                true,
+                l2cap(
+                    0x0001,
+                    0x0001,
+                    &[
+                        0x03, 0x01, 0x08, 0x00, 0x40, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    ],
+                ),
+            ),
+            (3, true, at(0x0001, "AT+BAC=1,2\r")),
+            (4, false, at(0x0001, "\r\n+BCS: 2\r\n")),
+            (5, true, at(0x0001, "AT+BCS=2\r")),
+            (6, false, setup(0x63)),
+            (7, true, synchronous_complete(0x1f, 0x0002, 0x03)),
+            (8, false, at(0x0001, "\r\n+BCS: 1\r\n")),
+            (9, true, at(0x0001, "AT+BCS=1\r")),
+            (10, false, setup(0x60)),
+            (11, true, synchronous_complete(0x00, 0x0002, 0x02)),
+            (12, true, event(0x05, &[0x00, 0x02, 0x00, 0x13])),
+        ]);
+        let sessions = hfp_sessions(&capture);
+        assert_eq!(sessions.len(), 1);
+        let session = &sessions[0];
+        assert_eq!(session.handle, Some(0x0001));
+        assert_eq!(
+            session.available_codecs(),
+            Some(&[CODEC_CVSD, CODEC_MSBC][..])
+        );
+        assert_eq!(session.negotiation.len(), 5);
+        assert_eq!(session.renegotiations(), 1);
+
+        let [failed, established] = &session.voice_links[..] else {
+            panic!("expected two voice links");
+        };
+        assert!(!failed.is_established());
+        assert_eq!(failed.setup_packet, Some(6));
+        assert_eq!(failed.attempted_codec(), Some(CODEC_MSBC));
+        assert_eq!(established.negotiated_codec, Some(CODEC_CVSD));
+        assert_eq!(established.codec(), Some(CODEC_CVSD));
+        assert!(established.fallback);
+        assert_eq!(established.disconnect_packet, Some(12));
+        assert_eq!(session.fallbacks(), 1);
+
+        assert_eq!(
+            AtMessage::parse("at+bac=1,2,3"),
+            Some(AtMessage::AvailableCodecs(vec![1, 2, 3]))
+        );
+        assert_eq!(AtMessage::parse("AT+BRSF=1023"), None);
+    }
+}
@@ -0,0 +1,395 @@
+//! The key presses and pointer movements of the HID devices of a capture, from the input
+//! reports of classic HID interrupt channels and of HID over GATT, and the text typed on a
+//! keyboard: what anyone decrypting the link would read.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    att::Pdu,
+    gatt::{AttributeMap, Uuid},
+    hci::{DisconnectionComplete, Event},
+    hid::{self, key_name, Input, ReportMap},
+    l2cap::{BasicFrame, ChannelMap, Reassembler, PSM_HID_INTERRUPT},
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// a keyboard usage pressed or released, modifiers included
+    Key { usage: u8, pressed: bool },
+    /// the buttons held and the relative movement
+    Pointer {
+        buttons: u32,
+        x: i32,
+        y: i32,
+        wheel: i32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HidEvent {
+    pub packet_index: usize,
+    pub timestamp: i64,
+    pub connection: u16,
+    /// carried by a HID over GATT report, else by a classic HID interrupt channel
+    pub gatt: bool,
+    pub event: InputEvent,
+}
+
+#[derive(Debug, Default)]
+struct Device {
+    keys: BTreeSet<u8>,
+    report_map: Vec<u8>,
+    parsed: Option<ReportMap>,
+    /// Report characteristic value handle -> report ID
+    report_ids: HashMap<u16, u8>,
+}
+
+impl Device {
+    /// The events of an input report, the keys pressed and released since the previous one
+    fn events(&mut self, input: Input) -> Vec<InputEvent> {
+        if input.rollover {
+            return vec![];
+        }
+        let mut events: Vec<_> = self
+            .keys
+            .difference(&input.keys)
+            .map(|usage| InputEvent::Key {
+                usage: *usage,
+                pressed: false,
+            })
+            .collect();
+        // the modifiers first, for the keys pressed with them
+        let (modifiers, keys): (Vec<u8>, Vec<u8>) = input
+            .keys
+            .difference(&self.keys)
+            .partition(|usage| **usage >= 0xE0);
+        events.extend(
+            modifiers
+                .into_iter()
+                .chain(keys)
+                .map(|usage| InputEvent::Key {
+                    usage,
+                    pressed: true,
+                }),
+        );
+        if input.is_pointer() {
+            events.push(InputEvent::Pointer {
+                buttons: input.buttons,
+                x: input.x,
+                y: input.y,
+                wheel: input.wheel,
+            });
+        }
+        self.keys = input.keys;
+        events
+    }
+}
+
+/// Without a report map, the boot layouts: 8 octets for a keyboard, 3 or 4 for a mouse,
+/// optionally after report ID 1 for a keyboard or 2 for a mouse
+fn boot_report(report: &[u8]) -> Option<Input> {
+    match (report.len(), report.first()) {
+        (8, _) => ReportMap::boot_keyboard().decode(0, report),
+        (9, Some(1)) => ReportMap::boot_keyboard().decode(0, &report[1..]),
+        (3 | 4, _) => ReportMap::boot_mouse().decode(0, report),
+        (5, Some(2)) => ReportMap::boot_mouse().decode(0, &report[1..]),
+        _ => None,
+    }
+}
+
+/// A report starting with its ID when the map has IDs
+fn report(map: &ReportMap, report: &[u8]) -> Option<Input> {
+    if map.has_report_ids() {
+        let (id, data) = report.split_first()?;
+        map.decode(*id, data)
+    } else {
+        map.decode(0, report)
+    }
+}
+
+/// The input events of every HID device, in order. The reports are decoded with the report
+/// map read over GATT, else with `report_map`, e.g. the one of the SDP record of a classic
+/// device, else as boot reports.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn hid_events(capture: &Btsnoop, report_map: Option<&ReportMap>) -> Vec<HidEvent> {
+    let mut events = vec![];
+    let mut devices: HashMap<u16, Device> = HashMap::new();
+    let mut attributes = AttributeMap::default();
+    let mut channels = ChannelMap::default();
+    let mut reassembler = Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                devices.remove(&disconnection.handle);
+                attributes.disconnected(disconnection.handle);
+                channels.disconnected(disconnection.handle);
+                reassembler.disconnected(disconnection.handle);
+            }
+            UartData::Acl(acl) => {
+                let sent = !is_received(packet);
+                for pdu in reassembler.push(&acl, sent, packet_index) {
+                    let Ok(frame) = pdu.frame() else {
+                        continue;
+                    };
+                    if !pdu.complete {
+                        continue;
+                    }
+                    let psm = channels
+                        .update(acl.handle, sent, packet_index, &frame)
+                        .map(|channel| channel.psm);
+                    let device = devices.entry(acl.handle).or_default();
+                    let (input, gatt) = if frame.channel_id == BasicFrame::ATT_CID {
+                        let Ok(pdu) = Pdu::try_from(frame.payload) else {
+                            continue;
+                        };
+                        let Some(attribute) = attributes.update(acl.handle, sent, &pdu) else {
+                            continue;
+                        };
+                        let uuid = attributes
+                            .uuid(acl.handle, attribute)
+                            .and_then(|uuid| uuid.as_u16());
+                        (
+                            gatt_report(
+                                device,
+                                &attributes,
+                                acl.handle,
+                                attribute,
+                                uuid,
+                                &pdu,
+                                report_map,
+                            ),
+                            true,
+                        )
+                    } else if psm == Some(PSM_HID_INTERRUPT) && frame.channel_id >= 0x0040 {
+                        let input = match frame.payload.split_first() {
+                            Some((&hid::HIDP_DATA_INPUT, data)) => match report_map {
+                                Some(map) => report(map, data),
+                                None => boot_report(data),
+                            },
+                            _ => None,
+                        };
+                        (input, false)
+                    } else {
+                        continue;
+                    };
+                    let Some(input) = input else {
+                        continue;
+                    };
+                    events.extend(device.events(input).into_iter().map(|event| HidEvent {
+                        packet_index: pdu.packet_index,
+                        timestamp: packet.description.timestamp,
+                        connection: acl.handle,
+                        gatt,
+                        event,
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Learns the report map and report IDs of a HID over GATT device from its reads, and decodes
+/// its input reports
+fn gatt_report(
+    device: &mut Device,
+    attributes: &AttributeMap,
+    connection: u16,
+    attribute: u16,
+    uuid: Option<u16>,
+    pdu: &Pdu,
+    report_map: Option<&ReportMap>,
+) -> Option<Input> {
+    let value = pdu.params;
+    match (pdu.opcode, uuid?) {
+        (Pdu::READ_RESPONSE | Pdu::READ_BLOB_RESPONSE, hid::REPORT_MAP) => {
+            if pdu.opcode == Pdu::READ_RESPONSE {
+                device.report_map.clear();
+            }
+            device.report_map.extend_from_slice(value);
+            device.parsed = ReportMap::parse(&device.report_map).ok();
+            None
+        }
+        (Pdu::READ_RESPONSE, hid::REPORT_REFERENCE) => {
+            // report ID, input report type
+            if let [id, 0x01, ..] = value {
+                let characteristic =
+                    attributes
+                        .attributes(connection)
+                        .into_iter()
+                        .rev()
+                        .find(|(handle, uuid)| {
+                            *handle < attribute && *uuid == Uuid::from_u16(hid::REPORT)
+                        });
+                if let Some((handle, _)) = characteristic {
+                    device.report_ids.insert(handle, *id);
+                }
+            }
+            None
+        }
+        (Pdu::HANDLE_VALUE_NOTIFICATION, hid::BOOT_KEYBOARD_INPUT_REPORT) => {
+            ReportMap::boot_keyboard().decode(0, value.get(2..)?)
+        }
+        (Pdu::HANDLE_VALUE_NOTIFICATION, hid::BOOT_MOUSE_INPUT_REPORT) => {
+            ReportMap::boot_mouse().decode(0, value.get(2..)?)
+        }
+        (Pdu::HANDLE_VALUE_NOTIFICATION, hid::REPORT) => {
+            // the report ID isn't in the value
+            let data = value.get(2..)?;
+            let Some(map) = device.parsed.as_ref().or(report_map) else {
+                return boot_report(data);
+            };
+            let ids = map.report_ids();
+            let id = match device.report_ids.get(&attribute) {
+                Some(id) => *id,
+                None if ids.len() == 1 => ids.first().copied()?,
+                None => return None,
+            };
+            map.decode(id, data)
+        }
+        _ => None,
+    }
+}
+
+/// The text typed by the key presses of one keyboard, on a US layout: the keys which don't type
+/// a character in angle brackets, e.g. `<F1>`, and control combinations as `<Ctrl+C>`.
+/// Backspace erases the previous character.
+pub fn typed_text(events: &[HidEvent]) -> String {
+    let mut text = String::new();
+    let mut held = BTreeSet::new();
+    let mut caps_lock = false;
+    for event in events {
+        let InputEvent::Key { usage, pressed } = event.event else {
+            continue;
+        };
+        if !pressed {
+            held.remove(&usage);
+            continue;
+        }
+        held.insert(usage);
+        let shift = held.contains(&0xE1) || held.contains(&0xE5);
+        let control = held.contains(&0xE0) || held.contains(&0xE4);
+        match (usage, Input::char(usage, shift)) {
+            (0x39, _) => caps_lock = !caps_lock,
+            (0x2A, _) => {
+                text.pop();
+            }
+            (0xE0..=0xE7, _) => {}
+            (_, Some(c)) if control => text.push_str(&format!("<Ctrl+{}>", c.to_ascii_uppercase())),
+            (_, Some(c)) if caps_lock && c.is_ascii_alphabetic() => text.push(if shift {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }),
+            (_, Some(c)) => text.push(c),
+            (_, None) => text.push_str(&format!(
+                "<{}>",
+                key_name(usage)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("0x{:02x}", usage))
+            )),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{h4, l2cap};
+
+    #[test]
+    fn keystrokes() {
+        let keyboard = |report: &[u8]| {
+            let mut value = vec![0x1b, 0x12, 0x00];
+            value.extend_from_slice(report);
+            l2cap(0x40, 0x0004, &value)
+        };
+        let capture = h4(vec![
+            // Find Information Response: 0x0012 Report, 0x0013 Report Reference
+            (
+                0,
+                true,
+                l2cap(
+                    0x40,
+                    0x0004,
+                    &[0x05, 0x01, 0x12, 0x00, 0x4d, 0x2a, 0x13, 0x00, 0x08, 0x29],
+                ),
+            ),
+            // read of the Report Reference: input report 1
+            (1, false, l2cap(0x40, 0x0004, &[0x0a, 0x13, 0x00])),
+            (2, true, l2cap(0x40, 0x0004, &[0x0b, 0x01, 0x01])),
+            // 'H', 'i', backspace, '!'
+            (3, true, keyboard(&[0x02, 0x00, 0x0b, 0, 0, 0, 0, 0])),
+            (4, true, keyboard(&[0x00, 0x00, 0x0c, 0, 0, 0, 0, 0])),
+            (5, true, keyboard(&[0x00, 0x00, 0x00, 0, 0, 0, 0, 0])),
+            (6, true, keyboard(&[0x00, 0x00, 0x2a, 0, 0, 0, 0, 0])),
+            (7, true, keyboard(&[0x20, 0x00, 0x2a, 0x1e, 0, 0, 0, 0])),
+            // rollover
+            (8, true, keyboard(&[0x00, 0x00, 0x01, 0x01, 1, 1, 1, 1])),
+        ]);
+        let events = hid_events(&capture, None);
+        assert!(events.iter().all(|event| event.gatt));
+        assert_eq!(
+            events[..2].iter().map(|e| e.event).collect::<Vec<_>>(),
+            [
+                InputEvent::Key {
+                    usage: 0xe1,
+                    pressed: true
+                },
+                InputEvent::Key {
+                    usage: 0x0b,
+                    pressed: true
+                },
+            ]
+        );
+        assert_eq!(events.last().unwrap().packet_index, 7);
+        assert_eq!(typed_text(&events), "H!");
+
+        // classic HID interrupt channel, boot mouse report after report ID 2
+        let capture = h4(vec![
+            // Connection Request for PSM 0x0013 and its response
+            (
+                0,
+                true,
+                l2cap(
+                    0x01,
+                    0x0001,
+                    &[0x02, 0x01, 0x04, 0x00, 0x13, 0x00, 0x41, 0x00],
+                ),
+            ),
+            (
+                1,
+                false,
+                l2cap(
+                    0x01,
+                    0x0001,
+                    &[0x03, 0x01, 0x08, 0x00, 0x40, 0x00, 0x41, 0x00, 0, 0, 0, 0],
+                ),
+            ),
+            (
+                2,
+                true,
+                l2cap(0x01, 0x0040, &[0xa1, 0x02, 0x01, 0x05, 0xfb, 0x00]),
+            ),
+        ]);
+        assert_eq!(
+            hid_events(&capture, None)[0].event,
+            InputEvent::Pointer {
+                buttons: 1,
+                x: 5,
+                y: -5,
+                wheel: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! The SDUs of the isochronous streams of a capture, and how each stream came through: the
+//! SDUs the Controller reported lost or possibly invalid, the ones missing from the sequence
+//! numbers, late or incomplete.
+
+use std::collections::HashMap;
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{DisconnectionComplete, Event, Iso},
+    iso::{Reassembler, Sdu, SduStatus},
+    Btsnoop, UartData, UartPacketType,
+};
+
+/// The SDUs of a CIS or BIS in one direction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IsoStream {
+    pub handle: u16,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    /// packet index of the first SDU
+    pub first_packet: usize,
+    pub sdus: usize,
+    pub possibly_invalid: usize,
+    pub lost: usize,
+    pub incomplete: usize,
+    /// SDUs skipped by the sequence numbers
+    pub missing: usize,
+    pub late: usize,
+    /// of the SDUs received valid
+    pub octets: usize,
+}
+
+/// The SDUs of every isochronous stream, in order of their first fragment, the incomplete
+/// ones at the fragment or disconnection which abandoned them
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn iso_sdus(capture: &Btsnoop) -> Vec<Sdu> {
+    let mut sdus = vec![];
+    let mut reassembler = Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            // ISO data isn't decoded with the rest of the UART data
+            UartData::Todos => {
+                let Some((&packet_type, data)) = packet.data.0.split_first() else {
+                    continue;
+                };
+                if packet_type != UartPacketType::Iso as u8 {
+                    continue;
+                }
+                let Ok(iso) = Iso::try_from(data) else {
+                    continue;
+                };
+                sdus.extend(reassembler.push(&iso, !is_received(packet), packet_index));
+            }
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                sdus.extend(reassembler.disconnected(disconnection.handle));
+            }
+            _ => {}
+        }
+    }
+    sdus
+}
+
+/// How the SDUs of each isochronous stream came through, in order of their first SDU
+pub fn iso_streams(capture: &Btsnoop) -> Vec<IsoStream> {
+    let mut streams: Vec<IsoStream> = vec![];
+    let mut open: HashMap<(u16, bool), usize> = HashMap::new();
+    for sdu in iso_sdus(capture) {
+        let index = *open.entry((sdu.handle, sdu.sent)).or_insert_with(|| {
+            streams.push(IsoStream {
+                handle: sdu.handle,
+                sent: sdu.sent,
+                first_packet: sdu.packet_index,
+                ..Default::default()
+            });
+            streams.len() - 1
+        });
+        let stream = &mut streams[index];
+        stream.sdus += 1;
+        stream.missing += sdu.missing as usize;
+        stream.late += sdu.late as usize;
+        match sdu.status {
+            SduStatus::Valid => stream.octets += sdu.data.len(),
+            SduStatus::PossiblyInvalid => stream.possibly_invalid += 1,
+            SduStatus::Lost => stream.lost += 1,
+            SduStatus::Incomplete => stream.incomplete += 1,
+        }
+    }
+    streams
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4};
+
+    /// H4 ISO packet, `header` the sequence number, SDU length and packet status flag of the
+    /// first fragment
+    fn iso(handle: u16, pb: u16, header: Option<(u16, u16, u16)>, data: &[u8]) -> Vec<u8> {
+        let mut load = vec![];
+        if let Some((sequence_number, sdu_length, status)) = header {
+            load.extend_from_slice(&sequence_number.to_le_bytes());
+            load.extend_from_slice(&(sdu_length | status << 14).to_le_bytes());
+        }
+        load.extend_from_slice(data);
+        let mut packet = vec![0x05];
+        packet.extend_from_slice(&(handle | pb << 12).to_le_bytes());
+        packet.extend_from_slice(&(load.len() as u16).to_le_bytes());
+        packet.extend_from_slice(&load);
+        packet
+    }
+
+    #[test]
+    fn reassembles_sdus() {
+        let capture = h4(vec![
+            (0, true, iso(0x60, 0b10, Some((1, 4, 0)), b"abcd")),
+            // fragmented
+            (1, true, iso(0x60, 0b00, Some((2, 6, 0)), b"ef")),
+            (2, true, iso(0x60, 0b01, None, b"gh")),
+            (3, true, iso(0x60, 0b11, None, b"ij")),
+            // 3 and 4 never came
+            (4, true, iso(0x60, 0b10, Some((5, 0, 0b10)), b"")),
+            (5, true, iso(0x60, 0b10, Some((6, 2, 0b01)), b"kl")),
+            // late
+            (6, true, iso(0x60, 0b10, Some((4, 2, 0)), b"mn")),
+            // the last fragment was lost
+            (7, true, iso(0x60, 0b00, Some((7, 4, 0)), b"op")),
+            (8, true, iso(0x60, 0b10, Some((8, 2, 0)), b"qr")),
+            (9, false, iso(0x61, 0b10, Some((0xffff, 2, 0)), b"st")),
+            (10, false, iso(0x61, 0b00, Some((0, 4, 0)), b"uv")),
+            (
+                11,
+                true,
+                event(Event::DISCONNECTION_COMPLETE, &[0, 0x61, 0, 0x13]),
+            ),
+        ]);
+        let sdus = iso_sdus(&capture);
+        let summary: Vec<_> = sdus
+            .iter()
+            .map(|sdu| (sdu.sequence_number, sdu.status, sdu.data.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, SduStatus::Valid, &b"abcd"[..]),
+                (2, SduStatus::Valid, b"efghij"),
+                (5, SduStatus::Lost, b""),
+                (6, SduStatus::PossiblyInvalid, b"kl"),
+                (4, SduStatus::Valid, b"mn"),
+                (7, SduStatus::Incomplete, b"op"),
+                (8, SduStatus::Valid, b"qr"),
+                (0xffff, SduStatus::Valid, b"st"),
+                (0, SduStatus::Incomplete, b"uv"),
+            ]
+        );
+        assert_eq!(sdus[2].missing, 2);
+        assert!(sdus[4].late);
+        assert_eq!(sdus[8].missing, 0);
+
+        let streams = iso_streams(&capture);
+        assert_eq!(streams.len(), 2);
+        let stream = &streams[0];
+        assert_eq!((stream.handle, stream.sent), (0x60, false));
+        assert_eq!(
+            (
+                stream.sdus,
+                stream.lost,
+                stream.possibly_invalid,
+                stream.incomplete
+            ),
+            (7, 1, 1, 1)
+        );
+        assert_eq!((stream.missing, stream.late, stream.octets), (2, 1, 14));
+        assert_eq!((streams[1].sent, streams[1].incomplete), (true, 1));
+    }
+}
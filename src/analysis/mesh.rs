@@ -0,0 +1,142 @@
+//! The Bluetooth Mesh messages of a capture carried over GATT, e.g. a phone provisioning a
+//! device or talking to the network through a proxy node.
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    att::Pdu,
+    gatt::AttributeMap,
+    hci::{DisconnectionComplete, Event},
+    l2cap::{BasicFrame, Reassembler},
+    mesh::{self, Message, ProxyPdu},
+    Btsnoop, UartData,
+};
+
+const CHARACTERISTICS: [u16; 4] = [
+    mesh::MESH_PROVISIONING_DATA_IN,
+    mesh::MESH_PROVISIONING_DATA_OUT,
+    mesh::MESH_PROXY_DATA_IN,
+    mesh::MESH_PROXY_DATA_OUT,
+];
+
+/// The proxy PDUs written to and notified by the Mesh Provisioning and Mesh Proxy
+/// characteristics, reassembled from their segments, in order of their last segment. The
+/// characteristics are known from their discovery in the capture.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn mesh_messages(capture: &Btsnoop) -> Vec<Message> {
+    let mut messages = vec![];
+    let mut attributes = AttributeMap::default();
+    let mut l2cap = Reassembler::default();
+    let mut proxy = mesh::Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                attributes.disconnected(disconnection.handle);
+                l2cap.disconnected(disconnection.handle);
+                messages.extend(proxy.disconnected(disconnection.handle));
+            }
+            UartData::Acl(acl) => {
+                let sent = !is_received(packet);
+                for pdu in l2cap.push(&acl, sent, packet_index) {
+                    let Ok(frame) = pdu.frame() else {
+                        continue;
+                    };
+                    if !pdu.complete || frame.channel_id != BasicFrame::ATT_CID {
+                        continue;
+                    }
+                    let Ok(att) = Pdu::try_from(frame.payload) else {
+                        continue;
+                    };
+                    attributes.update(acl.handle, sent, &att);
+                    let (Pdu::WRITE_COMMAND
+                    | Pdu::WRITE_REQUEST
+                    | Pdu::HANDLE_VALUE_NOTIFICATION
+                    | Pdu::HANDLE_VALUE_INDICATION) = att.opcode
+                    else {
+                        continue;
+                    };
+                    let Some((attribute, value)) = att.params.split_first_chunk::<2>() else {
+                        continue;
+                    };
+                    let uuid = attributes.uuid(acl.handle, u16::from_le_bytes(*attribute));
+                    if !uuid
+                        .and_then(|uuid| uuid.as_u16())
+                        .is_some_and(|uuid| CHARACTERISTICS.contains(&uuid))
+                    {
+                        continue;
+                    }
+                    let Ok(segment) = ProxyPdu::try_from(value) else {
+                        continue;
+                    };
+                    messages.extend(proxy.push(acl.handle, sent, pdu.packet_index, &segment));
+                }
+            }
+            _ => {}
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        analysis::test_capture::{h4, l2cap},
+        mesh::MessageType,
+    };
+
+    #[test]
+    fn provisioning_over_gatt() {
+        // Find Information Response: 0x0010 Mesh Provisioning Data In, 0x0012 Data Out
+        let discovery = [0x05, 0x01, 0x10, 0x00, 0xdb, 0x2a, 0x12, 0x00, 0xdc, 0x2a];
+        let capture = h4(vec![
+            (0, true, l2cap(0x40, 0x0004, &discovery)),
+            // Provisioning Invite
+            (
+                1,
+                false,
+                l2cap(0x40, 0x0004, &[0x52, 0x10, 0x00, 0x03, 0x00, 0x05]),
+            ),
+            // Provisioning Capabilities, in two segments
+            (
+                2,
+                true,
+                l2cap(0x40, 0x0004, &[0x1b, 0x12, 0x00, 0x43, 0x01, 0x01, 0x00]),
+            ),
+            (
+                3,
+                true,
+                l2cap(
+                    0x40,
+                    0x0004,
+                    &[
+                        0x1b, 0x12, 0x00, 0xc3, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        0x00,
+                    ],
+                ),
+            ),
+            // an unknown attribute
+            (
+                4,
+                false,
+                l2cap(0x40, 0x0004, &[0x52, 0x20, 0x00, 0x03, 0x00, 0x05]),
+            ),
+        ]);
+        let messages = mesh_messages(&capture);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].sent);
+        assert_eq!(messages[1].packet_index, 2);
+        assert_eq!(messages[1].segments, 2);
+        assert_eq!(messages[1].message_type, MessageType::ProvisioningPdu);
+        assert_eq!(
+            mesh::describe(messages[1].message_type, &messages[1].data),
+            "Provisioning Capabilities: 1 elements, algorithms 0x0001, public key OOB false, \
+             static OOB false, output OOB size 0 actions 0x0000, input OOB size 0 actions 0x0000"
+        );
+    }
+}
@@ -0,0 +1,855 @@
+//! Security audit of the pairings of a capture: the pairing method and association model of
+//! each LE Security Manager pairing, how it ended, and whether its keys can be recovered by
+//! anyone who captured it; and of each BR/EDR pairing, Secure Simple Pairing or legacy PIN
+//! pairing, from the events the Controller sent the Host.
+//!
+//! An LE legacy pairing with Just Works or Passkey Entry is crackable: its temporary key is 0
+//! or a passkey of at most 999999, so trying each one against the Mconfirm and Mrand values
+//! exchanged in plain finds it, and with it the short term key and the keys distributed
+//! encrypted by it. The confirm values also cover the addresses of both devices: the peer's is
+//! taken from the LE Connection Complete event, the local one from the Read BD_ADDR and LE Set
+//! Random Address commands of the capture. Recovering the passkeys needs the `crypto` feature.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{opcode, BdAddr, CommandComplete, Event, LeMetaEvent},
+    l2cap::BasicFrame,
+    Btsnoop, UartData,
+};
+
+#[cfg(feature = "crypto")]
+use crate::crypto::c1;
+
+// data format from: Core Specification 5.4, Vol 3 Part H 2.3.5.1 Selecting key generation
+// method, 2.3.5.5 LE legacy pairing phase 2 and 3.5 Pairing methods.
+
+/// Largest passkey of Passkey Entry
+pub const MAX_PASSKEY: u32 = 999_999;
+
+const PAIRING_REQUEST: u8 = 0x01;
+const PAIRING_RESPONSE: u8 = 0x02;
+const PAIRING_CONFIRM: u8 = 0x03;
+#[cfg(feature = "crypto")]
+const PAIRING_RANDOM: u8 = 0x04;
+const PAIRING_FAILED: u8 = 0x05;
+
+pub fn failure_reason_name(reason: u8) -> Option<&'static str> {
+    let name = match reason {
+        0x01 => "Passkey Entry Failed",
+        0x02 => "OOB Not Available",
+        0x03 => "Authentication Requirements",
+        0x04 => "Confirm Value Failed",
+        0x05 => "Pairing Not Supported",
+        0x06 => "Encryption Key Size",
+        0x07 => "Command Not Supported",
+        0x08 => "Unspecified Reason",
+        0x09 => "Repeated Attempts",
+        0x0A => "Invalid Parameters",
+        0x0B => "DHKey Check Failed",
+        0x0C => "Numeric Comparison Failed",
+        0x0D => "BR/EDR Pairing In Progress",
+        0x0E => "Cross-transport Key Derivation/Generation Not Allowed",
+        0x0F => "Key Rejected",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The temporary key of a passkey, least significant octet first
+pub fn passkey_tk(passkey: u32) -> [u8; 16] {
+    let mut tk = [0; 16];
+    tk[..4].copy_from_slice(&passkey.to_le_bytes());
+    tk
+}
+
+#[cfg(feature = "crypto")]
+fn reversed<const N: usize>(mut data: [u8; N]) -> [u8; N] {
+    data.reverse();
+    data
+}
+
+/// A Pairing Request or Pairing Response PDU, opcode included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PairingFeatures(pub [u8; 7]);
+
+impl PairingFeatures {
+    pub const DISPLAY_ONLY: u8 = 0x00;
+    pub const DISPLAY_YES_NO: u8 = 0x01;
+    pub const KEYBOARD_ONLY: u8 = 0x02;
+    pub const NO_INPUT_NO_OUTPUT: u8 = 0x03;
+    pub const KEYBOARD_DISPLAY: u8 = 0x04;
+
+    pub fn io_capability(&self) -> u8 {
+        self.0[1]
+    }
+
+    /// out of band data of the peer is present
+    pub fn oob(&self) -> bool {
+        self.0[2] != 0
+    }
+
+    pub fn bonding(&self) -> bool {
+        self.0[3] & 0b11 == 0b01
+    }
+
+    pub fn mitm(&self) -> bool {
+        self.0[3] & 0x04 != 0
+    }
+
+    pub fn secure_connections(&self) -> bool {
+        self.0[3] & 0x08 != 0
+    }
+
+    pub fn max_key_size(&self) -> u8 {
+        self.0[4]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssociationModel {
+    JustWorks,
+    PasskeyEntry,
+    NumericComparison,
+    OutOfBand,
+}
+
+impl AssociationModel {
+    /// The model of a pairing, from the features of the initiator and of the responder
+    pub fn select(request: &PairingFeatures, response: &PairingFeatures) -> Self {
+        use AssociationModel::*;
+
+        let secure_connections = request.secure_connections() && response.secure_connections();
+        let oob = match secure_connections {
+            true => request.oob() || response.oob(),
+            false => request.oob() && response.oob(),
+        };
+        if oob {
+            return OutOfBand;
+        }
+        if !request.mitm() && !response.mitm() {
+            return JustWorks;
+        }
+        const NO_IO: u8 = PairingFeatures::NO_INPUT_NO_OUTPUT;
+        const KEYBOARD: u8 = PairingFeatures::KEYBOARD_ONLY;
+        const DISPLAY: u8 = PairingFeatures::DISPLAY_ONLY;
+        const YES_NO: u8 = PairingFeatures::DISPLAY_YES_NO;
+        const KEYBOARD_DISPLAY: u8 = PairingFeatures::KEYBOARD_DISPLAY;
+        match (request.io_capability(), response.io_capability()) {
+            (NO_IO, _) | (_, NO_IO) => JustWorks,
+            (initiator, responder)
+                if initiator > KEYBOARD_DISPLAY || responder > KEYBOARD_DISPLAY =>
+            {
+                JustWorks
+            }
+            (KEYBOARD, _) | (_, KEYBOARD) => PasskeyEntry,
+            (DISPLAY, DISPLAY) | (DISPLAY, YES_NO) | (YES_NO, DISPLAY) => JustWorks,
+            (DISPLAY, KEYBOARD_DISPLAY) | (KEYBOARD_DISPLAY, DISPLAY) => PasskeyEntry,
+            // both display and confirm or enter
+            _ if secure_connections => NumericComparison,
+            (YES_NO, YES_NO) => JustWorks,
+            _ => PasskeyEntry,
+        }
+    }
+}
+
+impl Display for AssociationModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AssociationModel::JustWorks => "Just Works",
+            AssociationModel::PasskeyEntry => "Passkey Entry",
+            AssociationModel::NumericComparison => "Numeric Comparison",
+            AssociationModel::OutOfBand => "Out of Band",
+        })
+    }
+}
+
+/// What the confirm values of an LE legacy pairing are computed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LegacyPairing {
+    pub request: PairingFeatures,
+    pub response: PairingFeatures,
+    /// address type, 0 for public and 1 for random, and address of the initiator
+    pub initiator: (u8, BdAddr),
+    pub responder: (u8, BdAddr),
+}
+
+#[cfg(feature = "crypto")]
+impl LegacyPairing {
+    /// p1 and p2 of c1, most significant octet first
+    fn p1_p2(&self) -> ([u8; 16], [u8; 16]) {
+        let mut p1 = [0; 16];
+        p1[0] = self.initiator.0;
+        p1[1] = self.responder.0;
+        p1[2..9].copy_from_slice(&self.request.0);
+        p1[9..].copy_from_slice(&self.response.0);
+        let mut p2 = [0; 16];
+        p2[..6].copy_from_slice(&self.responder.1 .0);
+        p2[6..12].copy_from_slice(&self.initiator.1 .0);
+        (reversed(p1), reversed(p2))
+    }
+
+    /// The confirm value of `random` with temporary key `tk`, all least significant octet
+    /// first as in SMP PDUs
+    pub fn confirm(&self, tk: &[u8; 16], random: &[u8; 16]) -> [u8; 16] {
+        let (p1, p2) = self.p1_p2();
+        reversed(c1(&reversed(*tk), &reversed(*random), &p1, &p2))
+    }
+
+    /// The passkey, 0 for Just Works, whose temporary key gives `confirm` for `random`: the
+    /// Mconfirm and Mrand of the initiator or the Sconfirm and Srand of the responder
+    pub fn crack(&self, confirm: &[u8; 16], random: &[u8; 16]) -> Option<u32> {
+        let (p1, p2) = self.p1_p2();
+        let (confirm, random) = (reversed(*confirm), reversed(*random));
+        (0..=MAX_PASSKEY)
+            .find(|passkey| c1(&reversed(passkey_tk(*passkey)), &random, &p1, &p2) == confirm)
+    }
+}
+
+/// A pairing of the capture, from its Pairing Request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub handle: u16,
+    /// of the Pairing Request
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// the local device is the initiator, the Central
+    pub local_initiator: bool,
+    pub peer: Option<BdAddr>,
+    pub request: PairingFeatures,
+    pub response: Option<PairingFeatures>,
+    /// reason of the Pairing Failed PDU which ended it
+    pub failed: Option<u8>,
+    /// passkey of the temporary key recovered from the capture, 0 for Just Works
+    pub passkey: Option<u32>,
+}
+
+impl Pairing {
+    pub fn secure_connections(&self) -> bool {
+        self.request.secure_connections()
+            && self
+                .response
+                .is_some_and(|response| response.secure_connections())
+    }
+
+    pub fn model(&self) -> Option<AssociationModel> {
+        self.response
+            .map(|response| AssociationModel::select(&self.request, &response))
+    }
+
+    /// An LE legacy pairing with Just Works or Passkey Entry, its keys can be recovered from
+    /// the capture of the pairing
+    pub fn crackable(&self) -> bool {
+        !self.secure_connections()
+            && matches!(
+                self.model(),
+                Some(AssociationModel::JustWorks | AssociationModel::PasskeyEntry)
+            )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Connection {
+    /// address type and address
+    #[cfg_attr(not(feature = "crypto"), allow(dead_code))]
+    local: Option<(u8, BdAddr)>,
+    peer: Option<(u8, BdAddr)>,
+    /// index of the pairing in progress, with the Mconfirm of the initiator
+    pairing: Option<(usize, Option<[u8; 16]>)>,
+}
+
+/// Audit every LE pairing of the capture, recovering the passkeys of the crackable ones whose
+/// addresses are known
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn pairing_audit(capture: &Btsnoop) -> Vec<Pairing> {
+    let mut pairings: Vec<Pairing> = vec![];
+    let mut connections: HashMap<u16, Connection> = HashMap::new();
+    let mut public = None;
+    let mut random = None;
+    // own address type of the last connection or advertising parameters
+    let mut own_address_type = 0;
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            UartData::Command(command) => {
+                let params = command.params;
+                let offset = match command.opcode.raw() {
+                    // LE Set Random Address
+                    0x2005 if params.len() >= 6 => {
+                        random = BdAddr::parse(&mut &params[..]).ok();
+                        continue;
+                    }
+                    // LE Set Advertising Parameters
+                    0x2006 => 5,
+                    // LE Create Connection
+                    0x200D => 12,
+                    // LE Set Extended Advertising Parameters
+                    0x2036 => 10,
+                    // LE Extended Create Connection
+                    0x2043 => 1,
+                    _ => continue,
+                };
+                if let Some(own) = params.get(offset) {
+                    own_address_type = *own;
+                }
+            }
+            UartData::Event(event) if event.code == Event::COMMAND_COMPLETE => {
+                // Read BD_ADDR
+                if let Ok(complete) = CommandComplete::try_from(event.params) {
+                    if complete.opcode.raw() == 0x1009 && complete.status() == Some(0) {
+                        public = BdAddr::parse(&mut &complete.return_parameters[1..]).ok();
+                    }
+                }
+            }
+            UartData::Event(event)
+                if event.code == Event::DISCONNECTION_COMPLETE
+                    && event.params.len() >= 3
+                    && event.params[0] == 0 =>
+            {
+                let handle = u16::from_le_bytes([event.params[1], event.params[2]]) & 0x0FFF;
+                connections.remove(&handle);
+            }
+            UartData::Event(event) if event.code == Event::LE_META => {
+                let Ok(LeMetaEvent::ConnectionComplete(complete)) =
+                    LeMetaEvent::try_from(event.params)
+                else {
+                    continue;
+                };
+                if complete.status != 0 {
+                    continue;
+                }
+                let unset = |address: &&BdAddr| address.0 != [0; 6];
+                let local = match complete
+                    .local_resolvable_private_address
+                    .as_ref()
+                    .filter(unset)
+                {
+                    Some(rpa) => Some((1, *rpa)),
+                    // public, random, or the resolvable private address they fall back to
+                    None if own_address_type & 1 == 1 => random.map(|a| (1, a)),
+                    None => public.map(|a| (0, a)),
+                };
+                let peer = match complete
+                    .peer_resolvable_private_address
+                    .as_ref()
+                    .filter(unset)
+                {
+                    Some(rpa) => (1, *rpa),
+                    None => (complete.peer_address_type & 1, complete.peer_address),
+                };
+                connections.insert(
+                    complete.handle,
+                    Connection {
+                        local,
+                        peer: Some(peer),
+                        pairing: None,
+                    },
+                );
+            }
+            UartData::Acl(acl) if acl.packet_boundary_flag.is_start() => {
+                let Ok(frame) = BasicFrame::try_from(acl.data) else {
+                    continue;
+                };
+                let pdu = frame.payload;
+                if frame.channel_id != BasicFrame::SMP_CID || pdu.is_empty() {
+                    continue;
+                }
+                let sent = !is_received(packet);
+                let connection = connections.entry(acl.handle).or_default();
+                if pdu[0] == PAIRING_REQUEST && pdu.len() >= 7 {
+                    connection.pairing = Some((pairings.len(), None));
+                    pairings.push(Pairing {
+                        handle: acl.handle,
+                        packet_index,
+                        timestamp: packet.description.timestamp,
+                        local_initiator: sent,
+                        peer: connection.peer.map(|(_, address)| address),
+                        request: PairingFeatures(pdu[..7].try_into().unwrap_or_default()),
+                        response: None,
+                        failed: None,
+                        passkey: None,
+                    });
+                    continue;
+                }
+                let Some((index, mconfirm)) = &mut connection.pairing else {
+                    continue;
+                };
+                let pairing = &mut pairings[*index];
+                let from_initiator = sent == pairing.local_initiator;
+                match pdu[0] {
+                    PAIRING_RESPONSE if pdu.len() >= 7 => {
+                        pairing.response =
+                            Some(PairingFeatures(pdu[..7].try_into().unwrap_or_default()))
+                    }
+                    PAIRING_CONFIRM if pdu.len() >= 17 && from_initiator => {
+                        *mconfirm = pdu[1..17].try_into().ok()
+                    }
+                    #[cfg(feature = "crypto")]
+                    PAIRING_RANDOM if pdu.len() >= 17 && from_initiator => {
+                        let addresses = match pairing.local_initiator {
+                            true => connection.local.zip(connection.peer),
+                            false => connection.peer.zip(connection.local),
+                        };
+                        if let (true, Some(confirm), Some(response), Some((initiator, responder))) =
+                            (pairing.crackable(), *mconfirm, pairing.response, addresses)
+                        {
+                            let legacy = LegacyPairing {
+                                request: pairing.request,
+                                response,
+                                initiator,
+                                responder,
+                            };
+                            let random = pdu[1..17].try_into().unwrap_or_default();
+                            pairing.passkey = legacy.crack(&confirm, &random);
+                        }
+                    }
+                    PAIRING_FAILED if pdu.len() >= 2 => {
+                        pairing.failed = Some(pdu[1]);
+                        connection.pairing = None;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pairings
+}
+
+/// Name of a link key type of the Link Key Notification event
+pub fn key_type_name(key_type: u8) -> Option<&'static str> {
+    let name = match key_type {
+        0x00 => "Combination Key",
+        0x01 => "Local Unit Key",
+        0x02 => "Remote Unit Key",
+        0x03 => "Debug Combination Key",
+        0x04 => "Unauthenticated Combination Key generated from P-192",
+        0x05 => "Authenticated Combination Key generated from P-192",
+        0x06 => "Changed Combination Key",
+        0x07 => "Unauthenticated Combination Key generated from P-256",
+        0x08 => "Authenticated Combination Key generated from P-256",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The IO capabilities of a device in a BR/EDR Secure Simple Pairing, from the IO Capability
+/// Request Reply command or the IO Capability Response event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoCapabilities {
+    /// 0x00 DisplayOnly, 0x01 DisplayYesNo, 0x02 KeyboardOnly, 0x03 NoInputNoOutput
+    pub io_capability: u8,
+    /// out of band data of the peer is present
+    pub oob: bool,
+    pub authentication_requirements: u8,
+}
+
+impl IoCapabilities {
+    fn parse(params: &[u8]) -> Option<Self> {
+        let params = params.get(6..9)?;
+        Some(Self {
+            io_capability: params[0],
+            oob: params[1] != 0,
+            authentication_requirements: params[2],
+        })
+    }
+
+    pub fn mitm(&self) -> bool {
+        self.authentication_requirements & 0x01 != 0
+    }
+
+    pub fn bonding(&self) -> bool {
+        self.authentication_requirements & 0x06 != 0
+    }
+}
+
+impl AssociationModel {
+    /// The model of a BR/EDR Secure Simple Pairing, from the capabilities of both devices
+    pub fn select_classic(a: &IoCapabilities, b: &IoCapabilities) -> Self {
+        use AssociationModel::*;
+
+        if a.oob || b.oob {
+            return OutOfBand;
+        }
+        if !a.mitm() && !b.mitm() {
+            return JustWorks;
+        }
+        const YES_NO: u8 = PairingFeatures::DISPLAY_YES_NO;
+        const KEYBOARD: u8 = PairingFeatures::KEYBOARD_ONLY;
+        match (a.io_capability, b.io_capability) {
+            (a, b) if a > KEYBOARD || b > KEYBOARD => JustWorks,
+            (KEYBOARD, _) | (_, KEYBOARD) => PasskeyEntry,
+            (YES_NO, YES_NO) => NumericComparison,
+            // numeric comparison confirmed automatically by the device which only displays
+            _ => JustWorks,
+        }
+    }
+}
+
+/// A BR/EDR pairing of the capture, from its first pairing event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassicPairing {
+    pub peer: BdAddr,
+    /// of the first pairing event
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// the local device asked for the IO capabilities first; none when only one side was seen
+    pub local_initiator: Option<bool>,
+    pub local: Option<IoCapabilities>,
+    pub remote: Option<IoCapabilities>,
+    /// a legacy pairing with a PIN code, not Secure Simple Pairing
+    pub pin_code: bool,
+    /// value of the User Confirmation Request
+    pub numeric_value: Option<u32>,
+    /// the local Host was asked for the passkey
+    pub passkey_requested: bool,
+    /// passkey of the User Passkey Notification
+    pub passkey: Option<u32>,
+    pub oob_requested: bool,
+    /// status of the Simple Pairing Complete event
+    pub status: Option<u8>,
+    /// type of the link key notified
+    pub key_type: Option<u8>,
+}
+
+impl ClassicPairing {
+    fn new(peer: BdAddr, packet_index: usize, timestamp: i64) -> Self {
+        Self {
+            peer,
+            packet_index,
+            timestamp,
+            local_initiator: None,
+            local: None,
+            remote: None,
+            pin_code: false,
+            numeric_value: None,
+            passkey_requested: false,
+            passkey: None,
+            oob_requested: false,
+            status: None,
+            key_type: None,
+        }
+    }
+
+    /// Whether the link key was generated from P-256, Secure Connections, once notified
+    pub fn secure_connections(&self) -> Option<bool> {
+        self.key_type
+            .map(|key_type| matches!(key_type, 0x07 | 0x08))
+    }
+
+    /// Whether the link key is protected against man in the middle attacks, once notified
+    pub fn authenticated(&self) -> Option<bool> {
+        self.key_type
+            .map(|key_type| matches!(key_type, 0x05 | 0x08))
+    }
+
+    /// The model from the IO capabilities of both devices, or else from the events of the
+    /// pairing; none for legacy PIN pairings
+    pub fn model(&self) -> Option<AssociationModel> {
+        if self.pin_code {
+            return None;
+        }
+        if let (Some(local), Some(remote)) = (self.local, self.remote) {
+            return Some(AssociationModel::select_classic(&local, &remote));
+        }
+        if self.oob_requested {
+            Some(AssociationModel::OutOfBand)
+        } else if self.passkey_requested || self.passkey.is_some() {
+            Some(AssociationModel::PasskeyEntry)
+        } else if self.numeric_value.is_some() {
+            match self.authenticated() {
+                Some(false) => Some(AssociationModel::JustWorks),
+                _ => Some(AssociationModel::NumericComparison),
+            }
+        } else {
+            None
+        }
+    }
+}
+
+/// Audit every BR/EDR pairing of the capture. The pairing events carry the address of the
+/// peer, not a connection handle: a pairing with a peer lasts until its Simple Pairing Complete
+/// event, or for a legacy pairing its link key.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn classic_pairing_audit(capture: &Btsnoop) -> Vec<ClassicPairing> {
+    let mut pairings: Vec<ClassicPairing> = vec![];
+    // peer -> index of its pairing in progress, and of its last pairing for the link key
+    let mut in_progress: HashMap<BdAddr, usize> = HashMap::new();
+    let mut last: HashMap<BdAddr, usize> = HashMap::new();
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let (code, params) = match data {
+            UartData::Event(event) => (Some(event.code), event.params),
+            UartData::Command(command) if command.opcode == opcode::IO_CAPABILITY_REQUEST_REPLY => {
+                (None, command.params)
+            }
+            _ => continue,
+        };
+        // the status of Simple Pairing Complete comes before the address
+        let offset = (code == Some(Event::SIMPLE_PAIRING_COMPLETE)) as usize;
+        let Ok(peer) = BdAddr::parse(&mut params.get(offset..).unwrap_or_default()) else {
+            continue;
+        };
+        let starts = matches!(
+            code,
+            Some(
+                Event::PIN_CODE_REQUEST
+                    | Event::IO_CAPABILITY_REQUEST
+                    | Event::IO_CAPABILITY_RESPONSE
+                    | Event::USER_CONFIRMATION_REQUEST
+                    | Event::USER_PASSKEY_REQUEST
+                    | Event::REMOTE_OOB_DATA_REQUEST
+                    | Event::USER_PASSKEY_NOTIFICATION
+            )
+        );
+        let index = match (in_progress.get(&peer), starts) {
+            (Some(index), _) => *index,
+            (None, true) => {
+                pairings.push(ClassicPairing::new(
+                    peer,
+                    packet_index,
+                    packet.description.timestamp,
+                ));
+                in_progress.insert(peer, pairings.len() - 1);
+                last.insert(peer, pairings.len() - 1);
+                pairings.len() - 1
+            }
+            (None, false) => match (code, last.get(&peer)) {
+                (Some(Event::LINK_KEY_NOTIFICATION), Some(index)) => *index,
+                _ => continue,
+            },
+        };
+        let pairing = &mut pairings[index];
+        let value = || {
+            params
+                .get(6..10)
+                .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        };
+        match code {
+            None => pairing.local = IoCapabilities::parse(params),
+            Some(Event::PIN_CODE_REQUEST) => pairing.pin_code = true,
+            Some(Event::IO_CAPABILITY_REQUEST) => {
+                pairing.local_initiator.get_or_insert(true);
+            }
+            Some(Event::IO_CAPABILITY_RESPONSE) => {
+                pairing.remote = IoCapabilities::parse(params);
+                pairing.local_initiator.get_or_insert(false);
+            }
+            Some(Event::USER_CONFIRMATION_REQUEST) => pairing.numeric_value = value(),
+            Some(Event::USER_PASSKEY_REQUEST) => pairing.passkey_requested = true,
+            Some(Event::USER_PASSKEY_NOTIFICATION) => pairing.passkey = value(),
+            Some(Event::REMOTE_OOB_DATA_REQUEST) => pairing.oob_requested = true,
+            Some(Event::SIMPLE_PAIRING_COMPLETE) => {
+                pairing.status = params.first().copied();
+                in_progress.remove(&peer);
+            }
+            Some(Event::LINK_KEY_NOTIFICATION) => {
+                pairing.key_type = params.get(22).copied();
+                in_progress.remove(&peer);
+            }
+            _ => {}
+        }
+    }
+
+    pairings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4};
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn cracks_legacy_passkey() {
+        use crate::analysis::test_capture::l2cap;
+
+        let request = PairingFeatures([0x01, 0x04, 0x00, 0x05, 0x10, 0x01, 0x01]);
+        let response = PairingFeatures([0x02, 0x02, 0x00, 0x05, 0x10, 0x01, 0x01]);
+        assert_eq!(
+            AssociationModel::select(&request, &response),
+            AssociationModel::PasskeyEntry
+        );
+        let local = BdAddr([0x11, 0x22, 0x33, 0x44, 0x55, 0xc6]);
+        let peer = BdAddr([0xa6, 0xa5, 0xa4, 0xa3, 0xa2, 0xa1]);
+        let legacy = LegacyPairing {
+            request,
+            response,
+            initiator: (1, local),
+            responder: (0, peer),
+        };
+        let mrand = [0x5a; 16];
+        let mconfirm = legacy.confirm(&passkey_tk(1234), &mrand);
+        assert_eq!(legacy.crack(&mconfirm, &mrand), Some(1234));
+
+        // Core Specification 5.4, Vol 3 Part H 2.2.3 c1 sample data
+        let sample = LegacyPairing {
+            request: PairingFeatures([0x01, 0x01, 0x00, 0x00, 0x10, 0x07, 0x07]),
+            response: PairingFeatures([0x02, 0x03, 0x00, 0x00, 0x08, 0x00, 0x05]),
+            initiator: (1, peer),
+            responder: (0, BdAddr([0xb6, 0xb5, 0xb4, 0xb3, 0xb2, 0xb1])),
+        };
+        let mut confirm = [
+            0x1e, 0x1e, 0x3f, 0xef, 0x87, 0x89, 0x88, 0xea, 0xd2, 0xa7, 0x4d, 0xc5, 0xbe, 0xf1,
+            0x3b, 0x86,
+        ];
+        let mut random = [
+            0x57, 0x83, 0xd5, 0x21, 0x56, 0xad, 0x6f, 0x0e, 0x63, 0x88, 0x27, 0x4e, 0xc6, 0x70,
+            0x2e, 0xe0,
+        ];
+        confirm.reverse();
+        random.reverse();
+        assert_eq!(sample.confirm(&[0; 16], &random), confirm);
+
+        let smp = |pdu: &[u8]| l2cap(0x40, BasicFrame::SMP_CID, pdu);
+        let value = |opcode: u8, value: [u8; 16]| {
+            let mut pdu = vec![opcode];
+            pdu.extend_from_slice(&value);
+            smp(&pdu)
+        };
+        let mut connection_complete = vec![0x01, 0x00, 0x40, 0x00, 0x00, 0x00];
+        connection_complete.extend_from_slice(&peer.0);
+        connection_complete.extend_from_slice(&[0x18, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00]);
+        let mut set_random = vec![];
+        set_random.extend_from_slice(&local.0);
+        let capture = h4(vec![
+            (0, false, command(0x08, 0x0005, &set_random)),
+            // LE Create Connection from the random address
+            (
+                1,
+                false,
+                command(
+                    0x08,
+                    0x000D,
+                    &[0x60, 0, 0x30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01],
+                ),
+            ),
+            (2, true, event(Event::LE_META, &connection_complete)),
+            (3, false, smp(&request.0)),
+            (4, true, smp(&response.0)),
+            (5, false, value(PAIRING_CONFIRM, mconfirm)),
+            (6, true, value(PAIRING_CONFIRM, [0; 16])),
+            (7, false, value(PAIRING_RANDOM, mrand)),
+            // a Secure Connections pairing which fails
+            (8, true, smp(&[0x01, 0x03, 0x00, 0x0D, 0x10, 0x01, 0x01])),
+            (9, false, smp(&[0x02, 0x03, 0x00, 0x09, 0x10, 0x01, 0x01])),
+            (10, true, smp(&[PAIRING_FAILED, 0x05])),
+        ]);
+        let pairings = pairing_audit(&capture);
+        assert_eq!(pairings.len(), 2);
+        assert!(pairings[0].crackable() && pairings[0].local_initiator);
+        assert_eq!(pairings[0].peer, Some(peer));
+        assert_eq!(pairings[0].passkey, Some(1234));
+        assert!(pairings[1].secure_connections() && !pairings[1].crackable());
+        assert_eq!(pairings[1].model(), Some(AssociationModel::JustWorks));
+        assert_eq!(pairings[1].failed, Some(0x05));
+    }
+
+    #[test]
+    fn classic_pairings() {
+        let peer = BdAddr([0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        let with_peer = |before: &[u8], after: &[u8]| {
+            let mut params = before.to_vec();
+            params.extend_from_slice(&peer.0);
+            params.extend_from_slice(after);
+            params
+        };
+        let mut link_key = [0x11; 17];
+        link_key[16] = 0x08;
+        let capture = h4(vec![
+            // initiated locally, both DisplayYesNo with MITM
+            (
+                0,
+                true,
+                event(Event::IO_CAPABILITY_REQUEST, &with_peer(&[], &[])),
+            ),
+            (
+                1,
+                false,
+                command(0x01, 0x002B, &with_peer(&[], &[0x01, 0x00, 0x03])),
+            ),
+            (
+                2,
+                true,
+                event(
+                    Event::IO_CAPABILITY_RESPONSE,
+                    &with_peer(&[], &[0x01, 0x00, 0x03]),
+                ),
+            ),
+            (
+                3,
+                true,
+                event(
+                    Event::USER_CONFIRMATION_REQUEST,
+                    &with_peer(&[], &123456u32.to_le_bytes()),
+                ),
+            ),
+            (
+                4,
+                true,
+                event(Event::SIMPLE_PAIRING_COMPLETE, &with_peer(&[0x00], &[])),
+            ),
+            (
+                5,
+                true,
+                event(Event::LINK_KEY_NOTIFICATION, &with_peer(&[], &link_key)),
+            ),
+            // a responder without the IO capabilities of the initiator, which fails
+            (
+                6,
+                true,
+                event(Event::USER_PASSKEY_REQUEST, &with_peer(&[], &[])),
+            ),
+            (
+                7,
+                true,
+                event(Event::SIMPLE_PAIRING_COMPLETE, &with_peer(&[0x05], &[])),
+            ),
+            // legacy PIN pairing
+            (
+                8,
+                true,
+                event(Event::PIN_CODE_REQUEST, &with_peer(&[], &[])),
+            ),
+        ]);
+        let pairings = classic_pairing_audit(&capture);
+        assert_eq!(pairings.len(), 3);
+
+        let pairing = &pairings[0];
+        assert_eq!((pairing.peer, pairing.local_initiator), (peer, Some(true)));
+        assert_eq!(pairing.model(), Some(AssociationModel::NumericComparison));
+        assert_eq!(pairing.numeric_value, Some(123456));
+        assert_eq!(pairing.status, Some(0x00));
+        assert_eq!(pairing.secure_connections(), Some(true));
+        assert_eq!(pairing.authenticated(), Some(true));
+
+        assert_eq!(pairings[1].model(), Some(AssociationModel::PasskeyEntry));
+        assert_eq!(pairings[1].status, Some(0x05));
+        assert_eq!(pairings[1].secure_connections(), None);
+
+        assert!(pairings[2].pin_code);
+        assert_eq!(pairings[2].model(), None);
+
+        let display = IoCapabilities {
+            io_capability: 0x00,
+            oob: false,
+            authentication_requirements: 0x01,
+        };
+        let yes_no = IoCapabilities {
+            io_capability: 0x01,
+            ..display
+        };
+        assert_eq!(
+            AssociationModel::select_classic(&display, &yes_no),
+            AssociationModel::JustWorks
+        );
+    }
+}
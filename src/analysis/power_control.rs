@@ -0,0 +1,221 @@
+//! LE power control timelines: the transmit power levels of both ends of each LE connection and
+//! the path loss zones it went through, from the LE Power Control reports and reads of a
+//! capture, to tune range and coexistence on Bluetooth 5.2 devices.
+
+use std::collections::HashMap;
+
+use crate::{
+    analysis::uart_packets,
+    hci::{
+        opcode::LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL,
+        power_control::{EnhancedTransmitPowerLevel, TX_POWER_NOT_AVAILABLE},
+        CommandComplete, DisconnectionComplete, Event, LeMetaEvent,
+    },
+    Btsnoop, UartData,
+};
+
+/// A transmit power level of one end of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxPowerLevel {
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// the level of the remote device, not of the local Controller
+    pub remote: bool,
+    pub phy: u8,
+    /// in dBm
+    pub level: i8,
+    /// only known when the local level was read
+    pub max_level: Option<i8>,
+    /// change from the previous level in dB, only known when it was reported
+    pub delta: Option<i8>,
+    /// bit 0 at the minimum level, bit 1 at the maximum level, only known when it was reported
+    pub flags: Option<u8>,
+}
+
+/// The path loss zone a connection entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLossZone {
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// in dB, none when the Controller couldn't tell
+    pub path_loss: Option<u8>,
+    /// 0x00 low, 0x01 middle, 0x02 high
+    pub zone: u8,
+}
+
+/// The power levels and path loss zones of one connection, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionPower {
+    pub handle: u16,
+    /// packet index of the first report
+    pub first_packet: usize,
+    pub tx_power: Vec<TxPowerLevel>,
+    pub path_loss: Vec<PathLossZone>,
+}
+
+impl ConnectionPower {
+    /// The levels of the local Controller, or of the remote device
+    pub fn levels(&self, remote: bool) -> impl Iterator<Item = &TxPowerLevel> {
+        self.tx_power
+            .iter()
+            .filter(move |level| level.remote == remote)
+    }
+}
+
+#[derive(Default)]
+struct Timelines {
+    connections: Vec<ConnectionPower>,
+    /// handle -> index of its connection, until it is disconnected
+    open: HashMap<u16, usize>,
+}
+
+impl Timelines {
+    fn connection(&mut self, handle: u16, packet_index: usize) -> &mut ConnectionPower {
+        let connections = &mut self.connections;
+        let index = *self.open.entry(handle).or_insert_with(|| {
+            connections.push(ConnectionPower {
+                handle,
+                first_packet: packet_index,
+                ..Default::default()
+            });
+            connections.len() - 1
+        });
+        &mut self.connections[index]
+    }
+}
+
+/// The power timelines of every connection with a report or read, in order of the first one.
+/// A handle reused after a disconnection starts another timeline.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn power_timelines(capture: &Btsnoop) -> Vec<ConnectionPower> {
+    let mut timelines = Timelines::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let UartData::Event(event) = data else {
+            continue;
+        };
+        let timestamp = packet.description.timestamp;
+        match event.code {
+            Event::LE_META => match LeMetaEvent::try_from(event.params) {
+                Ok(LeMetaEvent::TransmitPowerReporting(report)) if report.status == 0 => {
+                    let Some(level) = report.tx_power() else {
+                        continue;
+                    };
+                    let delta = (report.delta != TX_POWER_NOT_AVAILABLE).then_some(report.delta);
+                    timelines
+                        .connection(report.handle, packet_index)
+                        .tx_power
+                        .push(TxPowerLevel {
+                            packet_index,
+                            timestamp,
+                            remote: report.is_remote(),
+                            phy: report.phy,
+                            level,
+                            max_level: None,
+                            delta,
+                            flags: Some(report.tx_power_level_flag),
+                        });
+                }
+                Ok(LeMetaEvent::PathLossThreshold(threshold)) => {
+                    timelines
+                        .connection(threshold.handle, packet_index)
+                        .path_loss
+                        .push(PathLossZone {
+                            packet_index,
+                            timestamp,
+                            path_loss: threshold.path_loss(),
+                            zone: threshold.zone_entered,
+                        });
+                }
+                _ => {}
+            },
+            Event::COMMAND_COMPLETE => {
+                let Ok(complete) = CommandComplete::try_from(event.params) else {
+                    continue;
+                };
+                if complete.opcode != LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL {
+                    continue;
+                }
+                let Ok(read) =
+                    EnhancedTransmitPowerLevel::parse(&mut &complete.return_parameters[..])
+                else {
+                    continue;
+                };
+                if read.status != 0 || read.current_tx_power_level == TX_POWER_NOT_AVAILABLE {
+                    continue;
+                }
+                timelines
+                    .connection(read.handle, packet_index)
+                    .tx_power
+                    .push(TxPowerLevel {
+                        packet_index,
+                        timestamp,
+                        remote: false,
+                        phy: read.phy,
+                        level: read.current_tx_power_level,
+                        max_level: Some(read.max_tx_power_level),
+                        delta: None,
+                        flags: None,
+                    });
+            }
+            Event::DISCONNECTION_COMPLETE => {
+                if let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) {
+                    timelines.open.remove(&disconnection.handle);
+                }
+            }
+            _ => {}
+        }
+    }
+    timelines.connections
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4};
+
+    #[test]
+    fn timelines() {
+        let capture = h4(vec![
+            // local level read: 0 dBm on LE 1M, 8 dBm at most
+            (
+                0,
+                true,
+                event(
+                    0x0e,
+                    &[0x01, 0x76, 0x20, 0x00, 0x40, 0x00, 0x01, 0x00, 0x08],
+                ),
+            ),
+            // the remote lowered its level by 4 dB to -4 dBm
+            (
+                1,
+                true,
+                event(
+                    0x3e,
+                    &[0x21, 0x00, 0x40, 0x00, 0x01, 0x01, 0xfc, 0x00, 0xfc],
+                ),
+            ),
+            // high path loss zone at 75 dB
+            (2, true, event(0x3e, &[0x20, 0x40, 0x00, 75, 0x02])),
+            (3, true, event(0x05, &[0x00, 0x40, 0x00, 0x13])),
+            // a new connection with the same handle
+            (4, true, event(0x3e, &[0x20, 0x40, 0x00, 40, 0x00])),
+        ]);
+        let timelines = power_timelines(&capture);
+        assert_eq!(timelines.len(), 2);
+        let first = &timelines[0];
+        assert_eq!(first.handle, 0x0040);
+        assert_eq!(first.tx_power.len(), 2);
+        let local: Vec<_> = first.levels(false).collect();
+        assert_eq!(local[0].level, 0);
+        assert_eq!(local[0].max_level, Some(8));
+        let remote: Vec<_> = first.levels(true).collect();
+        assert_eq!(remote[0].level, -4);
+        assert_eq!(remote[0].delta, Some(-4));
+        assert_eq!(first.path_loss[0].path_loss, Some(75));
+        assert_eq!(timelines[1].first_packet, 4);
+        assert_eq!(timelines[1].path_loss[0].zone, 0x00);
+    }
+}
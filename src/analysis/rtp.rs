@@ -0,0 +1,408 @@
+//! AVDTP media stream validation: RTP sequence numbers and timestamps of every media stream,
+//! reporting losses, reordering and timestamp jumps, and how late packets crossed HCI compared to
+//! their media time.
+//!
+//! A sequence gap means the packets never crossed HCI, lateness means they did but behind schedule.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    avdtp::{self, RtpHeader, SignalingMessage},
+    hci::{DisconnectionComplete, Event},
+    l2cap::{BasicFrame, ChannelMap, PSM_AVDTP},
+    Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone)]
+pub struct RtpConfig {
+    /// packets crossing HCI later than this compared to their media time start a late period
+    pub late_threshold_us: i64,
+}
+
+impl Default for RtpConfig {
+    fn default() -> Self {
+        Self {
+            late_threshold_us: 200_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtpEvent {
+    /// `missing` packets before `sequence_number` never showed up (so far)
+    Loss {
+        packet_index: usize,
+        sequence_number: u16,
+        missing: u16,
+    },
+    /// a packet older than the highest sequence number seen
+    Reordered {
+        packet_index: usize,
+        sequence_number: u16,
+        highest: u16,
+    },
+    Duplicate {
+        packet_index: usize,
+        sequence_number: u16,
+    },
+    /// the timestamp advanced by `step` per packet instead of the usual `nominal`
+    TimestampJump {
+        packet_index: usize,
+        step: i64,
+        nominal: i64,
+    },
+    /// a late period started: the packet crossed HCI `lateness_us` behind its media time
+    Late {
+        packet_index: usize,
+        lateness_us: i64,
+    },
+}
+
+impl Display for RtpEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RtpEvent::Loss {
+                sequence_number,
+                missing,
+                ..
+            } => write!(f, "{} packets missing before #{}", missing, sequence_number),
+            RtpEvent::Reordered {
+                sequence_number,
+                highest,
+                ..
+            } => write!(f, "#{} arrived after #{}", sequence_number, highest),
+            RtpEvent::Duplicate {
+                sequence_number, ..
+            } => write!(f, "#{} duplicated", sequence_number),
+            RtpEvent::TimestampJump { step, nominal, .. } => write!(
+                f,
+                "timestamp advanced {} per packet instead of {}",
+                step, nominal
+            ),
+            RtpEvent::Late { lateness_us, .. } => write!(
+                f,
+                "{:.2} ms behind media time",
+                *lateness_us as f64 / 1000.0
+            ),
+        }
+    }
+}
+
+impl RtpEvent {
+    pub fn packet_index(&self) -> usize {
+        match self {
+            RtpEvent::Loss { packet_index, .. }
+            | RtpEvent::Reordered { packet_index, .. }
+            | RtpEvent::Duplicate { packet_index, .. }
+            | RtpEvent::TimestampJump { packet_index, .. }
+            | RtpEvent::Late { packet_index, .. } => *packet_index,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RtpStreamReport {
+    pub handle: u16,
+    /// L2CAP endpoint of the media channel on the Host of the capture
+    pub local_cid: u16,
+    /// media sent by the Host, or received from the remote device
+    pub sent: bool,
+    pub ssrc: u32,
+    pub payload_type: u8,
+    /// the sampling frequency from the stream configuration, when it was captured
+    pub clock_rate: Option<u32>,
+    pub packets: usize,
+    /// sequence numbers never seen
+    pub lost: usize,
+    pub reordered: usize,
+    pub duplicates: usize,
+    pub max_lateness_us: Option<i64>,
+    pub events: Vec<RtpEvent>,
+}
+
+#[derive(Debug)]
+struct Stream {
+    report: RtpStreamReport,
+    highest: u16,
+    /// timestamp of the highest sequence number
+    last_timestamp: u32,
+    nominal_step: Option<i64>,
+    first_arrival: i64,
+    /// media time since the first packet, in clock ticks
+    elapsed_ticks: i64,
+    min_drift_us: i64,
+    late: bool,
+}
+
+impl Stream {
+    fn packet(&mut self, packet_index: usize, now: i64, header: &RtpHeader, config: &RtpConfig) {
+        self.report.packets += 1;
+        let diff = header.sequence_number.wrapping_sub(self.highest) as i16;
+        if diff == 0 {
+            self.report.duplicates += 1;
+            self.report.events.push(RtpEvent::Duplicate {
+                packet_index,
+                sequence_number: header.sequence_number,
+            });
+            return;
+        }
+        if diff < 0 {
+            self.report.reordered += 1;
+            // it was counted as lost when the gap was seen
+            self.report.lost = self.report.lost.saturating_sub(1);
+            self.report.events.push(RtpEvent::Reordered {
+                packet_index,
+                sequence_number: header.sequence_number,
+                highest: self.highest,
+            });
+            return;
+        }
+        if diff > 1 {
+            self.report.lost += diff as usize - 1;
+            self.report.events.push(RtpEvent::Loss {
+                packet_index,
+                sequence_number: header.sequence_number,
+                missing: diff as u16 - 1,
+            });
+        }
+
+        let ticks = header.timestamp.wrapping_sub(self.last_timestamp) as i32 as i64;
+        let step = ticks / diff as i64;
+        match self.nominal_step {
+            None => self.nominal_step = Some(step),
+            Some(nominal) if nominal * diff as i64 != ticks => {
+                self.report.events.push(RtpEvent::TimestampJump {
+                    packet_index,
+                    step,
+                    nominal,
+                });
+                // report a lasting change once
+                self.nominal_step = Some(step);
+            }
+            _ => {}
+        }
+        self.highest = header.sequence_number;
+        self.last_timestamp = header.timestamp;
+        self.elapsed_ticks += ticks;
+
+        let Some(clock_rate) = self.report.clock_rate else {
+            return;
+        };
+        let media_us = self.elapsed_ticks * 1_000_000 / clock_rate as i64;
+        let drift_us = now
+            .saturating_sub(self.first_arrival)
+            .saturating_sub(media_us);
+        self.min_drift_us = self.min_drift_us.min(drift_us);
+        let lateness_us = drift_us.saturating_sub(self.min_drift_us);
+        self.report.max_lateness_us = self.report.max_lateness_us.max(Some(lateness_us));
+        if lateness_us > config.late_threshold_us && !self.late {
+            self.late = true;
+            self.report.events.push(RtpEvent::Late {
+                packet_index,
+                lateness_us,
+            });
+        } else if lateness_us <= config.late_threshold_us / 2 {
+            self.late = false;
+        }
+    }
+}
+
+/// Validate every AVDTP media stream of the capture, streams are returned in order of their first packet.
+///
+/// Media channels can only be recognized when the capture contains the L2CAP connection of the
+/// AVDTP channels: on every ACL connection the first AVDTP channel is the signaling channel and
+/// the following ones carry media. The clock rate comes from the last SBC or AAC Set Configuration
+/// seen on the ACL connection.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn validate_rtp_streams(capture: &Btsnoop, config: &RtpConfig) -> Vec<RtpStreamReport> {
+    let mut channels = ChannelMap::default();
+    // handle -> local cid of the AVDTP signaling channel
+    let mut signaling: HashMap<u16, u16> = HashMap::new();
+    let mut clock_rates: HashMap<u16, u32> = HashMap::new();
+    let mut streams: HashMap<(u16, u16, bool, u32), Stream> = HashMap::new();
+    let mut order = vec![];
+
+    for (packet_index, packet, data) in uart_packets(capture) {
+        let now = packet.description.timestamp;
+        let acl = match data {
+            UartData::Acl(acl) => acl,
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                if let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) {
+                    channels.disconnected(disconnection.handle);
+                    signaling.remove(&disconnection.handle);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if !acl.packet_boundary_flag.is_start() {
+            continue;
+        }
+        let Ok(frame) = BasicFrame::try_from(acl.data) else {
+            continue;
+        };
+        let sent = !is_received(packet);
+        let Some(channel) = channels.update(acl.handle, sent, packet_index, &frame) else {
+            continue;
+        };
+        if channel.psm != PSM_AVDTP {
+            continue;
+        }
+        let local_cid = channel.local_cid;
+        if frame.channel_id == BasicFrame::SIGNALING_CID {
+            // a new AVDTP channel opened, the first one is for signaling
+            signaling.entry(acl.handle).or_insert(local_cid);
+            continue;
+        }
+
+        if signaling.get(&acl.handle) == Some(&local_cid) {
+            let Ok(message) = SignalingMessage::try_from(frame.payload) else {
+                continue;
+            };
+            if message.signal_identifier == SignalingMessage::SET_CONFIGURATION
+                && message.message_type == SignalingMessage::COMMAND
+            {
+                let clock_rate = message
+                    .capabilities()
+                    .find(|(category, _)| *category == avdtp::MEDIA_CODEC_CATEGORY)
+                    .and_then(|(_, codec)| avdtp::codec_sampling_frequency(codec));
+                if let Some(clock_rate) = clock_rate {
+                    clock_rates.insert(acl.handle, clock_rate);
+                }
+            }
+            continue;
+        }
+
+        let Ok(header) = RtpHeader::parse(&mut &frame.payload[..]) else {
+            continue;
+        };
+        let key = (acl.handle, local_cid, sent, header.ssrc);
+        match streams.get_mut(&key) {
+            Some(stream) => stream.packet(packet_index, now, &header, config),
+            None => {
+                order.push(key);
+                streams.insert(
+                    key,
+                    Stream {
+                        report: RtpStreamReport {
+                            handle: acl.handle,
+                            local_cid,
+                            sent,
+                            ssrc: header.ssrc,
+                            payload_type: header.payload_type,
+                            clock_rate: clock_rates.get(&acl.handle).copied(),
+                            packets: 1,
+                            lost: 0,
+                            reordered: 0,
+                            duplicates: 0,
+                            max_lateness_us: None,
+                            events: vec![],
+                        },
+                        highest: header.sequence_number,
+                        last_timestamp: header.timestamp,
+                        nominal_step: None,
+                        first_arrival: now,
+                        elapsed_ticks: 0,
+                        min_drift_us: 0,
+                        late: false,
+                    },
+                );
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|key| streams.remove(&key))
+        .map(|stream| stream.report)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{h4, l2cap};
+
+    fn rtp(sequence_number: u16, timestamp: u32) -> Vec<u8> {
+        let mut data = vec![0x80, 0x60];
+        data.extend_from_slice(&sequence_number.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        // SBC media payload header and a frame
+        data.extend_from_slice(&[0x01, 0x9C]);
+        data
+    }
+
+    #[test]
+    fn sequence_and_timing() {
+        let connect = |id: u8, scid: u8| {
+            l2cap(
+                1,
+                BasicFrame::SIGNALING_CID,
+                &[0x02, id, 4, 0, 0x19, 0, scid, 0],
+            )
+        };
+        let accept = |id: u8, dcid: u8, scid: u8| {
+            l2cap(
+                1,
+                BasicFrame::SIGNALING_CID,
+                &[0x03, id, 8, 0, dcid, 0, scid, 0, 0, 0, 0, 0],
+            )
+        };
+        // Set Configuration: SBC 44.1 kHz
+        let set_configuration = l2cap(
+            1,
+            0x41,
+            &[
+                0x00, 0x03, 0x04, 0x04, 0x01, 0x00, 0x07, 0x06, 0x00, 0x00, 0x21, 0x15, 2, 53,
+            ],
+        );
+        // 128 samples per packet at 44.1 kHz is 2902 us
+        let capture = h4(vec![
+            (0, false, connect(1, 0x40)),
+            (1, true, accept(1, 0x41, 0x40)),
+            (2, false, set_configuration),
+            (3, false, connect(2, 0x42)),
+            (4, true, accept(2, 0x43, 0x42)),
+            (10_000, false, l2cap(1, 0x43, &rtp(1, 0))),
+            (12_902, false, l2cap(1, 0x43, &rtp(2, 128))),
+            (15_804, false, l2cap(1, 0x43, &rtp(4, 384))),
+            (15_900, false, l2cap(1, 0x43, &rtp(3, 256))),
+            (500_000, false, l2cap(1, 0x43, &rtp(5, 512))),
+            (500_100, false, l2cap(1, 0x43, &rtp(6, 1024))),
+        ]);
+        let reports = validate_rtp_streams(&capture, &RtpConfig::default());
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.clock_rate, Some(44100));
+        assert_eq!((report.packets, report.lost, report.reordered), (6, 0, 1));
+        assert_eq!(
+            report.events,
+            vec![
+                RtpEvent::Loss {
+                    packet_index: 7,
+                    sequence_number: 4,
+                    missing: 1
+                },
+                RtpEvent::Reordered {
+                    packet_index: 8,
+                    sequence_number: 3,
+                    highest: 4
+                },
+                RtpEvent::Late {
+                    packet_index: 9,
+                    lateness_us: 481_294
+                },
+                RtpEvent::TimestampJump {
+                    packet_index: 10,
+                    step: 512,
+                    nominal: 128
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Packet and byte counts of a capture, by HCI packet type, command, event and connection.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::Event,
+    Btsnoop, DatalinkType, UartData,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Count {
+    pub sent: usize,
+    pub received: usize,
+    /// packet data octets
+    pub bytes: u64,
+}
+
+impl Count {
+    pub fn total(&self) -> usize {
+        self.sent + self.received
+    }
+
+    fn add(&mut self, received: bool, bytes: usize) {
+        if received {
+            self.received += 1;
+        } else {
+            self.sent += 1;
+        }
+        self.bytes += bytes as u64;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Statistics {
+    pub packets: usize,
+    /// packet data octets included in the capture
+    pub bytes: u64,
+    /// packets whose included length is less than their original length
+    pub truncated: usize,
+    /// cumulative drops of the last packet record
+    pub dropped: u32,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+    /// by HCI UART packet type indicator, H4 captures only
+    pub packet_types: BTreeMap<u8, Count>,
+    /// by opcode
+    pub commands: BTreeMap<u16, Count>,
+    /// by event code
+    pub events: BTreeMap<u8, Count>,
+    /// by LE Meta subevent code
+    pub le_subevents: BTreeMap<u8, Count>,
+    /// ACL data by connection handle
+    pub acl_handles: BTreeMap<u16, Count>,
+}
+
+impl Statistics {
+    pub fn duration_us(&self) -> i64 {
+        match (self.first_timestamp, self.last_timestamp) {
+            (Some(first), Some(last)) => last.saturating_sub(first),
+            _ => 0,
+        }
+    }
+}
+
+/// Name of an HCI UART packet type indicator
+pub fn packet_type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        0x01 => "Command",
+        0x02 => "ACL Data",
+        0x03 => "SCO Data",
+        0x04 => "Event",
+        0x05 => "ISO Data",
+        _ => "Unknown",
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn statistics(capture: &Btsnoop) -> Statistics {
+    let mut statistics = Statistics::default();
+    let uart = matches!(capture.header.datalink_type, DatalinkType::Uart);
+
+    for packet in &capture.packets {
+        let description = &packet.description;
+        statistics.packets += 1;
+        statistics.bytes += packet.data.0.len() as u64;
+        if description.included_length < description.original_length {
+            statistics.truncated += 1;
+        }
+        statistics.dropped = description.cumulative_drops;
+        statistics
+            .first_timestamp
+            .get_or_insert(description.timestamp);
+        statistics.last_timestamp = Some(description.timestamp);
+        if let Some(packet_type) = packet.data.0.first().filter(|_| uart) {
+            statistics
+                .packet_types
+                .entry(*packet_type)
+                .or_default()
+                .add(is_received(packet), packet.data.0.len());
+        }
+    }
+
+    for (_, packet, data) in uart_packets(capture) {
+        let received = is_received(packet);
+        let bytes = packet.data.0.len();
+        match data {
+            UartData::Command(command) => {
+                statistics
+                    .commands
+                    .entry(command.opcode.raw())
+                    .or_default()
+                    .add(received, bytes);
+            }
+            UartData::Event(event) => {
+                statistics
+                    .events
+                    .entry(event.code)
+                    .or_default()
+                    .add(received, bytes);
+                if event.code == Event::LE_META {
+                    if let Some(subevent_code) = event.params.first() {
+                        statistics
+                            .le_subevents
+                            .entry(*subevent_code)
+                            .or_default()
+                            .add(received, bytes);
+                    }
+                }
+            }
+            UartData::Acl(acl) => {
+                statistics
+                    .acl_handles
+                    .entry(acl.handle)
+                    .or_default()
+                    .add(received, bytes);
+            }
+            UartData::Todos => {}
+        }
+    }
+
+    statistics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    #[test]
+    fn counts() {
+        let mut capture = h4(vec![
+            (1_000, false, command(0x03, 0x0003, &[])),
+            (
+                2_000,
+                true,
+                event(Event::COMMAND_COMPLETE, &[0x01, 0x03, 0x0c, 0x00]),
+            ),
+            (3_000, true, event(Event::LE_META, &[0x02, 0x00])),
+            (4_000, true, event(Event::LE_META, &[0x02, 0x00])),
+            (5_000, false, l2cap(0x40, 0x0004, &[0x0a, 0x03, 0x00])),
+            (6_000, true, l2cap(0x40, 0x0004, &[0x0b])),
+        ]);
+        capture.packets[5].description.original_length += 10;
+        capture.packets[5].description.cumulative_drops = 2;
+
+        let statistics = statistics(&capture);
+        assert_eq!(statistics.packets, 6);
+        assert_eq!(statistics.bytes, 4 + 7 + 5 + 5 + 12 + 10);
+        assert_eq!(statistics.truncated, 1);
+        assert_eq!(statistics.dropped, 2);
+        assert_eq!(statistics.duration_us(), 5_000);
+        assert_eq!(statistics.packet_types[&0x04].received, 3);
+        assert_eq!(statistics.packet_types[&0x02].total(), 2);
+        let reset = statistics.commands[&0x0c03];
+        assert_eq!((reset.sent, reset.received, reset.bytes), (1, 0, 4));
+        assert_eq!(statistics.events[&Event::LE_META].received, 2);
+        assert_eq!(statistics.le_subevents[&0x02].bytes, 10);
+        let acl = statistics.acl_handles[&0x40];
+        assert_eq!((acl.sent, acl.received, acl.bytes), (1, 1, 22));
+    }
+
+    #[test]
+    fn malformed_packets() {
+        let capture = h4(vec![
+            (0, false, vec![]),
+            // truncated command header
+            (1, false, vec![0x01, 0x03]),
+            // LE Meta event without its subevent code
+            (2, true, event(Event::LE_META, &[])),
+            // unknown packet type
+            (3, true, vec![0x09, 0x00]),
+        ]);
+        let statistics = statistics(&capture);
+        assert_eq!(statistics.packets, 4);
+        // counted by type as long as there is one
+        assert_eq!(statistics.packet_types.len(), 3);
+        assert_eq!(statistics.packet_types[&0x09].received, 1);
+        assert_eq!(statistics.packet_types[&0x01].sent, 1);
+        assert!(statistics.commands.is_empty());
+        assert_eq!(statistics.events[&Event::LE_META].received, 1);
+        assert!(statistics.le_subevents.is_empty());
+        assert_eq!(packet_type_name(0x09), "Unknown");
+    }
+
+    #[test]
+    fn not_uart() {
+        let mut capture = h4(vec![(0, false, command(0x03, 0x0003, &[]))]);
+        capture.header.datalink_type = DatalinkType::UnencapsulatedHci;
+        let statistics = statistics(&capture);
+        assert_eq!(statistics.packets, 1);
+        assert!(statistics.packet_types.is_empty());
+        assert!(statistics.commands.is_empty());
+    }
+
+    #[test]
+    fn corrupt_timestamps() {
+        let capture = h4(vec![(i64::MIN, false, vec![]), (i64::MAX, false, vec![])]);
+        assert_eq!(statistics(&capture).duration_us(), i64::MAX);
+        assert_eq!(Statistics::default().duration_us(), 0);
+    }
+}
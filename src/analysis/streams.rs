@@ -0,0 +1,304 @@
+//! Byte streams of L2CAP channels: the payload of every PDU of a channel, in order and for each
+//! direction, reassembled from the ACL fragments. RFCOMM channels are split into a stream per
+//! data link connection (DLCI) carrying the serial data of its UIH frames, and the SDU length
+//! of LE credit based channels is left out, so what remains is the data the applications
+//! exchanged.
+//!
+//! Dynamic channels are only recognized when the capture contains their L2CAP connection. The
+//! frames of a dynamic channel opened before the capture go to a stream per endpoint they are
+//! addressed to, so each has a single direction.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{DisconnectionComplete, Event},
+    l2cap::{BasicFrame, Channel, ChannelMap, Pdu, Reassembler, PSM_RFCOMM},
+    rfcomm, Btsnoop, UartData,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStream {
+    pub handle: u16,
+    /// the fixed channel, the endpoint on the Host of the capture of a dynamic channel, or the
+    /// endpoint the frames were addressed to when the channel is unknown
+    pub cid: u16,
+    /// none for fixed channels and the dynamic channels opened before the capture
+    pub channel: Option<Channel>,
+    /// data link connection of an RFCOMM channel
+    pub dlci: Option<u8>,
+    /// packet index of the first PDU
+    pub first_packet: usize,
+    /// data sent by the Host of the capture
+    pub sent: Vec<u8>,
+    pub received: Vec<u8>,
+    /// PDUs missing from the streams, their fragments were lost or truncated
+    pub gaps: usize,
+}
+
+impl ChannelStream {
+    pub fn psm(&self) -> Option<u16> {
+        self.channel.map(|channel| channel.psm)
+    }
+
+    /// `{prefix}.sent` and `{prefix}.received`, the raw data of each direction
+    pub fn write_files(&self, prefix: impl AsRef<Path>) -> io::Result<(PathBuf, PathBuf)> {
+        let file = |direction: &str| {
+            let mut path = prefix.as_ref().as_os_str().to_owned();
+            path.push(direction);
+            PathBuf::from(path)
+        };
+        let (sent, received) = (file(".sent"), file(".received"));
+        fs::write(&sent, &self.sent)?;
+        fs::write(&received, &self.received)?;
+        Ok((sent, received))
+    }
+}
+
+/// Identifies a stream among the open channels of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct StreamKey {
+    handle: u16,
+    cid: u16,
+    dlci: Option<u8>,
+    /// the direction of the frames of an unknown dynamic channel
+    unknown_sent: Option<bool>,
+    opened_at: Option<usize>,
+}
+
+#[derive(Default)]
+struct Extractor {
+    channels: ChannelMap,
+    streams: Vec<ChannelStream>,
+    open: HashMap<StreamKey, usize>,
+    /// octets left of the SDU being segmented, by stream and direction
+    sdu_remaining: HashMap<(usize, bool), usize>,
+}
+
+impl Extractor {
+    fn stream(&mut self, pdu: &Pdu, cid: u16, channel: Option<Channel>, dlci: Option<u8>) -> usize {
+        let key = StreamKey {
+            handle: pdu.handle,
+            cid: channel.map_or(cid, |channel| channel.local_cid),
+            dlci,
+            unknown_sent: (channel.is_none() && cid >= 0x0040).then_some(pdu.sent),
+            opened_at: channel.map(|channel| channel.opened_at),
+        };
+        let streams = &mut self.streams;
+        *self.open.entry(key).or_insert_with(|| {
+            streams.push(ChannelStream {
+                handle: pdu.handle,
+                cid: key.cid,
+                channel,
+                dlci,
+                first_packet: pdu.packet_index,
+                sent: vec![],
+                received: vec![],
+                gaps: 0,
+            });
+            streams.len() - 1
+        })
+    }
+
+    fn pdu(&mut self, pdu: Pdu) {
+        let Ok(frame) = pdu.frame() else {
+            return;
+        };
+        let cid = frame.channel_id;
+        if cid == BasicFrame::SIGNALING_CID || cid == BasicFrame::LE_SIGNALING_CID {
+            if pdu.complete {
+                self.channels
+                    .update(pdu.handle, pdu.sent, pdu.packet_index, &frame);
+            }
+            return;
+        }
+        let channel = self.channels.lookup(pdu.handle, pdu.sent, cid).copied();
+        let payload = &frame.payload[..frame.payload.len().min(frame.length as usize)];
+        let rfcomm = channel.is_some_and(|channel| channel.psm == PSM_RFCOMM);
+
+        if !pdu.complete {
+            // the address of an RFCOMM frame tells its DLCI
+            let dlci = match (rfcomm, payload.first()) {
+                (false, _) => None,
+                (true, Some(address)) if address >> 2 != 0 => Some(address >> 2),
+                (true, _) => return,
+            };
+            let stream = self.stream(&pdu, cid, channel, dlci);
+            self.streams[stream].gaps += 1;
+            self.sdu_remaining.remove(&(stream, pdu.sent));
+            return;
+        }
+
+        let (dlci, mut data) = if rfcomm {
+            match rfcomm::Frame::try_from(payload) {
+                Ok(frame) if frame.is_data() => (Some(frame.dlci), frame.information),
+                _ => return,
+            }
+        } else {
+            (None, payload)
+        };
+        let stream = self.stream(&pdu, cid, channel, dlci);
+        if channel.is_some_and(|channel| channel.le_credit_based) {
+            // the first K-frame of an SDU starts with its length
+            let remaining = self.sdu_remaining.entry((stream, pdu.sent)).or_default();
+            if *remaining == 0 {
+                let Some(length) = data.get(..2) else {
+                    self.streams[stream].gaps += 1;
+                    return;
+                };
+                *remaining = u16::from_le_bytes([length[0], length[1]]) as usize;
+                data = &data[2..];
+            }
+            *remaining = remaining.saturating_sub(data.len());
+        }
+        let stream = &mut self.streams[stream];
+        if pdu.sent {
+            stream.sent.extend_from_slice(data);
+        } else {
+            stream.received.extend_from_slice(data);
+        }
+    }
+}
+
+/// The streams of every channel which carried data, in order of their first PDU
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn channel_streams(capture: &Btsnoop) -> Vec<ChannelStream> {
+    let mut extractor = Extractor::default();
+    let mut reassembler = Reassembler::default();
+    for (packet_index, packet, data) in uart_packets(capture) {
+        match data {
+            UartData::Acl(acl) => {
+                let sent = !is_received(packet);
+                for pdu in reassembler.push(&acl, sent, packet_index) {
+                    extractor.pdu(pdu);
+                }
+            }
+            UartData::Event(event) if event.code == Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..]) else {
+                    continue;
+                };
+                for pdu in reassembler.disconnected(disconnection.handle) {
+                    extractor.pdu(pdu);
+                }
+                extractor.channels.disconnected(disconnection.handle);
+                extractor
+                    .open
+                    .retain(|key, _| key.handle != disconnection.handle);
+            }
+            _ => {}
+        }
+    }
+    extractor.streams
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{h4, l2cap};
+
+    /// ACL packet of a fragment of a PDU
+    fn fragment(handle: u16, start: bool, data: &[u8]) -> Vec<u8> {
+        let flags = if start { 0x2000 } else { 0x1000 };
+        let mut packet = vec![0x02];
+        packet.extend_from_slice(&(handle | flags).to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn uih(dlci: u8, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![
+            dlci << 2 | 0x03,
+            rfcomm::Frame::UIH,
+            (data.len() as u8) << 1 | 1,
+        ];
+        frame.extend_from_slice(data);
+        frame.push(0x00);
+        frame
+    }
+
+    #[test]
+    fn rfcomm_and_credit_based_streams() {
+        let connection_request = [0x02, 0x01, 0x04, 0x00, 0x03, 0x00, 0x40, 0x00];
+        let connection_response = [
+            0x03, 0x01, 0x08, 0x00, 0x41, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let le_request = [
+            0x14, 0x02, 0x0a, 0x00, 0x80, 0x00, 0x50, 0x00, 0x40, 0x00, 0x40, 0x00, 0x05, 0x00,
+        ];
+        let le_response = [
+            0x15, 0x02, 0x0a, 0x00, 0x60, 0x00, 0x40, 0x00, 0x40, 0x00, 0x05, 0x00, 0x00, 0x00,
+        ];
+        // "hello" in two fragments, then " world"
+        let hello = l2cap(0x40, 0x0040, &uih(2, b"hello"));
+        let world = l2cap(0x40, 0x0040, &uih(2, b" world"));
+        // an SDU of 6 octets in two K-frames
+        let sdu = [0x06, 0x00, b'a', b'b', b'c'];
+
+        let capture = h4(vec![
+            (
+                0,
+                false,
+                l2cap(0x40, BasicFrame::SIGNALING_CID, &connection_request),
+            ),
+            (
+                1,
+                true,
+                l2cap(0x40, BasicFrame::SIGNALING_CID, &connection_response),
+            ),
+            (2, true, fragment(0x40, true, &hello[5..10])),
+            (3, true, fragment(0x40, false, &hello[10..])),
+            (4, true, world),
+            (5, false, l2cap(0x40, 0x0041, &uih(2, b"AT\r"))),
+            // lost fragments
+            (6, true, fragment(0x40, true, &hello[5..10])),
+            (
+                7,
+                false,
+                l2cap(0x41, BasicFrame::LE_SIGNALING_CID, &le_request),
+            ),
+            (
+                8,
+                true,
+                l2cap(0x41, BasicFrame::LE_SIGNALING_CID, &le_response),
+            ),
+            (9, false, l2cap(0x41, 0x0060, &sdu)),
+            (10, false, l2cap(0x41, 0x0060, b"def")),
+            (11, true, l2cap(0x41, 0x0050, &[0x02, 0x00, b'o', b'k'])),
+            (
+                12,
+                true,
+                l2cap(0x41, BasicFrame::ATT_CID, &[0x1b, 0x03, 0x00, 0x01]),
+            ),
+            (13, true, l2cap(0x40, 0x0040, &uih(2, b"!"))),
+        ]);
+        let streams = channel_streams(&capture);
+        assert_eq!(streams.len(), 3);
+
+        let serial = &streams[0];
+        assert_eq!(
+            (serial.handle, serial.cid, serial.dlci),
+            (0x40, 0x0040, Some(2))
+        );
+        assert_eq!(serial.psm(), Some(PSM_RFCOMM));
+        assert_eq!(serial.received, b"hello world!");
+        assert_eq!(serial.sent, b"AT\r");
+        assert_eq!(serial.gaps, 1);
+
+        let coc = &streams[1];
+        assert_eq!((coc.cid, coc.psm()), (0x0050, Some(0x0080)));
+        assert_eq!(coc.sent, b"abcdef");
+        assert_eq!(coc.received, b"ok");
+        assert_eq!(coc.gaps, 0);
+
+        assert_eq!(streams[2].cid, BasicFrame::ATT_CID);
+        assert_eq!(streams[2].received, [0x1b, 0x03, 0x00, 0x01]);
+    }
+}
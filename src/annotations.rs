@@ -0,0 +1,302 @@
+//! Bookmarks and comments on the packets of a capture, kept in a JSON sidecar file next to it
+//! so they can be shared with the capture.
+//!
+//! The sidecar of `btsnoop_hci.log` is `btsnoop_hci.log.annotations.json`:
+//!
+//! ```json
+//! {"version": 1, "capture": "fnv1a64:0123456789abcdef", "packets": [
+//!   {"index": 12, "bookmark": true, "comments": [{"author": "ana", "text": "pairing fails"}]}
+//! ]}
+//! ```
+//!
+//! The annotations are keyed by the [hash](capture_hash) of the capture and the packet index,
+//! so the annotations of another capture, or of the same log after packets were appended, are
+//! not applied to it by mistake.
+//!
+//! ```no_run
+//! use btsnoop::{annotations::Annotations, Btsnoop};
+//!
+//! let path = "btsnoop_hci.log";
+//! let capture = Btsnoop::parse_from_slice(&std::fs::read(path)?)?;
+//! let mut annotations = Annotations::open(&capture, path)?;
+//! annotations.bookmark(12, true);
+//! annotations.comment(12, Some("ana"), "pairing fails");
+//! // notes of a colleague on the same capture
+//! annotations.merge(&Annotations::load("theirs.annotations.json")?)?;
+//! annotations.save(Annotations::sidecar_path(path))?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{index::Fnv1a, Btsnoop};
+
+/// Version of the sidecar format
+pub const VERSION: u64 = 1;
+
+/// `fnv1a64:` and the hex of the 64 bit FNV-1a hash of the capture written as btsnoop, the
+/// same whatever format it was read from
+pub fn capture_hash(capture: &Btsnoop) -> String {
+    let mut hasher = Fnv1a::default();
+    // writing to the hasher does not fail
+    let _ = capture.write(&mut hasher);
+    format!("fnv1a64:{:016x}", hasher.0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Comment {
+    pub author: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub bookmark: bool,
+    pub comments: Vec<Comment>,
+}
+
+impl Annotation {
+    fn is_empty(&self) -> bool {
+        !self.bookmark && self.comments.is_empty()
+    }
+}
+
+/// The annotations of one capture, by packet index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotations {
+    capture: String,
+    packets: BTreeMap<usize, Annotation>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl Annotations {
+    /// No annotation yet on `capture`
+    pub fn new(capture: &Btsnoop) -> Self {
+        Self::with_hash(capture_hash(capture))
+    }
+
+    /// No annotation yet on the capture of hash `capture`
+    pub fn with_hash(capture: String) -> Self {
+        Self {
+            capture,
+            packets: BTreeMap::new(),
+        }
+    }
+
+    /// `<capture path>.annotations.json`
+    pub fn sidecar_path(capture_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = capture_path.as_ref().as_os_str().to_owned();
+        path.push(".annotations.json");
+        PathBuf::from(path)
+    }
+
+    /// The annotations in the sidecar of the capture read from `capture_path`, none when
+    /// there is no sidecar. The sidecar of another capture is an error.
+    pub fn open(capture: &Btsnoop, capture_path: impl AsRef<Path>) -> io::Result<Self> {
+        let annotations = match Self::load(Self::sidecar_path(capture_path)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new(capture)),
+            result => result?,
+        };
+        let hash = capture_hash(capture);
+        if annotations.capture != hash {
+            return Err(invalid(format!(
+                "annotations of capture {}, not of this capture {}",
+                annotations.capture, hash
+            )));
+        }
+        Ok(annotations)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json() + "\n")
+    }
+
+    /// The hash of the annotated capture
+    pub fn capture(&self) -> &str {
+        &self.capture
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Annotation> {
+        self.packets.get(&index)
+    }
+
+    /// The annotated packets, in order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Annotation)> {
+        self.packets
+            .iter()
+            .map(|(index, annotation)| (*index, annotation))
+    }
+
+    pub fn bookmarks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.iter()
+            .filter(|(_, annotation)| annotation.bookmark)
+            .map(|(index, _)| index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Mark or unmark packet `index`
+    pub fn bookmark(&mut self, index: usize, bookmark: bool) {
+        self.packets.entry(index).or_default().bookmark = bookmark;
+        self.remove_empty(index);
+    }
+
+    pub fn comment(&mut self, index: usize, author: Option<&str>, text: &str) {
+        self.packets
+            .entry(index)
+            .or_default()
+            .comments
+            .push(Comment {
+                author: author.map(str::to_string),
+                text: text.to_string(),
+            });
+    }
+
+    /// Remove the bookmark and comments of packet `index`
+    pub fn clear(&mut self, index: usize) -> Option<Annotation> {
+        self.packets.remove(&index)
+    }
+
+    fn remove_empty(&mut self, index: usize) {
+        if self.packets.get(&index).is_some_and(Annotation::is_empty) {
+            self.packets.remove(&index);
+        }
+    }
+
+    /// Add the annotations of `other`, on the same capture: a packet bookmarked in either is
+    /// bookmarked and the comments not already there are appended
+    pub fn merge(&mut self, other: &Annotations) -> io::Result<()> {
+        if other.capture != self.capture {
+            return Err(invalid(format!(
+                "annotations of capture {}, not of {}",
+                other.capture, self.capture
+            )));
+        }
+        for (index, theirs) in other.iter() {
+            let ours = self.packets.entry(index).or_default();
+            ours.bookmark |= theirs.bookmark;
+            for comment in &theirs.comments {
+                if !ours.comments.contains(comment) {
+                    ours.comments.push(comment.clone());
+                }
+            }
+            self.remove_empty(index);
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let packets: Vec<Value> = self
+            .iter()
+            .map(|(index, annotation)| {
+                json!({
+                    "index": index,
+                    "bookmark": annotation.bookmark,
+                    "comments": annotation.comments.iter().map(|comment| json!({
+                        "author": comment.author,
+                        "text": comment.text,
+                    })).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({
+            "version": VERSION,
+            "capture": self.capture,
+            "packets": packets,
+        })
+        .to_string()
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let document: Value = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+        let version = document["version"].as_u64().unwrap_or_default();
+        if version != VERSION {
+            return Err(invalid(format!(
+                "unknown annotations version {}",
+                document["version"]
+            )));
+        }
+        let capture = document["capture"]
+            .as_str()
+            .ok_or_else(|| invalid("annotations without a capture hash"))?;
+        let mut annotations = Self::with_hash(capture.to_string());
+        for packet in document["packets"].as_array().into_iter().flatten() {
+            let index = packet["index"]
+                .as_u64()
+                .ok_or_else(|| invalid("annotation without a packet index"))?;
+            let mut annotation = Annotation {
+                bookmark: packet["bookmark"].as_bool().unwrap_or_default(),
+                comments: vec![],
+            };
+            for comment in packet["comments"].as_array().into_iter().flatten() {
+                annotation.comments.push(Comment {
+                    author: comment["author"].as_str().map(str::to_string),
+                    text: comment["text"]
+                        .as_str()
+                        .ok_or_else(|| invalid("comment without a text"))?
+                        .to_string(),
+                });
+            }
+            annotations.packets.insert(index as usize, annotation);
+        }
+        Ok(annotations)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sidecar() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse_from_slice(original).unwrap();
+        let mut ours = Annotations::new(&capture);
+        ours.bookmark(1, true);
+        ours.comment(3, Some("ana"), "slow response");
+        ours.bookmark(5, true);
+        ours.bookmark(5, false);
+        assert_eq!(ours.bookmarks().collect::<Vec<_>>(), [1]);
+        assert!(ours.get(5).is_none());
+
+        let json = ours.to_json();
+        assert_eq!(Annotations::from_json(&json).unwrap(), ours);
+
+        let mut theirs = Annotations::from_json(&json).unwrap();
+        theirs.comment(3, None, "controller busy");
+        theirs.bookmark(7, true);
+        ours.merge(&theirs).unwrap();
+        ours.merge(&theirs).unwrap();
+        assert_eq!(ours.get(3).unwrap().comments.len(), 2);
+        assert_eq!(ours.bookmarks().collect::<Vec<_>>(), [1, 7]);
+
+        let mut other = capture.clone();
+        other.packets.pop();
+        assert_ne!(capture_hash(&other), ours.capture());
+        assert!(ours.merge(&Annotations::new(&other)).is_err());
+
+        let dir = std::env::temp_dir().join(format!("btsnoop-annotations-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("btsnoop_hci.log");
+        assert!(Annotations::open(&capture, &path).unwrap().is_empty());
+        ours.save(Annotations::sidecar_path(&path)).unwrap();
+        assert_eq!(Annotations::open(&capture, &path).unwrap(), ours);
+        assert!(Annotations::open(&other, &path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
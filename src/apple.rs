@@ -0,0 +1,499 @@
+//! The Apple Notification Center Service (ANCS) and Apple Media Service (AMS), with which a
+//! wearable paired with an iPhone reads its notifications and controls its media player.
+//!
+//! Both are GATT services of the iPhone, multi-octet fields little endian and strings UTF-8.
+//! A Data Source response longer than the ATT MTU continues in the next notifications, only
+//! the attributes of its first one are decoded.
+
+use std::{fmt::Display, io};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::gatt::Uuid;
+
+// data format from: Apple Notification Center Service (ANCS) Specification and Apple Media
+// Service Specification
+
+pub const ANCS_SERVICE: Uuid = Uuid(0x7905_f431_b5ce_4e99_a40f_4b1e_122d_00d0);
+pub const NOTIFICATION_SOURCE: Uuid = Uuid(0x9fbf_120d_6301_42d9_8c58_25e6_99a2_1dbd);
+pub const CONTROL_POINT: Uuid = Uuid(0x69d1_d8f3_45e1_49a8_9821_9bbd_fdaa_d9d9);
+pub const DATA_SOURCE: Uuid = Uuid(0x22ea_c6e9_24d6_4bb5_be44_b36a_ce7c_7bfb);
+
+pub const AMS_SERVICE: Uuid = Uuid(0x89d3_502b_0f36_433a_8ef4_c502_ad55_f8dc);
+pub const REMOTE_COMMAND: Uuid = Uuid(0x9b3c_81d8_57b1_4a8a_b8df_0e56_f7ca_51c2);
+pub const ENTITY_UPDATE: Uuid = Uuid(0x2f7c_abce_808d_411f_9a0c_bb92_ba96_c102);
+pub const ENTITY_ATTRIBUTE: Uuid = Uuid(0xc6b2_f38c_23ab_46d8_a6ab_c3a0_1ea8_c12f);
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn event_name(event_id: u8) -> Option<&'static str> {
+    Some(match event_id {
+        0 => "Added",
+        1 => "Modified",
+        2 => "Removed",
+        _ => return None,
+    })
+}
+
+pub fn category_name(category_id: u8) -> Option<&'static str> {
+    Some(match category_id {
+        0 => "Other",
+        1 => "Incoming Call",
+        2 => "Missed Call",
+        3 => "Voicemail",
+        4 => "Social",
+        5 => "Schedule",
+        6 => "Email",
+        7 => "News",
+        8 => "Health and Fitness",
+        9 => "Business and Finance",
+        10 => "Location",
+        11 => "Entertainment",
+        _ => return None,
+    })
+}
+
+pub fn notification_attribute_name(attribute_id: u8) -> Option<&'static str> {
+    Some(match attribute_id {
+        0 => "App Identifier",
+        1 => "Title",
+        2 => "Subtitle",
+        3 => "Message",
+        4 => "Message Size",
+        5 => "Date",
+        6 => "Positive Action Label",
+        7 => "Negative Action Label",
+        _ => return None,
+    })
+}
+
+/// The error codes of the Control Point, in ATT Error Responses
+pub fn ancs_error_name(error_code: u8) -> Option<&'static str> {
+    Some(match error_code {
+        0xA0 => "Unknown command",
+        0xA1 => "Invalid command",
+        0xA2 => "Invalid parameter",
+        0xA3 => "Action failed",
+        _ => return None,
+    })
+}
+
+pub fn remote_command_name(command_id: u8) -> Option<&'static str> {
+    Some(match command_id {
+        0 => "Play",
+        1 => "Pause",
+        2 => "Toggle Play/Pause",
+        3 => "Next Track",
+        4 => "Previous Track",
+        5 => "Volume Up",
+        6 => "Volume Down",
+        7 => "Advance Repeat Mode",
+        8 => "Advance Shuffle Mode",
+        9 => "Skip Forward",
+        10 => "Skip Backward",
+        11 => "Like Track",
+        12 => "Dislike Track",
+        13 => "Bookmark Track",
+        _ => return None,
+    })
+}
+
+pub fn entity_name(entity_id: u8) -> Option<&'static str> {
+    Some(match entity_id {
+        0 => "Player",
+        1 => "Queue",
+        2 => "Track",
+        _ => return None,
+    })
+}
+
+pub fn entity_attribute_name(entity_id: u8, attribute_id: u8) -> Option<&'static str> {
+    Some(match (entity_id, attribute_id) {
+        (0, 0) => "Name",
+        (0, 1) => "Playback Info",
+        (0, 2) => "Volume",
+        (1, 0) => "Index",
+        (1, 1) => "Count",
+        (1, 2) => "Shuffle Mode",
+        (1, 3) => "Repeat Mode",
+        (2, 0) => "Artist",
+        (2, 1) => "Album",
+        (2, 2) => "Title",
+        (2, 3) => "Duration",
+        _ => return None,
+    })
+}
+
+fn name(name: Option<&str>, id: u8) -> String {
+    format!("{} ({})", name.unwrap_or("Reserved"), id)
+}
+
+/// A notification added, modified or removed on the iPhone, notified by the Notification
+/// Source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationSource {
+    pub event_id: u8,
+    /// silent, important, pre-existing, positive action, negative action
+    pub event_flags: u8,
+    pub category_id: u8,
+    /// active notifications of the category
+    pub category_count: u8,
+    pub notification_uid: u32,
+}
+
+impl NotificationSource {
+    pub const SILENT: u8 = 1 << 0;
+    pub const IMPORTANT: u8 = 1 << 1;
+    pub const PRE_EXISTING: u8 = 1 << 2;
+    pub const POSITIVE_ACTION: u8 = 1 << 3;
+    pub const NEGATIVE_ACTION: u8 = 1 << 4;
+}
+
+impl TryFrom<&[u8]> for NotificationSource {
+    type Error = io::Error;
+
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            event_id: value.read_u8()?,
+            event_flags: value.read_u8()?,
+            category_id: value.read_u8()?,
+            category_count: value.read_u8()?,
+            notification_uid: value.read_u32::<LittleEndian>()?,
+        })
+    }
+}
+
+impl Display for NotificationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} UID {}: {}, {} in category",
+            event_name(self.event_id).unwrap_or("Reserved event"),
+            self.notification_uid,
+            category_name(self.category_id).unwrap_or("Reserved category"),
+            self.category_count
+        )?;
+        for (flag, label) in [
+            (Self::SILENT, "silent"),
+            (Self::IMPORTANT, "important"),
+            (Self::PRE_EXISTING, "pre-existing"),
+            (Self::POSITIVE_ACTION, "positive action"),
+            (Self::NEGATIVE_ACTION, "negative action"),
+        ] {
+            if self.event_flags & flag != 0 {
+                write!(f, ", {}", label)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A nul terminated string
+fn c_string(value: &mut &[u8]) -> io::Result<String> {
+    let end = value
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| invalid("unterminated string"))?;
+    let string = String::from_utf8_lossy(&value[..end]).into_owned();
+    *value = &value[end + 1..];
+    Ok(string)
+}
+
+/// A command written to the Control Point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlPointCommand {
+    /// the attributes requested, with the maximum length of the title, subtitle and message
+    GetNotificationAttributes {
+        notification_uid: u32,
+        attributes: Vec<(u8, Option<u16>)>,
+    },
+    GetAppAttributes {
+        app_identifier: String,
+        attributes: Vec<u8>,
+    },
+    /// 0 positive, 1 negative
+    PerformNotificationAction {
+        notification_uid: u32,
+        action_id: u8,
+    },
+}
+
+impl TryFrom<&[u8]> for ControlPointCommand {
+    type Error = io::Error;
+
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(match value.read_u8()? {
+            0 => {
+                let notification_uid = value.read_u32::<LittleEndian>()?;
+                let mut attributes = vec![];
+                while let Ok(attribute) = value.read_u8() {
+                    let max_length = match attribute {
+                        1..=3 => Some(value.read_u16::<LittleEndian>()?),
+                        _ => None,
+                    };
+                    attributes.push((attribute, max_length));
+                }
+                Self::GetNotificationAttributes {
+                    notification_uid,
+                    attributes,
+                }
+            }
+            1 => Self::GetAppAttributes {
+                app_identifier: c_string(&mut value)?,
+                attributes: value.to_vec(),
+            },
+            2 => Self::PerformNotificationAction {
+                notification_uid: value.read_u32::<LittleEndian>()?,
+                action_id: value.read_u8()?,
+            },
+            _ => return Err(invalid("unknown ANCS command")),
+        })
+    }
+}
+
+impl Display for ControlPointCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::GetNotificationAttributes {
+                notification_uid,
+                attributes,
+            } => {
+                write!(f, "Get Notification Attributes UID {}:", notification_uid)?;
+                for (attribute, max_length) in attributes {
+                    write!(
+                        f,
+                        " {}",
+                        notification_attribute_name(*attribute).unwrap_or("Reserved")
+                    )?;
+                    if let Some(max_length) = max_length {
+                        write!(f, " ({})", max_length)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::GetAppAttributes {
+                app_identifier,
+                attributes,
+            } => write!(
+                f,
+                "Get App Attributes {}: {} attributes",
+                app_identifier,
+                attributes.len()
+            ),
+            Self::PerformNotificationAction {
+                notification_uid,
+                action_id,
+            } => write!(
+                f,
+                "Perform Notification Action UID {}: {}",
+                notification_uid,
+                match action_id {
+                    0 => "positive",
+                    1 => "negative",
+                    _ => "reserved",
+                }
+            ),
+        }
+    }
+}
+
+/// The response of a Get Notification Attributes or Get App Attributes command, notified by
+/// the Data Source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataSourceResponse {
+    pub command_id: u8,
+    /// the notification UID, or the app identifier
+    pub subject: String,
+    /// (attribute ID, value)
+    pub attributes: Vec<(u8, String)>,
+    /// the last attribute continues in the next notification
+    pub truncated: bool,
+}
+
+impl TryFrom<&[u8]> for DataSourceResponse {
+    type Error = io::Error;
+
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        let command_id = value.read_u8()?;
+        let subject = match command_id {
+            0 => value.read_u32::<LittleEndian>()?.to_string(),
+            1 => c_string(&mut value)?,
+            _ => return Err(invalid("unknown ANCS command")),
+        };
+        let mut attributes = vec![];
+        let mut truncated = false;
+        while let Ok(attribute) = value.read_u8() {
+            let Ok(length) = value.read_u16::<LittleEndian>() else {
+                truncated = true;
+                break;
+            };
+            let length = length as usize;
+            truncated = length > value.len();
+            let (data, rest) = value.split_at(length.min(value.len()));
+            attributes.push((attribute, String::from_utf8_lossy(data).into_owned()));
+            value = rest;
+        }
+        Ok(Self {
+            command_id,
+            subject,
+            attributes,
+            truncated,
+        })
+    }
+}
+
+impl DataSourceResponse {
+    /// The attributes, `Title: "Lunch?"`
+    pub fn lines(&self) -> Vec<String> {
+        self.attributes
+            .iter()
+            .map(|(attribute, value)| {
+                let name = match self.command_id {
+                    0 => notification_attribute_name(*attribute),
+                    _ => (*attribute == 0).then_some("Display Name"),
+                };
+                format!("{}: {:?}", name.unwrap_or("Reserved"), value)
+            })
+            .collect()
+    }
+}
+
+impl Display for DataSourceResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}{}",
+            match self.command_id {
+                0 => "Notification Attributes UID",
+                _ => "App Attributes",
+            },
+            self.subject,
+            if self.truncated { " (continues)" } else { "" }
+        )
+    }
+}
+
+/// An AMS entity attribute changed on the iPhone, notified by the Entity Update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityUpdate {
+    pub entity_id: u8,
+    pub attribute_id: u8,
+    /// longer than the ATT MTU, the rest is read from the Entity Attribute
+    pub truncated: bool,
+    pub value: String,
+}
+
+impl TryFrom<&[u8]> for EntityUpdate {
+    type Error = io::Error;
+
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            entity_id: value.read_u8()?,
+            attribute_id: value.read_u8()?,
+            truncated: value.read_u8()? & 0x01 != 0,
+            value: String::from_utf8_lossy(value).into_owned(),
+        })
+    }
+}
+
+impl Display for EntityUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {:?}{}",
+            entity_name(self.entity_id).unwrap_or("Reserved entity"),
+            entity_attribute_name(self.entity_id, self.attribute_id).unwrap_or("Reserved"),
+            self.value,
+            if self.truncated { " (truncated)" } else { "" }
+        )
+    }
+}
+
+/// The attributes of an entity a client subscribes to, written to the Entity Update, or the
+/// attribute whose value it reads next, written to the Entity Attribute
+pub fn entity_attributes(value: &[u8]) -> io::Result<String> {
+    let (entity_id, attributes) = value
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no entity"))?;
+    Ok(format!(
+        "{}: {}",
+        name(entity_name(*entity_id), *entity_id),
+        attributes
+            .iter()
+            .map(|attribute| name(entity_attribute_name(*entity_id, *attribute), *attribute))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// A remote command sent to the iPhone, or the commands it supports, notified
+pub fn remote_commands(value: &[u8]) -> String {
+    value
+        .iter()
+        .map(|command| name(remote_command_name(*command), *command))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ancs_and_ams() {
+        let source =
+            NotificationSource::try_from(&[0x00, 0x12, 0x04, 0x02, 0x2a, 0x00, 0x00, 0x00][..])
+                .unwrap();
+        assert_eq!(
+            source.to_string(),
+            "Added UID 42: Social, 2 in category, important, negative action"
+        );
+
+        let command = ControlPointCommand::try_from(
+            &[
+                0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x01, 0x20, 0x00, 0x03, 0x64, 0x00,
+            ][..],
+        )
+        .unwrap();
+        assert_eq!(
+            command.to_string(),
+            "Get Notification Attributes UID 42: App Identifier Title (32) Message (100)"
+        );
+        let command = ControlPointCommand::try_from(&b"\x01com.apple.MobileSMS\0\x00"[..]).unwrap();
+        assert_eq!(
+            command.to_string(),
+            "Get App Attributes com.apple.MobileSMS: 1 attributes"
+        );
+
+        let response = DataSourceResponse::try_from(
+            &b"\x00\x2a\x00\x00\x00\x01\x03\x00Bob\x03\x06\x00Lunch?\x05\x0f\x0020261016T"[..],
+        )
+        .unwrap();
+        assert!(response.truncated);
+        assert_eq!(
+            response.to_string(),
+            "Notification Attributes UID 42 (continues)"
+        );
+        assert_eq!(
+            response.lines(),
+            [
+                "Title: \"Bob\"",
+                "Message: \"Lunch?\"",
+                "Date: \"20261016T\""
+            ]
+        );
+
+        let update = EntityUpdate::try_from(&b"\x02\x02\x00Intro"[..]).unwrap();
+        assert_eq!(update.to_string(), "Track Title: \"Intro\"");
+        assert_eq!(
+            entity_attributes(&[0x00, 0x01, 0x02]).unwrap(),
+            "Player (0): Playback Info (1), Volume (2)"
+        );
+        assert_eq!(
+            remote_commands(&[0x02, 0x0e]),
+            "Toggle Play/Pause (2), Reserved (14)"
+        );
+    }
+}
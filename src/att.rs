@@ -0,0 +1,195 @@
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+// data format from: Bluetooth core specification 5.4 Vol 3: Host Part F Attribute Protocol
+
+/// Attribute PDU, carried on the ATT fixed channel (CID 0x0004) over LE
+///```text
+/// --------------------------
+/// | opcode 8 bit           |
+/// --------------------------
+/// | attribute parameters   |
+/// --------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pdu<'a> {
+    /// bit 6 is the command flag, bit 7 the authentication signature flag
+    pub opcode: u8,
+    pub params: &'a [u8],
+}
+
+impl<'a> Pdu<'a> {
+    pub const ERROR_RESPONSE: u8 = 0x01;
+    pub const EXCHANGE_MTU_REQUEST: u8 = 0x02;
+    pub const EXCHANGE_MTU_RESPONSE: u8 = 0x03;
+    pub const FIND_INFORMATION_REQUEST: u8 = 0x04;
+    pub const FIND_INFORMATION_RESPONSE: u8 = 0x05;
+    pub const FIND_BY_TYPE_VALUE_REQUEST: u8 = 0x06;
+    pub const READ_BY_TYPE_REQUEST: u8 = 0x08;
+    pub const READ_BY_TYPE_RESPONSE: u8 = 0x09;
+    pub const READ_REQUEST: u8 = 0x0A;
+    pub const READ_RESPONSE: u8 = 0x0B;
+    pub const READ_BLOB_REQUEST: u8 = 0x0C;
+    pub const READ_BLOB_RESPONSE: u8 = 0x0D;
+    pub const READ_BY_GROUP_TYPE_REQUEST: u8 = 0x10;
+    pub const WRITE_REQUEST: u8 = 0x12;
+    pub const WRITE_RESPONSE: u8 = 0x13;
+    pub const PREPARE_WRITE_REQUEST: u8 = 0x16;
+    pub const PREPARE_WRITE_RESPONSE: u8 = 0x17;
+    pub const EXECUTE_WRITE_REQUEST: u8 = 0x18;
+    pub const EXECUTE_WRITE_RESPONSE: u8 = 0x19;
+    pub const HANDLE_VALUE_NOTIFICATION: u8 = 0x1B;
+    pub const HANDLE_VALUE_INDICATION: u8 = 0x1D;
+    pub const WRITE_COMMAND: u8 = 0x52;
+}
+
+impl<'a> TryFrom<&'a [u8]> for Pdu<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        match data.split_first() {
+            Some((opcode, params)) => Ok(Self {
+                opcode: *opcode,
+                params,
+            }),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "empty attribute PDU",
+            )),
+        }
+    }
+}
+
+/// The request with `request_opcode` on `handle` failed with `error_code`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorResponse {
+    pub request_opcode: u8,
+    pub handle: u16,
+    pub error_code: u8,
+}
+
+impl ErrorResponse {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            request_opcode: reader.read_u8()?,
+            handle: reader.read_u16::<LittleEndian>()?,
+            error_code: reader.read_u8()?,
+        })
+    }
+}
+
+pub fn opcode_name(opcode: u8) -> Option<&'static str> {
+    let name = match opcode {
+        0x01 => "Error Response",
+        0x02 => "Exchange MTU Request",
+        0x03 => "Exchange MTU Response",
+        0x04 => "Find Information Request",
+        0x05 => "Find Information Response",
+        0x06 => "Find By Type Value Request",
+        0x07 => "Find By Type Value Response",
+        0x08 => "Read By Type Request",
+        0x09 => "Read By Type Response",
+        0x0A => "Read Request",
+        0x0B => "Read Response",
+        0x0C => "Read Blob Request",
+        0x0D => "Read Blob Response",
+        0x0E => "Read Multiple Request",
+        0x0F => "Read Multiple Response",
+        0x10 => "Read By Group Type Request",
+        0x11 => "Read By Group Type Response",
+        0x12 => "Write Request",
+        0x13 => "Write Response",
+        0x16 => "Prepare Write Request",
+        0x17 => "Prepare Write Response",
+        0x18 => "Execute Write Request",
+        0x19 => "Execute Write Response",
+        0x1B => "Handle Value Notification",
+        0x1D => "Handle Value Indication",
+        0x1E => "Handle Value Confirmation",
+        0x20 => "Read Multiple Variable Request",
+        0x21 => "Read Multiple Variable Response",
+        0x23 => "Multiple Handle Value Notification",
+        0x52 => "Write Command",
+        0xD2 => "Signed Write Command",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Name of an ATT error code, 0x80 - 0x9F are application errors and 0xE0 - 0xFF common profile errors
+pub fn error_code_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x01 => "Invalid Handle",
+        0x02 => "Read Not Permitted",
+        0x03 => "Write Not Permitted",
+        0x04 => "Invalid PDU",
+        0x05 => "Insufficient Authentication",
+        0x06 => "Request Not Supported",
+        0x07 => "Invalid Offset",
+        0x08 => "Insufficient Authorization",
+        0x09 => "Prepare Queue Full",
+        0x0A => "Attribute Not Found",
+        0x0B => "Attribute Not Long",
+        0x0C => "Encryption Key Size Too Short",
+        0x0D => "Invalid Attribute Value Length",
+        0x0E => "Unlikely Error",
+        0x0F => "Insufficient Encryption",
+        0x10 => "Unsupported Group Type",
+        0x11 => "Insufficient Resources",
+        0x12 => "Database Out Of Sync",
+        0x13 => "Value Not Allowed",
+        0x80..=0x9F => "Application Error",
+        0xFC => "Write Request Rejected",
+        0xFD => "Client Characteristic Configuration Descriptor Improperly Configured",
+        0xFE => "Procedure Already in Progress",
+        0xFF => "Out of Range",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pdus() {
+        let pdu = Pdu::try_from(&[0x0a, 0x03, 0x00][..]).unwrap();
+        assert_eq!(pdu.opcode, Pdu::READ_REQUEST);
+        assert_eq!(pdu.params, [0x03, 0x00]);
+        assert_eq!(opcode_name(pdu.opcode), Some("Read Request"));
+
+        // a Write Response has no parameters
+        let pdu = Pdu::try_from(&[0x13][..]).unwrap();
+        assert!(pdu.params.is_empty());
+
+        let error = Pdu::try_from(&[][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(opcode_name(0x14), None);
+    }
+
+    #[test]
+    fn error_responses() {
+        let error = ErrorResponse::parse(&mut &[0x0a, 0x03, 0x00, 0x0a][..]).unwrap();
+        assert_eq!(
+            error,
+            ErrorResponse {
+                request_opcode: Pdu::READ_REQUEST,
+                handle: 0x0003,
+                error_code: 0x0a,
+            }
+        );
+        assert_eq!(
+            error_code_name(error.error_code),
+            Some("Attribute Not Found")
+        );
+        assert_eq!(error_code_name(0x85), Some("Application Error"));
+        assert_eq!(error_code_name(0x00), None);
+
+        for len in 0..4 {
+            let truncated = &[0x0a, 0x03, 0x00, 0x0a][..len];
+            assert!(ErrorResponse::parse(&mut &truncated[..]).is_err());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+// data format from: Audio/Video Distribution Transport Protocol Specification 1.3 and RFC 3550
+
+/// AVDTP signaling message header, single packet type
+///```text
+/// ---------------------------------------------------------------------
+/// | transaction label 4 bit | packet type 2 bit | message type 2 bit |
+/// ---------------------------------------------------------------------
+/// | rfa 2 bit | signal identifier 6 bit                               |
+/// ---------------------------------------------------------------------
+/// | parameters                                                        |
+/// ---------------------------------------------------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalingMessage<'a> {
+    pub transaction_label: u8,
+    /// 0 = single, 1 = start, 2 = continue, 3 = end
+    pub packet_type: u8,
+    /// 0 = command, 1 = general reject, 2 = response accept, 3 = response reject
+    pub message_type: u8,
+    pub signal_identifier: u8,
+    pub params: &'a [u8],
+}
+
+impl<'a> SignalingMessage<'a> {
+    pub const SET_CONFIGURATION: u8 = 0x03;
+    pub const OPEN: u8 = 0x06;
+    pub const START: u8 = 0x07;
+    pub const SUSPEND: u8 = 0x09;
+
+    pub const COMMAND: u8 = 0x00;
+    pub const RESPONSE_ACCEPT: u8 = 0x02;
+
+    /// Service categories of a Set Configuration command, the media codec one carries the codec configuration.
+    /// Yields (service category, information elements).
+    pub fn capabilities(&self) -> impl Iterator<Item = (u8, &'a [u8])> {
+        // ACP SEID and INT SEID precede the capabilities
+        let mut rest = self.params.get(2..).unwrap_or_default();
+        std::iter::from_fn(move || {
+            let (&category, tail) = rest.split_first()?;
+            let (&length, tail) = tail.split_first()?;
+            let elements = tail.get(..length as usize)?;
+            rest = &tail[length as usize..];
+            Some((category, elements))
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SignalingMessage<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "avdtp header too short",
+            ));
+        }
+        Ok(Self {
+            transaction_label: data[0] >> 4,
+            packet_type: (data[0] >> 2) & 0b11,
+            message_type: data[0] & 0b11,
+            signal_identifier: data[1] & 0x3F,
+            params: &data[2..],
+        })
+    }
+}
+
+pub const MEDIA_CODEC_CATEGORY: u8 = 0x07;
+
+/// Sampling frequency of a media codec service capability, which is the RTP clock rate of the stream.
+/// Only SBC and MPEG-2,4 AAC are understood.
+pub fn codec_sampling_frequency(media_codec: &[u8]) -> Option<u32> {
+    let codec_type = *media_codec.get(1)?;
+    let info = media_codec.get(2..)?;
+    let frequencies: &[(u8, usize, u32)] = match codec_type {
+        // SBC
+        0x00 => &[
+            (0x80, 0, 16000),
+            (0x40, 0, 32000),
+            (0x20, 0, 44100),
+            (0x10, 0, 48000),
+        ],
+        // MPEG-2,4 AAC
+        0x02 => &[
+            (0x80, 1, 8000),
+            (0x40, 1, 11025),
+            (0x20, 1, 12000),
+            (0x10, 1, 16000),
+            (0x08, 1, 22050),
+            (0x04, 1, 24000),
+            (0x02, 1, 32000),
+            (0x01, 1, 44100),
+            (0x80, 2, 48000),
+            (0x40, 2, 64000),
+            (0x20, 2, 88200),
+            (0x10, 2, 96000),
+        ],
+        _ => return None,
+    };
+    frequencies
+        .iter()
+        .find(|(mask, octet, _)| info.get(*octet).is_some_and(|b| b & mask != 0))
+        .map(|(_, _, frequency)| *frequency)
+}
+
+/// RTP fixed header of a media packet (RFC 3550)
+///```text
+/// ------------------------------------------------------------------
+/// | V 2 | P 1 | X 1 | CC 4 | M 1 | PT 7 | sequence number 16 bit  |
+/// ------------------------------------------------------------------
+/// | timestamp 32 bit                                               |
+/// ------------------------------------------------------------------
+/// | synchronization source (SSRC) identifier 32 bit                |
+/// ------------------------------------------------------------------
+/// | contributing source (CSRC) identifiers, CC × 32 bit            |
+/// ------------------------------------------------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RtpHeader {
+    pub version: u8,
+    pub padding: bool,
+    pub extension: bool,
+    pub csrc_count: u8,
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence_number: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+}
+
+impl RtpHeader {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let first = reader.read_u8()?;
+        let second = reader.read_u8()?;
+        let version = first >> 6;
+        if version != 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported rtp version",
+            ));
+        }
+        Ok(Self {
+            version,
+            padding: first & 0x20 != 0,
+            extension: first & 0x10 != 0,
+            csrc_count: first & 0x0F,
+            marker: second & 0x80 != 0,
+            payload_type: second & 0x7F,
+            sequence_number: reader.read_u16::<BigEndian>()?,
+            timestamp: reader.read_u32::<BigEndian>()?,
+            ssrc: reader.read_u32::<BigEndian>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_configuration() {
+        // ACP SEID 1, INT SEID 2, media transport, then SBC at 44.1 kHz
+        let data = [
+            0x30, 0x03, 0x04, 0x08, 0x01, 0x00, 0x07, 0x06, 0x00, 0x00, 0x21, 0x15, 0x02, 0x35,
+        ];
+        let message = SignalingMessage::try_from(&data[..]).unwrap();
+        assert_eq!(message.transaction_label, 3);
+        assert_eq!(message.packet_type, 0);
+        assert_eq!(message.message_type, SignalingMessage::COMMAND);
+        assert_eq!(
+            message.signal_identifier,
+            SignalingMessage::SET_CONFIGURATION
+        );
+
+        let capabilities: Vec<_> = message.capabilities().collect();
+        assert_eq!(capabilities.len(), 2);
+        assert_eq!(capabilities[0], (0x01, &[][..]));
+        assert_eq!(capabilities[1].0, MEDIA_CODEC_CATEGORY);
+        assert_eq!(codec_sampling_frequency(capabilities[1].1), Some(44100));
+
+        // a capability longer than the message ends the iteration
+        let truncated = SignalingMessage::try_from(&data[..data.len() - 1]).unwrap();
+        assert_eq!(truncated.capabilities().count(), 1);
+        let bare = SignalingMessage::try_from(&[0x30, 0x03, 0x04][..]).unwrap();
+        assert_eq!(bare.capabilities().count(), 0);
+
+        let error = SignalingMessage::try_from(&[0x30][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn sampling_frequencies() {
+        // MPEG-2,4 AAC at 48 kHz, in the second frequency octet
+        assert_eq!(
+            codec_sampling_frequency(&[0x00, 0x02, 0x80, 0x00, 0x8c, 0x00, 0x00, 0x00]),
+            Some(48000)
+        );
+        // vendor specific codecs and cut elements are unknown
+        assert_eq!(codec_sampling_frequency(&[0x00, 0xff, 0x80]), None);
+        assert_eq!(codec_sampling_frequency(&[0x00, 0x00]), None);
+        assert_eq!(codec_sampling_frequency(&[0x00]), None);
+    }
+
+    #[test]
+    fn rtp_header() {
+        let data = [
+            0x80, 0x60, 0x00, 0x05, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let header = RtpHeader::parse(&mut &data[..]).unwrap();
+        assert_eq!(header.payload_type, 0x60);
+        assert!(!header.marker && !header.padding);
+        assert_eq!(header.sequence_number, 5);
+        assert_eq!(header.timestamp, 0x200);
+        assert_eq!(header.ssrc, 1);
+
+        let error = RtpHeader::parse(&mut &[0x40, 0x60][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = RtpHeader::parse(&mut &data[..11]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use btsnoop::{
+    adb::{Device, SnoopMode},
+    DatalinkType,
+};
+use clap::{Args, Subcommand, ValueEnum};
+
+use crate::{
+    dump::{self, DumpOptions},
+    filter::PacketFilter,
+    json, write, written,
+};
+
+#[derive(Debug, Args)]
+pub struct Adb {
+    /// serial number of the device, when several are connected
+    #[arg(long, short)]
+    serial: Option<String>,
+    #[command(subcommand)]
+    command: AdbCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum AdbCommand {
+    /// Copy the active snoop log to a local capture
+    Pull {
+        output: PathBuf,
+        /// the last packets kept in memory by the stack instead, without root
+        #[arg(long)]
+        summary: bool,
+    },
+    /// Print the packets of the active snoop log as they are written
+    Follow {
+        #[command(flatten)]
+        filter: PacketFilter,
+        #[command(flatten)]
+        options: DumpOptions,
+    },
+    /// Show or change the snoop log mode, changing it restarts Bluetooth
+    SnoopMode { mode: Option<Mode> },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    Disabled,
+    Filtered,
+    Full,
+}
+
+impl From<Mode> for SnoopMode {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Disabled => SnoopMode::Disabled,
+            Mode::Filtered => SnoopMode::Filtered,
+            Mode::Full => SnoopMode::Full,
+        }
+    }
+}
+
+pub fn run<W: Write>(adb: Adb, json: bool, out: &mut W) -> io::Result<()> {
+    let device = Device::new(adb.serial);
+    match adb.command {
+        AdbCommand::Pull { output, summary } => {
+            let capture = if summary {
+                device.pull_summary()?
+            } else {
+                device.pull()?
+            };
+            if !json {
+                writeln!(out, "{} packets", capture.packets.len())?;
+            }
+            write(&capture, &output)?;
+            written(json, &output, capture.packets.len(), out)
+        }
+        AdbCommand::Follow {
+            filter,
+            mut options,
+        } => {
+            options.json = json;
+            let stream = device.stream()?;
+            dump::header(out, &options, stream.header())?;
+            let uart = matches!(stream.header().datalink_type, DatalinkType::Uart);
+            dump::stream(stream, uart, filter, &options, out)
+        }
+        AdbCommand::SnoopMode { mode: None } => {
+            let mode = device.snoop_mode()?;
+            if json {
+                writeln!(out, "{}", json::snoop_mode(mode))
+            } else {
+                writeln!(out, "{}", mode.as_str())
+            }
+        }
+        AdbCommand::SnoopMode { mode: Some(mode) } => {
+            device.set_snoop_mode(mode.into())?;
+            if json {
+                writeln!(out, "{}", json::snoop_mode(mode.into()))?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use btsnoop::annotations::Annotations;
+use clap::Args;
+
+use crate::{json, read};
+
+#[derive(Debug, Args)]
+pub struct Annotate {
+    file: PathBuf,
+    /// packet number to annotate
+    #[arg(long)]
+    packet: Option<usize>,
+    /// bookmark the packet
+    #[arg(long, requires = "packet")]
+    bookmark: bool,
+    /// remove the bookmark of the packet
+    #[arg(long, requires = "packet", conflicts_with = "bookmark")]
+    unbookmark: bool,
+    /// add a comment to the packet
+    #[arg(long, requires = "packet")]
+    comment: Option<String>,
+    /// author of the comment
+    #[arg(long, requires = "comment")]
+    author: Option<String>,
+    /// add the annotations of another sidecar file of the same capture, can be repeated
+    #[arg(long)]
+    merge: Vec<PathBuf>,
+}
+
+/// Apply the changes to the sidecar file of the capture and list its annotations
+pub fn run<W: Write>(annotate: Annotate, json: bool, out: &mut W) -> io::Result<()> {
+    let capture = read(&annotate.file)?;
+    let mut annotations = Annotations::open(&capture, &annotate.file)?;
+    let original = annotations.clone();
+    if let Some(index) = annotate.packet {
+        if index >= capture.packets.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no packet {}", index),
+            ));
+        }
+        if annotate.bookmark || annotate.unbookmark {
+            annotations.bookmark(index, annotate.bookmark);
+        }
+        if let Some(comment) = annotate.comment {
+            annotations.comment(index, annotate.author.as_deref(), &comment);
+        }
+    }
+    for path in annotate.merge {
+        annotations.merge(&Annotations::load(path)?)?;
+    }
+    if annotations != original {
+        annotations.save(Annotations::sidecar_path(&annotate.file))?;
+    }
+    print(&annotations, json, out)
+}
+
+/// The annotated packets, a line for the bookmark and one per comment
+fn print<W: Write>(annotations: &Annotations, json: bool, out: &mut W) -> io::Result<()> {
+    if json {
+        return writeln!(out, "{}", json::annotations(annotations));
+    }
+    for (index, annotation) in annotations.iter() {
+        if annotation.bookmark {
+            writeln!(out, "{:>6} bookmark", index)?;
+        }
+        for comment in &annotation.comments {
+            match &comment.author {
+                Some(author) => writeln!(out, "{:>6} {}: {}", index, author, comment.text)?,
+                None => writeln!(out, "{:>6} {}", index, comment.text)?,
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::hci_socket::MonitorSocket;
+
+use crate::{write_live, written};
+
+/// Capture from the monitor socket, all the controllers' traffic unless `index` is given,
+/// until `count` packets
+pub fn run<W: Write>(
+    output: &Path,
+    index: Option<u16>,
+    count: Option<usize>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let socket = MonitorSocket::open(index)?;
+    let header = socket.header();
+    let packets = write_live(header, socket.take(count.unwrap_or(usize::MAX)), output)?;
+    written(json, output, packets, out)
+}
@@ -0,0 +1,41 @@
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+};
+
+use btsnoop::{
+    analysis::{command_errors::command_errors, health_check::health_check},
+    Btsnoop,
+};
+
+use crate::{json, FINDINGS};
+
+pub fn run<W: Write>(capture: &Btsnoop, json: bool, out: &mut W) -> io::Result<ExitCode> {
+    let findings = health_check(capture);
+    let errors = command_errors(capture);
+    if json {
+        writeln!(out, "{}", json::check(&findings, &errors))?;
+    } else {
+        for finding in &findings {
+            writeln!(
+                out,
+                "#{} {:?}: {}",
+                finding.packet_index,
+                finding.issue.category(),
+                finding.issue
+            )?;
+        }
+        write!(out, "{}", errors)?;
+        writeln!(
+            out,
+            "{} findings, {} failed commands and ATT requests",
+            findings.len(),
+            errors.count()
+        )?;
+    }
+    Ok(if findings.is_empty() && errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FINDINGS)
+    })
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    formats::{pcap, pcap::LinkType, pcapng, Format},
+    transform::convert_datalink,
+    DatalinkType,
+};
+use clap::ValueEnum;
+
+use crate::{create, extension_format, read_as, write, written, FileFormat};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Datalink {
+    /// Un-encapsulated HCI, btsnoop only
+    H1,
+    /// HCI UART
+    H4,
+    /// HCI UART with the direction, pcap only
+    H4Phdr,
+    /// Linux monitor socket, pcap only
+    Monitor,
+}
+
+impl Datalink {
+    fn datalink_type(&self) -> Option<DatalinkType> {
+        match self {
+            Datalink::H1 => Some(DatalinkType::UnencapsulatedHci),
+            Datalink::H4 => Some(DatalinkType::Uart),
+            _ => None,
+        }
+    }
+
+    fn link_type(&self) -> Option<LinkType> {
+        match self {
+            Datalink::H4 => Some(LinkType::H4),
+            Datalink::H4Phdr => Some(LinkType::H4WithPhdr),
+            Datalink::Monitor => Some(LinkType::LinuxMonitor),
+            _ => None,
+        }
+    }
+}
+
+pub fn run<W: Write>(
+    input: &Path,
+    output: &Path,
+    from: Option<FileFormat>,
+    to: Option<FileFormat>,
+    datalink: Option<Datalink>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let packets = convert(input, output, from, to, datalink)?;
+    written(json, output, packets, out)
+}
+
+fn convert(
+    input: &Path,
+    output: &Path,
+    from: Option<FileFormat>,
+    to: Option<FileFormat>,
+    datalink: Option<Datalink>,
+) -> io::Result<usize> {
+    let mut capture = read_as(input, from)?;
+    let to = match to {
+        Some(to) => to.into(),
+        None => extension_format(output).unwrap_or(Format::Btsnoop),
+    };
+    let unsupported = |message: String| io::Error::new(io::ErrorKind::Unsupported, message);
+    match to {
+        Format::Btsnoop => {
+            if let Some(datalink) = datalink {
+                let datalink_type = datalink.datalink_type().ok_or_else(|| {
+                    unsupported(format!("btsnoop has no {:?} datalink", datalink))
+                })?;
+                convert_datalink(&mut capture, datalink_type)?;
+            }
+            write(&capture, output)?;
+        }
+        Format::Pcap | Format::Pcapng => {
+            let datalink = datalink.unwrap_or(Datalink::H4Phdr);
+            let link_type = datalink
+                .link_type()
+                .ok_or_else(|| unsupported(format!("pcap has no {:?} link type", datalink)))?;
+            convert_datalink(&mut capture, DatalinkType::Uart)?;
+            let mut writer = create(output)?;
+            if to == Format::Pcap {
+                pcap::write(&capture, link_type, &mut writer)?;
+            } else {
+                pcapng::write(&capture, link_type, &mut writer)?;
+            }
+            writer.flush()?;
+        }
+        Format::PacketLogger | Format::Btsnooz => {
+            return Err(unsupported(format!("{:?} files can only be read", to)))
+        }
+    }
+    Ok(capture.packets.len())
+}
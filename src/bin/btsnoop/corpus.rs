@@ -0,0 +1,68 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    analysis::corpus::{fuzz_seeds, write_corpus, Layer},
+    Btsnoop,
+};
+use clap::ValueEnum;
+
+use crate::{json, Direction};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CorpusLayer {
+    HciCommand,
+    HciEvent,
+    Acl,
+    L2cap,
+    Att,
+}
+
+impl From<CorpusLayer> for Layer {
+    fn from(value: CorpusLayer) -> Self {
+        match value {
+            CorpusLayer::HciCommand => Layer::Command,
+            CorpusLayer::HciEvent => Layer::Event,
+            CorpusLayer::Acl => Layer::Acl,
+            CorpusLayer::L2cap => Layer::L2cap,
+            CorpusLayer::Att => Layer::Att,
+        }
+    }
+}
+
+/// Write the seeds of `layers`, all when empty, to a subdirectory of `output` per layer
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    output: &Path,
+    layers: Vec<CorpusLayer>,
+    direction: Option<Direction>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let layers: Vec<Layer> = layers.into_iter().map(Layer::from).collect();
+    let seeds: Vec<_> = fuzz_seeds(capture)
+        .into_iter()
+        .filter(|seed| layers.is_empty() || layers.contains(&seed.layer))
+        .filter(|seed| {
+            direction.is_none_or(|direction| seed.sent == matches!(direction, Direction::Sent))
+        })
+        .collect();
+    write_corpus(&seeds, output)?;
+    if json {
+        return writeln!(out, "{}", json::corpus(output, &seeds));
+    }
+    for layer in Layer::ALL {
+        let count = seeds.iter().filter(|seed| seed.layer == layer).count();
+        if count > 0 {
+            writeln!(
+                out,
+                "{}: {} seeds",
+                output.join(layer.name()).display(),
+                count
+            )?;
+        }
+    }
+    Ok(())
+}
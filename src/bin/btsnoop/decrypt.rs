@@ -0,0 +1,106 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use btsnoop::{
+    decrypt::{passkey_tk, Decryption, Decryptor, KeyStore},
+    formats::le_ll,
+};
+use clap::Args;
+
+use crate::{create, open, read, write, written};
+
+#[derive(Debug, Args)]
+pub struct Decrypt {
+    input: PathBuf,
+    output: PathBuf,
+    /// long term key in hex, most significant octet first as Wireshark takes it, can be
+    /// repeated
+    #[arg(long, value_parser = parse_key)]
+    ltk: Vec<[u8; 16]>,
+    /// temporary key of a legacy pairing: the passkey in decimal or the out of band key in
+    /// hex, can be repeated
+    #[arg(long, value_parser = parse_tk)]
+    tk: Vec<[u8; 16]>,
+    /// HCI capture of one of the devices to learn the keys from, can be repeated
+    #[arg(long)]
+    keys_from: Vec<PathBuf>,
+    /// write the decrypted L2CAP traffic as the HCI ACL data of the Central instead
+    #[arg(long)]
+    hci: bool,
+}
+
+/// A key in hex, most significant octet first, as stored least significant octet first
+fn parse_key(hex: &str) -> Result<[u8; 16], String> {
+    let hex = hex.trim_start_matches("0x");
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err("expected 32 hex digits".to_string());
+    }
+    let mut key = [0; 16];
+    for (i, octet) in key.iter_mut().rev().enumerate() {
+        *octet = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(key)
+}
+
+/// A passkey of up to 6 decimal digits, or a key in hex
+fn parse_tk(tk: &str) -> Result<[u8; 16], String> {
+    match tk.parse::<u32>() {
+        Ok(passkey) if tk.len() <= 6 => Ok(passkey_tk(passkey)),
+        _ => parse_key(tk),
+    }
+}
+
+pub fn run<W: Write>(decrypt: Decrypt, json: bool, out: &mut W) -> io::Result<()> {
+    let packets = self::decrypt(&decrypt)?;
+    written(json, &decrypt.output, packets, out)
+}
+
+/// Returns the number of packets written
+fn decrypt(decrypt: &Decrypt) -> io::Result<usize> {
+    let mut keys = KeyStore::new();
+    for ltk in &decrypt.ltk {
+        keys.add_ltk(*ltk, None, None);
+    }
+    for tk in &decrypt.tk {
+        keys.add_tk(*tk);
+    }
+    for path in &decrypt.keys_from {
+        keys.learn_from_hci(&read(path)?);
+    }
+    let (_, mut reader) = open(&decrypt.input, None)?;
+    let mut packets = le_ll::read(&mut reader)?;
+    let mut decryptor = Decryptor::new(keys);
+    let decryptions: Vec<_> = packets.iter_mut().map(|p| decryptor.process(p)).collect();
+    for (access_address, passkey) in decryptor.passkeys() {
+        eprintln!(
+            "btsnoop: recovered passkey {:06} of the legacy pairing on access address 0x{:08x}",
+            passkey, access_address
+        );
+    }
+    let failed = decryptions
+        .iter()
+        .filter(|d| **d == Decryption::Failed)
+        .count();
+    if failed > 0 {
+        eprintln!(
+            "btsnoop: {} packets not decrypted, their key is unknown",
+            failed
+        );
+    }
+    if decrypt.hci {
+        let plaintext = packets
+            .iter()
+            .zip(&decryptions)
+            .filter(|(_, d)| **d != Decryption::Failed)
+            .map(|(packet, _)| packet);
+        let capture = le_ll::acl_capture(plaintext);
+        write(&capture, &decrypt.output)?;
+        return Ok(capture.packets.len());
+    }
+    let mut writer = create(&decrypt.output)?;
+    le_ll::write(&packets, &mut writer)?;
+    writer.flush()?;
+    Ok(packets.len())
+}
@@ -0,0 +1,329 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    dissect::{self, Registry, Session},
+    flags::{FlagField, FlagInterpretations},
+    follow::Follower,
+    formats::{self, Format},
+    parse_uart_packet, DatalinkType, Header, Packet, Reader, UartData,
+};
+use clap::{Args, ValueEnum};
+
+use crate::{filter::PacketFilter, is_std, is_uart, json, open};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// when writing to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct DumpOptions {
+    /// one line per packet, without the decoded layers
+    #[arg(long)]
+    pub brief: bool,
+    #[arg(long, value_enum, default_value_t)]
+    pub color: ColorChoice,
+    /// JSON lines instead, set by the global --json
+    #[arg(skip)]
+    pub json: bool,
+    /// decode with the dissectors of a plugin library too, can be repeated
+    #[cfg(feature = "plugins")]
+    #[arg(long = "plugin", value_name = "LIBRARY")]
+    pub plugins: Vec<std::path::PathBuf>,
+    /// name a field of the reserved flag bits, e.g. `adapter=0xff00` or `retry=0x4,0=no,1=yes`,
+    /// can be repeated
+    #[arg(long = "flag-field", value_name = "NAME=MASK[,VALUE=LABEL...]")]
+    pub flag_fields: Vec<FlagField>,
+}
+
+pub const BLUE: &str = "\x1b[34m";
+pub const MAGENTA: &str = "\x1b[35m";
+pub const GREEN: &str = "\x1b[32m";
+pub const CYAN: &str = "\x1b[36m";
+pub const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Indentation of the decoded layers
+const INDENT: &str = "        ";
+
+struct Printer {
+    filter: PacketFilter,
+    brief: bool,
+    color: bool,
+    json: bool,
+    uart: bool,
+    start: Option<i64>,
+    registry: &'static Registry,
+    flag_fields: FlagInterpretations,
+    /// follows the channels and attributes of the capture, filtered out packets included
+    session: Session<'static>,
+}
+
+pub fn dump<W: Write>(
+    path: &Path,
+    filter: PacketFilter,
+    options: &DumpOptions,
+    out: &mut W,
+) -> io::Result<()> {
+    match open(path, None)? {
+        // printed as they are read, for pipes
+        (Format::Btsnoop, reader) => {
+            let reader = Reader::new(reader)?;
+            header(out, options, reader.header())?;
+            let uart = matches!(reader.header().datalink_type, DatalinkType::Uart);
+            stream(reader, uart, filter, options, out)
+        }
+        (format, mut reader) => {
+            let capture = formats::read(&mut reader, format)?;
+            header(out, options, &capture.header)?;
+            let mut printer = Printer::new(filter, options)?;
+            printer.uart = is_uart(&capture);
+            for (index, packet) in capture.packets.iter().enumerate() {
+                printer.print(out, index, packet)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the packets as they are appended to the log, until interrupted. Rotations of the log
+/// are marked, followed by the header of the new log in JSON, and the packet numbers start over
+/// with the new log.
+pub fn follow<W: Write>(
+    path: &Path,
+    filter: PacketFilter,
+    options: &DumpOptions,
+    out: &mut W,
+) -> io::Result<()> {
+    if is_std(path) {
+        return dump(path, filter, options, out);
+    }
+    let mut follower = Follower::open(path)?;
+    header(out, options, follower.header())?;
+    let mut printer = Printer::new(filter, options)?;
+    let mut rotations = 0;
+    let mut index = 0;
+    loop {
+        let packet = follower.next_packet()?;
+        if follower.rotations() != rotations {
+            rotations = follower.rotations();
+            index = 0;
+            printer.session = printer.registry.session();
+            if options.json {
+                writeln!(out, "{}", serde_json::json!({ "type": "rotated" }))?;
+                header(out, options, follower.header())?;
+            } else {
+                writeln!(out, "--- {} was rotated ---", path.display())?;
+            }
+        }
+        printer.uart = matches!(follower.header().datalink_type, DatalinkType::Uart);
+        printer.print(out, index, &packet)?;
+        out.flush()?;
+        index += 1;
+    }
+}
+
+/// The header document of a capture in JSON, nothing otherwise
+pub fn header<W: Write>(out: &mut W, options: &DumpOptions, header: &Header) -> io::Result<()> {
+    if options.json {
+        writeln!(out, "{}", json::header(header))?;
+    }
+    Ok(())
+}
+
+/// Print the packets of a live source as they arrive
+pub fn stream<W: Write, I: Iterator<Item = io::Result<Packet>>>(
+    packets: I,
+    uart: bool,
+    filter: PacketFilter,
+    options: &DumpOptions,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut printer = Printer::new(filter, options)?;
+    printer.uart = uart;
+    for (index, packet) in packets.enumerate() {
+        printer.print(out, index, &packet?)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// The built-in dissectors, with the ones of the plugins given. The registry lives until the
+/// process exits.
+fn registry(options: &DumpOptions) -> io::Result<&'static Registry> {
+    #[cfg(feature = "plugins")]
+    if !options.plugins.is_empty() {
+        let mut registry = Registry::with_builtins();
+        for path in &options.plugins {
+            // Safety: the user asked for the plugin to be loaded
+            let plugin = unsafe { btsnoop::plugin::Plugin::load(path)? };
+            plugin.register(&mut registry);
+        }
+        return Ok(Box::leak(Box::new(registry)));
+    }
+    let _ = options;
+    Ok(Registry::builtins())
+}
+
+impl Printer {
+    fn new(filter: PacketFilter, options: &DumpOptions) -> io::Result<Self> {
+        let color = match options.color {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        let registry = registry(options)?;
+        let mut flag_fields = FlagInterpretations::new();
+        for field in &options.flag_fields {
+            flag_fields.register(field.clone())?;
+        }
+        Ok(Printer {
+            filter,
+            brief: options.brief,
+            color,
+            json: options.json,
+            uart: false,
+            start: None,
+            registry,
+            flag_fields,
+            session: registry.session(),
+        })
+    }
+
+    fn print<W: Write>(&mut self, out: &mut W, index: usize, packet: &Packet) -> io::Result<()> {
+        let start = *self.start.get_or_insert(packet.description.timestamp);
+        let details = if self.uart && !self.brief && !self.json {
+            self.session.dissect(packet)
+        } else {
+            vec![]
+        };
+        if !self.filter.matches(start, self.uart, packet) {
+            return Ok(());
+        }
+        if self.json {
+            let mut document = json::packet(index, packet, start, self.uart);
+            if !self.flag_fields.is_empty() {
+                document["flag_fields"] =
+                    json::flag_fields(&self.flag_fields, packet.description.flags);
+            }
+            return writeln!(out, "{}", document);
+        }
+        let (mut line, color) = if self.uart {
+            summary(packet)
+        } else {
+            (raw_summary(packet), "")
+        };
+        if !self.flag_fields.is_empty() && packet.description.flags.reserved_bits() != 0 {
+            line = format!(
+                "{} [{}]",
+                line,
+                self.flag_fields.describe(packet.description.flags)
+            );
+        }
+        let (color, reset) = if self.color && !color.is_empty() {
+            (color, RESET)
+        } else {
+            ("", "")
+        };
+        writeln!(
+            out,
+            "{:>6} {:>14.6} {}{}{}",
+            index,
+            packet.description.timestamp.saturating_sub(start) as f64 / 1e6,
+            color,
+            line,
+            reset
+        )?;
+        for detail in dissect::lines(&details) {
+            writeln!(out, "{}{}", INDENT, detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Packets of other datalink types are only described by their flags
+fn raw_summary(packet: &Packet) -> String {
+    let flags = packet.description.flags;
+    format!(
+        "{} {} ({} bytes)",
+        if flags.is_received() { '>' } else { '<' },
+        match (flags.is_command_or_event(), flags.is_received()) {
+            (true, false) => "Command",
+            (true, true) => "Event",
+            _ => "Data",
+        },
+        packet.data.0.len()
+    )
+}
+
+/// One line description of an HCI UART packet in the style of btmon, with its color
+pub fn summary(packet: &Packet) -> (String, &'static str) {
+    let received = packet.description.flags.is_received();
+    let color = match parse_uart_packet(packet) {
+        Ok(UartData::Command(_)) => BLUE,
+        Ok(UartData::Event(_)) => MAGENTA,
+        Ok(UartData::Acl(_)) if received => CYAN,
+        Ok(UartData::Acl(_)) => GREEN,
+        Ok(UartData::Todos) if packet.data.0.is_empty() => RED,
+        Ok(UartData::Todos) => "",
+        Err(e) => {
+            return (
+                format!(
+                    "{} Undecoded: {} ({} bytes)",
+                    if received { '>' } else { '<' },
+                    e,
+                    packet.data.0.len()
+                ),
+                RED,
+            )
+        }
+    };
+    (packet.summary().to_string(), color)
+}
+
+/// The decoded layers of a packet with the built-in dissectors, one line each
+pub fn details(packet: &Packet) -> Vec<String> {
+    dissect::lines(&dissect::dissect(packet))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use btsnoop::{PacketData, PacketDescription, PacketFlags};
+
+    #[test]
+    fn att_error_layers() {
+        // ACL handle 0x0040, ATT Error Response to a Read Request of handle 0x0003
+        let data = vec![
+            0x02, 0x40, 0x20, 0x09, 0x00, 0x05, 0x00, 0x04, 0x00, 0x01, 0x0A, 0x03, 0x00, 0x0A,
+        ];
+        let packet = Packet {
+            description: PacketDescription {
+                original_length: data.len() as u32,
+                included_length: data.len() as u32,
+                flags: PacketFlags(1),
+                cumulative_drops: 0,
+                timestamp: 0,
+            },
+            data: PacketData(data),
+        };
+        let (line, color) = summary(&packet);
+        assert_eq!(line, "> ACL Data RX: Handle 64 flags 0x02 dlen 9");
+        assert_eq!(color, CYAN);
+        assert_eq!(
+            details(&packet),
+            vec![
+                "L2CAP: CID 0x0004 len 5",
+                "  ATT: Error Response (0x01)",
+                "    Read Request (0x0a) handle 0x0003: Attribute Not Found (0x0a)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Wireshark extcap interface: with this binary in the extcap folder, Wireshark lists a
+//! "btsnoop/adb HCI" interface capturing the snoop log of a connected Android device.
+
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use btsnoop::{
+    adb::Device,
+    formats::{pcap::LinkType, pcapng},
+    DatalinkType,
+};
+use clap::Args;
+
+const INTERFACE: &str = "btsnoop-adb";
+
+/// The options Wireshark passes, hidden from the help
+#[derive(Debug, Default, Args)]
+pub struct Extcap {
+    #[arg(long, hide = true)]
+    extcap_interfaces: bool,
+    #[arg(long, hide = true)]
+    extcap_version: Option<String>,
+    #[arg(long, hide = true)]
+    extcap_interface: Option<String>,
+    #[arg(long, hide = true)]
+    extcap_dlts: bool,
+    #[arg(long, hide = true)]
+    extcap_config: bool,
+    #[arg(long, hide = true)]
+    capture: bool,
+    #[arg(long, hide = true)]
+    fifo: Option<PathBuf>,
+    /// not supported, every packet is captured
+    #[arg(long, hide = true)]
+    extcap_capture_filter: Option<String>,
+    #[arg(long, hide = true)]
+    extcap_control_in: Option<PathBuf>,
+    #[arg(long, hide = true)]
+    extcap_control_out: Option<PathBuf>,
+    /// device serial number, set in the interface options
+    #[arg(long, hide = true)]
+    serial: Option<String>,
+}
+
+impl Extcap {
+    pub fn is_requested(&self) -> bool {
+        self.extcap_interfaces || self.extcap_interface.is_some()
+    }
+}
+
+pub fn run<W: Write>(extcap: Extcap, out: &mut W) -> io::Result<()> {
+    if extcap.extcap_interfaces {
+        writeln!(out, "extcap {{version={}}}", env!("CARGO_PKG_VERSION"))?;
+        return writeln!(
+            out,
+            "interface {{value={}}}{{display=btsnoop/adb HCI}}",
+            INTERFACE
+        );
+    }
+    if extcap.extcap_interface.as_deref() != Some(INTERFACE) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown interface, only {} is provided", INTERFACE),
+        ));
+    }
+    if extcap.extcap_dlts {
+        return writeln!(
+            out,
+            "dlt {{number={}}}{{name=BLUETOOTH_HCI_H4_WITH_PHDR}}{{display=Bluetooth HCI UART transport layer plus pseudo-header}}",
+            LinkType::H4WithPhdr as u32
+        );
+    }
+    if extcap.extcap_config {
+        return writeln!(
+            out,
+            "arg {{number=0}}{{call=--serial}}{{display=Device serial}}{{type=string}}{{tooltip=Serial number from adb devices, when several devices are connected}}"
+        );
+    }
+    if extcap.capture {
+        let fifo = extcap.fifo.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "--capture requires --fifo")
+        })?;
+        let stream = Device::new(extcap.serial).stream()?;
+        if !matches!(stream.header().datalink_type, DatalinkType::Uart) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the snoop log is not HCI UART (H4)",
+            ));
+        }
+        let fifo = OpenOptions::new().write(true).open(fifo)?;
+        let mut writer = pcapng::Writer::new(BufWriter::new(fifo), LinkType::H4WithPhdr)?;
+        writer.flush()?;
+        for packet in stream {
+            writer.write_packet(&packet?)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interfaces_and_dlts() {
+        let mut out = vec![];
+        let extcap = Extcap {
+            extcap_interfaces: true,
+            ..Default::default()
+        };
+        run(extcap, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("extcap {version="));
+        assert!(out.contains("interface {value=btsnoop-adb}{display=btsnoop/adb HCI}"));
+
+        let mut out = vec![];
+        let extcap = Extcap {
+            extcap_interface: Some(INTERFACE.to_string()),
+            extcap_dlts: true,
+            ..Default::default()
+        };
+        run(extcap, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("dlt {number=201}"));
+    }
+}
@@ -0,0 +1,26 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::Btsnoop;
+
+use crate::{write, written};
+
+/// Write the packets from number `first` to `last` included, to the end when not given
+pub fn run<W: Write>(
+    mut capture: Btsnoop,
+    output: &Path,
+    first: usize,
+    last: Option<usize>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let end = last.map_or(capture.packets.len(), |last| {
+        (last + 1).min(capture.packets.len())
+    });
+    capture.packets.truncate(end);
+    capture.packets.drain(..first.min(end));
+    write(&capture, output)?;
+    written(json, output, capture.packets.len(), out)
+}
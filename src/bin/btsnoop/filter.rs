@@ -0,0 +1,149 @@
+use std::{
+    io::{self, Write},
+    num::ParseIntError,
+    path::Path,
+};
+
+use btsnoop::{
+    hci::{
+        CommandComplete, CommandStatus, DisconnectionComplete, Event, LeMetaEvent,
+        NumberOfCompletedPackets,
+    },
+    parse_uart_packet, Btsnoop, Packet, UartData,
+};
+use clap::Args;
+
+use crate::{first_timestamp, is_uart, write, written};
+
+/// Packet selection shared by the subcommands
+#[derive(Debug, Default, Args)]
+pub struct PacketFilter {
+    /// skip the packets before this many seconds after the first packet
+    #[arg(long)]
+    pub since: Option<f64>,
+    /// skip the packets after this many seconds after the first packet
+    #[arg(long)]
+    pub until: Option<f64>,
+    /// only ACL data and connection events of this connection handle
+    #[arg(long, value_parser = parse_number)]
+    pub handle: Option<u16>,
+    /// only this command opcode, e.g. 0x200C, and the events answering it
+    #[arg(long, value_parser = parse_number)]
+    pub opcode: Option<u16>,
+}
+
+/// decimal or 0x prefixed hexadecimal
+pub fn parse_number(value: &str) -> Result<u16, ParseIntError> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+}
+
+impl PacketFilter {
+    /// `start` is the timestamp of the first packet of the capture, `uart` whether its packets
+    /// are HCI UART (H4), the handle and opcode filters only match those.
+    pub fn matches(&self, start: i64, uart: bool, packet: &Packet) -> bool {
+        let seconds = packet.description.timestamp.saturating_sub(start) as f64 / 1e6;
+        if self.since.is_some_and(|since| seconds < since)
+            || self.until.is_some_and(|until| seconds > until)
+        {
+            return false;
+        }
+        if self.handle.is_none() && self.opcode.is_none() {
+            return true;
+        }
+        let Some(data) = uart.then(|| parse_uart_packet(packet).ok()).flatten() else {
+            return false;
+        };
+        self.handle
+            .is_none_or(|handle| concerns_handle(&data, handle))
+            && self
+                .opcode
+                .is_none_or(|opcode| concerns_opcode(&data, opcode))
+    }
+}
+
+/// Write the packets matching `filter` to `output`
+pub fn run<W: Write>(
+    mut capture: Btsnoop,
+    output: &Path,
+    filter: &PacketFilter,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let start = first_timestamp(&capture);
+    let uart = is_uart(&capture);
+    capture
+        .packets
+        .retain(|packet| filter.matches(start, uart, packet));
+    write(&capture, output)?;
+    written(json, output, capture.packets.len(), out)
+}
+
+fn concerns_handle(data: &UartData, handle: u16) -> bool {
+    match data {
+        UartData::Acl(acl) => acl.handle == handle,
+        UartData::Event(event) => {
+            let mut params = event.params;
+            match event.code {
+                Event::DISCONNECTION_COMPLETE => DisconnectionComplete::parse(&mut params)
+                    .is_ok_and(|disconnection| disconnection.handle == handle),
+                Event::NUMBER_OF_COMPLETED_PACKETS => NumberOfCompletedPackets::parse(&mut params)
+                    .is_ok_and(|completed| completed.completed.iter().any(|(h, _)| *h == handle)),
+                Event::LE_META => match LeMetaEvent::try_from(event.params) {
+                    Ok(LeMetaEvent::ConnectionComplete(complete)) => complete.handle == handle,
+                    Ok(LeMetaEvent::ConnectionUpdateComplete(update)) => update.handle == handle,
+                    Ok(LeMetaEvent::RemoteConnectionParameterRequest(request)) => {
+                        request.handle == handle
+                    }
+                    _ => false,
+                },
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn concerns_opcode(data: &UartData, opcode: u16) -> bool {
+    match data {
+        UartData::Command(command) => command.opcode.raw() == opcode,
+        UartData::Event(event) => match event.code {
+            Event::COMMAND_COMPLETE => CommandComplete::try_from(event.params)
+                .is_ok_and(|complete| complete.opcode.raw() == opcode),
+            Event::COMMAND_STATUS => CommandStatus::parse(&mut &event.params[..])
+                .is_ok_and(|status| status.opcode.raw() == opcode),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x200C"), Ok(0x200C));
+        assert_eq!(parse_number("64"), Ok(64));
+        assert!(parse_number("0x10000").is_err());
+    }
+
+    #[test]
+    fn corrupt_start() {
+        let original: &[u8] = include_bytes!("../../../res/btsnoop_hci_android.log");
+        let mut capture = btsnoop::Btsnoop::parse(&mut &original[..]).unwrap();
+        capture.packets[0].description.timestamp = i64::MIN;
+        let filter = PacketFilter {
+            until: Some(60.0),
+            ..Default::default()
+        };
+        assert!(filter.matches(i64::MIN, true, &capture.packets[0]));
+        assert!(!filter.matches(i64::MIN, true, &capture.packets[1]));
+    }
+}
@@ -0,0 +1,37 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{analysis::firmware::firmware_downloads, Btsnoop};
+
+use crate::{create, json};
+
+/// The firmware downloads, the patch of the last one written to `hcd` when given
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    hcd: Option<&Path>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let downloads = firmware_downloads(capture);
+    if json {
+        writeln!(out, "{}", json::firmware(&downloads))?;
+    } else {
+        for (number, download) in downloads.iter().enumerate() {
+            writeln!(out, "download {}: {}", number + 1, download)?;
+        }
+    }
+    if let Some(path) = hcd {
+        let download = downloads
+            .last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no firmware download"))?;
+        if !download.is_complete() {
+            eprintln!("btsnoop: the last firmware download is incomplete");
+        }
+        let mut writer = create(path)?;
+        download.write_hcd(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+
+use btsnoop::{
+    analysis::hfp::{codec_name, hfp_sessions, AtMessage},
+    hci::{error_code_name, synchronous::coding_format_name},
+    report::format_utc,
+    Btsnoop,
+};
+
+use crate::json;
+
+/// The codec negotiation and voice links of each session, in packet order
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let sessions: Vec<_> = hfp_sessions(capture)
+        .into_iter()
+        .filter(|session| handle.is_none_or(|handle| session.handle == Some(handle)))
+        .collect();
+    if json {
+        return writeln!(out, "{}", json::hfp(&sessions));
+    }
+    let codec = |codec: u8| {
+        codec_name(codec)
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("codec 0x{:02x}", codec))
+    };
+    for session in &sessions {
+        let connection = match session.handle {
+            Some(handle) => format!("handle 0x{:04x}", handle),
+            None => "unknown connection".to_owned(),
+        };
+        let mut lines: Vec<_> = session
+            .negotiation
+            .iter()
+            .map(|step| {
+                let line = match &step.message {
+                    AtMessage::AvailableCodecs(codecs) => format!(
+                        "available codecs {}",
+                        codecs
+                            .iter()
+                            .map(|&id| codec(id))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    AtMessage::CodecSelection(id) => format!("{} selected", codec(*id)),
+                    AtMessage::CodecConfirmation(id) => format!("{} confirmed", codec(*id)),
+                    AtMessage::CodecConnection => "codec connection requested".into(),
+                };
+                (step.packet_index, step.timestamp, line)
+            })
+            .chain(session.voice_links.iter().map(|link| {
+                let attempted = link
+                    .attempted_codec()
+                    .map(|id| format!(", {} attempted", codec(id)))
+                    .unwrap_or_default();
+                let line = match link.complete {
+                    Some(complete) if link.is_established() => format!(
+                        "voice link 0x{:04x} established: {}, air mode {}{}{}",
+                        complete.handle,
+                        link.codec().map(codec).unwrap_or("unknown codec".into()),
+                        coding_format_name(complete.air_mode).unwrap_or("unknown"),
+                        link.setup
+                            .map(|setup| format!(
+                                ", {} octets/s, retransmission effort 0x{:02x}",
+                                setup.transmit_bandwidth, setup.retransmission_effort
+                            ))
+                            .unwrap_or_default(),
+                        if link.fallback { ", fallback" } else { "" }
+                    ),
+                    _ => format!(
+                        "voice link failed: {}{}",
+                        error_code_name(link.status).unwrap_or("unknown error"),
+                        attempted
+                    ),
+                };
+                (link.complete_packet, link.timestamp, line)
+            }))
+            .collect();
+        lines.sort_by_key(|(packet_index, _, _)| *packet_index);
+        for (packet_index, timestamp, line) in lines {
+            writeln!(
+                out,
+                "#{} {} {}: {}",
+                packet_index,
+                format_utc(timestamp),
+                connection,
+                line
+            )?;
+        }
+        writeln!(
+            out,
+            "{}: {} renegotiations, {} fallbacks",
+            connection,
+            session.renegotiations(),
+            session.fallbacks()
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    analysis::hid::{hid_events, typed_text, InputEvent},
+    hid::{key_name, Input, ReportMap},
+    report::format_utc,
+    Btsnoop,
+};
+
+use crate::json;
+
+/// The input events of the devices selected and the text typed on each
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    report_map: Option<&Path>,
+    handle: Option<u16>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let report_map = report_map
+        .map(|path| ReportMap::parse(&std::fs::read(path)?))
+        .transpose()?;
+    let events: Vec<_> = hid_events(capture, report_map.as_ref())
+        .into_iter()
+        .filter(|event| handle.is_none_or(|handle| event.connection == handle))
+        .collect();
+    let mut connections: Vec<_> = events.iter().map(|event| event.connection).collect();
+    connections.sort_unstable();
+    connections.dedup();
+    let texts: Vec<_> = connections
+        .iter()
+        .map(|connection| {
+            let events: Vec<_> = events
+                .iter()
+                .filter(|event| event.connection == *connection)
+                .cloned()
+                .collect();
+            (*connection, typed_text(&events))
+        })
+        .collect();
+    if json {
+        return writeln!(out, "{}", json::hid(&events, &texts));
+    }
+    for event in &events {
+        let description = match event.event {
+            InputEvent::Key { usage, pressed } => format!(
+                "{} {}",
+                match (Input::char(usage, false), key_name(usage)) {
+                    (_, Some(name)) => name.to_string(),
+                    (Some(c), None) => format!("{:?}", c),
+                    (None, None) => format!("usage 0x{:02x}", usage),
+                },
+                if pressed { "down" } else { "up" }
+            ),
+            InputEvent::Pointer {
+                buttons,
+                x,
+                y,
+                wheel,
+            } => format!(
+                "pointer buttons 0x{:x} x {} y {} wheel {}",
+                buttons, x, y, wheel
+            ),
+        };
+        writeln!(
+            out,
+            "#{} {} handle 0x{:04x}: {}",
+            event.packet_index,
+            format_utc(event.timestamp),
+            event.connection,
+            description
+        )?;
+    }
+    for (connection, text) in &texts {
+        writeln!(out, "handle 0x{:04x} typed: {:?}", connection, text)?;
+    }
+    Ok(())
+}
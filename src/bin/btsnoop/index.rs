@@ -0,0 +1,64 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::Path,
+};
+
+use btsnoop::{hci::error_code_name, index::CaptureIndex};
+
+use crate::json;
+
+/// The index of `file`, from its sidecar file unless `rebuild`
+pub fn run<W: Write>(file: &Path, rebuild: bool, json: bool, out: &mut W) -> io::Result<()> {
+    let index = if rebuild {
+        let index = CaptureIndex::build(BufReader::new(File::open(file)?))?;
+        index.save(CaptureIndex::sidecar_path(file))?;
+        index
+    } else {
+        CaptureIndex::open(file)?
+    };
+    if json {
+        writeln!(out, "{}", json::index(&index))
+    } else {
+        print(&index, out)
+    }
+}
+
+/// The connections and devices of an index, one per line
+fn print<W: Write>(index: &CaptureIndex, out: &mut W) -> io::Result<()> {
+    writeln!(out, "packets: {}", index.len())?;
+    for connection in &index.connections {
+        write!(
+            out,
+            "connection 0x{:04x} {} {}: #{}",
+            connection.handle,
+            if connection.le { "LE" } else { "BR/EDR" },
+            connection.address,
+            connection.connected
+        )?;
+        match (connection.disconnected, connection.reason) {
+            (Some(end), Some(reason)) => writeln!(
+                out,
+                "-#{}, {}",
+                end,
+                error_code_name(reason).unwrap_or("unknown reason")
+            )?,
+            _ => writeln!(out, "-")?,
+        }
+    }
+    for device in &index.devices {
+        write!(
+            out,
+            "device {} ({}): #{}-#{}",
+            device.address,
+            device.address.kind(device.address_type),
+            device.first_seen,
+            device.last_seen
+        )?;
+        match &device.name {
+            Some(name) => writeln!(out, " {:?}", name)?,
+            None => writeln!(out)?,
+        }
+    }
+    Ok(())
+}
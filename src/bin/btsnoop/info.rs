@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+
+use btsnoop::{analysis::statistics::statistics, parse_uart_packet, report::format_utc, Btsnoop};
+
+use crate::{is_uart, json};
+
+pub fn run<W: Write>(capture: &Btsnoop, json: bool, out: &mut W) -> io::Result<()> {
+    let statistics = statistics(capture);
+    let decoded = is_uart(capture).then(|| {
+        capture
+            .packets
+            .iter()
+            .filter(|p| parse_uart_packet(p).is_ok())
+            .count()
+    });
+    if json {
+        return writeln!(out, "{}", json::info(&capture.header, &statistics, decoded));
+    }
+    writeln!(out, "Version:   {}", capture.header.version)?;
+    writeln!(
+        out,
+        "Datalink:  {} ({})",
+        capture.header.datalink_type,
+        u32::from(capture.header.datalink_type)
+    )?;
+    writeln!(out, "Packets:   {}", statistics.packets)?;
+    writeln!(out, "Bytes:     {}", statistics.bytes)?;
+    if let (Some(first), Some(last)) = (statistics.first_timestamp, statistics.last_timestamp) {
+        writeln!(out, "Start:     {}", format_utc(first))?;
+        writeln!(out, "End:       {}", format_utc(last))?;
+        writeln!(
+            out,
+            "Duration:  {:.6} s",
+            statistics.duration_us() as f64 / 1e6
+        )?;
+    }
+    writeln!(out, "Truncated: {}", statistics.truncated)?;
+    writeln!(out, "Dropped:   {}", statistics.dropped)?;
+    if let Some(decoded) = decoded {
+        writeln!(out, "Decoded:   {}", decoded)?;
+    }
+    Ok(())
+}
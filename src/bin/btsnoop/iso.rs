@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use btsnoop::{analysis::iso::iso_streams, Btsnoop};
+
+use crate::json;
+
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let streams: Vec<_> = iso_streams(capture)
+        .into_iter()
+        .filter(|stream| handle.is_none_or(|handle| stream.handle == handle))
+        .collect();
+    if json {
+        return writeln!(out, "{}", json::iso(&streams));
+    }
+    for stream in &streams {
+        writeln!(
+            out,
+            "handle 0x{:04x} {}: {} SDUs, {} bytes, {} lost, {} possibly invalid, {} missing, {} late, {} incomplete",
+            stream.handle,
+            if stream.sent { "TX" } else { "RX" },
+            stream.sdus,
+            stream.octets,
+            stream.lost,
+            stream.possibly_invalid,
+            stream.missing,
+            stream.late,
+            stream.incomplete
+        )?;
+    }
+    Ok(())
+}
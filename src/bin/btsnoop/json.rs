@@ -0,0 +1,656 @@
+//! JSON output of the command line tool, selected with `--json`. Every document is an object
+//! on its own line with its `type`; the ones which start an output also carry the `schema`
+//! version, bumped when a field is removed or changes meaning. Fields may be added without a
+//! bump.
+//!
+//! | Subcommand | Documents |
+//! | --- | --- |
+//! | info | `info` |
+//! | stats | `stats` |
+//! | check | `check` |
+//! | dump, follow, adb follow | `header`, then a `packet` per packet, `rotated` and `header` again when the log is rotated |
+//! | filter, sequence, trace, extract, convert, decrypt, capture, packetlogger, play, adb pull | `written`, on stderr when the capture is written to stdout |
+//! | annotate | `annotations` |
+//! | index | `index` |
+//! | streams | `streams` |
+//! | replay | `gatt_replays` |
+//! | hid | `hid` |
+//! | corpus | `corpus` |
+//! | iso | `iso` |
+//! | power | `power` |
+//! | hfp | `hfp` |
+//! | mesh | `mesh` |
+//! | firmware | `firmware` |
+//! | vendor | `vendor` |
+//! | adb snoop-mode | `snoop_mode` |
+//! | serve | `header` and `packet` messages, with or without `--json` |
+//!
+//! A failed command prints an `error` document, `{"type": "error", "schema", "message"}`.
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "adb")]
+use btsnoop::adb::SnoopMode;
+use btsnoop::{
+    analysis::{
+        command_errors::{ErrorReport, FailedOperation},
+        corpus::{Layer, Seed},
+        firmware::FirmwareDownload,
+        gatt_replay::{GattReplay, Operation, Outcome},
+        health_check::{self, HealthFinding},
+        hfp::{codec_name, AtMessage, HfpSession},
+        hid::{HidEvent, InputEvent},
+        iso::IsoStream,
+        power_control::ConnectionPower,
+        statistics::{packet_type_name, Count, Statistics},
+        streams::ChannelStream,
+    },
+    annotations::Annotations,
+    flags::FlagInterpretations,
+    hci::{
+        event_code_name, opcode_name, power_control::PathLossThreshold,
+        synchronous::coding_format_name, LeMetaEvent,
+    },
+    hexdump::hex_string,
+    index::CaptureIndex,
+    mesh::{self, Message},
+    report::format_utc,
+    vendor::{Diagnostic, VendorRecord},
+    Header, Packet, PacketDescription, PacketFlags,
+};
+use serde_json::{json, Value};
+
+use crate::dump;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `{"type": "header", "schema", "version", "datalink", "datalink_code"}`
+pub fn header(header: &Header) -> Value {
+    json!({
+        "type": "header",
+        "schema": SCHEMA_VERSION,
+        "version": header.version,
+        "datalink": header.datalink_type.to_string(),
+        "datalink_code": u32::from(header.datalink_type),
+    })
+}
+
+/// `{"type": "packet", "index", "timestamp_us", "relative_s", "direction", "flags",
+/// "original_length", "drops", "summary", "details", "data"}`, with the timestamp in
+/// microseconds since the Unix epoch and the data in hex. Only HCI UART packets are decoded,
+/// the summary and details of others are empty.
+pub fn packet(index: usize, packet: &Packet, start: i64, uart: bool) -> Value {
+    let description = &packet.description;
+    let (summary, details) = if uart {
+        (dump::summary(packet).0, dump::details(packet))
+    } else {
+        (String::new(), vec![])
+    };
+    json!({
+        "type": "packet",
+        "index": index,
+        "timestamp_us": description.unix_timestamp(),
+        "relative_s": description.timestamp.saturating_sub(start) as f64 / 1e6,
+        "direction": if description.flags.is_received() { "received" } else { "sent" },
+        "flags": description.flags.0,
+        "original_length": description.original_length,
+        "drops": description.cumulative_drops,
+        "summary": summary,
+        "details": details,
+        "data": hex_string(&packet.data.0),
+    })
+}
+
+/// `{"type": "info", "schema", "header", "packets", "bytes", "start", "end", "duration_us",
+/// "truncated", "dropped", "decoded"}`, with the start and end in UTC and `decoded` null
+/// unless the capture is HCI UART
+pub fn info(header: &Header, statistics: &Statistics, decoded: Option<usize>) -> Value {
+    json!({
+        "type": "info",
+        "schema": SCHEMA_VERSION,
+        "header": self::header(header),
+        "packets": statistics.packets,
+        "bytes": statistics.bytes,
+        "start": statistics.first_timestamp.map(format_utc),
+        "end": statistics.last_timestamp.map(format_utc),
+        "duration_us": statistics.duration_us(),
+        "truncated": statistics.truncated,
+        "dropped": statistics.dropped,
+        "decoded": decoded,
+    })
+}
+
+/// `{"code", "name", "sent", "received", "bytes"}`
+fn count(code: u32, name: Option<&str>, count: &Count) -> Value {
+    json!({
+        "code": code,
+        "name": name,
+        "sent": count.sent,
+        "received": count.received,
+        "bytes": count.bytes,
+    })
+}
+
+/// `{"type": "stats", "schema", "packet_types", "commands", "events", "le_subevents",
+/// "acl_handles"}`, each a list of counts `{"code", "name", "sent", "received", "bytes"}`,
+/// the name null when unknown
+pub fn stats(statistics: &Statistics) -> Value {
+    json!({
+        "type": "stats",
+        "schema": SCHEMA_VERSION,
+        "packet_types": statistics.packet_types.iter()
+            .map(|(t, c)| count((*t).into(), Some(packet_type_name(*t)), c))
+            .collect::<Vec<_>>(),
+        "commands": statistics.commands.iter()
+            .map(|(opcode, c)| count((*opcode).into(), opcode_name(*opcode), c))
+            .collect::<Vec<_>>(),
+        "events": statistics.events.iter()
+            .map(|(code, c)| count((*code).into(), event_code_name(*code), c))
+            .collect::<Vec<_>>(),
+        "le_subevents": statistics.le_subevents.iter()
+            .map(|(code, c)| count((*code).into(), LeMetaEvent::subevent_name(*code), c))
+            .collect::<Vec<_>>(),
+        "acl_handles": statistics.acl_handles.iter()
+            .map(|(handle, c)| count((*handle).into(), None, c))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "check", "schema", "passed", "findings", "summary", "failures"}`: the findings of
+/// the health check `{"packet_index", "category", "message"}`, their number by category
+/// `{"category", "count"}` and the failed commands and ATT requests `{"operation", "opcode",
+/// "name", "status", "status_name", "count", "first_packet_index"}`, the operation "hci" or
+/// "att"
+pub fn check(findings: &[HealthFinding], errors: &ErrorReport) -> Value {
+    json!({
+        "type": "check",
+        "schema": SCHEMA_VERSION,
+        "passed": findings.is_empty() && errors.is_empty(),
+        "findings": findings.iter().map(|finding| json!({
+            "packet_index": finding.packet_index,
+            "category": format!("{:?}", finding.issue.category()).to_lowercase(),
+            "message": finding.issue.to_string(),
+        })).collect::<Vec<_>>(),
+        "summary": health_check::summary(findings).iter().map(|(category, count)| json!({
+            "category": format!("{:?}", category).to_lowercase(),
+            "count": count,
+        })).collect::<Vec<_>>(),
+        "failures": errors.groups.iter().map(|group| {
+            let (operation, opcode) = match group.operation {
+                FailedOperation::Command(opcode) => ("hci", u32::from(opcode)),
+                FailedOperation::AttRequest(opcode) => ("att", u32::from(opcode)),
+            };
+            json!({
+                "operation": operation,
+                "opcode": opcode,
+                "name": group.operation.name(),
+                "status": group.status,
+                "status_name": group.operation.status_name(group.status),
+                "count": group.count,
+                "first_packet_index": group.first_packet_index,
+            })
+        }).collect::<Vec<_>>(),
+    })
+}
+
+/// The value of each field of the reserved flag bits by name, and the bits no field covers as
+/// `reserved`, added to the `packet` documents as `flag_fields` when fields are given
+pub fn flag_fields(interpretations: &FlagInterpretations, flags: PacketFlags) -> Value {
+    let mut fields: serde_json::Map<_, _> = interpretations
+        .fields()
+        .iter()
+        .map(|field| {
+            let value = match field.labels.get(&field.value(flags)) {
+                Some(label) => json!(label),
+                None => json!(field.value(flags)),
+            };
+            (field.name.clone(), value)
+        })
+        .collect();
+    fields.insert(
+        "reserved".to_owned(),
+        json!(interpretations.unknown_bits(flags)),
+    );
+    Value::Object(fields)
+}
+
+/// `{"type": "written", "schema", "output", "packets"}`
+pub fn written(output: &str, packets: usize) -> Value {
+    json!({
+        "type": "written",
+        "schema": SCHEMA_VERSION,
+        "output": output,
+        "packets": packets,
+    })
+}
+
+/// `{"type": "corpus", "schema", "output", "layers"}`, the number of seeds written to the
+/// directory of each layer in `output`, by its name
+pub fn corpus(output: &Path, seeds: &[Seed]) -> Value {
+    let layers: serde_json::Map<_, _> = Layer::ALL
+        .into_iter()
+        .map(|layer| {
+            let count = seeds.iter().filter(|seed| seed.layer == layer).count();
+            (layer.name().to_owned(), json!(count))
+        })
+        .collect();
+    json!({
+        "type": "corpus",
+        "schema": SCHEMA_VERSION,
+        "output": output.display().to_string(),
+        "layers": layers,
+    })
+}
+
+/// `{"type": "annotations", "schema", "capture", "packets": [{"index", "bookmark",
+/// "comments": [{"author", "text"}]}]}`, the author is null when not given
+pub fn annotations(annotations: &Annotations) -> Value {
+    json!({
+        "type": "annotations",
+        "schema": SCHEMA_VERSION,
+        "capture": annotations.capture(),
+        "packets": annotations.iter().map(|(index, annotation)| json!({
+            "index": index,
+            "bookmark": annotation.bookmark,
+            "comments": annotation.comments.iter().map(|comment| json!({
+                "author": comment.author,
+                "text": comment.text,
+            })).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "index", "schema", "packets", "connections", "devices"}`, each connection with
+/// its `handle`, `le`, `address_type`, `address`, the packet indexes it was `connected` and
+/// `disconnected` at and the `reason`, each device with its `address_type`, `address`, `name`
+/// and the packet indexes it was `first_seen` and `last_seen` at
+pub fn index(index: &CaptureIndex) -> Value {
+    json!({
+        "type": "index",
+        "schema": SCHEMA_VERSION,
+        "packets": index.len(),
+        "connections": index.connections.iter().map(|connection| json!({
+            "handle": connection.handle,
+            "le": connection.le,
+            "address_type": connection.address_type,
+            "address": connection.address.to_string(),
+            "connected": connection.connected,
+            "disconnected": connection.disconnected,
+            "reason": connection.reason,
+        })).collect::<Vec<_>>(),
+        "devices": index.devices.iter().map(|device| json!({
+            "address_type": device.address_type,
+            "address": device.address.to_string(),
+            "name": device.name,
+            "first_seen": device.first_seen,
+            "last_seen": device.last_seen,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "streams", "schema", "streams", "files"}`, each stream with its `handle`, `cid`,
+/// `psm` and `dlci`, the packet index of its `first_packet`, the octets `sent` and `received`
+/// and the PDUs missing as `gaps`; `files` are the sent and received files written, or null
+pub fn streams(streams: &[ChannelStream], files: Option<&(PathBuf, PathBuf)>) -> Value {
+    json!({
+        "type": "streams",
+        "schema": SCHEMA_VERSION,
+        "streams": streams.iter().map(|stream| json!({
+            "handle": stream.handle,
+            "cid": stream.cid,
+            "psm": stream.psm(),
+            "dlci": stream.dlci,
+            "first_packet": stream.first_packet,
+            "sent": stream.sent.len(),
+            "received": stream.received.len(),
+            "gaps": stream.gaps,
+        })).collect::<Vec<_>>(),
+        "files": files.map(|(sent, received)| [sent.to_string_lossy(), received.to_string_lossy()]),
+    })
+}
+
+/// `{"type": "gatt_replays", "schema", "replays"}`, each replay with its connection `handle`,
+/// the `peer` `address_type` and `address` or null, `local_central`, the negotiated `mtu`,
+/// whether the client ran `discovery`, the discovered `attributes` types and its `steps`: the
+/// `index` of the packet of the request, the `delay_us` since the previous step, the
+/// `operation` with its `attribute` and hex `value`, the attribute `uuid` and the `outcome`
+pub fn gatt_replays(replays: &[GattReplay]) -> Value {
+    json!({
+        "type": "gatt_replays",
+        "schema": SCHEMA_VERSION,
+        "replays": replays.iter().map(|replay| json!({
+            "handle": replay.handle,
+            "peer": replay.peer.map(|(address_type, address)| json!({
+                "address_type": address_type,
+                "address": address.to_string(),
+            })),
+            "local_central": replay.local_central,
+            "mtu": replay.mtu,
+            "discovery": replay.discovery,
+            "attributes": replay.attributes.iter().map(|(attribute, uuid)| json!({
+                "attribute": attribute,
+                "uuid": uuid.to_full_string(),
+            })).collect::<Vec<_>>(),
+            "steps": replay.steps.iter().map(|step| {
+                let operation = match &step.operation {
+                    Operation::ExchangeMtu { mtu } => json!({"type": "exchange_mtu", "mtu": mtu}),
+                    Operation::Read { attribute } => json!({"type": "read", "attribute": attribute}),
+                    Operation::ReadBlob { attribute, offset } => json!({
+                        "type": "read_blob",
+                        "attribute": attribute,
+                        "offset": offset,
+                    }),
+                    Operation::Write { attribute, value, with_response } => json!({
+                        "type": if *with_response { "write_request" } else { "write_command" },
+                        "attribute": attribute,
+                        "value": hex_string(value),
+                    }),
+                    Operation::PrepareWrite { attribute, offset, value } => json!({
+                        "type": "prepare_write",
+                        "attribute": attribute,
+                        "offset": offset,
+                        "value": hex_string(value),
+                    }),
+                    Operation::ExecuteWrite { execute } => json!({
+                        "type": "execute_write",
+                        "execute": execute,
+                    }),
+                };
+                let outcome = step.outcome.as_ref().map(|outcome| match outcome {
+                    Outcome::Mtu(mtu) => json!({"mtu": mtu}),
+                    Outcome::Value(value) => json!({"value": hex_string(value)}),
+                    Outcome::Done => json!({"done": true}),
+                    Outcome::Error(code) => json!({"error": code}),
+                });
+                json!({
+                    "index": step.packet_index,
+                    "delay_us": step.delay_us,
+                    "operation": operation,
+                    "uuid": step.uuid.map(|uuid| uuid.to_full_string()),
+                    "outcome": outcome,
+                })
+            }).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "hid", "schema", "events", "typed"}`, each event with the `index` and
+/// `timestamp_us` of its packet, its `connection`, whether it came over `gatt`, and either
+/// the keyboard `usage` and whether it is `pressed`, or the pointer `buttons`, `x`, `y` and
+/// `wheel`; `typed` the text typed on each `connection`
+pub fn hid(events: &[HidEvent], typed: &[(u16, String)]) -> Value {
+    json!({
+        "type": "hid",
+        "schema": SCHEMA_VERSION,
+        "events": events.iter().map(|event| {
+            let mut value = json!({
+                "index": event.packet_index,
+                "timestamp_us": event.timestamp.saturating_sub(PacketDescription::UNIX_EPOCH_OFFSET),
+                "connection": event.connection,
+                "gatt": event.gatt,
+            });
+            match event.event {
+                InputEvent::Key { usage, pressed } => {
+                    value["usage"] = json!(usage);
+                    value["pressed"] = json!(pressed);
+                }
+                InputEvent::Pointer { buttons, x, y, wheel } => {
+                    value["buttons"] = json!(buttons);
+                    value["x"] = json!(x);
+                    value["y"] = json!(y);
+                    value["wheel"] = json!(wheel);
+                }
+            }
+            value
+        }).collect::<Vec<_>>(),
+        "typed": typed.iter().map(|(connection, text)| json!({
+            "connection": connection,
+            "text": text,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "iso", "schema", "streams"}`, each stream with its `handle`, whether it is `sent`
+/// by the Host, the packet index of its `first_packet`, the count of `sdus` and of the
+/// `lost`, `possibly_invalid`, `missing`, `late` and `incomplete` ones, and the `octets` of
+/// the valid ones
+pub fn iso(streams: &[IsoStream]) -> Value {
+    json!({
+        "type": "iso",
+        "schema": SCHEMA_VERSION,
+        "streams": streams.iter().map(|stream| json!({
+            "handle": stream.handle,
+            "sent": stream.sent,
+            "first_packet": stream.first_packet,
+            "sdus": stream.sdus,
+            "lost": stream.lost,
+            "possibly_invalid": stream.possibly_invalid,
+            "missing": stream.missing,
+            "late": stream.late,
+            "incomplete": stream.incomplete,
+            "octets": stream.octets,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "power", "schema", "connections"}`, each connection with its `handle`, the packet
+/// index of its `first_packet`, its `tx_power` levels, each with its packet `index`, whether it
+/// is of the `remote` device, its `phy`, `level`, `max_level`, `delta` and `flags`, null when
+/// unknown, and the `path_loss` zones it entered, each with its packet `index`, `path_loss` in
+/// dB, null when unknown, and `zone` name
+pub fn power(connections: &[ConnectionPower]) -> Value {
+    json!({
+        "type": "power",
+        "schema": SCHEMA_VERSION,
+        "connections": connections.iter().map(|connection| json!({
+            "handle": connection.handle,
+            "first_packet": connection.first_packet,
+            "tx_power": connection.tx_power.iter().map(|level| json!({
+                "index": level.packet_index,
+                "remote": level.remote,
+                "phy": level.phy,
+                "level": level.level,
+                "max_level": level.max_level,
+                "delta": level.delta,
+                "flags": level.flags,
+            })).collect::<Vec<_>>(),
+            "path_loss": connection.path_loss.iter().map(|zone| json!({
+                "index": zone.packet_index,
+                "path_loss": zone.path_loss,
+                "zone": PathLossThreshold::zone_name(zone.zone),
+            })).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "hfp", "schema", "sessions"}`, each session with its ACL `handle` and `address`,
+/// null when unknown, its `renegotiations` and `fallbacks`, its `negotiation` steps, each with
+/// its packet `index`, whether it is `sent` by the Host, its `message` and `codecs`, and its
+/// `voice_links`, each with the packet index of its `setup` and `complete`, its `status`, SCO
+/// `handle`, `air_mode`, the `codec` carried and the one `attempted`, its transmit `bandwidth`,
+/// `retransmission_effort`, number of `changes` and whether it is a `fallback`
+pub fn hfp(sessions: &[HfpSession]) -> Value {
+    json!({
+        "type": "hfp",
+        "schema": SCHEMA_VERSION,
+        "sessions": sessions.iter().map(|session| json!({
+            "handle": session.handle,
+            "address": session.bd_addr.map(|address| address.to_string()),
+            "first_packet": session.first_packet,
+            "renegotiations": session.renegotiations(),
+            "fallbacks": session.fallbacks(),
+            "negotiation": session.negotiation.iter().map(|step| {
+                let (message, codecs) = match &step.message {
+                    AtMessage::AvailableCodecs(codecs) => ("available_codecs", codecs.clone()),
+                    AtMessage::CodecSelection(codec) => ("codec_selection", vec![*codec]),
+                    AtMessage::CodecConfirmation(codec) => ("codec_confirmation", vec![*codec]),
+                    AtMessage::CodecConnection => ("codec_connection", vec![]),
+                };
+                json!({
+                    "index": step.packet_index,
+                    "sent": step.sent,
+                    "dlci": step.dlci,
+                    "message": message,
+                    "codecs": codecs.iter().map(|&codec| codec_name(codec)).collect::<Vec<_>>(),
+                })
+            }).collect::<Vec<_>>(),
+            "voice_links": session.voice_links.iter().map(|link| json!({
+                "setup": link.setup_packet,
+                "complete": link.complete_packet,
+                "status": link.status,
+                "handle": link.complete.filter(|_| link.is_established()).map(|complete| complete.handle),
+                "air_mode": link.complete.and_then(|complete| coding_format_name(complete.air_mode)),
+                "codec": link.codec().and_then(codec_name),
+                "attempted": link.attempted_codec().and_then(codec_name),
+                "bandwidth": link.setup.map(|setup| setup.transmit_bandwidth),
+                "retransmission_effort": link.setup.map(|setup| setup.retransmission_effort),
+                "changes": link.changes.len(),
+                "disconnect": link.disconnect_packet,
+                "fallback": link.fallback,
+            })).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "mesh", "schema", "messages"}`, each message with the packet index of its first
+/// segment, its `connection`, whether it is `sent` by the Host, its `message_type`, number of
+/// `segments`, whether it is `incomplete`, its `data` in hex and its `description`
+pub fn mesh(messages: &[Message]) -> Value {
+    json!({
+        "type": "mesh",
+        "schema": SCHEMA_VERSION,
+        "messages": messages.iter().map(|message| json!({
+            "index": message.packet_index,
+            "connection": message.connection,
+            "sent": message.sent,
+            "message_type": message.message_type.to_string(),
+            "segments": message.segments,
+            "incomplete": message.incomplete,
+            "data": hex_string(&message.data),
+            "description": mesh::describe(message.message_type, &message.data),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "firmware", "schema", "downloads"}`, each download with the packet index of its
+/// `minidriver` command, its `size`, `writes`, written `ranges` as `[start, end)` pairs, the
+/// `launch` address, `failed_writes` and `truncated`
+pub fn firmware(downloads: &[FirmwareDownload]) -> Value {
+    json!({
+        "type": "firmware",
+        "schema": SCHEMA_VERSION,
+        "downloads": downloads.iter().map(|download| json!({
+            "minidriver": download.minidriver_index,
+            "size": download.size(),
+            "writes": download.segments.len(),
+            "ranges": download.ranges(),
+            "launch": download.launch.map(|(_, address)| address),
+            "failed_writes": download.failed_writes,
+            "truncated": download.truncated,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "vendor", "schema", "records"}`, each record with the `index` and `timestamp_us`
+/// of its packet, its `source` and its `diagnostic`: a `key`, `connection_event` or `quality`
+/// object. Keys are in hex, most significant octet first.
+pub fn vendor(records: &[VendorRecord]) -> Value {
+    let diagnostic = |diagnostic: &Diagnostic| match diagnostic {
+        Diagnostic::Key { handle, kind, key } => json!({
+            "type": "key",
+            "handle": handle,
+            "kind": kind.to_string(),
+            "key": hex_string(&key.iter().rev().copied().collect::<Vec<_>>()),
+        }),
+        Diagnostic::ConnectionEvent {
+            handle,
+            counter,
+            rssi,
+            channel,
+        } => json!({
+            "type": "connection_event",
+            "handle": handle,
+            "counter": counter,
+            "rssi": rssi,
+            "channel": channel,
+        }),
+        Diagnostic::Quality(report) => json!({
+            "type": "quality",
+            "report_id": report.report_id,
+            "handle": report.handle,
+            "role": report.role,
+            "tx_power_level": report.tx_power_level,
+            "rssi": report.rssi,
+            "snr": report.snr,
+            "retransmissions": report.retransmissions,
+            "no_rx": report.no_rx,
+            "naks": report.naks,
+            "flow_off": report.flow_off,
+        }),
+    };
+    json!({
+        "type": "vendor",
+        "schema": SCHEMA_VERSION,
+        "records": records.iter().map(|record| json!({
+            "index": record.packet_index,
+            "timestamp_us": record.timestamp,
+            "source": record.source,
+            "diagnostic": diagnostic(&record.diagnostic),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// `{"type": "snoop_mode", "schema", "mode"}`
+#[cfg(feature = "adb")]
+pub fn snoop_mode(mode: SnoopMode) -> Value {
+    json!({
+        "type": "snoop_mode",
+        "schema": SCHEMA_VERSION,
+        "mode": mode.as_str(),
+    })
+}
+
+/// `{"type": "error", "schema", "message"}`
+pub fn error(message: &str) -> Value {
+    json!({
+        "type": "error",
+        "schema": SCHEMA_VERSION,
+        "message": message,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn packet_document() {
+        let original: &[u8] = include_bytes!("../../../res/btsnoop_hci_android.log");
+        let capture = btsnoop::Btsnoop::parse(&mut &original[..]).unwrap();
+        let start = capture.packets[0].description.timestamp;
+        let document = packet(1, &capture.packets[1], start, true);
+        assert_eq!(document["type"], "packet");
+        assert_eq!(document["direction"], "received");
+        assert_eq!(document["relative_s"], 0.00543);
+        assert_eq!(document["data"], "040e0401030c00");
+        assert_eq!(document["details"][0], "Opcode: Reset (0x0c03)");
+        assert_eq!(header(&capture.header)["datalink_code"], 1002);
+
+        let statistics = btsnoop::analysis::statistics::statistics(&capture);
+        let document = stats(&statistics);
+        assert_eq!(document["packet_types"][0]["name"], "Command");
+        assert_eq!(document["commands"][2]["name"], "Reset");
+        assert_eq!(document["commands"][2]["code"], 0x0c03);
+    }
+
+    #[test]
+    fn corrupt_timestamp() {
+        let original: &[u8] = include_bytes!("../../../res/btsnoop_hci_android.log");
+        let mut capture = btsnoop::Btsnoop::parse(&mut &original[..]).unwrap();
+        capture.packets[0].description.timestamp = i64::MIN;
+        let document = packet(1, &capture.packets[1], i64::MIN, true);
+        assert_eq!(document["relative_s"], i64::MAX as f64 / 1e6);
+        let document = packet(0, &capture.packets[0], i64::MIN, true);
+        assert_eq!(document["relative_s"], 0.0);
+    }
+}
@@ -0,0 +1,600 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use btsnoop::{
+    formats::{self, Format},
+    vendor::EventLayout,
+    Btsnoop, DatalinkType, Header, Packet,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[cfg(feature = "adb")]
+mod adb;
+mod annotate;
+#[cfg(all(feature = "hci-socket", target_os = "linux"))]
+mod capture;
+mod check;
+mod convert;
+mod corpus;
+#[cfg(feature = "crypto")]
+mod decrypt;
+mod dump;
+#[cfg(feature = "extcap")]
+mod extcap;
+mod extract;
+mod filter;
+mod firmware;
+mod hfp;
+mod hid;
+mod index;
+mod info;
+mod iso;
+mod json;
+mod mesh;
+mod packetlogger;
+mod play;
+mod power;
+mod replay;
+mod sequence;
+#[cfg(feature = "websocket")]
+mod serve;
+mod stats;
+mod streams;
+mod trace;
+#[cfg(feature = "tui")]
+mod tui;
+mod vendor;
+
+use dump::DumpOptions;
+use filter::{parse_number, PacketFilter};
+
+/// Inspect and edit btsnoop HCI captures
+///
+/// A file named `-` is the standard input or output, so commands can be piped into each other.
+///
+/// Exit status: 0 on success, 1 when check finds issues, 2 on errors.
+#[derive(Debug, Parser)]
+#[command(name = "btsnoop", version, arg_required_else_help = true)]
+struct Cli {
+    /// print versioned JSON documents instead of text, one per line
+    #[arg(long, global = true)]
+    json: bool,
+    #[cfg(feature = "extcap")]
+    #[command(flatten)]
+    extcap: extcap::Extcap,
+    /// only missing when run by Wireshark as an extcap
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show the header and a summary of a capture
+    Info { file: PathBuf },
+    /// Print the decoded packets
+    Dump {
+        file: PathBuf,
+        /// keep printing the packets appended to the file, like follow
+        #[arg(long)]
+        follow: bool,
+        #[command(flatten)]
+        filter: PacketFilter,
+        #[command(flatten)]
+        options: DumpOptions,
+    },
+    /// Stream the decoded packets of a capture, and the ones appended to it, as JSON over
+    /// WebSocket
+    #[cfg(feature = "websocket")]
+    Serve {
+        file: PathBuf,
+        /// address to listen on
+        #[arg(long, default_value = "127.0.0.1:8765")]
+        listen: String,
+    },
+    /// Browse a capture in the terminal
+    #[cfg(feature = "tui")]
+    View { file: PathBuf },
+    /// Print the decoded packets of a log as they are written, surviving log rotation
+    Follow {
+        file: PathBuf,
+        #[command(flatten)]
+        filter: PacketFilter,
+        #[command(flatten)]
+        options: DumpOptions,
+    },
+    /// Count the packets by type, command, event and connection
+    Stats { file: PathBuf },
+    /// List the connections and devices of a capture from its index, built and cached in a
+    /// sidecar file next to it the first time
+    Index {
+        file: PathBuf,
+        /// index the capture again even when its cached index is up to date
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Check the structure of a capture and its failed commands and ATT requests, exiting with
+    /// status 1 when there are any
+    Check { file: PathBuf },
+    /// List the byte streams of the L2CAP channels, and RFCOMM DLCIs, of a capture, and write
+    /// the one selected to raw files
+    Streams {
+        file: PathBuf,
+        /// only the channels of this connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+        /// only this channel, its channel ID on the Host of the capture
+        #[arg(long, value_parser = parse_number)]
+        cid: Option<u16>,
+        /// only this RFCOMM data link connection
+        #[arg(long)]
+        dlci: Option<u8>,
+        /// write the data of the stream selected to PREFIX.sent and PREFIX.received
+        #[arg(long, value_name = "PREFIX")]
+        output: Option<PathBuf>,
+    },
+    /// List the GATT operations of the Central of each LE connection, with their values and
+    /// the delays between them, and write a script replaying them
+    Replay {
+        file: PathBuf,
+        /// only this connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+        /// write a Python script replaying the operations of the connection selected with bleak
+        #[arg(long, value_name = "PATH")]
+        bleak: Option<PathBuf>,
+    },
+    /// Count the SDUs of the isochronous streams of a capture: lost, possibly invalid, missing
+    /// from the sequence numbers, late and incomplete
+    Iso {
+        file: PathBuf,
+        /// only the streams of this CIS or BIS handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+    },
+    /// List the transmit power levels of both ends of each LE connection and the path loss
+    /// zones it entered, from the LE Power Control reports
+    Power {
+        file: PathBuf,
+        /// only this connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+    },
+    /// Follow the HFP codec negotiation and the voice links set up after it, to tell which codec
+    /// each link carried and whether it was renegotiated or fell back to another
+    Hfp {
+        file: PathBuf,
+        /// only this ACL connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+    },
+    /// List the Bluetooth Mesh messages carried over GATT: provisioning PDUs, beacons, and the
+    /// headers of the encrypted network PDUs
+    Mesh {
+        file: PathBuf,
+        /// only the messages of this connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+    },
+    /// Reconstruct the key presses and pointer movements of the HID devices of a capture, and
+    /// the text typed on each keyboard
+    Hid {
+        file: PathBuf,
+        /// report descriptor of the devices whose report map isn't read over GATT in the
+        /// capture, e.g. from the SDP record of a classic device
+        #[arg(long)]
+        report_map: Option<PathBuf>,
+        /// only the devices of this connection handle
+        #[arg(long, value_parser = parse_number)]
+        handle: Option<u16>,
+    },
+    /// List the Broadcom firmware patch downloads of a capture
+    Firmware {
+        file: PathBuf,
+        /// write the patch of the last download as a .hcd file
+        #[arg(long)]
+        hcd: Option<PathBuf>,
+    },
+    /// List the controller diagnostics of the vendor specific events: Bluetooth Quality
+    /// Reports, and the keys and connection events of the debug events described by layouts
+    Vendor {
+        file: PathBuf,
+        /// layout of a debug event, NAME:FIELD=VALUE,... with the fields company, prefix (hex),
+        /// handle, counter, rssi, channel (offsets), key (OFFSET+LENGTH) and kind (link-key,
+        /// ltk, session or other), can be repeated
+        #[arg(long)]
+        layout: Vec<EventLayout>,
+    },
+    /// Write the data of the packets with their original timing, e.g. the H4 packets of a
+    /// capture to a serial port or a socket driving a simulation
+    Play(play::Play),
+    /// Write the HCI commands and events, ACL payloads, L2CAP and ATT PDUs of a capture to a
+    /// file each, without duplicates, as the seed corpora of fuzzers
+    Corpus {
+        file: PathBuf,
+        /// directory to write a subdirectory per layer to
+        output: PathBuf,
+        /// only these layers, all by default
+        #[arg(long, value_enum, value_delimiter = ',')]
+        layer: Vec<corpus::CorpusLayer>,
+        /// only the packets of this direction
+        #[arg(long, value_enum)]
+        direction: Option<Direction>,
+    },
+    /// Write the packets matching the filter to a new capture
+    Filter {
+        input: PathBuf,
+        output: PathBuf,
+        #[command(flatten)]
+        filter: PacketFilter,
+    },
+    /// Draw the packets of a capture as a sequence diagram between the Host, the Controller
+    /// and the Peer, with the names and key parameters of the commands, events and PDUs
+    Sequence {
+        file: PathBuf,
+        /// diagram to write, - for stdout
+        output: PathBuf,
+        /// language of the diagram, PlantUML for the .puml, .pu and .plantuml outputs and
+        /// Mermaid otherwise by default
+        #[arg(long, value_enum)]
+        format: Option<sequence::SequenceFormat>,
+        /// prefix the labels with the number of their packet
+        #[arg(long)]
+        packet_numbers: bool,
+        #[command(flatten)]
+        filter: PacketFilter,
+    },
+    /// Export a capture to the Chrome trace event format, to open it in Perfetto: a track per
+    /// connection, L2CAP channel and RFCOMM data link connection
+    Trace {
+        file: PathBuf,
+        /// trace to write, - for stdout
+        output: PathBuf,
+        /// start the timeline at the first packet instead of the Unix epoch
+        #[arg(long)]
+        relative: bool,
+        /// microseconds added to every timestamp, to line the capture up with another trace
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        offset: i64,
+    },
+    /// Write a capture in another format or with another datalink type
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// format of the input, detected from its contents or extension by default
+        #[arg(long, value_enum)]
+        from: Option<FileFormat>,
+        /// format of the output, from its extension by default
+        #[arg(long, value_enum)]
+        to: Option<FileFormat>,
+        /// datalink of the output, by default the input's for btsnoop and h4-phdr for pcap
+        #[arg(long, value_enum)]
+        datalink: Option<convert::Datalink>,
+    },
+    /// Pull or stream the snoop log of a connected Android device
+    #[cfg(feature = "adb")]
+    Adb(adb::Adb),
+    /// Capture the HCI traffic of the local controllers from the monitor socket
+    #[cfg(all(feature = "hci-socket", target_os = "linux"))]
+    Capture {
+        output: PathBuf,
+        /// only the traffic of hciN
+        #[arg(long)]
+        index: Option<u16>,
+        /// stop after this many packets
+        #[arg(long)]
+        count: Option<usize>,
+    },
+    /// Convert PacketLogger records read from stdin, writing each packet as it arrives, e.g. of
+    /// a capture PacketLogger is still writing:
+    /// `tail -c +1 -f capture.pklg | btsnoop packetlogger out.log`. Not a live macOS capture,
+    /// `log stream` output carries no HCI records
+    Packetlogger { output: PathBuf },
+    /// Decrypt the encrypted LE connections of an air sniffer pcap capture, with the keys given
+    /// and the ones found in the capture and in HCI captures of the devices
+    #[cfg(feature = "crypto")]
+    Decrypt(decrypt::Decrypt),
+    /// Bookmark and comment packets, kept in the annotations sidecar file of the capture, and
+    /// list its annotations
+    Annotate(annotate::Annotate),
+    /// Write a range of packets, by packet number, to a new capture
+    Extract {
+        input: PathBuf,
+        output: PathBuf,
+        /// first packet number to extract
+        #[arg(long, default_value_t = 0)]
+        first: usize,
+        /// last packet number to extract, included
+        #[arg(long)]
+        last: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FileFormat {
+    Btsnoop,
+    Pcap,
+    Pcapng,
+    /// Apple PacketLogger, input only
+    Pklg,
+    /// Android bug report, input only
+    Btsnooz,
+}
+
+impl From<FileFormat> for Format {
+    fn from(value: FileFormat) -> Self {
+        match value {
+            FileFormat::Btsnoop => Format::Btsnoop,
+            FileFormat::Pcap => Format::Pcap,
+            FileFormat::Pcapng => Format::Pcapng,
+            FileFormat::Pklg => Format::PacketLogger,
+            FileFormat::Btsnooz => Format::Btsnooz,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Direction {
+    /// by the Host of the capture
+    Sent,
+    /// by the Controller, received by the Host
+    Received,
+}
+
+/// check found issues
+const FINDINGS: u8 = 1;
+const ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Some(command) => run(command, cli.json),
+        #[cfg(feature = "extcap")]
+        None if cli.extcap.is_requested() => {
+            extcap::run(cli.extcap, &mut io::stdout().lock()).map(|()| ExitCode::SUCCESS)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a command is required, see --help",
+        )),
+    };
+    match result {
+        Ok(code) => code,
+        // e.g. piped into head
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            if cli.json {
+                println!("{}", json::error(&e.to_string()));
+            }
+            eprintln!("btsnoop: {}", e);
+            ExitCode::from(ERROR)
+        }
+    }
+}
+
+fn run(command: Command, json: bool) -> io::Result<ExitCode> {
+    let mut out = io::stdout().lock();
+    match command {
+        Command::Info { file } => info::run(&read(&file)?, json, &mut out),
+        Command::Dump {
+            file,
+            follow: false,
+            filter,
+            mut options,
+        } => {
+            options.json = json;
+            dump::dump(&file, filter, &options, &mut out)
+        }
+        Command::Dump {
+            file,
+            follow: true,
+            filter,
+            mut options,
+        }
+        | Command::Follow {
+            file,
+            filter,
+            mut options,
+        } => {
+            options.json = json;
+            dump::follow(&file, filter, &options, &mut out)
+        }
+        #[cfg(feature = "websocket")]
+        Command::Serve { file, listen } => serve::serve(&file, &listen),
+        #[cfg(feature = "tui")]
+        Command::View { .. } if json => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "view has no JSON output",
+        )),
+        #[cfg(feature = "tui")]
+        Command::View { file } => tui::view(read(&file)?),
+        Command::Stats { file } => stats::run(&read(&file)?, json, &mut out),
+        Command::Check { file } => return check::run(&read(&file)?, json, &mut out),
+        Command::Index { file, rebuild } => index::run(&file, rebuild, json, &mut out),
+        Command::Streams {
+            file,
+            handle,
+            cid,
+            dlci,
+            output,
+        } => streams::run(&read(&file)?, handle, cid, dlci, output, json, &mut out),
+        Command::Replay {
+            file,
+            handle,
+            bleak,
+        } => replay::run(&read(&file)?, handle, bleak.as_deref(), json, &mut out),
+        Command::Iso { file, handle } => iso::run(&read(&file)?, handle, json, &mut out),
+        Command::Power { file, handle } => power::run(&read(&file)?, handle, json, &mut out),
+        Command::Hfp { file, handle } => hfp::run(&read(&file)?, handle, json, &mut out),
+        Command::Mesh { file, handle } => mesh::run(&read(&file)?, handle, json, &mut out),
+        Command::Play(play) => play::run(play, json, &mut out),
+        Command::Corpus {
+            file,
+            output,
+            layer,
+            direction,
+        } => corpus::run(&read(&file)?, &output, layer, direction, json, &mut out),
+        Command::Hid {
+            file,
+            report_map,
+            handle,
+        } => hid::run(&read(&file)?, report_map.as_deref(), handle, json, &mut out),
+        Command::Firmware { file, hcd } => {
+            firmware::run(&read(&file)?, hcd.as_deref(), json, &mut out)
+        }
+        Command::Vendor { file, layout } => vendor::run(&read(&file)?, layout, json, &mut out),
+        Command::Filter {
+            input,
+            output,
+            filter,
+        } => filter::run(read(&input)?, &output, &filter, json, &mut out),
+        Command::Sequence {
+            file,
+            output,
+            format,
+            packet_numbers,
+            filter,
+        } => sequence::run(
+            &read(&file)?,
+            &output,
+            format,
+            packet_numbers,
+            &filter,
+            json,
+            &mut out,
+        ),
+        Command::Trace {
+            file,
+            output,
+            relative,
+            offset,
+        } => trace::run(&read(&file)?, &output, relative, offset, json, &mut out),
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+            datalink,
+        } => convert::run(&input, &output, from, to, datalink, json, &mut out),
+        Command::Extract {
+            input,
+            output,
+            first,
+            last,
+        } => extract::run(read(&input)?, &output, first, last, json, &mut out),
+        #[cfg(feature = "crypto")]
+        Command::Decrypt(decrypt) => decrypt::run(decrypt, json, &mut out),
+        Command::Annotate(annotate) => annotate::run(annotate, json, &mut out),
+        #[cfg(feature = "adb")]
+        Command::Adb(command) => adb::run(command, json, &mut out),
+        Command::Packetlogger { output } => packetlogger::run(&output, json, &mut out),
+        #[cfg(all(feature = "hci-socket", target_os = "linux"))]
+        Command::Capture {
+            output,
+            index,
+            count,
+        } => capture::run(&output, index, count, json, &mut out),
+    }
+    .map(|()| ExitCode::SUCCESS)
+}
+
+/// The `written` document of a command writing a capture, on stderr when the capture is
+/// written to stdout
+fn written<W: Write>(json: bool, output: &Path, packets: usize, out: &mut W) -> io::Result<()> {
+    if !json {
+        return Ok(());
+    }
+    let document = json::written(&output.to_string_lossy(), packets);
+    if is_std(output) {
+        eprintln!("{}", document);
+        Ok(())
+    } else {
+        writeln!(out, "{}", document)
+    }
+}
+
+fn read(path: &Path) -> io::Result<Btsnoop> {
+    read_as(path, None)
+}
+
+/// Read a capture of any format, detected from its contents or extension unless given
+fn read_as(path: &Path, format: Option<FileFormat>) -> io::Result<Btsnoop> {
+    let (format, mut reader) = open(path, format)?;
+    formats::read(&mut reader, format)
+}
+
+/// `-` is the standard input or output
+fn is_std(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Open an input and find its format. The first octets, read to detect it, are put back in
+/// front of the reader, so pipes can be read like files.
+fn open(path: &Path, format: Option<FileFormat>) -> io::Result<(Format, Box<dyn Read>)> {
+    let mut reader: Box<dyn Read> = if is_std(path) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    let mut start = vec![];
+    (&mut reader).take(8).read_to_end(&mut start)?;
+    let format = match format {
+        Some(format) => format.into(),
+        None => Format::detect(&start)
+            .or_else(|| extension_format(path))
+            .unwrap_or(Format::Btsnoop),
+    };
+    Ok((format, Box::new(io::Cursor::new(start).chain(reader))))
+}
+
+fn create(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_std(path) {
+        Ok(Box::new(io::stdout().lock()))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+fn extension_format(path: &Path) -> Option<Format> {
+    Format::from_extension(path.extension()?.to_str()?)
+}
+
+fn write(capture: &Btsnoop, path: &Path) -> io::Result<()> {
+    let mut writer = create(path)?;
+    capture.write(&mut writer)?;
+    writer.flush()
+}
+
+/// Packets are flushed as they arrive, so the file can be followed. Returns the number of
+/// packets written.
+fn write_live<I: Iterator<Item = io::Result<Packet>>>(
+    header: Header,
+    packets: I,
+    output: &Path,
+) -> io::Result<usize> {
+    let mut writer = create(output)?;
+    header.write(&mut writer)?;
+    writer.flush()?;
+    let mut count = 0;
+    for packet in packets {
+        packet?.write(&mut writer)?;
+        writer.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn first_timestamp(capture: &Btsnoop) -> i64 {
+    capture
+        .packets
+        .first()
+        .map(|p| p.description.timestamp)
+        .unwrap_or_default()
+}
+
+fn is_uart(capture: &Btsnoop) -> bool {
+    matches!(capture.header.datalink_type, DatalinkType::Uart)
+}
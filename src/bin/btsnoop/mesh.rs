@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use btsnoop::{analysis::mesh::mesh_messages, mesh, Btsnoop};
+
+use crate::json;
+
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let messages: Vec<_> = mesh_messages(capture)
+        .into_iter()
+        .filter(|message| handle.is_none_or(|handle| message.connection == handle))
+        .collect();
+    if json {
+        return writeln!(out, "{}", json::mesh(&messages));
+    }
+    for message in &messages {
+        writeln!(
+            out,
+            "#{} handle 0x{:04x} {}: {}{}",
+            message.packet_index,
+            message.connection,
+            if message.sent { "TX" } else { "RX" },
+            mesh::describe(message.message_type, &message.data),
+            if message.incomplete {
+                " (incomplete)"
+            } else {
+                ""
+            }
+        )?;
+    }
+    Ok(())
+}
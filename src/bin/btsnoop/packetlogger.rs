@@ -0,0 +1,15 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{formats::packet_logger::Reader, Header};
+
+use crate::{write_live, written};
+
+/// Convert the records read from stdin as they arrive
+pub fn run<W: Write>(output: &Path, json: bool, out: &mut W) -> io::Result<()> {
+    let packets = Reader::new(io::stdin().lock());
+    let packets = write_live(Header::default(), packets, output)?;
+    written(json, output, packets, out)
+}
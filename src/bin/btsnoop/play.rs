@@ -0,0 +1,66 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use btsnoop::{
+    playback::{write_paced, PlaybackOptions},
+    DirectionFlag,
+};
+use clap::Args;
+
+use crate::{create, json, read, written, Direction};
+
+#[derive(Debug, Args)]
+pub struct Play {
+    file: PathBuf,
+    /// file, FIFO or device to write to, - for stdout
+    #[arg(required_unless_present = "connect")]
+    output: Option<PathBuf>,
+    /// TCP address to write to instead
+    #[arg(long, conflicts_with = "output")]
+    connect: Option<String>,
+    /// multiplier of the original pace
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+    /// shorten the idle gaps longer than this many seconds
+    #[arg(long)]
+    max_gap: Option<f64>,
+    /// only the packets of this direction
+    #[arg(long, value_enum)]
+    direction: Option<Direction>,
+}
+
+pub fn run<W: Write>(play: Play, json: bool, out: &mut W) -> io::Result<()> {
+    let capture = read(&play.file)?;
+    let mut options = PlaybackOptions::new().speed(play.speed);
+    if let Some(max_gap) = play.max_gap {
+        options = options.max_gap(
+            Duration::try_from_secs_f64(max_gap)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        );
+    }
+    if let Some(direction) = play.direction {
+        options = options.direction(match direction {
+            Direction::Sent => DirectionFlag::Sent,
+            Direction::Received => DirectionFlag::Received,
+        });
+    }
+    match (play.output, play.connect) {
+        (Some(output), _) => {
+            let packets = write_paced(&capture.packets, &options, &mut create(&output)?)?;
+            written(json, &output, packets, out)
+        }
+        (None, Some(address)) => {
+            let mut stream = std::net::TcpStream::connect(&address)?;
+            let packets = write_paced(&capture.packets, &options, &mut stream)?;
+            if json {
+                writeln!(out, "{}", json::written(&address, packets))
+            } else {
+                Ok(())
+            }
+        }
+        (None, None) => Err(io::Error::new(io::ErrorKind::InvalidInput, "no output")),
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, Write};
+
+use btsnoop::{
+    analysis::power_control::power_timelines,
+    hci::power_control::{phy_name, PathLossThreshold},
+    report::format_utc,
+    Btsnoop,
+};
+
+use crate::json;
+
+/// The power levels and path loss zones of each connection, in packet order
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let connections: Vec<_> = power_timelines(capture)
+        .into_iter()
+        .filter(|connection| handle.is_none_or(|handle| connection.handle == handle))
+        .collect();
+    if json {
+        return writeln!(out, "{}", json::power(&connections));
+    }
+    for connection in &connections {
+        let mut lines: Vec<_> = connection
+            .tx_power
+            .iter()
+            .map(|level| {
+                let mut line = format!(
+                    "{} TX power {} dBm on {}",
+                    if level.remote { "remote" } else { "local" },
+                    level.level,
+                    phy_name(level.phy).unwrap_or("unknown PHY")
+                );
+                if let Some(max) = level.max_level {
+                    line.push_str(&format!(", max {} dBm", max));
+                }
+                if let Some(delta) = level.delta {
+                    line.push_str(&format!(", {:+} dB", delta));
+                }
+                (level.packet_index, level.timestamp, line)
+            })
+            .chain(connection.path_loss.iter().map(|zone| {
+                let line = format!(
+                    "path loss {} zone{}",
+                    PathLossThreshold::zone_name(zone.zone).unwrap_or("unknown"),
+                    zone.path_loss
+                        .map(|loss| format!(", {} dB", loss))
+                        .unwrap_or_default()
+                );
+                (zone.packet_index, zone.timestamp, line)
+            }))
+            .collect();
+        lines.sort_by_key(|(packet_index, _, _)| *packet_index);
+        for (packet_index, timestamp, line) in lines {
+            writeln!(
+                out,
+                "#{} {} handle 0x{:04x}: {}",
+                packet_index,
+                format_utc(timestamp),
+                connection.handle,
+                line
+            )?;
+        }
+    }
+    Ok(())
+}
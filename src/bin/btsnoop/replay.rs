@@ -0,0 +1,81 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    analysis::gatt_replay::{gatt_replays, Outcome},
+    hexdump::hex_string,
+    Btsnoop,
+};
+
+use crate::{create, is_std, json};
+
+/// The GATT operations of the connections selected, the bleak script of the only one selected
+/// written to `bleak` when given
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    bleak: Option<&Path>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let replays: Vec<_> = gatt_replays(capture)
+        .into_iter()
+        .filter(|replay| handle.is_none_or(|handle| replay.handle == handle))
+        .collect();
+    if let Some(path) = bleak {
+        let [replay] = &replays[..] else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} connections have GATT operations, select one with --handle",
+                    replays.len()
+                ),
+            ));
+        };
+        let mut script = create(path)?;
+        replay.write_bleak_script(&mut script)?;
+        script.flush()?;
+    }
+    if bleak.is_some_and(is_std) {
+        // the script went to stdout
+        return Ok(());
+    }
+    if json {
+        return writeln!(out, "{}", json::gatt_replays(&replays));
+    }
+    for replay in &replays {
+        write!(out, "handle 0x{:04x}", replay.handle)?;
+        if let Some((_, address)) = replay.peer {
+            write!(out, " {}", address)?;
+        }
+        if let Some(mtu) = replay.mtu {
+            write!(out, " MTU {}", mtu)?;
+        }
+        if replay.discovery {
+            write!(out, ", discovery")?;
+        }
+        writeln!(out)?;
+        for step in &replay.steps {
+            write!(
+                out,
+                "  #{} +{:.3} ms {}",
+                step.packet_index,
+                step.delay_us as f64 / 1000.0,
+                step.operation
+            )?;
+            if let Some(uuid) = step.uuid {
+                write!(out, " ({})", uuid)?;
+            }
+            match &step.outcome {
+                Some(Outcome::Mtu(mtu)) => writeln!(out, " -> MTU {}", mtu)?,
+                Some(Outcome::Value(value)) => writeln!(out, " -> {}", hex_string(value))?,
+                Some(Outcome::Done) => writeln!(out, " -> done")?,
+                Some(Outcome::Error(code)) => writeln!(out, " -> error 0x{:02x}", code)?,
+                None => writeln!(out)?,
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    sequence::{DiagramFormat, SequenceDiagram},
+    Btsnoop,
+};
+use clap::ValueEnum;
+
+use crate::{create, filter::PacketFilter, first_timestamp, is_uart, written};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SequenceFormat {
+    Mermaid,
+    Plantuml,
+}
+
+/// Write the diagram of the packets matching `filter`, in `format` or the one of the
+/// extension of `output`
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    output: &Path,
+    format: Option<SequenceFormat>,
+    packet_numbers: bool,
+    filter: &PacketFilter,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let start = first_timestamp(capture);
+    let uart = is_uart(capture);
+    let packets: Vec<_> = capture
+        .packets
+        .iter()
+        .enumerate()
+        .filter(|(_, packet)| filter.matches(start, uart, packet))
+        .collect();
+    let count = packets.len();
+    let format = match format {
+        Some(SequenceFormat::Mermaid) => DiagramFormat::Mermaid,
+        Some(SequenceFormat::Plantuml) => DiagramFormat::PlantUml,
+        None => match output.extension().and_then(|extension| extension.to_str()) {
+            Some("puml" | "pu" | "plantuml") => DiagramFormat::PlantUml,
+            _ => DiagramFormat::Mermaid,
+        },
+    };
+    let diagram = SequenceDiagram::new(packets).with_packet_numbers(packet_numbers);
+    let mut writer = create(output)?;
+    writer.write_all(diagram.render(format).as_bytes())?;
+    writer.flush()?;
+    written(json, output, count, out)
+}
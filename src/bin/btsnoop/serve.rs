@@ -0,0 +1,68 @@
+//! WebSocket server streaming the decoded packets of a capture as JSON, one text message per
+//! document. A client first receives the header document, then every packet from the start of
+//! the capture and the ones appended to it while connected. A rotation of the log is sent as
+//! `{"type": "rotated"}`, the packet indexes start over after it.
+
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use btsnoop::{follow::Follower, DatalinkType};
+use serde_json::json;
+use tungstenite::{Message, WebSocket};
+
+use crate::json;
+
+pub fn serve(path: &Path, listen: &str) -> io::Result<()> {
+    // fail early on a missing file
+    Follower::open(path)?;
+    let listener = TcpListener::bind(listen)?;
+    eprintln!(
+        "serving {} on ws://{}",
+        path.display(),
+        listener.local_addr()?
+    );
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().ok();
+            if let Err(e) = client(stream, path) {
+                eprintln!("{:?}: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Stream the capture to one client, until it disconnects
+fn client(stream: TcpStream, path: PathBuf) -> io::Result<()> {
+    let mut socket = tungstenite::accept(stream).map_err(io::Error::other)?;
+    let mut follower = Follower::open(&path)?;
+    send(&mut socket, json::header(follower.header()))?;
+    let mut start = None;
+    let mut rotations = 0;
+    let mut index = 0;
+    loop {
+        let packet = follower.next_packet()?;
+        if follower.rotations() != rotations {
+            rotations = follower.rotations();
+            index = 0;
+            send(&mut socket, json!({ "type": "rotated" }))?;
+            send(&mut socket, json::header(follower.header()))?;
+        }
+        let start = *start.get_or_insert(packet.description.timestamp);
+        let uart = matches!(follower.header().datalink_type, DatalinkType::Uart);
+        send(&mut socket, json::packet(index, &packet, start, uart))?;
+        index += 1;
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, document: serde_json::Value) -> io::Result<()> {
+    socket
+        .send(Message::text(document.to_string()))
+        .map_err(io::Error::other)
+}
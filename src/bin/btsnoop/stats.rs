@@ -0,0 +1,83 @@
+use std::io::{self, Write};
+
+use btsnoop::{
+    analysis::statistics::{packet_type_name, statistics, Count},
+    hci::{event_code_name, opcode_name, LeMetaEvent},
+    Btsnoop,
+};
+
+use crate::json;
+
+pub fn run<W: Write>(capture: &Btsnoop, json: bool, out: &mut W) -> io::Result<()> {
+    let statistics = statistics(capture);
+    if json {
+        return writeln!(out, "{}", json::stats(&statistics));
+    }
+    let mut table = |title: &str, rows: Vec<(String, &Count)>| -> io::Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            "{:<60} {:>8} {:>8} {:>10}",
+            title, "sent", "received", "bytes"
+        )?;
+        for (name, count) in rows {
+            writeln!(
+                out,
+                "  {:<58} {:>8} {:>8} {:>10}",
+                name, count.sent, count.received, count.bytes
+            )?;
+        }
+        writeln!(out)
+    };
+    table(
+        "Packet type",
+        statistics
+            .packet_types
+            .iter()
+            .map(|(t, c)| (packet_type_name(*t).to_string(), c))
+            .collect(),
+    )?;
+    table(
+        "Command",
+        statistics
+            .commands
+            .iter()
+            .map(|(opcode, c)| {
+                let name = opcode_name(*opcode).unwrap_or("Unknown");
+                (format!("{} (0x{:04X})", name, opcode), c)
+            })
+            .collect(),
+    )?;
+    table(
+        "Event",
+        statistics
+            .events
+            .iter()
+            .map(|(code, c)| {
+                let name = event_code_name(*code).unwrap_or("Unknown");
+                (format!("{} (0x{:02X})", name, code), c)
+            })
+            .collect(),
+    )?;
+    table(
+        "LE Meta subevent",
+        statistics
+            .le_subevents
+            .iter()
+            .map(|(code, c)| {
+                let name = LeMetaEvent::subevent_name(*code).unwrap_or("Unknown");
+                (format!("{} (0x{:02X})", name, code), c)
+            })
+            .collect(),
+    )?;
+    table(
+        "ACL handle",
+        statistics
+            .acl_handles
+            .iter()
+            .map(|(handle, c)| (format!("0x{:04X}", handle), c))
+            .collect(),
+    )
+}
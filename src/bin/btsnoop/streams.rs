@@ -0,0 +1,71 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use btsnoop::{analysis::streams::channel_streams, Btsnoop};
+
+use crate::json;
+
+/// The streams of the channels selected, the data of the only one selected written to the
+/// files of `output` when given
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    handle: Option<u16>,
+    cid: Option<u16>,
+    dlci: Option<u8>,
+    output: Option<PathBuf>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let streams: Vec<_> = channel_streams(capture)
+        .into_iter()
+        .filter(|stream| {
+            handle.is_none_or(|handle| stream.handle == handle)
+                && cid.is_none_or(|cid| stream.cid == cid)
+                && dlci.is_none_or(|dlci| stream.dlci == Some(dlci))
+        })
+        .collect();
+    let files = match output {
+        Some(prefix) => {
+            let [stream] = &streams[..] else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} streams match, select one with --handle, --cid and --dlci",
+                        streams.len()
+                    ),
+                ));
+            };
+            Some(stream.write_files(prefix)?)
+        }
+        None => None,
+    };
+    if json {
+        return writeln!(out, "{}", json::streams(&streams, files.as_ref()));
+    }
+    for stream in &streams {
+        write!(
+            out,
+            "handle 0x{:04x} cid 0x{:04x}",
+            stream.handle, stream.cid
+        )?;
+        if let Some(psm) = stream.psm() {
+            write!(out, " PSM 0x{:04x}", psm)?;
+        }
+        if let Some(dlci) = stream.dlci {
+            write!(out, " DLCI {}", dlci)?;
+        }
+        write!(
+            out,
+            ": {} bytes sent, {} bytes received",
+            stream.sent.len(),
+            stream.received.len()
+        )?;
+        match stream.gaps {
+            0 => writeln!(out)?,
+            gaps => writeln!(out, ", {} PDUs missing", gaps)?,
+        }
+    }
+    Ok(())
+}
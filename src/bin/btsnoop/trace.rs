@@ -0,0 +1,26 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use btsnoop::{
+    trace_event::{write_trace, TraceOptions},
+    Btsnoop,
+};
+
+use crate::{create, written};
+
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    output: &Path,
+    relative: bool,
+    offset: i64,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let options = TraceOptions::new().relative(relative).offset(offset);
+    let mut writer = create(output)?;
+    write_trace(capture, &options, &mut writer)?;
+    writer.flush()?;
+    written(json, output, capture.packets.len(), out)
+}
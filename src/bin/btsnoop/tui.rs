@@ -0,0 +1,331 @@
+//! Terminal viewer: the packet list, the decoded layers and hex dump of the selected packet,
+//! incremental search and a filter on the packet summaries.
+
+use std::io;
+
+use btsnoop::{Btsnoop, DatalinkType};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::dump::{self, BLUE, CYAN, GREEN, MAGENTA, RED};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Search(String),
+    Filter(String),
+}
+
+struct App {
+    capture: Btsnoop,
+    uart: bool,
+    /// one line summaries with their color, and lowercase for matching
+    summaries: Vec<(String, &'static str)>,
+    lowercase: Vec<String>,
+    /// packet indexes shown, the ones matching the filter
+    rows: Vec<usize>,
+    /// position in the rows
+    selected: usize,
+    /// first row shown
+    offset: usize,
+    detail_scroll: u16,
+    mode: Mode,
+    search: String,
+    filter: String,
+    status: String,
+}
+
+impl App {
+    fn new(capture: Btsnoop) -> Self {
+        let uart = matches!(capture.header.datalink_type, DatalinkType::Uart);
+        let summaries: Vec<_> = capture
+            .packets
+            .iter()
+            .map(|packet| {
+                if uart {
+                    dump::summary(packet)
+                } else {
+                    (format!("{} bytes", packet.data.0.len()), "")
+                }
+            })
+            .collect();
+        let lowercase = summaries.iter().map(|(s, _)| s.to_lowercase()).collect();
+        let rows = (0..capture.packets.len()).collect();
+        Self {
+            capture,
+            uart,
+            summaries,
+            lowercase,
+            rows,
+            selected: 0,
+            offset: 0,
+            detail_scroll: 0,
+            mode: Mode::Browse,
+            search: String::new(),
+            filter: String::new(),
+            status: String::new(),
+        }
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.rows.len().saturating_sub(1));
+        self.detail_scroll = 0;
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        self.select(self.selected.saturating_add_signed(delta));
+    }
+
+    /// Keep the packets whose summary contains the filter, case insensitive. The selected
+    /// packet stays selected when it matches.
+    fn apply_filter(&mut self, filter: &str) {
+        let filter = filter.to_lowercase();
+        let current = self.rows.get(self.selected).copied();
+        self.rows = (0..self.capture.packets.len())
+            .filter(|i| self.lowercase[*i].contains(&filter))
+            .collect();
+        let selected = current
+            .and_then(|current| self.rows.iter().position(|i| *i >= current))
+            .unwrap_or(0);
+        self.select(selected);
+        self.filter = filter;
+        self.status = format!("{} of {} packets", self.rows.len(), self.summaries.len());
+    }
+
+    /// Select the next row whose summary contains the query, from the selected one included
+    /// when `from_selected`, wrapping around
+    fn search(&mut self, query: &str, forward: bool, from_selected: bool) -> bool {
+        let query = query.to_lowercase();
+        let len = self.rows.len();
+        if len == 0 || query.is_empty() {
+            return false;
+        }
+        let start = if from_selected { 0 } else { 1 };
+        for step in start..len + start {
+            let position = if forward {
+                (self.selected + step) % len
+            } else {
+                (self.selected + len * 2 - step) % len
+            };
+            if self.lowercase[self.rows[position]].contains(&query) {
+                self.select(position);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns false to quit
+    fn handle_key(&mut self, key: KeyEvent, page: usize) -> bool {
+        match self.mode.clone() {
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return false
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                KeyCode::PageDown | KeyCode::Char(' ') => self.move_by(page as isize),
+                KeyCode::PageUp => self.move_by(-(page as isize)),
+                KeyCode::Home | KeyCode::Char('g') => self.select(0),
+                KeyCode::End | KeyCode::Char('G') => self.select(usize::MAX),
+                KeyCode::Char('J') => self.detail_scroll = self.detail_scroll.saturating_add(1),
+                KeyCode::Char('K') => self.detail_scroll = self.detail_scroll.saturating_sub(1),
+                KeyCode::Char('/') => self.mode = Mode::Search(String::new()),
+                KeyCode::Char('f') => self.mode = Mode::Filter(self.filter.clone()),
+                KeyCode::Char('n') | KeyCode::Char('N') => {
+                    let search = self.search.clone();
+                    if !self.search(&search, key.code == KeyCode::Char('n'), false) {
+                        self.status = format!("{:?} not found", search);
+                    }
+                }
+                _ => {}
+            },
+            Mode::Search(mut query) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => {
+                    self.search = query;
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    self.mode = Mode::Search(query);
+                }
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    // incremental, from the selected packet
+                    if !self.search(&query, true, true) {
+                        self.status = format!("{:?} not found", query);
+                    }
+                    self.mode = Mode::Search(query);
+                }
+                _ => {}
+            },
+            Mode::Filter(mut filter) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => {
+                    self.apply_filter(&filter);
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    filter.pop();
+                    self.mode = Mode::Filter(filter);
+                }
+                KeyCode::Char(c) => {
+                    filter.push(c);
+                    self.mode = Mode::Filter(filter);
+                }
+                _ => {}
+            },
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, detail_area, footer_area] = Layout::vertical([
+            Constraint::Percentage(55),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        // only the visible rows are built
+        let height = list_area.height.saturating_sub(2) as usize;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+        let start = self
+            .capture
+            .packets
+            .first()
+            .map_or(0, |p| p.description.timestamp);
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .skip(self.offset)
+            .take(height)
+            .map(|index| {
+                let packet = &self.capture.packets[*index];
+                let (summary, color) = &self.summaries[*index];
+                ListItem::new(format!(
+                    "{:>6} {:>12.6} {}",
+                    index,
+                    packet.description.timestamp.saturating_sub(start) as f64 / 1e6,
+                    summary
+                ))
+                .style(Style::default().fg(color_of(color)))
+            })
+            .collect();
+        let title = if self.filter.is_empty() {
+            " Packets ".to_string()
+        } else {
+            format!(" Packets matching {:?} ", self.filter)
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default()
+            .with_selected((!self.rows.is_empty()).then(|| self.selected - self.offset));
+        frame.render_stateful_widget(list, list_area, &mut state);
+
+        let mut lines = vec![];
+        if let Some(index) = self.rows.get(self.selected) {
+            let packet = &self.capture.packets[*index];
+            if self.uart {
+                lines.extend(dump::details(packet).into_iter().map(Line::from));
+                lines.push(Line::from(""));
+            }
+            let hexdump = packet.data.hexdump().to_string();
+            lines.extend(hexdump.lines().map(|line| Line::from(line.to_string())));
+        }
+        let detail = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Details "))
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(detail, detail_area);
+
+        let footer = match &self.mode {
+            Mode::Search(query) => format!("/{}", query),
+            Mode::Filter(filter) => format!("filter: {}", filter),
+            Mode::Browse if !self.status.is_empty() => self.status.clone(),
+            Mode::Browse => {
+                "q quit  j/k move  / search  n/N next/previous  f filter  J/K scroll details"
+                    .to_string()
+            }
+        };
+        frame.render_widget(Paragraph::new(footer), footer_area);
+    }
+}
+
+fn color_of(ansi: &str) -> Color {
+    match ansi {
+        BLUE => Color::Blue,
+        MAGENTA => Color::Magenta,
+        GREEN => Color::Green,
+        CYAN => Color::Cyan,
+        RED => Color::Red,
+        _ => Color::Reset,
+    }
+}
+
+pub fn view(capture: Btsnoop) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, App::new(capture));
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, mut app: App) -> io::Result<()> {
+    loop {
+        let mut page = 1;
+        terminal.draw(|frame| {
+            page = (frame.area().height as usize * 55 / 100)
+                .saturating_sub(2)
+                .max(1);
+            app.draw(frame)
+        })?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if !matches!(app.mode, Mode::Browse) || key.code != KeyCode::Char('n') {
+                app.status.clear();
+            }
+            if !app.handle_key(key, page) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search_and_filter() {
+        let mut file: &[u8] = include_bytes!("../../../res/btsnoop_hci_android.log");
+        let mut app = App::new(Btsnoop::parse(&mut file).unwrap());
+
+        assert!(app.search("set event mask", true, true));
+        assert_eq!(app.rows[app.selected], 2);
+        assert!(app.search("command complete", true, false));
+        assert_eq!(app.rows[app.selected], 3);
+        assert!(!app.search("no such packet", true, false));
+
+        app.apply_filter("HCI Command:");
+        assert!(app
+            .rows
+            .iter()
+            .all(|i| app.summaries[*i].0.contains("HCI Command:")));
+        // the selected Command Complete is filtered out, the next command is selected
+        assert_eq!(app.rows[app.selected], 4);
+    }
+}
@@ -0,0 +1,33 @@
+use std::io::{self, Write};
+
+use btsnoop::{
+    vendor::{EventLayout, VendorDecoder},
+    Btsnoop,
+};
+
+use crate::json;
+
+/// The controller diagnostics of the vendor specific events, with the debug events of
+/// `layouts`
+pub fn run<W: Write>(
+    capture: &Btsnoop,
+    layouts: Vec<EventLayout>,
+    json: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut decoder = VendorDecoder::new();
+    for layout in layouts {
+        decoder.add_layout(layout);
+    }
+    let records = decoder.extract(capture);
+    if json {
+        return writeln!(out, "{}", json::vendor(&records));
+    }
+    records.iter().try_for_each(|record| {
+        writeln!(
+            out,
+            "#{} {}: {}",
+            record.packet_index, record.source, record.diagnostic
+        )
+    })
+}
@@ -0,0 +1,190 @@
+//! Interop with [Bumble](https://github.com/google/bumble), the Python Bluetooth stack, through
+//! the serialized form of its HCI model: `bytes(packet)` of a `bumble.hci.HCI_Packet` is the
+//! H4 packet type followed by the packet, which `HCI_Packet.from_bytes` reads back, and
+//! `str(address)` of a `bumble.hci.Address` is the address most significant octet first,
+//! followed by `/P` for public addresses.
+//!
+//! ```
+//! use btsnoop::{bumble::HciPacket, UartData};
+//!
+//! // bytes(HCI_Reset_Command())
+//! let packet = HciPacket(vec![0x01, 0x03, 0x0c, 0x00]);
+//! let Ok(UartData::Command(command)) = UartData::try_from(&packet) else {
+//!     unreachable!()
+//! };
+//! assert_eq!(command.opcode.name(), Some("Reset"));
+//! assert_eq!(HciPacket::from(&command), packet);
+//! ```
+
+use std::{fmt::Display, io, str::FromStr};
+
+use crate::{
+    formats::h4_packet,
+    hci::{Acl, BdAddr, Command, Event},
+    Packet, UartData, UartPacketType,
+};
+
+/// An HCI packet as Bumble serializes it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HciPacket(pub Vec<u8>);
+
+impl HciPacket {
+    /// A packet record of a capture, for the packets of a live Bumble stack to be written or
+    /// compared with the packets of a capture
+    pub fn to_packet(&self, unix_timestamp: i64, received: bool) -> Packet {
+        h4_packet(
+            unix_timestamp,
+            received,
+            self.0.clone(),
+            self.0.len() as u32,
+        )
+    }
+}
+
+impl From<&Command<'_>> for HciPacket {
+    fn from(command: &Command<'_>) -> Self {
+        let mut data = vec![UartPacketType::Cmd as u8];
+        data.extend_from_slice(&command.opcode.raw().to_le_bytes());
+        data.push(command.params.len() as u8);
+        data.extend_from_slice(command.params);
+        Self(data)
+    }
+}
+
+impl From<&Event<'_>> for HciPacket {
+    fn from(event: &Event<'_>) -> Self {
+        let mut data = vec![
+            UartPacketType::Evt as u8,
+            event.code,
+            event.params.len() as u8,
+        ];
+        data.extend_from_slice(event.params);
+        Self(data)
+    }
+}
+
+impl From<&Acl<'_>> for HciPacket {
+    fn from(acl: &Acl<'_>) -> Self {
+        let handle_and_flags = acl.handle
+            | (acl.packet_boundary_flag as u16) << 12
+            | (acl.broadcast_flag as u16) << 14;
+        let mut data = vec![UartPacketType::Acl as u8];
+        data.extend_from_slice(&handle_and_flags.to_le_bytes());
+        data.extend_from_slice(&(acl.data.len() as u16).to_le_bytes());
+        data.extend_from_slice(acl.data);
+        Self(data)
+    }
+}
+
+/// The packet data of an HCI UART (H4) capture
+impl TryFrom<&Packet> for HciPacket {
+    type Error = io::Error;
+
+    fn try_from(packet: &Packet) -> Result<Self, Self::Error> {
+        match packet.data.0.first().map(|t| UartPacketType::try_from(*t)) {
+            Some(Ok(_)) => Ok(Self(packet.data.0.clone())),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an HCI UART (H4) packet",
+            )),
+        }
+    }
+}
+
+/// SCO and ISO data are [`UartData::Todos`]
+impl<'a> TryFrom<&'a HciPacket> for UartData<'a> {
+    type Error = io::Error;
+
+    fn try_from(packet: &'a HciPacket) -> Result<Self, Self::Error> {
+        let (packet_type, data) = packet
+            .0
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "empty HCI packet"))?;
+        let packet_type = UartPacketType::try_from(*packet_type)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid packet type"))?;
+        Ok(match packet_type {
+            UartPacketType::Cmd => UartData::Command(Command::try_from(data)?),
+            UartPacketType::Evt => UartData::Event(Event::try_from(data)?),
+            UartPacketType::Acl => UartData::Acl(Acl::try_from(data)?),
+            UartPacketType::Sco | UartPacketType::Iso => UartData::Todos,
+        })
+    }
+}
+
+/// A device address in the string form of Bumble, e.g. `00:1A:7D:DA:71:13/P`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address {
+    /// 0x00 public, 0x01 random, as in HCI commands and events
+    pub address_type: u8,
+    pub address: BdAddr,
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address)?;
+        if self.address_type & 1 == 0 {
+            f.write_str("/P")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Address {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid Bumble address");
+        let (address, address_type) = match s.strip_suffix("/P") {
+            Some(address) => (address, 0x00),
+            None => (s, 0x01),
+        };
+        let mut octets = [0; 6];
+        let mut parts = address.split(':');
+        // most significant octet first
+        for octet in octets.iter_mut().rev() {
+            *octet =
+                u8::from_str_radix(parts.next().ok_or_else(invalid)?, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            address_type,
+            address: BdAddr(octets),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let acl = [0x02, 0x40, 0x20, 0x05, 0x00, 0x01, 0x00, 0x04, 0x00, 0x0a];
+        let packet = HciPacket(acl.to_vec());
+        let Ok(UartData::Acl(decoded)) = UartData::try_from(&packet) else {
+            panic!("not ACL data");
+        };
+        assert_eq!(decoded.handle, 0x0040);
+        assert_eq!(HciPacket::from(&decoded), packet);
+
+        let record = packet.to_packet(0, true);
+        assert!(record.description.flags.is_received());
+        assert_eq!(HciPacket::try_from(&record).unwrap(), packet);
+
+        let event = Event::try_from(&[0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00][..]).unwrap();
+        assert_eq!(HciPacket::from(&event).0[..3], [0x04, 0x0e, 0x04]);
+
+        let public: Address = "00:1A:7D:DA:71:13/P".parse().unwrap();
+        assert_eq!(public.address, BdAddr([0x13, 0x71, 0xda, 0x7d, 0x1a, 0x00]));
+        assert_eq!(public.address_type, 0x00);
+        assert_eq!(public.to_string(), "00:1A:7D:DA:71:13/P");
+        let random: Address = "C1:02:03:04:05:06".parse().unwrap();
+        assert_eq!(
+            (random.address_type, random.to_string().as_str()),
+            (0x01, "C1:02:03:04:05:06")
+        );
+        assert!("C1:02:03:04:05".parse::<Address>().is_err());
+    }
+}
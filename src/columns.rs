@@ -0,0 +1,205 @@
+//! Capture stored column by column: the timestamps, flags, lengths, H4 packet types, handles and
+//! opcodes of the packets in parallel arrays and their data in one buffer, for passes over
+//! millions of packets which only look at a few fields.
+//!
+//! ```no_run
+//! use btsnoop::columns::CaptureColumns;
+//!
+//! let columns = CaptureColumns::parse(&mut std::fs::File::open("btsnoop_hci.log")?)?;
+//! // ACL bytes received on handle 0x0040
+//! let bytes: u64 = (0..columns.len())
+//!     .filter(|i| columns.handles[*i] == Some(0x0040) && columns.flags[*i].is_received())
+//!     .map(|i| columns.included_lengths[i] as u64)
+//!     .sum();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Read};
+
+use crate::{
+    hci::Event, options::ParseOptions, Btsnoop, DatalinkType, Header, Packet, PacketData,
+    PacketDescription, PacketFlags, Reader, UartPacketType,
+};
+
+/// The packets of a capture as columns, each of them indexed by packet index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptureColumns {
+    pub header: Header,
+    pub timestamps: Vec<i64>,
+    pub flags: Vec<PacketFlags>,
+    pub original_lengths: Vec<u32>,
+    pub included_lengths: Vec<u32>,
+    pub cumulative_drops: Vec<u32>,
+    /// H4 packet type indicator, none unless the capture is HCI UART (H4)
+    pub packet_types: Vec<Option<UartPacketType>>,
+    /// connection handle of ACL, SCO and ISO data
+    pub handles: Vec<Option<u16>>,
+    /// opcode of a command, or of the command a Command Complete or Command Status event
+    /// answers
+    pub opcodes: Vec<Option<u16>>,
+    pub event_codes: Vec<Option<u8>>,
+    data: Vec<u8>,
+    /// end of the data of each packet in `data`
+    ends: Vec<usize>,
+}
+
+impl CaptureColumns {
+    pub fn new(header: Header) -> Self {
+        Self {
+            header,
+            ..Default::default()
+        }
+    }
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Self::parse_with(reader, &ParseOptions::default())
+    }
+
+    /// Parse a capture straight into columns, without keeping the packets. A truncated last
+    /// record is ignored unless parsing is strict, as [`Btsnoop::parse_with`] does.
+    pub fn parse_with<R: Read>(reader: &mut R, options: &ParseOptions) -> io::Result<Self> {
+        let mut reader = Reader::with_options(reader, options.clone())?;
+        let mut columns = Self::new(reader.header().clone());
+        for packet in &mut reader {
+            match packet {
+                Ok(packet) => columns.push(&packet.description, &packet.data.0),
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !options.strict => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(columns)
+    }
+
+    pub fn parse_from_slice(mut data: &[u8]) -> io::Result<Self> {
+        Self::parse(&mut data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// Append a packet, its included length is the length of `data`
+    pub fn push(&mut self, description: &PacketDescription, data: &[u8]) {
+        self.timestamps.push(description.timestamp);
+        self.flags.push(description.flags);
+        self.original_lengths.push(description.original_length);
+        self.included_lengths.push(data.len() as u32);
+        self.cumulative_drops.push(description.cumulative_drops);
+
+        let packet_type = data
+            .first()
+            .filter(|_| self.header.datalink_type == DatalinkType::Uart)
+            .and_then(|t| UartPacketType::try_from(*t).ok());
+        let u16_at = |offset: usize| {
+            data.get(offset..offset + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        };
+        let (mut handle, mut opcode, mut event_code) = (None, None, None);
+        match packet_type {
+            Some(UartPacketType::Cmd) => opcode = u16_at(1),
+            Some(UartPacketType::Acl | UartPacketType::Sco | UartPacketType::Iso) => {
+                handle = u16_at(1).map(|h| h & 0x0FFF)
+            }
+            Some(UartPacketType::Evt) => {
+                event_code = data.get(1).copied();
+                opcode = match event_code {
+                    Some(Event::COMMAND_COMPLETE) => u16_at(4),
+                    Some(Event::COMMAND_STATUS) => u16_at(5),
+                    _ => None,
+                };
+            }
+            None => {}
+        }
+        self.packet_types.push(packet_type);
+        self.handles.push(handle);
+        self.opcodes.push(opcode);
+        self.event_codes.push(event_code);
+
+        self.data.extend_from_slice(data);
+        self.ends.push(self.data.len());
+    }
+
+    /// The packet data of packet `index`
+    pub fn data(&self, index: usize) -> &[u8] {
+        let start = index.checked_sub(1).map_or(0, |i| self.ends[i]);
+        &self.data[start..self.ends[index]]
+    }
+
+    pub fn description(&self, index: usize) -> PacketDescription {
+        PacketDescription {
+            original_length: self.original_lengths[index],
+            included_length: self.included_lengths[index],
+            flags: self.flags[index],
+            cumulative_drops: self.cumulative_drops[index],
+            timestamp: self.timestamps[index],
+        }
+    }
+
+    pub fn packet(&self, index: usize) -> Packet {
+        Packet {
+            description: self.description(index),
+            data: PacketData(self.data(index).to_vec()),
+        }
+    }
+
+    /// Back to a capture of packets
+    pub fn to_capture(&self) -> Btsnoop {
+        Btsnoop {
+            header: self.header.clone(),
+            packets: (0..self.len()).map(|index| self.packet(index)).collect(),
+        }
+    }
+}
+
+impl From<&Btsnoop> for CaptureColumns {
+    fn from(capture: &Btsnoop) -> Self {
+        let mut columns = Self::new(capture.header.clone());
+        let bytes = capture.packets.iter().map(|p| p.data.0.len()).sum();
+        columns.data.reserve_exact(bytes);
+        for packet in &capture.packets {
+            columns.push(&packet.description, &packet.data.0);
+        }
+        columns
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse_uart_packet, UartData};
+
+    #[test]
+    fn columns() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse_from_slice(original).unwrap();
+        let columns = CaptureColumns::parse_from_slice(original).unwrap();
+        assert_eq!(columns, CaptureColumns::from(&capture));
+        assert_eq!(columns.len(), capture.packets.len());
+        assert_eq!(columns.to_capture(), capture);
+        assert_eq!(
+            columns
+                .packet_types
+                .iter()
+                .filter(|t| **t == Some(UartPacketType::Cmd))
+                .count(),
+            capture.commands().count()
+        );
+
+        for (index, packet) in capture.packets.iter().enumerate() {
+            match parse_uart_packet(packet) {
+                Ok(UartData::Command(command)) => {
+                    assert_eq!(columns.opcodes[index], Some(command.opcode.raw()))
+                }
+                Ok(UartData::Event(event)) => {
+                    assert_eq!(columns.event_codes[index], Some(event.code))
+                }
+                Ok(UartData::Acl(acl)) => assert_eq!(columns.handles[index], Some(acl.handle)),
+                _ => {}
+            }
+        }
+    }
+}
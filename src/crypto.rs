@@ -0,0 +1,198 @@
+#[cfg(feature = "crypto")]
+use aes::{
+    cipher::{BlockEncrypt, KeyInit},
+    Aes128,
+};
+
+use crate::hci::BdAddr;
+
+// data format from: Core Specification 5.4, Vol 3 Part H 2.2 Cryptographic toolbox.
+// Keys and values are most significant octet first, as in the specification's sample data.
+
+/// Security function e, AES-128 encryption of `plaintext` with `key`. Without the `crypto`
+/// feature it is a small software AES-128, fast enough to resolve private addresses.
+pub fn e(key: &[u8; 16], plaintext: &[u8; 16]) -> [u8; 16] {
+    #[cfg(feature = "crypto")]
+    {
+        let cipher = Aes128::new(key.into());
+        let mut block = (*plaintext).into();
+        cipher.encrypt_block(&mut block);
+        block.into()
+    }
+    #[cfg(not(feature = "crypto"))]
+    aes128::encrypt(key, plaintext)
+}
+
+/// Random address hash function ah, the hash of a resolvable private address
+pub fn ah(irk: &[u8; 16], prand: [u8; 3]) -> [u8; 3] {
+    let mut plaintext = [0; 16];
+    plaintext[13..].copy_from_slice(&prand);
+    let hash = e(irk, &plaintext);
+    [hash[13], hash[14], hash[15]]
+}
+
+/// Confirm value generation function c1 of LE legacy pairing, `p1` and `p2` already formed
+/// from the pairing features and the addresses of the devices
+#[cfg(feature = "crypto")]
+pub fn c1(k: &[u8; 16], r: &[u8; 16], p1: &[u8; 16], p2: &[u8; 16]) -> [u8; 16] {
+    let xor = |a: [u8; 16], b: &[u8; 16]| -> [u8; 16] { std::array::from_fn(|i| a[i] ^ b[i]) };
+    e(k, &xor(e(k, &xor(*r, p1)), p2))
+}
+
+/// Random device address whose two most significant bits are 0b01
+pub fn is_resolvable_private_address(address: &BdAddr) -> bool {
+    address.0[5] >> 6 == 0b01
+}
+
+/// Whether `address` is a resolvable private address generated from `irk`
+pub fn resolve_private_address(irk: &[u8; 16], address: &BdAddr) -> bool {
+    if !is_resolvable_private_address(address) {
+        return false;
+    }
+    // the address is little-endian: hash in the lower 24 bits, prand in the upper ones
+    let [hash0, hash1, hash2, prand0, prand1, prand2] = address.0;
+    ah(irk, [prand2, prand1, prand0]) == [hash2, hash1, hash0]
+}
+
+/// AES-128 encryption of FIPS-197, one block at a time without lookup tables but the S-box
+#[cfg(any(test, not(feature = "crypto")))]
+mod aes128 {
+    const SBOX: [u8; 256] = sbox();
+
+    /// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1
+    const fn mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0;
+        while b != 0 {
+            if b & 1 != 0 {
+                product ^= a;
+            }
+            a = xtime(a);
+            b >>= 1;
+        }
+        product
+    }
+
+    const fn xtime(a: u8) -> u8 {
+        (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 }
+    }
+
+    /// Multiplicative inverse followed by the affine transformation
+    const fn sbox() -> [u8; 256] {
+        let mut sbox = [0; 256];
+        let mut a = 0;
+        while a < 256 {
+            let mut inverse = 0u8;
+            let mut b = 1;
+            while a != 0 && b < 256 {
+                if mul(a as u8, b as u8) == 1 {
+                    inverse = b as u8;
+                }
+                b += 1;
+            }
+            sbox[a] = inverse
+                ^ inverse.rotate_left(1)
+                ^ inverse.rotate_left(2)
+                ^ inverse.rotate_left(3)
+                ^ inverse.rotate_left(4)
+                ^ 0x63;
+            a += 1;
+        }
+        sbox
+    }
+
+    /// The 11 round keys
+    fn expand(key: &[u8; 16]) -> [[u8; 16]; 11] {
+        let mut round_keys = [[0; 16]; 11];
+        round_keys[0] = *key;
+        let mut rcon = 1;
+        for round in 1..11 {
+            let previous = round_keys[round - 1];
+            let mut word = [previous[13], previous[14], previous[15], previous[12]];
+            for byte in &mut word {
+                *byte = SBOX[*byte as usize];
+            }
+            word[0] ^= rcon;
+            rcon = xtime(rcon);
+            for i in 0..16 {
+                let value = previous[i] ^ word[i % 4];
+                round_keys[round][i] = value;
+                word[i % 4] = value;
+            }
+        }
+        round_keys
+    }
+
+    /// The state is column major: byte `i` is at row `i % 4` of column `i / 4`
+    pub fn encrypt(key: &[u8; 16], plaintext: &[u8; 16]) -> [u8; 16] {
+        let round_keys = expand(key);
+        let mut state: [u8; 16] = std::array::from_fn(|i| plaintext[i] ^ round_keys[0][i]);
+        for (round, round_key) in round_keys.iter().enumerate().skip(1) {
+            // SubBytes and ShiftRows
+            let shifted: [u8; 16] =
+                std::array::from_fn(|i| SBOX[state[(i + 4 * (i % 4)) % 16] as usize]);
+            state = shifted;
+            if round < 10 {
+                for column in state.chunks_exact_mut(4) {
+                    let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+                    column[0] = mul(a0, 2) ^ mul(a1, 3) ^ a2 ^ a3;
+                    column[1] = a0 ^ mul(a1, 2) ^ mul(a2, 3) ^ a3;
+                    column[2] = a0 ^ a1 ^ mul(a2, 2) ^ mul(a3, 3);
+                    column[3] = mul(a0, 3) ^ a1 ^ a2 ^ mul(a3, 2);
+                }
+            }
+            for (byte, key) in state.iter_mut().zip(round_key) {
+                *byte ^= key;
+            }
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn software_aes() {
+        // FIPS-197 C.1
+        let key: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let plaintext: [u8; 16] = std::array::from_fn(|i| (i as u8) * 0x11);
+        let ciphertext = [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ];
+        assert_eq!(aes128::encrypt(&key, &plaintext), ciphertext);
+        assert_eq!(e(&key, &plaintext), ciphertext);
+    }
+
+    #[test]
+    fn ah_sample_data() {
+        // Core Specification 5.4, Vol 3 Part H D.7
+        let irk = [
+            0xec, 0x02, 0x34, 0xa3, 0x57, 0xc8, 0xad, 0x05, 0x34, 0x10, 0x10, 0xa6, 0x0a, 0x39,
+            0x7d, 0x9b,
+        ];
+        assert_eq!(ah(&irk, [0x70, 0x81, 0x94]), [0x0d, 0xfb, 0xaa]);
+        let address = BdAddr([0xaa, 0xfb, 0x0d, 0x94, 0x81, 0x70]);
+        assert!(resolve_private_address(&irk, &address));
+        assert!(!resolve_private_address(&[0; 16], &address));
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn c1_sample_data() {
+        // Core Specification 5.4, Vol 3 Part H 2.2.3
+        let hex = |hex: &str| -> [u8; 16] {
+            std::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+        };
+        assert_eq!(
+            c1(
+                &[0; 16],
+                &hex("5783D52156AD6F0E6388274EC6702EE0"),
+                &hex("05000800000302070710000001010001"),
+                &hex("00000000A1A2A3A4A5A6B1B2B3B4B5B6"),
+            ),
+            hex("1E1E3FEF878988EAD2A74DC5BEF13B86")
+        );
+    }
+}
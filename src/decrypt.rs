@@ -0,0 +1,821 @@
+//! Decryption of the LE link layer encryption of air sniffer captures, see
+//! [`formats::le_ll`](crate::formats::le_ll).
+//!
+//! The session key is derived from a long term key and the session key diversifiers and
+//! initialization vectors exchanged in the LL_ENC_REQ and LL_ENC_RSP PDUs, then each data
+//! channel PDU is decrypted with AES-CCM, its 32 bit MIC telling the right packet counter and
+//! sender. The long term keys come from:
+//!
+//! - the user, e.g. read from the bonding database of a device,
+//! - the HCI traffic of one of the devices: the LE Enable Encryption and LE Long Term Key
+//!   Request Reply commands, and the SMP keys distributed in plain over HCI,
+//! - the SMP keys distributed in the decrypted traffic, used for the next connections,
+//! - the short term key of a legacy pairing in the capture, from its temporary key: 0 for
+//!   Just Works, the passkey, or the out of band data. The passkey is recovered from the
+//!   pairing's confirm and random values when not given, see
+//!   [`analysis::pairing`](crate::analysis::pairing). The keys of an LE Secure Connections
+//!   pairing can't be derived from the capture.
+//!
+//! ```no_run
+//! use btsnoop::{decrypt::{Decryptor, KeyStore}, formats::le_ll, Btsnoop};
+//!
+//! let mut keys = KeyStore::new();
+//! // the phone's HCI log of the same connection
+//! keys.learn_from_hci(&Btsnoop::parse_from_slice(&std::fs::read("btsnoop_hci.log")?)?);
+//! let mut packets = le_ll::read(&mut std::fs::File::open("sniffer.pcap")?)?;
+//! let mut decryptor = Decryptor::new(keys);
+//! for packet in &mut packets {
+//!     decryptor.process(packet);
+//! }
+//! le_ll::write(&packets, &mut std::fs::File::create("decrypted.pcap")?)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Keys are least significant octet first, as in HCI and SMP PDUs.
+
+use std::collections::{HashMap, HashSet};
+
+pub use crate::analysis::pairing::passkey_tk;
+use crate::{
+    analysis::pairing::{AssociationModel, LegacyPairing, PairingFeatures},
+    crypto::e,
+    formats::le_ll::{LlPacket, LlRole},
+    hci::{BdAddr, Event},
+    l2cap::BasicFrame,
+    parse_uart_packet, Btsnoop, DirectionFlag, UartData,
+};
+
+// data format from: Core Specification 5.4, Vol 6 Part E 2 Encryption and Part B 2.4.2 LL
+// Control PDU, and Vol 3 Part H 2.2.4 s1 and 3.6 Security in Bluetooth Low Energy.
+
+/// Length of the MIC of an encrypted payload
+pub const MIC_LENGTH: usize = 4;
+
+/// Packets a sniffer may miss in a row, the counters of the next ones are tried
+const MAX_MISSED: u64 = 16;
+
+fn reversed<const N: usize>(mut data: [u8; N]) -> [u8; N] {
+    data.reverse();
+    data
+}
+
+fn xor(a: &mut [u8; 16], b: &[u8]) {
+    a.iter_mut().zip(b).for_each(|(a, b)| *a ^= b);
+}
+
+/// CBC-MAC of AES-CCM with a 4 octet MIC and a 2 octet length field
+fn ccm_mac(key: &[u8; 16], nonce: &[u8; 13], aad: &[u8], plaintext: &[u8]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[0] = (!aad.is_empty() as u8) << 6 | ((MIC_LENGTH as u8 - 2) / 2) << 3 | 1;
+    block[1..14].copy_from_slice(nonce);
+    block[14..].copy_from_slice(&(plaintext.len() as u16).to_be_bytes());
+    let mut mac = e(key, &block);
+    if !aad.is_empty() {
+        let mut header = (aad.len() as u16).to_be_bytes().to_vec();
+        header.extend_from_slice(aad);
+        for chunk in header.chunks(16) {
+            xor(&mut mac, chunk);
+            mac = e(key, &mac);
+        }
+    }
+    for chunk in plaintext.chunks(16) {
+        xor(&mut mac, chunk);
+        mac = e(key, &mac);
+    }
+    mac
+}
+
+/// CTR keystream block `index` of AES-CCM
+fn ccm_keystream(key: &[u8; 16], nonce: &[u8; 13], index: u16) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[0] = 1;
+    block[1..14].copy_from_slice(nonce);
+    block[14..].copy_from_slice(&index.to_be_bytes());
+    e(key, &block)
+}
+
+fn ccm_ctr(key: &[u8; 16], nonce: &[u8; 13], data: &mut [u8]) {
+    for (index, chunk) in data.chunks_mut(16).enumerate() {
+        let keystream = ccm_keystream(key, nonce, index as u16 + 1);
+        chunk.iter_mut().zip(keystream).for_each(|(d, k)| *d ^= k);
+    }
+}
+
+/// AES-CCM encryption as the link layer does it, the ciphertext followed by the MIC. The key
+/// is most significant octet first, as [`e`] takes it.
+pub fn ccm_encrypt(key: &[u8; 16], nonce: &[u8; 13], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut mic = ccm_mac(key, nonce, aad, plaintext);
+    xor(&mut mic, &ccm_keystream(key, nonce, 0));
+    let mut data = plaintext.to_vec();
+    ccm_ctr(key, nonce, &mut data);
+    data.extend_from_slice(&mic[..MIC_LENGTH]);
+    data
+}
+
+/// The plaintext of a ciphertext followed by its MIC, none when the MIC does not match
+pub fn ccm_decrypt(key: &[u8; 16], nonce: &[u8; 13], aad: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let length = data.len().checked_sub(MIC_LENGTH)?;
+    let mut plaintext = data[..length].to_vec();
+    ccm_ctr(key, nonce, &mut plaintext);
+    let mut mic = ccm_mac(key, nonce, aad, &plaintext);
+    xor(&mut mic, &ccm_keystream(key, nonce, 0));
+    (mic[..MIC_LENGTH] == data[length..]).then_some(plaintext)
+}
+
+/// The short term key of a legacy pairing, s1(TK, Srand, Mrand)
+pub fn stk(tk: &[u8; 16], srand: &[u8; 16], mrand: &[u8; 16]) -> [u8; 16] {
+    // r' is the least significant halves of Srand then Mrand, most significant octet first
+    let mut r = [0; 16];
+    r[..8].copy_from_slice(&mrand[..8]);
+    r[8..].copy_from_slice(&srand[..8]);
+    reversed(e(&reversed(*tk), &reversed(r)))
+}
+
+/// The session key and initialization vector of an encrypted link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionKey {
+    /// most significant octet first
+    pub sk: [u8; 16],
+    pub iv: [u8; 8],
+}
+
+impl SessionKey {
+    /// From the long term key and the SKD and IV halves of LL_ENC_REQ (Central) and
+    /// LL_ENC_RSP (Peripheral)
+    pub fn derive(
+        ltk: &[u8; 16],
+        skd_central: &[u8; 8],
+        skd_peripheral: &[u8; 8],
+        iv_central: &[u8; 4],
+        iv_peripheral: &[u8; 4],
+    ) -> Self {
+        let mut skd = [0; 16];
+        skd[..8].copy_from_slice(skd_central);
+        skd[8..].copy_from_slice(skd_peripheral);
+        let mut iv = [0; 8];
+        iv[..4].copy_from_slice(iv_central);
+        iv[4..].copy_from_slice(iv_peripheral);
+        SessionKey {
+            sk: e(&reversed(*ltk), &reversed(skd)),
+            iv,
+        }
+    }
+
+    /// The 39 bit packet counter and the direction, then the IV
+    pub fn nonce(&self, counter: u64, sender: LlRole) -> [u8; 13] {
+        let mut nonce = [0; 13];
+        nonce[..5].copy_from_slice(&counter.to_le_bytes()[..5]);
+        nonce[4] &= 0x7F;
+        if sender == LlRole::Central {
+            nonce[4] |= 0x80;
+        }
+        nonce[5..].copy_from_slice(&self.iv);
+        nonce
+    }
+
+    /// The encrypted payload of a data channel PDU and its MIC. Its header's length must
+    /// include the MIC.
+    pub fn encrypt(&self, counter: u64, sender: LlRole, header: u8, payload: &[u8]) -> Vec<u8> {
+        let nonce = self.nonce(counter, sender);
+        ccm_encrypt(&self.sk, &nonce, &[header & 0xE3], payload)
+    }
+
+    pub fn decrypt(
+        &self,
+        counter: u64,
+        sender: LlRole,
+        header: u8,
+        payload: &[u8],
+    ) -> Option<Vec<u8>> {
+        let nonce = self.nonce(counter, sender);
+        // NESN, SN and MD are not authenticated
+        ccm_decrypt(&self.sk, &nonce, &[header & 0xE3], payload)
+    }
+}
+
+/// A long term key and, when known, the EDIV and Rand it is used with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ltk {
+    pub key: [u8; 16],
+    pub ediv: Option<u16>,
+    pub rand: Option<[u8; 8]>,
+}
+
+/// The keys to try on the encrypted links
+#[derive(Debug, Clone, Default)]
+pub struct KeyStore {
+    ltks: Vec<Ltk>,
+    tks: Vec<[u8; 16]>,
+}
+
+impl KeyStore {
+    /// The temporary key of Just Works, 0, is always tried
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_ltk(&mut self, key: [u8; 16], ediv: Option<u16>, rand: Option<[u8; 8]>) {
+        let ltk = Ltk { key, ediv, rand };
+        if !self.ltks.contains(&ltk) {
+            self.ltks.push(ltk);
+        }
+    }
+
+    /// A temporary key of legacy pairing, see [`passkey_tk`]
+    pub fn add_tk(&mut self, tk: [u8; 16]) {
+        if !self.tks.contains(&tk) {
+            self.tks.push(tk);
+        }
+    }
+
+    pub fn ltks(&self) -> &[Ltk] {
+        &self.ltks
+    }
+
+    /// Learn the keys an HCI capture shows in plain: the LE Enable Encryption and LE Long
+    /// Term Key Request Reply commands and the SMP keys distributed
+    pub fn learn_from_hci(&mut self, capture: &Btsnoop) {
+        // (EDIV, Rand) of the LE Long Term Key Request events by connection handle
+        let mut requests = HashMap::new();
+        let mut distributed = None;
+        for packet in capture {
+            match parse_uart_packet(packet) {
+                Ok(UartData::Command(command)) => {
+                    let params = command.params;
+                    match command.opcode.raw() {
+                        // LE Enable Encryption
+                        0x2019 if params.len() >= 28 => self.add_ltk(
+                            params[12..28].try_into().unwrap_or_default(),
+                            Some(u16::from_le_bytes([params[10], params[11]])),
+                            params[2..10].try_into().ok(),
+                        ),
+                        // LE Long Term Key Request Reply
+                        0x201A if params.len() >= 18 => {
+                            let handle = u16::from_le_bytes([params[0], params[1]]);
+                            let request: Option<(u16, [u8; 8])> = requests.remove(&handle);
+                            self.add_ltk(
+                                params[2..18].try_into().unwrap_or_default(),
+                                request.map(|(ediv, _)| ediv),
+                                request.map(|(_, rand)| rand),
+                            )
+                        }
+                        _ => {}
+                    }
+                }
+                // LE Long Term Key Request
+                Ok(UartData::Event(event))
+                    if event.code == Event::LE_META
+                        && event.params.len() >= 13
+                        && event.params[0] == 0x05 =>
+                {
+                    let params = event.params;
+                    let handle = u16::from_le_bytes([params[1], params[2]]);
+                    let rand: [u8; 8] = params[3..11].try_into().unwrap_or_default();
+                    requests.insert(handle, (u16::from_le_bytes([params[11], params[12]]), rand));
+                }
+                Ok(UartData::Acl(acl)) if acl.packet_boundary_flag.is_start() => {
+                    if let Ok(frame) = BasicFrame::try_from(acl.data) {
+                        if frame.channel_id == BasicFrame::SMP_CID {
+                            let sent = packet.description.flags.direction() == DirectionFlag::Sent;
+                            self.smp(frame.payload, &mut distributed, (acl.handle, sent));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The keys distributed by SMP, Encryption Information followed by Central Identification
+    fn smp<T: PartialEq>(&mut self, pdu: &[u8], pending: &mut Option<(T, [u8; 16])>, from: T) {
+        match pdu.first() {
+            Some(0x06) if pdu.len() >= 17 => {
+                *pending = Some((from, pdu[1..17].try_into().unwrap_or_default()));
+            }
+            Some(0x07) if pdu.len() >= 11 => {
+                if let Some((_, key)) = pending.take_if(|(sender, _)| *sender == from) {
+                    self.add_ltk(
+                        key,
+                        Some(u16::from_le_bytes([pdu[1], pdu[2]])),
+                        pdu[3..11].try_into().ok(),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The keys to try for an encryption started with `ediv` and `rand`, the short term keys
+    /// of a legacy pairing too for EDIV and Rand 0
+    fn candidates(&self, ediv: u16, rand: [u8; 8], pairing: &Pairing) -> Vec<[u8; 16]> {
+        let mut keys = vec![];
+        if ediv == 0 && rand == [0; 8] && !pairing.secure_connections {
+            if let (Some(mrand), Some(srand)) = (pairing.mrand, pairing.srand) {
+                for tk in std::iter::once([0; 16]).chain(self.tks.iter().copied()) {
+                    keys.push(stk(&tk, &srand, &mrand));
+                }
+            }
+        }
+        let exact = |ltk: &&Ltk| ltk.ediv == Some(ediv) && ltk.rand == Some(rand);
+        keys.extend(self.ltks.iter().filter(exact).map(|ltk| ltk.key));
+        keys.extend(
+            self.ltks
+                .iter()
+                .filter(|ltk| !exact(ltk))
+                .map(|ltk| ltk.key),
+        );
+        let mut seen = HashSet::new();
+        keys.retain(|key| seen.insert(*key));
+        keys
+    }
+}
+
+/// What [`Decryptor::process`] did with a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decryption {
+    /// not encrypted, or already decrypted by the sniffer
+    Plaintext,
+    Decrypted,
+    /// encrypted with a key not known, or the packets of too many counters were missed
+    Failed,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Pairing {
+    secure_connections: bool,
+    request: Option<PairingFeatures>,
+    response: Option<PairingFeatures>,
+    mconfirm: Option<[u8; 16]>,
+    mrand: Option<[u8; 16]>,
+    srand: Option<[u8; 16]>,
+}
+
+/// The packet counter of the next packet of a sender, and the SN and counter of its last one
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    next: u64,
+    last: Option<(bool, u64)>,
+}
+
+/// The fields of an LL_ENC_REQ
+#[derive(Debug, Clone, Copy)]
+struct EncryptionRequest {
+    rand: [u8; 8],
+    ediv: u16,
+    skd_central: [u8; 8],
+    iv_central: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+struct Link {
+    request: Option<EncryptionRequest>,
+    /// the session keys of the keys which may be used, until one decrypts a packet
+    candidates: Vec<SessionKey>,
+    session: Option<SessionKey>,
+    counters: [Counter; 2],
+    pairing: Pairing,
+    distributed: Option<(Option<LlRole>, [u8; 16])>,
+    /// address type and address of the initiator and of the advertiser, from the CONNECT_IND
+    addresses: Option<((u8, BdAddr), (u8, BdAddr))>,
+}
+
+impl Link {
+    /// The passkey of the legacy pairing in progress, once its Mconfirm and Mrand are known
+    fn crack(&self) -> Option<u32> {
+        let pairing = &self.pairing;
+        if pairing.secure_connections {
+            return None;
+        }
+        let ((initiator, responder), request, response) =
+            (self.addresses?, pairing.request?, pairing.response?);
+        if !matches!(
+            AssociationModel::select(&request, &response),
+            AssociationModel::JustWorks | AssociationModel::PasskeyEntry
+        ) {
+            return None;
+        }
+        let legacy = LegacyPairing {
+            request,
+            response,
+            initiator,
+            responder,
+        };
+        legacy.crack(&pairing.mconfirm?, &pairing.mrand?)
+    }
+
+    fn decrypt(&mut self, packet: &LlPacket) -> Option<(Vec<u8>, LlRole)> {
+        let roles = match packet.sender {
+            Some(role) => vec![role],
+            None => vec![LlRole::Central, LlRole::Peripheral],
+        };
+        let sessions = match self.session {
+            Some(session) => vec![session],
+            None => self.candidates.clone(),
+        };
+        for role in roles {
+            let counter = &mut self.counters[role as usize];
+            // a retransmission has the counter of the last packet, then there may be missed ones
+            let retransmission = counter
+                .last
+                .filter(|(sn, _)| *sn == packet.sn())
+                .map(|(_, c)| c);
+            let counters = retransmission
+                .into_iter()
+                .chain(counter.next..counter.next + MAX_MISSED);
+            for c in counters {
+                for session in &sessions {
+                    let Some(plaintext) =
+                        session.decrypt(c, role, packet.header[0], &packet.payload)
+                    else {
+                        continue;
+                    };
+                    counter.next = counter.next.max(c + 1);
+                    counter.last = Some((packet.sn(), c));
+                    self.session = Some(*session);
+                    self.candidates.clear();
+                    return Some((plaintext, role));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Follows the connections of an air sniffer capture and decrypts their encrypted packets
+#[derive(Debug, Clone, Default)]
+pub struct Decryptor {
+    keys: KeyStore,
+    /// by access address
+    links: HashMap<u32, Link>,
+    passkeys: Vec<(u32, u32)>,
+}
+
+impl Decryptor {
+    pub fn new(keys: KeyStore) -> Self {
+        Self {
+            keys,
+            links: HashMap::new(),
+            passkeys: vec![],
+        }
+    }
+
+    /// The keys given and the ones learned from the capture
+    pub fn keys(&self) -> &KeyStore {
+        &self.keys
+    }
+
+    /// The access address of each legacy pairing whose passkey was recovered, and the
+    /// passkey: 0 for Just Works
+    pub fn passkeys(&self) -> &[(u32, u32)] {
+        &self.passkeys
+    }
+
+    /// Decrypt the next packet of the capture in place, replacing its encrypted payload with
+    /// the plaintext followed by the MIC and marking it decrypted. The sender of a decrypted
+    /// packet is known from its nonce.
+    pub fn process(&mut self, packet: &mut LlPacket) -> Decryption {
+        if packet.is_advertising() {
+            // CONNECT_IND: InitA, AdvA, then the access address of the new connection
+            if packet.advertising_pdu_type() == 0x05 && packet.payload.len() >= 16 {
+                let payload = &packet.payload;
+                let access_address =
+                    u32::from_le_bytes(payload[12..16].try_into().unwrap_or_default());
+                // TxAdd and RxAdd, the types of InitA and AdvA
+                let initiator = (
+                    packet.header[0] >> 6 & 1,
+                    BdAddr(payload[..6].try_into().unwrap_or_default()),
+                );
+                let advertiser = (
+                    packet.header[0] >> 7,
+                    BdAddr(payload[6..12].try_into().unwrap_or_default()),
+                );
+                let link = Link {
+                    addresses: Some((initiator, advertiser)),
+                    ..Default::default()
+                };
+                self.links.insert(access_address, link);
+            }
+            return Decryption::Plaintext;
+        }
+        if packet.payload.is_empty() {
+            return Decryption::Plaintext;
+        }
+        let link = self.links.entry(packet.access_address).or_default();
+        let mut decryption = Decryption::Plaintext;
+        let mut plaintext = packet.payload.clone();
+        if !packet.decrypted && (link.session.is_some() || !link.candidates.is_empty()) {
+            match link.decrypt(packet) {
+                Some((decrypted, role)) => {
+                    plaintext = decrypted;
+                    packet.payload[..plaintext.len()].copy_from_slice(&plaintext);
+                    packet.decrypted = true;
+                    packet.sender = Some(role);
+                    decryption = Decryption::Decrypted;
+                }
+                None if link.session.is_some() => return Decryption::Failed,
+                // not encrypted yet, e.g. LL_START_ENC_REQ
+                None => {}
+            }
+        } else if packet.decrypted {
+            plaintext.truncate(plaintext.len().saturating_sub(MIC_LENGTH));
+        }
+        match packet.llid() {
+            LlPacket::LLID_CONTROL => self.control(packet, &plaintext, decryption),
+            LlPacket::LLID_START => self.l2cap(packet, &plaintext),
+            _ => {}
+        }
+        decryption
+    }
+
+    fn control(&mut self, packet: &LlPacket, pdu: &[u8], decryption: Decryption) {
+        let link = self.links.entry(packet.access_address).or_default();
+        match pdu.first() {
+            // LL_TERMINATE_IND
+            Some(0x02) => *link = Link::default(),
+            // LL_ENC_REQ
+            Some(0x03) if pdu.len() >= 23 => {
+                link.request = Some(EncryptionRequest {
+                    rand: pdu[1..9].try_into().unwrap_or_default(),
+                    ediv: u16::from_le_bytes([pdu[9], pdu[10]]),
+                    skd_central: pdu[11..19].try_into().unwrap_or_default(),
+                    iv_central: pdu[19..23].try_into().unwrap_or_default(),
+                });
+                link.session = None;
+            }
+            // LL_ENC_RSP
+            Some(0x04) if pdu.len() >= 13 => {
+                let Some(request) = link.request.take() else {
+                    return;
+                };
+                let skd_peripheral = pdu[1..9].try_into().unwrap_or_default();
+                let iv_peripheral = pdu[9..13].try_into().unwrap_or_default();
+                link.candidates = self
+                    .keys
+                    .candidates(request.ediv, request.rand, &link.pairing)
+                    .iter()
+                    .map(|ltk| {
+                        SessionKey::derive(
+                            ltk,
+                            &request.skd_central,
+                            &skd_peripheral,
+                            &request.iv_central,
+                            &iv_peripheral,
+                        )
+                    })
+                    .collect();
+                link.counters = Default::default();
+            }
+            // LL_PAUSE_ENC_RSP of the Peripheral, the last encrypted PDU
+            Some(0x0B) if decryption == Decryption::Decrypted => link.session = None,
+            _ => {}
+        }
+    }
+
+    /// The SMP PDUs of a start fragment, for the random values of legacy pairing and the keys
+    /// distributed
+    fn l2cap(&mut self, packet: &LlPacket, data: &[u8]) {
+        let Ok(frame) = BasicFrame::try_from(data) else {
+            return;
+        };
+        if frame.channel_id != BasicFrame::SMP_CID {
+            return;
+        }
+        let link = self.links.entry(packet.access_address).or_default();
+        let pdu = frame.payload;
+        match pdu.first() {
+            // Pairing Request, a new pairing
+            Some(0x01) if pdu.len() >= 4 => {
+                link.pairing = Pairing {
+                    secure_connections: pdu[3] & 0x08 != 0,
+                    request: pdu
+                        .get(..7)
+                        .and_then(|pdu| pdu.try_into().ok())
+                        .map(PairingFeatures),
+                    ..Default::default()
+                }
+            }
+            // Pairing Response
+            Some(0x02) if pdu.len() >= 4 => {
+                link.pairing.secure_connections &= pdu[3] & 0x08 != 0;
+                link.pairing.response = pdu
+                    .get(..7)
+                    .and_then(|pdu| pdu.try_into().ok())
+                    .map(PairingFeatures);
+            }
+            // Pairing Confirm and Pairing Random, the Central's first
+            Some(opcode @ (0x03 | 0x04)) if pdu.len() >= 17 => {
+                let value = pdu[1..17].try_into().unwrap_or_default();
+                let (central, peripheral) = match opcode {
+                    0x03 => (&mut link.pairing.mconfirm, None),
+                    _ => (&mut link.pairing.mrand, Some(&mut link.pairing.srand)),
+                };
+                let from_central = match packet.sender {
+                    Some(role) => role == LlRole::Central,
+                    None => central.is_none(),
+                };
+                match (from_central, peripheral) {
+                    (true, _) => *central = Some(value),
+                    (false, Some(peripheral)) => *peripheral = Some(value),
+                    (false, None) => {}
+                }
+                if *opcode == 0x04 && from_central {
+                    if let Some(passkey) = link.crack() {
+                        self.keys.add_tk(passkey_tk(passkey));
+                        self.passkeys.push((packet.access_address, passkey));
+                    }
+                }
+            }
+            _ => self.keys.smp(pdu, &mut link.distributed, packet.sender),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        let mut data = [0; N];
+        for (i, octet) in data.iter_mut().enumerate() {
+            *octet = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        data
+    }
+
+    fn packet(sender: Option<LlRole>, header: u8, payload: Vec<u8>) -> LlPacket {
+        LlPacket {
+            timestamp: 0,
+            rf_channel: Some(1),
+            access_address: 0x5065_4b8e,
+            header: [header, payload.len() as u8],
+            payload,
+            crc: [0; 3],
+            sender,
+            decrypted: false,
+        }
+    }
+
+    #[test]
+    fn candidates_without_duplicates() {
+        let (key, other) = ([1; 16], [2; 16]);
+        let mut keys = KeyStore::new();
+        keys.add_ltk(other, None, None);
+        keys.add_ltk(key, None, None);
+        keys.add_ltk(key, Some(1), Some([1; 8]));
+        assert_eq!(
+            keys.candidates(1, [1; 8], &Pairing::default()),
+            vec![key, other]
+        );
+    }
+
+    #[test]
+    fn decryption() {
+        // Core Specification 5.4, Vol 6 Part C 1 Encryption sample data, most significant
+        // octet first
+        let ltk = reversed(from_hex::<16>("4C68384139F574D836BCF34E9DFB01BF"));
+        let skd_central = reversed(from_hex::<8>("ACBDCEDFE0F10213"));
+        let skd_peripheral = reversed(from_hex::<8>("0213243546576879"));
+        let iv_central = reversed(from_hex::<4>("BADCAB24"));
+        let iv_peripheral = reversed(from_hex::<4>("DEAFBABE"));
+        let session = SessionKey::derive(
+            &ltk,
+            &skd_central,
+            &skd_peripheral,
+            &iv_central,
+            &iv_peripheral,
+        );
+        assert_eq!(session.sk, from_hex("99AD1B5226A37E3E058E3B8E27C2C666"));
+        // LL_START_ENC_RSP of both devices, packet counter 0
+        let start = [0x06];
+        let central = session.encrypt(0, LlRole::Central, 0x0F, &start);
+        assert_eq!(central, from_hex::<5>("9FCDA7F448"));
+        let peripheral = session.encrypt(0, LlRole::Peripheral, 0x0F, &start);
+        assert_eq!(peripheral, from_hex::<5>("A34C13A415"));
+        assert_eq!(
+            session.decrypt(0, LlRole::Central, 0x07, &central),
+            Some(start.to_vec())
+        );
+        assert_eq!(session.decrypt(1, LlRole::Central, 0x07, &central), None);
+
+        // Vol 3 Part H D.3 s1
+        let stk = stk(
+            &[0; 16],
+            &reversed(from_hex("000F0E0D0C0B0A091122334455667788")),
+            &reversed(from_hex("010203040506070899AABBCCDDEEFF00")),
+        );
+        assert_eq!(reversed(stk), from_hex("9A1FE1F0E8B0F49B5B4216AE796DA062"));
+
+        let mut keys = KeyStore::new();
+        let ediv = 0x2474u16;
+        let rand: [u8; 8] = reversed(from_hex("ABCDEF1234567890"));
+        keys.add_ltk(ltk, Some(ediv), Some(rand));
+        let mut enc_req = vec![0x03];
+        enc_req.extend_from_slice(&rand);
+        enc_req.extend_from_slice(&ediv.to_le_bytes());
+        enc_req.extend_from_slice(&skd_central);
+        enc_req.extend_from_slice(&iv_central);
+        let mut enc_rsp = vec![0x04];
+        enc_rsp.extend_from_slice(&skd_peripheral);
+        enc_rsp.extend_from_slice(&iv_peripheral);
+        // a notification, then the keys distributed by the Peripheral
+        let notification = [
+            0x07, 0x00, 0x04, 0x00, 0x1b, 0x12, 0x00, 0x01, 0x02, 0x03, 0x04,
+        ];
+        let mut encryption_information = vec![0x11, 0x00, 0x06, 0x00, 0x06];
+        encryption_information.extend_from_slice(&[0x5a; 16]);
+        let identification = [
+            0x0b, 0x00, 0x06, 0x00, 0x07, 0x34, 0x12, 1, 2, 3, 4, 5, 6, 7, 8,
+        ];
+
+        let encrypted = |counter, sender, header, payload: &[u8]| {
+            let mut packet = packet(Some(sender), header, vec![]);
+            packet.payload = session.encrypt(counter, sender, header, payload);
+            packet.header[1] = packet.payload.len() as u8;
+            packet
+        };
+        let mut packets = vec![
+            packet(Some(LlRole::Central), 0x03, enc_req),
+            packet(Some(LlRole::Peripheral), 0x0B, enc_rsp),
+            packet(Some(LlRole::Peripheral), 0x07, vec![0x05]),
+            encrypted(0, LlRole::Central, 0x0F, &start),
+            encrypted(0, LlRole::Peripheral, 0x03, &start),
+            // counter 1 of the Peripheral missed
+            encrypted(2, LlRole::Peripheral, 0x0A, &notification),
+            encrypted(2, LlRole::Peripheral, 0x0A, &notification),
+            encrypted(3, LlRole::Peripheral, 0x02, &encryption_information),
+            encrypted(4, LlRole::Peripheral, 0x0A, &identification),
+            encrypted(9, LlRole::Peripheral, 0x0A, &notification),
+            encrypted(40, LlRole::Peripheral, 0x02, &notification),
+        ];
+        packets[6].sender = None;
+
+        let mut decryptor = Decryptor::new(keys);
+        let results: Vec<_> = packets.iter_mut().map(|p| decryptor.process(p)).collect();
+        use Decryption::*;
+        assert_eq!(
+            results,
+            [
+                Plaintext, Plaintext, Plaintext, Decrypted, Decrypted, Decrypted, Decrypted,
+                Decrypted, Decrypted, Decrypted, Failed
+            ]
+        );
+        assert_eq!(packets[6].sender, Some(LlRole::Peripheral));
+        assert_eq!(packets[5].payload[..notification.len()], notification);
+        assert!(packets[5].decrypted && !packets[10].decrypted);
+        assert_eq!(
+            decryptor.keys().ltks()[1],
+            Ltk {
+                key: [0x5a; 16],
+                ediv: Some(0x1234),
+                rand: Some([1, 2, 3, 4, 5, 6, 7, 8]),
+            }
+        );
+    }
+
+    #[test]
+    fn recovers_passkey() {
+        let initiator = BdAddr([0x11, 0x22, 0x33, 0x44, 0x55, 0xc6]);
+        let advertiser = BdAddr([0xa6, 0xa5, 0xa4, 0xa3, 0xa2, 0xa1]);
+        let request = PairingFeatures([0x01, 0x04, 0x00, 0x05, 0x10, 0x01, 0x01]);
+        let response = PairingFeatures([0x02, 0x02, 0x00, 0x05, 0x10, 0x01, 0x01]);
+        let legacy = LegacyPairing {
+            request,
+            response,
+            initiator: (1, initiator),
+            responder: (0, advertiser),
+        };
+        let mrand = [0x5a; 16];
+        let mconfirm = legacy.confirm(&passkey_tk(4321), &mrand);
+
+        // CONNECT_IND from a random address to a public one
+        let mut connect = packet(None, 0x45, vec![]);
+        connect.access_address = crate::formats::le_ll::ADVERTISING_ACCESS_ADDRESS;
+        connect.payload.extend_from_slice(&initiator.0);
+        connect.payload.extend_from_slice(&advertiser.0);
+        connect
+            .payload
+            .extend_from_slice(&0x5065_4b8eu32.to_le_bytes());
+        connect.payload.extend_from_slice(&[0; 22]);
+        let smp = |sender, pdu: &[u8]| {
+            let mut payload = (pdu.len() as u16).to_le_bytes().to_vec();
+            payload.extend_from_slice(&BasicFrame::SMP_CID.to_le_bytes());
+            payload.extend_from_slice(pdu);
+            packet(Some(sender), 0x02, payload)
+        };
+        let value = |opcode: u8, value: &[u8; 16]| [&[opcode][..], value].concat();
+        let mut packets = vec![
+            connect,
+            smp(LlRole::Central, &request.0),
+            smp(LlRole::Peripheral, &response.0),
+            smp(LlRole::Central, &value(0x03, &mconfirm)),
+            smp(LlRole::Peripheral, &value(0x03, &[0; 16])),
+            smp(LlRole::Central, &value(0x04, &mrand)),
+        ];
+        let mut decryptor = Decryptor::new(KeyStore::new());
+        for packet in &mut packets {
+            decryptor.process(packet);
+        }
+        assert_eq!(decryptor.passkeys(), [(0x5065_4b8e, 4321)]);
+        assert_eq!(decryptor.keys().tks, [passkey_tk(4321)]);
+    }
+}
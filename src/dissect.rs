@@ -0,0 +1,473 @@
+//! Decoding of the layers of HCI UART (H4) packets into a tree of labeled fields.
+//!
+//! The payloads are routed to [`Dissector`]s by what carries them: the opcode of a command,
+//! the code of an event, the L2CAP channel or protocol, the handle or GATT type of an
+//! attribute value. A [`Session`] follows the capture to know the protocol of the dynamic L2CAP
+//! channels and the type of the attributes discovered. The built-in decoders are dissectors
+//! too, registered for the same keys, so a registered dissector replaces them.
+//!
+//! ```
+//! use std::{io, sync::Arc};
+//! use btsnoop::{
+//!     dissect::{Context, Dissection, Dissector, Key, Node, Registry},
+//!     gatt::Uuid,
+//! };
+//!
+//! /// A proprietary characteristic, a temperature in hundredths of a degree
+//! struct Thermometer;
+//!
+//! impl Dissector for Thermometer {
+//!     fn name(&self) -> &str {
+//!         "thermometer"
+//!     }
+//!
+//!     fn dissect<'a>(&self, _: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+//!         let (value, remaining) = payload
+//!             .split_first_chunk::<2>()
+//!             .ok_or(io::ErrorKind::UnexpectedEof)?;
+//!         let temperature = i16::from_le_bytes(*value) as f64 / 100.0;
+//!         let mut dissection = Dissection::new(vec![Node::new(format!("{} °C", temperature))]);
+//!         dissection.remaining = remaining;
+//!         Ok(dissection)
+//!     }
+//! }
+//!
+//! let mut registry = Registry::with_builtins();
+//! let uuid = Uuid(0x1234_5678_0000_1000_8000_0080_5f9b_34fb);
+//! registry.register(Key::Gatt(uuid), Arc::new(Thermometer));
+//! let mut session = registry.session();
+//! # let capture = btsnoop::Btsnoop::default();
+//! for packet in &capture {
+//!     for line in btsnoop::dissect::lines(&session.dissect(packet)) {
+//!         println!("{}", line);
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    att,
+    gatt::{AttributeMap, Uuid},
+    hci::{Acl, DisconnectionComplete, Event},
+    hexdump::Hexdump,
+    l2cap::{BasicFrame, ChannelMap, PSM_ATT},
+    parse_uart_packet, DirectionFlag, Packet, UartData,
+};
+
+pub mod builtin;
+pub mod profiles;
+
+/// Dissectors handing their payload to each other stop at this depth
+const MAX_DEPTH: usize = 16;
+
+/// A decoded field, or a layer with its fields as children
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Node {
+    pub label: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: vec![],
+        }
+    }
+
+    pub fn with_children(mut self, children: Vec<Node>) -> Self {
+        self.children = children;
+        self
+    }
+}
+
+/// The lines of a tree, children indented by two spaces
+pub fn lines(tree: &[Node]) -> Vec<String> {
+    fn add(lines: &mut Vec<String>, depth: usize, tree: &[Node]) {
+        for node in tree {
+            lines.push(format!("{:1$}{2}", "", depth * 2, node.label));
+            add(lines, depth + 1, &node.children);
+        }
+    }
+    let mut lines = vec![];
+    add(&mut lines, 0, tree);
+    lines
+}
+
+/// [`Hexdump`] of undecoded data, a node for each line of 16 octets
+pub fn hex(data: &[u8]) -> Vec<Node> {
+    Hexdump::new(data)
+        .to_string()
+        .lines()
+        .map(Node::new)
+        .collect()
+}
+
+/// What a dissector is registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// the parameters of an HCI command, and the return parameters after the status in its
+    /// Command Complete event, e.g. of vendor specific commands (OGF 0x3F)
+    Opcode(u16),
+    /// the parameters of an HCI event
+    Event(u8),
+    /// the payload of the frames of an L2CAP channel, the fixed ones and the dynamic ones
+    /// whose protocol is unknown
+    Cid(u16),
+    /// the payload of the frames of the dynamic L2CAP channels of a protocol
+    Psm(u16),
+    /// the value of an attribute handle on any connection, written, notified, indicated or read
+    Attribute(u16),
+    /// the value of the attributes of a GATT type, once discovered
+    Gatt(Uuid),
+}
+
+/// What carried a payload, filled as the lower layers are decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub direction: DirectionFlag,
+    /// index of the packet in the capture
+    pub packet_index: usize,
+    /// the code of an event
+    pub event: Option<u8>,
+    /// the command, or the one completed
+    pub opcode: Option<u16>,
+    /// ACL connection handle
+    pub connection: Option<u16>,
+    pub channel_id: Option<u16>,
+    /// the protocol of a dynamic L2CAP channel
+    pub psm: Option<u16>,
+    /// the ATT PDU carrying a value, e.g. to tell a write from a notification
+    pub att_opcode: Option<u8>,
+    /// the attribute handle of a value
+    pub attribute: Option<u16>,
+    /// the GATT type of the attribute, once discovered
+    pub uuid: Option<Uuid>,
+}
+
+impl Context {
+    pub fn new(direction: DirectionFlag, packet_index: usize) -> Self {
+        Self {
+            direction,
+            packet_index,
+            event: None,
+            opcode: None,
+            connection: None,
+            channel_id: None,
+            psm: None,
+            att_opcode: None,
+            attribute: None,
+            uuid: None,
+        }
+    }
+}
+
+/// What a dissector decoded of a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dissection<'a> {
+    /// the header line of a layer, its tree and payload are nested under it
+    pub summary: Option<String>,
+    pub tree: Vec<Node>,
+    /// the payload carried, handed to the dissector of `next` or dumped in hex after the tree
+    pub remaining: &'a [u8],
+    pub next: Option<Key>,
+}
+
+impl<'a> Dissection<'a> {
+    /// Fields which consumed the whole payload
+    pub fn new(tree: Vec<Node>) -> Self {
+        Self {
+            summary: None,
+            tree,
+            remaining: &[],
+            next: None,
+        }
+    }
+}
+
+/// A decoder of one kind of payload. It should not panic and reports what it can't decode as
+/// an error, the payload is then dumped in hex.
+pub trait Dissector: Send + Sync {
+    /// For the Debug output of a registry
+    fn name(&self) -> &str;
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>>;
+}
+
+/// The dissectors by key. Clones share the dissectors.
+#[derive(Clone, Default)]
+pub struct Registry {
+    dissectors: HashMap<Key, Arc<dyn Dissector>>,
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.dissectors.iter().map(|(key, d)| (key, d.name())))
+            .finish()
+    }
+}
+
+impl Registry {
+    /// Without any dissector, every payload is dumped in hex
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The decoders of this crate: HCI events, the LE Direction Finding and Power Control
+    /// commands, L2CAP signaling, ATT and the values of standard GATT characteristics
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        builtin::register(&mut registry);
+        profiles::register(&mut registry);
+        registry
+    }
+
+    /// A shared registry of the built-in dissectors
+    pub fn builtins() -> &'static Registry {
+        static BUILTINS: OnceLock<Registry> = OnceLock::new();
+        BUILTINS.get_or_init(Self::with_builtins)
+    }
+
+    /// Route `key` to `dissector`, returning the one it replaces
+    pub fn register(
+        &mut self,
+        key: Key,
+        dissector: Arc<dyn Dissector>,
+    ) -> Option<Arc<dyn Dissector>> {
+        self.dissectors.insert(key, dissector)
+    }
+
+    pub fn unregister(&mut self, key: &Key) -> Option<Arc<dyn Dissector>> {
+        self.dissectors.remove(key)
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Arc<dyn Dissector>> {
+        self.dissectors.get(key)
+    }
+
+    /// Dissect the packets of a capture, in order
+    pub fn session(&self) -> Session<'_> {
+        Session {
+            registry: self,
+            channels: ChannelMap::default(),
+            attributes: AttributeMap::default(),
+            packet_index: 0,
+        }
+    }
+}
+
+/// The layers of one packet with the built-in dissectors, without what the earlier packets of
+/// its capture tell
+pub fn dissect(packet: &Packet) -> Vec<Node> {
+    Registry::builtins().session().dissect(packet)
+}
+
+/// Dissects the packets of a capture in order, following the L2CAP channels opened and the
+/// attributes discovered
+#[derive(Debug)]
+pub struct Session<'r> {
+    registry: &'r Registry,
+    channels: ChannelMap,
+    attributes: AttributeMap,
+    packet_index: usize,
+}
+
+impl Session<'_> {
+    /// The decoded layers of the next packet, below its summary line
+    pub fn dissect(&mut self, packet: &Packet) -> Vec<Node> {
+        let mut context = Context::new(packet.description.flags.direction(), self.packet_index);
+        self.packet_index += 1;
+        match parse_uart_packet(packet) {
+            Ok(UartData::Command(command)) => self.route(
+                Key::Opcode(command.opcode.raw()),
+                &context,
+                command.params,
+                0,
+            ),
+            Ok(UartData::Event(event)) => {
+                if event.code == Event::DISCONNECTION_COMPLETE {
+                    if let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..])
+                    {
+                        self.channels.disconnected(disconnection.handle);
+                        self.attributes.disconnected(disconnection.handle);
+                    }
+                }
+                self.route(Key::Event(event.code), &context, event.params, 0)
+            }
+            Ok(UartData::Acl(acl)) => self.acl(&mut context, &acl),
+            _ => hex(packet.data.0.get(1..).unwrap_or_default()),
+        }
+    }
+
+    /// The basic L2CAP frame started by ACL data and its payload, the continuing fragments
+    /// are dumped in hex
+    fn acl(&mut self, context: &mut Context, acl: &Acl) -> Vec<Node> {
+        context.connection = Some(acl.handle);
+        let frame = acl
+            .packet_boundary_flag
+            .is_start()
+            .then(|| BasicFrame::try_from(acl.data).ok())
+            .flatten();
+        let Some(frame) = frame else {
+            return hex(acl.data);
+        };
+        let cid = frame.channel_id;
+        let sent = context.direction == DirectionFlag::Sent;
+        let channel = self
+            .channels
+            .update(acl.handle, sent, context.packet_index, &frame)
+            .copied();
+        context.channel_id = Some(cid);
+        if cid != BasicFrame::SIGNALING_CID && cid != BasicFrame::LE_SIGNALING_CID {
+            context.psm = channel.map(|channel| channel.psm);
+        }
+        if cid == BasicFrame::ATT_CID || context.psm == Some(PSM_ATT) {
+            if let Ok(pdu) = att::Pdu::try_from(frame.payload) {
+                context.att_opcode = Some(pdu.opcode);
+                context.attribute = self.attributes.update(acl.handle, sent, &pdu);
+            }
+        }
+        let key = match context.psm {
+            Some(psm) if cid >= 0x0040 => Key::Psm(psm),
+            _ => Key::Cid(cid),
+        };
+        let label = format!(
+            "{}{}",
+            frame,
+            if frame.is_complete() {
+                ""
+            } else {
+                " (fragmented)"
+            }
+        );
+        vec![Node::new(label).with_children(self.route(key, context, frame.payload, 0))]
+    }
+
+    fn route(&self, key: Key, context: &Context, payload: &[u8], depth: usize) -> Vec<Node> {
+        let mut context = *context;
+        let key = match key {
+            Key::Opcode(opcode) => {
+                context.opcode = Some(opcode);
+                key
+            }
+            Key::Event(code) => {
+                context.event = Some(code);
+                key
+            }
+            Key::Attribute(attribute) => {
+                context.attribute = Some(attribute);
+                context.uuid = context
+                    .connection
+                    .and_then(|connection| self.attributes.uuid(connection, attribute));
+                match context.uuid {
+                    Some(uuid) if self.registry.get(&key).is_none() => Key::Gatt(uuid),
+                    _ => key,
+                }
+            }
+            _ => key,
+        };
+        let dissection = self
+            .registry
+            .get(&key)
+            .filter(|_| depth < MAX_DEPTH)
+            .and_then(|dissector| dissector.dissect(&context, payload).ok());
+        let Some(dissection) = dissection else {
+            return hex(payload);
+        };
+        let mut tree = dissection.tree;
+        tree.extend(match dissection.next {
+            Some(next) => self.route(next, &context, dissection.remaining, depth + 1),
+            None => hex(dissection.remaining),
+        });
+        match dissection.summary {
+            Some(summary) => vec![Node::new(summary).with_children(tree)],
+            None => tree,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{analysis::test_capture, gatt::Uuid};
+
+    struct Temperature;
+
+    impl Dissector for Temperature {
+        fn name(&self) -> &str {
+            "temperature"
+        }
+
+        fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+            let value = payload.get(..2).ok_or(io::ErrorKind::UnexpectedEof)?;
+            let mut dissection = Dissection::new(vec![Node::new(format!(
+                "Temperature: {} (handle 0x{:04x})",
+                i16::from_le_bytes([value[0], value[1]]),
+                context.attribute.unwrap_or_default()
+            ))]);
+            dissection.remaining = &payload[2..];
+            Ok(dissection)
+        }
+    }
+
+    #[test]
+    fn routing() {
+        let uuid = Uuid(0x1234_5678_0000_1000_8000_0080_5f9b_34fb);
+        let mut discovery = vec![0x05, 0x02, 0x21, 0x00];
+        discovery.extend_from_slice(&uuid.0.to_le_bytes());
+        let capture = test_capture::h4(vec![
+            // Reset
+            (0, false, vec![0x01, 0x03, 0x0c, 0x00]),
+            (1, true, test_capture::l2cap(0x40, 0x0004, &discovery)),
+            (
+                2,
+                true,
+                test_capture::l2cap(0x40, 0x0004, &[0x1b, 0x21, 0x00, 0xe8, 0x03, 0xff]),
+            ),
+        ]);
+
+        let mut registry = Registry::with_builtins();
+        let mut session = registry.session();
+        let tree: Vec<_> = capture.iter().map(|p| session.dissect(p)).collect();
+        assert!(tree[0].is_empty());
+        assert_eq!(
+            lines(&tree[2]),
+            [
+                "L2CAP: CID 0x0004 len 6",
+                "  ATT: Handle Value Notification (0x1b)",
+                "    Handle: 0x0021",
+                "    0000  e8 03 ff                                         ...",
+            ]
+        );
+
+        registry.register(Key::Gatt(uuid), Arc::new(Temperature));
+        let mut session = registry.session();
+        let tree: Vec<_> = capture.iter().map(|p| session.dissect(p)).collect();
+        assert_eq!(
+            lines(&tree[2])[2..],
+            [
+                "    Handle: 0x0021",
+                "    Temperature: 1000 (handle 0x0021)",
+                "    0000  ff                                               .",
+            ]
+        );
+        // without the discovery the type is unknown
+        assert_eq!(lines(&dissect(&capture.packets[2])).len(), 4);
+        assert_eq!(
+            lines(&registry.session().dissect(&capture.packets[2]))[3],
+            "    0000  e8 03 ff                                         ..."
+        );
+
+        let reset: Arc<dyn Dissector> = Arc::new(Temperature);
+        registry.register(Key::Opcode(0x0c03), reset);
+        assert!(registry.get(&Key::Opcode(0x0c03)).is_some());
+        assert!(format!("{:?}", registry).contains("temperature"));
+    }
+}
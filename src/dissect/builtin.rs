@@ -0,0 +1,807 @@
+//! The decoders of this crate as dissectors, registered by [`Registry::with_builtins`].
+
+use std::{io, sync::Arc};
+
+use super::{hex, Context, Dissection, Dissector, Key, Node, Registry};
+use crate::{
+    att,
+    hci::{
+        direction_finding::{cte_type_name, CteCommand, IqReport},
+        error_code_name,
+        opcode::{LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL, LE_READ_ANTENNA_INFORMATION},
+        opcode_name,
+        power_control::{
+            phy_name, PathLossThreshold, PowerControlCommand, TransmitPowerReporting,
+            TX_POWER_NOT_AVAILABLE, TX_POWER_NOT_MANAGED,
+        },
+        read_handle, CommandComplete, CommandStatus, ConnectionComplete, DisconnectionComplete,
+        Event, LeConnectionComplete, LeMetaEvent, NumberOfCompletedPackets,
+    },
+    l2cap::{signaling_code_name, BasicFrame, SignalingCommand, PSM_ATT},
+    vendor::VendorDecoder,
+};
+
+pub(super) fn register(registry: &mut Registry) {
+    let events: Arc<dyn Dissector> = Arc::new(HciEvents);
+    for code in [
+        Event::COMMAND_COMPLETE,
+        Event::COMMAND_STATUS,
+        Event::CONNECTION_COMPLETE,
+        Event::DISCONNECTION_COMPLETE,
+        Event::NUMBER_OF_COMPLETED_PACKETS,
+        Event::LE_META,
+    ] {
+        registry.register(Key::Event(code), events.clone());
+    }
+    registry.register(Key::Event(Event::VENDOR_SPECIFIC), Arc::new(VendorEvents));
+    let direction_finding: Arc<dyn Dissector> = Arc::new(DirectionFinding);
+    for opcode in CteCommand::OPCODES {
+        registry.register(Key::Opcode(opcode.raw()), direction_finding.clone());
+    }
+    let power_control: Arc<dyn Dissector> = Arc::new(PowerControl);
+    for opcode in PowerControlCommand::OPCODES {
+        registry.register(Key::Opcode(opcode.raw()), power_control.clone());
+    }
+    let att: Arc<dyn Dissector> = Arc::new(Att);
+    registry.register(Key::Cid(BasicFrame::ATT_CID), att.clone());
+    registry.register(Key::Psm(PSM_ATT), att);
+    let signaling: Arc<dyn Dissector> = Arc::new(Signaling);
+    registry.register(Key::Cid(BasicFrame::SIGNALING_CID), signaling.clone());
+    registry.register(Key::Cid(BasicFrame::LE_SIGNALING_CID), signaling);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn status(status: u8) -> Node {
+    Node::new(format!(
+        "Status: {} (0x{:02x})",
+        error_code_name(status).unwrap_or("Unknown"),
+        status
+    ))
+}
+
+pub fn opcode(opcode: u16) -> Node {
+    Node::new(format!(
+        "Opcode: {} (0x{:04x})",
+        opcode_name(opcode).unwrap_or("Unknown"),
+        opcode
+    ))
+}
+
+/// The parameters of the connection and command flow events. The return parameters of a
+/// Command Complete event after the status go to the dissector of the command's opcode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HciEvents;
+
+impl Dissector for HciEvents {
+    fn name(&self) -> &str {
+        "hci-events"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let code = context.event.ok_or_else(|| invalid("not an event"))?;
+        let mut params = payload;
+        let tree = match code {
+            Event::COMMAND_COMPLETE => {
+                let complete = CommandComplete::try_from(payload)?;
+                let opcode = complete.opcode.raw();
+                let mut dissection = Dissection::new(vec![self::opcode(opcode)]);
+                if let Some((first, rest)) = complete.return_parameters.split_first() {
+                    dissection.tree.push(status(*first));
+                    dissection.remaining = rest;
+                    dissection.next = Some(Key::Opcode(opcode));
+                }
+                return Ok(dissection);
+            }
+            Event::COMMAND_STATUS => {
+                let command_status = CommandStatus::parse(&mut params)?;
+                vec![
+                    status(command_status.status),
+                    opcode(command_status.opcode.raw()),
+                ]
+            }
+            Event::CONNECTION_COMPLETE => {
+                let complete = ConnectionComplete::parse(&mut params)?;
+                vec![
+                    status(complete.status),
+                    Node::new(format!("Handle: {}", complete.handle)),
+                    Node::new(format!("Address: {}", complete.bd_addr)),
+                    Node::new(format!("Link type: 0x{:02x}", complete.link_type)),
+                ]
+            }
+            Event::DISCONNECTION_COMPLETE => {
+                let disconnection = DisconnectionComplete::parse(&mut params)?;
+                vec![
+                    status(disconnection.status),
+                    Node::new(format!("Handle: {}", disconnection.handle)),
+                    Node::new(format!(
+                        "Reason: {} (0x{:02x})",
+                        error_code_name(disconnection.reason).unwrap_or("Unknown"),
+                        disconnection.reason
+                    )),
+                ]
+            }
+            Event::NUMBER_OF_COMPLETED_PACKETS => NumberOfCompletedPackets::parse(&mut params)?
+                .completed
+                .iter()
+                .map(|(handle, count)| Node::new(format!("Handle: {} Count: {}", handle, count)))
+                .collect(),
+            Event::LE_META => le_meta(payload)?,
+            _ => return Err(invalid("unknown event")),
+        };
+        Ok(Dissection::new(tree))
+    }
+}
+
+fn le_meta(params: &[u8]) -> io::Result<Vec<Node>> {
+    Ok(match LeMetaEvent::try_from(params)? {
+        LeMetaEvent::ConnectionComplete(complete) => vec![
+            status(complete.status),
+            Node::new(format!("Handle: {}", complete.handle)),
+            Node::new(format!(
+                "Role: {}",
+                match complete.role {
+                    LeConnectionComplete::ROLE_CENTRAL => "Central",
+                    LeConnectionComplete::ROLE_PERIPHERAL => "Peripheral",
+                    _ => "Unknown",
+                }
+            )),
+            Node::new(format!(
+                "Peer address: {} (type 0x{:02x})",
+                complete.peer_address, complete.peer_address_type
+            )),
+            Node::new(format!(
+                "Connection interval: {:.2} msec",
+                complete.connection_interval as f64 * 1.25
+            )),
+            Node::new(format!(
+                "Peripheral latency: {}",
+                complete.peripheral_latency
+            )),
+            Node::new(format!(
+                "Supervision timeout: {} msec",
+                complete.supervision_timeout as u32 * 10
+            )),
+        ],
+        LeMetaEvent::ConnectionUpdateComplete(update) => vec![
+            status(update.status),
+            Node::new(format!("Handle: {}", update.handle)),
+            Node::new(format!(
+                "Connection interval: {:.2} msec",
+                update.connection_interval as f64 * 1.25
+            )),
+            Node::new(format!("Peripheral latency: {}", update.peripheral_latency)),
+            Node::new(format!(
+                "Supervision timeout: {} msec",
+                update.supervision_timeout as u32 * 10
+            )),
+        ],
+        LeMetaEvent::AdvertisingReport(reports) => reports
+            .iter()
+            .flat_map(|report| {
+                let mut nodes = vec![
+                    Node::new(format!(
+                        "Address: {} (type 0x{:02x})",
+                        report.address, report.address_type
+                    )),
+                    Node::new(format!("Event type: 0x{:04x}", report.event_type)),
+                ];
+                if let Some(rssi) = report.rssi {
+                    nodes.push(Node::new(format!("RSSI: {} dBm", rssi)));
+                }
+                let last = nodes.pop().unwrap_or_default();
+                nodes.push(last.with_children(hex(report.data)));
+                nodes
+            })
+            .collect(),
+        LeMetaEvent::ConnectionlessIqReport(report) | LeMetaEvent::ConnectionIqReport(report) => {
+            iq_report(&report)
+        }
+        LeMetaEvent::CteRequestFailed {
+            status: code,
+            handle,
+        } => vec![status(code), Node::new(format!("Handle: {}", handle))],
+        LeMetaEvent::PathLossThreshold(threshold) => vec![
+            Node::new(format!("Handle: {}", threshold.handle)),
+            Node::new(match threshold.path_loss() {
+                Some(path_loss) => format!("Path loss: {} dB", path_loss),
+                None => "Path loss: not available".to_owned(),
+            }),
+            Node::new(format!(
+                "Zone entered: {} (0x{:02x})",
+                PathLossThreshold::zone_name(threshold.zone_entered).unwrap_or("Unknown"),
+                threshold.zone_entered
+            )),
+        ],
+        LeMetaEvent::TransmitPowerReporting(report) => vec![
+            status(report.status),
+            Node::new(format!("Handle: {}", report.handle)),
+            Node::new(format!(
+                "Reason: {} (0x{:02x})",
+                match report.reason {
+                    TransmitPowerReporting::REASON_LOCAL_CHANGE => "Local power level changed",
+                    TransmitPowerReporting::REASON_REMOTE_CHANGE => "Remote power level changed",
+                    TransmitPowerReporting::REASON_READ_REMOTE => "Remote power level read",
+                    _ => "Unknown",
+                },
+                report.reason
+            )),
+            phy(report.phy),
+            tx_power("TX power", report.tx_power_level),
+            Node::new(format!(
+                "TX power flags: 0x{:02x}{}{}",
+                report.tx_power_level_flag,
+                if report.tx_power_level_flag & TransmitPowerReporting::FLAG_MIN != 0 {
+                    " minimum"
+                } else {
+                    ""
+                },
+                if report.tx_power_level_flag & TransmitPowerReporting::FLAG_MAX != 0 {
+                    " maximum"
+                } else {
+                    ""
+                }
+            )),
+            Node::new(match report.delta {
+                TX_POWER_NOT_AVAILABLE => "Delta: not available".to_owned(),
+                delta => format!("Delta: {} dB", delta),
+            }),
+        ],
+        _ => return Err(invalid("undecoded LE subevent")),
+    })
+}
+
+fn phy(phy: u8) -> Node {
+    Node::new(format!(
+        "PHY: {} (0x{:02x})",
+        phy_name(phy).unwrap_or("Unknown"),
+        phy
+    ))
+}
+
+fn tx_power(label: &str, level: i8) -> Node {
+    Node::new(match level {
+        TX_POWER_NOT_AVAILABLE => format!("{}: not available", label),
+        TX_POWER_NOT_MANAGED => format!("{}: not managed", label),
+        level => format!("{}: {} dBm", label, level),
+    })
+}
+
+/// The parameters of the LE Power Control commands, and the return parameters of LE Enhanced
+/// Read Transmit Power Level
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerControl;
+
+impl Dissector for PowerControl {
+    fn name(&self) -> &str {
+        "power-control"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let opcode = context.opcode.ok_or_else(|| invalid("not a command"))?;
+        let mut params = payload;
+        let handle = |handle: u16| Node::new(format!("Handle: {}", handle));
+        let tree = if context.event.is_some() {
+            // the status was dissected with the Command Complete event
+            let mut tree = vec![handle(read_handle(&mut params)?)];
+            if opcode == LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL.raw() {
+                let [code, current, max] = params
+                    .first_chunk::<3>()
+                    .copied()
+                    .ok_or_else(|| invalid("transmit power level too short"))?;
+                params = &params[3..];
+                tree.extend([
+                    phy(code),
+                    tx_power("Current TX power", current as i8),
+                    tx_power("Max TX power", max as i8),
+                ]);
+            }
+            tree
+        } else {
+            let enable = |label: &str, enable: bool| Node::new(format!("{}: {}", label, enable));
+            match PowerControlCommand::parse(opcode.into(), &mut params)? {
+                PowerControlCommand::EnhancedReadTransmitPowerLevel { handle: h, phy: p }
+                | PowerControlCommand::ReadRemoteTransmitPowerLevel { handle: h, phy: p } => {
+                    vec![handle(h), phy(p)]
+                }
+                PowerControlCommand::SetPathLossReportingParameters {
+                    handle: h,
+                    high_threshold,
+                    high_hysteresis,
+                    low_threshold,
+                    low_hysteresis,
+                    min_time_spent,
+                } => vec![
+                    handle(h),
+                    Node::new(format!(
+                        "High threshold: {} dB, hysteresis {} dB",
+                        high_threshold, high_hysteresis
+                    )),
+                    Node::new(format!(
+                        "Low threshold: {} dB, hysteresis {} dB",
+                        low_threshold, low_hysteresis
+                    )),
+                    Node::new(format!(
+                        "Min time spent: {} connection events",
+                        min_time_spent
+                    )),
+                ],
+                PowerControlCommand::SetPathLossReportingEnable {
+                    handle: h,
+                    enable: e,
+                } => {
+                    vec![handle(h), enable("Enable", e)]
+                }
+                PowerControlCommand::SetTransmitPowerReportingEnable {
+                    handle: h,
+                    local_enable,
+                    remote_enable,
+                } => vec![
+                    handle(h),
+                    enable("Local enable", local_enable),
+                    enable("Remote enable", remote_enable),
+                ],
+            }
+        };
+        let mut dissection = Dissection::new(tree);
+        dissection.remaining = params;
+        Ok(dissection)
+    }
+}
+
+fn cte_type(cte_type: u8) -> String {
+    format!(
+        "CTE type: {} (0x{:02x})",
+        cte_type_name(cte_type).unwrap_or("Unknown"),
+        cte_type
+    )
+}
+
+fn antenna_ids(antenna_ids: &[u8]) -> Node {
+    Node::new(format!("Antenna IDs: {:02x?}", antenna_ids))
+}
+
+fn iq_report(report: &IqReport) -> Vec<Node> {
+    let mut nodes = vec![Node::new(format!(
+        "{} handle: {}",
+        if report.rx_phy.is_some() {
+            "Connection"
+        } else {
+            "Sync"
+        },
+        report.handle
+    ))];
+    if let Some(rx_phy) = report.rx_phy {
+        nodes.push(Node::new(format!("RX PHY: 0x{:02x}", rx_phy)));
+    }
+    nodes.extend([
+        Node::new(format!("Channel index: {}", report.channel_index)),
+        Node::new(format!(
+            "RSSI: {:.1} dBm (antenna {})",
+            report.rssi_dbm(),
+            report.rssi_antenna_id
+        )),
+        Node::new(cte_type(report.cte_type)),
+        Node::new(format!("Slot durations: 0x{:02x}", report.slot_durations)),
+        Node::new(format!("Packet status: 0x{:02x}", report.packet_status)),
+        Node::new(format!("Event counter: {}", report.event_counter)),
+        Node::new(format!("Samples: {}", report.samples.len())).with_children(
+            report
+                .samples
+                .iter()
+                .map(|sample| {
+                    if sample.is_available() {
+                        Node::new(format!("I: {:4} Q: {:4}", sample.i, sample.q))
+                    } else {
+                        Node::new("Not available")
+                    }
+                })
+                .collect(),
+        ),
+    ]);
+    nodes
+}
+
+/// The parameters of the LE Direction Finding commands, and the return parameters of LE Read
+/// Antenna Information
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirectionFinding;
+
+impl Dissector for DirectionFinding {
+    fn name(&self) -> &str {
+        "direction-finding"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let opcode = context.opcode.ok_or_else(|| invalid("not a command"))?;
+        let mut params = payload;
+        if context.event.is_some() {
+            // the status was dissected with the Command Complete event
+            let tree = if opcode == LE_READ_ANTENNA_INFORMATION.raw() {
+                let [rates, antennae, pattern_length, cte_length] = params
+                    .first_chunk::<4>()
+                    .copied()
+                    .ok_or_else(|| invalid("antenna information too short"))?;
+                params = &params[4..];
+                vec![
+                    Node::new(format!(
+                        "Supported switching sampling rates: 0x{:02x}",
+                        rates
+                    )),
+                    Node::new(format!("Antennae: {}", antennae)),
+                    Node::new(format!("Max switching pattern length: {}", pattern_length)),
+                    Node::new(format!("Max CTE length: {} usec", cte_length as u32 * 8)),
+                ]
+            } else if let Ok(handle) = read_handle(&mut params) {
+                vec![Node::new(format!("Handle: {}", handle))]
+            } else {
+                vec![]
+            };
+            let mut dissection = Dissection::new(tree);
+            dissection.remaining = params;
+            return Ok(dissection);
+        }
+        let enable = |enable: bool| Node::new(format!("Enable: {}", enable));
+        let tree = match CteCommand::parse(opcode.into(), &mut params)? {
+            CteCommand::SetConnectionlessCteTransmitParameters {
+                advertising_handle,
+                cte_length,
+                cte_type: kind,
+                cte_count,
+                antenna_ids: ids,
+            } => vec![
+                Node::new(format!("Advertising handle: {}", advertising_handle)),
+                Node::new(format!("CTE length: {} usec", cte_length as u32 * 8)),
+                Node::new(cte_type(kind)),
+                Node::new(format!("CTE count: {}", cte_count)),
+                antenna_ids(&ids),
+            ],
+            CteCommand::SetConnectionlessCteTransmitEnable {
+                advertising_handle,
+                enable: enabled,
+            } => vec![
+                Node::new(format!("Advertising handle: {}", advertising_handle)),
+                enable(enabled),
+            ],
+            CteCommand::SetConnectionlessIqSamplingEnable {
+                sync_handle,
+                enable: enabled,
+                slot_durations,
+                max_sampled_ctes,
+                antenna_ids: ids,
+            } => vec![
+                Node::new(format!("Sync handle: {}", sync_handle)),
+                enable(enabled),
+                Node::new(format!("Slot durations: 0x{:02x}", slot_durations)),
+                Node::new(format!("Max sampled CTEs: {}", max_sampled_ctes)),
+                antenna_ids(&ids),
+            ],
+            CteCommand::SetConnectionCteReceiveParameters {
+                handle,
+                enable: enabled,
+                slot_durations,
+                antenna_ids: ids,
+            } => vec![
+                Node::new(format!("Handle: {}", handle)),
+                enable(enabled),
+                Node::new(format!("Slot durations: 0x{:02x}", slot_durations)),
+                antenna_ids(&ids),
+            ],
+            CteCommand::SetConnectionCteTransmitParameters {
+                handle,
+                cte_types,
+                antenna_ids: ids,
+            } => vec![
+                Node::new(format!("Handle: {}", handle)),
+                Node::new(format!("CTE types: 0x{:02x}", cte_types)),
+                antenna_ids(&ids),
+            ],
+            CteCommand::ConnectionCteRequestEnable {
+                handle,
+                enable: enabled,
+                cte_request_interval,
+                requested_cte_length,
+                requested_cte_type,
+            } => vec![
+                Node::new(format!("Handle: {}", handle)),
+                enable(enabled),
+                Node::new(format!("CTE request interval: {}", cte_request_interval)),
+                Node::new(format!(
+                    "Requested CTE length: {} usec",
+                    requested_cte_length as u32 * 8
+                )),
+                Node::new(cte_type(requested_cte_type)),
+            ],
+            CteCommand::ConnectionCteResponseEnable {
+                handle,
+                enable: enabled,
+            } => vec![Node::new(format!("Handle: {}", handle)), enable(enabled)],
+            CteCommand::ReadAntennaInformation => vec![],
+        };
+        let mut dissection = Dissection::new(tree);
+        dissection.remaining = params;
+        Ok(dissection)
+    }
+}
+
+/// The Bluetooth Quality Reports of vendor specific events, other vendor events in hex
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VendorEvents;
+
+impl Dissector for VendorEvents {
+    fn name(&self) -> &str {
+        "vendor"
+    }
+
+    fn dissect<'a>(&self, _: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let tree = match VendorDecoder::new().decode(None, payload) {
+            Some((_, diagnostic)) => vec![Node::new(diagnostic.to_string())],
+            None => hex(payload),
+        };
+        Ok(Dissection::new(tree))
+    }
+}
+
+/// ATT PDUs, with the value of the attribute written, notified, indicated or read handed to
+/// the dissector of the attribute
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Att;
+
+impl Dissector for Att {
+    fn name(&self) -> &str {
+        "att"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let pdu = att::Pdu::try_from(payload)?;
+        let mut dissection = Dissection::new(vec![]);
+        dissection.summary = Some(format!(
+            "ATT: {} (0x{:02x})",
+            att::opcode_name(pdu.opcode).unwrap_or("Unknown"),
+            pdu.opcode
+        ));
+        match pdu.opcode {
+            att::Pdu::ERROR_RESPONSE => {
+                let error = att::ErrorResponse::parse(&mut &pdu.params[..])?;
+                dissection.tree.push(Node::new(format!(
+                    "{} (0x{:02x}) handle 0x{:04x}: {} (0x{:02x})",
+                    att::opcode_name(error.request_opcode).unwrap_or("Unknown"),
+                    error.request_opcode,
+                    error.handle,
+                    att::error_code_name(error.error_code).unwrap_or("Unknown"),
+                    error.error_code
+                )));
+            }
+            att::Pdu::WRITE_REQUEST
+            | att::Pdu::WRITE_COMMAND
+            | att::Pdu::HANDLE_VALUE_NOTIFICATION
+            | att::Pdu::HANDLE_VALUE_INDICATION
+                if pdu.params.len() >= 2 =>
+            {
+                let handle = u16::from_le_bytes([pdu.params[0], pdu.params[1]]);
+                dissection
+                    .tree
+                    .push(Node::new(format!("Handle: 0x{:04x}", handle)));
+                dissection.remaining = &pdu.params[2..];
+                dissection.next = Some(Key::Attribute(handle));
+            }
+            att::Pdu::READ_RESPONSE => {
+                dissection.remaining = pdu.params;
+                dissection.next = context.attribute.map(Key::Attribute);
+            }
+            _ => dissection.remaining = pdu.params,
+        }
+        Ok(dissection)
+    }
+}
+
+/// The commands of the signaling channels, their data in hex
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Signaling;
+
+impl Dissector for Signaling {
+    fn name(&self) -> &str {
+        "l2cap-signaling"
+    }
+
+    fn dissect<'a>(&self, _: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        Ok(Dissection::new(
+            SignalingCommand::iter(payload)
+                .map(|command| {
+                    Node::new(format!(
+                        "Signaling: {} (0x{:02x}) ident {} len {}",
+                        signaling_code_name(command.code).unwrap_or("Unknown"),
+                        command.code,
+                        command.identifier,
+                        command.length
+                    ))
+                    .with_children(hex(command.data))
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{dissect::lines, DirectionFlag};
+
+    fn event(code: u8) -> Context {
+        Context {
+            event: Some(code),
+            ..Context::new(DirectionFlag::Received, 0)
+        }
+    }
+
+    fn command(opcode: u16) -> Context {
+        Context {
+            opcode: Some(opcode),
+            ..Context::new(DirectionFlag::Sent, 0)
+        }
+    }
+
+    #[test]
+    fn command_complete() {
+        let context = event(Event::COMMAND_COMPLETE);
+        let params = [0x01, 0x76, 0x20, 0x00, 0x40, 0x00];
+        let dissection = HciEvents.dissect(&context, &params).unwrap();
+        assert_eq!(
+            lines(&dissection.tree),
+            [
+                "Opcode: LE Enhanced Read Transmit Power Level (0x2076)",
+                "Status: Success (0x00)"
+            ]
+        );
+        // the return parameters after the status go to the command's dissector
+        assert_eq!(dissection.remaining, [0x40, 0x00]);
+        assert_eq!(dissection.next, Some(Key::Opcode(0x2076)));
+
+        // without return parameters
+        let dissection = HciEvents.dissect(&context, &params[..3]).unwrap();
+        assert_eq!(dissection.tree.len(), 1);
+        assert!(dissection.remaining.is_empty());
+        assert_eq!(dissection.next, None);
+
+        assert!(HciEvents.dissect(&context, &params[..2]).is_err());
+    }
+
+    #[test]
+    fn malformed_events() {
+        let disconnection = [0x00, 0x40, 0x00, 0x13];
+        let dissection = HciEvents
+            .dissect(&event(Event::DISCONNECTION_COMPLETE), &disconnection)
+            .unwrap();
+        assert_eq!(
+            lines(&dissection.tree),
+            [
+                "Status: Success (0x00)",
+                "Handle: 64",
+                "Reason: Remote User Terminated Connection (0x13)"
+            ]
+        );
+        let error = HciEvents
+            .dissect(&event(Event::DISCONNECTION_COMPLETE), &disconnection[..3])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let error = HciEvents
+            .dissect(&command(0x0c03), &disconnection)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // LE Read Remote Features Complete is not decoded
+        let error = HciEvents
+            .dissect(&event(Event::LE_META), &[0x04, 0x00, 0x40, 0x00])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn power_control() {
+        let opcode = LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL.raw();
+        let dissection = PowerControl
+            .dissect(&command(opcode), &[0x40, 0x00, 0x01, 0xff])
+            .unwrap();
+        assert_eq!(dissection.tree.len(), 2);
+        assert_eq!(dissection.remaining, [0xff]);
+        assert!(PowerControl.dissect(&command(opcode), &[0x40]).is_err());
+
+        // the return parameters after the status
+        let context = Context {
+            event: Some(Event::COMMAND_COMPLETE),
+            ..command(opcode)
+        };
+        let dissection = PowerControl
+            .dissect(&context, &[0x40, 0x00, 0x01, 0x04, 0x7e])
+            .unwrap();
+        assert_eq!(
+            lines(&dissection.tree)[2..],
+            ["Current TX power: 4 dBm", "Max TX power: not managed"]
+        );
+        let error = PowerControl
+            .dissect(&context, &[0x40, 0x00, 0x01, 0x04])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(PowerControl
+            .dissect(&event(Event::COMMAND_COMPLETE), &[])
+            .is_err());
+    }
+
+    #[test]
+    fn antenna_information() {
+        let context = Context {
+            event: Some(Event::COMMAND_COMPLETE),
+            ..command(LE_READ_ANTENNA_INFORMATION.raw())
+        };
+        let dissection = DirectionFinding
+            .dissect(&context, &[0x0f, 0x04, 0x12, 0x14])
+            .unwrap();
+        assert_eq!(lines(&dissection.tree)[3], "Max CTE length: 160 usec");
+        assert!(DirectionFinding.dissect(&context, &[0x0f]).is_err());
+    }
+
+    #[test]
+    fn att() {
+        let write = [0x12, 0x21, 0x00, 0x01, 0x02];
+        let dissection = Att.dissect(&command(0), &write).unwrap();
+        assert_eq!(
+            dissection.summary.as_deref(),
+            Some("ATT: Write Request (0x12)")
+        );
+        assert_eq!(lines(&dissection.tree), ["Handle: 0x0021"]);
+        assert_eq!(dissection.remaining, [0x01, 0x02]);
+        assert_eq!(dissection.next, Some(Key::Attribute(0x0021)));
+        // too short for a handle
+        let dissection = Att.dissect(&command(0), &write[..2]).unwrap();
+        assert!(dissection.tree.is_empty());
+        assert_eq!(dissection.remaining, [0x21]);
+        assert_eq!(dissection.next, None);
+
+        // a read response is a value of the attribute read
+        let context = Context {
+            attribute: Some(0x0021),
+            ..event(0)
+        };
+        let dissection = Att.dissect(&context, &[0x0b, 0x64]).unwrap();
+        assert_eq!(dissection.next, Some(Key::Attribute(0x0021)));
+
+        let error = [0x01, 0x0a, 0x21, 0x00, 0x0a];
+        let dissection = Att.dissect(&context, &error).unwrap();
+        assert_eq!(
+            lines(&dissection.tree),
+            ["Read Request (0x0a) handle 0x0021: Attribute Not Found (0x0a)"]
+        );
+        assert!(Att.dissect(&context, &error[..4]).is_err());
+        assert!(Att.dissect(&context, &[]).is_err());
+    }
+
+    #[test]
+    fn signaling() {
+        // the second command is cut short
+        let payload = [
+            0x12, 0x01, 0x02, 0x00, 0xaa, 0xbb, 0x13, 0x02, 0x04, 0x00, 0xcc,
+        ];
+        let dissection = Signaling.dissect(&command(0), &payload).unwrap();
+        assert_eq!(dissection.tree.len(), 2);
+        assert_eq!(
+            dissection.tree[0].label,
+            "Signaling: Connection Parameter Update Request (0x12) ident 1 len 2"
+        );
+        assert_eq!(dissection.tree[0].children, hex(&[0xaa, 0xbb]));
+        assert_eq!(
+            dissection.tree[1].label,
+            "Signaling: Connection Parameter Update Response (0x13) ident 2 len 4"
+        );
+        assert_eq!(dissection.tree[1].children, hex(&[0xcc]));
+        // shorter than a command header
+        let dissection = Signaling.dissect(&command(0), &payload[..3]).unwrap();
+        assert!(dissection.tree.is_empty());
+    }
+
+    #[test]
+    fn vendor_events() {
+        let dissection = VendorEvents.dissect(&event(0xff), &[0xaa]).unwrap();
+        assert_eq!(dissection.tree, hex(&[0xaa]));
+    }
+}
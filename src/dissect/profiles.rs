@@ -0,0 +1,547 @@
+//! The values of standard GATT characteristics, registered by [`Registry::with_builtins`] for
+//! their types: battery, device information, time, heart rate, health thermometer, cycling and
+//! running speed, the proxy PDUs of Bluetooth Mesh, and Apple's notification and media services.
+
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{Context, Dissection, Dissector, Key, Node, Registry};
+use crate::{
+    apple::{self, ControlPointCommand, DataSourceResponse, EntityUpdate, NotificationSource},
+    att,
+    gatt::Uuid,
+    mesh::{self, ProxyPdu, Sar},
+};
+
+// data format from: GATT Specification Supplement, and the profiles of the services
+
+pub const BATTERY_LEVEL: u16 = 0x2A19;
+pub const TEMPERATURE_MEASUREMENT: u16 = 0x2A1C;
+pub const INTERMEDIATE_TEMPERATURE: u16 = 0x2A1E;
+pub const MODEL_NUMBER_STRING: u16 = 0x2A24;
+pub const SERIAL_NUMBER_STRING: u16 = 0x2A25;
+pub const FIRMWARE_REVISION_STRING: u16 = 0x2A26;
+pub const HARDWARE_REVISION_STRING: u16 = 0x2A27;
+pub const SOFTWARE_REVISION_STRING: u16 = 0x2A28;
+pub const MANUFACTURER_NAME_STRING: u16 = 0x2A29;
+pub const CURRENT_TIME: u16 = 0x2A2B;
+pub const HEART_RATE_MEASUREMENT: u16 = 0x2A37;
+pub const RSC_MEASUREMENT: u16 = 0x2A53;
+pub const CSC_MEASUREMENT: u16 = 0x2A5B;
+
+/// The name of the characteristics decoded here
+pub fn characteristic_name(uuid: u16) -> Option<&'static str> {
+    Some(match uuid {
+        BATTERY_LEVEL => "Battery Level",
+        TEMPERATURE_MEASUREMENT => "Temperature Measurement",
+        INTERMEDIATE_TEMPERATURE => "Intermediate Temperature",
+        MODEL_NUMBER_STRING => "Model Number String",
+        SERIAL_NUMBER_STRING => "Serial Number String",
+        FIRMWARE_REVISION_STRING => "Firmware Revision String",
+        HARDWARE_REVISION_STRING => "Hardware Revision String",
+        SOFTWARE_REVISION_STRING => "Software Revision String",
+        MANUFACTURER_NAME_STRING => "Manufacturer Name String",
+        CURRENT_TIME => "Current Time",
+        HEART_RATE_MEASUREMENT => "Heart Rate Measurement",
+        RSC_MEASUREMENT => "RSC Measurement",
+        CSC_MEASUREMENT => "CSC Measurement",
+        _ => return None,
+    })
+}
+
+pub(super) fn register(registry: &mut Registry) {
+    let characteristics: Arc<dyn Dissector> = Arc::new(Characteristics);
+    for uuid in [
+        BATTERY_LEVEL,
+        TEMPERATURE_MEASUREMENT,
+        INTERMEDIATE_TEMPERATURE,
+        MODEL_NUMBER_STRING,
+        SERIAL_NUMBER_STRING,
+        FIRMWARE_REVISION_STRING,
+        HARDWARE_REVISION_STRING,
+        SOFTWARE_REVISION_STRING,
+        MANUFACTURER_NAME_STRING,
+        CURRENT_TIME,
+        HEART_RATE_MEASUREMENT,
+        RSC_MEASUREMENT,
+        CSC_MEASUREMENT,
+    ] {
+        registry.register(Key::Gatt(Uuid::from_u16(uuid)), characteristics.clone());
+    }
+    let mesh: Arc<dyn Dissector> = Arc::new(MeshProxy);
+    for uuid in [
+        mesh::MESH_PROVISIONING_DATA_IN,
+        mesh::MESH_PROVISIONING_DATA_OUT,
+        mesh::MESH_PROXY_DATA_IN,
+        mesh::MESH_PROXY_DATA_OUT,
+    ] {
+        registry.register(Key::Gatt(Uuid::from_u16(uuid)), mesh.clone());
+    }
+    let apple: Arc<dyn Dissector> = Arc::new(Apple);
+    for uuid in [
+        apple::NOTIFICATION_SOURCE,
+        apple::CONTROL_POINT,
+        apple::DATA_SOURCE,
+        apple::REMOTE_COMMAND,
+        apple::ENTITY_UPDATE,
+        apple::ENTITY_ATTRIBUTE,
+    ] {
+        registry.register(Key::Gatt(uuid), apple.clone());
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The value of the characteristics of [`characteristic_name`], by the type of the attribute.
+/// The octets after the fields, e.g. of a later version of a characteristic, are dumped in hex.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Characteristics;
+
+impl Dissector for Characteristics {
+    fn name(&self) -> &str {
+        "gatt-profiles"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let uuid = context
+            .uuid
+            .and_then(|uuid| uuid.as_u16())
+            .ok_or_else(|| invalid("not a standard characteristic"))?;
+        let name = characteristic_name(uuid).ok_or_else(|| invalid("unknown characteristic"))?;
+        let mut value = payload;
+        let tree = match uuid {
+            BATTERY_LEVEL => vec![Node::new(format!("Level: {}%", value.read_u8()?))],
+            MODEL_NUMBER_STRING..=MANUFACTURER_NAME_STRING => {
+                let string = String::from_utf8_lossy(value);
+                value = &[];
+                vec![Node::new(format!("{:?}", string.trim_end_matches('\0')))]
+            }
+            CURRENT_TIME => current_time(&mut value)?,
+            HEART_RATE_MEASUREMENT => heart_rate(&mut value)?,
+            TEMPERATURE_MEASUREMENT | INTERMEDIATE_TEMPERATURE => temperature(&mut value)?,
+            CSC_MEASUREMENT => cycling_speed(&mut value)?,
+            RSC_MEASUREMENT => running_speed(&mut value)?,
+            _ => return Err(invalid("unknown characteristic")),
+        };
+        let mut dissection = Dissection::new(tree);
+        dissection.summary = Some(format!("{} (0x{:04x})", name, uuid));
+        dissection.remaining = value;
+        Ok(dissection)
+    }
+}
+
+/// The proxy PDUs of the Mesh Provisioning and Mesh Proxy Data In and Out characteristics.
+/// A complete PDU is decoded as far as it can be without the network key, the segments are
+/// dumped in hex, see [`mesh::Reassembler`] for the messages they carry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeshProxy;
+
+impl Dissector for MeshProxy {
+    fn name(&self) -> &str {
+        "mesh-proxy"
+    }
+
+    fn dissect<'a>(&self, _: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let pdu = ProxyPdu::try_from(payload)?;
+        let mut dissection = Dissection::new(vec![]);
+        dissection.summary = Some(format!("Mesh Proxy: {} ({})", pdu.message_type, pdu.sar));
+        if pdu.sar == Sar::Complete {
+            dissection
+                .tree
+                .push(Node::new(mesh::describe(pdu.message_type, pdu.data)));
+        }
+        dissection.remaining = pdu.data;
+        Ok(dissection)
+    }
+}
+
+/// The characteristics of the Apple Notification Center Service (ANCS) and Apple Media
+/// Service (AMS). The written values are told from the notified ones by the ATT PDU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Apple;
+
+impl Dissector for Apple {
+    fn name(&self) -> &str {
+        "apple"
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let uuid = context
+            .uuid
+            .ok_or_else(|| invalid("unknown characteristic"))?;
+        let written = matches!(
+            context.att_opcode,
+            Some(att::Pdu::WRITE_REQUEST | att::Pdu::WRITE_COMMAND)
+        );
+        let (summary, tree) = match uuid {
+            apple::NOTIFICATION_SOURCE => (
+                "ANCS Notification Source",
+                vec![Node::new(
+                    NotificationSource::try_from(payload)?.to_string(),
+                )],
+            ),
+            apple::CONTROL_POINT => (
+                "ANCS Control Point",
+                vec![Node::new(
+                    ControlPointCommand::try_from(payload)?.to_string(),
+                )],
+            ),
+            apple::DATA_SOURCE => {
+                let response = DataSourceResponse::try_from(payload)?;
+                (
+                    "ANCS Data Source",
+                    vec![Node::new(response.to_string())
+                        .with_children(response.lines().into_iter().map(Node::new).collect())],
+                )
+            }
+            apple::REMOTE_COMMAND => (
+                "AMS Remote Command",
+                vec![Node::new(if written {
+                    apple::remote_commands(payload)
+                } else {
+                    format!("Supported: {}", apple::remote_commands(payload))
+                })],
+            ),
+            apple::ENTITY_UPDATE if written => (
+                "AMS Entity Update",
+                vec![Node::new(format!(
+                    "Subscribe: {}",
+                    apple::entity_attributes(payload)?
+                ))],
+            ),
+            apple::ENTITY_UPDATE => (
+                "AMS Entity Update",
+                vec![Node::new(EntityUpdate::try_from(payload)?.to_string())],
+            ),
+            apple::ENTITY_ATTRIBUTE if written => (
+                "AMS Entity Attribute",
+                vec![Node::new(apple::entity_attributes(payload)?)],
+            ),
+            apple::ENTITY_ATTRIBUTE => (
+                "AMS Entity Attribute",
+                vec![Node::new(format!("{:?}", String::from_utf8_lossy(payload)))],
+            ),
+            _ => return Err(invalid("unknown characteristic")),
+        };
+        let mut dissection = Dissection::new(tree);
+        dissection.summary = Some(summary.to_string());
+        Ok(dissection)
+    }
+}
+
+/// Date Time, 0 for an unknown year, month or day
+fn date_time(value: &mut &[u8]) -> io::Result<String> {
+    let year = value.read_u16::<LittleEndian>()?;
+    let mut fields = [0; 5];
+    value.read_exact(&mut fields)?;
+    let [month, day, hours, minutes, seconds] = fields;
+    Ok(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hours, minutes, seconds
+    ))
+}
+
+fn current_time(value: &mut &[u8]) -> io::Result<Vec<Node>> {
+    let date_time = date_time(value)?;
+    let day_of_week = value.read_u8()?;
+    let fractions256 = value.read_u8()?;
+    let adjust_reason = value.read_u8()?;
+    let day = match day_of_week {
+        1 => "Monday",
+        2 => "Tuesday",
+        3 => "Wednesday",
+        4 => "Thursday",
+        5 => "Friday",
+        6 => "Saturday",
+        7 => "Sunday",
+        _ => "unknown day",
+    };
+    let reasons: Vec<_> = [
+        "manual time update",
+        "external reference time update",
+        "change of time zone",
+        "change of DST",
+    ]
+    .iter()
+    .enumerate()
+    .filter(|(bit, _)| adjust_reason & 1 << bit != 0)
+    .map(|(_, reason)| *reason)
+    .collect();
+    Ok(vec![
+        Node::new(format!("Date: {} {}", date_time, day)),
+        Node::new(format!("Fractions: {}/256 s", fractions256)),
+        Node::new(format!(
+            "Adjust reason: {} (0x{:02x})",
+            if reasons.is_empty() {
+                "none".to_string()
+            } else {
+                reasons.join(", ")
+            },
+            adjust_reason
+        )),
+    ])
+}
+
+fn heart_rate(value: &mut &[u8]) -> io::Result<Vec<Node>> {
+    let flags = value.read_u8()?;
+    let heart_rate = match flags & 0x01 {
+        0 => value.read_u8()? as u16,
+        _ => value.read_u16::<LittleEndian>()?,
+    };
+    let mut tree = vec![Node::new(format!("Heart rate: {} bpm", heart_rate))];
+    tree.push(Node::new(format!(
+        "Sensor contact: {}",
+        match (flags >> 1) & 0x03 {
+            0b11 => "detected",
+            0b10 => "not detected",
+            _ => "not supported",
+        }
+    )));
+    if flags & 0x08 != 0 {
+        tree.push(Node::new(format!(
+            "Energy expended: {} kJ",
+            value.read_u16::<LittleEndian>()?
+        )));
+    }
+    if flags & 0x10 != 0 {
+        while !value.is_empty() {
+            let rr = value.read_u16::<LittleEndian>()?;
+            tree.push(Node::new(format!(
+                "RR interval: {:.3} s",
+                rr as f64 / 1024.0
+            )));
+        }
+    }
+    Ok(tree)
+}
+
+/// An IEEE 11073-20601 FLOAT: a 24 bit mantissa and an 8 bit exponent of 10, both signed
+fn medfloat32(raw: u32) -> String {
+    let mantissa = ((raw << 8) as i32) >> 8;
+    let exponent = (raw >> 24) as i8;
+    match mantissa {
+        0x7F_FFFF => "NaN".to_string(),
+        -0x80_0000 => "NRes".to_string(),
+        0x7F_FFFE => "+INFINITY".to_string(),
+        -0x7F_FFFE => "-INFINITY".to_string(),
+        -0x7F_FFFF => "reserved".to_string(),
+        _ if exponent < 0 => format!(
+            "{:.*}",
+            exponent.unsigned_abs() as usize,
+            mantissa as f64 * 10f64.powi(exponent as i32)
+        ),
+        _ => format!("{}", mantissa as f64 * 10f64.powi(exponent as i32)),
+    }
+}
+
+fn temperature(value: &mut &[u8]) -> io::Result<Vec<Node>> {
+    let flags = value.read_u8()?;
+    let temperature = medfloat32(value.read_u32::<LittleEndian>()?);
+    let unit = if flags & 0x01 == 0 { "°C" } else { "°F" };
+    let mut tree = vec![Node::new(format!("Temperature: {} {}", temperature, unit))];
+    if flags & 0x02 != 0 {
+        tree.push(Node::new(format!("Time stamp: {}", date_time(value)?)));
+    }
+    if flags & 0x04 != 0 {
+        let location = value.read_u8()?;
+        tree.push(Node::new(format!(
+            "Type: {} ({})",
+            match location {
+                1 => "Armpit",
+                2 => "Body (general)",
+                3 => "Ear (usually earlobe)",
+                4 => "Finger",
+                5 => "Gastro-intestinal Tract",
+                6 => "Mouth",
+                7 => "Rectum",
+                8 => "Toe",
+                9 => "Tympanum (ear drum)",
+                _ => "Reserved",
+            },
+            location
+        )));
+    }
+    Ok(tree)
+}
+
+fn cycling_speed(value: &mut &[u8]) -> io::Result<Vec<Node>> {
+    let flags = value.read_u8()?;
+    let mut tree = vec![];
+    if flags & 0x01 != 0 {
+        tree.push(Node::new(format!(
+            "Cumulative wheel revolutions: {}",
+            value.read_u32::<LittleEndian>()?
+        )));
+        tree.push(Node::new(format!(
+            "Last wheel event time: {:.3} s",
+            value.read_u16::<LittleEndian>()? as f64 / 1024.0
+        )));
+    }
+    if flags & 0x02 != 0 {
+        tree.push(Node::new(format!(
+            "Cumulative crank revolutions: {}",
+            value.read_u16::<LittleEndian>()?
+        )));
+        tree.push(Node::new(format!(
+            "Last crank event time: {:.3} s",
+            value.read_u16::<LittleEndian>()? as f64 / 1024.0
+        )));
+    }
+    Ok(tree)
+}
+
+fn running_speed(value: &mut &[u8]) -> io::Result<Vec<Node>> {
+    let flags = value.read_u8()?;
+    let speed = value.read_u16::<LittleEndian>()?;
+    let cadence = value.read_u8()?;
+    let mut tree = vec![
+        Node::new(format!("Speed: {:.2} m/s", speed as f64 / 256.0)),
+        Node::new(format!("Cadence: {} steps/min", cadence)),
+    ];
+    if flags & 0x01 != 0 {
+        tree.push(Node::new(format!(
+            "Stride length: {:.2} m",
+            value.read_u16::<LittleEndian>()? as f64 / 100.0
+        )));
+    }
+    if flags & 0x02 != 0 {
+        tree.push(Node::new(format!(
+            "Total distance: {:.1} m",
+            value.read_u32::<LittleEndian>()? as f64 / 10.0
+        )));
+    }
+    tree.push(Node::new(if flags & 0x04 != 0 {
+        "Running"
+    } else {
+        "Walking"
+    }));
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        analysis::test_capture,
+        dissect::{hex, lines},
+        DirectionFlag,
+    };
+
+    fn dissect(uuid: u16, value: &[u8]) -> Vec<String> {
+        let mut context = Context::new(DirectionFlag::Received, 0);
+        context.uuid = Some(Uuid::from_u16(uuid));
+        let dissection = Characteristics.dissect(&context, value).unwrap();
+        let mut tree = vec![Node::new(dissection.summary.unwrap()).with_children(dissection.tree)];
+        tree.extend(hex(dissection.remaining));
+        lines(&tree)
+    }
+
+    #[test]
+    fn characteristics() {
+        assert_eq!(
+            dissect(
+                HEART_RATE_MEASUREMENT,
+                &[0x1e, 0x48, 0x10, 0x00, 0x00, 0x04]
+            ),
+            [
+                "Heart Rate Measurement (0x2a37)",
+                "  Heart rate: 72 bpm",
+                "  Sensor contact: detected",
+                "  Energy expended: 16 kJ",
+                "  RR interval: 1.000 s",
+            ]
+        );
+        // 36.4 °C, in the ear
+        assert_eq!(
+            dissect(
+                TEMPERATURE_MEASUREMENT,
+                &[0x04, 0x6c, 0x01, 0x00, 0xff, 0x03]
+            )[1..],
+            [
+                "  Temperature: 36.4 °C",
+                "  Type: Ear (usually earlobe) (3)"
+            ]
+        );
+        assert_eq!(
+            dissect(
+                CURRENT_TIME,
+                &[0xea, 0x07, 0x0a, 0x10, 0x0c, 0x1e, 0x00, 0x05, 0x80, 0x01]
+            )[1..],
+            [
+                "  Date: 2026-10-16 12:30:00 Friday",
+                "  Fractions: 128/256 s",
+                "  Adjust reason: manual time update (0x01)",
+            ]
+        );
+        assert_eq!(
+            dissect(CSC_MEASUREMENT, &[0x01, 0x10, 0x00, 0x00, 0x00, 0x00, 0x08])[1..],
+            [
+                "  Cumulative wheel revolutions: 16",
+                "  Last wheel event time: 2.000 s"
+            ]
+        );
+        assert_eq!(
+            dissect(RSC_MEASUREMENT, &[0x04, 0x00, 0x03, 0xa0, 0xff])[1..],
+            [
+                "  Speed: 3.00 m/s",
+                "  Cadence: 160 steps/min",
+                "  Running",
+                "0000  ff                                               ."
+            ]
+        );
+        assert_eq!(
+            dissect(MANUFACTURER_NAME_STRING, b"Acme\0"),
+            ["Manufacturer Name String (0x2a29)", "  \"Acme\""]
+        );
+        assert_eq!(medfloat32(0x007F_FFFF), "NaN");
+        let mut context = Context::new(DirectionFlag::Sent, 0);
+        context.uuid = Some(apple::ENTITY_UPDATE);
+        context.att_opcode = Some(att::Pdu::WRITE_REQUEST);
+        assert_eq!(
+            Apple.dissect(&context, &[0x02, 0x00, 0x02]).unwrap().tree,
+            [Node::new("Subscribe: Track (2): Artist (0), Title (2)")]
+        );
+        context.att_opcode = Some(att::Pdu::HANDLE_VALUE_NOTIFICATION);
+        assert_eq!(
+            Apple.dissect(&context, b"\x00\x00\x00Music").unwrap().tree,
+            [Node::new("Player Name: \"Music\"")]
+        );
+        assert_eq!(
+            MeshProxy
+                .dissect(&Context::new(DirectionFlag::Sent, 0), &[0x03, 0x00, 0x05])
+                .unwrap()
+                .tree,
+            [Node::new("Provisioning Invite: attention 5 s")]
+        );
+
+        // routed by the type of the attribute discovered
+        let capture = test_capture::h4(vec![
+            (
+                0,
+                true,
+                test_capture::l2cap(0x40, 0x0004, &[0x05, 0x01, 0x03, 0x00, 0x19, 0x2a]),
+            ),
+            (
+                1,
+                true,
+                test_capture::l2cap(0x40, 0x0004, &[0x1b, 0x03, 0x00, 0x5a]),
+            ),
+        ]);
+        let mut session = Registry::builtins().session();
+        let tree: Vec<_> = capture.iter().map(|p| session.dissect(p)).collect();
+        assert_eq!(
+            lines(&tree[1])[2..],
+            [
+                "    Handle: 0x0003",
+                "    Battery Level (0x2a19)",
+                "      Level: 90%"
+            ]
+        );
+    }
+}
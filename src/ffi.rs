@@ -0,0 +1,355 @@
+//! C ABI over the parser, for tools written in C or C++. The declarations are in
+//! `include/btsnoop.h`, generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/btsnoop.h`. Build the shared library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`, or the static one with
+//! `--crate-type staticlib`.
+//!
+//! ```c
+//! BtsnoopCapture *capture = btsnoop_open("btsnoop_hci.log");
+//! if (!capture) {
+//!     fprintf(stderr, "%s\n", btsnoop_last_error());
+//!     return 1;
+//! }
+//! BtsnoopHci hci;
+//! for (size_t i = 0; i < btsnoop_packet_count(capture); i++) {
+//!     if (btsnoop_packet_hci(capture, i, &hci) && hci.packet_type == 1) {
+//!         printf("command 0x%04x\n", hci.opcode);
+//!     }
+//! }
+//! btsnoop_free(capture);
+//! ```
+//!
+//! A function returning a pointer returns NULL on error and [`btsnoop_last_error`] describes
+//! it. A panic never unwinds into the caller, it fails the call like an error. Pointers into a
+//! capture stay valid until it is freed. Timestamps are in microseconds since the Unix epoch.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{
+    formats::{self, Format},
+    hci::Event,
+    parse_uart_packet, Btsnoop, DatalinkType, UartData,
+};
+
+/// A parsed capture, opaque to C
+pub struct BtsnoopCapture {
+    capture: Btsnoop,
+}
+
+/// A packet record, its data points into the capture
+#[repr(C)]
+pub struct BtsnoopPacket {
+    pub original_length: u32,
+    /// bit 0 received, bit 1 command or event
+    pub flags: u32,
+    pub cumulative_drops: u32,
+    pub timestamp_us: i64,
+    pub data: *const u8,
+    pub data_len: usize,
+}
+
+/// The HCI fields of an HCI UART (H4) packet, zero when not part of its type
+#[repr(C)]
+pub struct BtsnoopHci {
+    /// 1 command, 2 ACL data, 3 SCO data, 4 event, 5 ISO data
+    pub packet_type: u8,
+    pub opcode: u16,
+    pub event_code: u8,
+    /// the subevent of an LE Meta event
+    pub subevent_code: u8,
+    /// the connection handle of ACL data
+    pub handle: u16,
+    pub packet_boundary_flag: u8,
+    /// command or event parameters, ACL data, or the rest of the packet for other types
+    pub params: *const u8,
+    pub params_len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(error: &io::Error) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run the body of an entry point, returning `failed` with the error set if it panics, as
+/// unwinding into C is undefined behavior
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "unknown panic".to_string(),
+        };
+        set_error(&io::Error::other(format!("panic: {}", message)));
+        failed
+    })
+}
+
+/// # Safety
+///
+/// `capture` must be a capture not freed yet.
+unsafe fn get<'a>(capture: *const BtsnoopCapture) -> &'a Btsnoop {
+    &(*capture).capture
+}
+
+fn into_capture(capture: io::Result<Btsnoop>) -> *mut BtsnoopCapture {
+    match capture {
+        Ok(capture) => Box::into_raw(Box::new(BtsnoopCapture { capture })),
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Read a capture file, of any format the crate reads, detected from its first octets
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_open(path: *const c_char) -> *mut BtsnoopCapture {
+    guard(ptr::null_mut(), || {
+        if path.is_null() {
+            set_error(&io::Error::new(io::ErrorKind::InvalidInput, "null path"));
+            return ptr::null_mut();
+        }
+        let path = CStr::from_ptr(path).to_string_lossy().into_owned();
+        into_capture(fs::read(path).and_then(|data| {
+            let format = Format::detect(&data).unwrap_or(Format::Btsnoop);
+            formats::read(&mut &data[..], format)
+        }))
+    })
+}
+
+/// Parse a btsnoop capture in memory, the data is copied
+///
+/// # Safety
+///
+/// `data` must point to `len` readable octets.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_parse(data: *const u8, len: usize) -> *mut BtsnoopCapture {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            return into_capture(Btsnoop::parse_from_slice(&[]));
+        }
+        into_capture(Btsnoop::parse_from_slice(slice::from_raw_parts(data, len)))
+    })
+}
+
+/// Free a capture, NULL is ignored
+///
+/// # Safety
+///
+/// `capture` must come from [`btsnoop_open`] or [`btsnoop_parse`] and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_free(capture: *mut BtsnoopCapture) {
+    guard((), || {
+        if !capture.is_null() {
+            drop(Box::from_raw(capture));
+        }
+    })
+}
+
+/// The message of the last error on this thread, NULL if none. Valid until the next error.
+#[no_mangle]
+pub extern "C" fn btsnoop_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// The datalink type code of the header, 1002 for HCI UART (H4)
+///
+/// # Safety
+///
+/// `capture` must be a capture not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_datalink(capture: *const BtsnoopCapture) -> u32 {
+    guard(0, || get(capture).header.datalink_type.into())
+}
+
+/// # Safety
+///
+/// `capture` must be a capture not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_packet_count(capture: *const BtsnoopCapture) -> usize {
+    guard(0, || get(capture).packets.len())
+}
+
+/// Fill `out` with the packet at `index`, false if out of range
+///
+/// # Safety
+///
+/// `capture` must be a capture not freed yet and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_packet(
+    capture: *const BtsnoopCapture,
+    index: usize,
+    out: *mut BtsnoopPacket,
+) -> bool {
+    guard(false, || {
+        let Some(packet) = get(capture).packets.get(index) else {
+            return false;
+        };
+        let description = &packet.description;
+        *out = BtsnoopPacket {
+            original_length: description.original_length,
+            flags: description.flags.0,
+            cumulative_drops: description.cumulative_drops,
+            timestamp_us: description.unix_timestamp(),
+            data: packet.data.0.as_ptr(),
+            data_len: packet.data.0.len(),
+        };
+        true
+    })
+}
+
+/// Fill `out` with the HCI fields of the packet at `index`, false if out of range, the
+/// capture is not HCI UART or the packet does not decode
+///
+/// # Safety
+///
+/// `capture` must be a capture not freed yet and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_packet_hci(
+    capture: *const BtsnoopCapture,
+    index: usize,
+    out: *mut BtsnoopHci,
+) -> bool {
+    guard(false, || {
+        let capture = get(capture);
+        if !matches!(capture.header.datalink_type, DatalinkType::Uart) {
+            return false;
+        }
+        let Some(packet) = capture.packets.get(index) else {
+            return false;
+        };
+        let (Some(packet_type), Ok(data)) = (packet.data.0.first(), parse_uart_packet(packet))
+        else {
+            return false;
+        };
+        let mut hci = BtsnoopHci {
+            packet_type: *packet_type,
+            opcode: 0,
+            event_code: 0,
+            subevent_code: 0,
+            handle: 0,
+            packet_boundary_flag: 0,
+            params: ptr::null(),
+            params_len: 0,
+        };
+        let params = match data {
+            UartData::Command(command) => {
+                hci.opcode = command.opcode.raw();
+                command.params
+            }
+            UartData::Event(event) => {
+                hci.event_code = event.code;
+                if event.code == Event::LE_META {
+                    hci.subevent_code = event.params.first().copied().unwrap_or_default();
+                }
+                event.params
+            }
+            UartData::Acl(acl) => {
+                hci.handle = acl.handle;
+                hci.packet_boundary_flag = acl.packet_boundary_flag as u8;
+                acl.data
+            }
+            UartData::Todos => &packet.data.0[1..],
+        };
+        hci.params = params.as_ptr();
+        hci.params_len = params.len();
+        *out = hci;
+        true
+    })
+}
+
+/// Write the one line summary of the packet at `index` to `buffer`, truncated to `len - 1`
+/// octets and NUL terminated. Returns the length of the whole summary, like `snprintf`, 0 if
+/// out of range.
+///
+/// # Safety
+///
+/// `capture` must be a capture not freed yet and `buffer` point to `len` writable octets.
+#[no_mangle]
+pub unsafe extern "C" fn btsnoop_packet_summary(
+    capture: *const BtsnoopCapture,
+    index: usize,
+    buffer: *mut c_char,
+    len: usize,
+) -> usize {
+    guard(0, || {
+        let Some(packet) = get(capture).packets.get(index) else {
+            return 0;
+        };
+        let summary = packet.summary().to_string();
+        if !buffer.is_null() && len > 0 {
+            let copied = summary.len().min(len - 1);
+            ptr::copy_nonoverlapping(summary.as_ptr(), buffer as *mut u8, copied);
+            *buffer.add(copied) = 0;
+        }
+        summary.len()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capture_from_c() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        unsafe {
+            let capture = btsnoop_parse(original.as_ptr(), original.len());
+            assert!(!capture.is_null());
+            assert_eq!(btsnoop_datalink(capture), 1002);
+
+            let mut hci = std::mem::zeroed::<BtsnoopHci>();
+            assert!(btsnoop_packet_hci(capture, 0, &mut hci));
+            assert_eq!(
+                (hci.packet_type, hci.opcode, hci.params_len),
+                (1, 0x0c03, 0)
+            );
+            let mut packet = std::mem::zeroed::<BtsnoopPacket>();
+            assert!(btsnoop_packet(capture, 1, &mut packet));
+            assert_eq!(
+                slice::from_raw_parts(packet.data, packet.data_len)[..2],
+                [0x04, 0x0e]
+            );
+            let count = btsnoop_packet_count(capture);
+            assert!(!btsnoop_packet(capture, count, &mut packet));
+
+            let mut buffer = [0 as c_char; 8];
+            let len = btsnoop_packet_summary(capture, 0, buffer.as_mut_ptr(), buffer.len());
+            let summary = CStr::from_ptr(buffer.as_ptr()).to_str().unwrap();
+            assert_eq!(summary.len(), 7);
+            assert!(len > summary.len());
+            btsnoop_free(capture);
+
+            assert!(btsnoop_parse(b"btsnoop".as_ptr(), 7).is_null());
+            assert!(!btsnoop_last_error().is_null());
+        }
+    }
+
+    #[test]
+    fn panic_not_unwound() {
+        assert!(!guard(false, || panic!("corrupt capture")));
+        let error = unsafe { CStr::from_ptr(btsnoop_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "panic: corrupt capture");
+    }
+}
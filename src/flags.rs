@@ -0,0 +1,195 @@
+//! Interpretations of the reserved bits (2 to 31) of the packet flags, for the capture tools
+//! which carry their own information in them, e.g. an adapter index or a retransmission mark.
+//! Fields are registered with their mask and optionally a label for each of their values.
+//!
+//! ```
+//! use btsnoop::{flags::{FlagField, FlagInterpretations}, PacketFlags};
+//!
+//! let mut interpretations = FlagInterpretations::new();
+//! interpretations.register(FlagField::new("adapter", 0x0000_FF00))?;
+//! interpretations.register("retry=0x4,0=no,1=yes".parse()?)?;
+//! let flags = PacketFlags(0x0000_0205);
+//! assert_eq!(interpretations.describe(flags), "adapter=2 retry=yes");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{collections::BTreeMap, io, str::FromStr};
+
+use crate::PacketFlags;
+
+/// A named field of the reserved bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagField {
+    pub name: String,
+    /// the bits of the field in place, not necessarily contiguous
+    pub mask: u32,
+    /// labels of the values of the field, the value is displayed when it has none
+    pub labels: BTreeMap<u32, String>,
+}
+
+impl FlagField {
+    pub fn new(name: impl Into<String>, mask: u32) -> Self {
+        Self {
+            name: name.into(),
+            mask,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Display `value`, shifted down to bit 0, as `label`
+    pub fn label(mut self, value: u32, label: impl Into<String>) -> Self {
+        self.labels.insert(value, label.into());
+        self
+    }
+
+    /// The value of the field in `flags`, shifted down to bit 0
+    pub fn value(&self, flags: PacketFlags) -> u32 {
+        flags.field(self.mask)
+    }
+
+    /// The label of the value of the field in `flags`, or the value itself
+    pub fn display(&self, flags: PacketFlags) -> String {
+        let value = self.value(flags);
+        match self.labels.get(&value) {
+            Some(label) => label.clone(),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// `NAME=MASK[,VALUE=LABEL...]`, the mask and values in decimal or hex with `0x`, e.g.
+/// `retry=0x4,0=no,1=yes`
+impl FromStr for FlagField {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid flag field {:?}, expected NAME=MASK[,VALUE=LABEL...]",
+                    s
+                ),
+            )
+        };
+        let number = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16).ok(),
+            None => value.parse().ok(),
+        };
+        let mut parts = s.split(',');
+        let (name, mask) = parts
+            .next()
+            .and_then(|field| field.split_once('='))
+            .ok_or_else(invalid)?;
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let mut field = FlagField::new(name, number(mask).ok_or_else(invalid)?);
+        for part in parts {
+            let (value, label) = part.split_once('=').ok_or_else(invalid)?;
+            field = field.label(number(value).ok_or_else(invalid)?, label);
+        }
+        Ok(field)
+    }
+}
+
+/// The fields registered, which don't overlap
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagInterpretations {
+    fields: Vec<FlagField>,
+}
+
+impl FlagInterpretations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a field, an error when its mask is empty, covers the direction or command flag or
+    /// overlaps a field registered before
+    pub fn register(&mut self, field: FlagField) -> io::Result<()> {
+        if field.mask == 0 || field.mask & !PacketFlags::RESERVED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the mask 0x{:08X} of {} is not within the reserved bits 0x{:08X}",
+                    field.mask,
+                    field.name,
+                    PacketFlags::RESERVED
+                ),
+            ));
+        }
+        if let Some(other) = self
+            .fields
+            .iter()
+            .find(|other| other.mask & field.mask != 0)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} overlaps {}", field.name, other.name),
+            ));
+        }
+        self.fields.push(field);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn fields(&self) -> &[FlagField] {
+        &self.fields
+    }
+
+    /// The reserved bits of `flags` which no field covers, in place
+    pub fn unknown_bits(&self, flags: PacketFlags) -> u32 {
+        self.fields
+            .iter()
+            .fold(flags.reserved_bits(), |bits, field| bits & !field.mask)
+    }
+
+    /// `name=value` of each field, in order of registration, then the bits set which no field
+    /// covers, e.g. `adapter=2 retry=yes reserved=0x00010000`
+    pub fn describe(&self, flags: PacketFlags) -> String {
+        let mut parts: Vec<_> = self
+            .fields
+            .iter()
+            .map(|field| format!("{}={}", field.name, field.display(flags)))
+            .collect();
+        let unknown = self.unknown_bits(flags);
+        if unknown != 0 {
+            parts.push(format!("reserved=0x{:08X}", unknown));
+        }
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interpretations() {
+        let mut interpretations = FlagInterpretations::new();
+        interpretations
+            .register(FlagField::new("adapter", 0x0000_FF00))
+            .unwrap();
+        let retry: FlagField = "retry=0x4,0=no,1=yes".parse().unwrap();
+        assert_eq!(retry.labels.len(), 2);
+        interpretations.register(retry).unwrap();
+        assert!(interpretations
+            .register(FlagField::new("direction", 0b1))
+            .is_err());
+        assert!(interpretations
+            .register(FlagField::new("channel", 0x0000_1000))
+            .is_err());
+        assert!("retry".parse::<FlagField>().is_err());
+        assert!("=4".parse::<FlagField>().is_err());
+
+        let flags = PacketFlags(0x0001_0301);
+        assert_eq!(interpretations.unknown_bits(flags), 0x0001_0000);
+        assert_eq!(
+            interpretations.describe(flags),
+            "adapter=3 retry=no reserved=0x00010000"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Reading a capture file while it is still being written, like `tail -f`.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    options::{Checker, ParseOptions},
+    Header, Packet, PacketDescription,
+};
+
+const HEADER_LEN: usize = Header::LENGTH;
+const RECORD_HEADER_LEN: usize = PacketDescription::LENGTH;
+
+/// Yields the packets of a capture file as they are appended to it, waiting for new ones.
+/// A packet is only returned once its record is complete.
+///
+/// When the log is rotated, i.e. the file is truncated or replaced by a new one like Android does
+/// when the snoop log grows too large, the rest of the old file is read and then the new one is
+/// followed.
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    header: Header,
+    checker: Checker,
+    /// read but not yet parsed octets
+    pending: Vec<u8>,
+    /// octets read from the current file
+    position: u64,
+    rotations: usize,
+    poll_interval: Duration,
+}
+
+impl Follower {
+    /// Open `path`, waiting for its header to be written
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let poll_interval = Duration::from_millis(100);
+        let mut file = File::open(&path)?;
+        let mut pending = Vec::new();
+        while pending.len() < HEADER_LEN {
+            if file.read_to_end(&mut pending)? == 0 {
+                thread::sleep(poll_interval);
+            }
+        }
+        let position = pending.len() as u64;
+        let header = Header::parse(&mut &pending[..HEADER_LEN])?;
+        pending.drain(..HEADER_LEN);
+        let checker = Checker::new(ParseOptions::default(), &header)?;
+        Ok(Self {
+            path,
+            file,
+            header,
+            checker,
+            pending,
+            position,
+            rotations: 0,
+            poll_interval,
+        })
+    }
+
+    /// How long to wait before checking the file for new data again, 100 ms by default
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    /// Parse the following packets with `options`, which must accept the header. The packet
+    /// limit is ignored.
+    pub fn set_options(&mut self, options: ParseOptions) -> io::Result<()> {
+        self.checker = Checker::new(options.max_packets(usize::MAX), &self.header)?;
+        Ok(())
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How many times the log was rotated since it was opened
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// The next packet if it was completely written already
+    pub fn try_next(&mut self) -> io::Result<Option<Packet>> {
+        if let Some(packet) = self.parse_pending()? {
+            return Ok(Some(packet));
+        }
+        self.position += self.file.read_to_end(&mut self.pending)? as u64;
+        if let Some(packet) = self.parse_pending()? {
+            return Ok(Some(packet));
+        }
+        if self.is_rotated() && self.reopen()? {
+            return self.parse_pending();
+        }
+        Ok(None)
+    }
+
+    /// The next packet, waiting for it to be written
+    pub fn next_packet(&mut self) -> io::Result<Packet> {
+        loop {
+            if let Some(packet) = self.try_next()? {
+                return Ok(packet);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Whether the path now names a shorter or another file than the one being read
+    fn is_rotated(&self) -> bool {
+        // between the rename and the creation of the new log
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return false;
+        };
+        if metadata.len() < self.position {
+            return true;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if let Ok(current) = self.file.metadata() {
+                return (current.dev(), current.ino()) != (metadata.dev(), metadata.ino());
+            }
+        }
+        false
+    }
+
+    /// Follow the new file at the path once its header is written, the incomplete record left
+    /// in the old one is dropped
+    fn reopen(&mut self) -> io::Result<bool> {
+        let mut file = File::open(&self.path)?;
+        let mut pending = Vec::new();
+        file.read_to_end(&mut pending)?;
+        if pending.len() < HEADER_LEN {
+            return Ok(false);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            rotations = self.rotations + 1,
+            dropped = self.pending.len(),
+            "log rotated, following the new file"
+        );
+        self.position = pending.len() as u64;
+        self.header = Header::parse(&mut &pending[..HEADER_LEN])?;
+        self.checker = Checker::new(self.checker.options().clone(), &self.header)?;
+        pending.drain(..HEADER_LEN);
+        self.file = file;
+        self.pending = pending;
+        self.rotations += 1;
+        Ok(true)
+    }
+
+    fn parse_pending(&mut self) -> io::Result<Option<Packet>> {
+        let Some(mut record_header) = self.pending.get(..RECORD_HEADER_LEN) else {
+            return Ok(None);
+        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "packet",
+            index = self.checker.packets(),
+            offset = self.position - self.pending.len() as u64
+        )
+        .entered();
+        // checked before waiting for data which may never come
+        let description = PacketDescription::parse(&mut record_header)?;
+        self.checker.check_description(&description)?;
+        let record_len = RECORD_HEADER_LEN + description.included_length as usize;
+        if self.pending.len() < record_len {
+            return Ok(None);
+        }
+        let mut packet = Packet::parse(&mut &self.pending[..record_len])?;
+        self.pending.drain(..record_len);
+        self.checker.accept(&mut packet)?;
+        Ok(Some(packet))
+    }
+}
+
+/// Never ends, blocks until the next packet is written
+impl Iterator for Follower {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_packet())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn packets_appear_once_complete() {
+        let capture: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let path = std::env::temp_dir().join(format!("btsnoop-follow-{}.log", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        // header, the first record and half of the second one
+        let first_record = RECORD_HEADER_LEN + 4;
+        file.write_all(&capture[..HEADER_LEN + first_record + 10])
+            .unwrap();
+
+        let mut follower = Follower::open(&path).unwrap();
+        let packet = follower.try_next().unwrap().unwrap();
+        assert_eq!(packet.data.0, [0x01, 0x03, 0x0C, 0x00]);
+        assert!(follower.try_next().unwrap().is_none());
+
+        file.write_all(&capture[HEADER_LEN + first_record + 10..])
+            .unwrap();
+        let packet = follower.try_next().unwrap().unwrap();
+        assert_eq!(packet.data.0[..2], [0x04, 0x0E]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rotation() {
+        let capture: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let first_two = HEADER_LEN + RECORD_HEADER_LEN * 2 + 4 + 7;
+        let path = std::env::temp_dir().join(format!("btsnoop-rotate-{}.log", std::process::id()));
+        let rotated = path.with_extension("log.last");
+        fs::write(&path, &capture[..first_two]).unwrap();
+
+        let mut follower = Follower::open(&path).unwrap();
+        assert!(follower.try_next().unwrap().is_some());
+        assert!(follower.try_next().unwrap().is_some());
+        assert!(follower.try_next().unwrap().is_none());
+
+        // renamed away and started over, then only the header of the new log was written
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, &capture[..HEADER_LEN]).unwrap();
+        assert!(follower.try_next().unwrap().is_none());
+        assert_eq!(follower.rotations(), 1);
+
+        fs::write(&path, &capture[..HEADER_LEN + RECORD_HEADER_LEN + 4]).unwrap();
+        let packet = follower.try_next().unwrap().unwrap();
+        assert_eq!(packet.data.0, [0x01, 0x03, 0x0C, 0x00]);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
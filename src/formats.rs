@@ -0,0 +1,135 @@
+//! Other capture file formats. They are read into, and written from, a [`Btsnoop`] capture with
+//! HCI UART (H4) packet data, so the rest of the crate works on them unchanged.
+
+use std::io::{self, Read};
+
+use crate::{
+    options::{Checker, ParseOptions},
+    Btsnoop, DatalinkType, Header, Packet, PacketData, PacketDescription, PacketFlags,
+    UartPacketType,
+};
+
+#[cfg(feature = "btsnooz")]
+pub mod btsnooz;
+pub mod le_ll;
+pub mod packet_logger;
+pub mod pcap;
+pub mod pcapng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Btsnoop,
+    Pcap,
+    Pcapng,
+    /// Apple PacketLogger (.pklg), read only
+    PacketLogger,
+    /// the BTSNOOP_LOG_SUMMARY section of an Android bug report, read only
+    Btsnooz,
+}
+
+impl Format {
+    /// The format of a file starting with these octets, for the formats with a magic number
+    pub fn detect(start: &[u8]) -> Option<Self> {
+        match start.get(..8)? {
+            b"btsnoop\0" => Some(Format::Btsnoop),
+            [0x0A, 0x0D, 0x0D, 0x0A, ..] => Some(Format::Pcapng),
+            [a, b, c, d, ..] if pcap::is_magic([*a, *b, *c, *d]) => Some(Format::Pcap),
+            _ => None,
+        }
+    }
+
+    /// The format usually stored with this file extension
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "log" | "cfa" | "btsnoop" => Some(Format::Btsnoop),
+            "pcap" | "cap" => Some(Format::Pcap),
+            "pcapng" => Some(Format::Pcapng),
+            "pklg" => Some(Format::PacketLogger),
+            "txt" => Some(Format::Btsnooz),
+            _ => None,
+        }
+    }
+}
+
+/// Read a capture of any format
+pub fn read<R: Read>(reader: &mut R, format: Format) -> io::Result<Btsnoop> {
+    read_with(reader, format, &ParseOptions::default())
+}
+
+/// Read a capture of any format. The packets are checked as they are read, like a btsnoop
+/// capture, except that pcapng files and btsnooz sections are read into memory whole before
+/// their packets are: the limits then bound the packets kept, not the memory used to read them.
+pub fn read_with<R: Read>(
+    reader: &mut R,
+    format: Format,
+    options: &ParseOptions,
+) -> io::Result<Btsnoop> {
+    let checker = &mut h4_checker(options)?;
+    match format {
+        Format::Btsnoop => Btsnoop::parse_with(reader, options),
+        Format::Pcap => pcap::read_checked(reader, checker),
+        Format::Pcapng => pcapng::read_checked(reader, checker),
+        Format::PacketLogger => packet_logger::read_checked(reader, checker),
+        #[cfg(feature = "btsnooz")]
+        Format::Btsnooz => btsnooz::read_checked(reader, checker),
+        #[cfg(not(feature = "btsnooz"))]
+        Format::Btsnooz => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "btsnooz support requires the btsnooz feature",
+        )),
+    }
+}
+
+/// The checker of the H4 packets another format is read into
+pub(crate) fn h4_checker(options: &ParseOptions) -> io::Result<Checker> {
+    Checker::new(options.clone(), &Header::default())
+}
+
+/// Check a packet read from another format, its data is dropped here unless kept
+pub(crate) fn check_packet(checker: &mut Checker, mut packet: Packet) -> io::Result<Packet> {
+    checker.check_description(&packet.description)?;
+    checker.accept(&mut packet)?;
+    Ok(packet)
+}
+
+/// H4 packet with the flags derived from the packet type and direction
+pub(crate) fn h4_packet(
+    unix_timestamp: i64,
+    received: bool,
+    data: Vec<u8>,
+    original_length: u32,
+) -> Packet {
+    let packet_type = data.first().and_then(|t| UartPacketType::try_from(*t).ok());
+    Packet {
+        description: PacketDescription {
+            original_length,
+            included_length: data.len() as u32,
+            flags: PacketFlags::for_uart(received, packet_type),
+            cumulative_drops: 0,
+            timestamp: unix_timestamp.saturating_add(PacketDescription::UNIX_EPOCH_OFFSET),
+        },
+        data: PacketData(data),
+    }
+}
+
+pub(crate) fn h4_capture(packets: Vec<Packet>) -> Btsnoop {
+    Btsnoop {
+        header: Header::default(),
+        packets,
+    }
+}
+
+/// The writers only take H4 packet data, see [`crate::transform::convert_datalink`]
+pub(crate) fn require_uart(capture: &Btsnoop) -> io::Result<()> {
+    match capture.header.datalink_type {
+        DatalinkType::Uart => Ok(()),
+        other => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} packets must be converted to HCI UART (H4) first", other),
+        )),
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
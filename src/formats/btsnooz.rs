@@ -0,0 +1,173 @@
+//! The btsnooz log kept by the Android Bluetooth stack and dumped in bug reports, between the
+//! `--- BEGIN:BTSNOOP_LOG_SUMMARY` and `--- END:BTSNOOP_LOG_SUMMARY` lines, base64 encoded.
+//!
+//! ```text
+//! -----------------------------------------------------------------
+//! | version 8 bit | last timestamp 64 bit, milliseconds            |
+//! -----------------------------------------------------------------
+//! | zlib compressed records                                        |
+//! -----------------------------------------------------------------
+//! ```
+//!
+//! A record is little endian: the length of the type and data, in version 2 the original
+//! packet length, the milliseconds since the previous record, the type and the data.
+
+use std::io::{self, BufRead, BufReader, Read};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::ZlibDecoder;
+
+use super::{check_packet, h4_capture, h4_checker, h4_packet, invalid_data};
+use crate::{
+    options::{Checker, ParseOptions},
+    Btsnoop, UartPacketType,
+};
+
+const BEGIN: &str = "--- BEGIN:BTSNOOP_LOG_SUMMARY";
+const END: &str = "--- END:BTSNOOP_LOG_SUMMARY";
+
+/// The H4 packet type and direction of a record type
+fn packet_type(record_type: u8) -> Option<(UartPacketType, bool)> {
+    match record_type {
+        0x10 => Some((UartPacketType::Evt, true)),
+        0x11 => Some((UartPacketType::Acl, true)),
+        0x12 => Some((UartPacketType::Sco, true)),
+        0x17 => Some((UartPacketType::Iso, true)),
+        0x20 => Some((UartPacketType::Cmd, false)),
+        0x21 => Some((UartPacketType::Acl, false)),
+        0x22 => Some((UartPacketType::Sco, false)),
+        0x2D => Some((UartPacketType::Iso, false)),
+        _ => None,
+    }
+}
+
+/// Read the btsnooz log of a bug report
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Btsnoop> {
+    read_checked(reader, &mut h4_checker(&ParseOptions::default())?)
+}
+
+/// The section is decoded whole, the checker only bounds the packets kept from it
+pub(crate) fn read_checked<R: Read>(reader: &mut R, checker: &mut Checker) -> io::Result<Btsnoop> {
+    let mut encoded = String::new();
+    let mut found = false;
+    for line in BufReader::new(reader).split(b'\n') {
+        // bug reports are not always valid UTF-8
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        if found {
+            if line.contains(END) {
+                let snooz = STANDARD
+                    .decode(&encoded)
+                    .map_err(|e| invalid_data(&e.to_string()))?;
+                return decode_checked(&snooz, checker);
+            }
+            encoded.push_str(line.trim());
+        } else if line.contains(BEGIN) {
+            found = true;
+        }
+    }
+    Err(invalid_data("no BTSNOOP_LOG_SUMMARY section"))
+}
+
+/// Decode the base64 decoded log summary
+pub fn decode(snooz: &[u8]) -> io::Result<Btsnoop> {
+    decode_checked(snooz, &mut h4_checker(&ParseOptions::default())?)
+}
+
+fn decode_checked(snooz: &[u8], checker: &mut Checker) -> io::Result<Btsnoop> {
+    if snooz.len() < 9 {
+        return Err(invalid_data("truncated btsnooz header"));
+    }
+    let version = snooz[0];
+    let last_timestamp = LittleEndian::read_i64(&snooz[1..9]) * 1000;
+    let record_header_len = match version {
+        1 => 7,
+        2 => 9,
+        _ => return Err(invalid_data("unsupported btsnooz version")),
+    };
+    let mut records = vec![];
+    ZlibDecoder::new(&snooz[9..]).read_to_end(&mut records)?;
+
+    // (length, original length, delta, type, data)
+    let mut parsed = vec![];
+    let mut data = &records[..];
+    while !data.is_empty() {
+        let header = data
+            .get(..record_header_len)
+            .ok_or_else(|| invalid_data("truncated btsnooz record"))?;
+        let length = LittleEndian::read_u16(header) as usize;
+        let (original_length, delta) = match version {
+            1 => (length, LittleEndian::read_u32(&header[2..])),
+            _ => (
+                LittleEndian::read_u16(&header[2..]) as usize,
+                LittleEndian::read_u32(&header[4..]),
+            ),
+        };
+        let record_type = header[record_header_len - 1];
+        let payload = data
+            .get(record_header_len..record_header_len + length.saturating_sub(1))
+            .ok_or_else(|| invalid_data("truncated btsnooz record"))?;
+        parsed.push((original_length, delta as i64 * 1000, record_type, payload));
+        data = &data[record_header_len + length.saturating_sub(1)..];
+    }
+
+    // deltas are relative to the previous record, only the last timestamp is known
+    let mut timestamp = last_timestamp - parsed.iter().map(|r| r.1).sum::<i64>();
+    let mut packets = vec![];
+    for (original_length, delta, record_type, payload) in parsed {
+        if checker.is_done() {
+            break;
+        }
+        timestamp += delta;
+        if let Some((packet_type, received)) = packet_type(record_type) {
+            checker.check_length(payload.len() as u32 + 1)?;
+            let mut h4 = vec![packet_type as u8];
+            h4.extend_from_slice(payload);
+            let packet = h4_packet(timestamp, received, h4, original_length as u32);
+            packets.push(check_packet(checker, packet)?);
+        }
+    }
+    Ok(h4_capture(packets))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn bug_report_section() {
+        let mut records = vec![];
+        // version 2 records: Reset command, then its Command Complete 5 ms later
+        records.extend_from_slice(&[0x04, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20]);
+        records.extend_from_slice(&[0x03, 0x0C, 0x00]);
+        records.extend_from_slice(&[0x07, 0x00, 0x07, 0x00, 0x05, 0x00, 0x00, 0x00, 0x10]);
+        records.extend_from_slice(&[0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]);
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&records).unwrap();
+
+        let mut snooz = vec![2];
+        snooz.extend_from_slice(&1_700_000_000_000i64.to_le_bytes());
+        snooz.extend_from_slice(&encoder.finish().unwrap());
+        let report = format!(
+            "== dumpsys bluetooth_manager\n{} (1 bytes)\n{}\n{}\nmore\n",
+            BEGIN,
+            STANDARD.encode(&snooz),
+            END
+        );
+
+        let capture = read(&mut report.as_bytes()).unwrap();
+        assert_eq!(capture.packets.len(), 2);
+        assert_eq!(capture.packets[0].data.0, [0x01, 0x03, 0x0C, 0x00]);
+        assert_eq!(capture.packets[1].description.flags.0, 0b11);
+        assert_eq!(
+            capture.packets[0].description.unix_timestamp(),
+            1_699_999_999_995_000
+        );
+        assert_eq!(
+            capture.packets[1].description.unix_timestamp(),
+            1_700_000_000_000_000
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! pcap captures of the LE link layer from an air sniffer, with the link types
+//! `LINKTYPE_BLUETOOTH_LE_LL` (251) and `LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR` (256). These are
+//! over the air packets, not HCI traffic, so they are read as [`LlPacket`]s instead of a
+//! [`Btsnoop`](crate::Btsnoop) capture.
+//!
+//! ```text
+//! with PHDR: | RF channel 8 bit | signal 8 bit | noise 8 bit | AA offenses 8 bit |
+//!            | reference access address 32 bit | flags 16 bit |
+//! ----------------------------------------------------------------------------------
+//! | access address 32 bit | header 16 bit | payload | CRC 24 bit |
+//! ```
+
+use std::io::{self, Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::{h4_capture, h4_packet, invalid_data, pcap};
+use crate::{Btsnoop, UartPacketType};
+
+/// Link type of packets without the pseudo header
+pub const LINKTYPE_LE_LL: u32 = 251;
+/// Link type of packets after the pseudo header
+pub const LINKTYPE_LE_LL_WITH_PHDR: u32 = 256;
+
+/// Access address of the advertising physical channel
+pub const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8E89_BED6;
+
+/// The flags of the pseudo header
+const FLAG_DEWHITENED: u16 = 0x0001;
+const FLAG_DECRYPTED: u16 = 0x0008;
+const FLAG_PDU_TYPE_SHIFT: u16 = 7;
+const FLAG_CRC_CHECKED: u16 = 0x0400;
+const FLAG_CRC_VALID: u16 = 0x0800;
+const FLAG_MIC_CHECKED: u16 = 0x1000;
+const FLAG_MIC_VALID: u16 = 0x2000;
+
+/// The device which sent a data channel PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LlRole {
+    Central,
+    Peripheral,
+}
+
+/// A link layer packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlPacket {
+    /// microseconds since the Unix epoch
+    pub timestamp: i64,
+    /// RF channel 0 to 39, when captured with the pseudo header
+    pub rf_channel: Option<u8>,
+    pub access_address: u32,
+    /// LLID, NESN, SN, MD and CP for data channel PDUs, then the length of the payload
+    pub header: [u8; 2],
+    /// the payload, with its MIC when encrypted
+    pub payload: Vec<u8>,
+    pub crc: [u8; 3],
+    /// the sender of a data channel PDU, when the sniffer knows it
+    pub sender: Option<LlRole>,
+    /// the payload was decrypted, its MIC is still there
+    pub decrypted: bool,
+}
+
+impl LlPacket {
+    pub const LLID_CONTINUATION: u8 = 0b01;
+    pub const LLID_START: u8 = 0b10;
+    pub const LLID_CONTROL: u8 = 0b11;
+
+    pub fn is_advertising(&self) -> bool {
+        self.access_address == ADVERTISING_ACCESS_ADDRESS
+    }
+
+    /// The LLID of a data channel PDU
+    pub fn llid(&self) -> u8 {
+        self.header[0] & 0b11
+    }
+
+    /// The sequence number of a data channel PDU
+    pub fn sn(&self) -> bool {
+        self.header[0] & 0b1000 != 0
+    }
+
+    /// The PDU type of an advertising channel PDU
+    pub fn advertising_pdu_type(&self) -> u8 {
+        self.header[0] & 0x0F
+    }
+}
+
+/// Read the link layer packets of a pcap file of link type 251 or 256
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Vec<LlPacket>> {
+    let header = pcap::read_header(reader)?;
+    let link_type = header.link_type;
+    if link_type != LINKTYPE_LE_LL && link_type != LINKTYPE_LE_LL_WITH_PHDR {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("link type {} is not Bluetooth LE link layer", link_type),
+        ));
+    }
+    let mut packets = vec![];
+    while let Some((timestamp, _, data)) = pcap::read_record(reader, &header)? {
+        packets.push(decode(
+            timestamp,
+            &data,
+            link_type == LINKTYPE_LE_LL_WITH_PHDR,
+        )?);
+    }
+    Ok(packets)
+}
+
+fn decode(timestamp: i64, mut data: &[u8], phdr: bool) -> io::Result<LlPacket> {
+    let mut rf_channel = None;
+    let mut sender = None;
+    let mut decrypted = false;
+    if phdr {
+        let header = data
+            .get(..10)
+            .ok_or_else(|| invalid_data("truncated LE pseudo header"))?;
+        rf_channel = Some(header[0]);
+        let flags = LittleEndian::read_u16(&header[8..]);
+        sender = match (flags >> FLAG_PDU_TYPE_SHIFT) & 0b111 {
+            2 => Some(LlRole::Central),
+            3 => Some(LlRole::Peripheral),
+            _ => None,
+        };
+        decrypted = flags & FLAG_DECRYPTED != 0;
+        data = &data[10..];
+    }
+    if data.len() < 9 {
+        return Err(invalid_data("truncated LE link layer packet"));
+    }
+    let access_address = LittleEndian::read_u32(data);
+    let (payload, crc) = data[6..].split_at(data.len() - 9);
+    Ok(LlPacket {
+        timestamp,
+        rf_channel,
+        access_address,
+        header: [data[4], data[5]],
+        payload: payload.to_vec(),
+        crc: [crc[0], crc[1], crc[2]],
+        sender,
+        decrypted,
+    })
+}
+
+/// Write link layer packets as a little endian, microsecond pcap file with the pseudo header,
+/// the decrypted ones marked as decrypted with a valid MIC
+pub fn write<W: Write>(packets: &[LlPacket], writer: &mut W) -> io::Result<()> {
+    pcap::write_header(writer, LINKTYPE_LE_LL_WITH_PHDR)?;
+    for packet in packets {
+        let mut flags = FLAG_DEWHITENED | FLAG_CRC_CHECKED | FLAG_CRC_VALID;
+        let pdu_type: u16 = match (packet.is_advertising(), packet.sender) {
+            (true, _) => 0,
+            (false, None) => 1,
+            (false, Some(LlRole::Central)) => 2,
+            (false, Some(LlRole::Peripheral)) => 3,
+        };
+        flags |= pdu_type << FLAG_PDU_TYPE_SHIFT;
+        if packet.decrypted {
+            flags |= FLAG_DECRYPTED | FLAG_MIC_CHECKED | FLAG_MIC_VALID;
+        }
+        let mut data = vec![packet.rf_channel.unwrap_or_default(), 0, 0, 0];
+        data.write_u32::<LittleEndian>(packet.access_address)?;
+        data.write_u16::<LittleEndian>(flags)?;
+        data.write_u32::<LittleEndian>(packet.access_address)?;
+        data.extend_from_slice(&packet.header);
+        data.extend_from_slice(&packet.payload);
+        data.extend_from_slice(&packet.crc);
+        pcap::write_record(writer, packet.timestamp, &data, data.len())?;
+    }
+    Ok(())
+}
+
+/// The L2CAP traffic of the data channel PDUs as the HCI ACL data of the Central, each
+/// connection with its own handle from 1, for the decoders of the crate. Their payloads must
+/// be plaintext: not encrypted or decrypted.
+pub fn acl_capture<'a>(packets: impl IntoIterator<Item = &'a LlPacket>) -> Btsnoop {
+    let mut handles: Vec<u32> = vec![];
+    let mut acl = vec![];
+    for packet in packets {
+        let llid = packet.llid();
+        if packet.is_advertising()
+            || !matches!(llid, LlPacket::LLID_START | LlPacket::LLID_CONTINUATION)
+        {
+            continue;
+        }
+        let mut payload = &packet.payload[..];
+        if packet.decrypted {
+            payload = &payload[..payload.len().saturating_sub(4)];
+        }
+        if payload.is_empty() {
+            continue;
+        }
+        let handle = match handles.iter().position(|aa| *aa == packet.access_address) {
+            Some(index) => index + 1,
+            None => {
+                handles.push(packet.access_address);
+                handles.len()
+            }
+        } as u16;
+        // first automatically flushable or continuing fragment
+        let boundary = if llid == LlPacket::LLID_START {
+            0b10
+        } else {
+            0b01
+        };
+        let mut data = vec![UartPacketType::Acl as u8];
+        data.extend_from_slice(&(handle | boundary << 12).to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        data.extend_from_slice(payload);
+        let received = packet.sender != Some(LlRole::Central);
+        let length = data.len() as u32;
+        acl.push(h4_packet(packet.timestamp, received, data, length));
+    }
+    h4_capture(acl)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let packets = vec![
+            LlPacket {
+                timestamp: 1_700_000_000_000_000,
+                rf_channel: Some(37),
+                access_address: ADVERTISING_ACCESS_ADDRESS,
+                header: [0x00, 0x06],
+                payload: vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66],
+                crc: [1, 2, 3],
+                sender: None,
+                decrypted: false,
+            },
+            LlPacket {
+                timestamp: 1_700_000_000_001_250,
+                rf_channel: Some(3),
+                access_address: 0x5065_4b8e,
+                header: [0x0f, 0x05],
+                payload: vec![0x9f, 0xcd, 0xa7, 0xf4, 0x48],
+                crc: [4, 5, 6],
+                sender: Some(LlRole::Central),
+                decrypted: false,
+            },
+        ];
+        let mut written = vec![];
+        write(&packets, &mut written).unwrap();
+        assert_eq!(read(&mut &written[..]).unwrap(), packets);
+
+        let mut start = packets[1].clone();
+        start.header = [0x02, 0x08];
+        start.payload = vec![0x04, 0x00, 0x04, 0x00, 0x0a, 0x03, 0x00, 0x00];
+        let capture = acl_capture(&[packets[0].clone(), packets[1].clone(), start]);
+        assert_eq!(capture.packets.len(), 1);
+        assert_eq!(
+            capture.packets[0].data.0[..5],
+            [0x02, 0x01, 0x20, 0x08, 0x00]
+        );
+        assert!(!capture.packets[0].description.flags.is_received());
+        assert_eq!(packets[1].llid(), LlPacket::LLID_CONTROL);
+        assert!(packets[1].sn());
+    }
+}
@@ -0,0 +1,254 @@
+//! Apple PacketLogger (.pklg) captures, as saved by PacketLogger on macOS and iOS.
+//!
+//! ```text
+//! ----------------------------------------------------------------
+//! | length 32 bit, of the rest of the record                      |
+//! ----------------------------------------------------------------
+//! | seconds 32 bit | microseconds 32 bit | type 8 bit | data      |
+//! ----------------------------------------------------------------
+//! ```
+//!
+//! Older files are big endian, newer ones little endian. [`Reader`] converts the records as they
+//! arrive, e.g. of a capture PacketLogger is still writing, followed with
+//! `tail -c +1 -f capture.pklg`.
+//!
+//! This is not a live capture source for macOS: the `log stream` output of the Bluetooth
+//! logging profile only carries the text messages of the Bluetooth daemon, not HCI records,
+//! and PacketLogger's own live feed is not a documented interface. The packets of a Mac only
+//! reach this crate through a .pklg file PacketLogger saved or is writing.
+
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use super::{check_packet, h4_capture, h4_checker, h4_packet, invalid_data};
+use crate::{
+    options::{Checker, ParseOptions},
+    Btsnoop, Packet, UartPacketType,
+};
+
+/// timestamp and type
+const RECORD_HEADER_LEN: usize = 9;
+
+/// The largest H4 packet, an ACL packet with a full 16 bit length, after the record header
+const MAX_RECORD_LEN: usize = RECORD_HEADER_LEN + 4 + 0xFFFF;
+
+/// The H4 packet type and direction of a record type, none for the records which are not HCI
+/// traffic, e.g. notes, power events or syslog lines
+fn packet_type(record_type: u8) -> Option<(UartPacketType, bool)> {
+    match record_type {
+        0x00 => Some((UartPacketType::Cmd, false)),
+        0x01 => Some((UartPacketType::Evt, true)),
+        0x02 => Some((UartPacketType::Acl, false)),
+        0x03 => Some((UartPacketType::Acl, true)),
+        0x08 => Some((UartPacketType::Sco, false)),
+        0x09 => Some((UartPacketType::Sco, true)),
+        _ => None,
+    }
+}
+
+/// Read a whole capture
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Btsnoop> {
+    read_checked(reader, &mut h4_checker(&ParseOptions::default())?)
+}
+
+pub(crate) fn read_checked<R: Read>(reader: &mut R, checker: &mut Checker) -> io::Result<Btsnoop> {
+    let mut reader = Reader::new(reader);
+    let mut packets = vec![];
+    while !checker.is_done() {
+        let Some(packet) = reader.next_checked_packet(|length| checker.check_length(length))?
+        else {
+            break;
+        };
+        packets.push(check_packet(checker, packet)?);
+    }
+    Ok(h4_capture(packets))
+}
+
+/// Reads the HCI packets of a PacketLogger record stream as they arrive, e.g. from a pipe while
+/// PacketLogger is capturing. The byte order is detected from the first record.
+#[derive(Debug)]
+pub struct Reader<R> {
+    reader: R,
+    big_endian: Option<bool>,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            big_endian: None,
+        }
+    }
+
+    /// The next HCI packet, none at the end of the stream
+    pub fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        self.next_checked_packet(|_| Ok(()))
+    }
+
+    /// [`Reader::next_packet`], with a check of the H4 length of a packet before its data is
+    /// read
+    fn next_checked_packet(
+        &mut self,
+        check_length: impl Fn(u32) -> io::Result<()>,
+    ) -> io::Result<Option<Packet>> {
+        loop {
+            let mut len = [0; 4];
+            match self.reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            // a record is at least a timestamp and a type, and big endian lengths of little
+            // endian files are huge
+            let big_endian = *self.big_endian.get_or_insert_with(|| {
+                (RECORD_HEADER_LEN as u32..=0xFFFF).contains(&BigEndian::read_u32(&len))
+            });
+            let len = if big_endian {
+                BigEndian::read_u32(&len)
+            } else {
+                LittleEndian::read_u32(&len)
+            } as usize;
+            if len < RECORD_HEADER_LEN {
+                return Err(invalid_data("truncated PacketLogger record"));
+            }
+            if len > MAX_RECORD_LEN {
+                return Err(invalid_data(
+                    "PacketLogger record longer than any HCI packet",
+                ));
+            }
+            let mut header = [0; RECORD_HEADER_LEN];
+            self.reader.read_exact(&mut header)?;
+            let packet_type = packet_type(header[8]);
+            let data_len = len - RECORD_HEADER_LEN;
+            if packet_type.is_some() {
+                // the record type becomes the H4 packet type
+                check_length(data_len as u32 + 1)?;
+            }
+            // not allocated upfront, the length may come from a corrupt stream
+            let mut record = vec![0];
+            (&mut self.reader)
+                .take(data_len as u64)
+                .read_to_end(&mut record)?;
+            if record.len() < 1 + data_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "PacketLogger record shorter than its length",
+                ));
+            }
+
+            let (seconds, micros) = if big_endian {
+                (
+                    BigEndian::read_u32(&header),
+                    BigEndian::read_u32(&header[4..]),
+                )
+            } else {
+                (
+                    LittleEndian::read_u32(&header),
+                    LittleEndian::read_u32(&header[4..]),
+                )
+            };
+            if let Some((packet_type, received)) = packet_type {
+                record[0] = packet_type as u8;
+                let original_length = record.len() as u32;
+                return Ok(Some(h4_packet(
+                    seconds as i64 * 1_000_000 + micros as i64,
+                    received,
+                    record,
+                    original_length,
+                )));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(file: &mut Vec<u8>, big_endian: bool, len: u32, micros: u32, record_type: u8) {
+        for field in [len, 1_700_000_000, micros] {
+            if big_endian {
+                file.extend_from_slice(&field.to_be_bytes());
+            } else {
+                file.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        file.push(record_type);
+    }
+
+    #[test]
+    fn both_byte_orders() {
+        let records: [(u8, &[u8]); 3] = [
+            (0x00, &[0x03, 0x0C, 0x00]),
+            // note
+            (0xFC, b"hello"),
+            (0x01, &[0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]),
+        ];
+        for big_endian in [true, false] {
+            let mut file = vec![];
+            for (index, (record_type, data)) in records.iter().enumerate() {
+                let len = (RECORD_HEADER_LEN + data.len()) as u32;
+                record(&mut file, big_endian, len, index as u32, *record_type);
+                file.extend_from_slice(data);
+            }
+
+            let capture = read(&mut &file[..]).unwrap();
+            assert_eq!(capture.packets.len(), 2);
+            assert_eq!(capture.packets[0].data.0, [0x01, 0x03, 0x0C, 0x00]);
+            assert_eq!(capture.packets[0].description.flags.0, 0b10);
+            assert_eq!(capture.packets[1].description.flags.0, 0b11);
+            assert_eq!(
+                capture.packets[1].description.unix_timestamp(),
+                1_700_000_000_000_002
+            );
+        }
+    }
+
+    #[test]
+    fn next_packet() {
+        for big_endian in [true, false] {
+            let mut file = vec![];
+            record(&mut file, big_endian, RECORD_HEADER_LEN as u32 + 3, 5, 0x02);
+            file.extend_from_slice(&[0x01, 0x20, 0x00]);
+
+            let mut reader = Reader::new(&file[..]);
+            let packet = reader.next_packet().unwrap().unwrap();
+            assert_eq!(packet.data.0, [0x02, 0x01, 0x20, 0x00]);
+            assert_eq!(packet.description.flags.0, 0b00);
+            assert_eq!(packet.description.unix_timestamp(), 1_700_000_000_000_005);
+            assert!(reader.next_packet().unwrap().is_none());
+
+            // the record stops before its length
+            let mut truncated = file.clone();
+            record(
+                &mut truncated,
+                big_endian,
+                RECORD_HEADER_LEN as u32 + 8,
+                6,
+                0x03,
+            );
+            truncated.extend_from_slice(&[0x01, 0x20]);
+            let mut reader = Reader::new(&truncated[..]);
+            assert!(reader.next_packet().unwrap().is_some());
+            let error = reader.next_packet().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+            // rejected before reading, nothing follows the header
+            let mut oversized = file.clone();
+            record(&mut oversized, big_endian, u32::MAX, 7, 0x03);
+            let mut reader = Reader::new(&oversized[..]);
+            assert!(reader.next_packet().unwrap().is_some());
+            let error = reader.next_packet().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
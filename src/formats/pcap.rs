@@ -0,0 +1,381 @@
+//! libpcap capture files with one of the Bluetooth HCI link types.
+//!
+//! ```text
+//! ------------------------------------------------------------
+//! | magic 32 bit | version 2 x 16 bit | zone, sigfigs 2 x 32 bit |
+//! | snapshot length 32 bit | link type 32 bit                  |
+//! ------------------------------------------------------------
+//! | seconds 32 bit | micro or nanoseconds 32 bit              |
+//! | included length 32 bit | original length 32 bit | data    |
+//! ------------------------------------------------------------
+//! | ...                                                       |
+//! ------------------------------------------------------------
+//! ```
+
+use std::io::{self, Read, Write};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::{check_packet, h4_capture, h4_checker, h4_packet, invalid_data, require_uart};
+use crate::{
+    analysis::is_received,
+    options::{Checker, ParseOptions},
+    Btsnoop, Packet, UartPacketType,
+};
+
+const MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+/// written in the header, packets are never truncated
+const SNAPSHOT_LENGTH: u32 = 0x0004_0000;
+
+/// Bluetooth link types, shared with pcapng
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    /// H4 packets without the direction
+    H4 = 187,
+    /// H4 packets after a 32 bit big endian direction, 1 = received
+    H4WithPhdr = 201,
+    /// Linux monitor socket packets, after the controller index and opcode
+    LinuxMonitor = 254,
+}
+
+impl LinkType {
+    pub fn from_raw(link_type: u32) -> Option<Self> {
+        match link_type {
+            187 => Some(LinkType::H4),
+            201 => Some(LinkType::H4WithPhdr),
+            254 => Some(LinkType::LinuxMonitor),
+            _ => None,
+        }
+    }
+
+    /// The direction and H4 packet of a captured packet, none for the monitor packets without
+    /// HCI traffic, e.g. controller index or system notes.
+    ///
+    /// [`LinkType::H4`] packets carry no direction: events are taken as received and every
+    /// other packet as sent.
+    pub(crate) fn decode(&self, data: &[u8]) -> io::Result<Option<(bool, Vec<u8>)>> {
+        match self {
+            LinkType::H4 => Ok(Some((
+                data.first() == Some(&(UartPacketType::Evt as u8)),
+                data.to_vec(),
+            ))),
+            LinkType::H4WithPhdr => {
+                let direction = data
+                    .get(..4)
+                    .ok_or_else(|| invalid_data("truncated direction header"))?;
+                Ok(Some((
+                    BigEndian::read_u32(direction) & 1 == 1,
+                    data[4..].to_vec(),
+                )))
+            }
+            LinkType::LinuxMonitor => {
+                let header = data
+                    .get(..4)
+                    .ok_or_else(|| invalid_data("truncated monitor header"))?;
+                let (packet_type, received) = match BigEndian::read_u16(&header[2..]) {
+                    2 => (UartPacketType::Cmd, false),
+                    3 => (UartPacketType::Evt, true),
+                    4 => (UartPacketType::Acl, false),
+                    5 => (UartPacketType::Acl, true),
+                    6 => (UartPacketType::Sco, false),
+                    7 => (UartPacketType::Sco, true),
+                    18 => (UartPacketType::Iso, false),
+                    19 => (UartPacketType::Iso, true),
+                    _ => return Ok(None),
+                };
+                let mut h4 = vec![packet_type as u8];
+                h4.extend_from_slice(&data[4..]);
+                Ok(Some((received, h4)))
+            }
+        }
+    }
+
+    /// The captured packet of an H4 packet
+    pub(crate) fn encode(&self, packet: &Packet) -> Vec<u8> {
+        let data = &packet.data.0;
+        match self {
+            LinkType::H4 => data.clone(),
+            LinkType::H4WithPhdr => {
+                let mut encoded = (is_received(packet) as u32).to_be_bytes().to_vec();
+                encoded.extend_from_slice(data);
+                encoded
+            }
+            LinkType::LinuxMonitor => {
+                let received = is_received(packet) as u16;
+                let opcode = match data.first().map(|t| UartPacketType::try_from(*t)) {
+                    Some(Ok(UartPacketType::Cmd)) => 2,
+                    Some(Ok(UartPacketType::Evt)) => 3,
+                    Some(Ok(UartPacketType::Acl)) => 4 + received,
+                    Some(Ok(UartPacketType::Sco)) => 6 + received,
+                    Some(Ok(UartPacketType::Iso)) => 18 + received,
+                    // vendor diagnostic
+                    _ => 17,
+                };
+                // controller index 0
+                let mut encoded = vec![0, 0];
+                encoded.extend_from_slice(&opcode.to_be_bytes());
+                encoded.extend_from_slice(data.get(1..).unwrap_or_default());
+                encoded
+            }
+        }
+    }
+
+    /// Octets the link type adds to, or removes from, an H4 packet
+    pub(crate) fn overhead(&self) -> i64 {
+        match self {
+            LinkType::H4 => 0,
+            LinkType::H4WithPhdr => 4,
+            LinkType::LinuxMonitor => 3,
+        }
+    }
+}
+
+pub(crate) fn is_magic(start: [u8; 4]) -> bool {
+    [MAGIC_MICROS, MAGIC_NANOS]
+        .iter()
+        .any(|magic| magic.to_be_bytes() == start || magic.to_le_bytes() == start)
+}
+
+/// The link type, byte order and timestamp resolution of a pcap file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FileHeader {
+    pub link_type: u32,
+    /// the largest included length of a record
+    snapshot_length: u32,
+    nanos: bool,
+    big_endian: bool,
+}
+
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> io::Result<FileHeader> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    let (nanos, big_endian) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (MAGIC_MICROS, _) => (false, false),
+        (MAGIC_NANOS, _) => (true, false),
+        (_, MAGIC_MICROS) => (false, true),
+        (_, MAGIC_NANOS) => (true, true),
+        _ => return Err(invalid_data("not a pcap file")),
+    };
+    // version, zone and sigfigs
+    let mut skipped = [0; 12];
+    reader.read_exact(&mut skipped)?;
+    let mut fields = [0; 8];
+    reader.read_exact(&mut fields)?;
+    let mut values = [0; 2];
+    match big_endian {
+        true => BigEndian::read_u32_into(&fields, &mut values),
+        false => LittleEndian::read_u32_into(&fields, &mut values),
+    }
+    let [snapshot_length, link_type] = values;
+    Ok(FileHeader {
+        // the upper bits are the FCS length
+        link_type: link_type & 0x0FFF_FFFF,
+        // some writers leave it unset, libpcap then takes its own maximum
+        snapshot_length: match snapshot_length {
+            0 => SNAPSHOT_LENGTH,
+            length => length,
+        },
+        nanos,
+        big_endian,
+    })
+}
+
+/// The timestamp in microseconds since the Unix epoch, original length and data of the next
+/// record, none at the end of the file
+pub(crate) fn read_record<R: Read>(
+    reader: &mut R,
+    header: &FileHeader,
+) -> io::Result<Option<(i64, u32, Vec<u8>)>> {
+    read_checked_record(reader, header, |_| Ok(()))
+}
+
+/// [`read_record`], with a check of the included length before the data is read
+fn read_checked_record<R: Read>(
+    reader: &mut R,
+    header: &FileHeader,
+    check_length: impl FnOnce(u32) -> io::Result<()>,
+) -> io::Result<Option<(i64, u32, Vec<u8>)>> {
+    let mut fields = [0; 16];
+    match reader.read_exact(&mut fields[..4]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    reader.read_exact(&mut fields[4..])?;
+    let mut values = [0; 4];
+    match header.big_endian {
+        true => BigEndian::read_u32_into(&fields, &mut values),
+        false => LittleEndian::read_u32_into(&fields, &mut values),
+    }
+    let [seconds, fraction, included_length, original_length] = values;
+    if included_length > header.snapshot_length {
+        return Err(invalid_data(
+            "pcap record longer than the snapshot length of the file",
+        ));
+    }
+    check_length(included_length)?;
+    // not allocated upfront, the snapshot length of a corrupt header can be anything too
+    let mut data = vec![];
+    reader.take(included_length as u64).read_to_end(&mut data)?;
+    if data.len() < included_length as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "pcap record shorter than its included length",
+        ));
+    }
+    let micros = if header.nanos {
+        fraction / 1000
+    } else {
+        fraction
+    };
+    let timestamp = seconds as i64 * 1_000_000 + micros as i64;
+    Ok(Some((timestamp, original_length, data)))
+}
+
+/// Little endian, microsecond resolution
+pub(crate) fn write_header<W: Write>(writer: &mut W, link_type: u32) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(MAGIC_MICROS)?;
+    writer.write_u16::<LittleEndian>(2)?;
+    writer.write_u16::<LittleEndian>(4)?;
+    writer.write_i32::<LittleEndian>(0)?;
+    writer.write_u32::<LittleEndian>(0)?;
+    writer.write_u32::<LittleEndian>(SNAPSHOT_LENGTH)?;
+    writer.write_u32::<LittleEndian>(link_type)
+}
+
+/// A record of a timestamp in microseconds since the Unix epoch, which pcap's unsigned 32 bit
+/// seconds only hold from 1970 to 2106
+pub(crate) fn write_record<W: Write>(
+    writer: &mut W,
+    timestamp: i64,
+    data: &[u8],
+    original_length: usize,
+) -> io::Result<()> {
+    let seconds = u32::try_from(timestamp.div_euclid(1_000_000)).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("timestamp {} out of the range of pcap", timestamp),
+        )
+    })?;
+    writer.write_u32::<LittleEndian>(seconds)?;
+    writer.write_u32::<LittleEndian>(timestamp.rem_euclid(1_000_000) as u32)?;
+    writer.write_u32::<LittleEndian>(data.len() as u32)?;
+    writer.write_u32::<LittleEndian>(original_length as u32)?;
+    writer.write_all(data)
+}
+
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Btsnoop> {
+    read_checked(reader, &mut h4_checker(&ParseOptions::default())?)
+}
+
+pub(crate) fn read_checked<R: Read>(reader: &mut R, checker: &mut Checker) -> io::Result<Btsnoop> {
+    let header = read_header(reader)?;
+    let link_type = LinkType::from_raw(header.link_type).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("link type {} is not Bluetooth HCI", header.link_type),
+        )
+    })?;
+
+    let mut packets = vec![];
+    while !checker.is_done() {
+        let check_length = |included_length: u32| {
+            checker.check_length((included_length as i64 - link_type.overhead()).max(0) as u32)
+        };
+        let Some((timestamp, original_length, data)) =
+            read_checked_record(reader, &header, check_length)?
+        else {
+            break;
+        };
+        if let Some((received, h4)) = link_type.decode(&data)? {
+            let original_length = (original_length as i64 - link_type.overhead()).max(0) as u32;
+            let packet = h4_packet(timestamp, received, h4, original_length);
+            packets.push(check_packet(checker, packet)?);
+        }
+    }
+    Ok(h4_capture(packets))
+}
+
+/// Write an H4 capture as a little endian, microsecond pcap file
+pub fn write<W: Write>(capture: &Btsnoop, link_type: LinkType, writer: &mut W) -> io::Result<()> {
+    require_uart(capture)?;
+    write_header(writer, link_type as u32)?;
+    for packet in &capture.packets {
+        let data = link_type.encode(packet);
+        let original_length = packet.description.original_length as i64 + link_type.overhead();
+        write_record(
+            writer,
+            packet.description.unix_timestamp(),
+            &data,
+            original_length as usize,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut file: &[u8] = include_bytes!("../../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse(&mut file).unwrap();
+        for link_type in [LinkType::H4WithPhdr, LinkType::LinuxMonitor] {
+            let mut written = vec![];
+            write(&capture, link_type, &mut written).unwrap();
+            let read = read(&mut &written[..]).unwrap();
+
+            assert_eq!(read.packets.len(), capture.packets.len());
+            for (read, original) in read.packets.iter().zip(&capture.packets) {
+                assert_eq!(read.data.0, original.data.0);
+                assert_eq!(read.description.flags.0, original.description.flags.0);
+                assert_eq!(read.description.timestamp, original.description.timestamp);
+                assert_eq!(
+                    read.description.original_length,
+                    original.description.original_length
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn record_longer_than_snapshot_length() {
+        let mut file = vec![];
+        write_header(&mut file, LinkType::H4 as u32).unwrap();
+        file.extend_from_slice(&0u64.to_le_bytes());
+        file.extend_from_slice(&(SNAPSHOT_LENGTH + 1).to_le_bytes());
+        file.extend_from_slice(&(SNAPSHOT_LENGTH + 1).to_le_bytes());
+
+        let error = read(&mut &file[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // within the snapshot length, but the data stops short
+        let len = file.len();
+        file[len - 8..len - 4].copy_from_slice(&4u32.to_le_bytes());
+        file.push(0x01);
+        let error = read(&mut &file[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn timestamp_out_of_range() {
+        for timestamp in [-1, (u32::MAX as i64 + 1) * 1_000_000] {
+            let error = write_record(&mut vec![], timestamp, &[0x01], 1).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        let mut record = vec![];
+        write_record(
+            &mut record,
+            u32::MAX as i64 * 1_000_000 + 999_999,
+            &[0x01],
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            record[..8],
+            [0xff, 0xff, 0xff, 0xff, 0x3f, 0x42, 0x0f, 0x00]
+        );
+    }
+}
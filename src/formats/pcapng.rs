@@ -0,0 +1,290 @@
+//! pcapng capture files with one of the Bluetooth HCI link types, see [`LinkType`].
+//!
+//! ```text
+//! ------------------------------------------------------------
+//! | block type 32 bit | block total length 32 bit            |
+//! ------------------------------------------------------------
+//! | block body, padded to 32 bit                             |
+//! ------------------------------------------------------------
+//! | block total length 32 bit                                |
+//! ------------------------------------------------------------
+//! ```
+//!
+//! A file is a section header block followed by interface description blocks and packet blocks.
+//! The byte order is chosen by each section header.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::{check_packet, h4_capture, h4_checker, h4_packet, invalid_data, require_uart};
+use crate::{
+    options::{Checker, ParseOptions},
+    Btsnoop, Packet,
+};
+
+pub use super::pcap::LinkType;
+
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const SIMPLE_PACKET: u32 = 0x0000_0003;
+const ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// interface option with the timestamp resolution
+const IF_TSRESOL: u16 = 9;
+
+struct Interface {
+    /// none for link types other than Bluetooth HCI, their packets are skipped
+    link_type: Option<LinkType>,
+    /// timestamp units per second
+    units_per_second: u64,
+}
+
+/// Block body reader in the byte order of the current section
+struct Body<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Body<'_> {
+    fn u16(&self, offset: usize) -> io::Result<u16> {
+        let bytes = self
+            .data
+            .get(offset..offset + 2)
+            .ok_or_else(|| invalid_data("truncated pcapng block"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> io::Result<u32> {
+        let bytes = self
+            .data
+            .get(offset..offset + 4)
+            .ok_or_else(|| invalid_data("truncated pcapng block"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> io::Result<&[u8]> {
+        self.data
+            .get(offset..offset + len)
+            .ok_or_else(|| invalid_data("truncated pcapng block"))
+    }
+
+    /// The timestamp resolution option of an interface description, microseconds by default
+    fn units_per_second(&self, mut offset: usize) -> io::Result<u64> {
+        while offset + 4 <= self.data.len() {
+            let code = self.u16(offset)?;
+            let len = self.u16(offset + 2)? as usize;
+            if code == 0 {
+                break;
+            }
+            if code == IF_TSRESOL && len >= 1 {
+                let resolution = self.bytes(offset + 4, 1)?[0];
+                let exponent = (resolution & 0x7F) as u32;
+                let base: u64 = if resolution & 0x80 == 0 { 10 } else { 2 };
+                return base
+                    .checked_pow(exponent)
+                    .ok_or_else(|| invalid_data("invalid timestamp resolution"));
+            }
+            offset += 4 + len.next_multiple_of(4);
+        }
+        Ok(1_000_000)
+    }
+}
+
+pub fn read<R: Read>(reader: &mut R) -> io::Result<Btsnoop> {
+    read_checked(reader, &mut h4_checker(&ParseOptions::default())?)
+}
+
+/// The file is read whole, the checker only bounds the packets decoded from it
+pub(crate) fn read_checked<R: Read>(reader: &mut R, checker: &mut Checker) -> io::Result<Btsnoop> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+
+    let mut packets = vec![];
+    let mut interfaces: Vec<Interface> = vec![];
+    let mut big_endian = false;
+    let mut offset = 0;
+    while offset + 12 <= data.len() && !checker.is_done() {
+        let block_type = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        if block_type == SECTION_HEADER {
+            let magic = &data[offset + 8..offset + 12];
+            big_endian = if magic == BYTE_ORDER_MAGIC.to_be_bytes() {
+                true
+            } else if magic == BYTE_ORDER_MAGIC.to_le_bytes() {
+                false
+            } else {
+                return Err(invalid_data("invalid pcapng byte order magic"));
+            };
+            interfaces.clear();
+        }
+        let header = Body {
+            data: &data[offset..],
+            big_endian,
+        };
+        let block_type = header.u32(0)?;
+        let total_length = header.u32(4)? as usize;
+        if total_length < 12
+            || total_length % 4 != 0
+            || offset + total_length > data.len()
+        {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+        let body = Body {
+            data: &data[offset + 8..offset + total_length - 4],
+            big_endian,
+        };
+
+        match block_type {
+            INTERFACE_DESCRIPTION => interfaces.push(Interface {
+                link_type: LinkType::from_raw(body.u16(0)? as u32),
+                units_per_second: body.units_per_second(8)?,
+            }),
+            ENHANCED_PACKET => {
+                let interface = interfaces
+                    .get(body.u32(0)? as usize)
+                    .ok_or_else(|| invalid_data("packet of an undescribed interface"))?;
+                let units = (body.u32(4)? as u64) << 32 | body.u32(8)? as u64;
+                let captured_length = body.u32(12)? as usize;
+                let original_length = body.u32(16)?;
+                let captured = body.bytes(20, captured_length)?;
+                if let Some(link_type) = interface.link_type {
+                    let h4_length = (captured_length as i64 - link_type.overhead()).max(0);
+                    checker.check_length(h4_length as u32)?;
+                }
+                let timestamp =
+                    (units as u128 * 1_000_000 / interface.units_per_second as u128) as i64;
+                if let Some(link_type) = interface.link_type {
+                    if let Some((received, h4)) = link_type.decode(captured)? {
+                        let original_length =
+                            (original_length as i64 - link_type.overhead()).max(0) as u32;
+                        let packet = h4_packet(timestamp, received, h4, original_length);
+                        packets.push(check_packet(checker, packet)?);
+                    }
+                }
+            }
+            // no timestamp, the packets are timed like the previous one
+            SIMPLE_PACKET => {
+                let link_type = interfaces.first().and_then(|i| i.link_type);
+                let original_length = body.u32(0)?;
+                let captured =
+                    body.bytes(4, (original_length as usize).min(body.data.len() - 4))?;
+                if let Some(link_type) = link_type {
+                    let h4_length = (captured.len() as i64 - link_type.overhead()).max(0);
+                    checker.check_length(h4_length as u32)?;
+                    if let Some((received, h4)) = link_type.decode(captured)? {
+                        let timestamp = packets
+                            .last()
+                            .map(|p| p.description.unix_timestamp())
+                            .unwrap_or_default();
+                        let original_length =
+                            (original_length as i64 - link_type.overhead()).max(0) as u32;
+                        let packet = h4_packet(timestamp, received, h4, original_length);
+                        packets.push(check_packet(checker, packet)?);
+                    }
+                }
+            }
+            _ => {}
+        }
+        offset += total_length;
+    }
+    Ok(h4_capture(packets))
+}
+
+/// Write an H4 capture as a little endian pcapng file with a single interface, timestamps in
+/// microseconds
+pub fn write<W: Write>(capture: &Btsnoop, link_type: LinkType, writer: &mut W) -> io::Result<()> {
+    require_uart(capture)?;
+    let mut writer = Writer::new(writer, link_type)?;
+    for packet in &capture.packets {
+        writer.write_packet(packet)?;
+    }
+    Ok(())
+}
+
+/// Writes H4 packets one at a time, e.g. to a pipe read live by Wireshark
+#[derive(Debug)]
+pub struct Writer<W> {
+    writer: W,
+    link_type: LinkType,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the section header and the interface description
+    pub fn new(mut writer: W, link_type: LinkType) -> io::Result<Self> {
+        let mut section = vec![];
+        section.write_u32::<LittleEndian>(BYTE_ORDER_MAGIC)?;
+        section.write_u16::<LittleEndian>(1)?;
+        section.write_u16::<LittleEndian>(0)?;
+        // unknown section length
+        section.write_i64::<LittleEndian>(-1)?;
+        write_block(&mut writer, SECTION_HEADER, &section)?;
+
+        let mut interface = vec![];
+        interface.write_u16::<LittleEndian>(link_type as u16)?;
+        interface.write_u16::<LittleEndian>(0)?;
+        // no snapshot length limit
+        interface.write_u32::<LittleEndian>(0)?;
+        write_block(&mut writer, INTERFACE_DESCRIPTION, &interface)?;
+        Ok(Self { writer, link_type })
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        let timestamp = packet.description.unix_timestamp() as u64;
+        let data = self.link_type.encode(packet);
+        let original_length = packet.description.original_length as i64 + self.link_type.overhead();
+        let mut body = vec![];
+        body.write_u32::<LittleEndian>(0)?;
+        body.write_u32::<LittleEndian>((timestamp >> 32) as u32)?;
+        body.write_u32::<LittleEndian>(timestamp as u32)?;
+        body.write_u32::<LittleEndian>(data.len() as u32)?;
+        body.write_u32::<LittleEndian>(original_length as u32)?;
+        body.extend_from_slice(&data);
+        write_block(&mut self.writer, ENHANCED_PACKET, &body)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let padding = body.len().next_multiple_of(4) - body.len();
+    let total_length = (12 + body.len() + padding) as u32;
+    writer.write_u32::<LittleEndian>(block_type)?;
+    writer.write_u32::<LittleEndian>(total_length)?;
+    writer.write_all(body)?;
+    writer.write_all(&[0; 3][..padding])?;
+    writer.write_u32::<LittleEndian>(total_length)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut file: &[u8] = include_bytes!("../../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse(&mut file).unwrap();
+        let mut written = vec![];
+        write(&capture, LinkType::H4WithPhdr, &mut written).unwrap();
+        assert_eq!(written.len() % 4, 0);
+
+        let read = read(&mut &written[..]).unwrap();
+        assert_eq!(read.packets.len(), capture.packets.len());
+        for (read, original) in read.packets.iter().zip(&capture.packets) {
+            assert_eq!(read.data.0, original.data.0);
+            assert_eq!(read.description.flags.0, original.description.flags.0);
+            assert_eq!(read.description.timestamp, original.description.timestamp);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! GATT attribute types, learned from the discovery procedures of a capture.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::att::Pdu;
+
+// data format from: Bluetooth core specification 5.4 Vol 3: Host Part G Generic Attribute Profile
+
+/// A 128 bit UUID, 16 and 32 bit UUIDs are aliases in the Bluetooth Base UUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// 00000000-0000-1000-8000-00805F9B34FB
+    pub const BASE: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+
+    pub const PRIMARY_SERVICE: Uuid = Uuid::from_u16(0x2800);
+    pub const CHARACTERISTIC: Uuid = Uuid::from_u16(0x2803);
+
+    pub const fn from_u16(uuid: u16) -> Self {
+        Self::from_u32(uuid as u32)
+    }
+
+    pub const fn from_u32(uuid: u32) -> Self {
+        Uuid(Self::BASE | (uuid as u128) << 96)
+    }
+
+    /// The 16 bit alias of a UUID in the Bluetooth Base UUID
+    pub fn as_u16(&self) -> Option<u16> {
+        let alias = self.0 >> 96;
+        (self.0 & ((1 << 96) - 1) == Self::BASE && alias <= 0xFFFF).then_some(alias as u16)
+    }
+
+    /// The 128 bit form even of aliases, e.g. `0000180d-0000-1000-8000-00805f9b34fb`
+    pub fn to_full_string(&self) -> String {
+        let v = self.0;
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            v >> 96,
+            (v >> 80) & 0xFFFF,
+            (v >> 64) & 0xFFFF,
+            (v >> 48) & 0xFFFF,
+            v & 0xFFFF_FFFF_FFFF
+        )
+    }
+
+    /// A 16, 32 or 128 bit UUID in little endian, as in ATT PDUs
+    pub fn from_le_bytes(data: &[u8]) -> Option<Self> {
+        match data.len() {
+            2 => Some(Self::from_u16(u16::from_le_bytes([data[0], data[1]]))),
+            4 => Some(Self::from_u32(u32::from_le_bytes(data.try_into().ok()?))),
+            16 => Some(Uuid(u128::from_le_bytes(data.try_into().ok()?))),
+            _ => None,
+        }
+    }
+}
+
+/// `0x180d` for 16 bit aliases, `6e400001-b5a3-f393-e0a9-e50e24dcca9e` otherwise
+impl Display for Uuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.as_u16() {
+            Some(alias) => write!(f, "0x{:04x}", alias),
+            None => f.write_str(&self.to_full_string()),
+        }
+    }
+}
+
+impl From<u16> for Uuid {
+    fn from(uuid: u16) -> Self {
+        Self::from_u16(uuid)
+    }
+}
+
+/// Follows the ATT bearers to know the type of the attributes of each connection, from the
+/// Find Information and characteristic discovery responses, and which attribute a Read
+/// Response is the value of.
+#[derive(Debug, Default)]
+pub struct AttributeMap {
+    /// (connection handle, attribute handle) -> attribute type
+    types: HashMap<(u16, u16), Uuid>,
+    /// requests waiting for their response: (connection handle, sent by the Host) -> request
+    pending: HashMap<(u16, bool), Request>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    Read(u16),
+    ReadByType(Uuid),
+}
+
+impl AttributeMap {
+    /// Feed a PDU received (`sent` false) or sent by the Host on connection `handle`. Returns
+    /// the attribute whose value the PDU carries: written, notified, indicated or read.
+    pub fn update(&mut self, handle: u16, sent: bool, pdu: &Pdu) -> Option<u16> {
+        let params = pdu.params;
+        let attribute = params
+            .get(..2)
+            .map(|attribute| u16::from_le_bytes([attribute[0], attribute[1]]));
+        match pdu.opcode {
+            Pdu::READ_REQUEST | Pdu::READ_BLOB_REQUEST => {
+                self.pending
+                    .insert((handle, sent), Request::Read(attribute?));
+            }
+            Pdu::READ_BY_TYPE_REQUEST => {
+                let uuid = Uuid::from_le_bytes(params.get(4..)?)?;
+                self.pending
+                    .insert((handle, sent), Request::ReadByType(uuid));
+            }
+            Pdu::READ_RESPONSE | Pdu::READ_BLOB_RESPONSE => {
+                if let Some(Request::Read(attribute)) = self.pending.remove(&(handle, !sent)) {
+                    return Some(attribute);
+                }
+            }
+            Pdu::READ_BY_TYPE_RESPONSE => {
+                let request = self.pending.remove(&(handle, !sent));
+                let (length, entries) = params.split_first()?;
+                if !matches!(request, Some(Request::ReadByType(Uuid::CHARACTERISTIC))) {
+                    return None;
+                }
+                // declaration handle, properties, value handle, characteristic UUID
+                for entry in entries
+                    .chunks_exact(*length as usize)
+                    .filter(|e| e.len() >= 7)
+                {
+                    let value_handle = u16::from_le_bytes([entry[3], entry[4]]);
+                    if let Some(uuid) = Uuid::from_le_bytes(&entry[5..]) {
+                        self.types.insert((handle, value_handle), uuid);
+                    }
+                }
+            }
+            Pdu::FIND_INFORMATION_RESPONSE => {
+                let (format, entries) = params.split_first()?;
+                let uuid_len = if *format == 0x02 { 16 } else { 2 };
+                for entry in entries.chunks_exact(2 + uuid_len) {
+                    let attribute = u16::from_le_bytes([entry[0], entry[1]]);
+                    if let Some(uuid) = Uuid::from_le_bytes(&entry[2..]) {
+                        self.types.insert((handle, attribute), uuid);
+                    }
+                }
+            }
+            Pdu::ERROR_RESPONSE => {
+                self.pending.remove(&(handle, !sent));
+            }
+            Pdu::WRITE_REQUEST
+            | Pdu::WRITE_COMMAND
+            | Pdu::HANDLE_VALUE_NOTIFICATION
+            | Pdu::HANDLE_VALUE_INDICATION => return attribute,
+            _ => {}
+        }
+        None
+    }
+
+    /// The type of `attribute` on connection `handle`, once discovered
+    pub fn uuid(&self, handle: u16, attribute: u16) -> Option<Uuid> {
+        self.types.get(&(handle, attribute)).copied()
+    }
+
+    /// The attributes of connection `handle` whose type was discovered, by attribute handle
+    pub fn attributes(&self, handle: u16) -> Vec<(u16, Uuid)> {
+        let mut attributes: Vec<_> = self
+            .types
+            .iter()
+            .filter(|((h, _), _)| *h == handle)
+            .map(|((_, attribute), uuid)| (*attribute, *uuid))
+            .collect();
+        attributes.sort_unstable_by_key(|(attribute, _)| *attribute);
+        attributes
+    }
+
+    /// the ACL connection is gone, so is what was learned about its attributes
+    pub fn disconnected(&mut self, handle: u16) {
+        self.types.retain(|(h, _), _| *h != handle);
+        self.pending.retain(|(h, _), _| *h != handle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn discovery() {
+        assert_eq!(Uuid::from_u16(0x2a37).to_string(), "0x2a37");
+        let nus = Uuid(0x6e40_0003_b5a3_f393_e0a9_e50e_24dc_ca9e);
+        assert_eq!(nus.to_string(), "6e400003-b5a3-f393-e0a9-e50e24dcca9e");
+        assert_eq!(Uuid::from_le_bytes(&nus.0.to_le_bytes()), Some(nus));
+
+        let mut map = AttributeMap::default();
+        // characteristic discovery: Heart Rate Measurement, value handle 0x0012
+        let request = [0x08, 0x01, 0x00, 0xff, 0xff, 0x03, 0x28];
+        let pdu = Pdu::try_from(&request[..]).unwrap();
+        assert_eq!(map.update(0x40, true, &pdu), None);
+        let response = [0x09, 0x07, 0x11, 0x00, 0x10, 0x12, 0x00, 0x37, 0x2a];
+        map.update(0x40, false, &Pdu::try_from(&response[..]).unwrap());
+        assert_eq!(map.uuid(0x40, 0x12), Some(Uuid::from_u16(0x2a37)));
+
+        // descriptors
+        let mut response = vec![0x05, 0x02, 0x13, 0x00];
+        response.extend_from_slice(&nus.0.to_le_bytes());
+        map.update(0x40, false, &Pdu::try_from(&response[..]).unwrap());
+        assert_eq!(map.uuid(0x40, 0x13), Some(nus));
+
+        let notification = [0x1b, 0x12, 0x00, 0x00, 0x48];
+        let pdu = Pdu::try_from(&notification[..]).unwrap();
+        assert_eq!(map.update(0x40, false, &pdu), Some(0x12));
+        map.update(0x40, true, &Pdu::try_from(&[0x0a, 0x13, 0x00][..]).unwrap());
+        let pdu = Pdu::try_from(&[0x0b, 0x01][..]).unwrap();
+        assert_eq!(map.update(0x40, false, &pdu), Some(0x13));
+
+        map.disconnected(0x40);
+        assert_eq!(map.uuid(0x40, 0x12), None);
+    }
+}
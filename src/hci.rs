@@ -1,14 +1,25 @@
-use std::fmt::{Debug, Write};
+use std::{
+    fmt::{Debug, Display},
+    io::{self, Read},
+};
 
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use bytes::Buf;
+use byteorder::{LittleEndian, ReadBytesExt};
+use num_enum::TryFromPrimitive;
 
-/// data format from: Bluetooth core specification 5.4 Vol 4: Host Controller Interface Part E Host Controller Interface Functional Specification Hci Data Formats
+pub mod direction_finding;
+pub mod opcode;
+pub mod power_control;
+pub mod synchronous;
+
+use direction_finding::IqReport;
+use power_control::{PathLossThreshold, TransmitPowerReporting};
+
+// data format from: Bluetooth core specification 5.4 Vol 4: Host Controller Interface Part E Host Controller Interface Functional Specification Hci Data Formats
 
 /// - All values are in binary and hexadecimal little-endian formats unless otherwise noted.
 /// - In addition, all parameters which can have negative values shall use two's complement when specifying values.
 /// - Unless noted otherwise, the order of parameters in an HCI Command packet or HCI Event packet is the order the parameters are listed in the command or event.
-///```
+///```text
 /// --------------------------
 /// | opcode 16 bit          |
 /// --------------------------
@@ -24,7 +35,8 @@ use bytes::Buf;
 /// | parameter n            |
 /// --------------------------
 ///```
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Command<'a> {
     pub opcode: Opcode,
     /// Lengths of all of the parameters contained in this packet measured in octets. (N.B.: total length of parameters, not number of parameters)
@@ -36,24 +48,50 @@ pub struct Command<'a> {
 impl<'a> Command<'a> {
     const PARAMS_START_BYTE: usize = 3;
 
-    pub fn from(data: &'a mut [u8]) -> Self {
-        let mut reader = data.reader();
-        let opcode = Opcode(reader.read_u16::<LittleEndian>().unwrap());
-        let params_len = reader.read_u8().unwrap();
-        let params = &data[Self::PARAMS_START_BYTE..];
+    /// Panics when the data is shorter than the command header
+    #[deprecated(note = "panics on short data, use `Command::try_from`")]
+    pub fn from(data: &'a [u8]) -> Self {
+        Self::try_from(data).expect("command header too short")
+    }
+}
 
-        Self {
+impl<'a> TryFrom<&'a [u8]> for Command<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = data;
+        let opcode = Opcode(reader.read_u16::<LittleEndian>()?);
+        let params_len = reader.read_u8()?;
+        Ok(Self {
             opcode,
             params_len,
-            params,
-        }
+            params: &data[Self::PARAMS_START_BYTE..],
+        })
+    }
+}
+
+/// `HCI Command: Reset (0x03|0x0003) plen 0`
+impl Display for Command<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HCI Command: {} (0x{:02x}|0x{:04x}) plen {}",
+            self.opcode.name().unwrap_or("Unknown"),
+            self.opcode.ogf(),
+            self.opcode.ocf(),
+            self.params_len
+        )
     }
 }
 
 /// Opcode has two part: lower 10 bit is OCF, high 6 bit is OGF
 /// OGF Range (6 bits): 0x00 to 0x3F (0x3F reserved for vendor-specific debug commands)
 /// OCF Range (10 bits): 0x0000 to 0x03FF
-#[derive(Clone, Copy)]
+///
+/// The opcodes of the specification are constants of the [`opcode`] module, e.g.
+/// [`opcode::RESET`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Opcode(u16);
 
 impl Debug for Opcode {
@@ -63,6 +101,11 @@ impl Debug for Opcode {
 }
 
 impl Opcode {
+    /// The OCF is truncated to its 10 bits and the OGF to its 6 bits
+    pub const fn new(ogf: u8, ocf: u16) -> Self {
+        Self(((ogf as u16 & 0x3F) << 10) | (ocf & 0x3FF))
+    }
+
     pub fn ocf(&self) -> u16 {
         self.0 & 0x3FF
     }
@@ -70,7 +113,1367 @@ impl Opcode {
     pub fn ogf(&self) -> u8 {
         (self.0 >> 10) as u8
     }
+
+    /// OGF and OCF combined, as sent in the packet
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
+    /// command name as written in the specification, e.g. "LE Set Scan Enable"
+    pub fn name(&self) -> Option<&'static str> {
+        opcode::name(self.0)
+    }
+}
+
+impl From<u16> for Opcode {
+    fn from(raw: u16) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<Opcode> for u16 {
+    fn from(opcode: Opcode) -> Self {
+        opcode.0
+    }
+}
+
+/// 48 bit device address, kept in the little-endian order it has on the wire.
+/// Displayed most significant octet first, e.g. `00:1A:7D:DA:71:13`
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BdAddr(pub [u8; 6]);
+
+impl BdAddr {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut addr = [0u8; 6];
+        reader.read_exact(&mut addr)?;
+        Ok(Self(addr))
+    }
+
+    /// The organizationally unique identifier of a public address, its 3 most significant
+    /// octets, most significant first
+    pub fn oui(&self) -> [u8; 3] {
+        [self.0[5], self.0[4], self.0[3]]
+    }
+
+    /// The kind of the address given with `address_type`: 0x00 public, 0x01 random, or 0x02
+    /// and 0x03 the identity addresses of resolved ones
+    pub fn kind(&self, address_type: u8) -> AddressKind {
+        if address_type & 1 == 0 {
+            return AddressKind::Public;
+        }
+        match self.0[5] >> 6 {
+            0b11 => AddressKind::RandomStatic,
+            0b01 => AddressKind::ResolvablePrivate,
+            0b00 => AddressKind::NonResolvablePrivate,
+            _ => AddressKind::Reserved,
+        }
+    }
+}
+
+/// Public or one of the random device addresses, told apart by their two most significant bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    Public,
+    RandomStatic,
+    /// resolvable private address (RPA), changes periodically and resolves with an IRK
+    ResolvablePrivate,
+    /// non-resolvable private address (NRPA)
+    NonResolvablePrivate,
+    /// random address with the reserved most significant bits 0b10
+    Reserved,
+}
+
+impl Display for AddressKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AddressKind::Public => "public",
+            AddressKind::RandomStatic => "random static",
+            AddressKind::ResolvablePrivate => "resolvable private",
+            AddressKind::NonResolvablePrivate => "non-resolvable private",
+            AddressKind::Reserved => "reserved random",
+        })
+    }
+}
+
+impl Display for BdAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let a = &self.0;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a[5], a[4], a[3], a[2], a[1], a[0]
+        )
+    }
+}
+
+impl Debug for BdAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BdAddr({})", self)
+    }
 }
 
 /// hci event
-pub enum Event {}
+///```text
+/// --------------------------
+/// | event code 8 bit       |
+/// --------------------------
+/// | parameter total length |
+/// | 8 bit                  |
+/// --------------------------
+/// | event parameter 0      |
+/// --------------------------
+/// | ...                    |
+/// --------------------------
+/// | event parameter n      |
+/// --------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Event<'a> {
+    pub code: u8,
+    /// Length of all of the parameters contained in this packet, measured in octets.
+    pub params_len: u8,
+    pub params: &'a [u8],
+}
+
+impl<'a> Event<'a> {
+    const PARAMS_START_BYTE: usize = 2;
+
+    pub const CONNECTION_COMPLETE: u8 = 0x03;
+    pub const DISCONNECTION_COMPLETE: u8 = 0x05;
+    pub const REMOTE_NAME_REQUEST_COMPLETE: u8 = 0x07;
+    pub const COMMAND_COMPLETE: u8 = 0x0E;
+    pub const COMMAND_STATUS: u8 = 0x0F;
+    pub const NUMBER_OF_COMPLETED_PACKETS: u8 = 0x13;
+    pub const PIN_CODE_REQUEST: u8 = 0x16;
+    pub const LINK_KEY_NOTIFICATION: u8 = 0x18;
+    pub const SYNCHRONOUS_CONNECTION_COMPLETE: u8 = 0x2C;
+    pub const SYNCHRONOUS_CONNECTION_CHANGED: u8 = 0x2D;
+    pub const IO_CAPABILITY_REQUEST: u8 = 0x31;
+    pub const IO_CAPABILITY_RESPONSE: u8 = 0x32;
+    pub const USER_CONFIRMATION_REQUEST: u8 = 0x33;
+    pub const USER_PASSKEY_REQUEST: u8 = 0x34;
+    pub const REMOTE_OOB_DATA_REQUEST: u8 = 0x35;
+    pub const SIMPLE_PAIRING_COMPLETE: u8 = 0x36;
+    pub const USER_PASSKEY_NOTIFICATION: u8 = 0x3B;
+    pub const LE_META: u8 = 0x3E;
+    pub const VENDOR_SPECIFIC: u8 = 0xFF;
+}
+
+impl<'a> TryFrom<&'a [u8]> for Event<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() < Self::PARAMS_START_BYTE {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "event header too short",
+            ));
+        }
+        Ok(Self {
+            code: data[0],
+            params_len: data[1],
+            params: &data[Self::PARAMS_START_BYTE..],
+        })
+    }
+}
+
+/// `HCI Event: Command Complete (0x0e) plen 4`, followed by the subevent of LE Meta events
+impl Display for Event<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HCI Event: {} (0x{:02x}) plen {}",
+            event_code_name(self.code).unwrap_or("Unknown"),
+            self.code,
+            self.params_len
+        )?;
+        match self.params.first() {
+            Some(subevent_code) if self.code == Self::LE_META => write!(
+                f,
+                ": {} (0x{:02x})",
+                LeMetaEvent::subevent_name(*subevent_code).unwrap_or("Unknown"),
+                subevent_code
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Command Complete event parameters, the return parameters depend on the command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CommandComplete<'a> {
+    /// The number of HCI Command packets which are allowed to be sent to the Controller from the Host.
+    pub num_hci_command_packets: u8,
+    pub opcode: Opcode,
+    pub return_parameters: &'a [u8],
+}
+
+impl<'a> CommandComplete<'a> {
+    /// Nearly every command returns its status as the first return parameter
+    pub fn status(&self) -> Option<u8> {
+        self.return_parameters.first().copied()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for CommandComplete<'a> {
+    type Error = io::Error;
+
+    fn try_from(params: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = params;
+        let num_hci_command_packets = reader.read_u8()?;
+        let opcode = Opcode(reader.read_u16::<LittleEndian>()?);
+        Ok(Self {
+            num_hci_command_packets,
+            opcode,
+            return_parameters: reader,
+        })
+    }
+}
+
+/// Command Status event parameters, status 0 means the command is pending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CommandStatus {
+    pub status: u8,
+    pub num_hci_command_packets: u8,
+    pub opcode: Opcode,
+}
+
+impl CommandStatus {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let num_hci_command_packets = reader.read_u8()?;
+        let opcode = Opcode(reader.read_u16::<LittleEndian>()?);
+        Ok(Self {
+            status,
+            num_hci_command_packets,
+            opcode,
+        })
+    }
+}
+
+/// BR/EDR connection established
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ConnectionComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub bd_addr: BdAddr,
+    /// 0x00 = SCO, 0x01 = ACL
+    pub link_type: u8,
+    pub encryption_enabled: u8,
+}
+
+impl ConnectionComplete {
+    pub const LINK_TYPE_SCO: u8 = 0x00;
+    pub const LINK_TYPE_ACL: u8 = 0x01;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            bd_addr: BdAddr::parse(reader)?,
+            link_type: reader.read_u8()?,
+            encryption_enabled: reader.read_u8()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct DisconnectionComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub reason: u8,
+}
+
+impl DisconnectionComplete {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let handle = read_handle(reader)?;
+        let reason = reader.read_u8()?;
+        Ok(Self {
+            status,
+            handle,
+            reason,
+        })
+    }
+}
+
+/// Number of HCI data packets completed (transmitted or flushed) per connection handle
+/// since the previous Number Of Completed Packets event.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct NumberOfCompletedPackets {
+    /// (connection handle, number of completed packets)
+    pub completed: Vec<(u16, u16)>,
+}
+
+impl NumberOfCompletedPackets {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let num_handles = reader.read_u8()?;
+        let mut completed = Vec::with_capacity(num_handles as usize);
+        for _ in 0..num_handles {
+            let handle = read_handle(reader)?;
+            let count = reader.read_u16::<LittleEndian>()?;
+            completed.push((handle, count));
+        }
+        Ok(Self { completed })
+    }
+}
+
+/// LE Meta event, the first parameter is the subevent code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum LeMetaEvent<'a> {
+    /// LE Connection Complete and LE Enhanced Connection Complete (v1 and v2)
+    ConnectionComplete(LeConnectionComplete),
+    ConnectionUpdateComplete(LeConnectionUpdateComplete),
+    RemoteConnectionParameterRequest(LeRemoteConnectionParameterRequest),
+    /// LE Advertising Report and LE Extended Advertising Report
+    AdvertisingReport(Vec<LeAdvertisingReport<'a>>),
+    ConnectionlessIqReport(IqReport),
+    ConnectionIqReport(IqReport),
+    /// the peer didn't respond to an LE Connection CTE Request with a CTE
+    CteRequestFailed {
+        status: u8,
+        handle: u16,
+    },
+    PathLossThreshold(PathLossThreshold),
+    TransmitPowerReporting(TransmitPowerReporting),
+    /// subevents not decoded yet
+    Unknown {
+        subevent_code: u8,
+        params: &'a [u8],
+    },
+}
+
+impl<'a> LeMetaEvent<'a> {
+    pub const CONNECTION_COMPLETE: u8 = 0x01;
+    pub const ADVERTISING_REPORT: u8 = 0x02;
+    pub const CONNECTION_UPDATE_COMPLETE: u8 = 0x03;
+    pub const REMOTE_CONNECTION_PARAMETER_REQUEST: u8 = 0x06;
+    pub const ENHANCED_CONNECTION_COMPLETE: u8 = 0x0A;
+    pub const EXTENDED_ADVERTISING_REPORT: u8 = 0x0D;
+    pub const CONNECTIONLESS_IQ_REPORT: u8 = 0x15;
+    pub const CONNECTION_IQ_REPORT: u8 = 0x16;
+    pub const CTE_REQUEST_FAILED: u8 = 0x17;
+    pub const PATH_LOSS_THRESHOLD: u8 = 0x20;
+    pub const TRANSMIT_POWER_REPORTING: u8 = 0x21;
+    pub const ENHANCED_CONNECTION_COMPLETE_V2: u8 = 0x29;
+
+    /// Name of an LE Meta subevent code, as written in the specification without the "LE" prefix
+    pub fn subevent_name(subevent_code: u8) -> Option<&'static str> {
+        let name = match subevent_code {
+            0x01 => "Connection Complete",
+            0x02 => "Advertising Report",
+            0x03 => "Connection Update Complete",
+            0x04 => "Read Remote Features Complete",
+            0x05 => "Long Term Key Request",
+            0x06 => "Remote Connection Parameter Request",
+            0x07 => "Data Length Change",
+            0x08 => "Read Local P-256 Public Key Complete",
+            0x09 => "Generate DHKey Complete",
+            0x0A => "Enhanced Connection Complete",
+            0x0B => "Directed Advertising Report",
+            0x0C => "PHY Update Complete",
+            0x0D => "Extended Advertising Report",
+            0x0E => "Periodic Advertising Sync Established",
+            0x0F => "Periodic Advertising Report",
+            0x10 => "Periodic Advertising Sync Lost",
+            0x11 => "Scan Timeout",
+            0x12 => "Advertising Set Terminated",
+            0x13 => "Scan Request Received",
+            0x14 => "Channel Selection Algorithm",
+            0x15 => "Connectionless IQ Report",
+            0x16 => "Connection IQ Report",
+            0x17 => "CTE Request Failed",
+            0x18 => "Periodic Advertising Sync Transfer Received",
+            0x19 => "CIS Established",
+            0x1A => "CIS Request",
+            0x1B => "Create BIG Complete",
+            0x1C => "Terminate BIG Complete",
+            0x1D => "BIG Sync Established",
+            0x1E => "BIG Sync Lost",
+            0x1F => "Request Peer SCA Complete",
+            0x20 => "Path Loss Threshold",
+            0x21 => "Transmit Power Reporting",
+            0x22 => "BIGInfo Advertising Report",
+            0x23 => "Subrate Change",
+            0x24 => "Periodic Advertising Sync Established [v2]",
+            0x25 => "Periodic Advertising Report [v2]",
+            0x26 => "Periodic Advertising Sync Transfer Received [v2]",
+            0x27 => "Periodic Advertising Subevent Data Request",
+            0x28 => "Periodic Advertising Response Report",
+            0x29 => "Enhanced Connection Complete [v2]",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for LeMetaEvent<'a> {
+    type Error = io::Error;
+
+    fn try_from(params: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = params;
+        let subevent_code = reader.read_u8()?;
+        let event = match subevent_code {
+            Self::CONNECTION_COMPLETE => {
+                Self::ConnectionComplete(LeConnectionComplete::parse(&mut reader, false)?)
+            }
+            Self::ENHANCED_CONNECTION_COMPLETE | Self::ENHANCED_CONNECTION_COMPLETE_V2 => {
+                Self::ConnectionComplete(LeConnectionComplete::parse(&mut reader, true)?)
+            }
+            Self::CONNECTION_UPDATE_COMPLETE => {
+                Self::ConnectionUpdateComplete(LeConnectionUpdateComplete::parse(&mut reader)?)
+            }
+            Self::REMOTE_CONNECTION_PARAMETER_REQUEST => Self::RemoteConnectionParameterRequest(
+                LeRemoteConnectionParameterRequest::parse(&mut reader)?,
+            ),
+            Self::ADVERTISING_REPORT | Self::EXTENDED_ADVERTISING_REPORT => {
+                let extended = subevent_code == Self::EXTENDED_ADVERTISING_REPORT;
+                let num_reports = reader.read_u8()?;
+                let mut reports = Vec::with_capacity(num_reports as usize);
+                for _ in 0..num_reports {
+                    reports.push(LeAdvertisingReport::parse(&mut reader, extended)?);
+                }
+                Self::AdvertisingReport(reports)
+            }
+            Self::CONNECTIONLESS_IQ_REPORT => {
+                Self::ConnectionlessIqReport(IqReport::parse(&mut reader, false)?)
+            }
+            Self::CONNECTION_IQ_REPORT => {
+                Self::ConnectionIqReport(IqReport::parse(&mut reader, true)?)
+            }
+            Self::CTE_REQUEST_FAILED => Self::CteRequestFailed {
+                status: reader.read_u8()?,
+                handle: read_handle(&mut reader)?,
+            },
+            Self::PATH_LOSS_THRESHOLD => {
+                Self::PathLossThreshold(PathLossThreshold::parse(&mut reader)?)
+            }
+            Self::TRANSMIT_POWER_REPORTING => {
+                Self::TransmitPowerReporting(TransmitPowerReporting::parse(&mut reader)?)
+            }
+            _ => Self::Unknown {
+                subevent_code,
+                params: reader,
+            },
+        };
+        Ok(event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeConnectionComplete {
+    pub status: u8,
+    pub handle: u16,
+    /// 0 = Central, 1 = Peripheral
+    pub role: u8,
+    pub peer_address_type: u8,
+    pub peer_address: BdAddr,
+    /// only reported by the enhanced variants
+    pub local_resolvable_private_address: Option<BdAddr>,
+    /// only reported by the enhanced variants
+    pub peer_resolvable_private_address: Option<BdAddr>,
+    /// Range: 0x0006 to 0x0C80, Time = N × 1.25 ms
+    pub connection_interval: u16,
+    /// Range: 0x0000 to 0x01F3, number of connection events
+    pub peripheral_latency: u16,
+    /// Range: 0x000A to 0x0C80, Time = N × 10 ms
+    pub supervision_timeout: u16,
+    pub central_clock_accuracy: u8,
+}
+
+impl LeConnectionComplete {
+    pub const ROLE_CENTRAL: u8 = 0x00;
+    pub const ROLE_PERIPHERAL: u8 = 0x01;
+
+    /// parse the subevent parameters, `enhanced` for the variants that carry the resolvable private addresses
+    pub fn parse<R: Read>(reader: &mut R, enhanced: bool) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let handle = read_handle(reader)?;
+        let role = reader.read_u8()?;
+        let peer_address_type = reader.read_u8()?;
+        let peer_address = BdAddr::parse(reader)?;
+        let (local_resolvable_private_address, peer_resolvable_private_address) = if enhanced {
+            (Some(BdAddr::parse(reader)?), Some(BdAddr::parse(reader)?))
+        } else {
+            (None, None)
+        };
+        let connection_interval = reader.read_u16::<LittleEndian>()?;
+        let peripheral_latency = reader.read_u16::<LittleEndian>()?;
+        let supervision_timeout = reader.read_u16::<LittleEndian>()?;
+        let central_clock_accuracy = reader.read_u8()?;
+        Ok(Self {
+            status,
+            handle,
+            role,
+            peer_address_type,
+            peer_address,
+            local_resolvable_private_address,
+            peer_resolvable_private_address,
+            connection_interval,
+            peripheral_latency,
+            supervision_timeout,
+            central_clock_accuracy,
+        })
+    }
+}
+
+/// One report of LE Advertising Report or LE Extended Advertising Report.
+///
+/// The legacy event lays out its reports parameter by parameter, but controllers send a single
+/// report per event, so the reports are read one after the other like the extended ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeAdvertisingReport<'a> {
+    /// legacy: 0x00 ADV_IND, 0x01 ADV_DIRECT_IND, 0x02 ADV_SCAN_IND, 0x03 ADV_NONCONN_IND, 0x04 SCAN_RSP.
+    /// extended: bit field, see the `EVENT_TYPE_*` constants
+    pub event_type: u16,
+    pub extended: bool,
+    /// 0x00 public, 0x01 random, 0x02 public identity, 0x03 random static identity, 0xFF anonymous
+    pub address_type: u8,
+    pub address: BdAddr,
+    /// only reported by the extended event, 0xFF when no ADI field was present
+    pub advertising_sid: Option<u8>,
+    /// only reported by the extended event, in dBm
+    pub tx_power: Option<i8>,
+    /// in dBm, `None` when not available
+    pub rssi: Option<i8>,
+    pub data: &'a [u8],
+}
+
+impl<'a> LeAdvertisingReport<'a> {
+    pub const ADV_IND: u16 = 0x00;
+    pub const ADV_DIRECT_IND: u16 = 0x01;
+    pub const ADV_SCAN_IND: u16 = 0x02;
+    pub const ADV_NONCONN_IND: u16 = 0x03;
+    pub const SCAN_RSP: u16 = 0x04;
+
+    pub const EVENT_TYPE_CONNECTABLE: u16 = 0x0001;
+    pub const EVENT_TYPE_SCANNABLE: u16 = 0x0002;
+    pub const EVENT_TYPE_DIRECTED: u16 = 0x0004;
+    pub const EVENT_TYPE_SCAN_RESPONSE: u16 = 0x0008;
+    pub const EVENT_TYPE_LEGACY: u16 = 0x0010;
+    /// 0b00 complete, 0b01 incomplete more data to come, 0b10 incomplete truncated
+    pub const EVENT_TYPE_DATA_STATUS: u16 = 0x0060;
+
+    pub const ADDRESS_TYPE_RANDOM: u8 = 0x01;
+
+    pub fn parse(reader: &mut &'a [u8], extended: bool) -> io::Result<Self> {
+        let event_type = if extended {
+            reader.read_u16::<LittleEndian>()?
+        } else {
+            reader.read_u8()? as u16
+        };
+        let address_type = reader.read_u8()?;
+        let address = BdAddr::parse(reader)?;
+        let (advertising_sid, tx_power, rssi) = if extended {
+            // primary and secondary PHY
+            reader.read_u16::<LittleEndian>()?;
+            let advertising_sid = reader.read_u8()?;
+            let tx_power = reader.read_i8()?;
+            let rssi = reader.read_i8()?;
+            // periodic advertising interval, direct address type and direct address
+            let mut skipped = [0; 9];
+            reader.read_exact(&mut skipped)?;
+            (Some(advertising_sid), Some(tx_power), rssi)
+        } else {
+            (None, None, 0)
+        };
+        let data_length = reader.read_u8()? as usize;
+        if reader.len() < data_length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "advertising data too short",
+            ));
+        }
+        let (data, rest) = reader.split_at(data_length);
+        *reader = rest;
+        let rssi = if extended { rssi } else { reader.read_i8()? };
+        Ok(Self {
+            event_type,
+            extended,
+            address_type,
+            address,
+            advertising_sid,
+            tx_power,
+            rssi: (rssi != 127).then_some(rssi),
+            data,
+        })
+    }
+
+    pub fn is_scan_response(&self) -> bool {
+        if self.extended {
+            self.event_type & Self::EVENT_TYPE_SCAN_RESPONSE != 0
+        } else {
+            self.event_type == Self::SCAN_RSP
+        }
+    }
+
+    /// extended reports whose data continues in the next report of the same advertiser
+    pub fn is_incomplete(&self) -> bool {
+        self.extended && self.event_type & Self::EVENT_TYPE_DATA_STATUS == 0x20
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeConnectionUpdateComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub connection_interval: u16,
+    pub peripheral_latency: u16,
+    pub supervision_timeout: u16,
+}
+
+impl LeConnectionUpdateComplete {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            connection_interval: reader.read_u16::<LittleEndian>()?,
+            peripheral_latency: reader.read_u16::<LittleEndian>()?,
+            supervision_timeout: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// The remote device is requesting a change of the connection parameters,
+/// the Host replies with LE Remote Connection Parameter Request (Negative) Reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeRemoteConnectionParameterRequest {
+    pub handle: u16,
+    pub interval_min: u16,
+    pub interval_max: u16,
+    pub max_latency: u16,
+    pub timeout: u16,
+}
+
+impl LeRemoteConnectionParameterRequest {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            handle: read_handle(reader)?,
+            interval_min: reader.read_u16::<LittleEndian>()?,
+            interval_max: reader.read_u16::<LittleEndian>()?,
+            max_latency: reader.read_u16::<LittleEndian>()?,
+            timeout: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// Parameters of LE Connection Update, also used for LE Remote Connection Parameter Request Reply
+/// which shares the same layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeConnectionUpdate {
+    pub handle: u16,
+    pub interval_min: u16,
+    pub interval_max: u16,
+    pub max_latency: u16,
+    pub supervision_timeout: u16,
+    /// Time = N × 0.625 ms
+    pub min_ce_length: u16,
+    /// Time = N × 0.625 ms
+    pub max_ce_length: u16,
+}
+
+impl LeConnectionUpdate {
+    /// OGF 0x08, OCF 0x0013
+    pub const OCF: u16 = 0x0013;
+    /// LE Remote Connection Parameter Request Reply, OGF 0x08
+    pub const REPLY_OCF: u16 = 0x0020;
+    /// LE Remote Connection Parameter Request Negative Reply, OGF 0x08
+    pub const NEGATIVE_REPLY_OCF: u16 = 0x0021;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            handle: read_handle(reader)?,
+            interval_min: reader.read_u16::<LittleEndian>()?,
+            interval_max: reader.read_u16::<LittleEndian>()?,
+            max_latency: reader.read_u16::<LittleEndian>()?,
+            supervision_timeout: reader.read_u16::<LittleEndian>()?,
+            min_ce_length: reader.read_u16::<LittleEndian>()?,
+            max_ce_length: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// Read Buffer Size return parameters, the Controller's buffers for data sent by the Host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ReadBufferSize {
+    pub status: u8,
+    pub acl_data_packet_length: u16,
+    pub synchronous_data_packet_length: u8,
+    pub total_num_acl_data_packets: u16,
+    pub total_num_synchronous_data_packets: u16,
+}
+
+impl ReadBufferSize {
+    /// OGF 0x04, OCF 0x0005
+    pub const OPCODE: u16 = 0x1005;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            acl_data_packet_length: reader.read_u16::<LittleEndian>()?,
+            synchronous_data_packet_length: reader.read_u8()?,
+            total_num_acl_data_packets: reader.read_u16::<LittleEndian>()?,
+            total_num_synchronous_data_packets: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// LE Read Buffer Size [v1] and [v2] return parameters.
+/// `total_num_le_acl_data_packets` 0 means LE shares the buffers of Read Buffer Size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeReadBufferSize {
+    pub status: u8,
+    pub le_acl_data_packet_length: u16,
+    pub total_num_le_acl_data_packets: u8,
+    /// only returned by [v2]
+    pub iso_data_packet_length: Option<u16>,
+    /// only returned by [v2]
+    pub total_num_iso_data_packets: Option<u8>,
+}
+
+impl LeReadBufferSize {
+    /// OGF 0x08, OCF 0x0002
+    pub const OPCODE_V1: u16 = 0x2002;
+    /// OGF 0x08, OCF 0x0060
+    pub const OPCODE_V2: u16 = 0x2060;
+
+    pub fn parse<R: Read>(reader: &mut R, v2: bool) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let le_acl_data_packet_length = reader.read_u16::<LittleEndian>()?;
+        let total_num_le_acl_data_packets = reader.read_u8()?;
+        let (iso_data_packet_length, total_num_iso_data_packets) = if v2 {
+            (
+                Some(reader.read_u16::<LittleEndian>()?),
+                Some(reader.read_u8()?),
+            )
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            status,
+            le_acl_data_packet_length,
+            total_num_le_acl_data_packets,
+            iso_data_packet_length,
+            total_num_iso_data_packets,
+        })
+    }
+}
+
+/// Set AFH Host Channel Classification parameters, the BR/EDR channels the Host knows to be bad.
+/// Bit n of the 79 bit map is channel n, 0 = bad, 1 = unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SetAfhHostChannelClassification {
+    pub channel_map: [u8; 10],
+}
+
+impl SetAfhHostChannelClassification {
+    /// OGF 0x03, OCF 0x003F
+    pub const OPCODE: u16 = 0x0C3F;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut channel_map = [0; 10];
+        reader.read_exact(&mut channel_map)?;
+        Ok(Self { channel_map })
+    }
+}
+
+/// Read AFH Channel Map return parameters, the BR/EDR channels used by a connection.
+/// Bit n of the 79 bit map is channel n, 0 = unused, 1 = used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ReadAfhChannelMap {
+    pub status: u8,
+    pub handle: u16,
+    /// 0 = AFH disabled, 1 = AFH enabled
+    pub afh_mode: u8,
+    pub channel_map: [u8; 10],
+}
+
+impl ReadAfhChannelMap {
+    /// OGF 0x05, OCF 0x0006
+    pub const OPCODE: u16 = 0x1406;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let handle = read_handle(reader)?;
+        let afh_mode = reader.read_u8()?;
+        let mut channel_map = [0; 10];
+        reader.read_exact(&mut channel_map)?;
+        Ok(Self {
+            status,
+            handle,
+            afh_mode,
+            channel_map,
+        })
+    }
+}
+
+/// LE Set Host Channel Classification parameters.
+/// Bit n of the 37 bit map is data channel n, 0 = bad, 1 = unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeSetHostChannelClassification {
+    pub channel_map: [u8; 5],
+}
+
+impl LeSetHostChannelClassification {
+    /// OGF 0x08, OCF 0x0014
+    pub const OPCODE: u16 = 0x2014;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut channel_map = [0; 5];
+        reader.read_exact(&mut channel_map)?;
+        Ok(Self { channel_map })
+    }
+}
+
+/// LE Read Channel Map return parameters, the data channels used by a connection.
+/// Bit n of the 37 bit map is data channel n, 0 = unused, 1 = used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeReadChannelMap {
+    pub status: u8,
+    pub handle: u16,
+    pub channel_map: [u8; 5],
+}
+
+impl LeReadChannelMap {
+    /// OGF 0x08, OCF 0x0015
+    pub const OPCODE: u16 = 0x2015;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let status = reader.read_u8()?;
+        let handle = read_handle(reader)?;
+        let mut channel_map = [0; 5];
+        reader.read_exact(&mut channel_map)?;
+        Ok(Self {
+            status,
+            handle,
+            channel_map,
+        })
+    }
+}
+
+/// | Value | Parameter Description |
+/// | --- | --- |
+/// | 0b00 | First non-automatically-flushable packet of a higher layer message |
+/// | 0b01 | Continuing fragment of a higher layer message |
+/// | 0b10 | First automatically flushable packet of a higher layer message |
+/// | 0b11 | A complete L2CAP PDU. Automatically flushable. (deprecated) |
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, TryFromPrimitive)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PacketBoundaryFlag {
+    FirstNonAutomaticallyFlushable = 0,
+    ContinuingFragment,
+    FirstAutomaticallyFlushable,
+    CompletePdu,
+}
+
+impl PacketBoundaryFlag {
+    /// whether this fragment starts a new higher layer message
+    pub fn is_start(&self) -> bool {
+        !matches!(self, PacketBoundaryFlag::ContinuingFragment)
+    }
+}
+
+/// hci acl data
+///```text
+/// ------------------------------------------
+/// | handle 12 bit | pb flag 2 | bc flag 2 |
+/// ------------------------------------------
+/// | data total length 16 bit               |
+/// ------------------------------------------
+/// | data                                   |
+/// ------------------------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Acl<'a> {
+    pub handle: u16,
+    pub packet_boundary_flag: PacketBoundaryFlag,
+    /// 0b00 = Point-to-point, 0b01 = BR/EDR broadcast, others reserved
+    pub broadcast_flag: u8,
+    pub data_len: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> Acl<'a> {
+    const DATA_START_BYTE: usize = 4;
+}
+
+/// `ACL Data: Handle 64 flags 0x02 dlen 5`, the flags are the boundary and broadcast flags
+impl Display for Acl<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ACL Data: ")?;
+        self.fmt_fields(f)
+    }
+}
+
+impl Acl<'_> {
+    /// handle, flags and length
+    pub(crate) fn fmt_fields(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Handle {} flags 0x{:02x} dlen {}",
+            self.handle,
+            (self.packet_boundary_flag as u8) | self.broadcast_flag << 2,
+            self.data_len
+        )
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Acl<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = data;
+        let handle_and_flags = reader.read_u16::<LittleEndian>()?;
+        let data_len = reader.read_u16::<LittleEndian>()?;
+        let packet_boundary_flag =
+            PacketBoundaryFlag::try_from_primitive(((handle_and_flags >> 12) & 0b11) as u8)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid pb flag"))?;
+        Ok(Self {
+            handle: handle_and_flags & 0x0FFF,
+            packet_boundary_flag,
+            broadcast_flag: (handle_and_flags >> 14) as u8,
+            data_len,
+            data: &data[Self::DATA_START_BYTE..],
+        })
+    }
+}
+
+/// | Value | Parameter Description |
+/// | --- | --- |
+/// | 0b00 | The first fragment of a fragmented SDU |
+/// | 0b01 | A continuation fragment of a fragmented SDU |
+/// | 0b10 | A complete SDU |
+/// | 0b11 | The last fragment of an SDU |
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, TryFromPrimitive)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum IsoBoundaryFlag {
+    FirstFragment = 0,
+    ContinuationFragment,
+    CompleteSdu,
+    LastFragment,
+}
+
+impl IsoBoundaryFlag {
+    /// whether this fragment starts an SDU, and carries its sequence number and length
+    pub fn is_start(&self) -> bool {
+        matches!(
+            self,
+            IsoBoundaryFlag::FirstFragment | IsoBoundaryFlag::CompleteSdu
+        )
+    }
+
+    /// whether this fragment ends an SDU
+    pub fn is_end(&self) -> bool {
+        matches!(
+            self,
+            IsoBoundaryFlag::CompleteSdu | IsoBoundaryFlag::LastFragment
+        )
+    }
+}
+
+/// hci iso data, the time stamp is there when the ts flag is set, the sequence number, SDU
+/// length and packet status flag in the first fragment of an SDU
+///```text
+/// ------------------------------------------------------
+/// | handle 12 bit | pb flag 2 | ts flag 1 | RFU 1      |
+/// ------------------------------------------------------
+/// | data load length 14 bit | RFU 2                    |
+/// ------------------------------------------------------
+/// | time stamp 32 bit                                  |
+/// ------------------------------------------------------
+/// | packet sequence number 16 bit                      |
+/// ------------------------------------------------------
+/// | SDU length 12 bit | RFU 2 | packet status flag 2   |
+/// ------------------------------------------------------
+/// | SDU fragment                                       |
+/// ------------------------------------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Iso<'a> {
+    pub handle: u16,
+    pub packet_boundary_flag: IsoBoundaryFlag,
+    /// in microseconds
+    pub time_stamp: Option<u32>,
+    pub sequence_number: Option<u16>,
+    pub sdu_length: Option<u16>,
+    /// 0b00 = valid, 0b01 = possibly invalid, 0b10 = lost, only set by the Controller
+    pub packet_status_flag: Option<u8>,
+    pub data_load_length: u16,
+    /// the SDU fragment
+    pub data: &'a [u8],
+}
+
+impl Iso<'_> {
+    pub const STATUS_VALID: u8 = 0b00;
+    pub const STATUS_POSSIBLY_INVALID: u8 = 0b01;
+    pub const STATUS_LOST: u8 = 0b10;
+}
+
+/// `ISO Data: Handle 96 flags 0x02 dlen 44`, the flags are the boundary and time stamp flags
+impl Display for Iso<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ISO Data: Handle {} flags 0x{:02x} dlen {}",
+            self.handle,
+            (self.packet_boundary_flag as u8) | (self.time_stamp.is_some() as u8) << 2,
+            self.data_load_length
+        )
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Iso<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = data;
+        let handle_and_flags = reader.read_u16::<LittleEndian>()?;
+        let data_load_length = reader.read_u16::<LittleEndian>()? & 0x3FFF;
+        let packet_boundary_flag =
+            IsoBoundaryFlag::try_from_primitive(((handle_and_flags >> 12) & 0b11) as u8)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid pb flag"))?;
+        let time_stamp = if handle_and_flags & 0x4000 != 0 {
+            Some(reader.read_u32::<LittleEndian>()?)
+        } else {
+            None
+        };
+        let (mut sequence_number, mut sdu_length, mut packet_status_flag) = (None, None, None);
+        if packet_boundary_flag.is_start() {
+            sequence_number = Some(reader.read_u16::<LittleEndian>()?);
+            let length_and_status = reader.read_u16::<LittleEndian>()?;
+            sdu_length = Some(length_and_status & 0x0FFF);
+            packet_status_flag = Some((length_and_status >> 14) as u8);
+        }
+        Ok(Self {
+            handle: handle_and_flags & 0x0FFF,
+            packet_boundary_flag,
+            time_stamp,
+            sequence_number,
+            sdu_length,
+            packet_status_flag,
+            data_load_length,
+            data: reader,
+        })
+    }
+}
+
+/// Name of a command opcode, as written in the specification, e.g. "LE Set Scan Enable"
+pub fn opcode_name(opcode: u16) -> Option<&'static str> {
+    opcode::name(opcode)
+}
+
+/// Name of an event code, as written in the specification
+pub fn event_code_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x01 => "Inquiry Complete",
+        0x02 => "Inquiry Result",
+        0x03 => "Connection Complete",
+        0x04 => "Connection Request",
+        0x05 => "Disconnection Complete",
+        0x06 => "Authentication Complete",
+        0x07 => "Remote Name Request Complete",
+        0x08 => "Encryption Change",
+        0x09 => "Change Connection Link Key Complete",
+        0x0A => "Central Link Key Complete",
+        0x0B => "Read Remote Supported Features Complete",
+        0x0C => "Read Remote Version Information Complete",
+        0x0D => "QoS Setup Complete",
+        0x0E => "Command Complete",
+        0x0F => "Command Status",
+        0x10 => "Hardware Error",
+        0x11 => "Flush Occurred",
+        0x12 => "Role Change",
+        0x13 => "Number Of Completed Packets",
+        0x14 => "Mode Change",
+        0x15 => "Return Link Keys",
+        0x16 => "PIN Code Request",
+        0x17 => "Link Key Request",
+        0x18 => "Link Key Notification",
+        0x19 => "Loopback Command",
+        0x1A => "Data Buffer Overflow",
+        0x1B => "Max Slots Change",
+        0x1C => "Read Clock Offset Complete",
+        0x1D => "Connection Packet Type Changed",
+        0x1E => "QoS Violation",
+        0x20 => "Page Scan Repetition Mode Change",
+        0x21 => "Flow Specification Complete",
+        0x22 => "Inquiry Result with RSSI",
+        0x23 => "Read Remote Extended Features Complete",
+        0x2C => "Synchronous Connection Complete",
+        0x2D => "Synchronous Connection Changed",
+        0x2E => "Sniff Subrating",
+        0x2F => "Extended Inquiry Result",
+        0x30 => "Encryption Key Refresh Complete",
+        0x31 => "IO Capability Request",
+        0x32 => "IO Capability Response",
+        0x33 => "User Confirmation Request",
+        0x34 => "User Passkey Request",
+        0x35 => "Remote OOB Data Request",
+        0x36 => "Simple Pairing Complete",
+        0x38 => "Link Supervision Timeout Changed",
+        0x39 => "Enhanced Flush Complete",
+        0x3B => "User Passkey Notification",
+        0x3C => "Keypress Notification",
+        0x3D => "Remote Host Supported Features Notification",
+        0x3E => "LE Meta",
+        0x48 => "Number Of Completed Data Blocks",
+        0xFF => "Vendor Specific",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Name of an error code (Core specification Vol 1 Part F), used for status and reason parameters
+pub fn error_code_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x00 => "Success",
+        0x01 => "Unknown HCI Command",
+        0x02 => "Unknown Connection Identifier",
+        0x03 => "Hardware Failure",
+        0x04 => "Page Timeout",
+        0x05 => "Authentication Failure",
+        0x06 => "PIN or Key Missing",
+        0x07 => "Memory Capacity Exceeded",
+        0x08 => "Connection Timeout",
+        0x09 => "Connection Limit Exceeded",
+        0x0A => "Synchronous Connection Limit To A Device Exceeded",
+        0x0B => "Connection Already Exists",
+        0x0C => "Command Disallowed",
+        0x0D => "Connection Rejected due to Limited Resources",
+        0x0E => "Connection Rejected Due To Security Reasons",
+        0x0F => "Connection Rejected due to Unacceptable BD_ADDR",
+        0x10 => "Connection Accept Timeout Exceeded",
+        0x11 => "Unsupported Feature or Parameter Value",
+        0x12 => "Invalid HCI Command Parameters",
+        0x13 => "Remote User Terminated Connection",
+        0x14 => "Remote Device Terminated Connection due to Low Resources",
+        0x15 => "Remote Device Terminated Connection due to Power Off",
+        0x16 => "Connection Terminated By Local Host",
+        0x17 => "Repeated Attempts",
+        0x18 => "Pairing Not Allowed",
+        0x19 => "Unknown LMP PDU",
+        0x1A => "Unsupported Remote Feature",
+        0x1B => "SCO Offset Rejected",
+        0x1C => "SCO Interval Rejected",
+        0x1D => "SCO Air Mode Rejected",
+        0x1E => "Invalid LMP Parameters / Invalid LL Parameters",
+        0x1F => "Unspecified Error",
+        0x20 => "Unsupported LMP Parameter Value / Unsupported LL Parameter Value",
+        0x21 => "Role Change Not Allowed",
+        0x22 => "LMP Response Timeout / LL Response Timeout",
+        0x23 => "LMP Error Transaction Collision / LL Procedure Collision",
+        0x24 => "LMP PDU Not Allowed",
+        0x25 => "Encryption Mode Not Acceptable",
+        0x26 => "Link Key cannot be Changed",
+        0x27 => "Requested QoS Not Supported",
+        0x28 => "Instant Passed",
+        0x29 => "Pairing With Unit Key Not Supported",
+        0x2A => "Different Transaction Collision",
+        0x2C => "QoS Unacceptable Parameter",
+        0x2D => "QoS Rejected",
+        0x2E => "Channel Classification Not Supported",
+        0x2F => "Insufficient Security",
+        0x30 => "Parameter Out Of Mandatory Range",
+        0x32 => "Role Switch Pending",
+        0x34 => "Reserved Slot Violation",
+        0x35 => "Role Switch Failed",
+        0x36 => "Extended Inquiry Response Too Large",
+        0x37 => "Secure Simple Pairing Not Supported By Host",
+        0x38 => "Host Busy - Pairing",
+        0x39 => "Connection Rejected due to No Suitable Channel Found",
+        0x3A => "Controller Busy",
+        0x3B => "Unacceptable Connection Parameters",
+        0x3C => "Advertising Timeout",
+        0x3D => "Connection Terminated due to MIC Failure",
+        0x3E => "Connection Failed to be Established / Synchronization Timeout",
+        0x40 => "Coarse Clock Adjustment Rejected but Will Try to Adjust Using Clock Dragging",
+        0x41 => "Type0 Submap Not Defined",
+        0x42 => "Unknown Advertising Identifier",
+        0x43 => "Limit Reached",
+        0x44 => "Operation Cancelled by Host",
+        0x45 => "Packet Too Long",
+        0x46 => "Too Late",
+        0x47 => "Too Early",
+        0x48 => "Insufficient Channels",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// connection handles are 12 bit, the upper 4 bits are reserved or flags
+pub(crate) fn read_handle<R: Read>(reader: &mut R) -> io::Result<u16> {
+    Ok(reader.read_u16::<LittleEndian>()? & 0x0FFF)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// LE Enhanced Connection Complete parameters after the subevent code, handle 0x0040
+    fn enhanced_connection_complete() -> Vec<u8> {
+        let mut params = vec![0x00, 0x40, 0x00, 0x01, 0x03];
+        params.extend_from_slice(&[0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6]);
+        params.extend_from_slice(&[0xb1, 0xb2, 0xb3, 0xb4, 0xb5, 0x46]);
+        params.extend_from_slice(&[0x00; 6]);
+        params.extend_from_slice(&[0x18, 0x00, 0x02, 0x00, 0x48, 0x00, 0x01]);
+        params
+    }
+
+    #[test]
+    fn event_header() {
+        let event = Event::try_from(&[0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00][..]).unwrap();
+        assert_eq!(event.code, Event::COMMAND_COMPLETE);
+        assert_eq!(event.params_len, 4);
+        assert_eq!(event.params, [0x01, 0x03, 0x0c, 0x00]);
+
+        let error = Event::try_from(&[0x0e][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        // the parameters are the bytes present, whatever the length says
+        let event = Event::try_from(&[0x0e, 0x04, 0x01][..]).unwrap();
+        assert_eq!(event.params, [0x01]);
+    }
+
+    #[test]
+    fn command_complete() {
+        let complete = CommandComplete::try_from(&[0x01, 0x03, 0x0c, 0x00][..]).unwrap();
+        assert_eq!(complete.opcode, opcode::RESET);
+        assert_eq!(complete.status(), Some(0x00));
+
+        // e.g. the Command Complete of a NOP only frees command credits
+        let complete = CommandComplete::try_from(&[0x01, 0x00, 0x00][..]).unwrap();
+        assert_eq!(complete.opcode.raw(), 0x0000);
+        assert!(complete.return_parameters.is_empty());
+        assert_eq!(complete.status(), None);
+
+        assert!(CommandComplete::try_from(&[0x01, 0x03][..]).is_err());
+    }
+
+    #[test]
+    fn command_status() {
+        let status = CommandStatus::parse(&mut &[0x0c, 0x01, 0x0d, 0x20][..]).unwrap();
+        assert_eq!(status.status, 0x0c);
+        assert_eq!(status.num_hci_command_packets, 1);
+        assert_eq!(status.opcode.raw(), 0x200d);
+        assert!(CommandStatus::parse(&mut &[0x00, 0x01, 0x0d][..]).is_err());
+    }
+
+    #[test]
+    fn enhanced_connection_complete_layouts() {
+        let mut v1 = vec![LeMetaEvent::ENHANCED_CONNECTION_COMPLETE];
+        v1.extend(enhanced_connection_complete());
+        let LeMetaEvent::ConnectionComplete(complete) = LeMetaEvent::try_from(&v1[..]).unwrap()
+        else {
+            panic!("not a connection complete");
+        };
+        assert_eq!(complete.handle, 0x0040);
+        assert_eq!(complete.role, LeConnectionComplete::ROLE_PERIPHERAL);
+        assert_eq!(complete.peer_address.to_string(), "A6:A5:A4:A3:A2:A1");
+        assert_eq!(
+            complete.local_resolvable_private_address,
+            Some(BdAddr([0xb1, 0xb2, 0xb3, 0xb4, 0xb5, 0x46]))
+        );
+        assert_eq!(
+            complete.peer_resolvable_private_address,
+            Some(BdAddr([0; 6]))
+        );
+        assert_eq!(complete.connection_interval, 0x0018);
+        assert_eq!(complete.peripheral_latency, 2);
+        assert_eq!(complete.supervision_timeout, 0x0048);
+        assert_eq!(complete.central_clock_accuracy, 1);
+
+        // v2 appends the advertising handle and the sync handle
+        let mut v2 = vec![LeMetaEvent::ENHANCED_CONNECTION_COMPLETE_V2];
+        v2.extend(enhanced_connection_complete());
+        v2.extend_from_slice(&[0x02, 0xff, 0xff]);
+        assert_eq!(
+            LeMetaEvent::try_from(&v2[..]).unwrap(),
+            LeMetaEvent::ConnectionComplete(complete)
+        );
+
+        // the legacy event has no resolvable private addresses
+        let mut legacy = vec![LeMetaEvent::CONNECTION_COMPLETE];
+        legacy.extend_from_slice(&v1[1..12]);
+        legacy.extend_from_slice(&v1[24..]);
+        let LeMetaEvent::ConnectionComplete(legacy) = LeMetaEvent::try_from(&legacy[..]).unwrap()
+        else {
+            panic!("not a connection complete");
+        };
+        assert_eq!(legacy.local_resolvable_private_address, None);
+        assert_eq!(legacy.connection_interval, 0x0018);
+    }
+
+    #[test]
+    fn truncated_le_meta_parameters() {
+        assert!(LeMetaEvent::try_from(&[][..]).is_err());
+        let mut v1 = vec![LeMetaEvent::ENHANCED_CONNECTION_COMPLETE];
+        v1.extend(enhanced_connection_complete());
+        for len in 1..v1.len() {
+            let error = LeMetaEvent::try_from(&v1[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        // an advertising report announcing more reports than it carries
+        let error = LeMetaEvent::try_from(&[LeMetaEvent::ADVERTISING_REPORT, 0x02][..]);
+        assert!(error.is_err());
+        // undecoded subevents keep their parameters
+        assert_eq!(
+            LeMetaEvent::try_from(&[0x05, 0x40][..]).unwrap(),
+            LeMetaEvent::Unknown {
+                subevent_code: 0x05,
+                params: &[0x40],
+            }
+        );
+    }
+
+    #[test]
+    fn address_kinds() {
+        let address = |msb: u8| BdAddr([0x01, 0x02, 0x03, 0x04, 0x05, msb]);
+        assert_eq!(address(0xc0).kind(0x00), AddressKind::Public);
+        assert_eq!(address(0xc0).kind(0x01), AddressKind::RandomStatic);
+        assert_eq!(address(0x40).kind(0x01), AddressKind::ResolvablePrivate);
+        assert_eq!(address(0x00).kind(0x01), AddressKind::NonResolvablePrivate);
+        assert_eq!(address(0x80).kind(0x01), AddressKind::Reserved);
+        assert_eq!(address(0xc0).kind(0x03), AddressKind::RandomStatic);
+        assert_eq!(address(0x40).oui(), [0x40, 0x05, 0x04]);
+        assert_eq!(format!("{:?}", address(0x40)), "BdAddr(40:05:04:03:02:01)");
+        assert!(BdAddr::parse(&mut &[0x01, 0x02][..]).is_err());
+    }
+
+    #[test]
+    fn acl_header() {
+        let acl = Acl::try_from(&[0x40, 0x20, 0x05, 0x00, 0x01, 0x00, 0x04, 0x00][..]).unwrap();
+        assert_eq!(acl.handle, 0x0040);
+        assert_eq!(
+            acl.packet_boundary_flag,
+            PacketBoundaryFlag::FirstAutomaticallyFlushable
+        );
+        assert_eq!(acl.broadcast_flag, 0);
+        // the data is what the packet carries, not what the header announces
+        assert_eq!(acl.data_len, 5);
+        assert_eq!(acl.data, [0x01, 0x00, 0x04, 0x00]);
+
+        assert!(Acl::try_from(&[0x40, 0x20, 0x05][..]).is_err());
+    }
+}
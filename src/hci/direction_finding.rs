@@ -0,0 +1,352 @@
+//! LE Direction Finding: the commands configuring the Constant Tone Extension (CTE) sent and
+//! sampled for Angle of Arrival (AoA) and Angle of Departure (AoD), and the IQ reports of the
+//! samples, see Vol 4, Part E, 7.8.80 to 7.8.87 and 7.7.65.21 to 7.7.65.23.
+
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{opcode, read_handle, Opcode};
+
+/// CTE_Type of the commands and reports
+pub const CTE_TYPE_AOA: u8 = 0x00;
+pub const CTE_TYPE_AOD_1US: u8 = 0x01;
+pub const CTE_TYPE_AOD_2US: u8 = 0x02;
+
+/// `AoA`, `AoD 1 µs` or `AoD 2 µs`
+pub fn cte_type_name(cte_type: u8) -> Option<&'static str> {
+    let name = match cte_type {
+        CTE_TYPE_AOA => "AoA",
+        CTE_TYPE_AOD_1US => "AoD 1 µs",
+        CTE_TYPE_AOD_2US => "AoD 2 µs",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn read_antenna_ids<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let length = reader.read_u8()?;
+    let mut antenna_ids = vec![0; length as usize];
+    reader.read_exact(&mut antenna_ids)?;
+    Ok(antenna_ids)
+}
+
+/// The parameters of the Direction Finding commands
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum CteCommand {
+    /// the CTE of the periodic advertising of an advertising set
+    SetConnectionlessCteTransmitParameters {
+        advertising_handle: u8,
+        /// in 8 µs units, 0x02 to 0x14
+        cte_length: u8,
+        cte_type: u8,
+        /// CTEs sent in each periodic advertising interval
+        cte_count: u8,
+        /// the antenna switching pattern of AoD
+        antenna_ids: Vec<u8>,
+    },
+    SetConnectionlessCteTransmitEnable {
+        advertising_handle: u8,
+        enable: bool,
+    },
+    /// sampling of the CTEs of a periodic advertising train
+    SetConnectionlessIqSamplingEnable {
+        sync_handle: u16,
+        enable: bool,
+        /// 0x01 switching and sampling slots of 1 µs, 0x02 of 2 µs
+        slot_durations: u8,
+        /// 0x00 as many as possible
+        max_sampled_ctes: u8,
+        /// the antenna switching pattern of AoA
+        antenna_ids: Vec<u8>,
+    },
+    SetConnectionCteReceiveParameters {
+        handle: u16,
+        enable: bool,
+        slot_durations: u8,
+        antenna_ids: Vec<u8>,
+    },
+    SetConnectionCteTransmitParameters {
+        handle: u16,
+        /// bit 0 AoA, bit 1 AoD 1 µs, bit 2 AoD 2 µs
+        cte_types: u8,
+        antenna_ids: Vec<u8>,
+    },
+    ConnectionCteRequestEnable {
+        handle: u16,
+        enable: bool,
+        /// in connection events, 0x0000 to request a CTE once
+        cte_request_interval: u16,
+        /// in 8 µs units
+        requested_cte_length: u8,
+        requested_cte_type: u8,
+    },
+    ConnectionCteResponseEnable {
+        handle: u16,
+        enable: bool,
+    },
+    ReadAntennaInformation,
+}
+
+impl CteCommand {
+    pub const OPCODES: [Opcode; 8] = [
+        opcode::LE_SET_CONNECTIONLESS_CTE_TRANSMIT_PARAMETERS,
+        opcode::LE_SET_CONNECTIONLESS_CTE_TRANSMIT_ENABLE,
+        opcode::LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE,
+        opcode::LE_SET_CONNECTION_CTE_RECEIVE_PARAMETERS,
+        opcode::LE_SET_CONNECTION_CTE_TRANSMIT_PARAMETERS,
+        opcode::LE_CONNECTION_CTE_REQUEST_ENABLE,
+        opcode::LE_CONNECTION_CTE_RESPONSE_ENABLE,
+        opcode::LE_READ_ANTENNA_INFORMATION,
+    ];
+
+    /// The command of `opcode` with its parameters, an error for other commands
+    pub fn parse<R: Read>(opcode: Opcode, reader: &mut R) -> io::Result<Self> {
+        let command = match opcode {
+            opcode::LE_SET_CONNECTIONLESS_CTE_TRANSMIT_PARAMETERS => {
+                Self::SetConnectionlessCteTransmitParameters {
+                    advertising_handle: reader.read_u8()?,
+                    cte_length: reader.read_u8()?,
+                    cte_type: reader.read_u8()?,
+                    cte_count: reader.read_u8()?,
+                    antenna_ids: read_antenna_ids(reader)?,
+                }
+            }
+            opcode::LE_SET_CONNECTIONLESS_CTE_TRANSMIT_ENABLE => {
+                Self::SetConnectionlessCteTransmitEnable {
+                    advertising_handle: reader.read_u8()?,
+                    enable: reader.read_u8()? != 0,
+                }
+            }
+            opcode::LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE => {
+                Self::SetConnectionlessIqSamplingEnable {
+                    sync_handle: read_handle(reader)?,
+                    enable: reader.read_u8()? != 0,
+                    slot_durations: reader.read_u8()?,
+                    max_sampled_ctes: reader.read_u8()?,
+                    antenna_ids: read_antenna_ids(reader)?,
+                }
+            }
+            opcode::LE_SET_CONNECTION_CTE_RECEIVE_PARAMETERS => {
+                Self::SetConnectionCteReceiveParameters {
+                    handle: read_handle(reader)?,
+                    enable: reader.read_u8()? != 0,
+                    slot_durations: reader.read_u8()?,
+                    antenna_ids: read_antenna_ids(reader)?,
+                }
+            }
+            opcode::LE_SET_CONNECTION_CTE_TRANSMIT_PARAMETERS => {
+                Self::SetConnectionCteTransmitParameters {
+                    handle: read_handle(reader)?,
+                    cte_types: reader.read_u8()?,
+                    antenna_ids: read_antenna_ids(reader)?,
+                }
+            }
+            opcode::LE_CONNECTION_CTE_REQUEST_ENABLE => Self::ConnectionCteRequestEnable {
+                handle: read_handle(reader)?,
+                enable: reader.read_u8()? != 0,
+                cte_request_interval: reader.read_u16::<LittleEndian>()?,
+                requested_cte_length: reader.read_u8()?,
+                requested_cte_type: reader.read_u8()?,
+            },
+            opcode::LE_CONNECTION_CTE_RESPONSE_ENABLE => Self::ConnectionCteResponseEnable {
+                handle: read_handle(reader)?,
+                enable: reader.read_u8()? != 0,
+            },
+            opcode::LE_READ_ANTENNA_INFORMATION => Self::ReadAntennaInformation,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a direction finding command",
+                ))
+            }
+        };
+        Ok(command)
+    }
+}
+
+/// LE Read Antenna Information return parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AntennaInformation {
+    pub status: u8,
+    /// bit 0 1 µs switching for AoD transmission, bit 1 1 µs sampling for AoD reception, bit 2
+    /// 1 µs switching and sampling for AoA reception
+    pub supported_switching_sampling_rates: u8,
+    pub num_antennae: u8,
+    pub max_switching_pattern_length: u8,
+    /// in 8 µs units
+    pub max_cte_length: u8,
+}
+
+impl AntennaInformation {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            supported_switching_sampling_rates: reader.read_u8()?,
+            num_antennae: reader.read_u8()?,
+            max_switching_pattern_length: reader.read_u8()?,
+            max_cte_length: reader.read_u8()?,
+        })
+    }
+}
+
+/// One sample of a CTE, its in-phase and quadrature components
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IqSample {
+    pub i: i8,
+    pub q: i8,
+}
+
+impl IqSample {
+    /// The value of both components when no valid sample was available
+    pub const NOT_AVAILABLE: i8 = -128;
+
+    pub fn is_available(&self) -> bool {
+        self.i != Self::NOT_AVAILABLE && self.q != Self::NOT_AVAILABLE
+    }
+
+    /// sqrt(I² + Q²)
+    pub fn amplitude(&self) -> f64 {
+        (self.i as f64).hypot(self.q as f64)
+    }
+
+    /// atan2(Q, I), in radians
+    pub fn phase(&self) -> f64 {
+        (self.q as f64).atan2(self.i as f64)
+    }
+}
+
+/// LE Connectionless IQ Report and LE Connection IQ Report, the samples of a received CTE
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct IqReport {
+    /// the sync handle of a periodic advertising train, or the connection handle, 0x0FFF for
+    /// the test mode of the receiver
+    pub handle: u16,
+    /// only reported over a connection: 0x01 LE 1M, 0x02 LE 2M
+    pub rx_phy: Option<u8>,
+    pub channel_index: u8,
+    /// in units of 0.1 dBm
+    pub rssi: i16,
+    pub rssi_antenna_id: u8,
+    pub cte_type: u8,
+    /// 0x01 switching and sampling slots of 1 µs, 0x02 of 2 µs
+    pub slot_durations: u8,
+    /// 0x00 CRC correct, 0x01 CRC incorrect and the CTE length from the Length field, 0x02
+    /// CRC incorrect and the CTE length from another mechanism, 0xFF insufficient resources
+    pub packet_status: u8,
+    /// the periodic event counter or the connection event counter
+    pub event_counter: u16,
+    pub samples: Vec<IqSample>,
+}
+
+impl IqReport {
+    pub const PACKET_STATUS_CRC_CORRECT: u8 = 0x00;
+    pub const PACKET_STATUS_INSUFFICIENT_RESOURCES: u8 = 0xFF;
+
+    /// parse the subevent parameters, `connection` for LE Connection IQ Report
+    pub fn parse<R: Read>(reader: &mut R, connection: bool) -> io::Result<Self> {
+        let handle = read_handle(reader)?;
+        let rx_phy = if connection {
+            Some(reader.read_u8()?)
+        } else {
+            None
+        };
+        let channel_index = reader.read_u8()?;
+        let rssi = reader.read_i16::<LittleEndian>()?;
+        let rssi_antenna_id = reader.read_u8()?;
+        let cte_type = reader.read_u8()?;
+        let slot_durations = reader.read_u8()?;
+        let packet_status = reader.read_u8()?;
+        let event_counter = reader.read_u16::<LittleEndian>()?;
+        let sample_count = reader.read_u8()?;
+        // each I sample is followed by its Q sample
+        let samples = (0..sample_count)
+            .map(|_| {
+                Ok(IqSample {
+                    i: reader.read_i8()?,
+                    q: reader.read_i8()?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            handle,
+            rx_phy,
+            channel_index,
+            rssi,
+            rssi_antenna_id,
+            cte_type,
+            slot_durations,
+            packet_status,
+            event_counter,
+            samples,
+        })
+    }
+
+    /// The in-phase components, in order
+    pub fn i_samples(&self) -> Vec<i8> {
+        self.samples.iter().map(|sample| sample.i).collect()
+    }
+
+    /// The quadrature components, in order
+    pub fn q_samples(&self) -> Vec<i8> {
+        self.samples.iter().map(|sample| sample.q).collect()
+    }
+
+    /// in dBm
+    pub fn rssi_dbm(&self) -> f64 {
+        self.rssi as f64 / 10.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hci::LeMetaEvent;
+
+    #[test]
+    fn direction_finding() {
+        // sync handle 1, enabled, 1 µs slots, as many CTEs as possible, 4 antennae
+        let params = [0x01, 0x00, 0x01, 0x01, 0x00, 0x04, 0, 1, 2, 3];
+        let sampling = opcode::LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE;
+        assert_eq!(
+            CteCommand::parse(sampling, &mut &params[..9])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            CteCommand::parse(sampling, &mut &params[..]).unwrap(),
+            CteCommand::SetConnectionlessIqSamplingEnable {
+                sync_handle: 1,
+                enable: true,
+                slot_durations: 1,
+                max_sampled_ctes: 0,
+                antenna_ids: vec![0, 1, 2, 3],
+            }
+        );
+        assert!(CteCommand::parse(opcode::RESET, &mut &params[..]).is_err());
+
+        // LE Connection IQ Report: handle 0x0040, LE 1M, channel 12, -45.5 dBm, 2 samples
+        let report = [
+            0x16, 0x40, 0x00, 0x01, 0x0c, 0x39, 0xfe, 0x00, 0x00, 0x01, 0x00, 0x07, 0x00, 0x02,
+            0x0a, 0xf6, 0x80, 0x80,
+        ];
+        let LeMetaEvent::ConnectionIqReport(report) = LeMetaEvent::try_from(&report[..]).unwrap()
+        else {
+            panic!("not an IQ report");
+        };
+        assert_eq!(report.handle, 0x0040);
+        assert_eq!(report.rx_phy, Some(0x01));
+        assert_eq!(report.rssi_dbm(), -45.5);
+        assert_eq!(report.event_counter, 7);
+        assert_eq!(report.i_samples(), [10, -128]);
+        assert_eq!(report.q_samples(), [-10, -128]);
+        assert!(report.samples[0].is_available());
+        assert!(!report.samples[1].is_available());
+        assert!((report.samples[0].phase() + std::f64::consts::FRAC_PI_4).abs() < 1e-9);
+    }
+}
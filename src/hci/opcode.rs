@@ -0,0 +1,353 @@
+//! Opcodes of the commands from the OpCode enum of hci_packets.pdl, named after the
+//! specification, e.g. [`LE_SET_SCAN_ENABLE`] for "LE Set Scan Enable". Versioned commands end
+//! with their version, e.g. [`LE_RECEIVER_TEST_V2`].
+
+use super::Opcode;
+
+pub(crate) fn name(opcode: u16) -> Option<&'static str> {
+    NAMES
+        .binary_search_by_key(&opcode, |(opcode, _)| *opcode)
+        .ok()
+        .map(|index| NAMES[index].1)
+}
+
+macro_rules! opcodes {
+    ($(($value:literal, $name:literal, $constant:ident),)*) => {
+        $(
+            #[doc = concat!("`", $name, "`")]
+            pub const $constant: Opcode = Opcode($value);
+        )*
+
+        /// sorted by opcode
+        const NAMES: &[(u16, &str)] = &[$(($value, $name),)*];
+    };
+}
+
+opcodes! {
+    (0x0401, "Inquiry", INQUIRY),
+    (0x0402, "Inquiry Cancel", INQUIRY_CANCEL),
+    (0x0403, "Periodic Inquiry Mode", PERIODIC_INQUIRY_MODE),
+    (0x0404, "Exit Periodic Inquiry Mode", EXIT_PERIODIC_INQUIRY_MODE),
+    (0x0405, "Create Connection", CREATE_CONNECTION),
+    (0x0406, "Disconnect", DISCONNECT),
+    (0x0407, "Add SCO Connection", ADD_SCO_CONNECTION),
+    (0x0408, "Create Connection Cancel", CREATE_CONNECTION_CANCEL),
+    (0x0409, "Accept Connection Request", ACCEPT_CONNECTION_REQUEST),
+    (0x040A, "Reject Connection Request", REJECT_CONNECTION_REQUEST),
+    (0x040B, "Link Key Request Reply", LINK_KEY_REQUEST_REPLY),
+    (0x040C, "Link Key Request Negative Reply", LINK_KEY_REQUEST_NEGATIVE_REPLY),
+    (0x040D, "Pin Code Request Reply", PIN_CODE_REQUEST_REPLY),
+    (0x040E, "Pin Code Request Negative Reply", PIN_CODE_REQUEST_NEGATIVE_REPLY),
+    (0x040F, "Change Connection Packet Type", CHANGE_CONNECTION_PACKET_TYPE),
+    (0x0411, "Authentication Requested", AUTHENTICATION_REQUESTED),
+    (0x0413, "Set Connection Encryption", SET_CONNECTION_ENCRYPTION),
+    (0x0415, "Change Connection Link Key", CHANGE_CONNECTION_LINK_KEY),
+    (0x0417, "Central Link Key", CENTRAL_LINK_KEY),
+    (0x0419, "Remote Name Request", REMOTE_NAME_REQUEST),
+    (0x041A, "Remote Name Request Cancel", REMOTE_NAME_REQUEST_CANCEL),
+    (0x041B, "Read Remote Supported Features", READ_REMOTE_SUPPORTED_FEATURES),
+    (0x041C, "Read Remote Extended Features", READ_REMOTE_EXTENDED_FEATURES),
+    (0x041D, "Read Remote Version Information", READ_REMOTE_VERSION_INFORMATION),
+    (0x041F, "Read Clock Offset", READ_CLOCK_OFFSET),
+    (0x0420, "Read LMP Handle", READ_LMP_HANDLE),
+    (0x0428, "Setup Synchronous Connection", SETUP_SYNCHRONOUS_CONNECTION),
+    (0x0429, "Accept Synchronous Connection", ACCEPT_SYNCHRONOUS_CONNECTION),
+    (0x042A, "Reject Synchronous Connection", REJECT_SYNCHRONOUS_CONNECTION),
+    (0x042B, "IO Capability Request Reply", IO_CAPABILITY_REQUEST_REPLY),
+    (0x042C, "User Confirmation Request Reply", USER_CONFIRMATION_REQUEST_REPLY),
+    (0x042D, "User Confirmation Request Negative Reply", USER_CONFIRMATION_REQUEST_NEGATIVE_REPLY),
+    (0x042E, "User Passkey Request Reply", USER_PASSKEY_REQUEST_REPLY),
+    (0x042F, "User Passkey Request Negative Reply", USER_PASSKEY_REQUEST_NEGATIVE_REPLY),
+    (0x0430, "Remote OOB Data Request Reply", REMOTE_OOB_DATA_REQUEST_REPLY),
+    (0x0433, "Remote OOB Data Request Negative Reply", REMOTE_OOB_DATA_REQUEST_NEGATIVE_REPLY),
+    (0x0434, "IO Capability Request Negative Reply", IO_CAPABILITY_REQUEST_NEGATIVE_REPLY),
+    (0x043D, "Enhanced Setup Synchronous Connection", ENHANCED_SETUP_SYNCHRONOUS_CONNECTION),
+    (0x043E, "Enhanced Accept Synchronous Connection", ENHANCED_ACCEPT_SYNCHRONOUS_CONNECTION),
+    (0x043F, "Truncated Page", TRUNCATED_PAGE),
+    (0x0440, "Truncated Page Cancel", TRUNCATED_PAGE_CANCEL),
+    (0x0441, "Set Connectionless Peripheral Broadcast", SET_CONNECTIONLESS_PERIPHERAL_BROADCAST),
+    (0x0442, "Set Connectionless Peripheral Broadcast Receive", SET_CONNECTIONLESS_PERIPHERAL_BROADCAST_RECEIVE),
+    (0x0443, "Start Synchronization Train", START_SYNCHRONIZATION_TRAIN),
+    (0x0444, "Receive Synchronization Train", RECEIVE_SYNCHRONIZATION_TRAIN),
+    (0x0445, "Remote OOB Extended Data Request Reply", REMOTE_OOB_EXTENDED_DATA_REQUEST_REPLY),
+    (0x0801, "Hold Mode", HOLD_MODE),
+    (0x0803, "Sniff Mode", SNIFF_MODE),
+    (0x0804, "Exit Sniff Mode", EXIT_SNIFF_MODE),
+    (0x0807, "QOS Setup", QOS_SETUP),
+    (0x0809, "Role Discovery", ROLE_DISCOVERY),
+    (0x080B, "Switch Role", SWITCH_ROLE),
+    (0x080C, "Read Link Policy Settings", READ_LINK_POLICY_SETTINGS),
+    (0x080D, "Write Link Policy Settings", WRITE_LINK_POLICY_SETTINGS),
+    (0x080E, "Read Default Link Policy Settings", READ_DEFAULT_LINK_POLICY_SETTINGS),
+    (0x080F, "Write Default Link Policy Settings", WRITE_DEFAULT_LINK_POLICY_SETTINGS),
+    (0x0810, "Flow Specification", FLOW_SPECIFICATION),
+    (0x0811, "Sniff Subrating", SNIFF_SUBRATING),
+    (0x0C01, "Set Event Mask", SET_EVENT_MASK),
+    (0x0C03, "Reset", RESET),
+    (0x0C05, "Set Event Filter", SET_EVENT_FILTER),
+    (0x0C08, "Flush", FLUSH),
+    (0x0C09, "Read Pin Type", READ_PIN_TYPE),
+    (0x0C0A, "Write Pin Type", WRITE_PIN_TYPE),
+    (0x0C0D, "Read Stored Link Key", READ_STORED_LINK_KEY),
+    (0x0C11, "Write Stored Link Key", WRITE_STORED_LINK_KEY),
+    (0x0C12, "Delete Stored Link Key", DELETE_STORED_LINK_KEY),
+    (0x0C13, "Write Local Name", WRITE_LOCAL_NAME),
+    (0x0C14, "Read Local Name", READ_LOCAL_NAME),
+    (0x0C15, "Read Connection Accept Timeout", READ_CONNECTION_ACCEPT_TIMEOUT),
+    (0x0C16, "Write Connection Accept Timeout", WRITE_CONNECTION_ACCEPT_TIMEOUT),
+    (0x0C17, "Read Page Timeout", READ_PAGE_TIMEOUT),
+    (0x0C18, "Write Page Timeout", WRITE_PAGE_TIMEOUT),
+    (0x0C19, "Read Scan Enable", READ_SCAN_ENABLE),
+    (0x0C1A, "Write Scan Enable", WRITE_SCAN_ENABLE),
+    (0x0C1B, "Read Page Scan Activity", READ_PAGE_SCAN_ACTIVITY),
+    (0x0C1C, "Write Page Scan Activity", WRITE_PAGE_SCAN_ACTIVITY),
+    (0x0C1D, "Read Inquiry Scan Activity", READ_INQUIRY_SCAN_ACTIVITY),
+    (0x0C1E, "Write Inquiry Scan Activity", WRITE_INQUIRY_SCAN_ACTIVITY),
+    (0x0C1F, "Read Authentication Enable", READ_AUTHENTICATION_ENABLE),
+    (0x0C20, "Write Authentication Enable", WRITE_AUTHENTICATION_ENABLE),
+    (0x0C23, "Read Class Of Device", READ_CLASS_OF_DEVICE),
+    (0x0C24, "Write Class Of Device", WRITE_CLASS_OF_DEVICE),
+    (0x0C25, "Read Voice Setting", READ_VOICE_SETTING),
+    (0x0C26, "Write Voice Setting", WRITE_VOICE_SETTING),
+    (0x0C27, "Read Automatic Flush Timeout", READ_AUTOMATIC_FLUSH_TIMEOUT),
+    (0x0C28, "Write Automatic Flush Timeout", WRITE_AUTOMATIC_FLUSH_TIMEOUT),
+    (0x0C29, "Read Num Broadcast Retransmits", READ_NUM_BROADCAST_RETRANSMITS),
+    (0x0C2A, "Write Num Broadcast Retransmits", WRITE_NUM_BROADCAST_RETRANSMITS),
+    (0x0C2B, "Read Hold Mode Activity", READ_HOLD_MODE_ACTIVITY),
+    (0x0C2C, "Write Hold Mode Activity", WRITE_HOLD_MODE_ACTIVITY),
+    (0x0C2D, "Read Transmit Power Level", READ_TRANSMIT_POWER_LEVEL),
+    (0x0C2E, "Read Synchronous Flow Control Enable", READ_SYNCHRONOUS_FLOW_CONTROL_ENABLE),
+    (0x0C2F, "Write Synchronous Flow Control Enable", WRITE_SYNCHRONOUS_FLOW_CONTROL_ENABLE),
+    (0x0C31, "Set Controller To Host Flow Control", SET_CONTROLLER_TO_HOST_FLOW_CONTROL),
+    (0x0C33, "Host Buffer Size", HOST_BUFFER_SIZE),
+    (0x0C35, "Host Number Of Completed Packets", HOST_NUMBER_OF_COMPLETED_PACKETS),
+    (0x0C36, "Read Link Supervision Timeout", READ_LINK_SUPERVISION_TIMEOUT),
+    (0x0C37, "Write Link Supervision Timeout", WRITE_LINK_SUPERVISION_TIMEOUT),
+    (0x0C38, "Read Number Of Supported IAC", READ_NUMBER_OF_SUPPORTED_IAC),
+    (0x0C39, "Read Current IAC LAP", READ_CURRENT_IAC_LAP),
+    (0x0C3A, "Write Current IAC LAP", WRITE_CURRENT_IAC_LAP),
+    (0x0C3F, "Set AFH Host Channel Classification", SET_AFH_HOST_CHANNEL_CLASSIFICATION),
+    (0x0C42, "Read Inquiry Scan Type", READ_INQUIRY_SCAN_TYPE),
+    (0x0C43, "Write Inquiry Scan Type", WRITE_INQUIRY_SCAN_TYPE),
+    (0x0C44, "Read Inquiry Mode", READ_INQUIRY_MODE),
+    (0x0C45, "Write Inquiry Mode", WRITE_INQUIRY_MODE),
+    (0x0C46, "Read Page Scan Type", READ_PAGE_SCAN_TYPE),
+    (0x0C47, "Write Page Scan Type", WRITE_PAGE_SCAN_TYPE),
+    (0x0C48, "Read AFH Channel Assessment Mode", READ_AFH_CHANNEL_ASSESSMENT_MODE),
+    (0x0C49, "Write AFH Channel Assessment Mode", WRITE_AFH_CHANNEL_ASSESSMENT_MODE),
+    (0x0C51, "Read Extended Inquiry Response", READ_EXTENDED_INQUIRY_RESPONSE),
+    (0x0C52, "Write Extended Inquiry Response", WRITE_EXTENDED_INQUIRY_RESPONSE),
+    (0x0C53, "Refresh Encryption Key", REFRESH_ENCRYPTION_KEY),
+    (0x0C55, "Read Simple Pairing Mode", READ_SIMPLE_PAIRING_MODE),
+    (0x0C56, "Write Simple Pairing Mode", WRITE_SIMPLE_PAIRING_MODE),
+    (0x0C57, "Read Local OOB Data", READ_LOCAL_OOB_DATA),
+    (0x0C58, "Read Inquiry Response Transmit Power Level", READ_INQUIRY_RESPONSE_TRANSMIT_POWER_LEVEL),
+    (0x0C59, "Write Inquiry Transmit Power Level", WRITE_INQUIRY_TRANSMIT_POWER_LEVEL),
+    (0x0C5A, "Read Default Erroneous Data Reporting", READ_DEFAULT_ERRONEOUS_DATA_REPORTING),
+    (0x0C5B, "Write Default Erroneous Data Reporting", WRITE_DEFAULT_ERRONEOUS_DATA_REPORTING),
+    (0x0C5F, "Enhanced Flush", ENHANCED_FLUSH),
+    (0x0C60, "Send Keypress Notification", SEND_KEYPRESS_NOTIFICATION),
+    (0x0C63, "Set Event Mask Page 2", SET_EVENT_MASK_PAGE_2),
+    (0x0C66, "Read Flow Control Mode", READ_FLOW_CONTROL_MODE),
+    (0x0C67, "Write Flow Control Mode", WRITE_FLOW_CONTROL_MODE),
+    (0x0C68, "Read Enhanced Transmit Power Level", READ_ENHANCED_TRANSMIT_POWER_LEVEL),
+    (0x0C6C, "Read LE Host Support", READ_LE_HOST_SUPPORT),
+    (0x0C6D, "Write LE Host Support", WRITE_LE_HOST_SUPPORT),
+    (0x0C6E, "Set MWS Channel Parameters", SET_MWS_CHANNEL_PARAMETERS),
+    (0x0C6F, "Set External Frame Configuration", SET_EXTERNAL_FRAME_CONFIGURATION),
+    (0x0C70, "Set MWS Signaling", SET_MWS_SIGNALING),
+    (0x0C71, "Set MWS Transport Layer", SET_MWS_TRANSPORT_LAYER),
+    (0x0C72, "Set MWS Scan Frequency Table", SET_MWS_SCAN_FREQUENCY_TABLE),
+    (0x0C73, "Set MWS Pattern Configuration", SET_MWS_PATTERN_CONFIGURATION),
+    (0x0C74, "Set Reserved LT_ADDR", SET_RESERVED_LT_ADDR),
+    (0x0C75, "Delete Reserved LT_ADDR", DELETE_RESERVED_LT_ADDR),
+    (0x0C76, "Set Connectionless Peripheral Broadcast Data", SET_CONNECTIONLESS_PERIPHERAL_BROADCAST_DATA),
+    (0x0C77, "Read Synchronization Train Parameters", READ_SYNCHRONIZATION_TRAIN_PARAMETERS),
+    (0x0C78, "Write Synchronization Train Parameters", WRITE_SYNCHRONIZATION_TRAIN_PARAMETERS),
+    (0x0C79, "Read Secure Connections Host Support", READ_SECURE_CONNECTIONS_HOST_SUPPORT),
+    (0x0C7A, "Write Secure Connections Host Support", WRITE_SECURE_CONNECTIONS_HOST_SUPPORT),
+    (0x0C7B, "Read Authenticated Payload Timeout", READ_AUTHENTICATED_PAYLOAD_TIMEOUT),
+    (0x0C7C, "Write Authenticated Payload Timeout", WRITE_AUTHENTICATED_PAYLOAD_TIMEOUT),
+    (0x0C7D, "Read Local OOB Extended Data", READ_LOCAL_OOB_EXTENDED_DATA),
+    (0x0C7E, "Read Extended Page Timeout", READ_EXTENDED_PAGE_TIMEOUT),
+    (0x0C7F, "Write Extended Page Timeout", WRITE_EXTENDED_PAGE_TIMEOUT),
+    (0x0C80, "Read Extended Inquiry Length", READ_EXTENDED_INQUIRY_LENGTH),
+    (0x0C81, "Write Extended Inquiry Length", WRITE_EXTENDED_INQUIRY_LENGTH),
+    (0x0C82, "Set Ecosystem Base Interval", SET_ECOSYSTEM_BASE_INTERVAL),
+    (0x0C83, "Configure Data Path", CONFIGURE_DATA_PATH),
+    (0x0C84, "Set Min Encryption Key Size", SET_MIN_ENCRYPTION_KEY_SIZE),
+    (0x1001, "Read Local Version Information", READ_LOCAL_VERSION_INFORMATION),
+    (0x1002, "Read Local Supported Commands", READ_LOCAL_SUPPORTED_COMMANDS),
+    (0x1003, "Read Local Supported Features", READ_LOCAL_SUPPORTED_FEATURES),
+    (0x1004, "Read Local Extended Features", READ_LOCAL_EXTENDED_FEATURES),
+    (0x1005, "Read Buffer Size", READ_BUFFER_SIZE),
+    (0x1009, "Read BD_ADDR", READ_BD_ADDR),
+    (0x100A, "Read Data Block Size", READ_DATA_BLOCK_SIZE),
+    (0x100B, "Read Local Supported Codecs [v1]", READ_LOCAL_SUPPORTED_CODECS_V1),
+    (0x100C, "Read Local Simple Pairing Options", READ_LOCAL_SIMPLE_PAIRING_OPTIONS),
+    (0x100D, "Read Local Supported Codecs [v2]", READ_LOCAL_SUPPORTED_CODECS_V2),
+    (0x100E, "Read Local Supported Codec Capabilities", READ_LOCAL_SUPPORTED_CODEC_CAPABILITIES),
+    (0x100F, "Read Local Supported Controller Delay", READ_LOCAL_SUPPORTED_CONTROLLER_DELAY),
+    (0x1401, "Read Failed Contact Counter", READ_FAILED_CONTACT_COUNTER),
+    (0x1402, "Reset Failed Contact Counter", RESET_FAILED_CONTACT_COUNTER),
+    (0x1403, "Read Link Quality", READ_LINK_QUALITY),
+    (0x1405, "Read RSSI", READ_RSSI),
+    (0x1406, "Read AFH Channel Map", READ_AFH_CHANNEL_MAP),
+    (0x1407, "Read Clock", READ_CLOCK),
+    (0x1408, "Read Encryption Key Size", READ_ENCRYPTION_KEY_SIZE),
+    (0x140C, "Get MWS Transport Layer Configuration", GET_MWS_TRANSPORT_LAYER_CONFIGURATION),
+    (0x140D, "Set Triggered Clock Capture", SET_TRIGGERED_CLOCK_CAPTURE),
+    (0x1801, "Read Loopback Mode", READ_LOOPBACK_MODE),
+    (0x1802, "Write Loopback Mode", WRITE_LOOPBACK_MODE),
+    (0x1803, "Enable Device Under Test Mode", ENABLE_DEVICE_UNDER_TEST_MODE),
+    (0x1804, "Write Simple Pairing Debug Mode", WRITE_SIMPLE_PAIRING_DEBUG_MODE),
+    (0x180A, "Write Secure Connections Test Mode", WRITE_SECURE_CONNECTIONS_TEST_MODE),
+    (0x2001, "LE Set Event Mask", LE_SET_EVENT_MASK),
+    (0x2002, "LE Read Buffer Size [v1]", LE_READ_BUFFER_SIZE_V1),
+    (0x2003, "LE Read Local Supported Features", LE_READ_LOCAL_SUPPORTED_FEATURES),
+    (0x2005, "LE Set Random Address", LE_SET_RANDOM_ADDRESS),
+    (0x2006, "LE Set Advertising Parameters", LE_SET_ADVERTISING_PARAMETERS),
+    (0x2007, "LE Read Advertising Physical Channel TX Power", LE_READ_ADVERTISING_PHYSICAL_CHANNEL_TX_POWER),
+    (0x2008, "LE Set Advertising Data", LE_SET_ADVERTISING_DATA),
+    (0x2009, "LE Set Scan Response Data", LE_SET_SCAN_RESPONSE_DATA),
+    (0x200A, "LE Set Advertising Enable", LE_SET_ADVERTISING_ENABLE),
+    (0x200B, "LE Set Scan Parameters", LE_SET_SCAN_PARAMETERS),
+    (0x200C, "LE Set Scan Enable", LE_SET_SCAN_ENABLE),
+    (0x200D, "LE Create Connection", LE_CREATE_CONNECTION),
+    (0x200E, "LE Create Connection Cancel", LE_CREATE_CONNECTION_CANCEL),
+    (0x200F, "LE Read Filter Accept List Size", LE_READ_FILTER_ACCEPT_LIST_SIZE),
+    (0x2010, "LE Clear Filter Accept List", LE_CLEAR_FILTER_ACCEPT_LIST),
+    (0x2011, "LE Add Device To Filter Accept List", LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST),
+    (0x2012, "LE Remove Device From Filter Accept List", LE_REMOVE_DEVICE_FROM_FILTER_ACCEPT_LIST),
+    (0x2013, "LE Connection Update", LE_CONNECTION_UPDATE),
+    (0x2014, "LE Set Host Channel Classification", LE_SET_HOST_CHANNEL_CLASSIFICATION),
+    (0x2015, "LE Read Channel Map", LE_READ_CHANNEL_MAP),
+    (0x2016, "LE Read Remote Features", LE_READ_REMOTE_FEATURES),
+    (0x2017, "LE Encrypt", LE_ENCRYPT),
+    (0x2018, "LE Rand", LE_RAND),
+    (0x2019, "LE Start Encryption", LE_START_ENCRYPTION),
+    (0x201A, "LE Long Term Key Request Reply", LE_LONG_TERM_KEY_REQUEST_REPLY),
+    (0x201B, "LE Long Term Key Request Negative Reply", LE_LONG_TERM_KEY_REQUEST_NEGATIVE_REPLY),
+    (0x201C, "LE Read Supported States", LE_READ_SUPPORTED_STATES),
+    (0x201D, "LE Receiver Test [v1]", LE_RECEIVER_TEST_V1),
+    (0x201E, "LE Transmitter Test [v1]", LE_TRANSMITTER_TEST_V1),
+    (0x201F, "LE Test End", LE_TEST_END),
+    (0x2020, "LE Remote Connection Parameter Request Reply", LE_REMOTE_CONNECTION_PARAMETER_REQUEST_REPLY),
+    (0x2022, "LE Set Data Length", LE_SET_DATA_LENGTH),
+    (0x2023, "LE Read Suggested Default Data Length", LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH),
+    (0x2024, "LE Write Suggested Default Data Length", LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH),
+    (0x2025, "LE Read Local P-256 Public Key", LE_READ_LOCAL_P_256_PUBLIC_KEY),
+    (0x2026, "LE Generate DHKey [v1]", LE_GENERATE_DHKEY_V1),
+    (0x2027, "LE Add Device To Resolving List", LE_ADD_DEVICE_TO_RESOLVING_LIST),
+    (0x2028, "LE Remove Device From Resolving List", LE_REMOVE_DEVICE_FROM_RESOLVING_LIST),
+    (0x2029, "LE Clear Resolving List", LE_CLEAR_RESOLVING_LIST),
+    (0x202A, "LE Read Resolving List Size", LE_READ_RESOLVING_LIST_SIZE),
+    (0x202B, "LE Read Peer Resolvable Address", LE_READ_PEER_RESOLVABLE_ADDRESS),
+    (0x202C, "LE Read Local Resolvable Address", LE_READ_LOCAL_RESOLVABLE_ADDRESS),
+    (0x202D, "LE Set Address Resolution Enable", LE_SET_ADDRESS_RESOLUTION_ENABLE),
+    (0x202E, "LE Set Resolvable Private Address Timeout", LE_SET_RESOLVABLE_PRIVATE_ADDRESS_TIMEOUT),
+    (0x202F, "LE Read Maximum Data Length", LE_READ_MAXIMUM_DATA_LENGTH),
+    (0x2030, "LE Read PHY", LE_READ_PHY),
+    (0x2031, "LE Set Default PHY", LE_SET_DEFAULT_PHY),
+    (0x2032, "LE Set PHY", LE_SET_PHY),
+    (0x2033, "LE Receiver Test [v2]", LE_RECEIVER_TEST_V2),
+    (0x2034, "LE Transmitter Test [v2]", LE_TRANSMITTER_TEST_V2),
+    (0x2035, "LE Set Advertising Set Random Address", LE_SET_ADVERTISING_SET_RANDOM_ADDRESS),
+    (0x2036, "LE Set Extended Advertising Parameters", LE_SET_EXTENDED_ADVERTISING_PARAMETERS),
+    (0x2037, "LE Set Extended Advertising Data", LE_SET_EXTENDED_ADVERTISING_DATA),
+    (0x2038, "LE Set Extended Scan Response Data", LE_SET_EXTENDED_SCAN_RESPONSE_DATA),
+    (0x2039, "LE Set Extended Advertising Enable", LE_SET_EXTENDED_ADVERTISING_ENABLE),
+    (0x203A, "LE Read Maximum Advertising Data Length", LE_READ_MAXIMUM_ADVERTISING_DATA_LENGTH),
+    (0x203B, "LE Read Number Of Supported Advertising Sets", LE_READ_NUMBER_OF_SUPPORTED_ADVERTISING_SETS),
+    (0x203C, "LE Remove Advertising Set", LE_REMOVE_ADVERTISING_SET),
+    (0x203D, "LE Clear Advertising Sets", LE_CLEAR_ADVERTISING_SETS),
+    (0x203E, "LE Set Periodic Advertising Parameters", LE_SET_PERIODIC_ADVERTISING_PARAMETERS),
+    (0x203F, "LE Set Periodic Advertising Data", LE_SET_PERIODIC_ADVERTISING_DATA),
+    (0x2040, "LE Set Periodic Advertising Enable", LE_SET_PERIODIC_ADVERTISING_ENABLE),
+    (0x2041, "LE Set Extended Scan Parameters", LE_SET_EXTENDED_SCAN_PARAMETERS),
+    (0x2042, "LE Set Extended Scan Enable", LE_SET_EXTENDED_SCAN_ENABLE),
+    (0x2043, "LE Extended Create Connection", LE_EXTENDED_CREATE_CONNECTION),
+    (0x2044, "LE Periodic Advertising Create Sync", LE_PERIODIC_ADVERTISING_CREATE_SYNC),
+    (0x2045, "LE Periodic Advertising Create Sync Cancel", LE_PERIODIC_ADVERTISING_CREATE_SYNC_CANCEL),
+    (0x2046, "LE Periodic Advertising Terminate Sync", LE_PERIODIC_ADVERTISING_TERMINATE_SYNC),
+    (0x2047, "LE Add Device To Periodic Advertiser List", LE_ADD_DEVICE_TO_PERIODIC_ADVERTISER_LIST),
+    (0x2048, "LE Remove Device From Periodic Advertiser List", LE_REMOVE_DEVICE_FROM_PERIODIC_ADVERTISER_LIST),
+    (0x2049, "LE Clear Periodic Advertiser List", LE_CLEAR_PERIODIC_ADVERTISER_LIST),
+    (0x204A, "LE Read Periodic Advertiser List Size", LE_READ_PERIODIC_ADVERTISER_LIST_SIZE),
+    (0x204B, "LE Read Transmit Power", LE_READ_TRANSMIT_POWER),
+    (0x204C, "LE Read RF Path Compensation Power", LE_READ_RF_PATH_COMPENSATION_POWER),
+    (0x204D, "LE Write RF Path Compensation Power", LE_WRITE_RF_PATH_COMPENSATION_POWER),
+    (0x204E, "LE Set Privacy Mode", LE_SET_PRIVACY_MODE),
+    (0x204F, "LE Receiver Test [v3]", LE_RECEIVER_TEST_V3),
+    (0x2050, "LE Transmitter Test [v3]", LE_TRANSMITTER_TEST_V3),
+    (0x2051, "LE Set Connectionless CTE Transmit Parameters", LE_SET_CONNECTIONLESS_CTE_TRANSMIT_PARAMETERS),
+    (0x2052, "LE Set Connectionless CTE Transmit Enable", LE_SET_CONNECTIONLESS_CTE_TRANSMIT_ENABLE),
+    (0x2053, "LE Set Connectionless IQ Sampling Enable", LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE),
+    (0x2054, "LE Set Connection CTE Receive Parameters", LE_SET_CONNECTION_CTE_RECEIVE_PARAMETERS),
+    (0x2055, "LE Set Connection CTE Transmit Parameters", LE_SET_CONNECTION_CTE_TRANSMIT_PARAMETERS),
+    (0x2056, "LE Connection CTE Request Enable", LE_CONNECTION_CTE_REQUEST_ENABLE),
+    (0x2057, "LE Connection CTE Response Enable", LE_CONNECTION_CTE_RESPONSE_ENABLE),
+    (0x2058, "LE Read Antenna Information", LE_READ_ANTENNA_INFORMATION),
+    (0x2059, "LE Set Periodic Advertising Receive Enable", LE_SET_PERIODIC_ADVERTISING_RECEIVE_ENABLE),
+    (0x205A, "LE Periodic Advertising Sync Transfer", LE_PERIODIC_ADVERTISING_SYNC_TRANSFER),
+    (0x205B, "LE Periodic Advertising Set Info Transfer", LE_PERIODIC_ADVERTISING_SET_INFO_TRANSFER),
+    (0x205E, "LE Generate DHKey [v2]", LE_GENERATE_DHKEY_V2),
+    (0x205F, "LE Modify Sleep Clock Accuracy", LE_MODIFY_SLEEP_CLOCK_ACCURACY),
+    (0x2060, "LE Read Buffer Size [v2]", LE_READ_BUFFER_SIZE_V2),
+    (0x2061, "LE Read ISO TX Sync", LE_READ_ISO_TX_SYNC),
+    (0x2062, "LE Set CIG Parameters", LE_SET_CIG_PARAMETERS),
+    (0x2063, "LE Set CIG Parameters Test", LE_SET_CIG_PARAMETERS_TEST),
+    (0x2064, "LE Create CIS", LE_CREATE_CIS),
+    (0x2065, "LE Remove CIG", LE_REMOVE_CIG),
+    (0x2066, "LE Accept CIS Request", LE_ACCEPT_CIS_REQUEST),
+    (0x2067, "LE Reject CIS Request", LE_REJECT_CIS_REQUEST),
+    (0x2068, "LE Create BIG", LE_CREATE_BIG),
+    (0x2069, "LE Create BIG Test", LE_CREATE_BIG_TEST),
+    (0x206A, "LE Terminate BIG", LE_TERMINATE_BIG),
+    (0x206B, "LE BIG Create Sync", LE_BIG_CREATE_SYNC),
+    (0x206C, "LE BIG Terminate Sync", LE_BIG_TERMINATE_SYNC),
+    (0x206D, "LE Request Peer SCA", LE_REQUEST_PEER_SCA),
+    (0x206E, "LE Setup ISO Data Path", LE_SETUP_ISO_DATA_PATH),
+    (0x206F, "LE Remove ISO Data Path", LE_REMOVE_ISO_DATA_PATH),
+    (0x2070, "LE ISO Transmit Test", LE_ISO_TRANSMIT_TEST),
+    (0x2071, "LE ISO Receive Test", LE_ISO_RECEIVE_TEST),
+    (0x2072, "LE ISO Read Test Counters", LE_ISO_READ_TEST_COUNTERS),
+    (0x2073, "LE ISO Test End", LE_ISO_TEST_END),
+    (0x2074, "LE Set Host Feature", LE_SET_HOST_FEATURE),
+    (0x2075, "LE Read ISO Link Quality", LE_READ_ISO_LINK_QUALITY),
+    (0x2076, "LE Enhanced Read Transmit Power Level", LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL),
+    (0x2077, "LE Read Remote Transmit Power Level", LE_READ_REMOTE_TRANSMIT_POWER_LEVEL),
+    (0x2078, "LE Set Path Loss Reporting Parameters", LE_SET_PATH_LOSS_REPORTING_PARAMETERS),
+    (0x2079, "LE Set Path Loss Reporting Enable", LE_SET_PATH_LOSS_REPORTING_ENABLE),
+    (0x207A, "LE Set Transmit Power Reporting Enable", LE_SET_TRANSMIT_POWER_REPORTING_ENABLE),
+    (0x207B, "LE Transmitter Test [v4]", LE_TRANSMITTER_TEST_V4),
+    (0x207C, "LE Set Data Related Address Changes", LE_SET_DATA_RELATED_ADDRESS_CHANGES),
+    (0x207D, "LE Set Default Subrate", LE_SET_DEFAULT_SUBRATE),
+    (0x207E, "LE Subrate Request", LE_SUBRATE_REQUEST),
+    (0xFC1E, "MSFT Opcode Intel", MSFT_OPCODE_INTEL),
+    (0xFD30, "MSFT Opcode Mediatek", MSFT_OPCODE_MEDIATEK),
+    (0xFD53, "LE Get Vendor Capabilities", LE_GET_VENDOR_CAPABILITIES),
+    (0xFD56, "LE Batch Scan", LE_BATCH_SCAN),
+    (0xFD57, "LE APCF", LE_APCF),
+    (0xFD59, "LE Get Controller Activity Energy Info", LE_GET_CONTROLLER_ACTIVITY_ENERGY_INFO),
+    (0xFD5A, "LE Ex Set Scan Parameters", LE_EX_SET_SCAN_PARAMETERS),
+    (0xFD5B, "Get Controller Debug Info", GET_CONTROLLER_DEBUG_INFO),
+    (0xFD70, "MSFT Opcode Qualcomm", MSFT_OPCODE_QUALCOMM),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constants() {
+        assert_eq!(Opcode::new(0x08, 0x000C), LE_SET_SCAN_ENABLE);
+        assert_eq!(LE_SET_SCAN_ENABLE.raw(), 0x200C);
+        assert_eq!(LE_RECEIVER_TEST_V2.name(), Some("LE Receiver Test [v2]"));
+        assert!(NAMES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        match Opcode::from(0x0C03) {
+            RESET => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
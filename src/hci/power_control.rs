@@ -0,0 +1,279 @@
+//! LE Power Control: the commands reading the transmit power levels of a connection and
+//! enabling the reports of their changes and of the path loss zones, and those reports, see
+//! Vol 4, Part E, 7.8.117 to 7.8.121, 7.7.65.32 and 7.7.65.33.
+//!
+//! The LE Power Control Request procedure itself runs between the link layers, the Host only
+//! sees its outcome in the Transmit Power Reporting events.
+
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{opcode, read_handle, Opcode};
+
+/// A transmit power level which isn't available
+pub const TX_POWER_NOT_AVAILABLE: i8 = 0x7F;
+/// The remote device doesn't manage the power levels of the PHY
+pub const TX_POWER_NOT_MANAGED: i8 = 0x7E;
+
+/// The PHY of the power levels: `LE 1M`, `LE 2M`, `LE Coded S=8` or `LE Coded S=2`
+pub fn phy_name(phy: u8) -> Option<&'static str> {
+    let name = match phy {
+        0x01 => "LE 1M",
+        0x02 => "LE 2M",
+        0x03 => "LE Coded S=8",
+        0x04 => "LE Coded S=2",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The parameters of the Power Control commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum PowerControlCommand {
+    /// the current and maximum power levels of the local Controller on a PHY
+    EnhancedReadTransmitPowerLevel {
+        handle: u16,
+        phy: u8,
+    },
+    /// the power level of the remote device on a PHY, reported by a Transmit Power Reporting
+    /// event
+    ReadRemoteTransmitPowerLevel {
+        handle: u16,
+        phy: u8,
+    },
+    SetPathLossReportingParameters {
+        handle: u16,
+        /// in dB, 0xFF to disable the high zone
+        high_threshold: u8,
+        high_hysteresis: u8,
+        low_threshold: u8,
+        low_hysteresis: u8,
+        /// connection events spent past a threshold before the zone is entered
+        min_time_spent: u16,
+    },
+    SetPathLossReportingEnable {
+        handle: u16,
+        enable: bool,
+    },
+    SetTransmitPowerReportingEnable {
+        handle: u16,
+        local_enable: bool,
+        remote_enable: bool,
+    },
+}
+
+impl PowerControlCommand {
+    pub const OPCODES: [Opcode; 5] = [
+        opcode::LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL,
+        opcode::LE_READ_REMOTE_TRANSMIT_POWER_LEVEL,
+        opcode::LE_SET_PATH_LOSS_REPORTING_PARAMETERS,
+        opcode::LE_SET_PATH_LOSS_REPORTING_ENABLE,
+        opcode::LE_SET_TRANSMIT_POWER_REPORTING_ENABLE,
+    ];
+
+    /// The command of `opcode` with its parameters, an error for other commands
+    pub fn parse<R: Read>(opcode: Opcode, reader: &mut R) -> io::Result<Self> {
+        let command = match opcode {
+            opcode::LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL => Self::EnhancedReadTransmitPowerLevel {
+                handle: read_handle(reader)?,
+                phy: reader.read_u8()?,
+            },
+            opcode::LE_READ_REMOTE_TRANSMIT_POWER_LEVEL => Self::ReadRemoteTransmitPowerLevel {
+                handle: read_handle(reader)?,
+                phy: reader.read_u8()?,
+            },
+            opcode::LE_SET_PATH_LOSS_REPORTING_PARAMETERS => Self::SetPathLossReportingParameters {
+                handle: read_handle(reader)?,
+                high_threshold: reader.read_u8()?,
+                high_hysteresis: reader.read_u8()?,
+                low_threshold: reader.read_u8()?,
+                low_hysteresis: reader.read_u8()?,
+                min_time_spent: reader.read_u16::<LittleEndian>()?,
+            },
+            opcode::LE_SET_PATH_LOSS_REPORTING_ENABLE => Self::SetPathLossReportingEnable {
+                handle: read_handle(reader)?,
+                enable: reader.read_u8()? != 0,
+            },
+            opcode::LE_SET_TRANSMIT_POWER_REPORTING_ENABLE => {
+                Self::SetTransmitPowerReportingEnable {
+                    handle: read_handle(reader)?,
+                    local_enable: reader.read_u8()? != 0,
+                    remote_enable: reader.read_u8()? != 0,
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a power control command",
+                ))
+            }
+        };
+        Ok(command)
+    }
+}
+
+/// LE Enhanced Read Transmit Power Level return parameters, in dBm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct EnhancedTransmitPowerLevel {
+    pub status: u8,
+    pub handle: u16,
+    pub phy: u8,
+    pub current_tx_power_level: i8,
+    pub max_tx_power_level: i8,
+}
+
+impl EnhancedTransmitPowerLevel {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            phy: reader.read_u8()?,
+            current_tx_power_level: reader.read_i8()?,
+            max_tx_power_level: reader.read_i8()?,
+        })
+    }
+}
+
+/// LE Path Loss Threshold event: the path loss of a connection entered another zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PathLossThreshold {
+    pub handle: u16,
+    /// in dB, 0xFF when unavailable
+    pub current_path_loss: u8,
+    /// 0x00 low, 0x01 middle, 0x02 high
+    pub zone_entered: u8,
+}
+
+impl PathLossThreshold {
+    pub const ZONE_LOW: u8 = 0x00;
+    pub const ZONE_MIDDLE: u8 = 0x01;
+    pub const ZONE_HIGH: u8 = 0x02;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            handle: read_handle(reader)?,
+            current_path_loss: reader.read_u8()?,
+            zone_entered: reader.read_u8()?,
+        })
+    }
+
+    pub fn path_loss(&self) -> Option<u8> {
+        (self.current_path_loss != 0xFF).then_some(self.current_path_loss)
+    }
+
+    /// `low`, `middle` or `high`
+    pub fn zone_name(zone: u8) -> Option<&'static str> {
+        let name = match zone {
+            Self::ZONE_LOW => "low",
+            Self::ZONE_MIDDLE => "middle",
+            Self::ZONE_HIGH => "high",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+/// LE Transmit Power Reporting event: the power level of the local or remote device changed, or
+/// was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct TransmitPowerReporting {
+    pub status: u8,
+    pub handle: u16,
+    /// 0x00 the local level changed, 0x01 the remote level changed, 0x02 LE Read Remote
+    /// Transmit Power Level completed
+    pub reason: u8,
+    pub phy: u8,
+    /// in dBm, see [`TX_POWER_NOT_AVAILABLE`] and [`TX_POWER_NOT_MANAGED`]
+    pub tx_power_level: i8,
+    /// bit 0 at the minimum level, bit 1 at the maximum level
+    pub tx_power_level_flag: u8,
+    /// change from the previous level in dB, [`TX_POWER_NOT_AVAILABLE`] when unknown
+    pub delta: i8,
+}
+
+impl TransmitPowerReporting {
+    pub const REASON_LOCAL_CHANGE: u8 = 0x00;
+    pub const REASON_REMOTE_CHANGE: u8 = 0x01;
+    pub const REASON_READ_REMOTE: u8 = 0x02;
+
+    pub const FLAG_MIN: u8 = 0b01;
+    pub const FLAG_MAX: u8 = 0b10;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            reason: reader.read_u8()?,
+            phy: reader.read_u8()?,
+            tx_power_level: reader.read_i8()?,
+            tx_power_level_flag: reader.read_u8()?,
+            delta: reader.read_i8()?,
+        })
+    }
+
+    /// the level of the remote device, not the local one
+    pub fn is_remote(&self) -> bool {
+        self.reason != Self::REASON_LOCAL_CHANGE
+    }
+
+    pub fn tx_power(&self) -> Option<i8> {
+        (self.tx_power_level != TX_POWER_NOT_AVAILABLE
+            && self.tx_power_level != TX_POWER_NOT_MANAGED)
+            .then_some(self.tx_power_level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hci::LeMetaEvent;
+
+    #[test]
+    fn power_control() {
+        // handle 0x0040: high above 70 dB, low below 50 dB, 5 dB hysteresis, 10 events
+        let params = [0x40, 0x00, 70, 5, 50, 5, 0x0a, 0x00];
+        assert_eq!(
+            PowerControlCommand::parse(
+                opcode::LE_SET_PATH_LOSS_REPORTING_PARAMETERS,
+                &mut &params[..]
+            )
+            .unwrap(),
+            PowerControlCommand::SetPathLossReportingParameters {
+                handle: 0x0040,
+                high_threshold: 70,
+                high_hysteresis: 5,
+                low_threshold: 50,
+                low_hysteresis: 5,
+                min_time_spent: 10,
+            }
+        );
+        assert!(PowerControlCommand::parse(opcode::RESET, &mut &params[..]).is_err());
+
+        // the remote level went down 4 dB to -8 dBm on LE 2M
+        let event = [0x21, 0x00, 0x40, 0x00, 0x01, 0x02, 0xf8, 0x00, 0xfc];
+        let LeMetaEvent::TransmitPowerReporting(report) =
+            LeMetaEvent::try_from(&event[..]).unwrap()
+        else {
+            panic!("not a transmit power report");
+        };
+        assert!(report.is_remote());
+        assert_eq!(report.tx_power(), Some(-8));
+        assert_eq!(report.delta, -4);
+
+        let event = [0x20, 0x40, 0x00, 0xff, 0x02];
+        let LeMetaEvent::PathLossThreshold(threshold) = LeMetaEvent::try_from(&event[..]).unwrap()
+        else {
+            panic!("not a path loss threshold");
+        };
+        assert_eq!(threshold.path_loss(), None);
+        assert_eq!(
+            PathLossThreshold::zone_name(threshold.zone_entered),
+            Some("high")
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Synchronous (SCO and eSCO) connections: the parameters of the commands setting them up or
+//! accepting them, and the events completing or changing them, see Vol 4, Part E, 7.1.26,
+//! 7.1.27, 7.1.45, 7.1.46, 7.7.35 and 7.7.36.
+//!
+//! The legacy commands give the air coding in the voice setting, the enhanced ones give coding
+//! formats, e.g. mSBC or LC3, which the Controller carries over a transparent air mode.
+
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{opcode, read_handle, BdAddr, Opcode};
+
+pub const CODING_FORMAT_MU_LAW: u8 = 0x00;
+pub const CODING_FORMAT_A_LAW: u8 = 0x01;
+pub const CODING_FORMAT_CVSD: u8 = 0x02;
+pub const CODING_FORMAT_TRANSPARENT: u8 = 0x03;
+pub const CODING_FORMAT_LINEAR_PCM: u8 = 0x04;
+pub const CODING_FORMAT_MSBC: u8 = 0x05;
+pub const CODING_FORMAT_LC3: u8 = 0x06;
+pub const CODING_FORMAT_VENDOR: u8 = 0xFF;
+
+/// The name of a coding format of the Assigned Numbers, which also names the air modes 0x00 to
+/// 0x03 of the Synchronous Connection Complete event
+pub fn coding_format_name(coding_format: u8) -> Option<&'static str> {
+    let name = match coding_format {
+        CODING_FORMAT_MU_LAW => "u-law",
+        CODING_FORMAT_A_LAW => "A-law",
+        CODING_FORMAT_CVSD => "CVSD",
+        CODING_FORMAT_TRANSPARENT => "transparent",
+        CODING_FORMAT_LINEAR_PCM => "linear PCM",
+        CODING_FORMAT_MSBC => "mSBC",
+        CODING_FORMAT_LC3 => "LC3",
+        0x07 => "G.729A",
+        CODING_FORMAT_VENDOR => "vendor specific",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A codec of the enhanced commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct CodingFormat {
+    pub id: u8,
+    /// only meaningful for [`CODING_FORMAT_VENDOR`]
+    pub company_id: u16,
+    pub vendor_codec_id: u16,
+}
+
+impl CodingFormat {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            id: reader.read_u8()?,
+            company_id: reader.read_u16::<LittleEndian>()?,
+            vendor_codec_id: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// The parameters of Setup, Accept, Enhanced Setup and Enhanced Accept Synchronous Connection
+/// which the link depends on, those of the enhanced commands describing the data path to the
+/// Controller are skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SynchronousConnectionSetup {
+    pub opcode: Opcode,
+    /// the ACL connection of a setup, none when accepting
+    pub handle: Option<u16>,
+    /// the device whose request is accepted, none for a setup
+    pub bd_addr: Option<BdAddr>,
+    /// in octets per second
+    pub transmit_bandwidth: u32,
+    pub receive_bandwidth: u32,
+    /// in ms, 0xFFFF for don't care
+    pub max_latency: u16,
+    /// the voice setting of the legacy commands, bits 0 and 1 the air coding
+    pub voice_setting: Option<u16>,
+    /// the codec over the air of the enhanced commands
+    pub transmit_coding_format: Option<CodingFormat>,
+    /// 0x00 no retransmissions, 0x01 optimized for power, 0x02 for link quality, 0xFF don't care
+    pub retransmission_effort: u8,
+    pub packet_type: u16,
+}
+
+impl SynchronousConnectionSetup {
+    pub const OPCODES: [Opcode; 4] = [
+        opcode::SETUP_SYNCHRONOUS_CONNECTION,
+        opcode::ACCEPT_SYNCHRONOUS_CONNECTION,
+        opcode::ENHANCED_SETUP_SYNCHRONOUS_CONNECTION,
+        opcode::ENHANCED_ACCEPT_SYNCHRONOUS_CONNECTION,
+    ];
+
+    /// The parameters of the command of `opcode`, an error for other commands
+    pub fn parse<R: Read>(opcode: Opcode, reader: &mut R) -> io::Result<Self> {
+        let (handle, bd_addr) = match opcode {
+            opcode::SETUP_SYNCHRONOUS_CONNECTION
+            | opcode::ENHANCED_SETUP_SYNCHRONOUS_CONNECTION => (Some(read_handle(reader)?), None),
+            opcode::ACCEPT_SYNCHRONOUS_CONNECTION
+            | opcode::ENHANCED_ACCEPT_SYNCHRONOUS_CONNECTION => {
+                (None, Some(BdAddr::parse(reader)?))
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a synchronous connection setup",
+                ))
+            }
+        };
+        let transmit_bandwidth = reader.read_u32::<LittleEndian>()?;
+        let receive_bandwidth = reader.read_u32::<LittleEndian>()?;
+        if opcode == opcode::SETUP_SYNCHRONOUS_CONNECTION
+            || opcode == opcode::ACCEPT_SYNCHRONOUS_CONNECTION
+        {
+            return Ok(Self {
+                opcode,
+                handle,
+                bd_addr,
+                transmit_bandwidth,
+                receive_bandwidth,
+                max_latency: reader.read_u16::<LittleEndian>()?,
+                voice_setting: Some(reader.read_u16::<LittleEndian>()?),
+                transmit_coding_format: None,
+                retransmission_effort: reader.read_u8()?,
+                packet_type: reader.read_u16::<LittleEndian>()?,
+            });
+        }
+        let transmit_coding_format = CodingFormat::parse(reader)?;
+        // receive coding format and codec frame sizes, then the data path: input and output
+        // bandwidths, coding formats, coded data sizes, PCM data formats, PCM sample MSB
+        // positions, data paths and transport unit sizes
+        let mut skipped = [0; 5 + 2 + 2 + 4 + 4 + 5 + 5 + 2 + 2 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1];
+        reader.read_exact(&mut skipped)?;
+        Ok(Self {
+            opcode,
+            handle,
+            bd_addr,
+            transmit_bandwidth,
+            receive_bandwidth,
+            max_latency: reader.read_u16::<LittleEndian>()?,
+            voice_setting: None,
+            transmit_coding_format: Some(transmit_coding_format),
+            packet_type: reader.read_u16::<LittleEndian>()?,
+            retransmission_effort: reader.read_u8()?,
+        })
+    }
+
+    /// The coding format over the air, of the air coding of the voice setting for the legacy
+    /// commands
+    pub fn air_coding_format(&self) -> u8 {
+        match (self.transmit_coding_format, self.voice_setting) {
+            (Some(format), _) => format.id,
+            (None, Some(voice_setting)) => match voice_setting & 0b11 {
+                0b00 => CODING_FORMAT_CVSD,
+                0b01 => CODING_FORMAT_MU_LAW,
+                0b10 => CODING_FORMAT_A_LAW,
+                _ => CODING_FORMAT_TRANSPARENT,
+            },
+            (None, None) => CODING_FORMAT_CVSD,
+        }
+    }
+}
+
+/// Synchronous Connection Complete event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SynchronousConnectionComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub bd_addr: BdAddr,
+    /// 0x00 = SCO, 0x02 = eSCO
+    pub link_type: u8,
+    /// in slots
+    pub transmission_interval: u8,
+    pub retransmission_window: u8,
+    /// in octets
+    pub rx_packet_length: u16,
+    pub tx_packet_length: u16,
+    /// 0x00 u-law, 0x01 A-law, 0x02 CVSD, 0x03 transparent, see [`coding_format_name`]
+    pub air_mode: u8,
+}
+
+impl SynchronousConnectionComplete {
+    pub const LINK_TYPE_SCO: u8 = 0x00;
+    pub const LINK_TYPE_ESCO: u8 = 0x02;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            bd_addr: BdAddr::parse(reader)?,
+            link_type: reader.read_u8()?,
+            transmission_interval: reader.read_u8()?,
+            retransmission_window: reader.read_u8()?,
+            rx_packet_length: reader.read_u16::<LittleEndian>()?,
+            tx_packet_length: reader.read_u16::<LittleEndian>()?,
+            air_mode: reader.read_u8()?,
+        })
+    }
+}
+
+/// Synchronous Connection Changed event: an eSCO link was renegotiated by the link managers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SynchronousConnectionChanged {
+    pub status: u8,
+    pub handle: u16,
+    pub transmission_interval: u8,
+    pub retransmission_window: u8,
+    pub rx_packet_length: u16,
+    pub tx_packet_length: u16,
+}
+
+impl SynchronousConnectionChanged {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            status: reader.read_u8()?,
+            handle: read_handle(reader)?,
+            transmission_interval: reader.read_u8()?,
+            retransmission_window: reader.read_u8()?,
+            rx_packet_length: reader.read_u16::<LittleEndian>()?,
+            tx_packet_length: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn synchronous() {
+        // 8000 octets/s, 13 ms, CVSD, link quality, EV3
+        let params = [
+            0x01, 0x00, 0x40, 0x1f, 0x00, 0x00, 0x40, 0x1f, 0x00, 0x00, 0x0d, 0x00, 0x60, 0x00,
+            0x02, 0x08, 0x00,
+        ];
+        let setup = SynchronousConnectionSetup::parse(
+            opcode::SETUP_SYNCHRONOUS_CONNECTION,
+            &mut &params[..],
+        )
+        .unwrap();
+        assert_eq!(setup.handle, Some(0x0001));
+        assert_eq!(setup.transmit_bandwidth, 8000);
+        assert_eq!(setup.max_latency, 13);
+        assert_eq!(setup.retransmission_effort, 0x02);
+        assert_eq!(setup.air_coding_format(), CODING_FORMAT_CVSD);
+
+        // mSBC over the air, with the data path parameters zeroed
+        let mut params = vec![0x01, 0x00, 0x40, 0x1f, 0x00, 0x00, 0x40, 0x1f, 0x00, 0x00];
+        params.extend_from_slice(&[CODING_FORMAT_MSBC, 0, 0, 0, 0]);
+        params.extend_from_slice(&[0; 39]);
+        params.extend_from_slice(&[0x0d, 0x00, 0x08, 0x03, 0x02]);
+        let setup = SynchronousConnectionSetup::parse(
+            opcode::ENHANCED_SETUP_SYNCHRONOUS_CONNECTION,
+            &mut &params[..],
+        )
+        .unwrap();
+        assert_eq!(setup.air_coding_format(), CODING_FORMAT_MSBC);
+        assert_eq!(setup.packet_type, 0x0308);
+        assert_eq!(setup.retransmission_effort, 0x02);
+        assert!(SynchronousConnectionSetup::parse(opcode::RESET, &mut &params[..]).is_err());
+
+        let event = [
+            0x00, 0x02, 0x01, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x02, 0x0c, 0x04, 0x3c, 0x00,
+            0x3c, 0x00, 0x03,
+        ];
+        let complete = SynchronousConnectionComplete::parse(&mut &event[..]).unwrap();
+        assert_eq!(complete.handle, 0x0102);
+        assert_eq!(
+            complete.link_type,
+            SynchronousConnectionComplete::LINK_TYPE_ESCO
+        );
+        assert_eq!(coding_format_name(complete.air_mode), Some("transparent"));
+    }
+}
@@ -0,0 +1,174 @@
+//! Live capture from the Linux Bluetooth monitor socket, the HCI channel btmon reads, which
+//! sees the traffic of every controller in both directions. Opening it requires
+//! `CAP_NET_RAW`.
+//!
+//! ```text
+//! -----------------------------------------------------------------
+//! | opcode 16 bit | controller index 16 bit | length 16 bit | data |
+//! -----------------------------------------------------------------
+//! ```
+//!
+//! The header is little endian, the data of HCI opcodes is the H4 packet without its type.
+
+use std::{
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{formats::h4_packet, Header, Packet, UartPacketType};
+
+const AF_BLUETOOTH: libc::c_int = 31;
+const BTPROTO_HCI: libc::c_int = 1;
+const HCI_DEV_NONE: u16 = 0xFFFF;
+const HCI_CHANNEL_MONITOR: u16 = 2;
+const MONITOR_HEADER_LEN: usize = 6;
+
+#[repr(C)]
+struct SockaddrHci {
+    hci_family: libc::sa_family_t,
+    hci_dev: u16,
+    hci_channel: u16,
+}
+
+/// The H4 packet type and direction of a monitor opcode, none for the other events, e.g.
+/// controller index or system notes
+fn packet_type(opcode: u16) -> Option<(UartPacketType, bool)> {
+    match opcode {
+        2 => Some((UartPacketType::Cmd, false)),
+        3 => Some((UartPacketType::Evt, true)),
+        4 => Some((UartPacketType::Acl, false)),
+        5 => Some((UartPacketType::Acl, true)),
+        6 => Some((UartPacketType::Sco, false)),
+        7 => Some((UartPacketType::Sco, true)),
+        18 => Some((UartPacketType::Iso, false)),
+        19 => Some((UartPacketType::Iso, true)),
+        _ => None,
+    }
+}
+
+/// Controller index and H4 packet of a monitor frame
+fn decode(frame: &[u8], unix_timestamp: i64) -> Option<(u16, Packet)> {
+    let header = frame.get(..MONITOR_HEADER_LEN)?;
+    let opcode = u16::from_le_bytes([header[0], header[1]]);
+    let index = u16::from_le_bytes([header[2], header[3]]);
+    let (packet_type, received) = packet_type(opcode)?;
+    let mut data = vec![packet_type as u8];
+    data.extend_from_slice(&frame[MONITOR_HEADER_LEN..]);
+    let original_length = data.len() as u32;
+    Some((
+        index,
+        h4_packet(unix_timestamp, received, data, original_length),
+    ))
+}
+
+/// The monitor socket, yielding the HCI packets of one or every controller
+#[derive(Debug)]
+pub struct MonitorSocket {
+    fd: OwnedFd,
+    /// hciN, every controller when none
+    index: Option<u16>,
+    buffer: Vec<u8>,
+}
+
+impl MonitorSocket {
+    pub fn open(index: Option<u16>) -> io::Result<Self> {
+        // SAFETY: plain socket creation, the descriptor is owned right away
+        let fd = unsafe {
+            libc::socket(
+                AF_BLUETOOTH,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                BTPROTO_HCI,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a valid descriptor nothing else owns
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let address = SockaddrHci {
+            hci_family: AF_BLUETOOTH as libc::sa_family_t,
+            hci_dev: HCI_DEV_NONE,
+            hci_channel: HCI_CHANNEL_MONITOR,
+        };
+        // SAFETY: address outlives the call and its size is given
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const SockaddrHci as *const libc::sockaddr,
+                mem::size_of::<SockaddrHci>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd,
+            index,
+            buffer: vec![0; MONITOR_HEADER_LEN + u16::MAX as usize],
+        })
+    }
+
+    /// Header of a capture of the packets
+    pub fn header(&self) -> Header {
+        Header::default()
+    }
+
+    /// The next HCI packet, waiting for it
+    pub fn next_packet(&mut self) -> io::Result<Packet> {
+        loop {
+            // SAFETY: the buffer is valid for its length
+            let len = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    self.buffer.as_mut_ptr() as *mut libc::c_void,
+                    self.buffer.len(),
+                    0,
+                )
+            };
+            if len < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_micros() as i64);
+            match decode(&self.buffer[..len as usize], timestamp) {
+                Some((index, packet)) if self.index.is_none_or(|i| i == index) => {
+                    return Ok(packet)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Never ends
+impl Iterator for MonitorSocket {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_packet())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn monitor_frames() {
+        // ACL received on hci1
+        let frame = [0x05, 0x00, 0x01, 0x00, 0x04, 0x00, 0x40, 0x20, 0x00, 0x00];
+        let (index, packet) = decode(&frame, 0).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(packet.data.0, [0x02, 0x40, 0x20, 0x00, 0x00]);
+        assert_eq!(packet.description.flags.0, 0b01);
+
+        // new controller index
+        assert!(decode(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00], 0).is_none());
+    }
+}
@@ -0,0 +1,111 @@
+//! Hex dump of packet data or decoded payloads, 16 octets per line with their offset and ASCII.
+//!
+//! ```text
+//! 0000  04 0e 04 01 03 0c 00                              .......
+//!          ^^ ^^
+//! ```
+//!
+//! A highlighted range is marked under the octets, e.g. the field a decoder failed on.
+//! [`hex_string`] is the compact form of the JSON output and the generated scripts.
+
+use std::{fmt::Display, ops::Range};
+
+const WIDTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Hexdump<'a> {
+    data: &'a [u8],
+    /// offset of the first octet, for slices of a larger buffer
+    offset: usize,
+    /// range of the data, before the offset is applied
+    highlight: Option<Range<usize>>,
+}
+
+impl<'a> Hexdump<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            offset: 0,
+            highlight: None,
+        }
+    }
+
+    /// Number the lines from `offset`, e.g. the position of a payload in its packet
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Mark the octets of `range`, indexes into the data
+    pub fn highlight(mut self, range: Range<usize>) -> Self {
+        self.highlight = Some(range);
+        self
+    }
+}
+
+/// Lines are separated by newlines, without one after the last line
+impl Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (line, chunk) in self.data.chunks(WIDTH).enumerate() {
+            if line > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:04x} ", self.offset + line * WIDTH)?;
+            for b in chunk {
+                write!(f, " {:02x}", b)?;
+            }
+            write!(f, "{:1$}  ", "", (WIDTH - chunk.len()) * 3)?;
+            for b in chunk {
+                let c = if b.is_ascii_graphic() {
+                    *b as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+
+            let Some(highlight) = &self.highlight else {
+                continue;
+            };
+            let start = line * WIDTH;
+            let marked: Vec<bool> = (start..start + chunk.len())
+                .map(|i| highlight.contains(&i))
+                .collect();
+            if let Some(last) = marked.iter().rposition(|m| *m) {
+                write!(f, "\n     ")?;
+                for m in &marked[..=last] {
+                    f.write_str(if *m { " ^^" } else { "   " })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The octets in lowercase hex without separators, e.g. `040e04`
+pub fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lines_and_highlight() {
+        let data: Vec<u8> = (0x20..0x33).collect();
+        assert_eq!(
+            Hexdump::new(&data).offset(0x10).to_string(),
+            "0010  20 21 22 23 24 25 26 27 28 29 2a 2b 2c 2d 2e 2f  .!\"#$%&'()*+,-./\n\
+             0020  30 31 32                                         012"
+        );
+        assert_eq!(
+            Hexdump::new(&[0x04, 0x0e, 0x04])
+                .highlight(1..2)
+                .to_string(),
+            "0000  04 0e 04                                         ...\n\
+             \x20        ^^"
+        );
+        assert_eq!(hex_string(&[0x04, 0x0e, 0xff]), "040eff");
+    }
+}
@@ -0,0 +1,429 @@
+//! HID input reports, of classic Bluetooth HID (HIDP) and of HID over GATT (HOGP): the report
+//! descriptor ("report map") telling the layout of the reports, and the keys and pointer
+//! movements they carry.
+//!
+//! ```
+//! use btsnoop::hid::{Input, ReportMap};
+//!
+//! // a boot keyboard report: left shift and 'h'
+//! let report = [0x02, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00];
+//! let input = ReportMap::boot_keyboard().decode(0, &report).unwrap();
+//! assert!(input.keys.contains(&0xe1));
+//! assert_eq!(Input::char(0x0b, input.shift()), Some('H'));
+//! ```
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+};
+
+// data format from: Device Class Definition for HID 1.11 and HID Usage Tables 1.5
+
+pub const PAGE_GENERIC_DESKTOP: u16 = 0x01;
+pub const PAGE_KEYBOARD: u16 = 0x07;
+pub const PAGE_BUTTON: u16 = 0x09;
+
+/// Generic Desktop usages of a pointer
+pub const USAGE_X: u16 = 0x30;
+pub const USAGE_Y: u16 = 0x31;
+pub const USAGE_WHEEL: u16 = 0x38;
+
+/// HIDP header of an input report on the interrupt channel: DATA transaction, input report
+pub const HIDP_DATA_INPUT: u8 = 0xA1;
+
+// HOGP characteristics
+pub const BOOT_KEYBOARD_INPUT_REPORT: u16 = 0x2A22;
+pub const BOOT_MOUSE_INPUT_REPORT: u16 = 0x2A33;
+pub const REPORT_MAP: u16 = 0x2A4B;
+pub const REPORT: u16 = 0x2A4D;
+/// descriptor of a Report characteristic: its report ID and type
+pub const REPORT_REFERENCE: u16 = 0x2908;
+
+/// Appendix B.1 of the HID specification
+const BOOT_KEYBOARD: &[u8] = &[
+    0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00, 0x25, 0x01,
+    0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x01, 0x95, 0x05, 0x75, 0x01,
+    0x05, 0x08, 0x19, 0x01, 0x29, 0x05, 0x91, 0x02, 0x95, 0x01, 0x75, 0x03, 0x91, 0x01, 0x95, 0x06,
+    0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65, 0x81, 0x00, 0xc0,
+];
+
+/// Appendix B.2 of the HID specification
+const BOOT_MOUSE: &[u8] = &[
+    0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03,
+    0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05, 0x81, 0x01,
+    0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06,
+    0xc0, 0xc0,
+];
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// An input item of a report descriptor: `count` values of `size` bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// 0 when the reports don't start with an ID
+    pub report_id: u8,
+    /// in bits, after the report ID
+    pub offset: usize,
+    pub size: usize,
+    pub count: usize,
+    /// a value per usage, else an array of the usages active
+    pub variable: bool,
+    pub constant: bool,
+    pub logical_minimum: i32,
+    /// the usages, page in the high 16 bits
+    pub usages: Vec<u32>,
+    /// the usages of the range Usage Minimum to Usage Maximum
+    pub usage_range: Option<(u32, u32)>,
+}
+
+impl Field {
+    /// The usage of value or array index `index`
+    fn usage(&self, index: usize) -> Option<u32> {
+        match self.usage_range {
+            Some((minimum, maximum)) => minimum
+                .checked_add(index as u32)
+                .filter(|usage| *usage <= maximum),
+            None => self
+                .usages
+                .get(index)
+                .or(if self.variable {
+                    self.usages.last()
+                } else {
+                    None
+                })
+                .copied(),
+        }
+    }
+
+    fn value(&self, data: &[u8], index: usize) -> Option<i32> {
+        let start = self.offset.saturating_add(index.saturating_mul(self.size));
+        if self.size == 0 || self.size > 32 || start.saturating_add(self.size) > data.len() * 8 {
+            return None;
+        }
+        let mut value = 0u64;
+        for bit in 0..self.size {
+            let position = start + bit;
+            value |= ((data[position / 8] >> (position % 8)) as u64 & 1) << bit;
+        }
+        Some(if self.logical_minimum < 0 && self.size < 32 {
+            // sign extended
+            ((value << (64 - self.size)) as i64 >> (64 - self.size)) as i32
+        } else {
+            value as i32
+        })
+    }
+}
+
+/// The input fields of a report descriptor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportMap {
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_minimum: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+impl ReportMap {
+    /// Parse the items of a report descriptor, keeping the input items
+    pub fn parse(mut descriptor: &[u8]) -> io::Result<Self> {
+        let mut fields = vec![];
+        let mut globals = Globals::default();
+        let mut stack = vec![];
+        let mut usages: Vec<u32> = vec![];
+        let mut usage_minimum = None;
+        let mut usage_maximum = None;
+        // (report ID) -> bits of its input fields so far
+        let mut offsets = HashMap::<u8, usize>::new();
+        while let Some((&prefix, rest)) = descriptor.split_first() {
+            if prefix == 0xFE {
+                // long item: size, tag, data
+                let size = *rest.first().ok_or_else(|| invalid("truncated long item"))? as usize;
+                descriptor = rest
+                    .get(2 + size..)
+                    .ok_or_else(|| invalid("truncated long item"))?;
+                continue;
+            }
+            let size = match prefix & 0x03 {
+                3 => 4,
+                size => size as usize,
+            };
+            let data = rest
+                .get(..size)
+                .ok_or_else(|| invalid("truncated report descriptor item"))?;
+            descriptor = &rest[size..];
+            let unsigned = data
+                .iter()
+                .rev()
+                .fold(0u32, |value, byte| value << 8 | *byte as u32);
+            let signed = match size {
+                1 => data[0] as i8 as i32,
+                2 => i16::from_le_bytes([data[0], data[1]]) as i32,
+                _ => unsigned as i32,
+            };
+            let extended = |usage: u32| {
+                if size == 4 {
+                    usage
+                } else {
+                    (globals.usage_page as u32) << 16 | usage
+                }
+            };
+            match (prefix >> 2 & 0x03, prefix >> 4) {
+                // Input
+                (0, 0x8) => {
+                    let report_id = globals.report_id;
+                    let offset = offsets.entry(report_id).or_default();
+                    fields.push(Field {
+                        report_id,
+                        offset: *offset,
+                        size: globals.report_size,
+                        count: globals.report_count,
+                        variable: unsigned & 0x02 != 0,
+                        constant: unsigned & 0x01 != 0,
+                        logical_minimum: globals.logical_minimum,
+                        usages: std::mem::take(&mut usages),
+                        usage_range: usage_minimum.zip(usage_maximum),
+                    });
+                    *offset = offset
+                        .saturating_add(globals.report_size.saturating_mul(globals.report_count));
+                    usage_minimum = None;
+                    usage_maximum = None;
+                }
+                // other main items end the local items too
+                (0, _) => {
+                    usages.clear();
+                    usage_minimum = None;
+                    usage_maximum = None;
+                }
+                (1, 0x0) => globals.usage_page = unsigned as u16,
+                (1, 0x1) => globals.logical_minimum = signed,
+                (1, 0x7) => globals.report_size = unsigned as usize,
+                (1, 0x8) => globals.report_id = unsigned as u8,
+                (1, 0x9) => globals.report_count = unsigned as usize,
+                (1, 0xA) => stack.push(globals),
+                (1, 0xB) => globals = stack.pop().ok_or_else(|| invalid("pop without push"))?,
+                (2, 0x0) => usages.push(extended(unsigned)),
+                (2, 0x1) => usage_minimum = Some(extended(unsigned)),
+                (2, 0x2) => usage_maximum = Some(extended(unsigned)),
+                _ => {}
+            }
+        }
+        Ok(Self { fields })
+    }
+
+    /// The report of a keyboard in boot protocol, without report ID
+    pub fn boot_keyboard() -> Self {
+        Self::parse(BOOT_KEYBOARD).unwrap_or_default()
+    }
+
+    /// The report of a mouse in boot protocol, without report ID
+    pub fn boot_mouse() -> Self {
+        Self::parse(BOOT_MOUSE).unwrap_or_default()
+    }
+
+    /// Whether the reports start with their ID
+    pub fn has_report_ids(&self) -> bool {
+        self.fields.iter().any(|field| field.report_id != 0)
+    }
+
+    /// The report IDs of the input reports
+    pub fn report_ids(&self) -> BTreeSet<u8> {
+        self.fields.iter().map(|field| field.report_id).collect()
+    }
+
+    /// The state of the keys and pointer in input report `report_id`, its data after the ID.
+    /// None when the report map has no such report.
+    pub fn decode(&self, report_id: u8, data: &[u8]) -> Option<Input> {
+        let mut input = Input::default();
+        let mut found = false;
+        for field in self.fields.iter().filter(|f| f.report_id == report_id) {
+            found = true;
+            if field.constant {
+                continue;
+            }
+            for index in 0..field.count {
+                let Some(value) = field.value(data, index) else {
+                    break;
+                };
+                let usage = if field.variable {
+                    field.usage(index)
+                } else {
+                    // an array of the usages active
+                    match value.checked_sub(field.logical_minimum) {
+                        Some(index) if index >= 0 => field.usage(index as usize),
+                        _ => None,
+                    }
+                };
+                let Some(usage) = usage else {
+                    continue;
+                };
+                let (page, id) = ((usage >> 16) as u16, usage as u16);
+                match page {
+                    PAGE_KEYBOARD => {
+                        // 0 no event, 1 to 3 errors
+                        let pressed = if field.variable { value != 0 } else { true };
+                        if !field.variable && id == 0x01 {
+                            input.rollover = true;
+                        } else if pressed && (0x04..=0xFF).contains(&id) {
+                            input.keys.insert(id as u8);
+                        }
+                    }
+                    PAGE_BUTTON if value != 0 && (1..=32).contains(&id) => {
+                        input.buttons |= 1 << (id - 1);
+                    }
+                    PAGE_GENERIC_DESKTOP if field.variable => match id {
+                        USAGE_X => input.x = input.x.saturating_add(value),
+                        USAGE_Y => input.y = input.y.saturating_add(value),
+                        USAGE_WHEEL => input.wheel = input.wheel.saturating_add(value),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+        }
+        found.then_some(input)
+    }
+}
+
+/// The keys held and the pointer movement of an input report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Input {
+    /// keyboard usages, the modifiers from 0xE0 left control to 0xE7 right GUI
+    pub keys: BTreeSet<u8>,
+    /// bit 0 is button 1
+    pub buttons: u32,
+    pub x: i32,
+    pub y: i32,
+    pub wheel: i32,
+    /// the keyboard reported too many keys held at once, which keys is unknown
+    pub rollover: bool,
+}
+
+impl Input {
+    pub fn shift(&self) -> bool {
+        self.keys.contains(&0xE1) || self.keys.contains(&0xE5)
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        self.buttons != 0 || self.x != 0 || self.y != 0 || self.wheel != 0
+    }
+
+    /// The character a keyboard usage types on a US layout
+    pub fn char(usage: u8, shift: bool) -> Option<char> {
+        const DIGITS: &[u8] = b"1234567890";
+        const SHIFTED_DIGITS: &[u8] = b"!@#$%^&*()";
+        const PUNCTUATION: &[u8] = b"-=[]\\#;'`,./";
+        const SHIFTED_PUNCTUATION: &[u8] = b"_+{}|~:\"~<>?";
+        const KEYPAD: &[u8] = b"/*-+\n1234567890.";
+        Some(match usage {
+            0x04..=0x1D => {
+                let c = (b'a' + usage - 0x04) as char;
+                if shift {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            }
+            0x1E..=0x27 if shift => SHIFTED_DIGITS[(usage - 0x1E) as usize] as char,
+            0x1E..=0x27 => DIGITS[(usage - 0x1E) as usize] as char,
+            0x28 => '\n',
+            0x2B => '\t',
+            0x2C => ' ',
+            0x2D..=0x38 if shift => SHIFTED_PUNCTUATION[(usage - 0x2D) as usize] as char,
+            0x2D..=0x38 => PUNCTUATION[(usage - 0x2D) as usize] as char,
+            0x54..=0x63 => KEYPAD[(usage - 0x54) as usize] as char,
+            _ => return None,
+        })
+    }
+}
+
+/// The name of a keyboard usage which doesn't type a character, e.g. `Backspace` or `F1`
+pub fn key_name(usage: u8) -> Option<&'static str> {
+    const FUNCTION_KEYS: [&str; 12] = [
+        "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    ];
+    Some(match usage {
+        0x29 => "Escape",
+        0x2A => "Backspace",
+        0x39 => "Caps Lock",
+        0x3A..=0x45 => FUNCTION_KEYS[(usage - 0x3A) as usize],
+        0x46 => "Print Screen",
+        0x47 => "Scroll Lock",
+        0x48 => "Pause",
+        0x49 => "Insert",
+        0x4A => "Home",
+        0x4B => "Page Up",
+        0x4C => "Delete",
+        0x4D => "End",
+        0x4E => "Page Down",
+        0x4F => "Right",
+        0x50 => "Left",
+        0x51 => "Down",
+        0x52 => "Up",
+        0x53 => "Num Lock",
+        0xE0 => "Left Control",
+        0xE1 => "Left Shift",
+        0xE2 => "Left Alt",
+        0xE3 => "Left GUI",
+        0xE4 => "Right Control",
+        0xE5 => "Right Shift",
+        0xE6 => "Right Alt",
+        0xE7 => "Right GUI",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_maps() {
+        let keyboard = ReportMap::boot_keyboard();
+        assert!(!keyboard.has_report_ids());
+        let input = keyboard
+            .decode(0, &[0x22, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00])
+            .unwrap();
+        assert_eq!(
+            input.keys.iter().copied().collect::<Vec<_>>(),
+            [0x04, 0x05, 0xe1, 0xe5]
+        );
+        assert!(input.shift() && !input.is_pointer());
+        assert!(keyboard.decode(1, &[]).is_none());
+
+        let mouse = ReportMap::boot_mouse()
+            .decode(0, &[0x05, 0xfe, 0x03])
+            .unwrap();
+        assert_eq!((mouse.buttons, mouse.x, mouse.y), (0b101, -2, 3));
+
+        // report ID 2, a consumer page usage and a 16 bit X, keyboard modifiers in a range
+        let map = ReportMap::parse(&[
+            0x85, 0x02, 0x05, 0x0c, 0x09, 0xe9, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x01,
+            0x81, 0x02, 0x75, 0x07, 0x81, 0x03, 0x05, 0x01, 0x09, 0x30, 0x16, 0x01, 0x80, 0x75,
+            0x10, 0x81, 0x06,
+        ])
+        .unwrap();
+        assert_eq!(map.report_ids(), BTreeSet::from([2]));
+        assert_eq!(map.decode(2, &[0x01, 0x00, 0x80]).unwrap().x, -32768);
+
+        // two 32 bit X values, both taken as X as the last usage repeats
+        let map = ReportMap::parse(&[
+            0x05, 0x01, 0x09, 0x30, 0x17, 0x01, 0x00, 0x00, 0x80, 0x27, 0xff, 0xff, 0xff, 0x7f,
+            0x75, 0x20, 0x95, 0x02, 0x81, 0x06,
+        ])
+        .unwrap();
+        let report = [0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(map.decode(0, &report).unwrap().x, i32::MAX);
+
+        assert_eq!(Input::char(0x1f, true), Some('@'));
+        assert_eq!(Input::char(0x38, false), Some('/'));
+        assert_eq!(key_name(0x45), Some("F12"));
+    }
+}
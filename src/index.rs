@@ -0,0 +1,604 @@
+//! Index of a capture, kept in a compact sidecar file next to it so a multi-GB capture is only
+//! parsed once: the offset and record description of every packet, the connections and the
+//! devices seen. Reopening the capture reads the sidecar, a summary of the packets and their
+//! connections needs no parsing and any packet is read with a single seek.
+//!
+//! The sidecar of `btsnoop_hci.log` is `btsnoop_hci.log.index`. It is keyed by the length and
+//! 64 bit FNV-1a hash of the capture file, so it is rebuilt when the capture changes, e.g.
+//! after packets were appended to it.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use btsnoop::index::CaptureIndex;
+//!
+//! let path = "btsnoop_hci.log";
+//! let index = CaptureIndex::open(path)?;
+//! for connection in &index.connections {
+//!     println!("0x{:04x} {}", connection.handle, connection.address);
+//! }
+//! // the packets from the first one 10 s after the start of the capture
+//! let mut file = File::open(path)?;
+//! let start = index.packets.first().map_or(0, |entry| entry.description.timestamp);
+//! let packet = index.read_packet(&mut file, index.packet_at(start + 10_000_000))?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    analysis::advertisers::{
+        ad_structures, AD_TYPE_COMPLETE_LOCAL_NAME, AD_TYPE_SHORTENED_LOCAL_NAME,
+    },
+    hci::{BdAddr, ConnectionComplete, DisconnectionComplete, Event, LeMetaEvent},
+    parse_uart_packet, DatalinkType, Header, Packet, PacketDescription, PacketFlags, Reader,
+    UartData,
+};
+
+/// Version of the sidecar format
+pub const VERSION: u32 = 1;
+
+const MAGIC: [u8; 8] = *b"btsnidx\0";
+
+/// 64 bit FNV-1a of the data written
+pub(crate) struct Fnv1a(pub u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for b in buf {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes and counts the bytes read through it
+struct Hashing<R> {
+    reader: R,
+    hasher: Fnv1a,
+    length: u64,
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.hasher.write_all(&buf[..n])?;
+        self.length += n as u64;
+        Ok(n)
+    }
+}
+
+/// Length and 64 bit FNV-1a hash of the contents of a file
+pub fn file_hash(path: impl AsRef<Path>) -> io::Result<(u64, u64)> {
+    let mut hashing = Hashing {
+        reader: File::open(path)?,
+        hasher: Fnv1a::default(),
+        length: 0,
+    };
+    io::copy(&mut hashing, &mut io::sink())?;
+    Ok((hashing.length, hashing.hasher.0))
+}
+
+/// Where a packet record is in the capture file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// of the record, its description then its data
+    pub offset: u64,
+    pub description: PacketDescription,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexedConnection {
+    pub handle: u16,
+    pub le: bool,
+    /// LE address type of the peer, 0x00 public for BR/EDR
+    pub address_type: u8,
+    pub address: BdAddr,
+    /// packet index of the Connection Complete event
+    pub connected: usize,
+    /// packet index of the Disconnection Complete event
+    pub disconnected: Option<usize>,
+    /// of the disconnection
+    pub reason: Option<u8>,
+}
+
+/// A device which advertised, connected or whose name was read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedDevice {
+    pub address_type: u8,
+    pub address: BdAddr,
+    /// advertised local name or remote name
+    pub name: Option<String>,
+    /// packet indexes
+    pub first_seen: usize,
+    pub last_seen: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureIndex {
+    pub header: Header,
+    /// length of the capture file
+    pub length: u64,
+    /// 64 bit FNV-1a hash of the capture file
+    pub hash: u64,
+    pub packets: Vec<IndexEntry>,
+    pub connections: Vec<IndexedConnection>,
+    pub devices: Vec<IndexedDevice>,
+}
+
+#[derive(Default)]
+struct Builder {
+    packets: Vec<IndexEntry>,
+    connections: Vec<IndexedConnection>,
+    devices: Vec<IndexedDevice>,
+    device_indexes: HashMap<(u8, BdAddr), usize>,
+}
+
+impl Builder {
+    fn seen(
+        &mut self,
+        packet_index: usize,
+        address_type: u8,
+        address: BdAddr,
+    ) -> &mut IndexedDevice {
+        let devices = &mut self.devices;
+        let index = *self
+            .device_indexes
+            .entry((address_type, address))
+            .or_insert_with(|| {
+                devices.push(IndexedDevice {
+                    address_type,
+                    address,
+                    name: None,
+                    first_seen: packet_index,
+                    last_seen: packet_index,
+                });
+                devices.len() - 1
+            });
+        let device = &mut self.devices[index];
+        device.last_seen = packet_index;
+        device
+    }
+
+    fn connected(
+        &mut self,
+        packet_index: usize,
+        handle: u16,
+        le: bool,
+        address_type: u8,
+        address: BdAddr,
+    ) {
+        self.connections.push(IndexedConnection {
+            handle,
+            le,
+            address_type,
+            address,
+            connected: packet_index,
+            disconnected: None,
+            reason: None,
+        });
+        self.seen(packet_index, address_type, address);
+    }
+
+    fn event(&mut self, packet_index: usize, event: Event) {
+        let mut params = event.params;
+        match event.code {
+            Event::CONNECTION_COMPLETE => {
+                if let Ok(complete) = ConnectionComplete::parse(&mut params) {
+                    if complete.status == 0 {
+                        self.connected(
+                            packet_index,
+                            complete.handle,
+                            false,
+                            0x00,
+                            complete.bd_addr,
+                        );
+                    }
+                }
+            }
+            Event::DISCONNECTION_COMPLETE => {
+                let Ok(disconnection) = DisconnectionComplete::parse(&mut params) else {
+                    return;
+                };
+                let connection = self.connections.iter_mut().rev().find(|connection| {
+                    connection.handle == disconnection.handle && connection.disconnected.is_none()
+                });
+                if let (0, Some(connection)) = (disconnection.status, connection) {
+                    connection.disconnected = Some(packet_index);
+                    connection.reason = Some(disconnection.reason);
+                }
+            }
+            Event::REMOTE_NAME_REQUEST_COMPLETE => {
+                // status, address and a null terminated name of up to 248 octets
+                let (Some(&0), Some(address)) = (params.first(), params.get(1..7)) else {
+                    return;
+                };
+                let name = &params[7..];
+                let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+                let address = BdAddr(address.try_into().unwrap_or_default());
+                self.seen(packet_index, 0x00, address).name =
+                    Some(String::from_utf8_lossy(name).into_owned());
+            }
+            Event::LE_META => match LeMetaEvent::try_from(params) {
+                Ok(LeMetaEvent::ConnectionComplete(complete)) if complete.status == 0 => {
+                    self.connected(
+                        packet_index,
+                        complete.handle,
+                        true,
+                        complete.peer_address_type,
+                        complete.peer_address,
+                    );
+                }
+                Ok(LeMetaEvent::AdvertisingReport(reports)) => {
+                    for report in reports {
+                        let name = ad_structures(report.data)
+                            .find(|(ad_type, _)| {
+                                *ad_type == AD_TYPE_COMPLETE_LOCAL_NAME
+                                    || *ad_type == AD_TYPE_SHORTENED_LOCAL_NAME
+                            })
+                            .map(|(_, name)| String::from_utf8_lossy(name).into_owned());
+                        let device = self.seen(packet_index, report.address_type, report.address);
+                        if name.is_some() {
+                            device.name = name;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl CaptureIndex {
+    /// Index a btsnoop capture, read to its end. A truncated last record is ignored, as
+    /// [`Btsnoop::parse`](crate::Btsnoop::parse) does.
+    pub fn build<R: Read>(reader: R) -> io::Result<Self> {
+        let mut hashing = Hashing {
+            reader,
+            hasher: Fnv1a::default(),
+            length: 0,
+        };
+        let mut reader = Reader::new(&mut hashing)?;
+        let header = reader.header().clone();
+        let uart = header.datalink_type == DatalinkType::Uart;
+        let mut builder = Builder::default();
+        loop {
+            let offset = reader.offset();
+            let packet = match reader.next() {
+                Some(Ok(packet)) => packet,
+                Some(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Some(Err(e)) => return Err(e),
+                None => break,
+            };
+            let packet_index = builder.packets.len();
+            builder.packets.push(IndexEntry {
+                offset,
+                description: packet.description,
+            });
+            if let (true, Ok(UartData::Event(event))) = (uart, parse_uart_packet(&packet)) {
+                builder.event(packet_index, event);
+            }
+        }
+        // the rest of a truncated record is part of the file hashed
+        io::copy(&mut hashing, &mut io::sink())?;
+        Ok(Self {
+            header,
+            length: hashing.length,
+            hash: hashing.hasher.0,
+            packets: builder.packets,
+            connections: builder.connections,
+            devices: builder.devices,
+        })
+    }
+
+    /// The index of the capture at `path` from its sidecar, or built and saved to the sidecar
+    /// when there is none or the capture changed since. Hashing the capture to check the
+    /// sidecar reads it but is much faster than parsing it.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let sidecar = Self::sidecar_path(path);
+        if let Ok(index) = Self::load(&sidecar) {
+            let length = path.metadata()?.len();
+            if index.length == length && (index.length, index.hash) == file_hash(path)? {
+                return Ok(index);
+            }
+        }
+        let index = Self::build(BufReader::new(File::open(path)?))?;
+        // without a writable directory the capture is indexed again next time
+        let _ = index.save(&sidecar);
+        Ok(index)
+    }
+
+    /// `btsnoop_hci.log.index` for `btsnoop_hci.log`
+    pub fn sidecar_path(capture: impl AsRef<Path>) -> PathBuf {
+        let mut path = capture.as_ref().as_os_str().to_owned();
+        path.push(".index");
+        PathBuf::from(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Index of the first packet at or after `timestamp`, the packets being in time order
+    pub fn packet_at(&self, timestamp: i64) -> usize {
+        self.packets
+            .partition_point(|entry| entry.description.timestamp < timestamp)
+    }
+
+    /// The connection `handle` referred to at packet `packet_index`
+    pub fn connection(&self, handle: u16, packet_index: usize) -> Option<&IndexedConnection> {
+        self.connections.iter().rev().find(|connection| {
+            connection.handle == handle
+                && connection.connected <= packet_index
+                && connection
+                    .disconnected
+                    .is_none_or(|end| packet_index <= end)
+        })
+    }
+
+    /// Read packet `index` from the capture file the index was built from
+    pub fn read_packet<R: Read + Seek>(&self, reader: &mut R, index: usize) -> io::Result<Packet> {
+        let entry = self.packets.get(index).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "packet index out of range")
+        })?;
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        Packet::parse(reader)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not a capture index"));
+        }
+        if reader.read_u32::<BigEndian>()? != VERSION {
+            return Err(invalid("unsupported capture index version"));
+        }
+        let length = reader.read_u64::<BigEndian>()?;
+        let hash = reader.read_u64::<BigEndian>()?;
+        let header = Header::parse(reader)?;
+        let index =
+            |value: u64| usize::try_from(value).map_err(|_| invalid("invalid packet index"));
+        let optional_index = |value: u64| match value {
+            u64::MAX => Ok(None),
+            value => index(value).map(Some),
+        };
+
+        let count = reader.read_u64::<BigEndian>()?;
+        // not allocated upfront, the count of a corrupt index can be anything
+        let mut packets = vec![];
+        for _ in 0..count {
+            let offset = reader.read_u64::<BigEndian>()?;
+            let description = PacketDescription::parse(reader)?;
+            packets.push(IndexEntry {
+                offset,
+                description,
+            });
+        }
+
+        let mut connections = vec![];
+        for _ in 0..reader.read_u32::<BigEndian>()? {
+            let handle = reader.read_u16::<BigEndian>()?;
+            let le = reader.read_u8()? != 0;
+            let address_type = reader.read_u8()?;
+            let address = BdAddr::parse(reader)?;
+            let connected = index(reader.read_u64::<BigEndian>()?)?;
+            let disconnected = optional_index(reader.read_u64::<BigEndian>()?)?;
+            let reason = reader.read_u8()?;
+            connections.push(IndexedConnection {
+                handle,
+                le,
+                address_type,
+                address,
+                connected,
+                disconnected,
+                reason: disconnected.map(|_| reason),
+            });
+        }
+
+        let mut devices = vec![];
+        for _ in 0..reader.read_u32::<BigEndian>()? {
+            let address_type = reader.read_u8()?;
+            let address = BdAddr::parse(reader)?;
+            let first_seen = index(reader.read_u64::<BigEndian>()?)?;
+            let last_seen = index(reader.read_u64::<BigEndian>()?)?;
+            let name = match reader.read_u16::<BigEndian>()? {
+                u16::MAX => None,
+                name_length => {
+                    let mut name = vec![0; name_length as usize];
+                    reader.read_exact(&mut name)?;
+                    Some(String::from_utf8(name).map_err(|_| invalid("invalid device name"))?)
+                }
+            };
+            devices.push(IndexedDevice {
+                address_type,
+                address,
+                name,
+                first_seen,
+                last_seen,
+            });
+        }
+
+        Ok(Self {
+            header,
+            length,
+            hash,
+            packets,
+            connections,
+            devices,
+        })
+    }
+
+    /// Big endian like the capture: the magic `btsnidx\0`, the version, the length and hash
+    /// of the capture, its header, then the packets, connections and devices, each preceded
+    /// by their count
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(VERSION)?;
+        writer.write_u64::<BigEndian>(self.length)?;
+        writer.write_u64::<BigEndian>(self.hash)?;
+        self.header.write(writer)?;
+
+        writer.write_u64::<BigEndian>(self.packets.len() as u64)?;
+        for entry in &self.packets {
+            let description = &entry.description;
+            let PacketFlags(flags) = description.flags;
+            writer.write_u64::<BigEndian>(entry.offset)?;
+            writer.write_u32::<BigEndian>(description.original_length)?;
+            writer.write_u32::<BigEndian>(description.included_length)?;
+            writer.write_u32::<BigEndian>(flags)?;
+            writer.write_u32::<BigEndian>(description.cumulative_drops)?;
+            writer.write_i64::<BigEndian>(description.timestamp)?;
+        }
+
+        writer.write_u32::<BigEndian>(self.connections.len() as u32)?;
+        for connection in &self.connections {
+            writer.write_u16::<BigEndian>(connection.handle)?;
+            writer.write_u8(connection.le as u8)?;
+            writer.write_u8(connection.address_type)?;
+            writer.write_all(&connection.address.0)?;
+            writer.write_u64::<BigEndian>(connection.connected as u64)?;
+            writer
+                .write_u64::<BigEndian>(connection.disconnected.map_or(u64::MAX, |i| i as u64))?;
+            writer.write_u8(connection.reason.unwrap_or_default())?;
+        }
+
+        writer.write_u32::<BigEndian>(self.devices.len() as u32)?;
+        for device in &self.devices {
+            writer.write_u8(device.address_type)?;
+            writer.write_all(&device.address.0)?;
+            writer.write_u64::<BigEndian>(device.first_seen as u64)?;
+            writer.write_u64::<BigEndian>(device.last_seen as u64)?;
+            match &device.name {
+                Some(name) => {
+                    let length = u16::try_from(name.len())
+                        .ok()
+                        .filter(|length| *length != u16::MAX)
+                        .ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidInput, "device name too long")
+                        })?;
+                    writer.write_u16::<BigEndian>(length)?;
+                    writer.write_all(name.as_bytes())?;
+                }
+                None => writer.write_u16::<BigEndian>(u16::MAX)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4, l2cap};
+
+    #[test]
+    fn index_roundtrip() {
+        let peer = [1, 2, 3, 4, 5, 6];
+        let mut connection = vec![
+            LeMetaEvent::CONNECTION_COMPLETE,
+            0x00,
+            0x40,
+            0x00,
+            0x00,
+            0x01,
+        ];
+        connection.extend_from_slice(&peer);
+        connection.extend_from_slice(&[24, 0, 0, 0, 0x90, 0x01, 0x00]);
+        let mut report = vec![LeMetaEvent::ADVERTISING_REPORT, 1, 0x00, 0x01];
+        report.extend_from_slice(&peer);
+        report.extend_from_slice(&[5, 0x04, 0x09, b'a', b'b', b'c', 0xc4]);
+        let mut remote_name = vec![0x00, 9, 8, 7, 6, 5, 4];
+        remote_name.extend_from_slice(b"headset\0\0\0");
+
+        let capture = h4(vec![
+            (0, true, event(Event::LE_META, &report)),
+            (10, true, event(Event::LE_META, &connection)),
+            (20, true, l2cap(0x40, 0x0004, &[0x1B])),
+            (
+                30,
+                true,
+                event(Event::DISCONNECTION_COMPLETE, &[0x00, 0x40, 0x00, 0x13]),
+            ),
+            (
+                40,
+                true,
+                event(Event::REMOTE_NAME_REQUEST_COMPLETE, &remote_name),
+            ),
+        ]);
+        let mut file = vec![];
+        capture.write(&mut file).unwrap();
+        // a truncated last record
+        file.extend_from_slice(&[0, 0, 0]);
+
+        let index = CaptureIndex::build(&file[..]).unwrap();
+        assert_eq!(index.length, file.len() as u64);
+        let mut hasher = Fnv1a::default();
+        hasher.write_all(&file).unwrap();
+        assert_eq!(index.hash, hasher.0);
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.packet_at(15), 2);
+
+        assert_eq!(index.connections.len(), 1);
+        let connection = index.connection(0x40, 2).unwrap();
+        assert_eq!((connection.le, connection.address_type), (true, 0x01));
+        assert_eq!(connection.address, BdAddr(peer));
+        assert_eq!(
+            (connection.disconnected, connection.reason),
+            (Some(3), Some(0x13))
+        );
+        assert!(index.connection(0x40, 4).is_none());
+
+        assert_eq!(index.devices.len(), 2);
+        assert_eq!(index.devices[0].name.as_deref(), Some("abc"));
+        assert_eq!(
+            (index.devices[0].first_seen, index.devices[0].last_seen),
+            (0, 1)
+        );
+        assert_eq!(index.devices[1].address, BdAddr([9, 8, 7, 6, 5, 4]));
+        assert_eq!(index.devices[1].name.as_deref(), Some("headset"));
+
+        let mut written = vec![];
+        index.write(&mut written).unwrap();
+        assert_eq!(CaptureIndex::read(&mut &written[..]).unwrap(), index);
+
+        let mut reader = io::Cursor::new(&file);
+        for (i, packet) in capture.packets.iter().enumerate() {
+            assert_eq!(&index.read_packet(&mut reader, i).unwrap(), packet);
+        }
+        assert!(index.read_packet(&mut reader, 5).is_err());
+    }
+}
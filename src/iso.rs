@@ -0,0 +1,296 @@
+//! ISO SDUs, the isochronous data of the Connected (CIS) and Broadcast (BIS) Isochronous Streams,
+//! reassembled from the fragments of the HCI ISO data packets.
+//!
+//! Every SDU carries a packet sequence number incremented once per SDU interval, so the SDUs
+//! the Host never got are the gaps in the numbers; the Controller reports the SDUs it received
+//! with errors or not at all with the packet status flag.
+
+use std::collections::HashMap;
+
+use crate::hci::Iso;
+
+/// How an SDU came through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SduStatus {
+    Valid,
+    /// the Controller received it with errors, see the packet status flag
+    PossiblyInvalid,
+    /// the Controller didn't receive it, the data is empty or to be ignored
+    Lost,
+    /// fragments are missing: an SDU started before this one ended, or the fragments are
+    /// shorter than the SDU length
+    Incomplete,
+}
+
+/// An SDU reassembled from the ISO data packets of a CIS or BIS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sdu {
+    pub handle: u16,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    /// packet index of its first fragment
+    pub packet_index: usize,
+    /// in microseconds, when the first fragment has one
+    pub time_stamp: Option<u32>,
+    pub sequence_number: u16,
+    pub data: Vec<u8>,
+    pub status: SduStatus,
+    /// sequence numbers skipped since the previous SDU of the stream
+    pub missing: u16,
+    /// its sequence number is not after the one of the previous SDU: it came late or twice
+    pub late: bool,
+}
+
+#[derive(Debug)]
+struct Pending {
+    sdu: Sdu,
+    length: usize,
+}
+
+/// Reassembles the SDUs of each isochronous stream and direction from their fragments.
+/// Fragments of an SDU started before the capture are ignored.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// (handle, sent) -> SDU being reassembled
+    pending: HashMap<(u16, bool), Pending>,
+    /// (handle, sent) -> sequence number of the last SDU
+    last: HashMap<(u16, bool), u16>,
+}
+
+impl Reassembler {
+    /// Feed the ISO data of packet `packet_index`. Returns the SDU it completed, preceded by
+    /// the incomplete SDU it abandoned when it starts a new one.
+    pub fn push(&mut self, iso: &Iso, sent: bool, packet_index: usize) -> Vec<Sdu> {
+        let key = (iso.handle, sent);
+        let mut sdus = vec![];
+        if let Some(sequence_number) = iso.sequence_number {
+            if let Some(abandoned) = self.pending.remove(&key) {
+                sdus.push(Sdu {
+                    status: SduStatus::Incomplete,
+                    ..abandoned.sdu
+                });
+            }
+            let (missing, late) = match self.last.insert(key, sequence_number) {
+                Some(last) => {
+                    let skipped = sequence_number.wrapping_sub(last.wrapping_add(1));
+                    // a number more than half the sequence number space ahead is behind
+                    if skipped < 0x8000 {
+                        (skipped, false)
+                    } else {
+                        // the stream continues from the SDU received before this one
+                        self.last.insert(key, last);
+                        (0, true)
+                    }
+                }
+                None => (0, false),
+            };
+            let status = match iso.packet_status_flag {
+                Some(Iso::STATUS_POSSIBLY_INVALID) => SduStatus::PossiblyInvalid,
+                Some(Iso::STATUS_LOST) => SduStatus::Lost,
+                _ => SduStatus::Valid,
+            };
+            self.pending.insert(
+                key,
+                Pending {
+                    sdu: Sdu {
+                        handle: iso.handle,
+                        sent,
+                        packet_index,
+                        time_stamp: iso.time_stamp,
+                        sequence_number,
+                        data: iso.data.to_vec(),
+                        status,
+                        missing,
+                        late,
+                    },
+                    length: iso.sdu_length.unwrap_or_default() as usize,
+                },
+            );
+        } else if let Some(pending) = self.pending.get_mut(&key) {
+            pending.sdu.data.extend_from_slice(iso.data);
+        }
+        if iso.packet_boundary_flag.is_end() {
+            if let Some(Pending { mut sdu, length }) = self.pending.remove(&key) {
+                if sdu.status != SduStatus::Lost && sdu.data.len() < length {
+                    sdu.status = SduStatus::Incomplete;
+                }
+                sdus.push(sdu);
+            }
+        }
+        sdus
+    }
+
+    /// the CIS is gone, with the SDUs it was carrying
+    pub fn disconnected(&mut self, handle: u16) -> Vec<Sdu> {
+        self.last.retain(|(h, _), _| *h != handle);
+        let handles: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|(h, _)| *h == handle)
+            .copied()
+            .collect();
+        handles
+            .iter()
+            .filter_map(|key| self.pending.remove(key))
+            .map(|pending| Sdu {
+                status: SduStatus::Incomplete,
+                ..pending.sdu
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    use crate::hci::IsoBoundaryFlag;
+
+    /// HCI ISO data packet of handle 0x060, with the header of an SDU start when `start` has
+    /// its sequence number, SDU length and packet status flag
+    fn packet(
+        flag: IsoBoundaryFlag,
+        time_stamp: Option<u32>,
+        start: Option<(u16, u16, u8)>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut load = vec![];
+        if let Some(time_stamp) = time_stamp {
+            load.extend_from_slice(&time_stamp.to_le_bytes());
+        }
+        if let Some((sequence_number, sdu_length, status)) = start {
+            load.extend_from_slice(&sequence_number.to_le_bytes());
+            load.extend_from_slice(&(sdu_length | (status as u16) << 14).to_le_bytes());
+        }
+        load.extend_from_slice(data);
+        let handle_and_flags = 0x060 | (flag as u16) << 12 | (time_stamp.is_some() as u16) << 14;
+        let mut packet = handle_and_flags.to_le_bytes().to_vec();
+        packet.extend_from_slice(&(load.len() as u16).to_le_bytes());
+        packet.extend_from_slice(&load);
+        packet
+    }
+
+    fn push(reassembler: &mut Reassembler, packet: &[u8], index: usize) -> Vec<Sdu> {
+        reassembler.push(&Iso::try_from(packet).unwrap(), true, index)
+    }
+
+    #[test]
+    fn iso_header() {
+        let data = packet(
+            IsoBoundaryFlag::CompleteSdu,
+            Some(1000),
+            Some((7, 2, Iso::STATUS_POSSIBLY_INVALID)),
+            &[0xaa, 0xbb],
+        );
+        let iso = Iso::try_from(&data[..]).unwrap();
+        assert_eq!(iso.handle, 0x060);
+        assert_eq!(iso.packet_boundary_flag, IsoBoundaryFlag::CompleteSdu);
+        assert_eq!(iso.time_stamp, Some(1000));
+        assert_eq!(iso.sequence_number, Some(7));
+        assert_eq!(iso.sdu_length, Some(2));
+        assert_eq!(iso.packet_status_flag, Some(Iso::STATUS_POSSIBLY_INVALID));
+        assert_eq!(iso.data_load_length, 10);
+        assert_eq!(iso.data, [0xaa, 0xbb]);
+        assert_eq!(iso.to_string(), "ISO Data: Handle 96 flags 0x06 dlen 10");
+
+        // a continuation has no sequence number, SDU length nor status
+        let data = packet(IsoBoundaryFlag::ContinuationFragment, None, None, &[0xcc]);
+        let iso = Iso::try_from(&data[..]).unwrap();
+        assert_eq!(iso.time_stamp, None);
+        assert_eq!(iso.sequence_number, None);
+        assert_eq!(iso.sdu_length, None);
+        assert_eq!(iso.data, [0xcc]);
+    }
+
+    #[test]
+    fn short_iso_header() {
+        let data = packet(
+            IsoBoundaryFlag::FirstFragment,
+            Some(1000),
+            Some((7, 2, 0)),
+            &[],
+        );
+        // cut in the time stamp, the sequence number and the SDU length
+        for length in [1, 3, 6, 9, 11] {
+            let error = Iso::try_from(&data[..length]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{length}");
+        }
+        assert!(Iso::try_from(&data[..]).is_ok());
+    }
+
+    #[test]
+    fn fragments() {
+        let mut reassembler = Reassembler::default();
+        let first = packet(
+            IsoBoundaryFlag::FirstFragment,
+            Some(1000),
+            Some((1, 4, 0)),
+            &[1, 2],
+        );
+        assert!(push(&mut reassembler, &first, 0).is_empty());
+        let last = packet(IsoBoundaryFlag::LastFragment, None, None, &[3, 4]);
+        let sdus = push(&mut reassembler, &last, 1);
+        assert_eq!(sdus.len(), 1);
+        assert_eq!(sdus[0].packet_index, 0);
+        assert_eq!(sdus[0].time_stamp, Some(1000));
+        assert_eq!(sdus[0].data, [1, 2, 3, 4]);
+        assert_eq!(sdus[0].status, SduStatus::Valid);
+        assert_eq!((sdus[0].missing, sdus[0].late), (0, false));
+
+        // a fragment of an SDU started before the capture
+        assert!(push(&mut reassembler, &last, 2).is_empty());
+    }
+
+    #[test]
+    fn incomplete_sdus() {
+        let mut reassembler = Reassembler::default();
+        let first = packet(
+            IsoBoundaryFlag::FirstFragment,
+            None,
+            Some((1, 4, 0)),
+            &[1, 2],
+        );
+        push(&mut reassembler, &first, 0);
+        // a new SDU before the end of the first one
+        let complete = packet(IsoBoundaryFlag::CompleteSdu, None, Some((2, 4, 0)), &[1]);
+        let sdus = push(&mut reassembler, &complete, 1);
+        assert_eq!(sdus.len(), 2);
+        assert_eq!(sdus[0].sequence_number, 1);
+        assert_eq!(sdus[0].status, SduStatus::Incomplete);
+        // shorter than its SDU length
+        assert_eq!(sdus[1].sequence_number, 2);
+        assert_eq!(sdus[1].status, SduStatus::Incomplete);
+
+        push(&mut reassembler, &first, 2);
+        let sdus = reassembler.disconnected(0x060);
+        assert_eq!(sdus.len(), 1);
+        assert_eq!(sdus[0].status, SduStatus::Incomplete);
+        assert!(reassembler.disconnected(0x060).is_empty());
+    }
+
+    #[test]
+    fn sequence_numbers() {
+        let mut reassembler = Reassembler::default();
+        let mut sdu = |sequence_number, status, index| {
+            let data = packet(
+                IsoBoundaryFlag::CompleteSdu,
+                None,
+                Some((sequence_number, 0, status)),
+                &[],
+            );
+            push(&mut reassembler, &data, index).remove(0)
+        };
+        assert_eq!(sdu(0xfffe, Iso::STATUS_VALID, 0).missing, 0);
+        // across the wrap around
+        let next = sdu(0x0002, Iso::STATUS_LOST, 1);
+        assert_eq!((next.missing, next.late), (3, false));
+        assert_eq!(next.status, SduStatus::Lost);
+        let late = sdu(0x0001, Iso::STATUS_POSSIBLY_INVALID, 2);
+        assert_eq!((late.missing, late.late), (0, true));
+        assert_eq!(late.status, SduStatus::PossiblyInvalid);
+        // the stream goes on from the last SDU in order
+        let next = sdu(0x0003, Iso::STATUS_VALID, 3);
+        assert_eq!((next.missing, next.late), (0, false));
+    }
+}
@@ -0,0 +1,699 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Read},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::hci::Acl;
+
+// data format from: Bluetooth core specification 5.4 Vol 3: Host Part A Logical Link Control and Adaptation Protocol Specification
+
+/// L2CAP PDU in basic L2CAP mode, carried in the payload of one or more ACL packets.
+///```text
+/// --------------------------
+/// | length 16 bit          |
+/// --------------------------
+/// | channel id 16 bit      |
+/// --------------------------
+/// | information payload    |
+/// --------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BasicFrame<'a> {
+    /// Size of the information payload in octets, excluding the basic L2CAP header
+    pub length: u16,
+    pub channel_id: u16,
+    /// the payload available in this ACL fragment, shorter than `length` when the PDU is fragmented
+    pub payload: &'a [u8],
+}
+
+impl<'a> BasicFrame<'a> {
+    const PAYLOAD_START_BYTE: usize = 4;
+
+    pub const SIGNALING_CID: u16 = 0x0001;
+    pub const ATT_CID: u16 = 0x0004;
+    pub const LE_SIGNALING_CID: u16 = 0x0005;
+    pub const SMP_CID: u16 = 0x0006;
+
+    /// whether the whole PDU is contained in this fragment
+    pub fn is_complete(&self) -> bool {
+        self.payload.len() >= self.length as usize
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for BasicFrame<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = data;
+        let length = reader.read_u16::<LittleEndian>()?;
+        let channel_id = reader.read_u16::<LittleEndian>()?;
+        Ok(Self {
+            length,
+            channel_id,
+            payload: &data[Self::PAYLOAD_START_BYTE..],
+        })
+    }
+}
+
+/// `L2CAP: CID 0x0004 len 7`
+impl Display for BasicFrame<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "L2CAP: CID 0x{:04x} len {}",
+            self.channel_id, self.length
+        )
+    }
+}
+
+/// Signaling command carried on the signaling channels (CID 0x0001 and 0x0005)
+///```text
+/// --------------------------------------------------
+/// | code 8 bit | identifier 8 bit | length 16 bit |
+/// --------------------------------------------------
+/// | data                                           |
+/// --------------------------------------------------
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalingCommand<'a> {
+    pub code: u8,
+    /// matches responses with requests
+    pub identifier: u8,
+    pub length: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> SignalingCommand<'a> {
+    const DATA_START_BYTE: usize = 4;
+
+    pub const COMMAND_REJECT: u8 = 0x01;
+    pub const CONNECTION_REQUEST: u8 = 0x02;
+    pub const CONNECTION_RESPONSE: u8 = 0x03;
+    pub const DISCONNECTION_REQUEST: u8 = 0x06;
+    pub const DISCONNECTION_RESPONSE: u8 = 0x07;
+    pub const CONNECTION_PARAMETER_UPDATE_REQUEST: u8 = 0x12;
+    pub const CONNECTION_PARAMETER_UPDATE_RESPONSE: u8 = 0x13;
+    pub const LE_CREDIT_BASED_CONNECTION_REQUEST: u8 = 0x14;
+    pub const LE_CREDIT_BASED_CONNECTION_RESPONSE: u8 = 0x15;
+
+    /// A C-frame on the BR/EDR signaling channel may carry several commands
+    pub fn iter(payload: &'a [u8]) -> impl Iterator<Item = SignalingCommand<'a>> {
+        let mut rest = payload;
+        std::iter::from_fn(move || {
+            let command = SignalingCommand::try_from(rest).ok()?;
+            rest = &rest[Self::DATA_START_BYTE + command.data.len()..];
+            Some(command)
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SignalingCommand<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut reader = data;
+        let code = reader.read_u8()?;
+        let identifier = reader.read_u8()?;
+        let length = reader.read_u16::<LittleEndian>()?;
+        let end = (Self::DATA_START_BYTE + length as usize).min(data.len());
+        Ok(Self {
+            code,
+            identifier,
+            length,
+            data: &data[Self::DATA_START_BYTE..end],
+        })
+    }
+}
+
+/// Sent by the Peripheral to request a set of new connection parameters (LE only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionParameterUpdateRequest {
+    /// Time = N × 1.25 ms
+    pub interval_min: u16,
+    /// Time = N × 1.25 ms
+    pub interval_max: u16,
+    pub latency: u16,
+    /// Time = N × 10 ms
+    pub timeout: u16,
+}
+
+impl ConnectionParameterUpdateRequest {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            interval_min: reader.read_u16::<LittleEndian>()?,
+            interval_max: reader.read_u16::<LittleEndian>()?,
+            latency: reader.read_u16::<LittleEndian>()?,
+            timeout: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// | Result | Description |
+/// | --- | --- |
+/// | 0x0000 | Connection Parameters accepted |
+/// | 0x0001 | Connection Parameters rejected |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionParameterUpdateResponse {
+    pub result: u16,
+}
+
+impl ConnectionParameterUpdateResponse {
+    pub const ACCEPTED: u16 = 0x0000;
+    pub const REJECTED: u16 = 0x0001;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            result: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// Connection Request, and LE Credit Based Connection Request which starts with the same fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionRequest {
+    pub psm: u16,
+    /// the channel endpoint on the device sending the request
+    pub source_cid: u16,
+}
+
+impl ConnectionRequest {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            psm: reader.read_u16::<LittleEndian>()?,
+            source_cid: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionResponse {
+    /// the channel endpoint on the device sending the response
+    pub destination_cid: u16,
+    pub source_cid: u16,
+    /// 0x0000 = successful, 0x0001 = pending, others refused
+    pub result: u16,
+    pub status: u16,
+}
+
+impl ConnectionResponse {
+    pub const SUCCESSFUL: u16 = 0x0000;
+
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            destination_cid: reader.read_u16::<LittleEndian>()?,
+            source_cid: reader.read_u16::<LittleEndian>()?,
+            result: reader.read_u16::<LittleEndian>()?,
+            status: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// LE Credit Based Connection Response, the request is read with [`ConnectionRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeCreditBasedConnectionResponse {
+    pub destination_cid: u16,
+    pub mtu: u16,
+    pub mps: u16,
+    pub initial_credits: u16,
+    pub result: u16,
+}
+
+impl LeCreditBasedConnectionResponse {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            destination_cid: reader.read_u16::<LittleEndian>()?,
+            mtu: reader.read_u16::<LittleEndian>()?,
+            mps: reader.read_u16::<LittleEndian>()?,
+            initial_credits: reader.read_u16::<LittleEndian>()?,
+            result: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// Disconnection Request and Response share the same parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Disconnection {
+    pub destination_cid: u16,
+    pub source_cid: u16,
+}
+
+impl Disconnection {
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            destination_cid: reader.read_u16::<LittleEndian>()?,
+            source_cid: reader.read_u16::<LittleEndian>()?,
+        })
+    }
+}
+
+/// Name of a signaling command code, as written in the specification
+pub fn signaling_code_name(code: u8) -> Option<&'static str> {
+    let name = match code {
+        0x01 => "Command Reject",
+        0x02 => "Connection Request",
+        0x03 => "Connection Response",
+        0x04 => "Configure Request",
+        0x05 => "Configure Response",
+        0x06 => "Disconnection Request",
+        0x07 => "Disconnection Response",
+        0x08 => "Echo Request",
+        0x09 => "Echo Response",
+        0x0A => "Information Request",
+        0x0B => "Information Response",
+        0x0C => "Create Channel Request",
+        0x0D => "Create Channel Response",
+        0x0E => "Move Channel Request",
+        0x0F => "Move Channel Response",
+        0x10 => "Move Channel Confirmation Request",
+        0x11 => "Move Channel Confirmation Response",
+        0x12 => "Connection Parameter Update Request",
+        0x13 => "Connection Parameter Update Response",
+        0x14 => "LE Credit Based Connection Request",
+        0x15 => "LE Credit Based Connection Response",
+        0x16 => "Flow Control Credit Indication",
+        0x17 => "Credit Based Connection Request",
+        0x18 => "Credit Based Connection Response",
+        0x19 => "Credit Based Reconfigure Request",
+        0x1A => "Credit Based Reconfigure Response",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub const PSM_SDP: u16 = 0x0001;
+pub const PSM_RFCOMM: u16 = 0x0003;
+pub const PSM_HID_CONTROL: u16 = 0x0011;
+pub const PSM_HID_INTERRUPT: u16 = 0x0013;
+pub const PSM_AVCTP: u16 = 0x0017;
+pub const PSM_AVDTP: u16 = 0x0019;
+pub const PSM_ATT: u16 = 0x001F;
+
+/// The protocol of the well known PSMs, e.g. `RFCOMM`
+pub fn psm_name(psm: u16) -> Option<&'static str> {
+    let name = match psm {
+        PSM_SDP => "SDP",
+        PSM_RFCOMM => "RFCOMM",
+        PSM_HID_CONTROL => "HID Control",
+        PSM_HID_INTERRUPT => "HID Interrupt",
+        PSM_AVCTP => "AVCTP",
+        PSM_AVDTP => "AVDTP",
+        PSM_ATT => "ATT",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A dynamically allocated channel, seen from the Host of the capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Channel {
+    pub handle: u16,
+    pub psm: u16,
+    /// endpoint on the Host of the capture, received frames are addressed to it
+    pub local_cid: u16,
+    /// endpoint on the remote device, sent frames are addressed to it
+    pub remote_cid: u16,
+    /// whether the Host of the capture requested the channel
+    pub local_initiated: bool,
+    /// packet index of the response which opened the channel
+    pub opened_at: usize,
+    /// an LE credit based channel, whose SDUs are segmented into K-frames
+    pub le_credit_based: bool,
+}
+
+/// Follows the signaling channels to know which dynamic channels are open on which connection
+#[derive(Debug, Default)]
+pub struct ChannelMap {
+    /// requests waiting for their response: (handle, sent by the Host, identifier) -> (psm, source cid)
+    pending: HashMap<(u16, bool, u8), (u16, u16)>,
+    /// (handle, local cid) -> channel
+    channels: HashMap<(u16, u16), Channel>,
+}
+
+impl ChannelMap {
+    /// Feed a frame received (`sent` false) or sent by the Host. Returns the channel the frame
+    /// belongs to, or the channel it opened when it is a successful connection response.
+    pub fn update(
+        &mut self,
+        handle: u16,
+        sent: bool,
+        packet_index: usize,
+        frame: &BasicFrame,
+    ) -> Option<&Channel> {
+        if frame.channel_id == BasicFrame::SIGNALING_CID
+            || frame.channel_id == BasicFrame::LE_SIGNALING_CID
+        {
+            let mut opened = None;
+            for command in SignalingCommand::iter(frame.payload) {
+                if let Some(local_cid) = self.signaling(handle, sent, packet_index, &command) {
+                    opened = Some(local_cid);
+                }
+            }
+            return opened.and_then(|cid| self.channels.get(&(handle, cid)));
+        }
+        self.lookup(handle, sent, frame.channel_id)
+    }
+
+    /// channel of a frame sent or received on `cid`
+    pub fn lookup(&self, handle: u16, sent: bool, cid: u16) -> Option<&Channel> {
+        if sent {
+            self.channels
+                .values()
+                .find(|c| c.handle == handle && c.remote_cid == cid)
+        } else {
+            self.channels.get(&(handle, cid))
+        }
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels.values()
+    }
+
+    /// the ACL connection is gone, so are its channels
+    pub fn disconnected(&mut self, handle: u16) {
+        self.channels.retain(|(h, _), _| *h != handle);
+        self.pending.retain(|(h, _, _), _| *h != handle);
+    }
+
+    fn signaling(
+        &mut self,
+        handle: u16,
+        sent: bool,
+        packet_index: usize,
+        command: &SignalingCommand,
+    ) -> Option<u16> {
+        let mut data = command.data;
+        match command.code {
+            SignalingCommand::CONNECTION_REQUEST
+            | SignalingCommand::LE_CREDIT_BASED_CONNECTION_REQUEST => {
+                let request = ConnectionRequest::parse(&mut data).ok()?;
+                self.pending.insert(
+                    (handle, sent, command.identifier),
+                    (request.psm, request.source_cid),
+                );
+            }
+            SignalingCommand::CONNECTION_RESPONSE
+            | SignalingCommand::LE_CREDIT_BASED_CONNECTION_RESPONSE => {
+                let (destination_cid, result) =
+                    if command.code == SignalingCommand::CONNECTION_RESPONSE {
+                        let response = ConnectionResponse::parse(&mut data).ok()?;
+                        (response.destination_cid, response.result)
+                    } else {
+                        let response = LeCreditBasedConnectionResponse::parse(&mut data).ok()?;
+                        (response.destination_cid, response.result)
+                    };
+                // pending stays around while the result is "pending"
+                if result == 0x0001 && command.code == SignalingCommand::CONNECTION_RESPONSE {
+                    return None;
+                }
+                let (psm, source_cid) =
+                    self.pending.remove(&(handle, !sent, command.identifier))?;
+                if result != ConnectionResponse::SUCCESSFUL {
+                    return None;
+                }
+                // the response is sent by the device which didn't request the channel
+                let (local_cid, remote_cid) = if sent {
+                    (destination_cid, source_cid)
+                } else {
+                    (source_cid, destination_cid)
+                };
+                self.channels.insert(
+                    (handle, local_cid),
+                    Channel {
+                        handle,
+                        psm,
+                        local_cid,
+                        remote_cid,
+                        local_initiated: !sent,
+                        opened_at: packet_index,
+                        le_credit_based: command.code
+                            == SignalingCommand::LE_CREDIT_BASED_CONNECTION_RESPONSE,
+                    },
+                );
+                return Some(local_cid);
+            }
+            SignalingCommand::DISCONNECTION_RESPONSE => {
+                let disconnection = Disconnection::parse(&mut data).ok()?;
+                // the response is addressed like the request: destination is the responder's endpoint
+                let local_cid = if sent {
+                    disconnection.destination_cid
+                } else {
+                    disconnection.source_cid
+                };
+                self.channels.remove(&(handle, local_cid));
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// An L2CAP PDU reassembled from the ACL fragments of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pdu {
+    pub handle: u16,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    /// packet index of its first fragment
+    pub packet_index: usize,
+    /// basic L2CAP header and information payload
+    pub data: Vec<u8>,
+    /// false when fragments were lost: the next PDU started before this one was whole
+    pub complete: bool,
+}
+
+impl Pdu {
+    pub fn frame(&self) -> io::Result<BasicFrame<'_>> {
+        BasicFrame::try_from(&self.data[..])
+    }
+}
+
+/// Reassembles the L2CAP PDUs of each connection and direction from their ACL fragments.
+/// Continuing fragments of a PDU started before the capture are ignored.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// (handle, sent) -> PDU being reassembled
+    pending: HashMap<(u16, bool), Pdu>,
+}
+
+impl Reassembler {
+    /// Feed the ACL data of packet `packet_index`. Returns the PDU it completed, preceded by
+    /// the incomplete PDU it abandoned when it starts a new one.
+    pub fn push(&mut self, acl: &Acl, sent: bool, packet_index: usize) -> Vec<Pdu> {
+        let key = (acl.handle, sent);
+        let mut pdus = vec![];
+        if acl.packet_boundary_flag.is_start() {
+            if let Some(abandoned) = self.pending.remove(&key) {
+                pdus.push(abandoned);
+            }
+            self.pending.insert(
+                key,
+                Pdu {
+                    handle: acl.handle,
+                    sent,
+                    packet_index,
+                    data: acl.data.to_vec(),
+                    complete: false,
+                },
+            );
+        } else if let Some(pdu) = self.pending.get_mut(&key) {
+            pdu.data.extend_from_slice(acl.data);
+        }
+        let whole = self.pending.get(&key).is_some_and(|pdu| {
+            pdu.data.get(..2).is_some_and(|length| {
+                pdu.data.len() >= u16::from_le_bytes([length[0], length[1]]) as usize + 4
+            })
+        });
+        if whole {
+            if let Some(mut pdu) = self.pending.remove(&key) {
+                pdu.complete = true;
+                pdus.push(pdu);
+            }
+        }
+        pdus
+    }
+
+    /// the ACL connection is gone, with the PDUs it was carrying
+    pub fn disconnected(&mut self, handle: u16) -> Vec<Pdu> {
+        let handles: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|(h, _)| *h == handle)
+            .copied()
+            .collect();
+        handles
+            .iter()
+            .filter_map(|key| self.pending.remove(key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hci::PacketBoundaryFlag;
+
+    fn acl(flag: PacketBoundaryFlag, data: &[u8]) -> Acl<'_> {
+        Acl {
+            handle: 0x0040,
+            packet_boundary_flag: flag,
+            broadcast_flag: 0,
+            data_len: data.len() as u16,
+            data,
+        }
+    }
+
+    /// C-frame on the BR/EDR signaling channel
+    fn frame(payload: &[u8]) -> BasicFrame<'_> {
+        BasicFrame {
+            length: payload.len() as u16,
+            channel_id: BasicFrame::SIGNALING_CID,
+            payload,
+        }
+    }
+
+    #[test]
+    fn basic_frame_lengths() {
+        let frame = BasicFrame::try_from(&[0x03, 0x00, 0x04, 0x00, 0x0a, 0x03, 0x00][..]).unwrap();
+        assert_eq!(frame.channel_id, BasicFrame::ATT_CID);
+        assert_eq!(frame.payload, [0x0a, 0x03, 0x00]);
+        assert!(frame.is_complete());
+
+        // a first fragment carries less than the length
+        let frame = BasicFrame::try_from(&[0x07, 0x00, 0x04, 0x00, 0x0a][..]).unwrap();
+        assert_eq!(frame.payload, [0x0a]);
+        assert!(!frame.is_complete());
+
+        // bytes past the length are kept in the payload
+        let frame = BasicFrame::try_from(&[0x01, 0x00, 0x04, 0x00, 0x0a, 0xff][..]).unwrap();
+        assert_eq!(frame.payload, [0x0a, 0xff]);
+        assert!(frame.is_complete());
+
+        let error = BasicFrame::try_from(&[0x01, 0x00, 0x04][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn signaling_commands() {
+        // a Connection Request then a Disconnection Request in one C-frame
+        let payload = [
+            0x02, 0x01, 0x04, 0x00, 0x19, 0x00, 0x41, 0x00, 0x06, 0x02, 0x04, 0x00, 0x41, 0x00,
+            0x42, 0x00,
+        ];
+        let commands: Vec<_> = SignalingCommand::iter(&payload).collect();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].code, SignalingCommand::CONNECTION_REQUEST);
+        let request = ConnectionRequest::parse(&mut &commands[0].data[..]).unwrap();
+        assert_eq!((request.psm, request.source_cid), (PSM_AVDTP, 0x0041));
+        assert_eq!(commands[1].identifier, 0x02);
+
+        // a length past the end of the frame is cut to the bytes present
+        let command =
+            SignalingCommand::try_from(&[0x12, 0x01, 0x08, 0x00, 0x06, 0x00][..]).unwrap();
+        assert_eq!(command.length, 8);
+        assert_eq!(command.data, [0x06, 0x00]);
+        assert!(ConnectionParameterUpdateRequest::parse(&mut &command.data[..]).is_err());
+        assert_eq!(
+            SignalingCommand::iter(&[0x12, 0x01, 0x08, 0x00, 0x06]).count(),
+            1
+        );
+        assert_eq!(SignalingCommand::iter(&[0x12, 0x01, 0x08]).count(), 0);
+    }
+
+    #[test]
+    fn channels_open_and_close() {
+        let mut map = ChannelMap::default();
+        // the Host requests AVDTP from source CID 0x0041, the peer answers with 0x0071
+        map.update(
+            0x0040,
+            true,
+            0,
+            &frame(&[0x02, 0x01, 0x04, 0x00, 0x19, 0x00, 0x41, 0x00]),
+        );
+        let response = [
+            0x03, 0x01, 0x08, 0x00, 0x71, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let channel = *map
+            .update(0x0040, false, 1, &frame(&response))
+            .expect("channel opened");
+        assert_eq!(channel.psm, PSM_AVDTP);
+        assert_eq!((channel.local_cid, channel.remote_cid), (0x0041, 0x0071));
+        assert!(channel.local_initiated);
+        assert_eq!(map.lookup(0x0040, true, 0x0071), Some(&channel));
+        assert_eq!(map.lookup(0x0040, false, 0x0041), Some(&channel));
+
+        // a truncated response opens nothing
+        map.update(
+            0x0040,
+            true,
+            2,
+            &frame(&[0x02, 0x02, 0x04, 0x00, 0x19, 0x00, 0x43, 0x00]),
+        );
+        assert!(map
+            .update(
+                0x0040,
+                false,
+                3,
+                &frame(&[0x03, 0x02, 0x08, 0x00, 0x73, 0x00])
+            )
+            .is_none());
+
+        map.disconnected(0x0040);
+        assert_eq!(map.channels().count(), 0);
+    }
+
+    #[test]
+    fn reassembly() {
+        let mut reassembler = Reassembler::default();
+        let start = [0x05, 0x00, 0x04, 0x00, 0x0b, 0x01];
+        assert!(reassembler
+            .push(
+                &acl(PacketBoundaryFlag::FirstAutomaticallyFlushable, &start),
+                false,
+                0
+            )
+            .is_empty());
+        let pdus = reassembler.push(
+            &acl(PacketBoundaryFlag::ContinuingFragment, &[0x02, 0x03, 0x04]),
+            false,
+            1,
+        );
+        assert_eq!(pdus.len(), 1);
+        assert!(pdus[0].complete);
+        assert_eq!(pdus[0].packet_index, 0);
+        assert_eq!(
+            pdus[0].frame().unwrap().payload,
+            [0x0b, 0x01, 0x02, 0x03, 0x04]
+        );
+
+        // a continuation without a start is dropped
+        let continuation = acl(PacketBoundaryFlag::ContinuingFragment, &[0x01]);
+        assert!(reassembler.push(&continuation, false, 2).is_empty());
+
+        // a new start abandons the PDU which never got whole
+        reassembler.push(
+            &acl(PacketBoundaryFlag::FirstAutomaticallyFlushable, &start),
+            false,
+            3,
+        );
+        let pdus = reassembler.push(
+            &acl(
+                PacketBoundaryFlag::FirstAutomaticallyFlushable,
+                &[0x01, 0x00, 0x04, 0x00, 0x0a],
+            ),
+            false,
+            4,
+        );
+        assert_eq!(pdus.len(), 2);
+        assert!(!pdus[0].complete && pdus[1].complete);
+
+        // a start too short for the length field waits for more
+        let short = acl(PacketBoundaryFlag::FirstAutomaticallyFlushable, &[0x05]);
+        assert!(reassembler.push(&short, true, 5).is_empty());
+        let pdus = reassembler.disconnected(0x0040);
+        assert_eq!(pdus.len(), 1);
+        assert!(pdus[0].frame().is_err());
+    }
+}
@@ -1,15 +1,62 @@
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use num_enum::TryFromPrimitive;
 use std::{
-    fmt::Display,
-    io::{self, Read},
+    fmt::{Debug, Display},
+    io::{self, Read, Write},
 };
 
-use crate::hci::Command;
+use crate::{
+    hci::Command,
+    options::{Checker, ParseOptions},
+};
 
+#[cfg(feature = "adb")]
+pub mod adb;
+pub mod analysis;
+#[cfg(feature = "annotations")]
+pub mod annotations;
+pub mod apple;
+pub mod att;
+pub mod avdtp;
+#[cfg(feature = "bumble")]
+pub mod bumble;
+pub mod columns;
+pub mod crypto;
+#[cfg(feature = "crypto")]
+pub mod decrypt;
+pub mod dissect;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flags;
+pub mod follow;
+pub mod formats;
+pub mod gatt;
 pub mod hci;
+#[cfg(all(feature = "hci-socket", target_os = "linux"))]
+pub mod hci_socket;
+pub mod hexdump;
+pub mod hid;
+pub mod index;
+pub mod iso;
+pub mod l2cap;
+pub mod mesh;
+pub mod options;
+#[cfg(feature = "oui")]
+pub mod oui;
+pub mod playback;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod report;
+pub mod rfcomm;
+pub mod sequence;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+#[cfg(feature = "trace-event")]
+pub mod trace_event;
+pub mod transform;
+pub mod vendor;
 
-///```
+///```text
 /// -----------------------
 /// | header              |
 /// -----------------------
@@ -22,13 +69,14 @@ pub mod hci;
 /// | packet record nbr n |
 /// -----------------------
 ///```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Btsnoop {
     pub header: Header,
     pub packets: Vec<Packet>,
 }
 
-/// ```
+/// ```text
 /// ----------------------------------------
 /// | identification pattern 64 bit        |
 /// ----------------------------------------
@@ -37,7 +85,8 @@ pub struct Btsnoop {
 /// | datalink type 32 bit                 |
 /// ----------------------------------------
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Header {
     // This is the ASCII string "btsnoop" followed by one null octets, must be: 62 74 73 6E 6F 6F 70 00
     pub identification_pattern: IdentificationPattern,
@@ -54,7 +103,7 @@ pub struct Header {
 /// | HCI Serial (H5) | 1004 |
 /// | Unassigned | 1005 - 4294967295 |
 #[repr(u32)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DatalinkType {
     Reserved(u32),
     UnencapsulatedHci = 1001,
@@ -68,10 +117,11 @@ pub enum DatalinkType {
 }
 
 /// 64 bit 62 74 73 6E 6F 6F 70 00 (aka. b'btsnoop\0')
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct IdentificationPattern;
 
-/// ```
+/// ```text
 /// --------------------------
 /// | original length        |
 /// | 32 bit
@@ -91,13 +141,20 @@ pub struct IdentificationPattern;
 /// | packet data            |
 /// --------------------------
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Packet {
     pub description: PacketDescription,
     pub data: PacketData,
 }
 
-#[derive(Debug)]
+/// One line description of a packet in the style of btmon, with its direction arrow, e.g.
+/// `> HCI Event: Command Complete (0x0e) plen 4`. The packet data is decoded as HCI UART (H4),
+/// the datalink of nearly every capture, packets which don't decode are described by their
+/// flags.
+pub struct PacketSummary<'a>(&'a Packet);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PacketDescription {
     /// A 32-bit unsigned integer representing the length in octets of the captured packet as received via a network.
     pub original_length: u32,
@@ -111,18 +168,32 @@ pub struct PacketDescription {
 }
 
 /// Variable-length field holding the packet that was captured, beginning with its datalink header. The Datalink Type field of the file header can be used to determine how to decode the datalink header. The length of the Packet Data field is given in the Included Length field.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PacketData(pub Vec<u8>);
 
+impl PacketData {
+    pub fn hexdump(&self) -> hexdump::Hexdump<'_> {
+        hexdump::Hexdump::new(&self.0)
+    }
+}
+
 /// | Bit No. | Definition |
 /// | --- | --- |
 /// | 0 | Direction flag 0 = Sent, 1 = Received |
 /// | 1 | Command flag 0 = Data, 1 = Command/Event |
 /// | 2 - 31 | Reserved |
-#[derive(Debug)]
+///
+/// Some capture tools carry their own information in the reserved bits. They are kept as is
+/// when a capture is read, transformed and written, see [`flags`] to name them.
+///
+/// Displayed as its flags, e.g. `Received | Command/Event`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PacketFlags(pub u32);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum DirectionFlag {
     Sent,
     Received,
@@ -132,30 +203,326 @@ pub enum DirectionFlag {
 /// Some Datalink Types already encode some or all of this information within the Packet Data.
 /// With these Datalink Types, these flags should be treated as informational only,
 /// and the value in the Packet Data should take precedence.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum CommandFlag {
     Data,
     CommandOrEvnet,
 }
 
+impl PacketFlags {
+    pub const RECEIVED: u32 = 0b01;
+    pub const COMMAND_OR_EVENT: u32 = 0b10;
+    pub const RESERVED: u32 = !0b11;
+
+    pub fn new(direction: DirectionFlag, command: CommandFlag) -> Self {
+        let mut flags = 0;
+        if direction == DirectionFlag::Received {
+            flags |= Self::RECEIVED;
+        }
+        if command == CommandFlag::CommandOrEvnet {
+            flags |= Self::COMMAND_OR_EVENT;
+        }
+        PacketFlags(flags)
+    }
+
+    /// The flags of a packet sent or received, with the command flag from its H4 packet type
+    /// indicator, data for unknown ones
+    pub fn for_uart(received: bool, packet_type: Option<UartPacketType>) -> Self {
+        let direction = if received {
+            DirectionFlag::Received
+        } else {
+            DirectionFlag::Sent
+        };
+        let command = match packet_type {
+            Some(UartPacketType::Cmd | UartPacketType::Evt) => CommandFlag::CommandOrEvnet,
+            _ => CommandFlag::Data,
+        };
+        Self::new(direction, command)
+    }
+
+    pub fn direction(&self) -> DirectionFlag {
+        if self.is_received() {
+            DirectionFlag::Received
+        } else {
+            DirectionFlag::Sent
+        }
+    }
+
+    pub fn command_flag(&self) -> CommandFlag {
+        if self.is_command_or_event() {
+            CommandFlag::CommandOrEvnet
+        } else {
+            CommandFlag::Data
+        }
+    }
+
+    pub fn is_received(&self) -> bool {
+        self.0 & Self::RECEIVED != 0
+    }
+
+    pub fn is_command_or_event(&self) -> bool {
+        self.0 & Self::COMMAND_OR_EVENT != 0
+    }
+
+    /// Bits 2 to 31, in place
+    pub fn reserved_bits(&self) -> u32 {
+        self.0 & Self::RESERVED
+    }
+
+    /// The same direction and command flags with other reserved bits, given in place
+    pub fn with_reserved_bits(self, bits: u32) -> Self {
+        PacketFlags(self.0 & !Self::RESERVED | bits & Self::RESERVED)
+    }
+
+    /// The bits of `mask` shifted down to bit 0, e.g. a field stored in bits 8 to 15 with
+    /// the mask `0x0000_FF00`
+    pub fn field(&self, mask: u32) -> u32 {
+        (self.0 & mask)
+            .checked_shr(mask.trailing_zeros())
+            .unwrap_or(0)
+    }
+}
+
+impl Display for PacketFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.is_received() {
+            "Received"
+        } else {
+            "Sent"
+        })?;
+        f.write_str(if self.is_command_or_event() {
+            " | Command/Event"
+        } else {
+            " | Data"
+        })?;
+        if self.reserved_bits() != 0 {
+            write!(f, " | Reserved 0x{:08X}", self.reserved_bits())?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for PacketFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PacketFlags({})", self)
+    }
+}
+
 impl Btsnoop {
     pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
-        let header = Header::parse(reader)?;
+        Self::parse_with(reader, &ParseOptions::default())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn parse_with<R: Read>(reader: &mut R, options: &ParseOptions) -> io::Result<Self> {
+        let mut reader = Reader::with_options(reader, options.clone())?;
         let mut packets = vec![];
-        loop {
-            let packet = Packet::parse(reader);
+        for packet in &mut reader {
             match packet {
                 Ok(packet) => packets.push(packet),
-                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                // a log cut while being written
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !options.strict => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        index = packets.len(),
+                        offset = reader.offset,
+                        "truncated last record ignored"
+                    );
+                    break;
+                }
                 Err(e) => return Err(e),
             }
         }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(packets = packets.len(), "capture parsed");
+
+        Ok(Self {
+            header: reader.header,
+            packets,
+        })
+    }
+
+    /// Parse a whole capture in memory. Never panics, whatever the data, so it can be fuzzed.
+    pub fn parse_from_slice(mut data: &[u8]) -> io::Result<Self> {
+        Self::parse(&mut data)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.header.write(writer)?;
+        for packet in &self.packets {
+            packet.write(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Packet> {
+        self.packets.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Packet> {
+        self.packets.iter_mut()
+    }
+
+    /// The HCI commands sent, none unless the capture is HCI UART (H4)
+    pub fn commands(&self) -> impl Iterator<Item = hci::Command<'_>> {
+        analysis::uart_packets(self).filter_map(|(_, _, data)| match data {
+            UartData::Command(command) => Some(command),
+            _ => None,
+        })
+    }
+
+    /// The HCI events received, none unless the capture is HCI UART (H4)
+    pub fn events(&self) -> impl Iterator<Item = hci::Event<'_>> {
+        analysis::uart_packets(self).filter_map(|(_, _, data)| match data {
+            UartData::Event(event) => Some(event),
+            _ => None,
+        })
+    }
+
+    /// The ACL data packets of a connection in both directions, none unless the capture is HCI
+    /// UART (H4)
+    pub fn acl_for_handle(&self, handle: u16) -> impl Iterator<Item = hci::Acl<'_>> {
+        analysis::uart_packets(self).filter_map(move |(_, _, data)| match data {
+            UartData::Acl(acl) if acl.handle == handle => Some(acl),
+            _ => None,
+        })
+    }
+}
+
+impl IntoIterator for Btsnoop {
+    type Item = Packet;
+    type IntoIter = std::vec::IntoIter<Packet>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Btsnoop {
+    type Item = &'a Packet;
+    type IntoIter = std::slice::Iter<'a, Packet>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Btsnoop {
+    type Item = &'a mut Packet;
+    type IntoIter = std::slice::IterMut<'a, Packet>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.iter_mut()
+    }
+}
+
+/// Reads the packets of a capture one at a time, without keeping them, e.g. from a pipe
+#[derive(Debug)]
+pub struct Reader<R> {
+    reader: R,
+    header: Header,
+    checker: Checker,
+    offset: u64,
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the header, the packets are read by iterating
+    pub fn new(reader: R) -> io::Result<Self> {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    pub fn with_options(mut reader: R, options: ParseOptions) -> io::Result<Self> {
+        let header = Header::parse(&mut reader)?;
+        let checker = Checker::new(options, &header)?;
+        Ok(Self {
+            reader,
+            header,
+            checker,
+            offset: Header::LENGTH as u64,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Offset in the input of the next record
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Ends at the end of the input, a truncated last record is an error
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.checker.is_done() {
+            return None;
+        }
+        let mut first = [0; 1];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "packet",
+            index = self.checker.packets(),
+            offset = self.offset
+        )
+        .entered();
+        let reader = &mut (&first[..]).chain(&mut self.reader);
+        let packet = PacketDescription::parse(reader)
+            .and_then(|description| {
+                self.checker.check_description(&description)?;
+                Packet::parse_data(reader, description)
+            })
+            .and_then(|mut packet| {
+                self.checker.accept(&mut packet)?;
+                Ok(packet)
+            });
+        match &packet {
+            Ok(packet) => {
+                self.offset +=
+                    (PacketDescription::LENGTH + packet.description.included_length as usize) as u64
+            }
+            #[cfg(feature = "tracing")]
+            Err(e) => tracing::debug!(error = %e, "record rejected"),
+            #[cfg(not(feature = "tracing"))]
+            Err(_) => {}
+        }
+        Some(packet)
+    }
+}
 
-        Ok(Self { header, packets })
+/// Version 1 HCI UART (H4), the header Android and most tools write
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            identification_pattern: IdentificationPattern,
+            version: Self::VERSION,
+            datalink_type: DatalinkType::default(),
+        }
     }
 }
 
 impl Header {
+    /// Length of the header, the first record follows it
+    pub const LENGTH: usize = 16;
+
+    /// The version of the format the records are parsed and written in
+    pub const VERSION: u32 = 1;
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err)
+    )]
     pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
         let mut id_pat = [0u8; 8];
         reader.read_exact(&mut id_pat)?;
@@ -163,6 +530,8 @@ impl Header {
         let version = reader.read_u32::<BigEndian>()?;
         let datalink_type = reader.read_u32::<BigEndian>()?;
         let datalink_type: DatalinkType = datalink_type.into();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(version, datalink = %datalink_type, "header");
 
         Ok(Self {
             identification_pattern,
@@ -171,11 +540,61 @@ impl Header {
         })
     }
 
+    /// Always written as [`Header::VERSION`], the version of the records written, even for a
+    /// capture of another version parsed with
+    /// [`ParseOptions::allow_unsupported_version`]
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&IdentificationPattern::IDENTIFICATION_PATTERN)?;
+        writer.write_u32::<BigEndian>(Self::VERSION)?;
+        writer.write_u32::<BigEndian>(self.datalink_type.into())
+    }
+
     pub fn identification_pattern(&self) -> &'static str {
         IdentificationPattern::NAME
     }
 }
 
+/// The error of a capture whose header has a version other than [`Header::VERSION`], carried by
+/// an [`io::Error`] of kind `InvalidData` unless the version is allowed with
+/// [`ParseOptions::allow_unsupported_version`]
+///
+/// ```
+/// use btsnoop::{Btsnoop, UnsupportedVersion};
+///
+/// let mut data = include_bytes!("../res/btsnoop_hci_android.log").to_vec();
+/// data[11] = 2;
+/// let error = Btsnoop::parse_from_slice(&data).unwrap_err();
+/// assert_eq!(UnsupportedVersion::of(&error), Some(UnsupportedVersion(2)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnsupportedVersion(pub u32);
+
+impl UnsupportedVersion {
+    /// The unsupported version error `error` carries
+    pub fn of(error: &io::Error) -> Option<Self> {
+        error.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unsupported btsnoop version {}, only version {} is supported",
+            self.0,
+            Header::VERSION
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+impl From<UnsupportedVersion> for io::Error {
+    fn from(error: UnsupportedVersion) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
 impl IdentificationPattern {
     pub const NAME: &'static str = "btsnoop";
     pub const IDENTIFICATION_PATTERN: [u8; 8] = [0x62, 0x74, 0x73, 0x6E, 0x6F, 0x6F, 0x70, 0x00];
@@ -215,19 +634,107 @@ impl From<u32> for DatalinkType {
     }
 }
 
+/// Any code, mapped like a parsed header
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for DatalinkType {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(DatalinkType::from(u32::arbitrary(u)?))
+    }
+}
+
+/// HCI UART (H4), the datalink of nearly every capture
+impl Default for DatalinkType {
+    fn default() -> Self {
+        DatalinkType::Uart
+    }
+}
+
+impl Display for DatalinkType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatalinkType::UnencapsulatedHci => f.write_str("Un-encapsulated HCI (H1)"),
+            DatalinkType::Uart => f.write_str("HCI UART (H4)"),
+            DatalinkType::Bscp => f.write_str("HCI BSCP"),
+            DatalinkType::Serial => f.write_str("HCI Serial (H5)"),
+            DatalinkType::Reserved(value) | DatalinkType::Unassigned(value) => {
+                write!(f, "Unknown ({})", value)
+            }
+        }
+    }
+}
+
+impl From<DatalinkType> for u32 {
+    fn from(value: DatalinkType) -> Self {
+        match value {
+            DatalinkType::Reserved(value) | DatalinkType::Unassigned(value) => value,
+            DatalinkType::UnencapsulatedHci => 1001,
+            DatalinkType::Uart => 1002,
+            DatalinkType::Bscp => 1003,
+            DatalinkType::Serial => 1004,
+        }
+    }
+}
+
+/// The included length is the length of the arbitrary data
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Packet {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let data = PacketData::arbitrary(u)?;
+        let mut description = PacketDescription::arbitrary(u)?;
+        description.included_length = data.0.len() as u32;
+        Ok(Self { description, data })
+    }
+}
+
 impl Packet {
     pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
         let description = PacketDescription::parse(reader)?;
-        let mut data = vec![0; description.included_length as usize];
-        // let mut data = Vec::with_capacity(description.included_length as usize);
-        reader.read_exact(&mut data)?;
+        Self::parse_data(reader, description)
+    }
+
+    /// Read the data of the record described
+    fn parse_data<R: Read>(reader: &mut R, description: PacketDescription) -> io::Result<Self> {
+        // not allocated upfront, the included length of a corrupt record can be anything
+        let mut data = vec![];
+        reader
+            .take(description.included_length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < description.included_length as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "packet data shorter than its included length",
+            ));
+        }
         let data = PacketData(data);
 
         Ok(Self { description, data })
     }
+
+    /// The included length written is the length of the packet data, which may have been edited
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let description = &self.description;
+        writer.write_u32::<BigEndian>(description.original_length)?;
+        writer.write_u32::<BigEndian>(self.data.0.len() as u32)?;
+        writer.write_u32::<BigEndian>(description.flags.0)?;
+        writer.write_u32::<BigEndian>(description.cumulative_drops)?;
+        writer.write_i64::<BigEndian>(description.timestamp)?;
+        writer.write_all(&self.data.0)
+    }
 }
 
 impl PacketDescription {
+    /// Length of a record before its packet data
+    pub const LENGTH: usize = 24;
+
+    /// microseconds between midnight, January 1st, 0 AD nominal Gregorian and the Unix epoch
+    pub const UNIX_EPOCH_OFFSET: i64 = 0x00DC_DDB3_0F2F_8000;
+
+    /// timestamp in microseconds since the Unix epoch (UTC), saturated for the timestamps
+    /// of a corrupt record before [`i64::MIN`] microseconds
+    pub fn unix_timestamp(&self) -> i64 {
+        self.timestamp.saturating_sub(Self::UNIX_EPOCH_OFFSET)
+    }
+
     pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
         let original_length = reader.read_u32::<BigEndian>()?;
         let included_length = reader.read_u32::<BigEndian>()?;
@@ -276,7 +783,8 @@ impl TryFrom<u8> for CommandFlag {
 }
 
 #[repr(u8)]
-#[derive(Debug, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, TryFromPrimitive)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UartPacketType {
     Cmd = 1,
     Acl,
@@ -285,15 +793,79 @@ pub enum UartPacketType {
     Iso,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UartData<'a> {
     Command(hci::Command<'a>),
+    Event(hci::Event<'a>),
+    Acl(hci::Acl<'a>),
     Todos,
 }
 
+impl Display for UartData<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UartData::Command(command) => write!(f, "{}", command),
+            UartData::Event(event) => write!(f, "{}", event),
+            UartData::Acl(acl) => write!(f, "{}", acl),
+            UartData::Todos => f.write_str("Other"),
+        }
+    }
+}
+
+impl Packet {
+    pub fn summary(&self) -> PacketSummary<'_> {
+        PacketSummary(self)
+    }
+}
+
+impl Display for PacketSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let packet = self.0;
+        let flags = packet.description.flags;
+        let received = flags.is_received();
+        write!(f, "{} ", if received { '>' } else { '<' })?;
+        match parse_uart_packet(packet) {
+            Ok(UartData::Acl(acl)) => {
+                write!(f, "ACL Data {}: ", if received { "RX" } else { "TX" })?;
+                acl.fmt_fields(f)
+            }
+            Ok(UartData::Todos) => match packet.data.0.first() {
+                Some(0x03) => f.write_str("SCO Data"),
+                Some(0x05) => f.write_str("ISO Data"),
+                _ => f.write_str("Empty packet"),
+            },
+            Ok(data) => write!(f, "{}", data),
+            Err(_) => write!(
+                f,
+                "{} ({} bytes)",
+                match (flags.is_command_or_event(), received) {
+                    (true, false) => "Command",
+                    (true, true) => "Event",
+                    _ => "Data",
+                },
+                packet.data.0.len()
+            ),
+        }
+    }
+}
+
+/// The UTC timestamp and the summary, e.g.
+/// `2023-01-28 02:48:36.395644 UTC < HCI Command: Reset (0x03|0x0003) plen 0`
+impl Display for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            report::format_utc(self.description.timestamp),
+            self.summary()
+        )
+    }
+}
+
 /// for uart packet, first 8 bit is the packet type
-pub fn parse_uart_packet(packet: &mut Packet) -> io::Result<UartData<'_>> {
-    let data = &mut packet.data.0;
+pub fn parse_uart_packet(packet: &Packet) -> io::Result<UartData<'_>> {
+    let data = &packet.data.0;
     if data.is_empty() {
         return Ok(UartData::Todos);
     }
@@ -302,17 +874,22 @@ pub fn parse_uart_packet(packet: &mut Packet) -> io::Result<UartData<'_>> {
     let uart_type = UartPacketType::try_from_primitive(tp)
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid packet type"))?;
     match uart_type {
-        Cmd => {
-            let cmd = Command::from(&mut data[1..]);
-            Ok(UartData::Command(cmd))
-        }
+        Cmd => Ok(UartData::Command(Command::try_from(&data[1..])?)),
+        Evt => Ok(UartData::Event(hci::Event::try_from(&data[1..])?)),
+        Acl => Ok(UartData::Acl(hci::Acl::try_from(&data[1..])?)),
         _ => Ok(UartData::Todos),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::explicit_counter_loop)]
 mod test {
-    use crate::{parse_uart_packet, Btsnoop, UartData};
+    use std::collections::HashSet;
+
+    use crate::{
+        parse_uart_packet, Btsnoop, CommandFlag, DirectionFlag, Header, PacketFlags, Reader,
+        UartData,
+    };
 
     #[test]
     fn read_test() {
@@ -334,4 +911,126 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn write_test() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let bs = Btsnoop::parse(&mut &original[..]).unwrap();
+        let mut written = vec![];
+        bs.write(&mut written).unwrap();
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn stream_test() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let bs = Btsnoop::parse(&mut &original[..]).unwrap();
+        let reader = Reader::new(original).unwrap();
+        assert_eq!(reader.count(), bs.packets.len());
+
+        let truncated = Reader::new(&original[..original.len() - 1]).unwrap();
+        assert!(truncated.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn flags_test() {
+        let flags = PacketFlags::new(DirectionFlag::Received, CommandFlag::CommandOrEvnet);
+        assert_eq!(flags, PacketFlags(0b11));
+        assert_eq!(flags.direction(), DirectionFlag::Received);
+        assert_eq!(flags.to_string(), "Received | Command/Event");
+
+        let flags = PacketFlags(0x100);
+        assert_eq!(flags.command_flag(), CommandFlag::Data);
+        assert_eq!(flags.reserved_bits(), 0x100);
+        assert_eq!(
+            format!("{:?}", flags),
+            "PacketFlags(Sent | Data | Reserved 0x00000100)"
+        );
+        assert_eq!(flags.field(0x0000_FF00), 1);
+        assert_eq!(flags.field(0), 0);
+        let flags = PacketFlags(0b11).with_reserved_bits(0xFFFF_0001);
+        assert_eq!(flags, PacketFlags(0xFFFF_0003));
+    }
+
+    #[test]
+    fn display_test() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let bs = Btsnoop::parse(&mut &original[..]).unwrap();
+        assert_eq!(
+            bs.packets[0].to_string(),
+            "2023-01-28 02:48:36.395644 UTC < HCI Command: Reset (0x03|0x0003) plen 0"
+        );
+        assert_eq!(
+            bs.packets[1].summary().to_string(),
+            "> HCI Event: Command Complete (0x0e) plen 4"
+        );
+
+        // the timestamps of corrupt records
+        let mut packet = bs.packets[0].clone();
+        packet.description.timestamp = i64::MIN;
+        assert_eq!(
+            packet.to_string(),
+            "timestamp -9223372036854775808 out of range < HCI Command: Reset (0x03|0x0003) plen 0"
+        );
+        assert_eq!(packet.description.unix_timestamp(), i64::MIN);
+        packet.description.timestamp = i64::MAX;
+        assert_eq!(
+            packet.to_string(),
+            "292276-12-28 04:00:54.775807 UTC < HCI Command: Reset (0x03|0x0003) plen 0"
+        );
+    }
+
+    #[test]
+    fn iter_test() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let bs = Btsnoop::parse(&mut &original[..]).unwrap();
+        assert_eq!((&bs).into_iter().count(), bs.packets.len());
+        let commands = bs.commands().count();
+        let events = bs.events().count();
+        assert_eq!(commands + events, bs.packets.len());
+        assert_eq!(bs.commands().next().unwrap().opcode.name(), Some("Reset"));
+        assert_eq!(bs.acl_for_handle(0x0001).count(), 0);
+    }
+
+    #[test]
+    fn derives_test() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let bs = Btsnoop::parse(&mut &original[..]).unwrap();
+        assert_eq!(bs.clone(), Btsnoop::parse(&mut &original[..]).unwrap());
+        assert_eq!(bs.header, Header::default());
+
+        let distinct: HashSet<_> = bs.iter().map(|p| &p.data).collect();
+        assert!(distinct.len() < bs.packets.len());
+        let commands: HashSet<_> = bs.commands().collect();
+        assert!(commands.contains(&bs.commands().next().unwrap()));
+    }
+
+    #[test]
+    fn malformed_test() {
+        let mut data = include_bytes!("../res/btsnoop_hci_android.log")[..16].to_vec();
+        // truncated Reset command
+        data.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&[0x01, 0x03]);
+        let bs = Btsnoop::parse_from_slice(&data).unwrap();
+        assert!(parse_uart_packet(&bs.packets[0]).is_err());
+
+        // an included length far beyond the data is not allocated
+        data[16 + 4..16 + 8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Btsnoop::parse_from_slice(&data).unwrap().packets.is_empty());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_test() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let noise: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut bs = Btsnoop::arbitrary(&mut Unstructured::new(&noise)).unwrap();
+        let mut written = vec![];
+        bs.write(&mut written).unwrap();
+        // written as the version of its records
+        bs.header.version = Header::VERSION;
+        assert_eq!(Btsnoop::parse_from_slice(&written).unwrap(), bs);
+    }
 }
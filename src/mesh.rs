@@ -0,0 +1,700 @@
+//! Bluetooth Mesh over GATT: the proxy PDUs of the Mesh Provisioning (PB-GATT) and Mesh Proxy
+//! services, their segments reassembled, and the messages they carry.
+//!
+//! The provisioning PDUs and the mesh beacons are in plain. The network PDUs and the proxy
+//! configuration messages are encrypted with the network key, only the IV index bit and the
+//! network ID of their first octet are decoded without it;
+//! [`ProxyConfiguration::parse`] reads a decrypted proxy configuration message.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, Read},
+};
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+// data format from: Mesh Protocol 1.1, 6 Proxy protocol and 5.4 Provisioning protocol
+
+/// The characteristics carrying proxy PDUs, written by the client and notified by the server
+pub const MESH_PROVISIONING_DATA_IN: u16 = 0x2ADB;
+pub const MESH_PROVISIONING_DATA_OUT: u16 = 0x2ADC;
+pub const MESH_PROXY_DATA_IN: u16 = 0x2ADD;
+pub const MESH_PROXY_DATA_OUT: u16 = 0x2ADE;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Segmentation and reassembly of a proxy PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sar {
+    Complete,
+    First,
+    Continuation,
+    Last,
+}
+
+impl Sar {
+    pub fn is_start(&self) -> bool {
+        matches!(self, Self::Complete | Self::First)
+    }
+
+    pub fn is_end(&self) -> bool {
+        matches!(self, Self::Complete | Self::Last)
+    }
+}
+
+impl Display for Sar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Complete => "complete",
+            Self::First => "first segment",
+            Self::Continuation => "continuation segment",
+            Self::Last => "last segment",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageType {
+    NetworkPdu,
+    MeshBeacon,
+    ProxyConfiguration,
+    ProvisioningPdu,
+    Reserved(u8),
+}
+
+impl From<u8> for MessageType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Self::NetworkPdu,
+            0x01 => Self::MeshBeacon,
+            0x02 => Self::ProxyConfiguration,
+            0x03 => Self::ProvisioningPdu,
+            _ => Self::Reserved(value),
+        }
+    }
+}
+
+impl Display for MessageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NetworkPdu => f.write_str("Network PDU"),
+            Self::MeshBeacon => f.write_str("Mesh Beacon"),
+            Self::ProxyConfiguration => f.write_str("Proxy Configuration"),
+            Self::ProvisioningPdu => f.write_str("Provisioning PDU"),
+            Self::Reserved(value) => write!(f, "Reserved (0x{:02x})", value),
+        }
+    }
+}
+
+/// A segment of a proxy PDU, the value of a Data In or Data Out characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyPdu<'a> {
+    pub sar: Sar,
+    pub message_type: MessageType,
+    pub data: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for ProxyPdu<'a> {
+    type Error = io::Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let (header, data) = value
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "empty proxy PDU"))?;
+        Ok(Self {
+            sar: match header >> 6 {
+                0 => Sar::Complete,
+                1 => Sar::First,
+                2 => Sar::Continuation,
+                _ => Sar::Last,
+            },
+            message_type: MessageType::from(header & 0x3F),
+            data,
+        })
+    }
+}
+
+/// A proxy PDU reassembled from its segments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub connection: u16,
+    /// sent by the Host of the capture
+    pub sent: bool,
+    /// packet index of its first segment
+    pub packet_index: usize,
+    pub message_type: MessageType,
+    pub data: Vec<u8>,
+    pub segments: usize,
+    /// a segment is missing: a message started before this one ended, or a segment of
+    /// another type came in between
+    pub incomplete: bool,
+}
+
+/// Reassembles the proxy PDUs of each connection and direction from their segments.
+/// Segments of a PDU started before the capture are ignored.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// (connection handle, sent) -> message being reassembled
+    pending: HashMap<(u16, bool), Message>,
+}
+
+impl Reassembler {
+    /// Feed the proxy PDU of packet `packet_index`. Returns the message it completed, preceded
+    /// by the incomplete message it abandoned.
+    pub fn push(
+        &mut self,
+        connection: u16,
+        sent: bool,
+        packet_index: usize,
+        pdu: &ProxyPdu,
+    ) -> Vec<Message> {
+        let key = (connection, sent);
+        let mut messages = vec![];
+        let continues = self
+            .pending
+            .get(&key)
+            .is_some_and(|message| !pdu.sar.is_start() && message.message_type == pdu.message_type);
+        if continues {
+            if let Some(message) = self.pending.get_mut(&key) {
+                message.data.extend_from_slice(pdu.data);
+                message.segments += 1;
+            }
+        } else {
+            if let Some(mut abandoned) = self.pending.remove(&key) {
+                abandoned.incomplete = true;
+                messages.push(abandoned);
+            }
+            if !pdu.sar.is_start() {
+                return messages;
+            }
+            self.pending.insert(
+                key,
+                Message {
+                    connection,
+                    sent,
+                    packet_index,
+                    message_type: pdu.message_type,
+                    data: pdu.data.to_vec(),
+                    segments: 1,
+                    incomplete: false,
+                },
+            );
+        }
+        if pdu.sar.is_end() {
+            messages.extend(self.pending.remove(&key));
+        }
+        messages
+    }
+
+    /// The connection is gone, with the messages it was carrying
+    pub fn disconnected(&mut self, connection: u16) -> Vec<Message> {
+        let keys: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|(c, _)| *c == connection)
+            .copied()
+            .collect();
+        keys.iter()
+            .filter_map(|key| self.pending.remove(key))
+            .map(|message| Message {
+                incomplete: true,
+                ..message
+            })
+            .collect()
+    }
+}
+
+/// The plain header of a network PDU, its rest is obfuscated and encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkPdu<'a> {
+    /// least significant bit of the IV index
+    pub ivi: u8,
+    /// identifies the network key
+    pub nid: u8,
+    /// CTL, TTL, SEQ and SRC obfuscated, then DST, the transport PDU and the NetMIC encrypted
+    pub encrypted: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for NetworkPdu<'a> {
+    type Error = io::Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        // the shortest one, an unsegmented control message of one octet and a 64 bit NetMIC
+        if value.len() < 18 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "network PDU too short",
+            ));
+        }
+        Ok(Self {
+            ivi: value[0] >> 7,
+            nid: value[0] & 0x7F,
+            encrypted: &value[1..],
+        })
+    }
+}
+
+/// A mesh beacon, multi-octet fields most significant octet first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Beacon {
+    UnprovisionedDevice {
+        device_uuid: u128,
+        oob_information: u16,
+        uri_hash: Option<u32>,
+    },
+    SecureNetwork {
+        key_refresh: bool,
+        iv_update: bool,
+        network_id: u64,
+        iv_index: u32,
+        authentication_value: u64,
+    },
+    /// its flags and IV index are obfuscated with the private beacon key
+    MeshPrivate {
+        random: [u8; 13],
+        obfuscated: [u8; 5],
+        authentication_tag: u64,
+    },
+}
+
+impl TryFrom<&[u8]> for Beacon {
+    type Error = io::Error;
+
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(match value.read_u8()? {
+            0x00 => Beacon::UnprovisionedDevice {
+                device_uuid: value.read_u128::<BigEndian>()?,
+                oob_information: value.read_u16::<BigEndian>()?,
+                uri_hash: value.read_u32::<BigEndian>().ok(),
+            },
+            0x01 => {
+                let flags = value.read_u8()?;
+                Beacon::SecureNetwork {
+                    key_refresh: flags & 0x01 != 0,
+                    iv_update: flags & 0x02 != 0,
+                    network_id: value.read_u64::<BigEndian>()?,
+                    iv_index: value.read_u32::<BigEndian>()?,
+                    authentication_value: value.read_u64::<BigEndian>()?,
+                }
+            }
+            0x02 => {
+                let mut random = [0; 13];
+                value.read_exact(&mut random)?;
+                let mut obfuscated = [0; 5];
+                value.read_exact(&mut obfuscated)?;
+                Beacon::MeshPrivate {
+                    random,
+                    obfuscated,
+                    authentication_tag: value.read_u64::<BigEndian>()?,
+                }
+            }
+            _ => return Err(invalid("unknown beacon type")),
+        })
+    }
+}
+
+impl Display for Beacon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Beacon::UnprovisionedDevice {
+                device_uuid,
+                oob_information,
+                uri_hash,
+            } => {
+                write!(
+                    f,
+                    "Unprovisioned Device Beacon: UUID {:032x} OOB 0x{:04x}",
+                    device_uuid, oob_information
+                )?;
+                if let Some(uri_hash) = uri_hash {
+                    write!(f, " URI hash 0x{:08x}", uri_hash)?;
+                }
+                Ok(())
+            }
+            Beacon::SecureNetwork {
+                key_refresh,
+                iv_update,
+                network_id,
+                iv_index,
+                ..
+            } => write!(
+                f,
+                "Secure Network Beacon: network ID {:016x} IV index 0x{:08x}{}{}",
+                network_id,
+                iv_index,
+                if *key_refresh { " key refresh" } else { "" },
+                if *iv_update { " IV update" } else { "" }
+            ),
+            Beacon::MeshPrivate { .. } => f.write_str("Mesh Private Beacon"),
+        }
+    }
+}
+
+pub fn provisioning_pdu_name(pdu_type: u8) -> Option<&'static str> {
+    Some(match pdu_type {
+        0x00 => "Provisioning Invite",
+        0x01 => "Provisioning Capabilities",
+        0x02 => "Provisioning Start",
+        0x03 => "Provisioning Public Key",
+        0x04 => "Provisioning Input Complete",
+        0x05 => "Provisioning Confirmation",
+        0x06 => "Provisioning Random",
+        0x07 => "Provisioning Data",
+        0x08 => "Provisioning Complete",
+        0x09 => "Provisioning Failed",
+        0x0A => "Provisioning Record Request",
+        0x0B => "Provisioning Record Response",
+        0x0C => "Provisioning Records Get",
+        0x0D => "Provisioning Records List",
+        _ => return None,
+    })
+}
+
+pub fn provisioning_error_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x00 => "Prohibited",
+        0x01 => "Invalid PDU",
+        0x02 => "Invalid Format",
+        0x03 => "Unexpected PDU",
+        0x04 => "Confirmation Failed",
+        0x05 => "Out of Resources",
+        0x06 => "Decryption Failed",
+        0x07 => "Unexpected Error",
+        0x08 => "Cannot Assign Addresses",
+        0x09 => "Invalid Data",
+        _ => return None,
+    })
+}
+
+/// A provisioning PDU, the parameters of the ones whose fields tell how the device was
+/// provisioned decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provisioning<'a> {
+    Invite {
+        attention_duration: u8,
+    },
+    Capabilities {
+        elements: u8,
+        algorithms: u16,
+        public_key_type: u8,
+        oob_type: u8,
+        output_oob_size: u8,
+        output_oob_action: u16,
+        input_oob_size: u8,
+        input_oob_action: u16,
+    },
+    Start {
+        algorithm: u8,
+        public_key: u8,
+        authentication_method: u8,
+        authentication_action: u8,
+        authentication_size: u8,
+    },
+    Failed {
+        error_code: u8,
+    },
+    /// the public keys, confirmations, random values, the encrypted provisioning data and the
+    /// records
+    Other {
+        pdu_type: u8,
+        params: &'a [u8],
+    },
+}
+
+impl<'a> TryFrom<&'a [u8]> for Provisioning<'a> {
+    type Error = io::Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let (pdu_type, mut params) = value.split_first().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "empty provisioning PDU")
+        })?;
+        Ok(match pdu_type & 0x3F {
+            0x00 => Provisioning::Invite {
+                attention_duration: params.read_u8()?,
+            },
+            0x01 => Provisioning::Capabilities {
+                elements: params.read_u8()?,
+                algorithms: params.read_u16::<BigEndian>()?,
+                public_key_type: params.read_u8()?,
+                oob_type: params.read_u8()?,
+                output_oob_size: params.read_u8()?,
+                output_oob_action: params.read_u16::<BigEndian>()?,
+                input_oob_size: params.read_u8()?,
+                input_oob_action: params.read_u16::<BigEndian>()?,
+            },
+            0x02 => Provisioning::Start {
+                algorithm: params.read_u8()?,
+                public_key: params.read_u8()?,
+                authentication_method: params.read_u8()?,
+                authentication_action: params.read_u8()?,
+                authentication_size: params.read_u8()?,
+            },
+            0x09 => Provisioning::Failed {
+                error_code: params.read_u8()?,
+            },
+            pdu_type => Provisioning::Other { pdu_type, params },
+        })
+    }
+}
+
+impl Provisioning<'_> {
+    pub fn pdu_type(&self) -> u8 {
+        match self {
+            Provisioning::Invite { .. } => 0x00,
+            Provisioning::Capabilities { .. } => 0x01,
+            Provisioning::Start { .. } => 0x02,
+            Provisioning::Failed { .. } => 0x09,
+            Provisioning::Other { pdu_type, .. } => *pdu_type,
+        }
+    }
+}
+
+impl Display for Provisioning<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(provisioning_pdu_name(self.pdu_type()).unwrap_or("Provisioning RFU"))?;
+        match self {
+            Provisioning::Invite { attention_duration } => {
+                write!(f, ": attention {} s", attention_duration)
+            }
+            Provisioning::Capabilities {
+                elements,
+                algorithms,
+                public_key_type,
+                oob_type,
+                output_oob_size,
+                output_oob_action,
+                input_oob_size,
+                input_oob_action,
+            } => write!(
+                f,
+                ": {} elements, algorithms 0x{:04x}, public key OOB {}, static OOB {}, \
+                 output OOB size {} actions 0x{:04x}, input OOB size {} actions 0x{:04x}",
+                elements,
+                algorithms,
+                public_key_type & 0x01 != 0,
+                oob_type & 0x01 != 0,
+                output_oob_size,
+                output_oob_action,
+                input_oob_size,
+                input_oob_action
+            ),
+            Provisioning::Start {
+                algorithm,
+                public_key,
+                authentication_method,
+                authentication_action,
+                authentication_size,
+            } => {
+                write!(
+                    f,
+                    ": {}, public key {}, {}",
+                    match algorithm {
+                        0x00 => "BTM_ECDH_P256_CMAC_AES128_AES_CCM",
+                        0x01 => "BTM_ECDH_P256_HMAC_SHA256_AES_CCM",
+                        _ => "RFU algorithm",
+                    },
+                    if *public_key == 0x01 {
+                        "OOB"
+                    } else {
+                        "in band"
+                    },
+                    match authentication_method {
+                        0x00 => "no OOB authentication",
+                        0x01 => "static OOB authentication",
+                        0x02 => "output OOB authentication",
+                        0x03 => "input OOB authentication",
+                        _ => "RFU authentication",
+                    }
+                )?;
+                if matches!(authentication_method, 0x02 | 0x03) {
+                    write!(
+                        f,
+                        " action {} size {}",
+                        authentication_action, authentication_size
+                    )?;
+                }
+                Ok(())
+            }
+            Provisioning::Failed { error_code } => write!(
+                f,
+                ": {} (0x{:02x})",
+                provisioning_error_name(*error_code).unwrap_or("RFU"),
+                error_code
+            ),
+            Provisioning::Other { params, .. } => write!(f, ", {} octets", params.len()),
+        }
+    }
+}
+
+/// A proxy configuration message, from the transport PDU of a network PDU once decrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyConfiguration {
+    /// 0x00 accept list, 0x01 reject list
+    SetFilterType(u8),
+    AddAddresses(Vec<u16>),
+    RemoveAddresses(Vec<u16>),
+    FilterStatus {
+        filter_type: u8,
+        list_size: u16,
+    },
+    Other {
+        opcode: u8,
+        params: Vec<u8>,
+    },
+}
+
+impl ProxyConfiguration {
+    pub fn parse(transport_pdu: &[u8]) -> io::Result<Self> {
+        let (opcode, mut params) = transport_pdu.split_first().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "empty proxy configuration")
+        })?;
+        let addresses = |params: &[u8]| {
+            params
+                .chunks_exact(2)
+                .map(|address| u16::from_be_bytes([address[0], address[1]]))
+                .collect()
+        };
+        Ok(match opcode {
+            0x00 => Self::SetFilterType(params.read_u8()?),
+            0x01 => Self::AddAddresses(addresses(params)),
+            0x02 => Self::RemoveAddresses(addresses(params)),
+            0x03 => Self::FilterStatus {
+                filter_type: params.read_u8()?,
+                list_size: params.read_u16::<BigEndian>()?,
+            },
+            _ => Self::Other {
+                opcode: *opcode,
+                params: params.to_vec(),
+            },
+        })
+    }
+}
+
+impl Display for ProxyConfiguration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let filter = |filter_type: u8| match filter_type {
+            0x00 => "accept list",
+            0x01 => "reject list",
+            _ => "RFU filter",
+        };
+        let list = |addresses: &[u16]| {
+            addresses
+                .iter()
+                .map(|address| format!("0x{:04x}", address))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self {
+            Self::SetFilterType(filter_type) => {
+                write!(f, "Set Filter Type: {}", filter(*filter_type))
+            }
+            Self::AddAddresses(addresses) => {
+                write!(f, "Add Addresses to Filter: {}", list(addresses))
+            }
+            Self::RemoveAddresses(addresses) => {
+                write!(f, "Remove Addresses from Filter: {}", list(addresses))
+            }
+            Self::FilterStatus {
+                filter_type,
+                list_size,
+            } => write!(
+                f,
+                "Filter Status: {}, {} addresses",
+                filter(*filter_type),
+                list_size
+            ),
+            Self::Other { opcode, params } => write!(
+                f,
+                "Proxy Configuration 0x{:02x}, {} octets",
+                opcode,
+                params.len()
+            ),
+        }
+    }
+}
+
+/// One line describing a complete proxy PDU, as far as it decodes without the network key
+pub fn describe(message_type: MessageType, data: &[u8]) -> String {
+    let decoded = match message_type {
+        MessageType::NetworkPdu | MessageType::ProxyConfiguration => NetworkPdu::try_from(data)
+            .map(|pdu| {
+                format!(
+                    "{}: IVI {} NID 0x{:02x}, {} octets encrypted",
+                    message_type,
+                    pdu.ivi,
+                    pdu.nid,
+                    pdu.encrypted.len()
+                )
+            }),
+        MessageType::MeshBeacon => Beacon::try_from(data).map(|beacon| beacon.to_string()),
+        MessageType::ProvisioningPdu => {
+            Provisioning::try_from(data).map(|provisioning| provisioning.to_string())
+        }
+        MessageType::Reserved(_) => Err(invalid("reserved message type")),
+    };
+    decoded.unwrap_or_else(|_| format!("{}, {} octets", message_type, data.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn proxy_pdus() {
+        let mut reassembler = Reassembler::default();
+        // a Provisioning Public Key in two segments
+        let first = [vec![0x43, 0x03], vec![0x11; 19]].concat();
+        let last = [vec![0xc3], vec![0x22; 45]].concat();
+        let pdu = ProxyPdu::try_from(&first[..]).unwrap();
+        assert_eq!(pdu.sar, Sar::First);
+        assert_eq!(pdu.message_type, MessageType::ProvisioningPdu);
+        assert!(reassembler.push(0x40, true, 0, &pdu).is_empty());
+        let messages = reassembler.push(0x40, true, 1, &ProxyPdu::try_from(&last[..]).unwrap());
+        assert_eq!(messages.len(), 1);
+        assert_eq!((messages[0].segments, messages[0].incomplete), (2, false));
+        assert_eq!(messages[0].data.len(), 65);
+        assert_eq!(
+            describe(messages[0].message_type, &messages[0].data),
+            "Provisioning Public Key, 64 octets"
+        );
+
+        // a first segment abandoned by a complete PDU
+        reassembler.push(0x40, false, 2, &ProxyPdu::try_from(&first[..]).unwrap());
+        let messages = reassembler.push(
+            0x40,
+            false,
+            3,
+            &ProxyPdu::try_from(&[0x03, 0x09, 0x04][..]).unwrap(),
+        );
+        assert!(messages[0].incomplete);
+        assert_eq!(
+            describe(messages[1].message_type, &messages[1].data),
+            "Provisioning Failed: Confirmation Failed (0x04)"
+        );
+
+        let beacon = [
+            [0x01, 0x02].as_slice(),
+            &[0xaa; 8],
+            &[0x00, 0x00, 0x00, 0x05],
+            &[0; 8],
+        ]
+        .concat();
+        assert_eq!(
+            describe(MessageType::MeshBeacon, &beacon),
+            "Secure Network Beacon: network ID aaaaaaaaaaaaaaaa IV index 0x00000005 IV update"
+        );
+        assert_eq!(
+            describe(MessageType::NetworkPdu, &[0xe8; 20]),
+            "Network PDU: IVI 1 NID 0x68, 19 octets encrypted"
+        );
+        assert_eq!(
+            ProxyConfiguration::parse(&[0x01, 0x00, 0x01, 0xc0, 0x00])
+                .unwrap()
+                .to_string(),
+            "Add Addresses to Filter: 0x0001 0xc000"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Options of the parsers, shared by [`Btsnoop::parse_with`], [`Reader::with_options`],
+//! [`Follower::set_options`](crate::follow::Follower::set_options) and
+//! [`formats::read_with`](crate::formats::read_with).
+//!
+//! The defaults parse like [`Btsnoop::parse`]: no limits, lenient, packets decoded only when
+//! analysed and their data kept.
+//!
+//! ```
+//! use btsnoop::{options::ParseOptions, Btsnoop};
+//!
+//! let options = ParseOptions::new()
+//!     .max_packets(1000)
+//!     .strict(true)
+//!     .keep_payloads(false);
+//! # let data: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+//! let capture = Btsnoop::parse_with(&mut &data[..], &options)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io;
+
+#[cfg(doc)]
+use crate::{formats::read_with, Btsnoop, Reader};
+use crate::{
+    parse_uart_packet, DatalinkType, Header, Packet, PacketDescription, UnsupportedVersion,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub(crate) max_packets: Option<usize>,
+    pub(crate) max_packet_length: Option<u32>,
+    pub(crate) strict: bool,
+    pub(crate) decode_hci: bool,
+    pub(crate) keep_payloads: bool,
+    pub(crate) validate_timestamps: bool,
+    pub(crate) allow_unsupported_version: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_packets: None,
+            max_packet_length: None,
+            strict: false,
+            decode_hci: false,
+            keep_payloads: true,
+            validate_timestamps: false,
+            allow_unsupported_version: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop after `max` packets, the rest of the capture is not read. A follower never stops
+    /// and ignores it. Pcapng files and btsnooz sections are still read whole, see
+    /// [`read_with`].
+    pub fn max_packets(mut self, max: usize) -> Self {
+        self.max_packets = Some(max);
+        self
+    }
+
+    /// Reject a record with an included length above `max` octets before reading its data,
+    /// e.g. a corrupt length in an untrusted capture
+    pub fn max_packet_length(mut self, max: u32) -> Self {
+        self.max_packet_length = Some(max);
+        self
+    }
+
+    /// In strict mode an unknown datalink type, an included length above the original length
+    /// and a truncated last record are errors. Lenient parsing accepts them
+    /// and ends at a truncated record, like a log cut while being written. A [`Reader`]
+    /// reports a truncated record in both modes.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Decode every packet of an HCI UART (H4) capture while parsing, failing on the first
+    /// one which does not decode instead of when it is analysed
+    pub fn decode_hci(mut self, decode: bool) -> Self {
+        self.decode_hci = decode;
+        self
+    }
+
+    /// Whether to keep the packet data, true by default. Without it only the records are
+    /// kept, enough for timing and length statistics of a large capture.
+    pub fn keep_payloads(mut self, keep: bool) -> Self {
+        self.keep_payloads = keep;
+        self
+    }
+
+    /// Reject a timestamp before the Unix epoch or earlier than the one of the previous packet
+    pub fn validate_timestamps(mut self, validate: bool) -> Self {
+        self.validate_timestamps = validate;
+        self
+    }
+
+    /// Parse a capture whose version is not [`Header::VERSION`] as if it was, best effort: the
+    /// records of a future or vendor variant of the format may not parse, or parse into
+    /// garbage. Without it such a capture fails with [`UnsupportedVersion`].
+    pub fn allow_unsupported_version(mut self, allow: bool) -> Self {
+        self.allow_unsupported_version = allow;
+        self
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The options applied to the packets of one capture, in order
+#[derive(Debug, Clone)]
+pub(crate) struct Checker {
+    options: ParseOptions,
+    uart: bool,
+    packets: usize,
+    previous_timestamp: Option<i64>,
+}
+
+impl Checker {
+    pub(crate) fn new(options: ParseOptions, header: &Header) -> io::Result<Self> {
+        if header.version != Header::VERSION && !options.allow_unsupported_version {
+            return Err(UnsupportedVersion(header.version).into());
+        }
+        if let (true, DatalinkType::Reserved(code) | DatalinkType::Unassigned(code)) =
+            (options.strict, header.datalink_type)
+        {
+            return Err(invalid(format!("unknown datalink type {}", code)));
+        }
+        Ok(Self {
+            uart: matches!(header.datalink_type, DatalinkType::Uart),
+            options,
+            packets: 0,
+            previous_timestamp: None,
+        })
+    }
+
+    pub(crate) fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Number of packets accepted, the index of the next one
+    #[cfg(feature = "tracing")]
+    pub(crate) fn packets(&self) -> usize {
+        self.packets
+    }
+
+    /// Whether the packet limit is reached
+    pub(crate) fn is_done(&self) -> bool {
+        self.options
+            .max_packets
+            .is_some_and(|max| self.packets >= max)
+    }
+
+    /// Check of the included length of a record of any format, before its data is read
+    pub(crate) fn check_length(&self, included_length: u32) -> io::Result<()> {
+        match self.options.max_packet_length {
+            Some(max) if included_length > max => Err(invalid(format!(
+                "packet {}: included length {} above the limit of {}",
+                self.packets, included_length, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks of a record before its data is read
+    pub(crate) fn check_description(&self, description: &PacketDescription) -> io::Result<()> {
+        let index = self.packets;
+        self.check_length(description.included_length)?;
+        if self.options.strict && description.included_length > description.original_length {
+            return Err(invalid(format!(
+                "packet {}: included length {} above the original length {}",
+                index, description.included_length, description.original_length
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks of a whole packet, its data is dropped here unless kept
+    pub(crate) fn accept(&mut self, packet: &mut Packet) -> io::Result<()> {
+        let index = self.packets;
+        let timestamp = packet.description.timestamp;
+        if self.options.validate_timestamps {
+            if timestamp < PacketDescription::UNIX_EPOCH_OFFSET {
+                return Err(invalid(format!(
+                    "packet {}: timestamp before the Unix epoch",
+                    index
+                )));
+            }
+            if self.previous_timestamp.is_some_and(|t| timestamp < t) {
+                return Err(invalid(format!(
+                    "packet {}: timestamp earlier than the previous packet",
+                    index
+                )));
+            }
+        }
+        if self.options.decode_hci && self.uart {
+            parse_uart_packet(packet).map_err(|e| invalid(format!("packet {}: {}", index, e)))?;
+        }
+        if !self.options.keep_payloads {
+            packet.data.0 = Vec::new();
+        }
+        self.previous_timestamp = Some(timestamp);
+        self.packets += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{formats, Btsnoop, Reader};
+
+    #[test]
+    fn options() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let all = Btsnoop::parse(&mut &original[..]).unwrap();
+        assert_eq!(
+            Btsnoop::parse_with(&mut &original[..], &ParseOptions::new()).unwrap(),
+            all
+        );
+
+        let options = ParseOptions::new().max_packets(3).keep_payloads(false);
+        let capture = Btsnoop::parse_with(&mut &original[..], &options).unwrap();
+        assert_eq!(capture.packets.len(), 3);
+        assert!(capture.packets[0].data.0.is_empty());
+        assert_eq!(capture.packets[0].description, all.packets[0].description);
+        let reader = Reader::with_options(original, options.clone()).unwrap();
+        assert_eq!(reader.count(), 3);
+
+        let truncated = &original[..original.len() - 1];
+        assert_eq!(
+            Btsnoop::parse_from_slice(truncated).unwrap().packets.len(),
+            all.packets.len() - 1
+        );
+        let strict = ParseOptions::new().strict(true);
+        assert!(Btsnoop::parse_with(&mut &truncated[..], &strict).is_err());
+        assert!(Btsnoop::parse_with(&mut &original[..], &strict).is_ok());
+
+        let limited = ParseOptions::new().max_packet_length(4);
+        assert!(Btsnoop::parse_with(&mut &original[..], &limited).is_err());
+
+        // an unknown H4 packet type
+        let mut corrupt = all.clone();
+        corrupt.packets[1].data.0[0] = 0x07;
+        let mut written = vec![];
+        corrupt.write(&mut written).unwrap();
+        assert!(Btsnoop::parse_from_slice(&written).is_ok());
+        let decode = ParseOptions::new().decode_hci(true);
+        assert!(Btsnoop::parse_with(&mut &written[..], &decode).is_err());
+
+        let mut backwards = all.clone();
+        backwards.packets[2].description.timestamp -= 1_000_000;
+        let validate = ParseOptions::new().validate_timestamps(true);
+        let mut written = vec![];
+        backwards.write(&mut written).unwrap();
+        assert!(Btsnoop::parse_with(&mut &original[..], &validate).is_ok());
+        let error = Btsnoop::parse_with(&mut &written[..], &validate).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "packet 2: timestamp earlier than the previous packet"
+        );
+
+        let mut pcap = vec![];
+        formats::pcap::write(&all, formats::pcap::LinkType::H4WithPhdr, &mut pcap).unwrap();
+        let capture = formats::read_with(&mut &pcap[..], formats::Format::Pcap, &options).unwrap();
+        assert_eq!(capture.packets.len(), 3);
+        assert!(capture.packets[0].data.0.is_empty());
+        assert!(formats::read_with(&mut &pcap[..], formats::Format::Pcap, &limited).is_err());
+
+        // a future version fails unless allowed, and is written back as version 1
+        let mut future = original.to_vec();
+        future[8..12].copy_from_slice(&2u32.to_be_bytes());
+        let error = Btsnoop::parse_from_slice(&future).unwrap_err();
+        assert_eq!(UnsupportedVersion::of(&error), Some(UnsupportedVersion(2)));
+        assert!(Reader::new(&future[..]).is_err());
+        let allow = ParseOptions::new().allow_unsupported_version(true);
+        let capture = Btsnoop::parse_with(&mut &future[..], &allow).unwrap();
+        assert_eq!(capture.header.version, 2);
+        assert_eq!(capture.packets, all.packets);
+        let mut written = vec![];
+        capture.write(&mut written).unwrap();
+        assert_eq!(written, original);
+    }
+}
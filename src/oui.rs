@@ -0,0 +1,201 @@
+//! Manufacturer of public device addresses from the IEEE registry of organizationally unique
+//! identifiers (OUI), the three most significant octets of the address.
+//!
+//! The built-in table only has vendors of common Bluetooth adapters, modules and boards; the
+//! whole registry, `oui.txt` or `oui.csv` from <https://standards-oui.ieee.org>, is loaded into
+//! an [`OuiTable`]. Random addresses have no OUI.
+//!
+//! ```
+//! use btsnoop::{hci::BdAddr, oui};
+//!
+//! let address = BdAddr([0x13, 0x71, 0xda, 0x7d, 0x1a, 0x00]);
+//! assert_eq!(oui::manufacturer(&address, 0x00), Some("cyber-blue(HK)Ltd"));
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::hci::{AddressKind, BdAddr};
+
+/// sorted by OUI
+const BUILTIN: &[([u8; 3], &str)] = &[
+    ([0x00, 0x02, 0x5B], "Cambridge Silicon Radio"),
+    ([0x00, 0x03, 0x7F], "Atheros Communications"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x0B, 0x57], "Silicon Laboratories"),
+    ([0x00, 0x0E, 0x6D], "Murata Manufacturing"),
+    ([0x00, 0x10, 0x18], "Broadcom"),
+    ([0x00, 0x17, 0xF2], "Apple"),
+    ([0x00, 0x1A, 0x7D], "cyber-blue(HK)Ltd"),
+    ([0x00, 0x1B, 0x63], "Apple"),
+    ([0x00, 0x25, 0xD3], "AzureWave Technology"),
+    ([0x00, 0x50, 0xF2], "Microsoft"),
+    ([0x00, 0xE0, 0x4C], "Realtek Semiconductor"),
+    ([0x24, 0x0A, 0xC4], "Espressif"),
+    ([0x24, 0x6F, 0x28], "Espressif"),
+    ([0x30, 0xAE, 0xA4], "Espressif"),
+    ([0x3C, 0x5A, 0xB4], "Google"),
+    ([0xB0, 0xB4, 0x48], "Texas Instruments"),
+    ([0xB8, 0x27, 0xEB], "Raspberry Pi Foundation"),
+    ([0xDC, 0xA6, 0x32], "Raspberry Pi Trading"),
+    ([0xE4, 0x5F, 0x01], "Raspberry Pi Trading"),
+];
+
+/// The manufacturer of a public address in the built-in table, none for random addresses
+pub fn manufacturer(address: &BdAddr, address_type: u8) -> Option<&'static str> {
+    if address.kind(address_type) != AddressKind::Public {
+        return None;
+    }
+    BUILTIN
+        .binary_search_by_key(&address.oui(), |(oui, _)| *oui)
+        .ok()
+        .map(|index| BUILTIN[index].1)
+}
+
+fn parse_oui(hex: &str) -> Option<[u8; 3]> {
+    let hex: String = hex.chars().filter(|c| *c != '-' && *c != ':').collect();
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let mut oui = [0; 3];
+    for (i, octet) in oui.iter_mut().enumerate() {
+        *octet = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(oui)
+}
+
+/// The first field of a CSV line and the rest of it, the field unquoted
+fn csv_field(line: &str) -> (String, &str) {
+    let Some(quoted) = line.strip_prefix('"') else {
+        return match line.split_once(',') {
+            Some((field, rest)) => (field.to_string(), rest),
+            None => (line.to_string(), ""),
+        };
+    };
+    let mut field = String::new();
+    let mut chars = quoted.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if chars.peek().map(|(_, c)| *c) == Some('"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => {
+                let rest = &quoted[i + 1..];
+                return (field, rest.strip_prefix(',').unwrap_or(rest));
+            }
+            c => field.push(c),
+        }
+    }
+    (field, "")
+}
+
+/// Manufacturers by OUI, the built-in ones and the ones loaded from the IEEE registry
+#[derive(Debug, Clone, Default)]
+pub struct OuiTable {
+    manufacturers: HashMap<[u8; 3], String>,
+}
+
+impl OuiTable {
+    /// The built-in table
+    pub fn new() -> Self {
+        let mut table = Self::default();
+        for (oui, name) in BUILTIN {
+            table.insert(*oui, name);
+        }
+        table
+    }
+
+    pub fn insert(&mut self, oui: [u8; 3], manufacturer: &str) {
+        self.manufacturers.insert(oui, manufacturer.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.manufacturers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manufacturers.is_empty()
+    }
+
+    /// Add the assignments of the IEEE registry file at `path`
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        self.read(BufReader::new(File::open(path)?))
+    }
+
+    /// Add the assignments of the IEEE registry as `oui.txt`, its `XX-XX-XX (hex)` lines, or as
+    /// `oui.csv`, returning how many were read
+    pub fn read<R: BufRead>(&mut self, reader: R) -> io::Result<usize> {
+        let mut count = 0;
+        for line in reader.lines() {
+            let line = line?;
+            let assignment = match line.split_once("(hex)") {
+                Some((oui, name)) => parse_oui(oui.trim()).map(|oui| (oui, name.to_string())),
+                None => {
+                    // Registry,Assignment,Organization Name,Organization Address
+                    let (_, rest) = csv_field(&line);
+                    let (oui, rest) = csv_field(rest);
+                    parse_oui(&oui).map(|oui| (oui, csv_field(rest).0))
+                }
+            };
+            if let Some((oui, name)) = assignment {
+                self.insert(oui, name.trim());
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// The manufacturer of a public address, none for random addresses
+    pub fn lookup(&self, address: &BdAddr, address_type: u8) -> Option<&str> {
+        if address.kind(address_type) != AddressKind::Public {
+            return None;
+        }
+        self.manufacturers.get(&address.oui()).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        assert!(BUILTIN.windows(2).all(|w| w[0].0 < w[1].0));
+        let apple = BdAddr([0x01, 0x02, 0x03, 0x93, 0x03, 0x00]);
+        assert_eq!(manufacturer(&apple, 0x00), Some("Apple"));
+        assert_eq!(manufacturer(&apple, 0x02), Some("Apple"));
+        // random
+        assert_eq!(manufacturer(&apple, 0x01), None);
+
+        let static_random = BdAddr([0x01, 0x02, 0x03, 0x04, 0x05, 0xc6]);
+        assert_eq!(static_random.kind(0x01), AddressKind::RandomStatic);
+        assert_eq!(
+            BdAddr([0, 0, 0, 0, 0, 0x4f]).kind(0x01),
+            AddressKind::ResolvablePrivate
+        );
+        assert_eq!(
+            BdAddr([0, 0, 0, 0, 0, 0x3f]).kind(0x01),
+            AddressKind::NonResolvablePrivate
+        );
+
+        let mut table = OuiTable::default();
+        let registry = "\
+Registry,Assignment,Organization Name,Organization Address
+MA-L,002258,\"Example Co., Ltd.\",\"1 Example Street\"
+00-03-93   (hex)\t\tApple, Inc.
+000393     (base 16)\t\tApple, Inc.
+";
+        assert_eq!(table.read(registry.as_bytes()).unwrap(), 2);
+        assert_eq!(table.lookup(&apple, 0x00), Some("Apple, Inc."));
+        assert_eq!(
+            table.lookup(&BdAddr([0, 0, 0, 0x58, 0x22, 0x00]), 0x00),
+            Some("Example Co., Ltd.")
+        );
+        assert_eq!(OuiTable::new().len(), BUILTIN.len());
+    }
+}
@@ -0,0 +1,205 @@
+//! Playback of the packets of a capture with their original timing, e.g. to drive a
+//! hardware-in-the-loop simulation: each packet is yielded, or written to a sink, when it is due
+//! after the first one, the gaps between the timestamps divided by a speed multiplier.
+//!
+//! ```no_run
+//! use std::{net::TcpStream, time::Duration};
+//! use btsnoop::{playback::{write_paced, PlaybackOptions}, Btsnoop, DirectionFlag};
+//!
+//! let capture = Btsnoop::parse_from_slice(&std::fs::read("btsnoop_hci.log")?)?;
+//! // what the controller sent, twice as fast, without waiting more than a second
+//! let options = PlaybackOptions::new()
+//!     .speed(2.0)
+//!     .max_gap(Duration::from_secs(1))
+//!     .direction(DirectionFlag::Received);
+//! let mut socket = TcpStream::connect("127.0.0.1:9000")?;
+//! write_paced(&capture.packets, &options, &mut socket)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    borrow::Borrow,
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{DirectionFlag, Packet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackOptions {
+    speed: f64,
+    max_gap: Option<Duration>,
+    direction: Option<DirectionFlag>,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            max_gap: None,
+            direction: None,
+        }
+    }
+}
+
+impl PlaybackOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Multiplier of the original pace, 1 by default. Infinity, or a speed which isn't
+    /// positive, plays without waiting.
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Shorten the idle gaps longer than `max` in the capture to `max`, before the speed
+    /// applies
+    pub fn max_gap(mut self, max: Duration) -> Self {
+        self.max_gap = Some(max);
+        self
+    }
+
+    /// Only play the packets of one direction, when they are due after the first packet of
+    /// the capture
+    pub fn direction(mut self, direction: DirectionFlag) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// The wait for a gap of `gap` microseconds between two timestamps of the capture, 0 for
+    /// timestamps going backwards, saturated when a very slow speed makes it too long
+    fn wait(&self, gap: i64) -> Duration {
+        let gap = Duration::from_micros(gap.max(0) as u64);
+        let gap = self.max_gap.map_or(gap, |max| gap.min(max));
+        if self.speed > 0.0 && self.speed.is_finite() {
+            Duration::try_from_secs_f64(gap.as_secs_f64() / self.speed).unwrap_or(Duration::MAX)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Yields the packets of an iterator when they are due, sleeping the thread in between
+#[derive(Debug)]
+pub struct Playback<I> {
+    packets: I,
+    options: PlaybackOptions,
+    /// when the first packet was played
+    start: Option<Instant>,
+    previous_timestamp: Option<i64>,
+    due: Duration,
+}
+
+impl<I> Playback<I> {
+    pub fn new<P: IntoIterator<IntoIter = I>>(packets: P, options: PlaybackOptions) -> Self {
+        Self {
+            packets: packets.into_iter(),
+            options,
+            start: None,
+            previous_timestamp: None,
+            due: Duration::ZERO,
+        }
+    }
+
+    /// When the last packet yielded was due, after the first one
+    pub fn due(&self) -> Duration {
+        self.due
+    }
+}
+
+impl<I: Iterator> Iterator for Playback<I>
+where
+    I::Item: Borrow<Packet>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.packets.next()?;
+            let packet = item.borrow();
+            let start = *self.start.get_or_insert_with(Instant::now);
+            let timestamp = packet.description.timestamp;
+            if let Some(previous) = self.previous_timestamp {
+                let wait = self.options.wait(timestamp.saturating_sub(previous));
+                self.due = self.due.saturating_add(wait);
+            }
+            self.previous_timestamp = Some(timestamp);
+            if self
+                .options
+                .direction
+                .is_some_and(|direction| packet.description.flags.direction() != direction)
+            {
+                continue;
+            }
+            if let Some(wait) = self.due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+            return Some(item);
+        }
+    }
+}
+
+/// Write the data of each packet to `writer` when it is due, flushed, e.g. the H4 packets of
+/// an HCI UART capture to a serial port or a socket. Returns the number of packets written.
+pub fn write_paced<P, W>(packets: P, options: &PlaybackOptions, writer: &mut W) -> io::Result<usize>
+where
+    P: IntoIterator,
+    P::Item: Borrow<Packet>,
+    W: Write,
+{
+    let mut written = 0;
+    for packet in Playback::new(packets, options.clone()) {
+        writer.write_all(&packet.borrow().data.0)?;
+        writer.flush()?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::h4;
+
+    #[test]
+    fn paced() {
+        // 1 s, an idle minute, 1 s
+        let capture = h4(vec![
+            (0, false, vec![0x01, 0x03, 0x0c, 0x00]),
+            (1_000_000, true, vec![0x04, 0x0e, 0x01, 0x00]),
+            (61_000_000, false, vec![0x01, 0x03, 0x0c, 0x00]),
+            (62_000_000, true, vec![0x04, 0x0e, 0x01, 0x00]),
+        ]);
+        let options = PlaybackOptions::new()
+            .speed(1000.0)
+            .max_gap(Duration::from_secs(5));
+        let start = Instant::now();
+        let mut playback = Playback::new(&capture.packets, options.clone());
+        let mut due = vec![];
+        while playback.next().is_some() {
+            due.push(playback.due());
+        }
+        assert_eq!(due, [0, 1, 6, 7].map(Duration::from_millis));
+        assert!(start.elapsed() >= Duration::from_millis(7));
+
+        let received = options.direction(DirectionFlag::Received);
+        let mut playback = Playback::new(&capture.packets, received.clone());
+        assert!(playback.next().unwrap().description.flags.is_received());
+        assert_eq!(playback.due(), Duration::from_millis(1));
+
+        let mut sink = vec![];
+        let fastest = received.speed(f64::INFINITY);
+        assert_eq!(
+            write_paced(&capture.packets, &fastest, &mut sink).unwrap(),
+            2
+        );
+        assert_eq!(sink, [[0x04, 0x0e, 0x01, 0x00]; 2].concat());
+
+        let slowest = PlaybackOptions::new().speed(1e-20);
+        assert_eq!(slowest.wait(1_000_000), Duration::MAX);
+        assert_eq!(slowest.wait(-1), Duration::ZERO);
+    }
+}
@@ -0,0 +1,388 @@
+//! Dissectors loaded from shared libraries at runtime, to decode proprietary protocols without
+//! recompiling.
+//!
+//! A plugin exports `btsnoop_plugin_v1`, a C function returning a [`PluginDescriptor`] of
+//! version [`ABI_VERSION`] which lists its dissectors and the keys they are registered for. A
+//! dissector adds the nodes of its tree with the callback of a [`PluginTree`] and returns the
+//! number of octets of the payload it decoded, the rest is dumped in hex, or a negative number
+//! when the payload does not decode. A later version of the ABI is exported under another
+//! symbol, so a plugin can support several.
+//!
+//! A plugin written in Rust is a `cdylib` crate:
+//!
+//! ```no_run
+//! use btsnoop::plugin::*;
+//!
+//! unsafe extern "C" fn dissect(
+//!     _: *const PluginContext,
+//!     payload: *const u8,
+//!     len: usize,
+//!     tree: *mut PluginTree,
+//! ) -> isize {
+//!     if len < 1 {
+//!         return -1;
+//!     }
+//!     let label = format!("Vendor opcode: 0x{:02x}", *payload);
+//!     ((*tree).add)((*tree).state, 0, label.as_ptr(), label.len());
+//!     1
+//! }
+//!
+//! static DISSECTORS: [PluginDissector; 1] = [PluginDissector {
+//!     key_kind: PluginDissector::KEY_OPCODE,
+//!     key_value: 0xfc01,
+//!     uuid: [0; 16],
+//!     name: c"acme-vendor".as_ptr(),
+//!     dissect,
+//! }];
+//! static DESCRIPTOR: PluginDescriptor = PluginDescriptor {
+//!     abi_version: ABI_VERSION,
+//!     name: c"acme".as_ptr(),
+//!     dissectors: DISSECTORS.as_ptr(),
+//!     dissector_count: 1,
+//! };
+//!
+//! #[no_mangle]
+//! pub extern "C" fn btsnoop_plugin_v1() -> *const PluginDescriptor {
+//!     &DESCRIPTOR
+//! }
+//! ```
+//!
+//! Loading it into a registry:
+//!
+//! ```no_run
+//! use btsnoop::{dissect::Registry, plugin::Plugin};
+//!
+//! let mut registry = Registry::with_builtins();
+//! // Safety: the plugin is trusted, loading it runs its code
+//! let plugin = unsafe { Plugin::load("libacme.so")? };
+//! plugin.register(&mut registry);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    ffi::{c_char, c_void, CStr, OsStr},
+    io, slice,
+    sync::Arc,
+};
+
+use libloading::Library;
+
+use crate::{
+    dissect::{Context, Dissection, Dissector, Key, Node, Registry},
+    gatt::Uuid,
+    DirectionFlag,
+};
+
+/// The version of the ABI of `btsnoop_plugin_v1`
+pub const ABI_VERSION: u32 = 1;
+
+/// The symbol exported by a plugin, NUL terminated
+pub const ENTRY_POINT: &[u8] = b"btsnoop_plugin_v1\0";
+
+/// What a plugin provides, returned by its `btsnoop_plugin_v1` function. It must stay valid
+/// while the plugin is loaded.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginDescriptor {
+    /// [`ABI_VERSION`]
+    pub abi_version: u32,
+    /// NUL terminated
+    pub name: *const c_char,
+    pub dissectors: *const PluginDissector,
+    pub dissector_count: usize,
+}
+
+/// A dissector of a plugin and what it is registered for
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginDissector {
+    /// one of the `KEY_` constants
+    pub key_kind: u8,
+    /// the opcode, event code, CID, PSM or attribute handle
+    pub key_value: u16,
+    /// the GATT type for [`Self::KEY_GATT`], in little endian as in ATT PDUs
+    pub uuid: [u8; 16],
+    /// NUL terminated
+    pub name: *const c_char,
+    pub dissect: DissectFn,
+}
+
+// Safety: the descriptors and the strings and dissectors they point to are immutable, so
+// plugins can declare them as statics
+unsafe impl Sync for PluginDescriptor {}
+unsafe impl Sync for PluginDissector {}
+
+impl PluginDissector {
+    pub const KEY_OPCODE: u8 = 1;
+    pub const KEY_EVENT: u8 = 2;
+    pub const KEY_CID: u8 = 3;
+    pub const KEY_PSM: u8 = 4;
+    pub const KEY_ATTRIBUTE: u8 = 5;
+    pub const KEY_GATT: u8 = 6;
+}
+
+/// Decode `len` octets at `payload` into `tree`. Returns the number of octets decoded, or a
+/// negative number when the payload does not decode. It may be called from several threads.
+pub type DissectFn = unsafe extern "C" fn(
+    context: *const PluginContext,
+    payload: *const u8,
+    len: usize,
+    tree: *mut PluginTree,
+) -> isize;
+
+/// [`Context`] for plugins, the fields not known are -1
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginContext {
+    /// 0 sent by the Host, 1 received
+    pub received: u8,
+    pub packet_index: usize,
+    pub event: i32,
+    pub opcode: i32,
+    pub connection: i32,
+    pub channel_id: i32,
+    pub psm: i32,
+    pub attribute: i32,
+}
+
+impl From<&Context> for PluginContext {
+    fn from(context: &Context) -> Self {
+        let field = |value: Option<u16>| value.map_or(-1, i32::from);
+        PluginContext {
+            received: (context.direction == DirectionFlag::Received) as u8,
+            packet_index: context.packet_index,
+            event: field(context.event.map(u16::from)),
+            opcode: field(context.opcode),
+            connection: field(context.connection),
+            channel_id: field(context.channel_id),
+            psm: field(context.psm),
+            attribute: field(context.attribute),
+        }
+    }
+}
+
+/// The tree a plugin dissector adds its nodes to
+#[repr(C)]
+pub struct PluginTree {
+    pub state: *mut c_void,
+    /// Add a node of `len` octets of UTF-8 at `label`, as the last child of the last node
+    /// added at `depth - 1`, or at the root for depth 0
+    pub add: unsafe extern "C" fn(state: *mut c_void, depth: u32, label: *const u8, len: usize),
+}
+
+unsafe extern "C" fn add_node(state: *mut c_void, depth: u32, label: *const u8, len: usize) {
+    let tree = &mut *(state as *mut Vec<Node>);
+    let label = match label.is_null() {
+        true => String::new(),
+        false => String::from_utf8_lossy(slice::from_raw_parts(label, len)).into_owned(),
+    };
+    let mut nodes = tree;
+    for _ in 0..depth {
+        if nodes.is_empty() {
+            break;
+        }
+        let last = nodes.len() - 1;
+        nodes = &mut nodes[last].children;
+    }
+    nodes.push(Node::new(label));
+}
+
+/// A loaded plugin, its dissectors keep the library loaded while registered
+#[derive(Debug)]
+pub struct Plugin {
+    name: String,
+    dissectors: Vec<(Key, Arc<PluginDissectorFn>)>,
+}
+
+#[derive(Debug)]
+struct PluginDissectorFn {
+    name: String,
+    dissect: DissectFn,
+    _library: Option<Arc<Library>>,
+}
+
+impl Dissector for PluginDissectorFn {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dissect<'a>(&self, context: &Context, payload: &'a [u8]) -> io::Result<Dissection<'a>> {
+        let context = PluginContext::from(context);
+        let mut nodes: Vec<Node> = vec![];
+        let mut tree = PluginTree {
+            state: &mut nodes as *mut Vec<Node> as *mut c_void,
+            add: add_node,
+        };
+        // Safety: the library is loaded while self exists, the payload and tree outlive the call
+        let decoded =
+            unsafe { (self.dissect)(&context, payload.as_ptr(), payload.len(), &mut tree) };
+        if decoded < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: payload not decoded", self.name),
+            ));
+        }
+        let mut dissection = Dissection::new(nodes);
+        dissection.remaining = payload.get(decoded as usize..).unwrap_or_default();
+        Ok(dissection)
+    }
+}
+
+/// # Safety
+///
+/// `name` must be NULL or NUL terminated.
+unsafe fn string(name: *const c_char) -> String {
+    if name.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(name).to_string_lossy().into_owned()
+}
+
+impl Plugin {
+    /// Load the plugin at `path`, a `.so`, `.dylib` or `.dll`
+    ///
+    /// # Safety
+    ///
+    /// Loading the library runs its initialization code and its dissectors are trusted to
+    /// follow the ABI, like any native code.
+    pub unsafe fn load(path: impl AsRef<OsStr>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let library = Library::new(path).map_err(io::Error::other)?;
+        let entry = library
+            .get::<unsafe extern "C" fn() -> *const PluginDescriptor>(ENTRY_POINT)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: not a btsnoop plugin: {}", path.to_string_lossy(), e),
+                )
+            })?;
+        let descriptor = entry();
+        Self::from_descriptor(descriptor, Some(Arc::new(library)))
+    }
+
+    /// # Safety
+    ///
+    /// `descriptor` must be NULL or point to a descriptor valid while `library` is loaded.
+    unsafe fn from_descriptor(
+        descriptor: *const PluginDescriptor,
+        library: Option<Arc<Library>>,
+    ) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let descriptor = descriptor
+            .as_ref()
+            .ok_or_else(|| invalid("no plugin descriptor".to_string()))?;
+        if descriptor.abi_version != ABI_VERSION {
+            return Err(invalid(format!(
+                "plugin ABI version {} instead of {}",
+                descriptor.abi_version, ABI_VERSION
+            )));
+        }
+        let name = string(descriptor.name);
+        let entries = match descriptor.dissectors.is_null() {
+            true => &[][..],
+            false => slice::from_raw_parts(descriptor.dissectors, descriptor.dissector_count),
+        };
+        let mut dissectors = vec![];
+        for entry in entries {
+            let key = match entry.key_kind {
+                PluginDissector::KEY_OPCODE => Key::Opcode(entry.key_value),
+                PluginDissector::KEY_EVENT => Key::Event(entry.key_value as u8),
+                PluginDissector::KEY_CID => Key::Cid(entry.key_value),
+                PluginDissector::KEY_PSM => Key::Psm(entry.key_value),
+                PluginDissector::KEY_ATTRIBUTE => Key::Attribute(entry.key_value),
+                PluginDissector::KEY_GATT => Key::Gatt(Uuid(u128::from_le_bytes(entry.uuid))),
+                kind => return Err(invalid(format!("{}: unknown key kind {}", name, kind))),
+            };
+            let dissector = PluginDissectorFn {
+                name: string(entry.name),
+                dissect: entry.dissect,
+                _library: library.clone(),
+            };
+            dissectors.push((key, Arc::new(dissector)));
+        }
+        Ok(Plugin { name, dissectors })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The keys the dissectors of the plugin are registered for
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.dissectors.iter().map(|(key, _)| *key)
+    }
+
+    /// Register the dissectors, replacing the ones registered for the same keys
+    pub fn register(&self, registry: &mut Registry) {
+        for (key, dissector) in &self.dissectors {
+            registry.register(*key, dissector.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture;
+
+    unsafe extern "C" fn vendor(
+        context: *const PluginContext,
+        payload: *const u8,
+        len: usize,
+        tree: *mut PluginTree,
+    ) -> isize {
+        if len < 2 || (*context).opcode != 0xfc01 {
+            return -1;
+        }
+        let data = slice::from_raw_parts(payload, len);
+        for (depth, label) in [(0, format!("Channel: {}", data[0])), (1, "Enabled".into())] {
+            ((*tree).add)((*tree).state, depth, label.as_ptr(), label.len());
+        }
+        1
+    }
+
+    #[test]
+    fn plugin_descriptor() {
+        let dissectors = [PluginDissector {
+            key_kind: PluginDissector::KEY_OPCODE,
+            key_value: 0xfc01,
+            uuid: [0; 16],
+            name: c"vendor".as_ptr(),
+            dissect: vendor,
+        }];
+        let mut descriptor = PluginDescriptor {
+            abi_version: ABI_VERSION,
+            name: c"acme".as_ptr(),
+            dissectors: dissectors.as_ptr(),
+            dissector_count: dissectors.len(),
+        };
+        let plugin = unsafe { Plugin::from_descriptor(&descriptor, None) }.unwrap();
+        assert_eq!(plugin.name(), "acme");
+        assert_eq!(plugin.keys().collect::<Vec<_>>(), [Key::Opcode(0xfc01)]);
+
+        let mut registry = Registry::with_builtins();
+        plugin.register(&mut registry);
+        let capture = test_capture::h4(vec![
+            (0, false, test_capture::command(0x3f, 0x01, &[0x07, 0xaa])),
+            (1, false, test_capture::command(0x3f, 0x01, &[])),
+        ]);
+        let mut session = registry.session();
+        let lines: Vec<_> = capture
+            .iter()
+            .map(|packet| crate::dissect::lines(&session.dissect(packet)))
+            .collect();
+        assert_eq!(
+            lines[0],
+            [
+                "Channel: 7",
+                "  Enabled",
+                "0000  aa                                               ."
+            ]
+        );
+        assert!(lines[1].is_empty());
+
+        descriptor.abi_version = 2;
+        assert!(unsafe { Plugin::from_descriptor(&descriptor, None) }.is_err());
+        assert!(unsafe { Plugin::load("/nonexistent/libplugin.so") }.is_err());
+    }
+}
@@ -0,0 +1,751 @@
+//! Human readable summary of a capture and the findings of the [analysis](crate::analysis) passes,
+//! rendered as Markdown or as a self-contained HTML page.
+
+use std::fmt::Write;
+
+use crate::{
+    analysis::{
+        advertisers::{scan_report, AdvertiserId, ScanConfig},
+        command_errors::command_errors,
+        connection_interval::{check_connection_intervals, ComplianceConfig},
+        data_stall::{data_stalls, StallConfig},
+        health_check::health_check,
+        pairing::{classic_pairing_audit, failure_reason_name, key_type_name, pairing_audit},
+        rtp::{validate_rtp_streams, RtpConfig},
+        statistics::{packet_type_name, statistics, Count},
+    },
+    hci::{
+        error_code_name, event_code_name, opcode_name, AddressKind, BdAddr, LeConnectionComplete,
+        LeMetaEvent,
+    },
+    Btsnoop, PacketDescription,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug)]
+struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug)]
+enum Block {
+    Paragraph(String),
+    Table(Table),
+}
+
+#[derive(Debug)]
+struct Section {
+    title: &'static str,
+    blocks: Vec<Block>,
+}
+
+impl Section {
+    fn new(title: &'static str) -> Self {
+        Self {
+            title,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn paragraph(&mut self, text: impl Into<String>) {
+        self.blocks.push(Block::Paragraph(text.into()));
+    }
+
+    /// Add a table, or `empty` as paragraph when there are no rows.
+    fn table(&mut self, headers: Vec<&'static str>, rows: Vec<Vec<String>>, empty: &str) {
+        if rows.is_empty() {
+            self.paragraph(empty);
+        } else {
+            self.blocks.push(Block::Table(Table { headers, rows }));
+        }
+    }
+}
+
+/// Report of a capture: capture information, traffic statistics, LE connections, the security audit
+/// of the LE and BR/EDR pairings, advertisers and the findings of the health check, command error, connection
+/// interval, data stall and RTP analyses, all run with their default configuration.
+#[derive(Debug)]
+pub struct Report {
+    pub title: String,
+    sections: Vec<Section>,
+}
+
+impl Report {
+    pub fn new(capture: &Btsnoop) -> Self {
+        let statistics = statistics(capture);
+        let start = statistics.first_timestamp.unwrap_or_default();
+        let end = statistics.last_timestamp.unwrap_or_default();
+        let relative = |timestamp: i64| format_relative(timestamp.saturating_sub(start));
+
+        let mut sections = Vec::new();
+
+        let mut section = Section::new("Capture");
+        let mut rows = vec![
+            vec!["Version".to_string(), capture.header.version.to_string()],
+            vec![
+                "Datalink".to_string(),
+                capture.header.datalink_type.to_string(),
+            ],
+            vec!["Packets".to_string(), statistics.packets.to_string()],
+            vec!["Bytes".to_string(), statistics.bytes.to_string()],
+        ];
+        if statistics.first_timestamp.is_some() {
+            rows.push(vec!["Start".to_string(), format_utc(start)]);
+            rows.push(vec!["End".to_string(), format_utc(end)]);
+            rows.push(vec![
+                "Duration".to_string(),
+                format_seconds(statistics.duration_us()),
+            ]);
+        }
+        rows.push(vec![
+            "Truncated packets".to_string(),
+            statistics.truncated.to_string(),
+        ]);
+        rows.push(vec![
+            "Dropped packets".to_string(),
+            statistics.dropped.to_string(),
+        ]);
+        section.table(vec!["", ""], rows, "");
+        sections.push(section);
+
+        let count_row = |name: String, count: &Count| {
+            vec![
+                name,
+                count.sent.to_string(),
+                count.received.to_string(),
+                count.bytes.to_string(),
+            ]
+        };
+        let mut section = Section::new("Statistics");
+        let rows = statistics
+            .packet_types
+            .iter()
+            .map(|(packet_type, count)| {
+                count_row(packet_type_name(*packet_type).to_string(), count)
+            })
+            .collect();
+        section.table(
+            vec!["Packet type", "Sent", "Received", "Bytes"],
+            rows,
+            "No packets.",
+        );
+        let rows = statistics
+            .commands
+            .iter()
+            .map(|(opcode, count)| {
+                let name = opcode_name(*opcode).unwrap_or("Unknown");
+                count_row(format!("{} (0x{:04X})", name, opcode), count)
+            })
+            .collect();
+        section.table(
+            vec!["Command", "Sent", "Received", "Bytes"],
+            rows,
+            "No commands.",
+        );
+        let rows = statistics
+            .events
+            .iter()
+            .map(|(code, count)| {
+                let name = event_code_name(*code).unwrap_or("Unknown");
+                count_row(format!("{} (0x{:02X})", name, code), count)
+            })
+            .collect();
+        section.table(
+            vec!["Event", "Sent", "Received", "Bytes"],
+            rows,
+            "No events.",
+        );
+        if !statistics.le_subevents.is_empty() {
+            let rows = statistics
+                .le_subevents
+                .iter()
+                .map(|(code, count)| {
+                    let name = LeMetaEvent::subevent_name(*code).unwrap_or("Unknown");
+                    count_row(format!("{} (0x{:02X})", name, code), count)
+                })
+                .collect();
+            section.table(
+                vec!["LE Meta subevent", "Sent", "Received", "Bytes"],
+                rows,
+                "",
+            );
+        }
+        if !statistics.acl_handles.is_empty() {
+            let rows = statistics
+                .acl_handles
+                .iter()
+                .map(|(handle, count)| count_row(format!("0x{:04X}", handle), count))
+                .collect();
+            section.table(vec!["ACL handle", "Sent", "Received", "Bytes"], rows, "");
+        }
+        sections.push(section);
+
+        let connections = check_connection_intervals(capture, &ComplianceConfig::default());
+        let mut section = Section::new("LE Connections");
+        let rows = connections
+            .iter()
+            .map(|connection| {
+                let (interval, timeout) = connection
+                    .parameters
+                    .last()
+                    .map(|(_, p)| {
+                        (
+                            format_millis(p.interval_us()),
+                            format_millis(p.supervision_timeout_us()),
+                        )
+                    })
+                    .unwrap_or_default();
+                vec![
+                    format!("0x{:04X}", connection.handle),
+                    connection.peer_address.to_string(),
+                    match connection.role {
+                        LeConnectionComplete::ROLE_CENTRAL => "Central".to_string(),
+                        LeConnectionComplete::ROLE_PERIPHERAL => "Peripheral".to_string(),
+                        role => format!("0x{:02X}", role),
+                    },
+                    relative(connection.connected_at),
+                    connection.disconnected_at.map(relative).unwrap_or_default(),
+                    interval,
+                    timeout,
+                    connection.findings.len().to_string(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec![
+                "Handle",
+                "Peer",
+                "Role",
+                "Connected",
+                "Disconnected",
+                "Interval",
+                "Supervision timeout",
+                "Findings",
+            ],
+            rows,
+            "No LE connections.",
+        );
+        sections.push(section);
+
+        let mut section = Section::new("LE Pairing");
+        let rows = pairing_audit(capture)
+            .iter()
+            .map(|pairing| {
+                let security = match (pairing.crackable(), pairing.passkey) {
+                    (true, Some(passkey)) => format!("Crackable: passkey {:06}", passkey),
+                    (true, None) => "Crackable".to_string(),
+                    (false, _) => String::new(),
+                };
+                vec![
+                    format!("0x{:04X}", pairing.handle),
+                    format!("#{} {}", pairing.packet_index, relative(pairing.timestamp)),
+                    pairing
+                        .peer
+                        .map(|peer| peer.to_string())
+                        .unwrap_or_default(),
+                    match pairing.secure_connections() {
+                        true => "LE Secure Connections".to_string(),
+                        false => "Legacy".to_string(),
+                    },
+                    pairing
+                        .model()
+                        .map(|model| model.to_string())
+                        .unwrap_or_default(),
+                    pairing
+                        .failed
+                        .map(|reason| {
+                            format!(
+                                "Failed: {} (0x{:02X})",
+                                failure_reason_name(reason).unwrap_or("Unknown"),
+                                reason
+                            )
+                        })
+                        .unwrap_or_default(),
+                    security,
+                ]
+            })
+            .collect();
+        section.table(
+            vec![
+                "Handle",
+                "Pairing Request",
+                "Peer",
+                "Method",
+                "Association model",
+                "Result",
+                "Security",
+            ],
+            rows,
+            "No LE pairings.",
+        );
+        sections.push(section);
+
+        let mut section = Section::new("BR/EDR Pairing");
+        let rows = classic_pairing_audit(capture)
+            .iter()
+            .map(|pairing| {
+                let method = match (pairing.pin_code, pairing.secure_connections()) {
+                    (true, _) => "Legacy PIN",
+                    (false, Some(true)) => "Secure Connections (P-256)",
+                    (false, Some(false)) => "Secure Simple Pairing (P-192)",
+                    (false, None) => "Secure Simple Pairing",
+                };
+                vec![
+                    pairing.peer.to_string(),
+                    format!("#{} {}", pairing.packet_index, relative(pairing.timestamp)),
+                    match pairing.local_initiator {
+                        Some(true) => "Initiator".to_string(),
+                        Some(false) => "Responder".to_string(),
+                        None => String::new(),
+                    },
+                    method.to_string(),
+                    pairing
+                        .model()
+                        .map(|model| model.to_string())
+                        .unwrap_or_default(),
+                    match pairing.status {
+                        Some(0) => "Success".to_string(),
+                        Some(status) => format!(
+                            "Failed: {} (0x{:02X})",
+                            error_code_name(status).unwrap_or("Unknown"),
+                            status
+                        ),
+                        None => String::new(),
+                    },
+                    pairing
+                        .key_type
+                        .map(|key_type| key_type_name(key_type).unwrap_or("Unknown").to_string())
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec![
+                "Peer",
+                "Pairing",
+                "Local role",
+                "Method",
+                "Association model",
+                "Result",
+                "Link key",
+            ],
+            rows,
+            "No BR/EDR pairings.",
+        );
+        sections.push(section);
+
+        let mut section = Section::new("Advertisers");
+        let rows = scan_report(capture, &ScanConfig::default())
+            .advertisers
+            .iter()
+            .map(|advertiser| {
+                let (id, kind) = match advertiser.id {
+                    AdvertiserId::Address {
+                        address_type,
+                        address,
+                    } => (
+                        address_name(&address, address_type),
+                        address.kind(address_type).to_string(),
+                    ),
+                    AdvertiserId::Irk(index) => (
+                        format!("IRK #{}", index),
+                        AddressKind::ResolvablePrivate.to_string(),
+                    ),
+                };
+                vec![
+                    id,
+                    kind,
+                    advertiser.local_name().unwrap_or_default(),
+                    advertiser.reports.to_string(),
+                    advertiser.scan_responses.to_string(),
+                    advertiser.data_variants.len().to_string(),
+                    advertiser
+                        .rssi
+                        .map(|(min, max)| format!("{} to {} dBm", min, max))
+                        .unwrap_or_default(),
+                    advertiser
+                        .interval_estimate_us
+                        .map(format_millis)
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec![
+                "Advertiser",
+                "Address type",
+                "Name",
+                "Reports",
+                "Scan responses",
+                "Data variants",
+                "RSSI",
+                "Interval estimate",
+            ],
+            rows,
+            "No advertising reports.",
+        );
+        sections.push(section);
+
+        let mut section = Section::new("Findings");
+        let timestamp = |index: usize| {
+            capture
+                .packets
+                .get(index)
+                .map(|p| relative(p.description.timestamp))
+                .unwrap_or_default()
+        };
+        let rows = health_check(capture)
+            .iter()
+            .map(|finding| {
+                vec![
+                    format!("{:?}", finding.issue.category()),
+                    format!(
+                        "#{} {}",
+                        finding.packet_index,
+                        timestamp(finding.packet_index)
+                    ),
+                    finding.issue.to_string(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec!["Category", "Packet", "Capture health"],
+            rows,
+            "No capture health findings.",
+        );
+        let errors = command_errors(capture);
+        let rows = errors
+            .groups
+            .iter()
+            .map(|group| {
+                vec![
+                    group.operation.to_string(),
+                    format!(
+                        "{} (0x{:02X})",
+                        group
+                            .operation
+                            .status_name(group.status)
+                            .unwrap_or("Unknown"),
+                        group.status
+                    ),
+                    group.count.to_string(),
+                    format!(
+                        "#{} {}",
+                        group.first_packet_index,
+                        relative(group.first_timestamp)
+                    ),
+                ]
+            })
+            .collect();
+        section.table(
+            vec!["Failed operation", "Status", "Count", "First"],
+            rows,
+            "No failed commands or ATT requests.",
+        );
+
+        let rows = connections
+            .iter()
+            .flat_map(|connection| {
+                connection.findings.iter().map(|finding| {
+                    vec![
+                        format!("0x{:04X}", connection.handle),
+                        format!(
+                            "#{} {}",
+                            finding.packet_index(),
+                            timestamp(finding.packet_index())
+                        ),
+                        finding.to_string(),
+                    ]
+                })
+            })
+            .collect();
+        section.table(
+            vec!["Handle", "Packet", "Connection parameter finding"],
+            rows,
+            "No connection parameter findings.",
+        );
+
+        let rows = data_stalls(capture, &StallConfig::default())
+            .iter()
+            .map(|stall| {
+                vec![
+                    format!("0x{:04X}", stall.handle),
+                    format!("#{} {}", stall.packet_index, relative(stall.start)),
+                    format_seconds(stall.duration_us(end)),
+                    stall.outstanding.to_string(),
+                    stall.kind.to_string(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec!["Handle", "Packet", "Duration", "Outstanding", "Data stall"],
+            rows,
+            "No data stalls.",
+        );
+
+        let streams = validate_rtp_streams(capture, &RtpConfig::default());
+        let rows = streams
+            .iter()
+            .map(|stream| {
+                vec![
+                    format!("0x{:04X}", stream.handle),
+                    format!("0x{:04X}", stream.local_cid),
+                    if stream.sent { "Sent" } else { "Received" }.to_string(),
+                    format!("0x{:08X}", stream.ssrc),
+                    stream.packets.to_string(),
+                    stream.lost.to_string(),
+                    stream.reordered.to_string(),
+                    stream.duplicates.to_string(),
+                    stream
+                        .max_lateness_us
+                        .map(format_millis)
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+        section.table(
+            vec![
+                "Handle",
+                "CID",
+                "Direction",
+                "SSRC",
+                "Packets",
+                "Lost",
+                "Reordered",
+                "Duplicates",
+                "Max lateness",
+            ],
+            rows,
+            "No RTP media streams.",
+        );
+        let rows: Vec<_> = streams
+            .iter()
+            .flat_map(|stream| {
+                stream.events.iter().map(|event| {
+                    vec![
+                        format!("0x{:08X}", stream.ssrc),
+                        format!(
+                            "#{} {}",
+                            event.packet_index(),
+                            timestamp(event.packet_index())
+                        ),
+                        event.to_string(),
+                    ]
+                })
+            })
+            .collect();
+        if !rows.is_empty() {
+            section.table(vec!["SSRC", "Packet", "RTP event"], rows, "");
+        }
+        sections.push(section);
+
+        Self {
+            title: "Bluetooth HCI capture report".to_string(),
+            sections,
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|");
+        let mut out = String::new();
+        let _ = writeln!(out, "# {}", self.title);
+        for section in &self.sections {
+            let _ = writeln!(out, "\n## {}", section.title);
+            for block in &section.blocks {
+                match block {
+                    Block::Paragraph(text) => {
+                        let _ = writeln!(out, "\n{}", text);
+                    }
+                    Block::Table(table) => {
+                        let _ = writeln!(out, "\n| {} |", table.headers.join(" | "));
+                        let _ = writeln!(out, "|{}", "---|".repeat(table.headers.len()));
+                        for row in &table.rows {
+                            let row: Vec<_> = row.iter().map(|c| cell(c)).collect();
+                            let _ = writeln!(out, "| {} |", row.join(" | "));
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        );
+        let _ = writeln!(out, "<title>{}</title>", escape_html(&self.title));
+        let _ = writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE);
+        let _ = writeln!(out, "<h1>{}</h1>", escape_html(&self.title));
+        for section in &self.sections {
+            let _ = writeln!(out, "<h2>{}</h2>", escape_html(section.title));
+            for block in &section.blocks {
+                match block {
+                    Block::Paragraph(text) => {
+                        let _ = writeln!(out, "<p>{}</p>", escape_html(text));
+                    }
+                    Block::Table(table) => {
+                        out.push_str("<table>\n<tr>");
+                        for header in &table.headers {
+                            let _ = write!(out, "<th>{}</th>", escape_html(header));
+                        }
+                        out.push_str("</tr>\n");
+                        for row in &table.rows {
+                            out.push_str("<tr>");
+                            for cell in row {
+                                let _ = write!(out, "<td>{}</td>", escape_html(cell));
+                            }
+                            out.push_str("</tr>\n");
+                        }
+                        out.push_str("</table>\n");
+                    }
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin:1em 0}\
+th,td{border:1px solid #ccc;padding:0.25em 0.5em;text-align:left}\
+th{background:#eee}";
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The address, with its manufacturer when public and known
+#[cfg_attr(not(feature = "oui"), allow(unused_variables))]
+fn address_name(address: &BdAddr, address_type: u8) -> String {
+    #[cfg(feature = "oui")]
+    if let Some(manufacturer) = crate::oui::manufacturer(address, address_type) {
+        return format!("{} ({})", address, manufacturer);
+    }
+    address.to_string()
+}
+
+fn format_seconds(us: i64) -> String {
+    format!("{}.{:06} s", us / 1_000_000, (us % 1_000_000).abs())
+}
+
+fn format_millis(us: i64) -> String {
+    format!("{:.2} ms", us as f64 / 1000.0)
+}
+
+fn format_relative(us: i64) -> String {
+    format!("+{}", format_seconds(us))
+}
+
+/// Format a btsnoop timestamp as UTC date and time, e.g. `2023-01-28 02:48:36.395644 UTC`.
+/// The timestamps of a corrupt record too far before the Unix epoch are formatted as such,
+/// e.g. `timestamp -9223372036854775808 out of range`.
+pub fn format_utc(timestamp: i64) -> String {
+    let Some(unix) = timestamp.checked_sub(PacketDescription::UNIX_EPOCH_OFFSET) else {
+        return format!("timestamp {} out of range", timestamp);
+    };
+    let seconds = unix.div_euclid(1_000_000);
+    let micros = unix.rem_euclid(1_000_000);
+    let days = seconds.div_euclid(86_400);
+    let time = seconds.rem_euclid(86_400);
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        micros
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn utc_time() {
+        assert_eq!(
+            format_utc(PacketDescription::UNIX_EPOCH_OFFSET),
+            "1970-01-01 00:00:00.000000 UTC"
+        );
+        assert_eq!(
+            format_utc(PacketDescription::UNIX_EPOCH_OFFSET + 1_709_251_199_500_000),
+            "2024-02-29 23:59:59.500000 UTC"
+        );
+        assert_eq!(
+            format_utc(i64::MIN),
+            "timestamp -9223372036854775808 out of range"
+        );
+        assert_eq!(format_utc(i64::MAX), "292276-12-28 04:00:54.775807 UTC");
+    }
+
+    #[test]
+    fn render_android_capture() {
+        let mut file: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let capture = Btsnoop::parse(&mut file).unwrap();
+        let report = Report::new(&capture);
+
+        let markdown = report.render(ReportFormat::Markdown);
+        assert!(markdown.starts_with("# Bluetooth HCI capture report\n"));
+        assert!(markdown.contains("\n## Statistics\n"));
+        assert!(markdown.contains("| Datalink | HCI UART (H4) |"));
+        assert!(markdown.contains("LE Set Extended Scan Enable (0x2042)"));
+
+        let html = report.render(ReportFormat::Html);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>Findings</h2>"));
+        assert!(html.contains("<td>4D:AB:43:2A:3F:10</td>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn corrupt_timestamp() {
+        let mut file: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let mut capture = Btsnoop::parse(&mut file).unwrap();
+        capture.packets[0].description.timestamp = i64::MIN;
+        assert_eq!(statistics(&capture).duration_us(), i64::MAX);
+
+        let markdown = Report::new(&capture).render(ReportFormat::Markdown);
+        assert!(markdown.contains("| Duration | 9223372036854.775807 s |"));
+    }
+}
@@ -0,0 +1,155 @@
+//! RFCOMM frames, the serial port emulation carried over L2CAP (PSM 0x0003), from the RFCOMM
+//! specification and the TS 07.10 multiplexer it adapts.
+//!
+//! ```text
+//! | address 8 bit | control 8 bit | length 8 or 16 bit | credits 8 bit | information | FCS 8 bit |
+//! ```
+//!
+//! The credits are only there in UIH frames of a data link connection with the P/F bit set,
+//! when credit based flow control was negotiated.
+
+use std::{fmt::Display, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Frame<'a> {
+    /// data link connection identifier, 0 for the multiplexer control channel, the server
+    /// channel is `dlci >> 1`
+    pub dlci: u8,
+    /// C/R bit of the address
+    pub command_response: bool,
+    /// control field without the P/F bit, see the frame type constants
+    pub frame_type: u8,
+    pub poll_final: bool,
+    pub credits: Option<u8>,
+    pub information: &'a [u8],
+}
+
+impl Frame<'_> {
+    pub const SABM: u8 = 0x2F;
+    pub const UA: u8 = 0x63;
+    pub const DM: u8 = 0x0F;
+    pub const DISC: u8 = 0x43;
+    pub const UIH: u8 = 0xEF;
+
+    const POLL_FINAL: u8 = 0x10;
+
+    /// whether the frame carries data of a data link connection
+    pub fn is_data(&self) -> bool {
+        self.frame_type == Self::UIH && self.dlci != 0
+    }
+}
+
+pub fn frame_type_name(frame_type: u8) -> Option<&'static str> {
+    let name = match frame_type {
+        Frame::SABM => "SABM",
+        Frame::UA => "UA",
+        Frame::DM => "DM",
+        Frame::DISC => "DISC",
+        Frame::UIH => "UIH",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl<'a> TryFrom<&'a [u8]> for Frame<'a> {
+    type Error = io::Error;
+
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        let too_short = || io::Error::new(io::ErrorKind::UnexpectedEof, "RFCOMM frame too short");
+        let (&address, rest) = data.split_first().ok_or_else(too_short)?;
+        let (&control, rest) = rest.split_first().ok_or_else(too_short)?;
+        let (&length, mut rest) = rest.split_first().ok_or_else(too_short)?;
+        let mut length = (length >> 1) as usize;
+        if data[2] & 0x01 == 0 {
+            let (&high, tail) = rest.split_first().ok_or_else(too_short)?;
+            length |= (high as usize) << 7;
+            rest = tail;
+        }
+        let dlci = address >> 2;
+        let frame_type = control & !Self::POLL_FINAL;
+        let poll_final = control & Self::POLL_FINAL != 0;
+        let mut credits = None;
+        if frame_type == Self::UIH && poll_final && dlci != 0 {
+            let (&credit, tail) = rest.split_first().ok_or_else(too_short)?;
+            credits = Some(credit);
+            rest = tail;
+        }
+        // followed by the FCS
+        let information = rest
+            .get(..length)
+            .filter(|_| rest.len() > length)
+            .ok_or_else(too_short)?;
+        Ok(Self {
+            dlci,
+            command_response: address & 0x02 != 0,
+            frame_type,
+            poll_final,
+            credits,
+            information,
+        })
+    }
+}
+
+/// `RFCOMM: UIH DLCI 2 len 10`
+impl Display for Frame<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match frame_type_name(self.frame_type) {
+            Some(name) => write!(f, "RFCOMM: {}", name)?,
+            None => write!(f, "RFCOMM: 0x{:02x}", self.frame_type)?,
+        }
+        write!(f, " DLCI {} len {}", self.dlci, self.information.len())?;
+        if let Some(credits) = self.credits {
+            write!(f, " credits {}", credits)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn control_frames() {
+        // SABM on the multiplexer control channel, P bit set, FCS 0x1c
+        let frame = Frame::try_from(&[0x03, 0x3f, 0x01, 0x1c][..]).unwrap();
+        assert_eq!(frame.dlci, 0);
+        assert!(frame.command_response && frame.poll_final);
+        assert_eq!(frame.frame_type, Frame::SABM);
+        assert!(frame.information.is_empty() && !frame.is_data());
+        assert_eq!(frame.to_string(), "RFCOMM: SABM DLCI 0 len 0");
+
+        // without its FCS
+        let error = Frame::try_from(&[0x03, 0x3f, 0x01][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(Frame::try_from(&[0x03, 0x3f][..]).is_err());
+    }
+
+    #[test]
+    fn information_lengths() {
+        // UIH on DLCI 2, one length octet with the EA bit set
+        let frame = Frame::try_from(&[0x09, 0xef, 0x05, 0x41, 0x54, 0x9a][..]).unwrap();
+        assert!(frame.is_data());
+        assert_eq!(frame.information, b"AT");
+        assert_eq!(frame.credits, None);
+
+        // EA bit clear: a second length octet, 128 + 2
+        let mut data = vec![0x09, 0xef, 0x04, 0x01];
+        data.extend(std::iter::repeat_n(0x55, 130));
+        data.push(0x9a);
+        let frame = Frame::try_from(&data[..]).unwrap();
+        assert_eq!(frame.information.len(), 130);
+        // missing the second length octet
+        assert!(Frame::try_from(&[0x09, 0xef, 0x04][..]).is_err());
+        // the length runs into the FCS
+        assert!(Frame::try_from(&data[..data.len() - 1]).is_err());
+
+        // P/F set on a data link connection: a credits octet before the information
+        let frame = Frame::try_from(&[0x09, 0xff, 0x03, 0x07, 0x41, 0x9a][..]).unwrap();
+        assert_eq!(frame.frame_type, Frame::UIH);
+        assert_eq!(frame.credits, Some(7));
+        assert_eq!(frame.information, b"A");
+        assert_eq!(frame.to_string(), "RFCOMM: UIH DLCI 2 len 1 credits 7");
+        assert!(Frame::try_from(&[0x09, 0xff, 0x03][..]).is_err());
+    }
+}
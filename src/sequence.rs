@@ -0,0 +1,535 @@
+//! Sequence diagrams of a capture, rendered as Mermaid or PlantUML, for design reviews and bug
+//! reports. The Host, the Controller and the Peer are the participants: commands and events
+//! are arrows between the Host and the Controller, the L2CAP PDUs, e.g. the ATT operations,
+//! arrows between the Host and the Peer, labeled with their name and key parameters.
+//!
+//! ```
+//! use btsnoop::sequence::{DiagramFormat, SequenceDiagram};
+//!
+//! # let capture = btsnoop::Btsnoop::default();
+//! let diagram = SequenceDiagram::new(capture.packets.iter().enumerate());
+//! println!("{}", diagram.render(DiagramFormat::Mermaid));
+//! ```
+
+use std::fmt::{self, Write};
+
+use crate::{
+    att,
+    hci::{
+        error_code_name, event_code_name, opcode, opcode_name, synchronous, BdAddr, Command,
+        CommandComplete, CommandStatus, ConnectionComplete, DisconnectionComplete, Event,
+        LeMetaEvent, NumberOfCompletedPackets,
+    },
+    l2cap::{signaling_code_name, BasicFrame, Pdu, Reassembler},
+    parse_uart_packet, Packet, UartData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    Mermaid,
+    PlantUml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Participant {
+    /// the Host of the capture
+    Host,
+    Controller,
+    /// the remote device of the connections
+    Peer,
+}
+
+impl Participant {
+    pub const ALL: [Participant; 3] = [
+        Participant::Host,
+        Participant::Controller,
+        Participant::Peer,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Participant::Host => "Host",
+            Participant::Controller => "Controller",
+            Participant::Peer => "Peer",
+        }
+    }
+}
+
+impl fmt::Display for Participant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A packet, or the PDU it completes, as an arrow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arrow {
+    pub packet_index: usize,
+    pub timestamp: i64,
+    pub from: Participant,
+    pub to: Participant,
+    /// the name of the command, event or PDU, then its key parameters in parentheses
+    pub label: String,
+    /// events are drawn dashed, as the answers of the Controller
+    pub dashed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceDiagram {
+    pub arrows: Vec<Arrow>,
+    /// prefix the labels with the index of their packet
+    pub packet_numbers: bool,
+}
+
+impl SequenceDiagram {
+    /// The arrows of `packets` with their index in the capture, e.g. the filtered packets of a
+    /// capture. ACL fragments make an arrow when they complete their PDU.
+    pub fn new<'a>(packets: impl IntoIterator<Item = (usize, &'a Packet)>) -> Self {
+        let mut arrows = vec![];
+        let mut reassembler = Reassembler::default();
+        for (packet_index, packet) in packets {
+            let timestamp = packet.description.timestamp;
+            let received = packet.description.flags.is_received();
+            let arrow = |from, to, label, dashed| Arrow {
+                packet_index,
+                timestamp,
+                from,
+                to,
+                label,
+                dashed,
+            };
+            let peer = |received| {
+                if received {
+                    (Participant::Peer, Participant::Host)
+                } else {
+                    (Participant::Host, Participant::Peer)
+                }
+            };
+            let Ok(data) = parse_uart_packet(packet) else {
+                // other datalinks, or undecodable packets, are only told by their flags
+                let flags = packet.description.flags;
+                let length = packet.data.0.len();
+                let (from, to) = match (flags.is_command_or_event(), received) {
+                    (true, false) => (Participant::Host, Participant::Controller),
+                    (true, true) => (Participant::Controller, Participant::Host),
+                    (false, received) => peer(received),
+                };
+                let kind = match (flags.is_command_or_event(), received) {
+                    (true, false) => "Command",
+                    (true, true) => "Event",
+                    _ => "Data",
+                };
+                arrows.push(arrow(
+                    from,
+                    to,
+                    format!("{} ({} bytes)", kind, length),
+                    flags.is_command_or_event() && received,
+                ));
+                continue;
+            };
+            match data {
+                UartData::Command(command) => arrows.push(arrow(
+                    Participant::Host,
+                    Participant::Controller,
+                    command_label(&command),
+                    false,
+                )),
+                UartData::Event(event) => {
+                    if event.code == Event::DISCONNECTION_COMPLETE {
+                        if let Ok(disconnection) =
+                            DisconnectionComplete::parse(&mut &event.params[..])
+                        {
+                            reassembler.disconnected(disconnection.handle);
+                        }
+                    }
+                    arrows.push(arrow(
+                        Participant::Controller,
+                        Participant::Host,
+                        event_label(&event),
+                        true,
+                    ));
+                }
+                UartData::Acl(acl) => {
+                    for pdu in reassembler.push(&acl, !received, packet_index) {
+                        let (from, to) = peer(!pdu.sent);
+                        arrows.push(arrow(from, to, pdu_label(&pdu), false));
+                    }
+                }
+                UartData::Todos => {
+                    let label = match packet.data.0.first() {
+                        Some(0x03) => "SCO Data",
+                        Some(0x05) => "ISO Data",
+                        _ => continue,
+                    };
+                    let (from, to) = peer(received);
+                    arrows.push(arrow(from, to, label.to_owned(), false));
+                }
+            }
+        }
+        Self {
+            arrows,
+            packet_numbers: false,
+        }
+    }
+
+    pub fn with_packet_numbers(mut self, packet_numbers: bool) -> Self {
+        self.packet_numbers = packet_numbers;
+        self
+    }
+
+    pub fn render(&self, format: DiagramFormat) -> String {
+        match format {
+            DiagramFormat::Mermaid => self.to_mermaid(),
+            DiagramFormat::PlantUml => self.to_plantuml(),
+        }
+    }
+
+    fn label(&self, arrow: &Arrow) -> String {
+        if self.packet_numbers {
+            format!("{}: {}", arrow.packet_index, arrow.label)
+        } else {
+            arrow.label.clone()
+        }
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("sequenceDiagram\n");
+        for participant in Participant::ALL {
+            let _ = writeln!(out, "    participant {}", participant);
+        }
+        for arrow in &self.arrows {
+            // `#` starts an entity code and `;` ends a statement
+            let label: String = self
+                .label(arrow)
+                .chars()
+                .map(|c| match c {
+                    '#' => "#35;".to_owned(),
+                    ';' => "#59;".to_owned(),
+                    c => c.to_string(),
+                })
+                .collect();
+            let _ = writeln!(
+                out,
+                "    {}{}{}: {}",
+                arrow.from,
+                if arrow.dashed { "-->>" } else { "->>" },
+                arrow.to,
+                label
+            );
+        }
+        out
+    }
+
+    pub fn to_plantuml(&self) -> String {
+        let mut out = String::from("@startuml\n");
+        for participant in Participant::ALL {
+            let _ = writeln!(out, "participant {}", participant);
+        }
+        for arrow in &self.arrows {
+            let _ = writeln!(
+                out,
+                "{} {} {} : {}",
+                arrow.from,
+                if arrow.dashed { "-->" } else { "->" },
+                arrow.to,
+                self.label(arrow)
+            );
+        }
+        out.push_str("@enduml\n");
+        out
+    }
+}
+
+/// `name (parameters)`, or just the name without parameters
+fn labeled(name: impl Into<String>, parameters: &[String]) -> String {
+    let mut label = name.into();
+    if !parameters.is_empty() {
+        let _ = write!(label, " ({})", parameters.join(", "));
+    }
+    label
+}
+
+fn handle(params: &[u8]) -> Option<String> {
+    let handle = params.first_chunk::<2>()?;
+    Some(format!(
+        "handle 0x{:04x}",
+        u16::from_le_bytes(*handle) & 0x0FFF
+    ))
+}
+
+fn address(params: &[u8], offset: usize) -> Option<String> {
+    BdAddr::parse(&mut params.get(offset..)?)
+        .ok()
+        .map(|address| address.to_string())
+}
+
+fn status(status: u8) -> String {
+    error_code_name(status)
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("status 0x{:02x}", status))
+}
+
+pub(crate) fn command_label(command: &Command) -> String {
+    let params = command.params;
+    let name = opcode_name(command.opcode.raw())
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("Opcode 0x{:04x}", command.opcode.raw()));
+    let parameters = match command.opcode {
+        opcode::DISCONNECT => handle(params)
+            .into_iter()
+            .chain(params.get(2).map(|&reason| status(reason)))
+            .collect(),
+        opcode::AUTHENTICATION_REQUESTED
+        | opcode::SET_CONNECTION_ENCRYPTION
+        | opcode::READ_REMOTE_SUPPORTED_FEATURES
+        | opcode::READ_REMOTE_VERSION_INFORMATION
+        | opcode::SETUP_SYNCHRONOUS_CONNECTION
+        | opcode::ENHANCED_SETUP_SYNCHRONOUS_CONNECTION
+        | opcode::LE_CONNECTION_UPDATE
+        | opcode::LE_READ_REMOTE_FEATURES
+        | opcode::LE_START_ENCRYPTION
+        | opcode::LE_LONG_TERM_KEY_REQUEST_REPLY
+        | opcode::LE_SET_DATA_LENGTH
+        | opcode::LE_SET_PHY => handle(params).into_iter().collect(),
+        opcode::CREATE_CONNECTION
+        | opcode::ACCEPT_CONNECTION_REQUEST
+        | opcode::ACCEPT_SYNCHRONOUS_CONNECTION
+        | opcode::ENHANCED_ACCEPT_SYNCHRONOUS_CONNECTION => {
+            address(params, 0).into_iter().collect()
+        }
+        opcode::LE_CREATE_CONNECTION => address(params, 6).into_iter().collect(),
+        opcode::LE_EXTENDED_CREATE_CONNECTION => address(params, 3).into_iter().collect(),
+        opcode::LE_SET_SCAN_ENABLE
+        | opcode::LE_SET_ADVERTISING_ENABLE
+        | opcode::LE_SET_EXTENDED_SCAN_ENABLE
+        | opcode::LE_SET_EXTENDED_ADVERTISING_ENABLE => params
+            .first()
+            .map(|&enable| if enable == 0 { "disable" } else { "enable" }.to_owned())
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    };
+    labeled(name, &parameters)
+}
+
+pub(crate) fn event_label(event: &Event) -> String {
+    let mut params = event.params;
+    let name = event_code_name(event.code)
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("Event 0x{:02x}", event.code));
+    let command = |opcode: u16| {
+        opcode_name(opcode)
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("0x{:04x}", opcode))
+    };
+    let parameters = match event.code {
+        Event::COMMAND_COMPLETE => match CommandComplete::try_from(event.params) {
+            Ok(complete) => [command(complete.opcode.raw())]
+                .into_iter()
+                .chain(complete.return_parameters.first().map(|&s| status(s)))
+                .collect(),
+            Err(_) => vec![],
+        },
+        Event::COMMAND_STATUS => match CommandStatus::parse(&mut params) {
+            Ok(command_status) => vec![
+                command(command_status.opcode.raw()),
+                status(command_status.status),
+            ],
+            Err(_) => vec![],
+        },
+        Event::CONNECTION_COMPLETE => match ConnectionComplete::parse(&mut params) {
+            Ok(complete) if complete.status == 0 => vec![
+                format!("handle 0x{:04x}", complete.handle),
+                complete.bd_addr.to_string(),
+            ],
+            Ok(complete) => vec![complete.bd_addr.to_string(), status(complete.status)],
+            Err(_) => vec![],
+        },
+        Event::DISCONNECTION_COMPLETE => match DisconnectionComplete::parse(&mut params) {
+            Ok(disconnection) => vec![
+                format!("handle 0x{:04x}", disconnection.handle),
+                status(disconnection.reason),
+            ],
+            Err(_) => vec![],
+        },
+        Event::SYNCHRONOUS_CONNECTION_COMPLETE => {
+            match synchronous::SynchronousConnectionComplete::parse(&mut params) {
+                Ok(complete) if complete.status == 0 => vec![
+                    format!("handle 0x{:04x}", complete.handle),
+                    format!(
+                        "air mode {}",
+                        synchronous::coding_format_name(complete.air_mode).unwrap_or("unknown")
+                    ),
+                ],
+                Ok(complete) => vec![complete.bd_addr.to_string(), status(complete.status)],
+                Err(_) => vec![],
+            }
+        }
+        Event::NUMBER_OF_COMPLETED_PACKETS => match NumberOfCompletedPackets::parse(&mut params) {
+            Ok(completed) => completed
+                .completed
+                .iter()
+                .map(|(handle, count)| format!("handle 0x{:04x}: {}", handle, count))
+                .collect(),
+            Err(_) => vec![],
+        },
+        Event::LE_META => {
+            let Some(&subevent_code) = event.params.first() else {
+                return name;
+            };
+            let name = match LeMetaEvent::subevent_name(subevent_code) {
+                Some(subevent) => format!("LE {}", subevent),
+                None => format!("LE Meta 0x{:02x}", subevent_code),
+            };
+            let parameters = match LeMetaEvent::try_from(event.params) {
+                Ok(LeMetaEvent::ConnectionComplete(complete)) if complete.status == 0 => vec![
+                    format!("handle 0x{:04x}", complete.handle),
+                    complete.peer_address.to_string(),
+                ],
+                Ok(LeMetaEvent::ConnectionComplete(complete)) => vec![status(complete.status)],
+                Ok(LeMetaEvent::ConnectionUpdateComplete(update)) => {
+                    vec![format!("handle 0x{:04x}", update.handle)]
+                }
+                Ok(LeMetaEvent::AdvertisingReport(reports)) => {
+                    vec![format!("{} reports", reports.len())]
+                }
+                _ => vec![],
+            };
+            return labeled(name, &parameters);
+        }
+        _ => vec![],
+    };
+    labeled(name, &parameters)
+}
+
+pub(crate) fn pdu_label(pdu: &Pdu) -> String {
+    let Ok(frame) = pdu.frame() else {
+        return "L2CAP (truncated)".to_owned();
+    };
+    if !pdu.complete {
+        return format!("L2CAP CID 0x{:04x} (incomplete)", frame.channel_id);
+    }
+    let payload = &frame.payload[..frame.payload.len().min(frame.length as usize)];
+    match frame.channel_id {
+        BasicFrame::ATT_CID => {
+            let Ok(att) = att::Pdu::try_from(payload) else {
+                return "ATT (empty)".to_owned();
+            };
+            let name = att::opcode_name(att.opcode)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("ATT 0x{:02x}", att.opcode));
+            let attribute = att
+                .params
+                .first_chunk::<2>()
+                .map(|handle| format!("handle 0x{:04x}", u16::from_le_bytes(*handle)));
+            let parameters: Vec<_> = match att.opcode {
+                att::Pdu::ERROR_RESPONSE => match att::ErrorResponse::parse(&mut &att.params[..]) {
+                    Ok(error) => vec![
+                        att::opcode_name(error.request_opcode)
+                            .unwrap_or("unknown request")
+                            .to_owned(),
+                        format!("handle 0x{:04x}", error.handle),
+                        att::error_code_name(error.error_code)
+                            .map(str::to_owned)
+                            .unwrap_or_else(|| format!("error 0x{:02x}", error.error_code)),
+                    ],
+                    Err(_) => vec![],
+                },
+                att::Pdu::EXCHANGE_MTU_REQUEST | att::Pdu::EXCHANGE_MTU_RESPONSE => att
+                    .params
+                    .first_chunk::<2>()
+                    .map(|mtu| format!("MTU {}", u16::from_le_bytes(*mtu)))
+                    .into_iter()
+                    .collect(),
+                att::Pdu::READ_REQUEST
+                | att::Pdu::READ_BLOB_REQUEST
+                | att::Pdu::WRITE_REQUEST
+                | att::Pdu::WRITE_COMMAND
+                | att::Pdu::PREPARE_WRITE_REQUEST
+                | att::Pdu::HANDLE_VALUE_NOTIFICATION
+                | att::Pdu::HANDLE_VALUE_INDICATION => attribute.into_iter().collect(),
+                _ => vec![],
+            };
+            labeled(name, &parameters)
+        }
+        BasicFrame::SIGNALING_CID | BasicFrame::LE_SIGNALING_CID => match payload.first() {
+            Some(&code) => signaling_code_name(code)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("L2CAP signaling 0x{:02x}", code)),
+            None => "L2CAP signaling (empty)".to_owned(),
+        },
+        BasicFrame::SMP_CID => match payload.first() {
+            Some(&code) => format!("SMP 0x{:02x}", code),
+            None => "SMP (empty)".to_owned(),
+        },
+        cid => format!("L2CAP CID 0x{:04x} ({} bytes)", cid, payload.len()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    #[test]
+    fn diagram() {
+        let capture = h4(vec![
+            (0, false, command(0x01, 0x0006, &[0x40, 0x00, 0x13])),
+            (1, true, event(0x0f, &[0x00, 0x01, 0x06, 0x04])),
+            (2, false, l2cap(0x40, 0x0004, &[0x0a, 0x03, 0x00])),
+            (
+                3,
+                true,
+                l2cap(0x40, 0x0004, &[0x01, 0x0a, 0x03, 0x00, 0x02]),
+            ),
+        ]);
+        let diagram = SequenceDiagram::new(capture.packets.iter().enumerate());
+        let arrows: Vec<_> = diagram
+            .arrows
+            .iter()
+            .map(|arrow| (arrow.from, arrow.to, arrow.label.as_str()))
+            .collect();
+        assert_eq!(
+            arrows,
+            [
+                (
+                    Participant::Host,
+                    Participant::Controller,
+                    "Disconnect (handle 0x0040, Remote User Terminated Connection)"
+                ),
+                (
+                    Participant::Controller,
+                    Participant::Host,
+                    "Command Status (Disconnect, Success)"
+                ),
+                (
+                    Participant::Host,
+                    Participant::Peer,
+                    "Read Request (handle 0x0003)"
+                ),
+                (
+                    Participant::Peer,
+                    Participant::Host,
+                    "Error Response (Read Request, handle 0x0003, Read Not Permitted)"
+                ),
+            ]
+        );
+
+        let mermaid = diagram
+            .with_packet_numbers(true)
+            .render(DiagramFormat::Mermaid);
+        assert!(mermaid.starts_with("sequenceDiagram\n    participant Host\n"));
+        assert!(
+            mermaid.contains("    Controller-->>Host: 1: Command Status (Disconnect, Success)\n")
+        );
+        let plantuml = SequenceDiagram::new(capture.packets.iter().enumerate().skip(2))
+            .render(DiagramFormat::PlantUml);
+        assert_eq!(
+            plantuml,
+            "@startuml\nparticipant Host\nparticipant Controller\nparticipant Peer\n\
+             Host -> Peer : Read Request (handle 0x0003)\n\
+             Peer -> Host : Error Response (Read Request, handle 0x0003, Read Not Permitted)\n\
+             @enduml\n"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! proptest strategies generating valid captures and HCI UART (H4) packets, for the property
+//! tests of this crate and of code consuming its output.
+//!
+//! Generated records are consistent: the included length is the length of the data, the
+//! original length is at least the included length and the flags of H4 packets match their
+//! packet type.
+
+use proptest::{collection::vec, prelude::*};
+
+use crate::{
+    Btsnoop, DatalinkType, Header, IdentificationPattern, Packet, PacketData, PacketDescription,
+    PacketFlags, UartPacketType,
+};
+
+/// Any datalink code, mapped like a parsed header
+pub fn datalink_type() -> impl Strategy<Value = DatalinkType> {
+    any::<u32>().prop_map(DatalinkType::from)
+}
+
+/// A version 1 header, the only version parsed by default, of any datalink
+pub fn header() -> impl Strategy<Value = Header> {
+    datalink_type().prop_map(|datalink_type| Header {
+        identification_pattern: IdentificationPattern,
+        version: Header::VERSION,
+        datalink_type,
+    })
+}
+
+/// A record of the generated data, with any flags, drops and timestamp
+pub fn packet_with(data: impl Strategy<Value = Vec<u8>>) -> impl Strategy<Value = Packet> {
+    (data, 0u32..16, any::<u32>(), any::<u32>(), any::<i64>()).prop_map(
+        |(data, truncated, flags, cumulative_drops, timestamp)| {
+            let included_length = data.len() as u32;
+            Packet {
+                description: PacketDescription {
+                    original_length: included_length.saturating_add(truncated),
+                    included_length,
+                    flags: PacketFlags(flags),
+                    cumulative_drops,
+                    timestamp,
+                },
+                data: PacketData(data),
+            }
+        },
+    )
+}
+
+/// A record of up to 64 octets of any data
+pub fn packet() -> impl Strategy<Value = Packet> {
+    packet_with(vec(any::<u8>(), 0..64))
+}
+
+pub fn capture() -> impl Strategy<Value = Btsnoop> {
+    (header(), vec(packet(), 0..16)).prop_map(|(header, packets)| Btsnoop { header, packets })
+}
+
+/// H4 command packet, with its type indicator
+pub fn hci_command() -> impl Strategy<Value = Vec<u8>> {
+    (any::<u16>(), vec(any::<u8>(), 0..=255)).prop_map(|(opcode, params)| {
+        let mut data = vec![UartPacketType::Cmd as u8];
+        data.extend_from_slice(&opcode.to_le_bytes());
+        data.push(params.len() as u8);
+        data.extend_from_slice(&params);
+        data
+    })
+}
+
+/// H4 event packet, with its type indicator
+pub fn hci_event() -> impl Strategy<Value = Vec<u8>> {
+    (any::<u8>(), vec(any::<u8>(), 0..=255)).prop_map(|(code, params)| {
+        let mut data = vec![UartPacketType::Evt as u8, code, params.len() as u8];
+        data.extend_from_slice(&params);
+        data
+    })
+}
+
+/// H4 ACL data packet, with its type indicator
+pub fn hci_acl() -> impl Strategy<Value = Vec<u8>> {
+    (0u16..0x1000, 0u16..4, 0u16..4, vec(any::<u8>(), 0..512)).prop_map(
+        |(handle, boundary, broadcast, payload)| {
+            let mut data = vec![UartPacketType::Acl as u8];
+            data.extend_from_slice(&(handle | boundary << 12 | broadcast << 14).to_le_bytes());
+            data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+            data.extend_from_slice(&payload);
+            data
+        },
+    )
+}
+
+/// An H4 command, event or ACL record, untruncated, with the flags of its packet type, no
+/// drops and a timestamp pcap can represent (seconds since the Unix epoch in 32 bits)
+pub fn uart_packet() -> impl Strategy<Value = Packet> {
+    (
+        prop_oneof![hci_command(), hci_event(), hci_acl()],
+        any::<bool>(),
+        0..u32::MAX as i64 * 1_000_000,
+    )
+        .prop_map(|(data, received, unix_timestamp)| {
+            let received = match UartPacketType::try_from(data[0]) {
+                Ok(UartPacketType::Cmd) => false,
+                Ok(UartPacketType::Evt) => true,
+                _ => received,
+            };
+            let length = data.len() as u32;
+            crate::formats::h4_packet(unix_timestamp, received, data, length)
+        })
+}
+
+/// An H4 capture of [`uart_packet`]s
+pub fn uart_capture() -> impl Strategy<Value = Btsnoop> {
+    vec(uart_packet(), 0..16).prop_map(|packets| Btsnoop {
+        header: Header::default(),
+        packets,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        analysis::{command_errors, health_check, statistics},
+        formats::{self, pcap::LinkType, pcapng, Format},
+        hci::{Acl, Command, Event},
+    };
+
+    proptest! {
+        #[test]
+        fn capture_round_trip(capture in capture()) {
+            let mut written = vec![];
+            capture.write(&mut written).unwrap();
+            prop_assert_eq!(Btsnoop::parse_from_slice(&written).unwrap(), capture);
+        }
+
+        #[test]
+        fn hci_round_trip(data in prop_oneof![hci_command(), hci_event(), hci_acl()]) {
+            let body = &data[1..];
+            match UartPacketType::try_from(data[0]).unwrap() {
+                UartPacketType::Cmd => {
+                    let command = Command::try_from(body).unwrap();
+                    prop_assert_eq!(command.opcode.raw(), u16::from_le_bytes([body[0], body[1]]));
+                    prop_assert_eq!(command.params_len as usize, command.params.len());
+                }
+                UartPacketType::Evt => {
+                    let event = Event::try_from(body).unwrap();
+                    prop_assert_eq!(event.params_len as usize, event.params.len());
+                }
+                _ => {
+                    let acl = Acl::try_from(body).unwrap();
+                    prop_assert_eq!(acl.data_len as usize, acl.data.len());
+                    prop_assert_eq!(acl.handle, u16::from_le_bytes([body[0], body[1]]) & 0x0FFF);
+                }
+            }
+        }
+
+        #[test]
+        fn pcap_round_trip(capture in uart_capture()) {
+            for format in [Format::Pcap, Format::Pcapng] {
+                let mut written = vec![];
+                if format == Format::Pcap {
+                    formats::pcap::write(&capture, LinkType::H4WithPhdr, &mut written).unwrap();
+                } else {
+                    pcapng::write(&capture, LinkType::H4WithPhdr, &mut written).unwrap();
+                }
+                let read = formats::read(&mut &written[..], format).unwrap();
+                prop_assert_eq!(&read.packets, &capture.packets);
+            }
+        }
+
+        #[test]
+        fn mutated_captures_never_panic(
+            capture in uart_capture(),
+            mutations in vec((any::<prop::sample::Index>(), any::<u8>()), 1..16),
+        ) {
+            let mut written = vec![];
+            capture.write(&mut written).unwrap();
+            for (index, value) in mutations {
+                let index = index.index(written.len());
+                written[index] = value;
+            }
+            if let Ok(capture) = Btsnoop::parse_from_slice(&written) {
+                for packet in &capture {
+                    let _ = packet.to_string();
+                }
+                health_check::health_check(&capture);
+                command_errors::command_errors(&capture);
+                statistics::statistics(&capture);
+            }
+        }
+    }
+}
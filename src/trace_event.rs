@@ -0,0 +1,571 @@
+//! Export of a capture to the trace event format of Chrome, which Perfetto and
+//! `chrome://tracing` open, to see the HCI activity on the same timeline as other traces.
+//!
+//! Each connection handle gets a track with a `connected` slice for its lifetime, and each of
+//! its L2CAP channels, and RFCOMM data link connections, a track of their own. Commands are
+//! slices of the HCI track until their Command Complete or Command Status, the other packets
+//! and the reassembled PDUs are instant events, all with their decoded metadata as arguments.
+//! Only the packets of HCI UART (H4) captures are decoded.
+//!
+//! ```
+//! use btsnoop::trace_event::{write_trace, TraceOptions};
+//!
+//! # let capture = btsnoop::Btsnoop::default();
+//! let mut trace = vec![];
+//! write_trace(&capture, &TraceOptions::new().relative(true), &mut trace)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{collections::HashMap, io};
+
+use serde_json::{json, Value};
+
+use crate::{
+    analysis::{is_received, uart_packets},
+    hci::{
+        synchronous::SynchronousConnectionComplete, CommandComplete, CommandStatus,
+        ConnectionComplete, DisconnectionComplete, Event, LeMetaEvent, Opcode,
+    },
+    l2cap::{psm_name, BasicFrame, ChannelMap, Pdu, Reassembler, PSM_RFCOMM},
+    rfcomm,
+    sequence::{command_label, event_label, pdu_label},
+    Btsnoop, PacketDescription, UartData,
+};
+
+/// The process of the tracks
+const PID: u32 = 1;
+/// The track of the commands and of the events of no connection
+const HCI_TID: u32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceOptions {
+    relative: bool,
+    offset: i64,
+}
+
+impl TraceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the timeline at the first packet, instead of the Unix epoch
+    pub fn relative(mut self, relative: bool) -> Self {
+        self.relative = relative;
+        self
+    }
+
+    /// Microseconds added to every timestamp, e.g. to move the capture onto the clock of
+    /// another trace
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+struct Connection {
+    tid: u32,
+    /// timestamp and packet index of the completion, none for the connections made before
+    /// the capture
+    start: Option<(i64, usize)>,
+}
+
+struct PendingCommand {
+    opcode: Opcode,
+    timestamp: i64,
+    name: String,
+    args: Value,
+}
+
+struct Exporter<'o> {
+    options: &'o TraceOptions,
+    /// timestamp of the start of the timeline
+    origin: i64,
+    events: Vec<Value>,
+    next_tid: u32,
+    connections: HashMap<u16, Connection>,
+    /// (connection track, CID, DLCI) -> track
+    channel_tracks: HashMap<(u32, u16, Option<u8>), u32>,
+    channels: ChannelMap,
+    pending: Vec<PendingCommand>,
+    last_timestamp: i64,
+}
+
+impl<'o> Exporter<'o> {
+    fn new(capture: &Btsnoop, options: &'o TraceOptions) -> Self {
+        let origin = match capture.packets.first() {
+            Some(packet) if options.relative => packet.description.timestamp,
+            _ => PacketDescription::UNIX_EPOCH_OFFSET,
+        };
+        let mut exporter = Self {
+            options,
+            origin,
+            events: vec![json!({
+                "name": "process_name",
+                "ph": "M",
+                "pid": PID,
+                "args": {"name": "Bluetooth HCI"},
+            })],
+            next_tid: HCI_TID + 1,
+            connections: HashMap::new(),
+            channel_tracks: HashMap::new(),
+            channels: ChannelMap::default(),
+            pending: vec![],
+            last_timestamp: origin,
+        };
+        exporter.name_track(HCI_TID, "HCI".to_owned());
+        exporter
+    }
+
+    fn ts(&self, timestamp: i64) -> i64 {
+        timestamp
+            .saturating_sub(self.origin)
+            .saturating_add(self.options.offset)
+    }
+
+    fn name_track(&mut self, tid: u32, name: String) {
+        self.events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": PID,
+            "tid": tid,
+            "args": {"name": name},
+        }));
+        self.events.push(json!({
+            "name": "thread_sort_index",
+            "ph": "M",
+            "pid": PID,
+            "tid": tid,
+            "args": {"sort_index": tid},
+        }));
+    }
+
+    fn track(&mut self, name: String) -> u32 {
+        let tid = self.next_tid;
+        self.next_tid += 1;
+        self.name_track(tid, name);
+        tid
+    }
+
+    /// The track of the connection of `handle`, opened by a packet before its completion when
+    /// it was made before the capture
+    fn connection(&mut self, handle: u16) -> u32 {
+        if let Some(connection) = self.connections.get(&handle) {
+            return connection.tid;
+        }
+        let tid = self.track(format!("Connection 0x{:04x}", handle));
+        self.connections
+            .insert(handle, Connection { tid, start: None });
+        tid
+    }
+
+    fn instant(&mut self, tid: u32, category: &str, name: String, timestamp: i64, args: Value) {
+        self.events.push(json!({
+            "name": name,
+            "cat": category,
+            "ph": "i",
+            "s": "t",
+            "ts": self.ts(timestamp),
+            "pid": PID,
+            "tid": tid,
+            "args": args,
+        }));
+    }
+
+    fn slice(&mut self, tid: u32, category: &str, name: String, start: i64, end: i64, args: Value) {
+        self.events.push(json!({
+            "name": name,
+            "cat": category,
+            "ph": "X",
+            "ts": self.ts(start),
+            "dur": end.saturating_sub(start),
+            "pid": PID,
+            "tid": tid,
+            "args": args,
+        }));
+    }
+
+    /// A connection completed, a new track for its handle
+    fn connected(&mut self, handle: u16, kind: &str, peer: String, timestamp: i64, index: usize) {
+        self.close(handle, timestamp);
+        let tid = self.track(format!("Connection 0x{:04x} {} {}", handle, kind, peer));
+        self.connections.insert(
+            handle,
+            Connection {
+                tid,
+                start: Some((timestamp, index)),
+            },
+        );
+    }
+
+    /// End the lifetime of the connection of `handle`
+    fn close(&mut self, handle: u16, timestamp: i64) {
+        let Some(connection) = self.connections.remove(&handle) else {
+            return;
+        };
+        self.channel_tracks
+            .retain(|(tid, _, _), _| *tid != connection.tid);
+        if let Some((start, index)) = connection.start {
+            self.slice(
+                connection.tid,
+                "connection",
+                "connected".to_owned(),
+                start,
+                timestamp,
+                json!({"handle": handle, "index": index}),
+            );
+        }
+    }
+
+    fn event(&mut self, event: &Event, index: usize, timestamp: i64, args: Value) {
+        let name = event_label(event);
+        let params = event.params;
+        let status = params.first().copied();
+        let handle = params
+            .get(1..3)
+            .map(|handle| u16::from_le_bytes([handle[0], handle[1]]) & 0x0FFF);
+        match event.code {
+            Event::COMMAND_COMPLETE | Event::COMMAND_STATUS => {
+                let (opcode, status) = if event.code == Event::COMMAND_COMPLETE {
+                    match CommandComplete::try_from(params) {
+                        Ok(complete) => {
+                            (complete.opcode, complete.return_parameters.first().copied())
+                        }
+                        Err(_) => return self.instant(HCI_TID, "hci", name, timestamp, args),
+                    }
+                } else {
+                    match CommandStatus::parse(&mut &params[..]) {
+                        Ok(command_status) => (command_status.opcode, Some(command_status.status)),
+                        Err(_) => return self.instant(HCI_TID, "hci", name, timestamp, args),
+                    }
+                };
+                match self
+                    .pending
+                    .iter()
+                    .position(|pending| pending.opcode == opcode)
+                {
+                    Some(position) => {
+                        let pending = self.pending.remove(position);
+                        let mut command_args = pending.args;
+                        command_args["answer"] = json!(index);
+                        command_args["status"] = json!(status);
+                        self.slice(
+                            HCI_TID,
+                            "hci",
+                            pending.name,
+                            pending.timestamp,
+                            timestamp,
+                            command_args,
+                        );
+                    }
+                    None => self.instant(HCI_TID, "hci", name, timestamp, args),
+                }
+            }
+            Event::CONNECTION_COMPLETE | Event::SYNCHRONOUS_CONNECTION_COMPLETE
+                if status == Some(0) =>
+            {
+                let complete = if event.code == Event::CONNECTION_COMPLETE {
+                    ConnectionComplete::parse(&mut &params[..]).map(|complete| {
+                        let kind = match complete.link_type {
+                            ConnectionComplete::LINK_TYPE_ACL => "ACL",
+                            _ => "SCO",
+                        };
+                        (complete.handle, kind, complete.bd_addr)
+                    })
+                } else {
+                    SynchronousConnectionComplete::parse(&mut &params[..]).map(|complete| {
+                        let kind = match complete.link_type {
+                            SynchronousConnectionComplete::LINK_TYPE_ESCO => "eSCO",
+                            _ => "SCO",
+                        };
+                        (complete.handle, kind, complete.bd_addr)
+                    })
+                };
+                let Ok((handle, kind, peer)) = complete else {
+                    return self.instant(HCI_TID, "hci", name, timestamp, args);
+                };
+                self.connected(handle, kind, peer.to_string(), timestamp, index);
+                let tid = self.connection(handle);
+                self.instant(tid, "hci", name, timestamp, args);
+            }
+            Event::DISCONNECTION_COMPLETE => match DisconnectionComplete::parse(&mut &params[..]) {
+                Ok(disconnection) if disconnection.status == 0 => {
+                    let tid = self.connection(disconnection.handle);
+                    self.instant(tid, "hci", name, timestamp, args);
+                    self.close(disconnection.handle, timestamp);
+                    self.channels.disconnected(disconnection.handle);
+                }
+                _ => self.instant(HCI_TID, "hci", name, timestamp, args),
+            },
+            // events of a connection, its status then its handle
+            0x08 | 0x0C | Event::SYNCHRONOUS_CONNECTION_CHANGED | 0x30 if status == Some(0) => {
+                match handle {
+                    Some(handle) => {
+                        let tid = self.connection(handle);
+                        self.instant(tid, "hci", name, timestamp, args);
+                    }
+                    None => self.instant(HCI_TID, "hci", name, timestamp, args),
+                }
+            }
+            Event::LE_META => match LeMetaEvent::try_from(params) {
+                Ok(LeMetaEvent::ConnectionComplete(complete)) if complete.status == 0 => {
+                    self.connected(
+                        complete.handle,
+                        "LE",
+                        complete.peer_address.to_string(),
+                        timestamp,
+                        index,
+                    );
+                    let tid = self.connection(complete.handle);
+                    self.instant(tid, "hci", name, timestamp, args);
+                }
+                Ok(LeMetaEvent::ConnectionUpdateComplete(update)) => {
+                    let tid = self.connection(update.handle);
+                    self.instant(tid, "hci", name, timestamp, args);
+                }
+                _ => self.instant(HCI_TID, "hci", name, timestamp, args),
+            },
+            _ => self.instant(HCI_TID, "hci", name, timestamp, args),
+        }
+    }
+
+    fn pdu(&mut self, pdu: Pdu, timestamp: i64) {
+        let connection = self.connection(pdu.handle);
+        let Ok(frame) = pdu.frame() else {
+            return;
+        };
+        let cid = frame.channel_id;
+        if pdu.complete && (cid == BasicFrame::SIGNALING_CID || cid == BasicFrame::LE_SIGNALING_CID)
+        {
+            self.channels
+                .update(pdu.handle, pdu.sent, pdu.packet_index, &frame);
+        }
+        let channel = self.channels.lookup(pdu.handle, pdu.sent, cid).copied();
+        let payload = &frame.payload[..frame.payload.len().min(frame.length as usize)];
+        let rfcomm = channel
+            .filter(|channel| channel.psm == PSM_RFCOMM && pdu.complete)
+            .and_then(|_| rfcomm::Frame::try_from(payload).ok())
+            .filter(|frame| frame.is_data());
+
+        let local_cid = channel.map_or(cid, |channel| channel.local_cid);
+        let dlci = rfcomm.map(|frame| frame.dlci);
+        let key = (connection, local_cid, dlci);
+        let tid = match self.channel_tracks.get(&key) {
+            Some(&tid) => tid,
+            None => {
+                let protocol = match (cid, channel) {
+                    (BasicFrame::SIGNALING_CID, _) => "L2CAP signaling".to_owned(),
+                    (BasicFrame::LE_SIGNALING_CID, _) => "LE signaling".to_owned(),
+                    (BasicFrame::ATT_CID, _) => "ATT".to_owned(),
+                    (BasicFrame::SMP_CID, _) => "SMP".to_owned(),
+                    (_, Some(channel)) => format!(
+                        "{} CID 0x{:04x}",
+                        psm_name(channel.psm)
+                            .map(str::to_owned)
+                            .unwrap_or_else(|| format!("PSM 0x{:04x}", channel.psm)),
+                        local_cid
+                    ),
+                    (_, None) => format!("CID 0x{:04x}", cid),
+                };
+                let name = match dlci {
+                    Some(dlci) => {
+                        format!("Connection 0x{:04x} {} DLCI {}", pdu.handle, protocol, dlci)
+                    }
+                    None => format!("Connection 0x{:04x} {}", pdu.handle, protocol),
+                };
+                let tid = self.track(name);
+                self.channel_tracks.insert(key, tid);
+                tid
+            }
+        };
+        let name = match rfcomm {
+            Some(frame) => format!("RFCOMM data ({} bytes)", frame.information.len()),
+            None => pdu_label(&pdu),
+        };
+        let category = match (cid, rfcomm) {
+            (_, Some(_)) => "rfcomm",
+            (BasicFrame::ATT_CID, _) => "att",
+            _ => "l2cap",
+        };
+        let args = json!({
+            "index": pdu.packet_index,
+            "direction": if pdu.sent { "sent" } else { "received" },
+            "cid": cid,
+            "psm": channel.map(|channel| channel.psm),
+            "dlci": dlci,
+            "length": payload.len(),
+            "complete": pdu.complete,
+        });
+        self.instant(tid, category, name, timestamp, args);
+    }
+}
+
+/// The trace of a capture, `{"traceEvents": [...], "displayTimeUnit": "ms"}`
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(packets = capture.packets.len()))
+)]
+pub fn trace_events(capture: &Btsnoop, options: &TraceOptions) -> Value {
+    let mut exporter = Exporter::new(capture, options);
+    let mut reassembler = Reassembler::default();
+    for (index, packet, data) in uart_packets(capture) {
+        let timestamp = packet.description.timestamp;
+        exporter.last_timestamp = timestamp;
+        let sent = !is_received(packet);
+        let args = json!({
+            "index": index,
+            "direction": if sent { "sent" } else { "received" },
+            "length": packet.data.0.len(),
+            "summary": packet.summary().to_string(),
+        });
+        match data {
+            UartData::Command(command) => {
+                exporter.pending.push(PendingCommand {
+                    opcode: command.opcode,
+                    timestamp,
+                    name: command_label(&command),
+                    args,
+                });
+            }
+            UartData::Event(event) => {
+                if event.code == Event::DISCONNECTION_COMPLETE {
+                    if let Ok(disconnection) = DisconnectionComplete::parse(&mut &event.params[..])
+                    {
+                        for pdu in reassembler.disconnected(disconnection.handle) {
+                            exporter.pdu(pdu, timestamp);
+                        }
+                    }
+                }
+                exporter.event(&event, index, timestamp, args);
+            }
+            UartData::Acl(acl) => {
+                for pdu in reassembler.push(&acl, sent, index) {
+                    exporter.pdu(pdu, timestamp);
+                }
+            }
+            UartData::Todos => {
+                // SCO and ISO data, on the track of their connection
+                let data = &packet.data.0;
+                let (category, name) = match data.first() {
+                    Some(0x03) => ("sco", "SCO Data"),
+                    Some(0x05) => ("iso", "ISO Data"),
+                    _ => continue,
+                };
+                let Some(handle) = data.get(1..3) else {
+                    continue;
+                };
+                let handle = u16::from_le_bytes([handle[0], handle[1]]) & 0x0FFF;
+                let tid = exporter.connection(handle);
+                exporter.instant(tid, category, name.to_owned(), timestamp, args);
+            }
+        }
+    }
+    // commands never answered are instants, connections still open end with the capture
+    for pending in std::mem::take(&mut exporter.pending) {
+        exporter.instant(
+            HCI_TID,
+            "hci",
+            pending.name,
+            pending.timestamp,
+            pending.args,
+        );
+    }
+    let mut handles: Vec<_> = exporter.connections.keys().copied().collect();
+    handles.sort_unstable();
+    for handle in handles {
+        exporter.close(handle, exporter.last_timestamp);
+    }
+    json!({
+        "traceEvents": exporter.events,
+        "displayTimeUnit": "ms",
+    })
+}
+
+/// Write the [trace](trace_events) of a capture as JSON
+pub fn write_trace<W: io::Write>(
+    capture: &Btsnoop,
+    options: &TraceOptions,
+    writer: W,
+) -> io::Result<()> {
+    serde_json::to_writer(writer, &trace_events(capture, options)).map_err(io::Error::from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{command, event, h4, l2cap};
+
+    #[test]
+    fn trace() {
+        let capture = h4(vec![
+            (100, false, command(0x03, 0x0003, &[])),
+            (150, true, event(0x0e, &[0x01, 0x03, 0x0c, 0x00])),
+            (
+                200,
+                true,
+                event(
+                    0x3e,
+                    &[
+                        0x01, 0x00, 0x40, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+                        0x18, 0x00, 0x00, 0x00, 0x48, 0x00, 0x00,
+                    ],
+                ),
+            ),
+            (300, false, l2cap(0x40, 0x0004, &[0x0a, 0x03, 0x00])),
+            (400, true, event(0x05, &[0x00, 0x40, 0x00, 0x13])),
+        ]);
+        let trace = trace_events(&capture, &TraceOptions::new().relative(true).offset(10));
+        let events = trace["traceEvents"].as_array().unwrap();
+        let track = |name: &str| {
+            events
+                .iter()
+                .find(|event| event["name"] == "thread_name" && event["args"]["name"] == name)
+                .map(|event| event["tid"].clone())
+                .unwrap()
+        };
+        let connection = track("Connection 0x0040 LE 06:05:04:03:02:01");
+        let att = track("Connection 0x0040 ATT");
+
+        let reset = events
+            .iter()
+            .find(|event| event["name"] == "Reset")
+            .unwrap();
+        assert_eq!(reset["ph"], "X");
+        assert_eq!(reset["ts"], 10);
+        assert_eq!(reset["dur"], 50);
+        assert_eq!(reset["tid"], HCI_TID);
+        assert_eq!(reset["args"]["status"], 0);
+
+        let read = events
+            .iter()
+            .find(|event| event["name"] == "Read Request (handle 0x0003)")
+            .unwrap();
+        assert_eq!(read["ph"], "i");
+        assert_eq!(read["tid"], att);
+        assert_eq!(read["ts"], 210);
+        assert_eq!(read["args"]["direction"], "sent");
+
+        let connected = events
+            .iter()
+            .find(|event| event["name"] == "connected")
+            .unwrap();
+        assert_eq!(connected["tid"], connection);
+        assert_eq!(connected["ts"], 110);
+        assert_eq!(connected["dur"], 200);
+    }
+
+    #[test]
+    fn corrupt_timestamp() {
+        let capture = h4(vec![
+            (i64::MIN, false, command(0x03, 0x0003, &[])),
+            (150, true, event(0x0e, &[0x01, 0x03, 0x0c, 0x00])),
+        ]);
+        let trace = trace_events(&capture, &TraceOptions::new().relative(true).offset(10));
+        let events = trace["traceEvents"].as_array().unwrap();
+        let reset = events
+            .iter()
+            .find(|event| event["name"] == "Reset")
+            .unwrap();
+        assert_eq!(reset["ts"], 10);
+        assert_eq!(reset["dur"], i64::MAX);
+    }
+}
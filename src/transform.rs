@@ -0,0 +1,226 @@
+//! Edits of a parsed capture, to be written back with [`Btsnoop::write`].
+
+use std::{collections::VecDeque, io};
+
+use crate::{Btsnoop, DatalinkType, Packet, PacketDescription, UartPacketType};
+
+/// New origin of the capture's timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rebase {
+    /// the first packet is moved to the Unix epoch
+    FirstPacket,
+    /// this instant, in microseconds since the Unix epoch, is moved to the Unix epoch
+    WallClock(i64),
+    /// every timestamp is shifted by this many microseconds
+    Offset(i64),
+}
+
+/// Rewrite the packet timestamps relative to a zero point. Zero points are moved to
+/// 1970-01-01 00:00:00 UTC, so tools displaying absolute times show the time since the zero point
+/// and the same traffic always gets the same timestamps. Timestamps shifted out of range, e.g.
+/// the ones of corrupt records, saturate.
+pub fn rebase_timestamps(capture: &mut Btsnoop, rebase: Rebase) {
+    let offset = match rebase {
+        Rebase::FirstPacket => match capture.packets.first() {
+            Some(packet) => {
+                PacketDescription::UNIX_EPOCH_OFFSET.saturating_sub(packet.description.timestamp)
+            }
+            None => return,
+        },
+        Rebase::WallClock(unix_timestamp) => unix_timestamp.saturating_neg(),
+        Rebase::Offset(offset) => offset,
+    };
+    for packet in &mut capture.packets {
+        let timestamp = &mut packet.description.timestamp;
+        *timestamp = timestamp.saturating_add(offset);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DedupConfig {
+    /// packets further apart than this are never duplicates
+    pub window_us: i64,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self { window_us: 1_000 }
+    }
+}
+
+/// Remove packets logged twice: same direction and command flags and same data as a packet kept
+/// less than the window before them. Returns how many packets were removed.
+///
+/// Identical packets legitimately exchanged within the window, e.g. repeated writes, are removed
+/// too, so keep the window short.
+pub fn remove_duplicates(capture: &mut Btsnoop, config: &DedupConfig) -> usize {
+    let before = capture.packets.len();
+    // timestamps and indexes of the kept packets within the window
+    let mut recent: VecDeque<(i64, usize)> = VecDeque::new();
+    let mut kept: Vec<Packet> = Vec::with_capacity(before);
+
+    for packet in capture.packets.drain(..) {
+        let timestamp = packet.description.timestamp;
+        while recent
+            .front()
+            .is_some_and(|(t, _)| timestamp.saturating_sub(*t) > config.window_us)
+        {
+            recent.pop_front();
+        }
+        let duplicate = recent.iter().any(|(_, index)| {
+            let other = &kept[*index];
+            other.description.flags.0 == packet.description.flags.0 && other.data.0 == packet.data.0
+        });
+        if !duplicate {
+            recent.push_back((timestamp, kept.len()));
+            kept.push(packet);
+        }
+    }
+
+    capture.packets = kept;
+    before - capture.packets.len()
+}
+
+/// Re-encapsulate the packets for another datalink type. Only HCI UART (H4) and
+/// un-encapsulated HCI (H1) are supported.
+///
+/// H1 packets have no type indicator, it is derived from the flags: commands and events
+/// from the command flag and the direction, everything else is taken as ACL data.
+/// H4 to H1 sets the flags from the type indicator.
+pub fn convert_datalink(capture: &mut Btsnoop, datalink_type: DatalinkType) -> io::Result<()> {
+    match (capture.header.datalink_type, datalink_type) {
+        (DatalinkType::Uart, DatalinkType::Uart)
+        | (DatalinkType::UnencapsulatedHci, DatalinkType::UnencapsulatedHci) => {}
+        (DatalinkType::UnencapsulatedHci, DatalinkType::Uart) => {
+            for packet in &mut capture.packets {
+                let flags = packet.description.flags;
+                let packet_type = match (flags.is_command_or_event(), flags.is_received()) {
+                    (true, false) => UartPacketType::Cmd,
+                    (true, true) => UartPacketType::Evt,
+                    (false, _) => UartPacketType::Acl,
+                };
+                packet.data.0.insert(0, packet_type as u8);
+                packet.description.original_length =
+                    packet.description.original_length.saturating_add(1);
+            }
+        }
+        (DatalinkType::Uart, DatalinkType::UnencapsulatedHci) => {
+            for packet in &mut capture.packets {
+                if packet.data.0.is_empty() {
+                    continue;
+                }
+                let packet_type = packet.data.0.remove(0);
+                packet.description.original_length =
+                    packet.description.original_length.saturating_sub(1);
+                let flags = &mut packet.description.flags.0;
+                match UartPacketType::try_from(packet_type) {
+                    Ok(UartPacketType::Cmd) => *flags = (*flags & !0b11) | 0b10,
+                    Ok(UartPacketType::Evt) => *flags |= 0b11,
+                    Ok(_) => *flags &= !0b10,
+                    Err(_) => {}
+                }
+            }
+        }
+        (from, to) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("cannot convert from {} to {}", from, to),
+            ))
+        }
+    }
+    capture.header.datalink_type = datalink_type;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4};
+
+    #[test]
+    fn rebase_to_first_packet() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let mut capture = Btsnoop::parse(&mut &original[..]).unwrap();
+        let first = capture.packets[0].description.unix_timestamp();
+        let last = capture.packets.last().unwrap().description.unix_timestamp();
+
+        rebase_timestamps(&mut capture, Rebase::FirstPacket);
+        assert_eq!(capture.packets[0].description.unix_timestamp(), 0);
+        assert_eq!(
+            capture.packets.last().unwrap().description.unix_timestamp(),
+            last - first
+        );
+
+        rebase_timestamps(&mut capture, Rebase::Offset(1_000));
+        rebase_timestamps(&mut capture, Rebase::WallClock(1_000));
+        assert_eq!(capture.packets[0].description.unix_timestamp(), 0);
+
+        // the timestamps of corrupt records saturate
+        capture.packets[1].description.timestamp = i64::MIN;
+        rebase_timestamps(&mut capture, Rebase::Offset(-1));
+        assert_eq!(capture.packets[1].description.timestamp, i64::MIN);
+        capture.packets[0].description.timestamp = i64::MIN;
+        rebase_timestamps(&mut capture, Rebase::FirstPacket);
+        assert_eq!(capture.packets[2].description.timestamp, i64::MAX);
+        rebase_timestamps(&mut capture, Rebase::WallClock(i64::MIN));
+        assert_eq!(capture.packets[2].description.timestamp, i64::MAX);
+    }
+
+    #[test]
+    fn remove_relayed_duplicates() {
+        let complete = || event(0x0E, &[0x01, 0x03, 0x0C, 0x00]);
+        let mut capture = h4(vec![
+            (0, true, complete()),
+            (100, true, event(0x13, &[0x01, 0x40, 0x00, 0x01, 0x00])),
+            (200, true, complete()),
+            // outside the window
+            (5_000, true, complete()),
+            // same data, other direction
+            (5_100, false, complete()),
+        ]);
+        assert_eq!(remove_duplicates(&mut capture, &DedupConfig::default()), 1);
+        let timestamps: Vec<_> = capture
+            .packets
+            .iter()
+            .map(|p| p.description.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![0, 100, 5_000, 5_100]);
+
+        // a corrupt timestamp is far outside the window
+        let mut capture = h4(vec![(i64::MIN, true, complete()), (0, true, complete())]);
+        assert_eq!(remove_duplicates(&mut capture, &DedupConfig::default()), 0);
+    }
+
+    #[test]
+    fn h4_h1_round_trip() {
+        let original: &[u8] = include_bytes!("../res/btsnoop_hci_android.log");
+        let mut capture = Btsnoop::parse(&mut &original[..]).unwrap();
+        convert_datalink(&mut capture, DatalinkType::UnencapsulatedHci).unwrap();
+        assert_eq!(
+            capture.packets[0].data.0.len() as u32,
+            capture.packets[0].description.included_length - 1
+        );
+        convert_datalink(&mut capture, DatalinkType::Uart).unwrap();
+        let mut written = vec![];
+        capture.write(&mut written).unwrap();
+        assert_eq!(written, original);
+        assert!(convert_datalink(&mut capture, DatalinkType::Serial).is_err());
+
+        // the bits tools stuff in the reserved flags survive
+        let flags = &mut capture.packets[0].description.flags;
+        *flags = flags.with_reserved_bits(0xAB00);
+        let expected = *flags;
+        convert_datalink(&mut capture, DatalinkType::UnencapsulatedHci).unwrap();
+        convert_datalink(&mut capture, DatalinkType::Uart).unwrap();
+        let mut written = vec![];
+        capture.write(&mut written).unwrap();
+        let read = Btsnoop::parse(&mut &written[..]).unwrap();
+        assert_eq!(read.packets[0].description.flags, expected);
+
+        // the original length of a corrupt record saturates
+        convert_datalink(&mut capture, DatalinkType::UnencapsulatedHci).unwrap();
+        capture.packets[0].description.original_length = u32::MAX;
+        convert_datalink(&mut capture, DatalinkType::Uart).unwrap();
+        assert_eq!(capture.packets[0].description.original_length, u32::MAX);
+    }
+}
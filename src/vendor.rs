@@ -0,0 +1,510 @@
+//! Diagnostics of the controller in vendor specific events (event code 0xFF): the Android
+//! Bluetooth Quality Report, and the debug events of engineering firmware which leak key
+//! material, connection event counters and the RSSI of each connection event.
+//!
+//! The debug events differ between vendors and firmware versions and are not documented, so
+//! they are described by [`EventLayout`]s: the manufacturer of the controller, read from the
+//! Read Local Version Information command of the capture, the leading octets of the event
+//! parameters and the offsets of the fields.
+//!
+//! ```
+//! use btsnoop::vendor::{EventLayout, VendorDecoder};
+//!
+//! let mut decoder = VendorDecoder::new();
+//! // LTK of a connection, and the RSSI and counter of each connection event
+//! decoder.add_layout("ltk:company=0x000f,prefix=c1,handle=1,key=3+16,kind=ltk".parse()?);
+//! decoder.add_layout("events:company=0x000f,prefix=c2,handle=1,counter=3,rssi=5,channel=6".parse()?);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    fmt::Display,
+    io::{self, Read},
+    str::FromStr,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::{
+    analysis::uart_packets,
+    hci::{CommandComplete, Event},
+    hexdump::hex_string,
+    Btsnoop, UartData,
+};
+
+// data format from: AOSP packages/modules/Bluetooth system/btif/include/btif_bqr.h
+
+pub const COMPANY_INTEL: u16 = 0x0002;
+pub const COMPANY_BROADCOM: u16 = 0x000F;
+pub const COMPANY_QUALCOMM: u16 = 0x001D;
+pub const COMPANY_REALTEK: u16 = 0x005D;
+
+/// Sub-event code of the Bluetooth Quality Report vendor event
+pub const BQR_SUBEVENT: u8 = 0x58;
+
+pub fn company_name(company: u16) -> Option<&'static str> {
+    let name = match company {
+        COMPANY_INTEL => "Intel",
+        COMPANY_BROADCOM => "Broadcom",
+        COMPANY_QUALCOMM => "Qualcomm",
+        COMPANY_REALTEK => "Realtek",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
+/// Link quality of a connection, from a Bluetooth Quality Report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityReport {
+    pub report_id: u8,
+    pub packet_types: u8,
+    pub handle: u16,
+    /// 0 = Central, 1 = Peripheral
+    pub role: u8,
+    /// dBm
+    pub tx_power_level: i8,
+    /// dBm
+    pub rssi: i8,
+    /// dB
+    pub snr: u8,
+    pub unused_afh_channels: u8,
+    pub unideal_afh_channels: u8,
+    /// Time = N × 0.625 ms
+    pub supervision_timeout: u16,
+    pub piconet_clock: u32,
+    pub retransmissions: u32,
+    pub no_rx: u32,
+    pub naks: u32,
+    pub last_tx_ack_timestamp: u32,
+    pub flow_off: u32,
+    pub last_flow_on_timestamp: u32,
+    pub buffer_overflow_bytes: u32,
+    pub buffer_underflow_bytes: u32,
+}
+
+impl QualityReport {
+    /// parse the parameters after the sub-event code
+    pub fn parse<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            report_id: reader.read_u8()?,
+            packet_types: reader.read_u8()?,
+            handle: reader.read_u16::<LittleEndian>()?,
+            role: reader.read_u8()?,
+            tx_power_level: reader.read_i8()?,
+            rssi: reader.read_i8()?,
+            snr: reader.read_u8()?,
+            unused_afh_channels: reader.read_u8()?,
+            unideal_afh_channels: reader.read_u8()?,
+            supervision_timeout: reader.read_u16::<LittleEndian>()?,
+            piconet_clock: reader.read_u32::<LittleEndian>()?,
+            retransmissions: reader.read_u32::<LittleEndian>()?,
+            no_rx: reader.read_u32::<LittleEndian>()?,
+            naks: reader.read_u32::<LittleEndian>()?,
+            last_tx_ack_timestamp: reader.read_u32::<LittleEndian>()?,
+            flow_off: reader.read_u32::<LittleEndian>()?,
+            last_flow_on_timestamp: reader.read_u32::<LittleEndian>()?,
+            buffer_overflow_bytes: reader.read_u32::<LittleEndian>()?,
+            buffer_underflow_bytes: reader.read_u32::<LittleEndian>()?,
+        })
+    }
+
+    pub fn report_name(report_id: u8) -> Option<&'static str> {
+        let name = match report_id {
+            0x01 => "Monitor Mode",
+            0x02 => "Approaching LSTO",
+            0x03 => "A2DP Audio Choppy",
+            0x04 => "SCO Voice Choppy",
+            0x05 => "Root Inflammation",
+            0x07 => "LE Audio Choppy",
+            0x08 => "Connect Fail",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyKind {
+    /// BR/EDR link key
+    LinkKey,
+    /// LE long term key
+    LongTermKey,
+    /// session key of an encrypted link
+    SessionKey,
+    Other,
+}
+
+impl FromStr for KeyKind {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "link-key" => KeyKind::LinkKey,
+            "ltk" => KeyKind::LongTermKey,
+            "session" => KeyKind::SessionKey,
+            "other" => KeyKind::Other,
+            _ => return Err(invalid(format!("unknown key kind {}", s))),
+        })
+    }
+}
+
+impl Display for KeyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            KeyKind::LinkKey => "link key",
+            KeyKind::LongTermKey => "LTK",
+            KeyKind::SessionKey => "session key",
+            KeyKind::Other => "key",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// key material, least significant octet first as the event has it
+    Key {
+        handle: Option<u16>,
+        kind: KeyKind,
+        key: Vec<u8>,
+    },
+    /// a connection event of a connection
+    ConnectionEvent {
+        handle: Option<u16>,
+        counter: Option<u16>,
+        /// dBm
+        rssi: Option<i8>,
+        channel: Option<u8>,
+    },
+    Quality(QualityReport),
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let handle = |handle: &Option<u16>| {
+            handle
+                .map(|handle| format!("handle 0x{:04x} ", handle))
+                .unwrap_or_default()
+        };
+        match self {
+            Diagnostic::Key {
+                handle: h,
+                kind,
+                key,
+            } => {
+                // most significant octet first, as keys are usually written
+                let key: Vec<u8> = key.iter().rev().copied().collect();
+                write!(f, "{}{}: {}", handle(h), kind, hex_string(&key))
+            }
+            Diagnostic::ConnectionEvent {
+                handle: h,
+                counter,
+                rssi,
+                channel,
+            } => {
+                write!(f, "{}connection event", handle(h))?;
+                if let Some(counter) = counter {
+                    write!(f, " {}", counter)?;
+                }
+                if let Some(channel) = channel {
+                    write!(f, " channel {}", channel)?;
+                }
+                if let Some(rssi) = rssi {
+                    write!(f, " RSSI {} dBm", rssi)?;
+                }
+                Ok(())
+            }
+            Diagnostic::Quality(report) => write!(
+                f,
+                "handle 0x{:04x} quality report {}: RSSI {} dBm, SNR {} dB, TX power {} dBm, {} retransmissions, {} NAKs, {} no RX",
+                report.handle,
+                QualityReport::report_name(report.report_id).unwrap_or("Unknown"),
+                report.rssi,
+                report.snr,
+                report.tx_power_level,
+                report.retransmissions,
+                report.naks,
+                report.no_rx,
+            ),
+        }
+    }
+}
+
+/// Where the fields of a vendor debug event are, offsets into the event parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLayout {
+    pub name: String,
+    /// manufacturer of the controllers which send it, any when none
+    pub company: Option<u16>,
+    /// leading octets of the event parameters, e.g. the vendor's sub-event code
+    pub prefix: Vec<u8>,
+    /// 16 bit connection handle
+    pub handle: Option<usize>,
+    /// offset, length and kind of a key
+    pub key: Option<(usize, usize, KeyKind)>,
+    /// 16 bit connection event counter
+    pub counter: Option<usize>,
+    /// signed RSSI in dBm
+    pub rssi: Option<usize>,
+    /// RF channel
+    pub channel: Option<usize>,
+}
+
+impl EventLayout {
+    pub fn matches(&self, company: Option<u16>, params: &[u8]) -> bool {
+        self.company.is_none_or(|c| Some(c) == company) && params.starts_with(&self.prefix)
+    }
+
+    /// The diagnostics of the event parameters, none when a field is past their end
+    pub fn decode(&self, params: &[u8]) -> Option<Diagnostic> {
+        let u16_at = |offset: usize| {
+            params
+                .get(offset..offset + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        };
+        let field = |offset: Option<usize>, read: &dyn Fn(usize) -> Option<u16>| match offset {
+            Some(offset) => read(offset).map(Some),
+            None => Some(None),
+        };
+        let handle = field(self.handle, &|o| u16_at(o).map(|h| h & 0x0FFF))?;
+        if let Some((offset, length, kind)) = self.key {
+            return Some(Diagnostic::Key {
+                handle,
+                kind,
+                key: params.get(offset..offset + length)?.to_vec(),
+            });
+        }
+        let byte = |o: usize| params.get(o).map(|b| *b as u16);
+        Some(Diagnostic::ConnectionEvent {
+            handle,
+            counter: field(self.counter, &u16_at)?,
+            rssi: field(self.rssi, &byte)?.map(|rssi| rssi as u8 as i8),
+            channel: field(self.channel, &byte)?.map(|channel| channel as u8),
+        })
+    }
+}
+
+/// `NAME:FIELD=VALUE,...` with the fields `company` (decimal or 0x hex), `prefix` (hex),
+/// `handle`, `counter`, `rssi` and `channel` (offsets), `key` (`OFFSET+LENGTH`) and `kind`
+/// of the key (`link-key`, `ltk`, `session` or `other`)
+impl FromStr for EventLayout {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, fields) = s
+            .split_once(':')
+            .ok_or_else(|| invalid("expected NAME:FIELD=VALUE,..."))?;
+        let mut layout = EventLayout {
+            name: name.to_string(),
+            company: None,
+            prefix: vec![],
+            handle: None,
+            key: None,
+            counter: None,
+            rssi: None,
+            channel: None,
+        };
+        let number = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+        let mut kind = KeyKind::Other;
+        for field in fields.split(',').filter(|field| !field.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected FIELD=VALUE, not {}", field)))?;
+            let bad = |_| invalid(format!("invalid value of {}: {}", key, value));
+            match key {
+                "company" => layout.company = Some(number(value).map_err(bad)? as u16),
+                "prefix" => {
+                    if value.len() % 2 != 0 || !value.is_ascii() {
+                        return Err(invalid(format!("invalid prefix {}", value)));
+                    }
+                    layout.prefix = (0..value.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&value[i..i + 2], 16))
+                        .collect::<Result<_, _>>()
+                        .map_err(bad)?;
+                }
+                "handle" => layout.handle = Some(number(value).map_err(bad)?),
+                "counter" => layout.counter = Some(number(value).map_err(bad)?),
+                "rssi" => layout.rssi = Some(number(value).map_err(bad)?),
+                "channel" => layout.channel = Some(number(value).map_err(bad)?),
+                "key" => {
+                    let (offset, length) = value
+                        .split_once('+')
+                        .ok_or_else(|| invalid("expected key=OFFSET+LENGTH"))?;
+                    let (offset, length) =
+                        (number(offset).map_err(bad)?, number(length).map_err(bad)?);
+                    layout.key = Some((offset, length, kind));
+                }
+                "kind" => kind = value.parse()?,
+                _ => return Err(invalid(format!("unknown field {}", key))),
+            }
+        }
+        if let Some((_, _, key_kind)) = &mut layout.key {
+            *key_kind = kind;
+        }
+        Ok(layout)
+    }
+}
+
+/// A diagnostic found in the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendorRecord {
+    pub packet_index: usize,
+    pub timestamp: i64,
+    /// `bqr`, or the name of the layout of the event
+    pub source: String,
+    pub diagnostic: Diagnostic,
+}
+
+/// Decodes the Bluetooth Quality Reports and the events of its layouts
+#[derive(Debug, Clone, Default)]
+pub struct VendorDecoder {
+    layouts: Vec<EventLayout>,
+}
+
+impl VendorDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layouts are tried in the order they were added
+    pub fn add_layout(&mut self, layout: EventLayout) {
+        self.layouts.push(layout);
+    }
+
+    pub fn layouts(&self) -> &[EventLayout] {
+        &self.layouts
+    }
+
+    /// The diagnostics of a vendor specific event from a controller of `company`
+    pub fn decode(&self, company: Option<u16>, params: &[u8]) -> Option<(&str, Diagnostic)> {
+        if let Some(layout) = self
+            .layouts
+            .iter()
+            .find(|layout| layout.matches(company, params))
+        {
+            return layout
+                .decode(params)
+                .map(|diagnostic| (layout.name.as_str(), diagnostic));
+        }
+        match params.split_first() {
+            Some((&BQR_SUBEVENT, mut report)) => QualityReport::parse(&mut report)
+                .ok()
+                .map(|report| ("bqr", Diagnostic::Quality(report))),
+            _ => None,
+        }
+    }
+
+    /// The diagnostics of the vendor specific events of a capture
+    pub fn extract(&self, capture: &Btsnoop) -> Vec<VendorRecord> {
+        let mut records = vec![];
+        let mut company = None;
+        for (packet_index, packet, data) in uart_packets(capture) {
+            let UartData::Event(event) = data else {
+                continue;
+            };
+            match event.code {
+                Event::COMMAND_COMPLETE => {
+                    // Read Local Version Information: status, HCI version and revision, LMP
+                    // version, then the company identifier
+                    if let Ok(complete) = CommandComplete::try_from(event.params) {
+                        let params = complete.return_parameters;
+                        if complete.opcode.raw() == 0x1001 && params.len() >= 7 && params[0] == 0 {
+                            company = Some(u16::from_le_bytes([params[5], params[6]]));
+                        }
+                    }
+                }
+                Event::VENDOR_SPECIFIC => {
+                    if let Some((source, diagnostic)) = self.decode(company, event.params) {
+                        records.push(VendorRecord {
+                            packet_index,
+                            timestamp: packet.description.timestamp,
+                            source: source.to_string(),
+                            diagnostic,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analysis::test_capture::{event, h4};
+
+    #[test]
+    fn extracts_diagnostics() {
+        let mut decoder = VendorDecoder::new();
+        decoder.add_layout(
+            "ltk:company=0x000f,prefix=c1,handle=1,key=3+16,kind=ltk"
+                .parse()
+                .unwrap(),
+        );
+        decoder.add_layout(
+            "events:company=15,prefix=c2,handle=1,counter=3,rssi=5,channel=6"
+                .parse()
+                .unwrap(),
+        );
+        assert!("ltk:key=3".parse::<EventLayout>().is_err());
+
+        let mut ltk = vec![0xc1, 0x40, 0x00];
+        ltk.extend(1..=16);
+        let mut bqr = vec![BQR_SUBEVENT, 0x01, 0x00, 0x40, 0x00, 0x00, 0x08, 0xc4, 0x1e];
+        bqr.extend_from_slice(&[0; 51]);
+        let capture = h4(vec![
+            // before the company is known
+            (0, true, event(Event::VENDOR_SPECIFIC, &ltk)),
+            // Read Local Version Information of a Broadcom controller
+            (
+                1,
+                true,
+                event(
+                    Event::COMMAND_COMPLETE,
+                    &[
+                        0x01, 0x01, 0x10, 0x00, 0x0c, 0x00, 0x00, 0x0c, 0x0f, 0x00, 0x00, 0x00,
+                    ],
+                ),
+            ),
+            (2, true, event(Event::VENDOR_SPECIFIC, &ltk)),
+            (
+                3,
+                true,
+                event(
+                    Event::VENDOR_SPECIFIC,
+                    &[0xc2, 0x40, 0x00, 0x34, 0x12, 0xb5, 0x11],
+                ),
+            ),
+            (4, true, event(Event::VENDOR_SPECIFIC, &bqr)),
+        ]);
+        let records = decoder.extract(&capture);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].packet_index, 2);
+        assert_eq!(
+            records[0].diagnostic.to_string(),
+            "handle 0x0040 LTK: 100f0e0d0c0b0a090807060504030201"
+        );
+        assert_eq!(
+            records[1].diagnostic,
+            Diagnostic::ConnectionEvent {
+                handle: Some(0x40),
+                counter: Some(0x1234),
+                rssi: Some(-75),
+                channel: Some(17),
+            }
+        );
+        let Diagnostic::Quality(report) = records[2].diagnostic else {
+            panic!("not a quality report");
+        };
+        assert_eq!((report.handle, report.rssi, report.snr), (0x40, -60, 30));
+    }
+}